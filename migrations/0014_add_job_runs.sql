@@ -0,0 +1,10 @@
+-- Last-run bookkeeping for scheduled (cron) jobs.
+CREATE TABLE IF NOT EXISTS job_runs (
+    name TEXT PRIMARY KEY NOT NULL,
+    last_started_at TEXT NOT NULL,
+    last_finished_at TEXT,
+    last_status TEXT NOT NULL,
+    last_error TEXT,
+    last_count INTEGER NOT NULL DEFAULT 0,
+    run_count INTEGER NOT NULL DEFAULT 0
+);
@@ -0,0 +1,34 @@
+-- Organizations and their memberships.
+CREATE TABLE IF NOT EXISTS organizations (
+    id TEXT PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    billing_email TEXT NOT NULL,
+    private_key TEXT, -- org private key encrypted with the org symmetric key
+    public_key TEXT,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+-- Status: -1=Revoked, 0=Invited, 1=Accepted, 2=Confirmed
+-- Type: 0=Owner, 1=Admin, 2=User, 3=Manager, 4=Custom
+CREATE TABLE IF NOT EXISTS users_organizations (
+    id TEXT PRIMARY KEY NOT NULL,
+    organization_id TEXT NOT NULL,
+    user_id TEXT, -- NULL while the invited email has no account yet
+    email TEXT NOT NULL,
+    akey TEXT, -- org symmetric key encrypted with the member's public key (set on confirm)
+    status INTEGER NOT NULL DEFAULT 0,
+    type INTEGER NOT NULL DEFAULT 2,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    FOREIGN KEY (organization_id) REFERENCES organizations(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_users_organizations_org_email
+    ON users_organizations(organization_id, email);
+CREATE INDEX IF NOT EXISTS idx_users_organizations_user_id
+    ON users_organizations(user_id);
+
+-- Organization ciphers have user_id NULL and are looked up by organization.
+CREATE INDEX IF NOT EXISTS idx_ciphers_organization_id ON ciphers(organization_id);
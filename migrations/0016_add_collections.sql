@@ -0,0 +1,40 @@
+-- Organization collections and their access assignments.
+CREATE TABLE IF NOT EXISTS collections (
+    id TEXT PRIMARY KEY NOT NULL,
+    organization_id TEXT NOT NULL,
+    name TEXT NOT NULL, -- encrypted with the org key
+    external_id TEXT,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    FOREIGN KEY (organization_id) REFERENCES organizations(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_collections_organization_id ON collections(organization_id);
+
+-- Which collections an organization cipher belongs to.
+CREATE TABLE IF NOT EXISTS ciphers_collections (
+    cipher_id TEXT NOT NULL,
+    collection_id TEXT NOT NULL,
+    PRIMARY KEY (cipher_id, collection_id),
+    FOREIGN KEY (cipher_id) REFERENCES ciphers(id) ON DELETE CASCADE,
+    FOREIGN KEY (collection_id) REFERENCES collections(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_ciphers_collections_collection_id
+    ON ciphers_collections(collection_id);
+
+-- Per-member collection access. Owners and admins can access every collection
+-- of their organization without an explicit assignment.
+CREATE TABLE IF NOT EXISTS users_collections (
+    membership_id TEXT NOT NULL,
+    collection_id TEXT NOT NULL,
+    read_only INTEGER NOT NULL DEFAULT 0,
+    hide_passwords INTEGER NOT NULL DEFAULT 0,
+    manage INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (membership_id, collection_id),
+    FOREIGN KEY (membership_id) REFERENCES users_organizations(id) ON DELETE CASCADE,
+    FOREIGN KEY (collection_id) REFERENCES collections(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_users_collections_collection_id
+    ON users_collections(collection_id);
@@ -0,0 +1,24 @@
+-- Emergency access grants between a grantor (vault owner) and a grantee.
+-- Type: 0=View, 1=Takeover
+-- Status: 0=Invited, 1=Accepted, 2=Confirmed, 3=RecoveryInitiated, 4=RecoveryApproved
+CREATE TABLE IF NOT EXISTS emergency_access (
+    id TEXT PRIMARY KEY NOT NULL,
+    grantor_id TEXT NOT NULL,
+    grantee_id TEXT, -- NULL while the invited email has no account yet
+    email TEXT NOT NULL,
+    key_encrypted TEXT, -- grantor's user key encrypted with the grantee's public key (set on confirm)
+    type INTEGER NOT NULL,
+    status INTEGER NOT NULL DEFAULT 0,
+    wait_time_days INTEGER NOT NULL,
+    recovery_initiated_at TEXT,
+    last_notification_at TEXT,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    FOREIGN KEY (grantor_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (grantee_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_emergency_access_grantor_email
+    ON emergency_access(grantor_id, email);
+CREATE INDEX IF NOT EXISTS idx_emergency_access_grantee_id ON emergency_access(grantee_id);
+CREATE INDEX IF NOT EXISTS idx_emergency_access_status ON emergency_access(status);
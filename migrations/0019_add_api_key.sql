@@ -0,0 +1,2 @@
+-- Personal API key used as the client secret of the client_credentials grant
+ALTER TABLE users ADD COLUMN api_key TEXT;
@@ -0,0 +1,5 @@
+-- Accounts marked for deletion are disabled immediately and wiped by the
+-- deleted_accounts job once the grace period has passed.
+ALTER TABLE users ADD COLUMN deletion_requested_at TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_users_deletion_requested_at ON users(deletion_requested_at);
@@ -0,0 +1,8 @@
+-- Admin API: disabled accounts, forced password resets and email invitations
+ALTER TABLE users ADD COLUMN disabled INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE users ADD COLUMN force_password_reset INTEGER NOT NULL DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS invitations (
+    email TEXT PRIMARY KEY NOT NULL,
+    invited_at TEXT NOT NULL
+);
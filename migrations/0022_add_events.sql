@@ -0,0 +1,19 @@
+-- Organization event log
+-- event_type follows the Bitwarden EventType codes (1000=User_LoggedIn, 1100=Cipher_Created, ...)
+CREATE TABLE IF NOT EXISTS events (
+  id TEXT PRIMARY KEY NOT NULL,
+  event_type INTEGER NOT NULL,
+  organization_id TEXT NOT NULL,
+  user_id TEXT, -- user the event is about (login, membership changes)
+  acting_user_id TEXT, -- user who performed the action
+  cipher_id TEXT,
+  collection_id TEXT,
+  member_id TEXT, -- users_organizations.id
+  device_type INTEGER,
+  ip_address TEXT,
+  event_date TEXT NOT NULL,
+  FOREIGN KEY (organization_id) REFERENCES organizations(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_events_org_date ON events(organization_id, event_date);
+CREATE INDEX IF NOT EXISTS idx_events_event_date ON events(event_date);
@@ -0,0 +1,143 @@
+-- Revision counters for delta sync (`/api/sync?since=<revision>`)
+-- Every write to a cipher or folder stamps the row with the next value of a global counter;
+-- deletions leave a tombstone. Membership and collection-access changes bump
+-- users.access_revision, which forces that user back to a full sync.
+CREATE TABLE IF NOT EXISTS sync_state (
+  id INTEGER PRIMARY KEY NOT NULL CHECK (id = 1),
+  revision INTEGER NOT NULL DEFAULT 0,
+  min_delta_revision INTEGER NOT NULL DEFAULT 0 -- tombstones up to here have been pruned
+);
+INSERT OR IGNORE INTO sync_state (id) VALUES (1);
+
+ALTER TABLE ciphers ADD COLUMN revision INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE folders ADD COLUMN revision INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE users ADD COLUMN access_revision INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX IF NOT EXISTS idx_ciphers_revision ON ciphers(revision);
+CREATE INDEX IF NOT EXISTS idx_folders_user_revision ON folders(user_id, revision);
+
+-- Deleted (or no longer shared) objects. object_type: 0=Cipher, 1=Folder
+CREATE TABLE IF NOT EXISTS sync_tombstones (
+  object_type INTEGER NOT NULL,
+  object_id TEXT NOT NULL,
+  user_id TEXT,
+  organization_id TEXT,
+  revision INTEGER NOT NULL,
+  deleted_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_sync_tombstones_revision ON sync_tombstones(revision);
+CREATE INDEX IF NOT EXISTS idx_sync_tombstones_deleted_at ON sync_tombstones(deleted_at);
+
+CREATE TRIGGER IF NOT EXISTS trg_ciphers_revision_insert AFTER INSERT ON ciphers
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE ciphers SET revision = (SELECT revision FROM sync_state WHERE id = 1) WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_ciphers_revision_update AFTER UPDATE ON ciphers
+WHEN NEW.revision = OLD.revision
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE ciphers SET revision = (SELECT revision FROM sync_state WHERE id = 1) WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_ciphers_tombstone AFTER DELETE ON ciphers
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  INSERT INTO sync_tombstones (object_type, object_id, user_id, organization_id, revision, deleted_at)
+  VALUES (0, OLD.id, OLD.user_id, OLD.organization_id,
+          (SELECT revision FROM sync_state WHERE id = 1), strftime('%Y-%m-%dT%H:%M:%fZ', 'now'));
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_folders_revision_insert AFTER INSERT ON folders
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE folders SET revision = (SELECT revision FROM sync_state WHERE id = 1) WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_folders_revision_update AFTER UPDATE ON folders
+WHEN NEW.revision = OLD.revision
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE folders SET revision = (SELECT revision FROM sync_state WHERE id = 1) WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_folders_tombstone AFTER DELETE ON folders
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  INSERT INTO sync_tombstones (object_type, object_id, user_id, organization_id, revision, deleted_at)
+  VALUES (1, OLD.id, OLD.user_id, NULL,
+          (SELECT revision FROM sync_state WHERE id = 1), strftime('%Y-%m-%dT%H:%M:%fZ', 'now'));
+END;
+
+-- Attachments are embedded in the cipher JSON, so they count as cipher changes.
+CREATE TRIGGER IF NOT EXISTS trg_attachments_revision_insert AFTER INSERT ON attachments
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE ciphers SET revision = (SELECT revision FROM sync_state WHERE id = 1) WHERE id = NEW.cipher_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_attachments_revision_delete AFTER DELETE ON attachments
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE ciphers SET revision = (SELECT revision FROM sync_state WHERE id = 1) WHERE id = OLD.cipher_id;
+END;
+
+-- Moving an org cipher between collections changes who can see it. Members who lost
+-- access find it in the tombstones; members who still see it get the updated row.
+CREATE TRIGGER IF NOT EXISTS trg_ciphers_collections_insert AFTER INSERT ON ciphers_collections
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE ciphers SET revision = (SELECT revision FROM sync_state WHERE id = 1) WHERE id = NEW.cipher_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_ciphers_collections_delete AFTER DELETE ON ciphers_collections
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  INSERT INTO sync_tombstones (object_type, object_id, user_id, organization_id, revision, deleted_at)
+  SELECT 0, c.id, NULL, c.organization_id,
+         (SELECT revision FROM sync_state WHERE id = 1), strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+  FROM ciphers c WHERE c.id = OLD.cipher_id;
+  UPDATE ciphers SET revision = (SELECT revision FROM sync_state WHERE id = 1) WHERE id = OLD.cipher_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_users_organizations_insert AFTER INSERT ON users_organizations
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE users SET access_revision = (SELECT revision FROM sync_state WHERE id = 1) WHERE id = NEW.user_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_users_organizations_update AFTER UPDATE ON users_organizations
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE users SET access_revision = (SELECT revision FROM sync_state WHERE id = 1)
+  WHERE id IN (NEW.user_id, OLD.user_id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_users_organizations_delete AFTER DELETE ON users_organizations
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE users SET access_revision = (SELECT revision FROM sync_state WHERE id = 1) WHERE id = OLD.user_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_users_collections_insert AFTER INSERT ON users_collections
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE users SET access_revision = (SELECT revision FROM sync_state WHERE id = 1)
+  WHERE id = (SELECT user_id FROM users_organizations WHERE id = NEW.membership_id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_users_collections_update AFTER UPDATE ON users_collections
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE users SET access_revision = (SELECT revision FROM sync_state WHERE id = 1)
+  WHERE id = (SELECT user_id FROM users_organizations WHERE id = NEW.membership_id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_users_collections_delete AFTER DELETE ON users_collections
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE users SET access_revision = (SELECT revision FROM sync_state WHERE id = 1)
+  WHERE id = (SELECT user_id FROM users_organizations WHERE id = OLD.membership_id);
+END;
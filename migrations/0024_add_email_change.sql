@@ -0,0 +1,2 @@
+-- Pending email change: JSON EmailTokenData (new address + hash of the emailed code)
+ALTER TABLE users ADD COLUMN email_change TEXT;
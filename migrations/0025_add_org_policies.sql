@@ -0,0 +1,16 @@
+-- Organization policies
+-- atype follows the Bitwarden PolicyType codes (0=TwoFactorAuthentication, 1=MasterPassword, ...)
+-- data holds the policy options as JSON (e.g. the MasterPassword requirements)
+CREATE TABLE IF NOT EXISTS org_policies (
+  id TEXT PRIMARY KEY NOT NULL,
+  organization_id TEXT NOT NULL,
+  atype INTEGER NOT NULL,
+  enabled INTEGER NOT NULL DEFAULT 0,
+  data TEXT,
+  created_at TEXT NOT NULL,
+  updated_at TEXT NOT NULL,
+  UNIQUE (organization_id, atype),
+  FOREIGN KEY (organization_id) REFERENCES organizations(id) ON DELETE CASCADE
+);
+
+ALTER TABLE events ADD COLUMN policy_id TEXT;
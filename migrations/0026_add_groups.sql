@@ -0,0 +1,91 @@
+-- Organization groups. Members of a group get the group's collection access in
+-- addition to their own assignments.
+CREATE TABLE IF NOT EXISTS groups (
+    id TEXT PRIMARY KEY NOT NULL,
+    organization_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    external_id TEXT,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    FOREIGN KEY (organization_id) REFERENCES organizations(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_groups_organization_id ON groups(organization_id);
+
+ALTER TABLE events ADD COLUMN group_id TEXT;
+
+CREATE TABLE IF NOT EXISTS groups_users (
+    group_id TEXT NOT NULL,
+    membership_id TEXT NOT NULL,
+    PRIMARY KEY (group_id, membership_id),
+    FOREIGN KEY (group_id) REFERENCES groups(id) ON DELETE CASCADE,
+    FOREIGN KEY (membership_id) REFERENCES users_organizations(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_groups_users_membership_id ON groups_users(membership_id);
+
+CREATE TABLE IF NOT EXISTS collections_groups (
+    collection_id TEXT NOT NULL,
+    group_id TEXT NOT NULL,
+    read_only INTEGER NOT NULL DEFAULT 0,
+    hide_passwords INTEGER NOT NULL DEFAULT 0,
+    manage INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (collection_id, group_id),
+    FOREIGN KEY (collection_id) REFERENCES collections(id) ON DELETE CASCADE,
+    FOREIGN KEY (group_id) REFERENCES groups(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_collections_groups_group_id ON collections_groups(group_id);
+
+-- Every (membership, collection) grant, direct or through a group. A member can have
+-- several rows for one collection; the most permissive one applies.
+CREATE VIEW IF NOT EXISTS member_collection_access AS
+SELECT membership_id, collection_id, read_only, hide_passwords, manage
+FROM users_collections
+UNION ALL
+SELECT gu.membership_id, cg.collection_id, cg.read_only, cg.hide_passwords, cg.manage
+FROM groups_users gu
+JOIN collections_groups cg ON cg.group_id = gu.group_id;
+
+-- Group changes alter what members can access, so they force a full sync like
+-- direct assignments do.
+CREATE TRIGGER IF NOT EXISTS trg_groups_users_insert AFTER INSERT ON groups_users
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE users SET access_revision = (SELECT revision FROM sync_state WHERE id = 1)
+  WHERE id = (SELECT user_id FROM users_organizations WHERE id = NEW.membership_id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_groups_users_delete AFTER DELETE ON groups_users
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE users SET access_revision = (SELECT revision FROM sync_state WHERE id = 1)
+  WHERE id = (SELECT user_id FROM users_organizations WHERE id = OLD.membership_id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_collections_groups_insert AFTER INSERT ON collections_groups
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE users SET access_revision = (SELECT revision FROM sync_state WHERE id = 1)
+  WHERE id IN (SELECT uo.user_id FROM groups_users gu
+               JOIN users_organizations uo ON uo.id = gu.membership_id
+               WHERE gu.group_id = NEW.group_id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_collections_groups_update AFTER UPDATE ON collections_groups
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE users SET access_revision = (SELECT revision FROM sync_state WHERE id = 1)
+  WHERE id IN (SELECT uo.user_id FROM groups_users gu
+               JOIN users_organizations uo ON uo.id = gu.membership_id
+               WHERE gu.group_id = NEW.group_id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_collections_groups_delete AFTER DELETE ON collections_groups
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE users SET access_revision = (SELECT revision FROM sync_state WHERE id = 1)
+  WHERE id IN (SELECT uo.user_id FROM groups_users gu
+               JOIN users_organizations uo ON uo.id = gu.membership_id
+               WHERE gu.group_id = OLD.group_id);
+END;
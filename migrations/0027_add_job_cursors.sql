@@ -0,0 +1,7 @@
+-- Continuation markers of scheduled jobs that stopped before finishing their work;
+-- the next run resumes from the marker.
+CREATE TABLE IF NOT EXISTS job_cursors (
+    name TEXT PRIMARY KEY NOT NULL,
+    cursor TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
@@ -0,0 +1,4 @@
+-- Individual storage quotas in KB, set through the admin API. NULL uses the
+-- USER_STORAGE_QUOTA_KB / ORG_STORAGE_QUOTA_KB default.
+ALTER TABLE users ADD COLUMN storage_quota_kb INTEGER;
+ALTER TABLE organizations ADD COLUMN storage_quota_kb INTEGER;
@@ -0,0 +1,3 @@
+-- Account recovery (admin password reset): the member's user key encrypted with the
+-- organization's public key, set while the member is enrolled.
+ALTER TABLE users_organizations ADD COLUMN reset_password_key TEXT;
@@ -0,0 +1,21 @@
+-- Passkeys registered for passwordless login. When the authenticator supports the
+-- PRF extension the client also stores a key pair derived from it, so the vault can
+-- be decrypted without the master password.
+CREATE TABLE IF NOT EXISTS passkeys (
+  id TEXT PRIMARY KEY NOT NULL,
+  user_id TEXT NOT NULL,
+  name TEXT NOT NULL,
+  credential_id TEXT NOT NULL UNIQUE,
+  public_key TEXT NOT NULL, -- JWK JSON
+  algorithm INTEGER NOT NULL, -- COSE algorithm identifier
+  counter INTEGER NOT NULL DEFAULT 0,
+  supports_prf INTEGER NOT NULL DEFAULT 0,
+  encrypted_user_key TEXT, -- User key encrypted with the PRF public key
+  encrypted_public_key TEXT, -- PRF public key encrypted with the user key
+  encrypted_private_key TEXT, -- PRF private key encrypted with the PRF-derived key
+  created_at TEXT NOT NULL,
+  updated_at TEXT NOT NULL,
+  FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_passkeys_user_id ON passkeys(user_id);
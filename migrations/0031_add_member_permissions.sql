@@ -0,0 +1,3 @@
+-- Permissions granted to members with the Custom role, as a bitset
+-- (see `Permission` in src/models/organization.rs).
+ALTER TABLE users_organizations ADD COLUMN permissions INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,12 @@
+-- Two-factor providers configured by an organization for its members (OrganizationDuo).
+CREATE TABLE IF NOT EXISTS organization_twofactor (
+  uuid TEXT PRIMARY KEY NOT NULL,
+  organization_id TEXT NOT NULL,
+  atype INTEGER NOT NULL,
+  enabled INTEGER NOT NULL DEFAULT 1,
+  data TEXT NOT NULL, -- JSON data specific to the 2FA type (e.g., Duo credentials)
+  created_at TEXT NOT NULL,
+  updated_at TEXT NOT NULL,
+  UNIQUE (organization_id, atype),
+  FOREIGN KEY (organization_id) REFERENCES organizations(id) ON DELETE CASCADE
+);
@@ -0,0 +1,28 @@
+-- Favorite flag and folder of an organization cipher, per member. Org ciphers are
+-- shared rows, so this state cannot live on the cipher; personal ciphers keep it there.
+CREATE TABLE IF NOT EXISTS users_ciphers (
+  user_id TEXT NOT NULL,
+  cipher_id TEXT NOT NULL,
+  favorite INTEGER NOT NULL DEFAULT 0,
+  folder_id TEXT,
+  PRIMARY KEY (user_id, cipher_id),
+  FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+  FOREIGN KEY (cipher_id) REFERENCES ciphers(id) ON DELETE CASCADE,
+  FOREIGN KEY (folder_id) REFERENCES folders(id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_users_ciphers_cipher_id ON users_ciphers(cipher_id);
+CREATE INDEX IF NOT EXISTS idx_users_ciphers_folder_id ON users_ciphers(folder_id);
+
+-- The state is part of the cipher JSON, so changing it counts as a cipher change.
+CREATE TRIGGER IF NOT EXISTS trg_users_ciphers_insert AFTER INSERT ON users_ciphers
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE ciphers SET revision = (SELECT revision FROM sync_state WHERE id = 1) WHERE id = NEW.cipher_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_users_ciphers_update AFTER UPDATE ON users_ciphers
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE ciphers SET revision = (SELECT revision FROM sync_state WHERE id = 1) WHERE id = NEW.cipher_id;
+END;
@@ -0,0 +1,16 @@
+-- R2 multipart upload sessions of large attachment and Send files. Rows outlive their
+-- user on purpose: the stale_multipart_uploads job still has to abort them in R2.
+CREATE TABLE IF NOT EXISTS multipart_uploads (
+  id TEXT PRIMARY KEY NOT NULL,
+  upload_id TEXT NOT NULL,
+  storage_key TEXT NOT NULL,
+  user_id TEXT NOT NULL,
+  target TEXT NOT NULL,
+  parent_id TEXT NOT NULL,
+  file_id TEXT NOT NULL,
+  file_size INTEGER NOT NULL,
+  part_size INTEGER NOT NULL,
+  created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_multipart_uploads_created_at ON multipart_uploads(created_at);
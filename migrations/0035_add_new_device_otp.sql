@@ -0,0 +1,2 @@
+-- Pending new device verification: JSON EmailTokenData (account address + hash of the emailed code)
+ALTER TABLE users ADD COLUMN new_device_otp TEXT;
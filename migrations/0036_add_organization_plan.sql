@@ -0,0 +1,5 @@
+-- Organization plan: seat limit (NULL = unlimited) and optional features, set through the admin API
+ALTER TABLE organizations ADD COLUMN seats INTEGER;
+ALTER TABLE organizations ADD COLUMN use_groups INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE organizations ADD COLUMN use_events INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE organizations ADD COLUMN use_policies INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,2 @@
+-- Pending user verification: JSON EmailTokenData (account address + hash of the emailed code)
+ALTER TABLE users ADD COLUMN verification_otp TEXT;
@@ -0,0 +1,17 @@
+-- Domains claimed by organizations, verified with a DNS TXT record
+CREATE TABLE IF NOT EXISTS organization_domains (
+    id TEXT PRIMARY KEY NOT NULL,
+    organization_id TEXT NOT NULL,
+    domain_name TEXT NOT NULL,
+    txt_record TEXT NOT NULL, -- Value the TXT record of the domain must have
+    verified_at TEXT,
+    last_checked_at TEXT,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    FOREIGN KEY (organization_id) REFERENCES organizations(id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_organization_domains_org_domain
+    ON organization_domains(organization_id, domain_name);
+CREATE INDEX IF NOT EXISTS idx_organization_domains_domain_name
+    ON organization_domains(domain_name);
@@ -0,0 +1,7 @@
+-- Links between users and their identity at the SSO identity provider
+CREATE TABLE IF NOT EXISTS sso_users (
+    user_id TEXT PRIMARY KEY NOT NULL,
+    identifier TEXT NOT NULL UNIQUE, -- `sub` claim of the identity provider
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
@@ -0,0 +1,10 @@
+-- Last login and last sync of the account, written at most once an hour
+ALTER TABLE users ADD COLUMN last_login_at TEXT;
+ALTER TABLE users ADD COLUMN last_sync_at TEXT;
+
+-- Existing accounts start from their most recently used device, so none of them looks
+-- inactive right after the upgrade.
+UPDATE users SET last_login_at = COALESCE(
+    (SELECT MAX(d.updated_at) FROM devices d WHERE d.user_id = users.id),
+    updated_at
+);
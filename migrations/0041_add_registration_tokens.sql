@@ -0,0 +1,15 @@
+-- Single-use registration tokens issued through the admin API. A token lets one account
+-- register even with SIGNUPS_ALLOWED=false; used tokens are kept as a record of which
+-- token created which account.
+CREATE TABLE IF NOT EXISTS registration_tokens (
+    id TEXT PRIMARY KEY NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE, -- SHA-256 of the token; the token itself is not stored
+    email TEXT, -- only this address may use the token; NULL allows any
+    note TEXT,
+    created_at TEXT NOT NULL,
+    expires_at TEXT NOT NULL,
+    used_at TEXT,
+    used_by_email TEXT,
+    used_by_user_id TEXT,
+    FOREIGN KEY (used_by_user_id) REFERENCES users(id) ON DELETE SET NULL
+);
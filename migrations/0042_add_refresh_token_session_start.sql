@@ -0,0 +1,9 @@
+-- When the login session of a refresh token family started. Every rotated token
+-- carries it over, so SESSION_MAX_AGE_HOURS can end a session however often it is
+-- refreshed.
+ALTER TABLE refresh_tokens ADD COLUMN session_started_at TEXT;
+
+-- Existing sessions start from their oldest remaining token.
+UPDATE refresh_tokens SET session_started_at = (
+    SELECT MIN(t.created_at) FROM refresh_tokens t WHERE t.family_id = refresh_tokens.family_id
+);
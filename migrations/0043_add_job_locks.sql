@@ -0,0 +1,8 @@
+-- Locks of scheduled jobs that are running, so overlapping cron invocations skip them.
+-- A lock left behind by a run that died is taken over after its expiry.
+CREATE TABLE IF NOT EXISTS job_locks (
+    name TEXT PRIMARY KEY NOT NULL,
+    owner TEXT NOT NULL,
+    acquired_at TEXT NOT NULL,
+    expires_at TEXT NOT NULL
+);
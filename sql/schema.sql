@@ -20,10 +20,45 @@ CREATE TABLE IF NOT EXISTS users (
     equivalent_domains TEXT NOT NULL DEFAULT '[]', -- JSON: Vec<Vec<String>>
     excluded_globals TEXT NOT NULL DEFAULT '[]', -- JSON: Vec<i32> (reserved for future global groups)
     totp_recover TEXT, -- Recovery code for 2FA
+    api_key TEXT, -- Personal API key (client_credentials client secret)
+    deletion_requested_at TEXT, -- Set when the account is scheduled for deletion
+    disabled INTEGER NOT NULL DEFAULT 0, -- Set by an admin; disabled accounts cannot log in
+    force_password_reset INTEGER NOT NULL DEFAULT 0, -- Clients prompt for a new master password
+    access_revision INTEGER NOT NULL DEFAULT 0, -- Sync revision of the last membership/collection access change
+    email_change TEXT, -- Pending email change: JSON EmailTokenData (new address + code hash)
+    storage_quota_kb INTEGER, -- Individual storage quota; NULL uses USER_STORAGE_QUOTA_KB
+    new_device_otp TEXT, -- Pending new device verification: JSON EmailTokenData (code hash)
+    verification_otp TEXT, -- Pending user verification: JSON EmailTokenData (code hash)
+    last_login_at TEXT, -- Last login, written at most once an hour
+    last_sync_at TEXT, -- Last vault sync, written at most once an hour
     created_at TEXT NOT NULL,
     updated_at TEXT NOT NULL
 );
 
+CREATE INDEX IF NOT EXISTS idx_users_deletion_requested_at ON users(deletion_requested_at);
+
+-- Emails invited by an admin; they may register even if ALLOWED_EMAILS does not match
+CREATE TABLE IF NOT EXISTS invitations (
+    email TEXT PRIMARY KEY NOT NULL,
+    invited_at TEXT NOT NULL
+);
+
+-- Single-use registration tokens issued through the admin API. A token lets one account
+-- register even with SIGNUPS_ALLOWED=false; used tokens are kept as a record of which
+-- token created which account.
+CREATE TABLE IF NOT EXISTS registration_tokens (
+    id TEXT PRIMARY KEY NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE, -- SHA-256 of the token; the token itself is not stored
+    email TEXT, -- only this address may use the token; NULL allows any
+    note TEXT,
+    created_at TEXT NOT NULL,
+    expires_at TEXT NOT NULL,
+    used_at TEXT,
+    used_by_email TEXT,
+    used_by_user_id TEXT,
+    FOREIGN KEY (used_by_user_id) REFERENCES users(id) ON DELETE SET NULL
+);
+
 -- Ciphers table for storing encrypted vault items
 CREATE TABLE IF NOT EXISTS ciphers (
     id TEXT PRIMARY KEY NOT NULL,
@@ -35,6 +70,7 @@ CREATE TABLE IF NOT EXISTS ciphers (
     folder_id TEXT,
     deleted_at TEXT,
     archived_at TEXT,
+    revision INTEGER NOT NULL DEFAULT 0, -- Sync revision of the last change (see sync_state)
     created_at TEXT NOT NULL,
     updated_at TEXT NOT NULL,
     FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
@@ -43,6 +79,8 @@ CREATE TABLE IF NOT EXISTS ciphers (
 
 -- Index to speed up common per-user cipher queries (sync/list/attachments joins)
 CREATE INDEX IF NOT EXISTS idx_ciphers_user_id ON ciphers(user_id);
+-- Organization ciphers have user_id NULL and are looked up by organization
+CREATE INDEX IF NOT EXISTS idx_ciphers_organization_id ON ciphers(organization_id);
 
 -- Attachments table for cipher file metadata
 CREATE TABLE IF NOT EXISTS attachments (
@@ -74,7 +112,7 @@ CREATE INDEX IF NOT EXISTS idx_attachments_pending_cipher ON attachments_pending
 CREATE INDEX IF NOT EXISTS idx_attachments_pending_created_at ON attachments_pending(created_at);
 
 -- TwoFactor table for two-factor authentication
--- Types: 0=Authenticator(TOTP), 1=Email, 5=Remember, 8=RecoveryCode
+-- Types: 0=Authenticator(TOTP), 1=Email, 2=Duo, 5=Remember, 7=WebAuthn, 8=RecoveryCode
 CREATE TABLE IF NOT EXISTS twofactor (
     uuid TEXT PRIMARY KEY NOT NULL,
     user_uuid TEXT NOT NULL,
@@ -134,6 +172,7 @@ CREATE TABLE IF NOT EXISTS folders (
     id TEXT PRIMARY KEY NOT NULL,
     user_id TEXT NOT NULL,
     name TEXT NOT NULL, -- Encrypted folder name
+    revision INTEGER NOT NULL DEFAULT 0, -- Sync revision of the last change (see sync_state)
     created_at TEXT NOT NULL,
     updated_at TEXT NOT NULL,
     FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
@@ -196,3 +235,529 @@ CREATE TABLE IF NOT EXISTS sends_pending (
   disabled INTEGER NOT NULL DEFAULT 0,
   hide_email INTEGER NOT NULL DEFAULT 0
 );
+
+-- Last-run bookkeeping for scheduled (cron) jobs.
+CREATE TABLE IF NOT EXISTS job_runs (
+  name TEXT PRIMARY KEY NOT NULL,
+  last_started_at TEXT NOT NULL,
+  last_finished_at TEXT,
+  last_status TEXT NOT NULL,
+  last_error TEXT,
+  last_count INTEGER NOT NULL DEFAULT 0,
+  run_count INTEGER NOT NULL DEFAULT 0
+);
+
+-- Continuation markers of scheduled jobs that stopped before finishing their work.
+CREATE TABLE IF NOT EXISTS job_cursors (
+  name TEXT PRIMARY KEY NOT NULL,
+  cursor TEXT NOT NULL,
+  updated_at TEXT NOT NULL
+);
+
+-- Locks of scheduled jobs that are running; expired locks may be taken over.
+CREATE TABLE IF NOT EXISTS job_locks (
+  name TEXT PRIMARY KEY NOT NULL,
+  owner TEXT NOT NULL,
+  acquired_at TEXT NOT NULL,
+  expires_at TEXT NOT NULL
+);
+
+-- Organizations table
+CREATE TABLE IF NOT EXISTS organizations (
+  id TEXT PRIMARY KEY NOT NULL,
+  name TEXT NOT NULL,
+  billing_email TEXT NOT NULL,
+  private_key TEXT, -- org private key encrypted with the org symmetric key
+  public_key TEXT,
+  storage_quota_kb INTEGER, -- Individual storage quota; NULL uses ORG_STORAGE_QUOTA_KB
+  seats INTEGER, -- Seat limit of the plan; NULL is unlimited
+  use_groups INTEGER NOT NULL DEFAULT 1,
+  use_events INTEGER NOT NULL DEFAULT 1,
+  use_policies INTEGER NOT NULL DEFAULT 1,
+  created_at TEXT NOT NULL,
+  updated_at TEXT NOT NULL
+);
+
+-- Organization memberships
+-- Status: -1=Revoked, 0=Invited, 1=Accepted, 2=Confirmed
+-- Type: 0=Owner, 1=Admin, 2=User, 3=Manager, 4=Custom
+CREATE TABLE IF NOT EXISTS users_organizations (
+  id TEXT PRIMARY KEY NOT NULL,
+  organization_id TEXT NOT NULL,
+  user_id TEXT, -- NULL while the invited email has no account yet
+  email TEXT NOT NULL,
+  akey TEXT, -- org symmetric key encrypted with the member's public key (set on confirm)
+  status INTEGER NOT NULL DEFAULT 0,
+  type INTEGER NOT NULL DEFAULT 2,
+  reset_password_key TEXT, -- user key encrypted with the org public key (account recovery enrollment)
+  permissions INTEGER NOT NULL DEFAULT 0, -- bitset of permissions granted to Custom members
+  created_at TEXT NOT NULL,
+  updated_at TEXT NOT NULL,
+  FOREIGN KEY (organization_id) REFERENCES organizations(id) ON DELETE CASCADE,
+  FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_users_organizations_org_email
+  ON users_organizations(organization_id, email);
+CREATE INDEX IF NOT EXISTS idx_users_organizations_user_id
+  ON users_organizations(user_id);
+
+-- Organization collections
+CREATE TABLE IF NOT EXISTS collections (
+  id TEXT PRIMARY KEY NOT NULL,
+  organization_id TEXT NOT NULL,
+  name TEXT NOT NULL, -- encrypted with the org key
+  external_id TEXT,
+  created_at TEXT NOT NULL,
+  updated_at TEXT NOT NULL,
+  FOREIGN KEY (organization_id) REFERENCES organizations(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_collections_organization_id ON collections(organization_id);
+
+-- Which collections an organization cipher belongs to
+CREATE TABLE IF NOT EXISTS ciphers_collections (
+  cipher_id TEXT NOT NULL,
+  collection_id TEXT NOT NULL,
+  PRIMARY KEY (cipher_id, collection_id),
+  FOREIGN KEY (cipher_id) REFERENCES ciphers(id) ON DELETE CASCADE,
+  FOREIGN KEY (collection_id) REFERENCES collections(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_ciphers_collections_collection_id
+  ON ciphers_collections(collection_id);
+
+-- Per-member collection access (owners and admins implicitly access all collections)
+CREATE TABLE IF NOT EXISTS users_collections (
+  membership_id TEXT NOT NULL,
+  collection_id TEXT NOT NULL,
+  read_only INTEGER NOT NULL DEFAULT 0,
+  hide_passwords INTEGER NOT NULL DEFAULT 0,
+  manage INTEGER NOT NULL DEFAULT 0,
+  PRIMARY KEY (membership_id, collection_id),
+  FOREIGN KEY (membership_id) REFERENCES users_organizations(id) ON DELETE CASCADE,
+  FOREIGN KEY (collection_id) REFERENCES collections(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_users_collections_collection_id
+  ON users_collections(collection_id);
+
+-- Emergency access grants
+-- Type: 0=View, 1=Takeover
+-- Status: 0=Invited, 1=Accepted, 2=Confirmed, 3=RecoveryInitiated, 4=RecoveryApproved
+CREATE TABLE IF NOT EXISTS emergency_access (
+  id TEXT PRIMARY KEY NOT NULL,
+  grantor_id TEXT NOT NULL,
+  grantee_id TEXT, -- NULL while the invited email has no account yet
+  email TEXT NOT NULL,
+  key_encrypted TEXT, -- grantor's user key encrypted with the grantee's public key (set on confirm)
+  type INTEGER NOT NULL,
+  status INTEGER NOT NULL DEFAULT 0,
+  wait_time_days INTEGER NOT NULL,
+  recovery_initiated_at TEXT,
+  last_notification_at TEXT,
+  created_at TEXT NOT NULL,
+  updated_at TEXT NOT NULL,
+  FOREIGN KEY (grantor_id) REFERENCES users(id) ON DELETE CASCADE,
+  FOREIGN KEY (grantee_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_emergency_access_grantor_email
+  ON emergency_access(grantor_id, email);
+CREATE INDEX IF NOT EXISTS idx_emergency_access_grantee_id ON emergency_access(grantee_id);
+CREATE INDEX IF NOT EXISTS idx_emergency_access_status ON emergency_access(status);
+
+-- Rotating refresh tokens (only the SHA-256 hash of each token is stored)
+-- Tokens issued by one login share a family_id; reusing a rotated token revokes the family.
+CREATE TABLE IF NOT EXISTS refresh_tokens (
+  id TEXT PRIMARY KEY NOT NULL,
+  token_hash TEXT NOT NULL UNIQUE,
+  family_id TEXT NOT NULL,
+  user_id TEXT NOT NULL,
+  device_identifier TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  expires_at TEXT NOT NULL,
+  used_at TEXT,
+  revoked_at TEXT,
+  session_started_at TEXT, -- created_at of the family's first token
+  FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+  FOREIGN KEY (device_identifier, user_id) REFERENCES devices(identifier, user_id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_refresh_tokens_family_id ON refresh_tokens(family_id);
+CREATE INDEX IF NOT EXISTS idx_refresh_tokens_device ON refresh_tokens(user_id, device_identifier);
+CREATE INDEX IF NOT EXISTS idx_refresh_tokens_expires_at ON refresh_tokens(expires_at);
+
+-- Passkeys registered for passwordless login. When the authenticator supports the
+-- PRF extension the client also stores a key pair derived from it, so the vault can
+-- be decrypted without the master password.
+CREATE TABLE IF NOT EXISTS passkeys (
+  id TEXT PRIMARY KEY NOT NULL,
+  user_id TEXT NOT NULL,
+  name TEXT NOT NULL,
+  credential_id TEXT NOT NULL UNIQUE,
+  public_key TEXT NOT NULL, -- JWK JSON
+  algorithm INTEGER NOT NULL, -- COSE algorithm identifier
+  counter INTEGER NOT NULL DEFAULT 0,
+  supports_prf INTEGER NOT NULL DEFAULT 0,
+  encrypted_user_key TEXT, -- User key encrypted with the PRF public key
+  encrypted_public_key TEXT, -- PRF public key encrypted with the user key
+  encrypted_private_key TEXT, -- PRF private key encrypted with the PRF-derived key
+  created_at TEXT NOT NULL,
+  updated_at TEXT NOT NULL,
+  FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_passkeys_user_id ON passkeys(user_id);
+
+-- Organization event log
+-- event_type follows the Bitwarden EventType codes (1000=User_LoggedIn, 1100=Cipher_Created, ...)
+CREATE TABLE IF NOT EXISTS events (
+  id TEXT PRIMARY KEY NOT NULL,
+  event_type INTEGER NOT NULL,
+  organization_id TEXT NOT NULL,
+  user_id TEXT, -- user the event is about (login, membership changes)
+  acting_user_id TEXT, -- user who performed the action
+  cipher_id TEXT,
+  collection_id TEXT,
+  member_id TEXT, -- users_organizations.id
+  policy_id TEXT,
+  group_id TEXT,
+  device_type INTEGER,
+  ip_address TEXT,
+  event_date TEXT NOT NULL,
+  FOREIGN KEY (organization_id) REFERENCES organizations(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_events_org_date ON events(organization_id, event_date);
+CREATE INDEX IF NOT EXISTS idx_events_event_date ON events(event_date);
+
+-- Revision counters for delta sync (`/api/sync?since=<revision>`)
+-- Every write to a cipher or folder stamps the row with the next value of a global counter;
+-- deletions leave a tombstone. Membership and collection-access changes bump
+-- users.access_revision, which forces that user back to a full sync.
+CREATE TABLE IF NOT EXISTS sync_state (
+  id INTEGER PRIMARY KEY NOT NULL CHECK (id = 1),
+  revision INTEGER NOT NULL DEFAULT 0,
+  min_delta_revision INTEGER NOT NULL DEFAULT 0 -- tombstones up to here have been pruned
+);
+INSERT OR IGNORE INTO sync_state (id) VALUES (1);
+
+CREATE INDEX IF NOT EXISTS idx_ciphers_revision ON ciphers(revision);
+CREATE INDEX IF NOT EXISTS idx_folders_user_revision ON folders(user_id, revision);
+
+-- Deleted (or no longer shared) objects. object_type: 0=Cipher, 1=Folder
+CREATE TABLE IF NOT EXISTS sync_tombstones (
+  object_type INTEGER NOT NULL,
+  object_id TEXT NOT NULL,
+  user_id TEXT,
+  organization_id TEXT,
+  revision INTEGER NOT NULL,
+  deleted_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_sync_tombstones_revision ON sync_tombstones(revision);
+CREATE INDEX IF NOT EXISTS idx_sync_tombstones_deleted_at ON sync_tombstones(deleted_at);
+
+CREATE TRIGGER IF NOT EXISTS trg_ciphers_revision_insert AFTER INSERT ON ciphers
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE ciphers SET revision = (SELECT revision FROM sync_state WHERE id = 1) WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_ciphers_revision_update AFTER UPDATE ON ciphers
+WHEN NEW.revision = OLD.revision
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE ciphers SET revision = (SELECT revision FROM sync_state WHERE id = 1) WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_ciphers_tombstone AFTER DELETE ON ciphers
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  INSERT INTO sync_tombstones (object_type, object_id, user_id, organization_id, revision, deleted_at)
+  VALUES (0, OLD.id, OLD.user_id, OLD.organization_id,
+          (SELECT revision FROM sync_state WHERE id = 1), strftime('%Y-%m-%dT%H:%M:%fZ', 'now'));
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_folders_revision_insert AFTER INSERT ON folders
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE folders SET revision = (SELECT revision FROM sync_state WHERE id = 1) WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_folders_revision_update AFTER UPDATE ON folders
+WHEN NEW.revision = OLD.revision
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE folders SET revision = (SELECT revision FROM sync_state WHERE id = 1) WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_folders_tombstone AFTER DELETE ON folders
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  INSERT INTO sync_tombstones (object_type, object_id, user_id, organization_id, revision, deleted_at)
+  VALUES (1, OLD.id, OLD.user_id, NULL,
+          (SELECT revision FROM sync_state WHERE id = 1), strftime('%Y-%m-%dT%H:%M:%fZ', 'now'));
+END;
+
+-- Attachments are embedded in the cipher JSON, so they count as cipher changes.
+CREATE TRIGGER IF NOT EXISTS trg_attachments_revision_insert AFTER INSERT ON attachments
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE ciphers SET revision = (SELECT revision FROM sync_state WHERE id = 1) WHERE id = NEW.cipher_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_attachments_revision_delete AFTER DELETE ON attachments
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE ciphers SET revision = (SELECT revision FROM sync_state WHERE id = 1) WHERE id = OLD.cipher_id;
+END;
+
+-- Moving an org cipher between collections changes who can see it. Members who lost
+-- access find it in the tombstones; members who still see it get the updated row.
+CREATE TRIGGER IF NOT EXISTS trg_ciphers_collections_insert AFTER INSERT ON ciphers_collections
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE ciphers SET revision = (SELECT revision FROM sync_state WHERE id = 1) WHERE id = NEW.cipher_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_ciphers_collections_delete AFTER DELETE ON ciphers_collections
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  INSERT INTO sync_tombstones (object_type, object_id, user_id, organization_id, revision, deleted_at)
+  SELECT 0, c.id, NULL, c.organization_id,
+         (SELECT revision FROM sync_state WHERE id = 1), strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+  FROM ciphers c WHERE c.id = OLD.cipher_id;
+  UPDATE ciphers SET revision = (SELECT revision FROM sync_state WHERE id = 1) WHERE id = OLD.cipher_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_users_organizations_insert AFTER INSERT ON users_organizations
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE users SET access_revision = (SELECT revision FROM sync_state WHERE id = 1) WHERE id = NEW.user_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_users_organizations_update AFTER UPDATE ON users_organizations
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE users SET access_revision = (SELECT revision FROM sync_state WHERE id = 1)
+  WHERE id IN (NEW.user_id, OLD.user_id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_users_organizations_delete AFTER DELETE ON users_organizations
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE users SET access_revision = (SELECT revision FROM sync_state WHERE id = 1) WHERE id = OLD.user_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_users_collections_insert AFTER INSERT ON users_collections
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE users SET access_revision = (SELECT revision FROM sync_state WHERE id = 1)
+  WHERE id = (SELECT user_id FROM users_organizations WHERE id = NEW.membership_id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_users_collections_update AFTER UPDATE ON users_collections
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE users SET access_revision = (SELECT revision FROM sync_state WHERE id = 1)
+  WHERE id = (SELECT user_id FROM users_organizations WHERE id = NEW.membership_id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_users_collections_delete AFTER DELETE ON users_collections
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE users SET access_revision = (SELECT revision FROM sync_state WHERE id = 1)
+  WHERE id = (SELECT user_id FROM users_organizations WHERE id = OLD.membership_id);
+END;
+
+-- Organization policies
+-- atype follows the Bitwarden PolicyType codes (0=TwoFactorAuthentication, 1=MasterPassword, ...)
+-- data holds the policy options as JSON (e.g. the MasterPassword requirements)
+CREATE TABLE IF NOT EXISTS org_policies (
+  id TEXT PRIMARY KEY NOT NULL,
+  organization_id TEXT NOT NULL,
+  atype INTEGER NOT NULL,
+  enabled INTEGER NOT NULL DEFAULT 0,
+  data TEXT,
+  created_at TEXT NOT NULL,
+  updated_at TEXT NOT NULL,
+  UNIQUE (organization_id, atype),
+  FOREIGN KEY (organization_id) REFERENCES organizations(id) ON DELETE CASCADE
+);
+
+-- Organization groups. Members of a group get the group's collection access in
+-- addition to their own assignments.
+CREATE TABLE IF NOT EXISTS groups (
+    id TEXT PRIMARY KEY NOT NULL,
+    organization_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    external_id TEXT,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    FOREIGN KEY (organization_id) REFERENCES organizations(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_groups_organization_id ON groups(organization_id);
+
+CREATE TABLE IF NOT EXISTS groups_users (
+    group_id TEXT NOT NULL,
+    membership_id TEXT NOT NULL,
+    PRIMARY KEY (group_id, membership_id),
+    FOREIGN KEY (group_id) REFERENCES groups(id) ON DELETE CASCADE,
+    FOREIGN KEY (membership_id) REFERENCES users_organizations(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_groups_users_membership_id ON groups_users(membership_id);
+
+CREATE TABLE IF NOT EXISTS collections_groups (
+    collection_id TEXT NOT NULL,
+    group_id TEXT NOT NULL,
+    read_only INTEGER NOT NULL DEFAULT 0,
+    hide_passwords INTEGER NOT NULL DEFAULT 0,
+    manage INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (collection_id, group_id),
+    FOREIGN KEY (collection_id) REFERENCES collections(id) ON DELETE CASCADE,
+    FOREIGN KEY (group_id) REFERENCES groups(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_collections_groups_group_id ON collections_groups(group_id);
+
+-- Every (membership, collection) grant, direct or through a group. A member can have
+-- several rows for one collection; the most permissive one applies.
+CREATE VIEW IF NOT EXISTS member_collection_access AS
+SELECT membership_id, collection_id, read_only, hide_passwords, manage
+FROM users_collections
+UNION ALL
+SELECT gu.membership_id, cg.collection_id, cg.read_only, cg.hide_passwords, cg.manage
+FROM groups_users gu
+JOIN collections_groups cg ON cg.group_id = gu.group_id;
+
+-- Group changes alter what members can access, so they force a full sync like
+-- direct assignments do.
+CREATE TRIGGER IF NOT EXISTS trg_groups_users_insert AFTER INSERT ON groups_users
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE users SET access_revision = (SELECT revision FROM sync_state WHERE id = 1)
+  WHERE id = (SELECT user_id FROM users_organizations WHERE id = NEW.membership_id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_groups_users_delete AFTER DELETE ON groups_users
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE users SET access_revision = (SELECT revision FROM sync_state WHERE id = 1)
+  WHERE id = (SELECT user_id FROM users_organizations WHERE id = OLD.membership_id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_collections_groups_insert AFTER INSERT ON collections_groups
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE users SET access_revision = (SELECT revision FROM sync_state WHERE id = 1)
+  WHERE id IN (SELECT uo.user_id FROM groups_users gu
+               JOIN users_organizations uo ON uo.id = gu.membership_id
+               WHERE gu.group_id = NEW.group_id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_collections_groups_update AFTER UPDATE ON collections_groups
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE users SET access_revision = (SELECT revision FROM sync_state WHERE id = 1)
+  WHERE id IN (SELECT uo.user_id FROM groups_users gu
+               JOIN users_organizations uo ON uo.id = gu.membership_id
+               WHERE gu.group_id = NEW.group_id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_collections_groups_delete AFTER DELETE ON collections_groups
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE users SET access_revision = (SELECT revision FROM sync_state WHERE id = 1)
+  WHERE id IN (SELECT uo.user_id FROM groups_users gu
+               JOIN users_organizations uo ON uo.id = gu.membership_id
+               WHERE gu.group_id = OLD.group_id);
+END;
+
+-- Two-factor providers configured by an organization for its members (OrganizationDuo).
+CREATE TABLE IF NOT EXISTS organization_twofactor (
+  uuid TEXT PRIMARY KEY NOT NULL,
+  organization_id TEXT NOT NULL,
+  atype INTEGER NOT NULL,
+  enabled INTEGER NOT NULL DEFAULT 1,
+  data TEXT NOT NULL, -- JSON data specific to the 2FA type (e.g., Duo credentials)
+  created_at TEXT NOT NULL,
+  updated_at TEXT NOT NULL,
+  UNIQUE (organization_id, atype),
+  FOREIGN KEY (organization_id) REFERENCES organizations(id) ON DELETE CASCADE
+);
+
+-- Favorite flag and folder of an organization cipher, per member. Org ciphers are
+-- shared rows, so this state cannot live on the cipher; personal ciphers keep it there.
+CREATE TABLE IF NOT EXISTS users_ciphers (
+  user_id TEXT NOT NULL,
+  cipher_id TEXT NOT NULL,
+  favorite INTEGER NOT NULL DEFAULT 0,
+  folder_id TEXT,
+  PRIMARY KEY (user_id, cipher_id),
+  FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+  FOREIGN KEY (cipher_id) REFERENCES ciphers(id) ON DELETE CASCADE,
+  FOREIGN KEY (folder_id) REFERENCES folders(id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_users_ciphers_cipher_id ON users_ciphers(cipher_id);
+CREATE INDEX IF NOT EXISTS idx_users_ciphers_folder_id ON users_ciphers(folder_id);
+
+-- The state is part of the cipher JSON, so changing it counts as a cipher change.
+CREATE TRIGGER IF NOT EXISTS trg_users_ciphers_insert AFTER INSERT ON users_ciphers
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE ciphers SET revision = (SELECT revision FROM sync_state WHERE id = 1) WHERE id = NEW.cipher_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_users_ciphers_update AFTER UPDATE ON users_ciphers
+BEGIN
+  UPDATE sync_state SET revision = revision + 1 WHERE id = 1;
+  UPDATE ciphers SET revision = (SELECT revision FROM sync_state WHERE id = 1) WHERE id = NEW.cipher_id;
+END;
+
+-- R2 multipart upload sessions of large attachment and Send files. Rows outlive their
+-- user on purpose: the stale_multipart_uploads job still has to abort them in R2.
+CREATE TABLE IF NOT EXISTS multipart_uploads (
+  id TEXT PRIMARY KEY NOT NULL,
+  upload_id TEXT NOT NULL,
+  storage_key TEXT NOT NULL,
+  user_id TEXT NOT NULL,
+  target TEXT NOT NULL,
+  parent_id TEXT NOT NULL,
+  file_id TEXT NOT NULL,
+  file_size INTEGER NOT NULL,
+  part_size INTEGER NOT NULL,
+  created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_multipart_uploads_created_at ON multipart_uploads(created_at);
+
+-- Domains claimed by organizations, verified with a DNS TXT record
+CREATE TABLE IF NOT EXISTS organization_domains (
+  id TEXT PRIMARY KEY NOT NULL,
+  organization_id TEXT NOT NULL,
+  domain_name TEXT NOT NULL,
+  txt_record TEXT NOT NULL, -- Value the TXT record of the domain must have
+  verified_at TEXT,
+  last_checked_at TEXT,
+  created_at TEXT NOT NULL,
+  updated_at TEXT NOT NULL,
+  FOREIGN KEY (organization_id) REFERENCES organizations(id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_organization_domains_org_domain
+  ON organization_domains(organization_id, domain_name);
+CREATE INDEX IF NOT EXISTS idx_organization_domains_domain_name
+  ON organization_domains(domain_name);
+
+-- Links between users and their identity at the SSO identity provider
+CREATE TABLE IF NOT EXISTS sso_users (
+  user_id TEXT PRIMARY KEY NOT NULL,
+  identifier TEXT NOT NULL UNIQUE, -- `sub` claim of the identity provider
+  created_at TEXT NOT NULL,
+  FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
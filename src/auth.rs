@@ -59,7 +59,11 @@ impl FromRequestParts<Arc<Env>> for Claims {
             .and_then(bearer_token_from_header_value)
             .ok_or_else(|| AppError::Unauthorized("Missing or invalid token".to_string()))?;
 
-        decode_access_token(state.as_ref(), &token).await
+        let claims = decode_access_token(state.as_ref(), &token).await?;
+        if let Some(context) = parts.extensions.get::<crate::logging::RequestContext>() {
+            context.set_user(&claims.sub);
+        }
+        Ok(claims)
     }
 }
 
@@ -0,0 +1,653 @@
+//! Database backups to R2.
+//!
+//! [`create_backup`] dumps the tables holding account and vault data as SQL
+//! `INSERT` statements, gzips the dump and, when the `BACKUP_ENCRYPTION_KEY` secret
+//! is set, encrypts it in the format of `openssl enc -aes-256-cbc -pbkdf2 -iter
+//! 100000`. The file goes to the `BACKUP_BUCKET` R2 binding under `backups/`, named
+//! like the GitHub Actions backups, so the same decrypt and restore steps apply.
+//! After each backup only the newest `BACKUP_RETENTION_COUNT` files (default 7)
+//! are kept. Short-lived state (sessions, pending uploads, job bookkeeping) is not
+//! included; a restored database needs the schema applied first.
+//!
+//! [`restore_backup`] reads such a file back: it loads the inserts into `_restore_*`
+//! staging tables and then swaps them for the live tables in one D1 batch.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+use worker::{Bucket, Env, HttpMetadata};
+
+use crate::crypto;
+use crate::d1_query;
+use crate::db;
+use crate::error::AppError;
+use crate::handlers::get_env_usize;
+use crate::logging;
+use crate::migrations;
+use crate::prelogin_cache;
+
+pub(crate) const BACKUP_BUCKET: &str = "BACKUP_BUCKET";
+
+const KEY_PREFIX: &str = "backups/";
+
+const DEFAULT_RETENTION_COUNT: usize = 7;
+
+/// Rows fetched per query while dumping a table.
+const PAGE_SIZE: usize = 500;
+
+/// Tables included in a backup, parents before children.
+const TABLES: &[&str] = &[
+    "users",
+    "invitations",
+    "registration_tokens",
+    "twofactor",
+    "passkeys",
+    "sso_users",
+    "folders",
+    "organizations",
+    "organization_twofactor",
+    "users_organizations",
+    "collections",
+    "groups",
+    "ciphers",
+    "attachments",
+    "ciphers_collections",
+    "users_ciphers",
+    "users_collections",
+    "groups_users",
+    "collections_groups",
+    "org_policies",
+    "organization_domains",
+    "sends",
+    "emergency_access",
+    "events",
+];
+
+/// Tables emptied on restore without being in the backup (they are not all tied to users).
+const CLEARED_ON_RESTORE: &[&str] = &["sends_pending", "sync_tombstones"];
+
+/// Prefix of the tables a restore is staged in.
+const STAGING_PREFIX: &str = "_restore_";
+
+/// Statements per D1 batch while staging a restore.
+const STAGING_BATCH_SIZE: usize = 100;
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(extends = web_sys::TransformStream, js_name = CompressionStream)]
+    type CompressionStream;
+
+    #[wasm_bindgen(constructor, catch, js_class = "CompressionStream")]
+    fn new(format: &str) -> Result<CompressionStream, JsValue>;
+
+    #[wasm_bindgen(extends = web_sys::TransformStream, js_name = DecompressionStream)]
+    type DecompressionStream;
+
+    #[wasm_bindgen(constructor, catch, js_class = "DecompressionStream")]
+    fn new(format: &str) -> Result<DecompressionStream, JsValue>;
+}
+
+/// One backup file in the bucket.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Backup {
+    pub key: String,
+    pub size: u64,
+    pub uploaded_at: String,
+    pub encrypted: bool,
+}
+
+impl From<worker::Object> for Backup {
+    fn from(object: worker::Object) -> Self {
+        let key = object.key();
+        Backup {
+            encrypted: key.ends_with(".enc"),
+            size: object.size(),
+            uploaded_at: chrono::DateTime::from_timestamp_millis(
+                object.uploaded().as_millis() as i64
+            )
+            .map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Millis, true))
+            .unwrap_or_default(),
+            key,
+        }
+    }
+}
+
+fn bucket(env: &Env) -> Result<Bucket, AppError> {
+    env.bucket(BACKUP_BUCKET).map_err(|_| {
+        AppError::BadRequest(format!("Backups require the {BACKUP_BUCKET} R2 binding"))
+    })
+}
+
+fn encryption_key(env: &Env) -> Option<String> {
+    env.secret("BACKUP_ENCRYPTION_KEY")
+        .ok()
+        .map(|v| v.to_string())
+        .filter(|v| !v.is_empty())
+}
+
+/// SQL literal for a value as D1 returns it; blobs come back as arrays of bytes.
+fn sql_literal(value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::Bool(b) => if *b { "1" } else { "0" }.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => format!("'{}'", s.replace('\'', "''")),
+        Value::Array(items) => {
+            let bytes: Option<Vec<u8>> = items
+                .iter()
+                .map(|item| item.as_u64().and_then(|b| u8::try_from(b).ok()))
+                .collect();
+            match bytes {
+                Some(bytes) => format!("X'{}'", hex::encode(bytes)),
+                None => sql_literal(&Value::String(value.to_string())),
+            }
+        }
+        Value::Object(_) => sql_literal(&Value::String(value.to_string())),
+    }
+}
+
+fn push_insert(out: &mut String, table: &str, row: &Map<String, Value>) {
+    let columns: Vec<String> = row.keys().map(|c| format!("\"{c}\"")).collect();
+    let values: Vec<String> = row.values().map(sql_literal).collect();
+    out.push_str(&format!(
+        "INSERT INTO \"{table}\" ({}) VALUES ({});\n",
+        columns.join(", "),
+        values.join(", ")
+    ));
+}
+
+/// The backed-up tables as an SQL script, and the number of rows it inserts.
+async fn dump(db: &db::Db, created_at: &str) -> Result<(String, usize), AppError> {
+    let mut out = format!(
+        "-- Warden database backup, created {created_at}\n\
+         -- Restore into a database with the current schema and no data.\n\
+         PRAGMA defer_foreign_keys = on;\n"
+    );
+    let mut total = 0;
+    for table in TABLES {
+        let mut offset = 0;
+        loop {
+            let rows: Vec<Map<String, Value>> = db
+                .prepare(format!(
+                    "SELECT * FROM \"{table}\" ORDER BY rowid LIMIT ?1 OFFSET ?2"
+                ))
+                .bind(&[(PAGE_SIZE as f64).into(), (offset as f64).into()])?
+                .all()
+                .await
+                .map_err(|_| AppError::Database)?
+                .results()
+                .map_err(|_| AppError::Database)?;
+            for row in &rows {
+                push_insert(&mut out, table, row);
+            }
+            total += rows.len();
+            offset += rows.len();
+            if rows.len() < PAGE_SIZE {
+                break;
+            }
+        }
+    }
+    Ok((out, total))
+}
+
+/// Run `data` through a `CompressionStream` or `DecompressionStream`.
+async fn transform(data: &mut [u8], stream: &web_sys::TransformStream) -> Result<Vec<u8>, JsValue> {
+    let input = web_sys::Response::new_with_opt_u8_array(Some(data))?;
+    let body = input
+        .body()
+        .ok_or_else(|| JsValue::from_str("missing body"))?;
+    let output_stream = body.pipe_through(&web_sys::ReadableWritablePair::new(
+        &stream.readable(),
+        &stream.writable(),
+    ));
+    let output = web_sys::Response::new_with_opt_readable_stream(Some(&output_stream))?;
+    let buffer = JsFuture::from(output.array_buffer()?).await?;
+    Ok(js_sys::Uint8Array::new(&buffer).to_vec())
+}
+
+async fn gzip(data: &mut [u8]) -> Result<Vec<u8>, AppError> {
+    let result = match CompressionStream::new("gzip") {
+        Ok(stream) => transform(data, &stream).await,
+        Err(e) => Err(e),
+    };
+    result.map_err(|e| {
+        log::error!("Backup compression failed: {e:?}");
+        AppError::Internal
+    })
+}
+
+async fn gunzip(data: &mut [u8]) -> Result<Vec<u8>, AppError> {
+    let result = match DecompressionStream::new("gzip") {
+        Ok(stream) => transform(data, &stream).await,
+        Err(e) => Err(e),
+    };
+    result.map_err(|_| AppError::BadRequest("Backup is not a valid gzip file".to_string()))
+}
+
+/// Write a new backup to the bucket and rotate old ones.
+pub async fn create_backup(env: &Env) -> Result<Backup, AppError> {
+    let bucket = bucket(env)?;
+    let db = db::get_db(env)?;
+
+    let now = chrono::Utc::now();
+    let (sql, rows) = dump(&db, &now.to_rfc3339()).await?;
+    let mut data = gzip(&mut sql.into_bytes()).await?;
+
+    let mut key = format!(
+        "{KEY_PREFIX}vault_{}.sql.gz",
+        now.format("%Y-%m-%d_%H-%M-%S")
+    );
+    let content_type = match encryption_key(env) {
+        Some(passphrase) => {
+            data = crypto::openssl_encrypt_aes_256_cbc(&passphrase, &data).await?;
+            key.push_str(".enc");
+            "application/octet-stream"
+        }
+        None => "application/gzip",
+    };
+
+    let object = bucket
+        .put(key.clone(), data)
+        .http_metadata(HttpMetadata {
+            content_type: Some(content_type.to_string()),
+            ..Default::default()
+        })
+        .custom_metadata(HashMap::from([("rows".to_string(), rows.to_string())]))
+        .execute()
+        .await?
+        .ok_or(AppError::Internal)?;
+    let backup = Backup::from(object);
+
+    let retention = get_env_usize(env, "BACKUP_RETENTION_COUNT", DEFAULT_RETENTION_COUNT);
+    let removed = rotate(&bucket, retention).await?;
+
+    logging::event(
+        log::Level::Info,
+        "backup",
+        serde_json::json!({
+            "key": backup.key,
+            "rows": rows,
+            "bytes": backup.size,
+            "encrypted": backup.encrypted,
+            "removed": removed,
+        }),
+    );
+    Ok(backup)
+}
+
+/// Every backup in the bucket, oldest first.
+pub async fn list_backups(env: &Env) -> Result<Vec<Backup>, AppError> {
+    list_objects(&bucket(env)?)
+        .await
+        .map(|objects| objects.into_iter().map(Backup::from).collect())
+}
+
+async fn list_objects(bucket: &Bucket) -> Result<Vec<worker::Object>, AppError> {
+    let mut objects = Vec::new();
+    let mut cursor = None;
+    loop {
+        let mut list = bucket.list().prefix(KEY_PREFIX);
+        if let Some(cursor) = cursor.take() {
+            list = list.cursor(cursor);
+        }
+        let page = list.execute().await?;
+        objects.extend(page.objects());
+        match page.cursor() {
+            Some(next) if page.truncated() => cursor = Some(next),
+            _ => break,
+        }
+    }
+    // Keys embed the creation time, so name order is age order.
+    objects.sort_by_key(|object| object.key());
+    Ok(objects)
+}
+
+/// Delete all but the newest `keep` backups (0 keeps everything).
+async fn rotate(bucket: &Bucket, keep: usize) -> Result<usize, AppError> {
+    if keep == 0 {
+        return Ok(0);
+    }
+    let objects = list_objects(bucket).await?;
+    let excess = objects.len().saturating_sub(keep);
+    for object in &objects[..excess] {
+        bucket.delete(object.key()).await?;
+    }
+    Ok(excess)
+}
+
+/// Scheduled job: back up when `BACKUP_BUCKET` is bound, otherwise do nothing.
+pub async fn run_scheduled_backup(env: &Env) -> Result<u32, worker::Error> {
+    if env.bucket(BACKUP_BUCKET).is_err() {
+        return Ok(0);
+    }
+    create_backup(env)
+        .await
+        .map(|_| 1)
+        .map_err(|e| worker::Error::RustError(e.to_string()))
+}
+
+/// Outcome of [`restore_backup`].
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreReport {
+    pub key: String,
+    pub dry_run: bool,
+    /// Whether the live tables were replaced (never for a dry run).
+    pub restored: bool,
+    pub tables: Vec<RestoredTable>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoredTable {
+    pub table: &'static str,
+    pub rows: usize,
+}
+
+fn invalid_backup(reason: impl std::fmt::Display) -> AppError {
+    AppError::BadRequest(format!("Invalid backup: {reason}"))
+}
+
+/// The backup's inserts, rewritten to target the staging tables, with the row count per table.
+fn staging_inserts(sql: &str) -> Result<(Vec<String>, Vec<RestoredTable>), AppError> {
+    let mut tables: Vec<RestoredTable> = TABLES
+        .iter()
+        .map(|&table| RestoredTable { table, rows: 0 })
+        .collect();
+    let mut inserts = Vec::new();
+    let statements = migrations::split_statements(sql)
+        .map_err(|_| invalid_backup("the file ends inside a statement"))?;
+    for statement in statements {
+        if statement
+            .get(..6)
+            .is_some_and(|s| s.eq_ignore_ascii_case("PRAGMA"))
+        {
+            continue;
+        }
+        let (table, rest) = statement
+            .strip_prefix("INSERT INTO \"")
+            .and_then(|rest| rest.split_once('"'))
+            .ok_or_else(|| invalid_backup("only INSERT statements are allowed"))?;
+        let entry = tables
+            .iter_mut()
+            .find(|entry| entry.table == table)
+            .ok_or_else(|| invalid_backup(format!("unexpected table {table}")))?;
+        entry.rows += 1;
+        inserts.push(format!("INSERT INTO \"{STAGING_PREFIX}{table}\"{rest}"));
+    }
+    Ok((inserts, tables))
+}
+
+async fn drop_staging(db: &db::Db) -> Result<(), AppError> {
+    // Children first, so dropping a table never has staged rows referencing it.
+    let statements = TABLES
+        .iter()
+        .rev()
+        .map(|table| db.prepare(format!("DROP TABLE IF EXISTS \"{STAGING_PREFIX}{table}\"")))
+        .collect();
+    db::execute_in_batches(db, statements, 0).await
+}
+
+#[derive(serde::Deserialize)]
+struct SchemaEntry {
+    #[serde(rename = "type")]
+    kind: String,
+    name: String,
+    tbl_name: String,
+    sql: String,
+}
+
+/// Skip whitespace and one table or index name, quoted or not, returning the unquoted
+/// name and the rest of `sql`.
+fn take_name(sql: &str) -> Option<(&str, &str)> {
+    let sql = sql.trim_start();
+    let close = match sql.chars().next()? {
+        '"' => '"',
+        '`' => '`',
+        '[' => ']',
+        _ => {
+            let end = sql
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(sql.len());
+            return (end > 0).then(|| sql.split_at(end));
+        }
+    };
+    let end = sql[1..].find(close)? + 1;
+    Some((&sql[1..end], &sql[end + 1..]))
+}
+
+/// Position of `keyword` in `sql` as a whole word, ignoring case.
+fn find_keyword(sql: &str, keyword: &str) -> Option<usize> {
+    let upper = sql.to_ascii_uppercase();
+    let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    upper.match_indices(keyword).map(|(at, _)| at).find(|&at| {
+        !upper[..at].ends_with(is_word) && !upper[at + keyword.len()..].starts_with(is_word)
+    })
+}
+
+/// `CREATE TABLE` (or `CREATE UNIQUE INDEX`) of a staging table, from the live
+/// table's definition in `sqlite_master`.
+///
+/// The staging tables keep every constraint of the live ones, so loading a backup
+/// into them fails exactly when inserting it into the live tables would. Foreign
+/// keys between backed-up tables point at their staging copies.
+fn staging_definition(entry: &SchemaEntry) -> Option<String> {
+    let body = if entry.kind == "table" {
+        let open = entry.sql.find('(')?;
+        format!(
+            "CREATE TABLE \"{STAGING_PREFIX}{}\" {}",
+            entry.name,
+            &entry.sql[open..]
+        )
+    } else {
+        let on = find_keyword(&entry.sql, "ON")?;
+        let (_, rest) = take_name(&entry.sql[on + 2..])?;
+        format!(
+            "CREATE UNIQUE INDEX \"{STAGING_PREFIX}{}\" ON \"{STAGING_PREFIX}{}\"{rest}",
+            entry.name, entry.tbl_name
+        )
+    };
+
+    let mut sql = String::with_capacity(body.len());
+    let mut rest = body.as_str();
+    while let Some(at) = find_keyword(rest, "REFERENCES") {
+        let (head, tail) = rest.split_at(at + "REFERENCES".len());
+        sql.push_str(head);
+        let (name, tail) = take_name(tail)?;
+        if TABLES.contains(&name) {
+            sql.push_str(&format!(" \"{STAGING_PREFIX}{name}\""));
+        } else {
+            sql.push_str(&format!(" \"{name}\""));
+        }
+        rest = tail;
+    }
+    sql.push_str(rest);
+    Some(sql)
+}
+
+/// Create empty staging copies of the backed-up tables and load the backup into them.
+async fn stage(db: &db::Db, inserts: Vec<String>) -> Result<(), AppError> {
+    drop_staging(db).await?;
+    let tables = serde_json::to_string(TABLES).map_err(|_| AppError::Internal)?;
+    let entries: Vec<SchemaEntry> = d1_query!(
+        db,
+        "SELECT type, name, tbl_name, sql FROM sqlite_master
+         WHERE tbl_name IN (SELECT value FROM json_each(?1)) AND sql IS NOT NULL
+           AND (type = 'table' OR (type = 'index' AND sql LIKE 'CREATE UNIQUE INDEX%'))",
+        tables
+    )
+    .map_err(|_| AppError::Database)?
+    .all()
+    .await
+    .map_err(|_| AppError::Database)?
+    .results()
+    .map_err(|_| AppError::Database)?;
+    let mut create = Vec::with_capacity(entries.len());
+    // Tables in backup order, so referenced tables exist first; then their indexes.
+    for table in TABLES {
+        let entry = entries
+            .iter()
+            .find(|e| e.kind == "table" && e.name == *table)
+            .ok_or_else(|| {
+                log::error!("Table {table} is missing; apply the migrations before restoring");
+                AppError::Internal
+            })?;
+        create.push(entry);
+    }
+    create.extend(entries.iter().filter(|e| e.kind == "index"));
+    let create = create
+        .into_iter()
+        .map(|entry| {
+            staging_definition(entry)
+                .map(|sql| db.prepare(sql))
+                .ok_or_else(|| {
+                    log::error!("Cannot parse the definition of {}", entry.name);
+                    AppError::Internal
+                })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    db::execute_in_batches(db, create, 0).await?;
+
+    let statements = inserts.into_iter().map(|sql| db.prepare(sql)).collect();
+    db::execute_in_batches(db, statements, STAGING_BATCH_SIZE)
+        .await
+        .map_err(|e| match e {
+            AppError::Worker(e) => {
+                invalid_backup(format!("it does not match the current schema ({e})"))
+            }
+            e => e,
+        })
+}
+
+/// Replace the live tables with the staged ones in a single transaction.
+async fn swap(db: &db::Db) -> Result<(), AppError> {
+    let mut statements = vec![db.prepare("PRAGMA defer_foreign_keys = on")];
+    // Deleting users cascades to their devices, sessions and pending uploads.
+    for table in TABLES.iter().rev().chain(CLEARED_ON_RESTORE) {
+        statements.push(db.prepare(format!("DELETE FROM \"{table}\"")));
+    }
+    for table in TABLES {
+        statements.push(db.prepare(format!(
+            "INSERT INTO \"{table}\" SELECT * FROM \"{STAGING_PREFIX}{table}\""
+        )));
+    }
+    // No tombstones exist for the replaced rows, so every client needs a full sync.
+    statements.push(db.prepare("UPDATE sync_state SET min_delta_revision = revision"));
+    db.batch(statements).await?;
+    Ok(())
+}
+
+/// Restore the backup `key` into D1.
+///
+/// The backup is loaded into staging tables first, which checks that it parses and
+/// fits the current schema; a dry run stops there. Otherwise the live tables are
+/// replaced in one batch, so a failure leaves the database as it was.
+pub async fn restore_backup(
+    env: &Env,
+    key: &str,
+    dry_run: bool,
+) -> Result<RestoreReport, AppError> {
+    if !key.starts_with(KEY_PREFIX) {
+        return Err(AppError::NotFound("Backup not found".to_string()));
+    }
+    let object = bucket(env)?
+        .get(key)
+        .execute()
+        .await?
+        .ok_or_else(|| AppError::NotFound("Backup not found".to_string()))?;
+    let mut data = object
+        .body()
+        .ok_or_else(|| AppError::NotFound("Backup not found".to_string()))?
+        .bytes()
+        .await?;
+
+    if key.ends_with(".enc") {
+        let passphrase = encryption_key(env).ok_or_else(|| {
+            AppError::BadRequest(
+                "The backup is encrypted but BACKUP_ENCRYPTION_KEY is not set".to_string(),
+            )
+        })?;
+        data = crypto::openssl_decrypt_aes_256_cbc(&passphrase, &data)
+            .await
+            .map_err(|_| invalid_backup("it does not decrypt with BACKUP_ENCRYPTION_KEY"))?;
+    }
+    let sql = String::from_utf8(gunzip(&mut data).await?)
+        .map_err(|_| invalid_backup("the SQL is not valid UTF-8"))?;
+    let (inserts, tables) = staging_inserts(&sql)?;
+
+    let db = db::get_db(env)?;
+    let staged = stage(&db, inserts).await;
+    let swapped = match staged {
+        Ok(()) if !dry_run => swap(&db).await,
+        other => other,
+    };
+    if let Err(e) = drop_staging(&db).await {
+        log::warn!("Restore of {key}: failed to drop the staging tables: {e}");
+    }
+    swapped?;
+    if !dry_run {
+        prelogin_cache::clear(env).await;
+    }
+
+    logging::event(
+        log::Level::Warn,
+        "restore",
+        serde_json::json!({
+            "key": key,
+            "dryRun": dry_run,
+            "rows": tables.iter().map(|t| t.rows).sum::<usize>(),
+        }),
+    );
+    Ok(RestoreReport {
+        key: key.to_string(),
+        dry_run,
+        restored: !dry_run,
+        tables,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(kind: &str, name: &str, tbl_name: &str, sql: &str) -> SchemaEntry {
+        SchemaEntry {
+            kind: kind.to_string(),
+            name: name.to_string(),
+            tbl_name: tbl_name.to_string(),
+            sql: sql.to_string(),
+        }
+    }
+
+    #[test]
+    fn staging_table_keeps_constraints_and_references_staged_parents() {
+        let sql = staging_definition(&entry(
+            "table",
+            "folders",
+            "folders",
+            "CREATE TABLE folders (\n  id TEXT PRIMARY KEY NOT NULL,\n  revision INTEGER NOT NULL DEFAULT 0,\n  user_id TEXT NOT NULL,\n  FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,\n  FOREIGN KEY (id) REFERENCES \"other\" (id)\n)",
+        ));
+        assert_eq!(
+            sql.as_deref(),
+            Some("CREATE TABLE \"_restore_folders\" (\n  id TEXT PRIMARY KEY NOT NULL,\n  revision INTEGER NOT NULL DEFAULT 0,\n  user_id TEXT NOT NULL,\n  FOREIGN KEY (user_id) REFERENCES \"_restore_users\"(id) ON DELETE CASCADE,\n  FOREIGN KEY (id) REFERENCES \"other\" (id)\n)")
+        );
+    }
+
+    #[test]
+    fn staging_unique_index_targets_the_staging_table() {
+        let sql = staging_definition(&entry(
+            "index",
+            "idx_users_organizations_org_email",
+            "users_organizations",
+            "CREATE UNIQUE INDEX IF NOT EXISTS idx_users_organizations_org_email\n    ON users_organizations(organization_id, email)",
+        ));
+        assert_eq!(
+            sql.as_deref(),
+            Some("CREATE UNIQUE INDEX \"_restore_idx_users_organizations_org_email\" ON \"_restore_users_organizations\"(organization_id, email)")
+        );
+    }
+}
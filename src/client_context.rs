@@ -4,6 +4,11 @@ use crate::{error::AppError, models::device::DeviceType};
 
 const UNKNOWN_IP: &str = "unknown";
 const DEVICE_TYPE_HEADER_NAMES: [&str; 3] = ["device-type", "deviceType", "x-device-type"];
+const CLIENT_VERSION_HEADER: &str = "bitwarden-client-version";
+/// First client release that encrypts ciphers with their own key.
+const CIPHER_KEY_MIN_CLIENT_VERSION: (u32, u32, u32) = (2024, 2, 0);
+/// First client version that knows the SSH key cipher type; older ones fail to sync it.
+const SSH_KEY_MIN_CLIENT_VERSION: (u32, u32, u32) = (2024, 12, 0);
 
 pub fn request_ip_from_headers(headers: &HeaderMap) -> String {
     headers
@@ -22,6 +27,31 @@ pub fn request_device_type_from_headers(headers: &HeaderMap) -> i32 {
         .as_i32()
 }
 
+/// `Bitwarden-Client-Version` as `(year, month, patch)`, ignoring suffixes like `-beta`.
+pub fn request_client_version_from_headers(headers: &HeaderMap) -> Option<(u32, u32, u32)> {
+    let raw = header_value(headers, &[CLIENT_VERSION_HEADER])?;
+    let mut parts = raw
+        .split(|c: char| !c.is_ascii_digit())
+        .take(3)
+        .map(|part| part.parse::<u32>().ok());
+    let year = parts.next()??;
+    let month = parts.next()??;
+    let patch = parts.next().flatten().unwrap_or(0);
+    Some((year, month, patch))
+}
+
+/// Whether the client understands individual cipher keys. Clients that do not send
+/// their version (CLI scripts, third-party tools) are assumed to.
+pub fn client_supports_cipher_keys(headers: &HeaderMap) -> bool {
+    request_client_version_from_headers(headers)
+        .is_none_or(|version| version >= CIPHER_KEY_MIN_CLIENT_VERSION)
+}
+
+pub fn client_supports_ssh_keys(headers: &HeaderMap) -> bool {
+    request_client_version_from_headers(headers)
+        .is_none_or(|version| version >= SSH_KEY_MIN_CLIENT_VERSION)
+}
+
 pub fn parse_required_device_type(raw: Option<&str>, field_name: &str) -> Result<i32, AppError> {
     let value = raw
         .map(str::trim)
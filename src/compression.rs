@@ -0,0 +1,171 @@
+//! Response compression.
+//!
+//! [`compress_responses`] picks Brotli or gzip from the request's `Accept-Encoding`
+//! and marks text responses of at least `COMPRESSION_MIN_BYTES` (default 1024) with
+//! that `Content-Encoding`. The Workers runtime then compresses the body as it
+//! streams out, so a large sync is never held compressed in memory. Set
+//! `COMPRESSION_ENABLED` to `false` to send every response uncompressed.
+
+use std::sync::Arc;
+
+use axum::{
+    body::HttpBody,
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use worker::Env;
+
+use crate::handlers::{get_env_bool, get_env_usize};
+
+const DEFAULT_MIN_BYTES: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    fn header_value(self) -> HeaderValue {
+        HeaderValue::from_static(match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        })
+    }
+}
+
+/// The preferred encoding the client accepts, Brotli winning ties. `*` stands for
+/// encodings not listed by name; `None` when the client prefers `identity` over both.
+fn negotiate(headers: &HeaderMap) -> Option<Encoding> {
+    let accept = headers.get(header::ACCEPT_ENCODING)?.to_str().ok()?;
+    let (mut brotli, mut gzip, mut identity, mut any) = (None, None, None, None);
+    for item in accept.split(',') {
+        let mut parts = item.split(';');
+        let name = parts.next().unwrap_or_default().trim().to_ascii_lowercase();
+        let quality = parts
+            .filter_map(|p| p.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        match name.as_str() {
+            "br" => brotli = Some(quality),
+            "gzip" | "x-gzip" => gzip = Some(quality),
+            "identity" => identity = Some(quality),
+            "*" => any = Some(quality),
+            _ => {}
+        }
+    }
+    let brotli = brotli.or(any).unwrap_or(0.0);
+    let gzip = gzip.or(any).unwrap_or(0.0);
+    let (encoding, quality) = if brotli >= gzip {
+        (Encoding::Brotli, brotli)
+    } else {
+        (Encoding::Gzip, gzip)
+    };
+    if quality <= 0.0 || identity.is_some_and(|q| q > quality) {
+        return None;
+    }
+    Some(encoding)
+}
+
+fn is_compressible(headers: &HeaderMap) -> bool {
+    let Some(content_type) = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+    else {
+        return false;
+    };
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    mime.starts_with("text/")
+        || mime == "application/json"
+        || mime.ends_with("+json")
+        || mime == "application/javascript"
+        || mime == "image/svg+xml"
+}
+
+/// Axum middleware compressing large text responses.
+pub async fn compress_responses(State(env): State<Arc<Env>>, req: Request, next: Next) -> Response {
+    let encoding =
+        if req.method() == Method::HEAD || !get_env_bool(&env, "COMPRESSION_ENABLED", true) {
+            None
+        } else {
+            negotiate(req.headers())
+        };
+    let mut response = next.run(req).await;
+    let Some(encoding) = encoding else {
+        return response;
+    };
+
+    let status = response.status();
+    if status == StatusCode::NO_CONTENT
+        || status == StatusCode::NOT_MODIFIED
+        || response.headers().contains_key(header::CONTENT_ENCODING)
+        || !is_compressible(response.headers())
+    {
+        return response;
+    }
+    // Bodies of unknown length are streamed and usually large.
+    let min_bytes = get_env_usize(&env, "COMPRESSION_MIN_BYTES", DEFAULT_MIN_BYTES) as u64;
+    if response
+        .body()
+        .size_hint()
+        .exact()
+        .is_some_and(|len| len < min_bytes)
+    {
+        return response;
+    }
+
+    let headers = response.headers_mut();
+    headers.insert(header::CONTENT_ENCODING, encoding.header_value());
+    headers.remove(header::CONTENT_LENGTH);
+    headers.append(header::VARY, HeaderValue::from_static("Accept-Encoding"));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accepting(value: &'static str) -> Option<Encoding> {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT_ENCODING, HeaderValue::from_static(value));
+        negotiate(&headers)
+    }
+
+    #[test]
+    fn highest_quality_wins() {
+        assert_eq!(accepting("gzip, deflate, br"), Some(Encoding::Brotli));
+        assert_eq!(accepting("br;q=0.5, gzip;q=0.8"), Some(Encoding::Gzip));
+        assert_eq!(accepting("gzip;q=0.8, br;q=0.8"), Some(Encoding::Brotli));
+        assert_eq!(accepting("x-gzip"), Some(Encoding::Gzip));
+        assert_eq!(accepting("deflate"), None);
+        assert_eq!(negotiate(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn zero_quality_refuses_an_encoding() {
+        assert_eq!(accepting("br;q=0, gzip"), Some(Encoding::Gzip));
+        assert_eq!(accepting("br;q=0, gzip;q=0"), None);
+    }
+
+    #[test]
+    fn wildcard_covers_unlisted_encodings() {
+        assert_eq!(accepting("*"), Some(Encoding::Brotli));
+        assert_eq!(accepting("br;q=0, *"), Some(Encoding::Gzip));
+        assert_eq!(accepting("gzip;q=0.5, *;q=0.1"), Some(Encoding::Gzip));
+        assert_eq!(accepting("*;q=0"), None);
+    }
+
+    #[test]
+    fn preferred_identity_disables_compression() {
+        assert_eq!(accepting("identity, gzip;q=0.5"), None);
+        assert_eq!(accepting("identity;q=0.5, gzip"), Some(Encoding::Gzip));
+        assert_eq!(accepting("identity, br"), Some(Encoding::Brotli));
+    }
+}
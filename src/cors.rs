@@ -0,0 +1,122 @@
+//! CORS for serving the web vault from another origin (e.g. Cloudflare Pages).
+//!
+//! `CORS_ALLOWED_ORIGINS` lists the origins (comma-separated, like
+//! `https://vault.example.com`) whose pages may call the API; unset or `*` allows
+//! every origin. The clients authenticate with bearer tokens, never cookies, so no
+//! credentials mode is needed. Preflights are answered by [`layer`]; the streaming
+//! routes, which bypass axum, get their headers from [`apply`].
+
+use axum::http::{header, HeaderName, HeaderValue, Method};
+use std::time::Duration;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use worker::Env;
+
+/// How long browsers may cache a preflight answer.
+const PREFLIGHT_MAX_AGE_SECS: u64 = 86_400;
+
+/// Request headers the Bitwarden clients send.
+const ALLOWED_HEADERS: &[&str] = &[
+    "authorization",
+    "content-type",
+    "accept",
+    "accept-language",
+    "cache-control",
+    "pragma",
+    "expires",
+    "if-none-match",
+    "range",
+    "device-type",
+    "bitwarden-client-name",
+    "bitwarden-client-version",
+    "bitwarden-package-type",
+    "auth-email",
+    // Attachment and Send file uploads use the Azure blob protocol.
+    "x-ms-blob-type",
+    "x-ms-date",
+    "x-ms-version",
+];
+
+/// Response headers the web vault reads.
+const EXPOSED_HEADERS: &[&str] = &[
+    "x-request-id",
+    "etag",
+    "retry-after",
+    "content-disposition",
+    "content-range",
+    "accept-ranges",
+    "x-continuation-token",
+];
+
+const ALLOWED_METHODS: &[Method] = &[
+    Method::GET,
+    Method::POST,
+    Method::PUT,
+    Method::PATCH,
+    Method::DELETE,
+    Method::OPTIONS,
+];
+
+/// The configured origins, or `None` when every origin is allowed.
+fn allowed_origins(env: &Env) -> Option<Vec<String>> {
+    let origins: Vec<String> = env
+        .var("CORS_ALLOWED_ORIGINS")
+        .ok()?
+        .to_string()
+        .split(',')
+        .map(|origin| origin.trim().trim_end_matches('/').to_ascii_lowercase())
+        .filter(|origin| !origin.is_empty())
+        .collect();
+    if origins.is_empty() || origins.iter().any(|origin| origin == "*") {
+        None
+    } else {
+        Some(origins)
+    }
+}
+
+/// The CORS layer of the API router.
+pub fn layer(env: &Env) -> CorsLayer {
+    let allow_origin = match allowed_origins(env) {
+        None => AllowOrigin::any(),
+        Some(origins) => AllowOrigin::list(
+            origins
+                .iter()
+                .filter_map(|origin| HeaderValue::from_str(origin).ok()),
+        ),
+    };
+    CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods(ALLOWED_METHODS.to_vec())
+        .allow_headers(
+            ALLOWED_HEADERS
+                .iter()
+                .map(|name| HeaderName::from_static(name))
+                .collect::<Vec<_>>(),
+        )
+        .expose_headers(
+            EXPOSED_HEADERS
+                .iter()
+                .map(|name| HeaderName::from_static(name))
+                .collect::<Vec<_>>(),
+        )
+        .max_age(Duration::from_secs(PREFLIGHT_MAX_AGE_SECS))
+}
+
+/// Add the CORS headers [`layer`] would set to a response of a streaming route.
+pub fn apply(env: &Env, origin: Option<&str>, response: &mut worker::Response) {
+    let allow_origin = match (allowed_origins(env), origin) {
+        (None, _) => "*".to_string(),
+        (Some(origins), Some(origin)) if origins.contains(&origin.to_ascii_lowercase()) => {
+            origin.to_string()
+        }
+        _ => return,
+    };
+    let headers = response.headers_mut();
+    let _ = headers.set(header::ACCESS_CONTROL_ALLOW_ORIGIN.as_str(), &allow_origin);
+    let _ = headers.set(
+        header::ACCESS_CONTROL_EXPOSE_HEADERS.as_str(),
+        &EXPOSED_HEADERS.join(","),
+    );
+    if allow_origin != "*" {
+        let _ = headers.append(header::VARY.as_str(), header::ORIGIN.as_str());
+    }
+}
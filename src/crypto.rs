@@ -2,7 +2,7 @@ use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use constant_time_eq::constant_time_eq;
 use js_sys::Uint8Array;
 use pbkdf2::pbkdf2_hmac;
-use sha2::Sha256;
+use sha2::{Digest, Sha256};
 use wasm_bindgen::{JsCast, JsValue};
 use wasm_bindgen_futures::JsFuture;
 use web_sys::{Crypto, CryptoKey, SubtleCrypto};
@@ -187,7 +187,7 @@ pub fn generate_totp_secret() -> Result<String, AppError> {
 }
 
 /// Computes HMAC-SHA1 using Web Crypto API.
-async fn hmac_sha1(key: &[u8], data: &[u8]) -> Result<Vec<u8>, AppError> {
+pub async fn hmac_sha1(key: &[u8], data: &[u8]) -> Result<Vec<u8>, AppError> {
     let subtle = subtle_crypto()?;
 
     // Create algorithm object for HMAC with SHA-1
@@ -316,7 +316,257 @@ pub fn generate_recovery_code() -> Result<String, AppError> {
     Ok(base32_encode(&bytes.to_vec()))
 }
 
+/// Hash under which a two-factor recovery code is stored. Case, spaces and dashes
+/// are ignored, as clients display the code in groups.
+pub fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    sha256_hex(&normalized)
+}
+
+/// Generates a 30-character alphanumeric personal API key (client secret).
+pub fn generate_api_key() -> Result<String, AppError> {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+    // 248 is the largest multiple of 62 below 256; rejecting larger bytes avoids modulo bias.
+    let mut key = String::with_capacity(30);
+    while key.len() < 30 {
+        for byte in random_bytes(32)? {
+            if byte < 248 && key.len() < 30 {
+                key.push(ALPHABET[(byte % 62) as usize] as char);
+            }
+        }
+    }
+    Ok(key)
+}
+
+/// Generates a 6-digit numeric code for email two-factor login.
+pub fn generate_email_token() -> Result<String, AppError> {
+    let bytes = random_bytes(4)?;
+    let value = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    Ok(format!("{:06}", value % 1_000_000))
+}
+
+/// Hex-encoded SHA-256 digest, used to store short-lived codes.
+pub fn sha256_hex(input: &str) -> String {
+    hex::encode(Sha256::digest(input.as_bytes()))
+}
+
 /// Constant-time string comparison wrapper.
 pub fn ct_eq(a: &str, b: &str) -> bool {
     constant_time_eq(a.as_bytes(), b.as_bytes())
 }
+
+/// Generates `len` cryptographically secure random bytes.
+pub fn random_bytes(len: u32) -> Result<Vec<u8>, AppError> {
+    let crypto = get_crypto()?;
+    let bytes = Uint8Array::new_with_length(len);
+    crypto
+        .get_random_values_with_array_buffer_view(&bytes)
+        .map_err(|e| AppError::Crypto(format!("Failed to generate random bytes: {:?}", e)))?;
+
+    Ok(bytes.to_vec())
+}
+
+// ============================================================================
+// Signature verification (WebAuthn) using Web Crypto API
+// ============================================================================
+
+/// Public key algorithms accepted for WebAuthn credentials (COSE identifiers).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureAlgorithm {
+    /// ECDSA P-256 with SHA-256 (COSE -7)
+    Es256,
+    /// RSASSA-PKCS1-v1_5 with SHA-256 (COSE -257)
+    Rs256,
+}
+
+fn js_object(entries: &[(&str, &str)]) -> Result<js_sys::Object, AppError> {
+    let object = js_sys::Object::new();
+    for (key, value) in entries {
+        js_sys::Reflect::set(&object, &JsValue::from_str(key), &JsValue::from_str(value))
+            .map_err(|e| AppError::Crypto(format!("Failed to set {key}: {:?}", e)))?;
+    }
+    Ok(object)
+}
+
+/// Verifies `signature` over `data` with a public key given as a JWK JSON string.
+///
+/// ES256 signatures are expected in the ASN.1 DER form produced by authenticators.
+pub async fn verify_signature(
+    jwk: &str,
+    algorithm: SignatureAlgorithm,
+    signature: &[u8],
+    data: &[u8],
+) -> Result<bool, AppError> {
+    let subtle = subtle_crypto()?;
+
+    let (import_params, verify_params, signature) = match algorithm {
+        SignatureAlgorithm::Es256 => (
+            js_object(&[("name", "ECDSA"), ("namedCurve", "P-256")])?,
+            js_object(&[("name", "ECDSA"), ("hash", "SHA-256")])?,
+            ecdsa_der_to_raw(signature)?,
+        ),
+        SignatureAlgorithm::Rs256 => (
+            js_object(&[("name", "RSASSA-PKCS1-v1_5"), ("hash", "SHA-256")])?,
+            js_object(&[("name", "RSASSA-PKCS1-v1_5")])?,
+            signature.to_vec(),
+        ),
+    };
+
+    let key_data: js_sys::Object = js_sys::JSON::parse(jwk)
+        .map_err(|e| AppError::Crypto(format!("Invalid public key: {:?}", e)))?
+        .unchecked_into();
+    let key_usages = js_sys::Array::of1(&JsValue::from_str("verify"));
+
+    let crypto_key = JsFuture::from(
+        subtle
+            .import_key_with_object("jwk", &key_data, &import_params, false, &key_usages)
+            .map_err(|e| AppError::Crypto(format!("Public key import failed: {:?}", e)))?,
+    )
+    .await
+    .map_err(|e| AppError::Crypto(format!("Public key import await failed: {:?}", e)))?;
+
+    let verified = JsFuture::from(
+        subtle
+            .verify_with_object_and_u8_array_and_u8_array(
+                &verify_params,
+                &CryptoKey::from(crypto_key),
+                &signature,
+                data,
+            )
+            .map_err(|e| AppError::Crypto(format!("Signature verify failed: {:?}", e)))?,
+    )
+    .await
+    .map_err(|e| AppError::Crypto(format!("Signature verify await failed: {:?}", e)))?;
+
+    Ok(verified.as_bool().unwrap_or(false))
+}
+
+/// Converts an ASN.1 DER ECDSA P-256 signature into the raw `r || s` form expected by WebCrypto.
+fn ecdsa_der_to_raw(der: &[u8]) -> Result<Vec<u8>, AppError> {
+    fn invalid() -> AppError {
+        AppError::Crypto("Invalid ECDSA signature".to_string())
+    }
+
+    fn read_integer(input: &[u8]) -> Result<(&[u8], &[u8]), AppError> {
+        let (&tag, rest) = input.split_first().ok_or_else(invalid)?;
+        let (&len, rest) = rest.split_first().ok_or_else(invalid)?;
+        let len = len as usize;
+        if tag != 0x02 || rest.len() < len {
+            return Err(invalid());
+        }
+        let (value, rest) = rest.split_at(len);
+        // Strip the sign padding DER adds to values with the high bit set.
+        let start = value.iter().position(|&b| b != 0).unwrap_or(value.len());
+        Ok((&value[start..], rest))
+    }
+
+    if der.len() < 2 || der[0] != 0x30 {
+        return Err(invalid());
+    }
+    let (r, rest) = read_integer(&der[2..])?;
+    let (s, _) = read_integer(rest)?;
+    if r.len() > 32 || s.len() > 32 {
+        return Err(invalid());
+    }
+
+    let mut raw = vec![0u8; 64];
+    raw[32 - r.len()..32].copy_from_slice(r);
+    raw[64 - s.len()..].copy_from_slice(s);
+    Ok(raw)
+}
+
+// ============================================================================
+// Backup encryption using Web Crypto API
+// ============================================================================
+
+/// PBKDF2 iterations of [`openssl_encrypt_aes_256_cbc`], the Workers WebCrypto maximum.
+pub const OPENSSL_PBKDF2_ITERATIONS: u32 = 100_000;
+
+const OPENSSL_SALT_MAGIC: &[u8] = b"Salted__";
+
+/// Derives the AES key and IV OpenSSL uses for `-aes-256-cbc -pbkdf2`, imported for `usage`.
+async fn openssl_aes_256_cbc_key(
+    passphrase: &str,
+    salt: &[u8],
+    usage: &str,
+) -> Result<(CryptoKey, web_sys::AesCbcParams), AppError> {
+    // OpenSSL derives the key and the IV from a single PBKDF2 output.
+    let derived = webcrypto_pbkdf2_sha256(
+        passphrase.as_bytes(),
+        salt,
+        OPENSSL_PBKDF2_ITERATIONS,
+        (32 + 16) * 8,
+    )
+    .await?;
+    let (key, iv) = derived.split_at(32);
+
+    let key_array = Uint8Array::new_from_slice(key);
+    let crypto_key = JsFuture::from(
+        subtle_crypto()?
+            .import_key_with_str(
+                "raw",
+                key_array.as_ref(),
+                "AES-CBC",
+                false,
+                &js_sys::Array::of1(&JsValue::from_str(usage)),
+            )
+            .map_err(|e| AppError::Crypto(format!("AES import_key failed: {e:?}")))?,
+    )
+    .await
+    .map_err(|e| AppError::Crypto(format!("AES import_key await failed: {e:?}")))?;
+
+    let params =
+        web_sys::AesCbcParams::new_with_u8_array("AES-CBC", &Uint8Array::new_from_slice(iv));
+    Ok((CryptoKey::from(crypto_key), params))
+}
+
+/// Encrypts `data` like `openssl enc -aes-256-cbc -pbkdf2 -iter 100000 -pass pass:<passphrase>`.
+///
+/// The output is `Salted__`, an 8-byte random salt and the ciphertext, so it decrypts
+/// with the same command plus `-d`.
+pub async fn openssl_encrypt_aes_256_cbc(
+    passphrase: &str,
+    data: &[u8],
+) -> Result<Vec<u8>, AppError> {
+    let salt = random_bytes(8)?;
+    let (key, params) = openssl_aes_256_cbc_key(passphrase, &salt, "encrypt").await?;
+    let ciphertext = JsFuture::from(
+        subtle_crypto()?
+            .encrypt_with_object_and_u8_array(params.as_ref(), &key, data)
+            .map_err(|e| AppError::Crypto(format!("AES encrypt failed: {e:?}")))?,
+    )
+    .await
+    .map_err(|e| AppError::Crypto(format!("AES encrypt await failed: {e:?}")))?;
+
+    let mut out = Vec::with_capacity(16 + data.len() + 16);
+    out.extend_from_slice(OPENSSL_SALT_MAGIC);
+    out.extend_from_slice(&salt);
+    out.extend_from_slice(&Uint8Array::new(&ciphertext).to_vec());
+    Ok(out)
+}
+
+/// Reverses [`openssl_encrypt_aes_256_cbc`]. A wrong passphrase fails the padding check.
+pub async fn openssl_decrypt_aes_256_cbc(
+    passphrase: &str,
+    data: &[u8],
+) -> Result<Vec<u8>, AppError> {
+    let (salt, ciphertext) = data
+        .strip_prefix(OPENSSL_SALT_MAGIC)
+        .filter(|rest| rest.len() > 8)
+        .map(|rest| rest.split_at(8))
+        .ok_or_else(|| AppError::Crypto("Not an OpenSSL encrypted file".to_string()))?;
+    let (key, params) = openssl_aes_256_cbc_key(passphrase, salt, "decrypt").await?;
+    let plaintext = JsFuture::from(
+        subtle_crypto()?
+            .decrypt_with_object_and_u8_array(params.as_ref(), &key, ciphertext)
+            .map_err(|e| AppError::Crypto(format!("AES decrypt failed: {e:?}")))?,
+    )
+    .await
+    .map_err(|_| AppError::Crypto("Wrong key or corrupted file".to_string()))?;
+
+    Ok(Uint8Array::new(&plaintext).to_vec())
+}
@@ -1,6 +1,7 @@
 use crate::d1_query;
 use crate::error::AppError;
-use chrono::Utc;
+use crate::metrics::{self, Metric};
+use chrono::{DateTime, Utc};
 use worker::{D1Database, D1DatabaseSession, D1PreparedStatement, D1Result, Env, Error};
 
 /// Unified database handle that wraps either a raw `D1Database` or a `D1DatabaseSession`.
@@ -25,10 +26,19 @@ impl Db {
         &self,
         statements: Vec<D1PreparedStatement>,
     ) -> Result<Vec<D1Result>, Error> {
-        match self {
+        let size = match statements.len() {
+            0..=1 => "1",
+            2..=10 => "2-10",
+            11..=100 => "11-100",
+            _ => "100+",
+        };
+        let started = js_sys::Date::now();
+        let result = match self {
             Db::Raw(db) => db.batch(statements).await,
             Db::Session(s) => s.batch(statements).await,
-        }
+        };
+        metrics::observe(Metric::D1BatchMs, metrics::elapsed_ms(started), &[size]);
+        result
     }
 }
 
@@ -72,23 +82,35 @@ pub fn map_d1_json_error(err: Error) -> AppError {
 }
 
 pub fn now_string() -> String {
-    Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+    format_time(Utc::now())
+}
+
+/// Timestamp in the format stored in the database, "YYYY-MM-DDTHH:MM:SS.SSSZ".
+pub fn format_time(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
 }
 
 /// This is a helper function to update the user's `updated_at` field.
 /// `now` is the current timestamp in the format "YYYY-MM-DDTHH:MM:SS.SSSZ".
 /// This should be called after any operation that modifies user data (ciphers, folders, etc.)
 pub async fn touch_user_updated_at(db: &Db, user_id: &str, now: &str) -> Result<(), AppError> {
+    touch_user_statement(db, user_id, now)?.run().await?;
+    Ok(())
+}
+
+/// [`touch_user_updated_at`] as a statement, for callers that batch it with the change.
+pub fn touch_user_statement(
+    db: &Db,
+    user_id: &str,
+    now: &str,
+) -> Result<D1PreparedStatement, AppError> {
     d1_query!(
         db,
         "UPDATE users SET updated_at = ?1 WHERE id = ?2",
         now,
         user_id
     )
-    .map_err(|_| AppError::Database)?
-    .run()
-    .await?;
-    Ok(())
+    .map_err(|_| AppError::Database)
 }
 
 /// Execute D1 statements in batches, allowing batch_size 0 to run everything at once.
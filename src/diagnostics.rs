@@ -0,0 +1,307 @@
+//! Deployment overview for `GET /admin/diagnostics`: what is in the database, which
+//! bindings and settings are configured, and how the scheduled jobs last ran.
+//!
+//! Secrets are only reported as set or not. Setting values are shown as configured,
+//! except for passwords embedded in URLs.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use worker::{Env, Url};
+
+use crate::db;
+use crate::error::AppError;
+use crate::handlers::attachments::{ATTACHMENTS_BUCKET, ATTACHMENTS_KV};
+use crate::jobs::{self, JobStatus};
+use crate::migrations;
+use crate::webauthn::CACHE_KV;
+
+/// Settings read from `[vars]`.
+const SETTINGS: &[&str] = &[
+    "ACCOUNT_DELETION_GRACE_DAYS",
+    "ATTACHMENT_MAX_BYTES",
+    "ATTACHMENT_TOTAL_LIMIT_KB",
+    "ATTACHMENT_TTL_SECS",
+    "AUTHENTICATOR_DISABLE_TIME_DRIFT",
+    "AUTO_MIGRATE",
+    "BACKUP_RETENTION_COUNT",
+    "BASE_URL",
+    "CIPHERS_DEFAULT_ROW_QUERY",
+    "CLIENT_FEATURE_FLAGS",
+    "COMPRESSION_ENABLED",
+    "COMPRESSION_MIN_BYTES",
+    "CORS_ALLOWED_ORIGINS",
+    "D1_TRACE_ENABLED",
+    "D1_TRACE_SLOWEST",
+    "DISABLE_ICON_DOWNLOAD",
+    "DISABLE_USER_REGISTRATION",
+    "DNS_RESOLVER_URL",
+    "EVENTS_RETENTION_DAYS",
+    "HIBP_CACHE_TTL_SECONDS",
+    "HIBP_REQUESTS_PER_HOUR",
+    "ICON_CACHE_NEGTTL",
+    "ICON_CACHE_TTL",
+    "ICON_DOWNLOAD_TIMEOUT",
+    "IMPORT_BATCH_SIZE",
+    "INACTIVE_ACCOUNT_DISABLE_DAYS",
+    "JOB_LOCK_TTL_SECONDS",
+    "LARGE_REQUEST_BODY_MAX_BYTES",
+    "LOG_LEVEL",
+    "LOGIN_BACKOFF_MAX_SECONDS",
+    "LOGIN_FAILURES_BEFORE_BACKOFF",
+    "LOGIN_FAILURE_ALERT_THRESHOLD",
+    "MAIL_FROM",
+    "MAIL_HTTP_URL",
+    "MAIL_PROVIDER",
+    "METRICS_ENABLED",
+    "MULTIPART_PART_BYTES",
+    "MULTIPART_UPLOAD_THRESHOLD_BYTES",
+    "NEW_DEVICE_VERIFICATION",
+    "ORG_INVITATION_EXPIRATION_HOURS",
+    "ORG_STORAGE_QUOTA_KB",
+    "PASSWORD_HINT_RATE_LIMIT",
+    "PASSWORD_HINTS_ALLOWED",
+    "PASSWORD_ITERATIONS",
+    "PRELOGIN_CACHE_TTL_SECONDS",
+    "PURGE_BATCH_SIZE",
+    "PURGE_TIME_BUDGET_SECONDS",
+    "PUSH_ENABLED",
+    "PUSH_IDENTITY_URI",
+    "PUSH_RELAY_URI",
+    "REQUEST_BODY_MAX_BYTES",
+    "SEND_MAX_BYTES",
+    "SEND_PASSWORD_FAILURES_BEFORE_LOCKOUT",
+    "SEND_PASSWORD_LOCKOUT_MAX_SECONDS",
+    "SEND_TEXT_MAX_BYTES",
+    "SEND_TTL_SECS",
+    "SESSION_MAX_AGE_HOURS",
+    "SIGNUPS_ALLOWED",
+    "SIGNUPS_DOMAINS_WHITELIST",
+    "SIGNUPS_VERIFY",
+    "SSO_ALLOW_UNVERIFIED_EMAIL",
+    "SSO_AUTHORITY",
+    "SSO_CLIENT_ID",
+    "SSO_ORGANIZATION_ID",
+    "SSO_SCOPES",
+    "SYNC_RESPONSE_PREALLOC_BYTES",
+    "SYNC_TOMBSTONE_RETENTION_DAYS",
+    "TRASH_AUTO_DELETE_DAYS",
+    "TURNSTILE_SITE_KEY",
+    "TWOFACTOR_REMEMBER_DAYS",
+    "USER_SEND_LIMIT_KB",
+    "USER_STORAGE_QUOTA_KB",
+    "YUBICO_CLIENT_ID",
+    "YUBICO_SERVER",
+];
+
+/// Secrets, whose values are never reported.
+const SECRETS: &[&str] = &[
+    "ADMIN_TOKEN",
+    "ALLOWED_EMAILS",
+    "BACKUP_ENCRYPTION_KEY",
+    "HIBP_API_KEY",
+    "JWT_REFRESH_SECRET",
+    "JWT_SECRET",
+    "MAILCHANNELS_API_KEY",
+    "MAIL_HTTP_TOKEN",
+    "PUSH_INSTALLATION_ID",
+    "PUSH_INSTALLATION_KEY",
+    "RESEND_API_KEY",
+    "SSO_CLIENT_SECRET",
+    "TURNSTILE_SECRET_KEY",
+    "YUBICO_SECRET_KEY",
+];
+
+/// Binding of the deployment's version metadata (`[version_metadata]` in wrangler.toml).
+const VERSION_METADATA_BINDING: &str = "CF_VERSION_METADATA";
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Diagnostics {
+    pub version: VersionInfo,
+    pub database: DatabaseInfo,
+    pub bindings: Map<String, Value>,
+    pub settings: Map<String, Value>,
+    pub secrets: Map<String, Value>,
+    pub migrations: MigrationSummary,
+    pub jobs: Vec<JobStatus>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionInfo {
+    /// Version of this crate.
+    pub worker: &'static str,
+    /// The deployment, when the version metadata binding is configured.
+    pub deployment: Option<DeploymentVersion>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeploymentVersion {
+    pub id: String,
+    pub tag: Option<String>,
+    pub timestamp: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatabaseInfo {
+    /// Size of the D1 database in bytes, as reported by the last query.
+    pub size_bytes: Option<usize>,
+    /// Row count of every table.
+    pub tables: Map<String, Value>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationSummary {
+    pub applied: usize,
+    pub pending: Vec<&'static str>,
+}
+
+/// Hide the password of settings that are URLs with credentials.
+fn redact(value: String) -> String {
+    match Url::parse(&value) {
+        Ok(mut url) if url.password().is_some() => {
+            let _ = url.set_password(Some("redacted"));
+            url.to_string()
+        }
+        _ => value,
+    }
+}
+
+async fn database_info(db: &db::Db) -> Result<DatabaseInfo, AppError> {
+    #[derive(Deserialize)]
+    struct TableName {
+        name: String,
+    }
+    #[derive(Deserialize)]
+    struct TableRows {
+        name: String,
+        count: i64,
+    }
+
+    let names: Vec<TableName> = db
+        .prepare(
+            "SELECT name FROM sqlite_master
+             WHERE type = 'table' AND name NOT LIKE 'sqlite\\_%' ESCAPE '\\'
+               AND name NOT LIKE '\\_cf\\_%' ESCAPE '\\'
+             ORDER BY name",
+        )
+        .all()
+        .await
+        .map_err(|_| AppError::Database)?
+        .results()
+        .map_err(|_| AppError::Database)?;
+    if names.is_empty() {
+        return Ok(DatabaseInfo {
+            size_bytes: None,
+            tables: Map::new(),
+        });
+    }
+
+    let sql = names
+        .iter()
+        .map(|table| {
+            let quoted = table.name.replace('"', "\"\"");
+            format!(
+                "SELECT '{}' AS name, COUNT(*) AS count FROM \"{quoted}\"",
+                table.name.replace('\'', "''")
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" UNION ALL ");
+    let result = db
+        .prepare(&sql)
+        .all()
+        .await
+        .map_err(|_| AppError::Database)?;
+    let counts: Vec<TableRows> = result.results().map_err(|_| AppError::Database)?;
+    let size_bytes = result
+        .meta()
+        .ok()
+        .flatten()
+        .and_then(|meta| meta.size_after);
+
+    Ok(DatabaseInfo {
+        size_bytes,
+        tables: counts
+            .into_iter()
+            .map(|table| (table.name, Value::from(table.count)))
+            .collect(),
+    })
+}
+
+fn bindings(env: &Env) -> Map<String, Value> {
+    [
+        ("vault1", env.d1("vault1").is_ok()),
+        (CACHE_KV, env.kv(CACHE_KV).is_ok()),
+        (ATTACHMENTS_KV, env.kv(ATTACHMENTS_KV).is_ok()),
+        (ATTACHMENTS_BUCKET, env.bucket(ATTACHMENTS_BUCKET).is_ok()),
+        (
+            crate::backup::BACKUP_BUCKET,
+            env.bucket(crate::backup::BACKUP_BUCKET).is_ok(),
+        ),
+        ("HEAVY_DO", env.durable_object("HEAVY_DO").is_ok()),
+        ("NOTIFY_DO", env.durable_object("NOTIFY_DO").is_ok()),
+        (
+            "LOGIN_RATE_LIMITER",
+            env.rate_limiter("LOGIN_RATE_LIMITER").is_ok(),
+        ),
+        ("METRICS", env.analytics_engine("METRICS").is_ok()),
+    ]
+    .into_iter()
+    .map(|(name, bound)| (name.to_string(), Value::Bool(bound)))
+    .collect()
+}
+
+fn settings(env: &Env) -> Map<String, Value> {
+    SETTINGS
+        .iter()
+        .map(|name| {
+            let value = env
+                .var(name)
+                .ok()
+                .map(|v| Value::String(redact(v.to_string())))
+                .unwrap_or(Value::Null);
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
+fn secrets(env: &Env) -> Map<String, Value> {
+    SECRETS
+        .iter()
+        .map(|name| {
+            let set = env
+                .secret(name)
+                .ok()
+                .is_some_and(|v| !v.to_string().trim().is_empty());
+            (name.to_string(), Value::Bool(set))
+        })
+        .collect()
+}
+
+/// Collect the diagnostics of this deployment.
+pub async fn collect(env: &Env) -> Result<Diagnostics, AppError> {
+    let db = db::get_db(env)?;
+    let migration_status = migrations::status(env).await?;
+    Ok(Diagnostics {
+        version: VersionInfo {
+            worker: env!("CARGO_PKG_VERSION"),
+            deployment: env.object_var(VERSION_METADATA_BINDING).ok(),
+        },
+        database: database_info(&db).await?,
+        bindings: bindings(env),
+        settings: settings(env),
+        secrets: secrets(env),
+        migrations: MigrationSummary {
+            applied: migration_status.iter().filter(|m| m.applied).count(),
+            pending: migration_status
+                .iter()
+                .filter(|m| !m.applied)
+                .map(|m| m.name)
+                .collect(),
+        },
+        jobs: jobs::status(env).await?,
+    })
+}
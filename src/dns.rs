@@ -0,0 +1,86 @@
+//! TXT record lookups over DNS-over-HTTPS, for organization domain verification.
+//!
+//! Workers cannot send DNS queries directly, so lookups go to the JSON API of a
+//! DNS-over-HTTPS resolver (`DNS_RESOLVER_URL`, Cloudflare's by default).
+
+use serde::Deserialize;
+use web_sys::UrlSearchParams;
+use worker::{Env, Fetch, Method, Request, RequestInit};
+
+use crate::error::AppError;
+
+const DEFAULT_RESOLVER_URL: &str = "https://cloudflare-dns.com/dns-query";
+/// `type` of TXT records in DNS answers.
+const TXT_RECORD_TYPE: u16 = 16;
+
+#[derive(Deserialize)]
+struct DnsResponse {
+    #[serde(rename = "Status")]
+    status: u16,
+    #[serde(rename = "Answer", default)]
+    answer: Vec<DnsAnswer>,
+}
+
+#[derive(Deserialize)]
+struct DnsAnswer {
+    #[serde(rename = "type")]
+    record_type: u16,
+    data: String,
+}
+
+/// Undo the quoting of TXT data: `"a" "b"` (one record split in strings) becomes `ab`.
+fn unquote_txt(data: &str) -> String {
+    let data = data.trim();
+    if !data.starts_with('"') {
+        return data.to_string();
+    }
+    data.split('"')
+        .skip(1)
+        .step_by(2)
+        .collect::<Vec<_>>()
+        .concat()
+}
+
+/// TXT records of `name`; empty when the name does not exist.
+pub async fn txt_records(env: &Env, name: &str) -> Result<Vec<String>, AppError> {
+    let resolver = env
+        .var("DNS_RESOLVER_URL")
+        .ok()
+        .map(|v| v.to_string())
+        .filter(|v| !v.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_RESOLVER_URL.to_string());
+
+    let params = UrlSearchParams::new().map_err(|_| AppError::Internal)?;
+    params.append("name", name);
+    params.append("type", "TXT");
+    let query: String = params.to_string().into();
+
+    let mut init = RequestInit::new();
+    init.with_method(Method::Get);
+    let mut req = Request::new_with_init(&format!("{}?{query}", resolver.trim()), &init)?;
+    req.headers_mut()?.set("Accept", "application/dns-json")?;
+
+    let mut response = Fetch::Request(req).send().await?;
+    if response.status_code() != 200 {
+        log::warn!("DNS resolver returned {}", response.status_code());
+        return Err(AppError::BadRequest("DNS lookup failed".to_string()));
+    }
+    let result: DnsResponse = response.json().await.map_err(|e| {
+        log::error!("DNS resolver returned an unexpected response: {e}");
+        AppError::Internal
+    })?;
+
+    // 0 is NOERROR and 3 NXDOMAIN; anything else means the lookup did not complete.
+    match result.status {
+        0 | 3 => Ok(result
+            .answer
+            .iter()
+            .filter(|a| a.record_type == TXT_RECORD_TYPE)
+            .map(|a| unquote_txt(&a.data))
+            .collect()),
+        status => {
+            log::warn!("DNS lookup of {name} failed with status {status}");
+            Err(AppError::BadRequest("DNS lookup failed".to_string()))
+        }
+    }
+}
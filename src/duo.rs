@@ -0,0 +1,342 @@
+//! Duo two-factor login through the Duo Universal Prompt (Web SDK v4).
+//!
+//! This is Duo's OIDC flow, implemented directly against its API:
+//! - [`start_login`] signs an authorization request with the application's client
+//!   secret (HS512) and returns the Universal Prompt URL the clients open,
+//! - Duo sends the user back to the web vault's `duo-redirect-connector.html`, which
+//!   hands `<duo_code>|<state>` to the client as the two-factor token,
+//! - [`finish_login`] exchanges the code for an ID token and checks that it was
+//!   issued for this user and this login attempt.
+//!
+//! The state and nonce of an in-flight login are kept in `CACHE_KV` for five
+//! minutes and consumed on first use.
+
+use chrono::Duration;
+use jwt_compact::{
+    alg::{Hs512, Hs512Key},
+    AlgorithmExt, Claims as JwtClaims, Header, UntrustedToken,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use web_sys::UrlSearchParams;
+use worker::{Env, Fetch, Method, Request, RequestInit};
+
+use crate::auth::jwt_time_options;
+use crate::crypto::{ct_eq, random_bytes};
+use crate::error::AppError;
+use crate::webauthn::CACHE_KV;
+
+/// How long a started Duo login can be completed.
+const LOGIN_TTL_SECS: u64 = 300;
+
+const CLIENT_ASSERTION_TYPE: &str = "urn:ietf:params:oauth:client-assertion-type:jwt-bearer";
+
+/// Credentials of a Duo "Web SDK" application, stored as JSON in the two-factor row data.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DuoConfig {
+    pub client_id: String,
+    pub client_secret: String,
+    /// API hostname, e.g. `api-1234abcd.duosecurity.com`.
+    pub host: String,
+}
+
+impl DuoConfig {
+    pub fn from_data(data: &str) -> Result<Self, AppError> {
+        serde_json::from_str(data).map_err(|_| AppError::Internal)
+    }
+
+    /// Trim the fields and check they look like Duo application credentials.
+    pub fn new(client_id: &str, client_secret: &str, host: &str) -> Result<Self, AppError> {
+        let host = host
+            .trim()
+            .trim_start_matches("https://")
+            .trim_end_matches('/')
+            .to_ascii_lowercase();
+        let config = Self {
+            client_id: client_id.trim().to_string(),
+            client_secret: client_secret.trim().to_string(),
+            host,
+        };
+        if config.client_id.len() != 20
+            || config.client_secret.len() != 40
+            || !config.host.ends_with(".duosecurity.com")
+            || config.host.contains('/')
+        {
+            return Err(AppError::BadRequest(
+                "Invalid Duo client ID, client secret or API hostname".to_string(),
+            ));
+        }
+        Ok(config)
+    }
+
+    /// The client secret as shown to clients: its first characters, then asterisks.
+    pub fn masked_secret(&self) -> String {
+        mask_secret(&self.client_secret)
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("https://{}/oauth/v1/{path}", self.host)
+    }
+
+    fn key(&self) -> Hs512Key {
+        Hs512Key::new(self.client_secret.as_bytes())
+    }
+}
+
+pub fn mask_secret(secret: &str) -> String {
+    secret
+        .chars()
+        .enumerate()
+        .map(|(i, c)| if i < 6 { c } else { '*' })
+        .collect()
+}
+
+/// Where Duo sends the user back to, depending on the client (`client_id` of the login).
+pub fn redirect_uri(base_url: &str, client_id: &str) -> String {
+    let client = match client_id {
+        "browser" | "desktop" | "mobile" | "cli" => client_id,
+        _ => "web",
+    };
+    format!("{base_url}/duo-redirect-connector.html?client={client}")
+}
+
+/// Claims of the signed authorization request.
+#[derive(Serialize, Deserialize)]
+struct AuthorizeClaims {
+    response_type: String,
+    scope: String,
+    client_id: String,
+    redirect_uri: String,
+    state: String,
+    duo_uname: String,
+    iss: String,
+    aud: String,
+    nonce: String,
+    use_duo_code_attribute: bool,
+}
+
+/// Claims of the client assertion authenticating us to Duo's API.
+#[derive(Serialize, Deserialize)]
+struct AssertionClaims {
+    iss: String,
+    sub: String,
+    aud: String,
+    jti: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Deserialize)]
+struct IdTokenClaims {
+    iss: String,
+    aud: String,
+    nonce: Option<String>,
+    preferred_username: Option<String>,
+}
+
+/// State of a started login, stored under the `state` sent to Duo.
+#[derive(Serialize, Deserialize)]
+struct PendingLogin {
+    user_id: String,
+    nonce: String,
+}
+
+fn random_token() -> Result<String, AppError> {
+    Ok(hex::encode(random_bytes(24)?))
+}
+
+fn sign<T: Serialize>(config: &DuoConfig, claims: T) -> Result<String, AppError> {
+    let claims =
+        JwtClaims::new(claims).set_duration_and_issuance(&jwt_time_options(), Duration::minutes(5));
+    Hs512
+        .token(&Header::empty(), &claims, &config.key())
+        .map_err(|_| AppError::Crypto("Failed to sign Duo request".to_string()))
+}
+
+fn client_assertion(config: &DuoConfig, endpoint: &str) -> Result<String, AppError> {
+    sign(
+        config,
+        AssertionClaims {
+            iss: config.client_id.clone(),
+            sub: config.client_id.clone(),
+            aud: config.url(endpoint),
+            jti: random_token()?,
+        },
+    )
+}
+
+/// POST a form to a Duo OAuth endpoint, returning the JSON body of a 2xx response.
+async fn post_form(
+    config: &DuoConfig,
+    endpoint: &str,
+    fields: &[(&str, &str)],
+) -> Result<Value, AppError> {
+    let params = UrlSearchParams::new().map_err(|_| AppError::Internal)?;
+    for (name, value) in fields {
+        params.append(name, value);
+    }
+    params.append("client_assertion_type", CLIENT_ASSERTION_TYPE);
+    params.append("client_assertion", &client_assertion(config, endpoint)?);
+    let body: String = params.to_string().into();
+
+    let mut init = RequestInit::new();
+    init.with_method(Method::Post).with_body(Some(body.into()));
+    let mut req = Request::new_with_init(&config.url(endpoint), &init)?;
+    req.headers_mut()?
+        .set("Content-Type", "application/x-www-form-urlencoded")?;
+
+    let mut response = Fetch::Request(req).send().await?;
+    let status = response.status_code();
+    let body: Value = response.json().await.unwrap_or(Value::Null);
+    if !(200..300).contains(&status) {
+        log::warn!("Duo {endpoint} request failed ({status}): {body}");
+        return Err(AppError::BadRequest(
+            "Duo rejected the request; check the client ID, client secret and API hostname"
+                .to_string(),
+        ));
+    }
+    Ok(body)
+}
+
+/// Check the credentials against Duo before they are saved.
+pub async fn health_check(config: &DuoConfig) -> Result<(), AppError> {
+    let body = post_form(
+        config,
+        "health_check",
+        &[("client_id", config.client_id.as_str())],
+    )
+    .await?;
+    if body["stat"] != "OK" {
+        return Err(AppError::BadRequest("Duo is not available".to_string()));
+    }
+    Ok(())
+}
+
+/// Start a Duo login for `user_id`, returning the Universal Prompt URL.
+pub async fn start_login(
+    env: &Env,
+    config: &DuoConfig,
+    user_id: &str,
+    username: &str,
+    redirect_uri: &str,
+) -> Result<Value, AppError> {
+    let kv = env.kv(CACHE_KV).map_err(|_| {
+        AppError::BadRequest(format!(
+            "Duo requires the {CACHE_KV} KV namespace to be bound"
+        ))
+    })?;
+
+    let state = random_token()?;
+    let nonce = random_token()?;
+    let request = sign(
+        config,
+        AuthorizeClaims {
+            response_type: "code".to_string(),
+            scope: "openid".to_string(),
+            client_id: config.client_id.clone(),
+            redirect_uri: redirect_uri.to_string(),
+            state: state.clone(),
+            duo_uname: username.to_string(),
+            iss: config.client_id.clone(),
+            aud: format!("https://{}", config.host),
+            nonce: nonce.clone(),
+            use_duo_code_attribute: true,
+        },
+    )?;
+
+    let pending = serde_json::to_string(&PendingLogin {
+        user_id: user_id.to_string(),
+        nonce,
+    })
+    .map_err(|_| AppError::Internal)?;
+    kv.put(&login_key(&state), pending)
+        .map_err(|_| AppError::Internal)?
+        .expiration_ttl(LOGIN_TTL_SECS)
+        .execute()
+        .await
+        .map_err(|e| {
+            log::error!("KV put error for Duo login: {e}");
+            AppError::Internal
+        })?;
+
+    let params = UrlSearchParams::new().map_err(|_| AppError::Internal)?;
+    params.append("response_type", "code");
+    params.append("client_id", &config.client_id);
+    params.append("request", &request);
+    let query: String = params.to_string().into();
+    Ok(json!({ "AuthUrl": format!("{}?{query}", config.url("authorize")) }))
+}
+
+/// Verify the `<duo_code>|<state>` token the client sends back after the prompt.
+pub async fn finish_login(
+    env: &Env,
+    config: &DuoConfig,
+    user_id: &str,
+    username: &str,
+    token: &str,
+    redirect_uri: &str,
+) -> Result<(), AppError> {
+    let invalid = || AppError::BadRequest("Invalid Duo login".to_string());
+    let (code, state) = token.split_once('|').ok_or_else(invalid)?;
+
+    let kv = env.kv(CACHE_KV).map_err(|_| AppError::Internal)?;
+    let key = login_key(state);
+    let pending = kv
+        .get(&key)
+        .text()
+        .await
+        .map_err(|_| AppError::Internal)?
+        .ok_or_else(|| AppError::BadRequest("Duo login expired".to_string()))?;
+    kv.delete(&key).await.map_err(|_| AppError::Internal)?;
+    let pending: PendingLogin = serde_json::from_str(&pending).map_err(|_| AppError::Internal)?;
+    if pending.user_id != user_id {
+        return Err(invalid());
+    }
+
+    let body = post_form(
+        config,
+        "token",
+        &[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", redirect_uri),
+        ],
+    )
+    .await?;
+    let response: TokenResponse = serde_json::from_value(body).map_err(|_| invalid())?;
+
+    let token = UntrustedToken::new(&response.id_token).map_err(|_| invalid())?;
+    let token = Hs512
+        .validator::<IdTokenClaims>(&config.key())
+        .validate(&token)
+        .map_err(|_| invalid())?;
+    token
+        .claims()
+        .validate_expiration(&jwt_time_options())
+        .map_err(|_| invalid())?;
+
+    let claims = &token.claims().custom;
+    let username_matches = claims
+        .preferred_username
+        .as_deref()
+        .is_some_and(|name| name.eq_ignore_ascii_case(username));
+    let nonce_matches = claims
+        .nonce
+        .as_deref()
+        .is_some_and(|nonce| ct_eq(nonce, &pending.nonce));
+    if claims.iss != config.url("token")
+        || claims.aud != config.client_id
+        || !username_matches
+        || !nonce_matches
+    {
+        return Err(invalid());
+    }
+    Ok(())
+}
+
+fn login_key(state: &str) -> String {
+    format!("duo:login:{state}")
+}
@@ -1,9 +1,10 @@
+use std::sync::Arc;
+
 use axum::{extract::DefaultBodyLimit, Extension};
-use tower_http::cors::{Any, CorsLayer};
 use tower_service::Service;
 use worker::{durable_object, DurableObject, Env, HttpRequest, Request, Response, Result, State};
 
-use crate::{router, BaseUrl};
+use crate::{cors, request_limits, router, BaseUrl};
 
 /// Durable Object used to run CPU-heavy API flows with a higher CPU budget.
 ///
@@ -23,7 +24,8 @@ impl DurableObject for HeavyDo {
     async fn fetch(&self, req: Request) -> Result<Response> {
         // Set up logging/panic hook (idempotent).
         console_error_panic_hook::set_once();
-        let _ = console_log::init_with_level(log::Level::Debug);
+        crate::logging::init(&self.env);
+        crate::metrics::init(&self.env);
 
         // Keep fields used to avoid "unused" warnings even if we don't currently rely on them.
         let _ = &self.state;
@@ -39,20 +41,21 @@ impl DurableObject for HeavyDo {
             uri.authority().map(|a| a.as_str()).unwrap_or("localhost")
         );
 
-        // Allow all origins for CORS, matching the main worker.
-        let cors = CorsLayer::new()
-            .allow_methods(Any)
-            .allow_headers(Any)
-            .allow_origin(Any);
+        // Same CORS policy as the main worker (`CORS_ALLOWED_ORIGINS`, exposed headers).
+        let cors = cors::layer(&self.env);
 
-        // Match the main worker's default body limit (5MB) for regular API requests.
-        const BODY_LIMIT: usize = 5 * 1024 * 1024;
+        // Same body limits as the main worker; offloaded requests never pass through it.
+        let body_limit = request_limits::max_body_bytes(&self.env);
 
         // Reuse the existing router stack.
         let mut app = router::api_router(self.env.clone())
             .layer(Extension(BaseUrl(base_url)))
+            .layer(axum::middleware::from_fn_with_state(
+                Arc::new(self.env.clone()),
+                request_limits::limit_request_bodies,
+            ))
             .layer(cors)
-            .layer(DefaultBodyLimit::max(BODY_LIMIT));
+            .layer(DefaultBodyLimit::max(body_limit));
 
         let http_resp = app.call(http_req).await?;
 
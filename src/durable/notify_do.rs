@@ -46,7 +46,7 @@ impl DurableObject for NotifyDo {
 
     async fn fetch(&self, mut req: Request) -> Result<Response> {
         console_error_panic_hook::set_once();
-        let _ = console_log::init_with_level(log::Level::Debug);
+        crate::logging::init(&self.env);
 
         match (req.method(), req.path().as_str()) {
             (Method::Get, "/notifications/hub") | (Method::Get, "/hub") => {
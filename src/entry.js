@@ -80,11 +80,142 @@ async function getHeavyDoShardKey(request, url) {
   return null;
 }
 
+// D1 query tracing: every request gets a view of `env` whose D1 binding times each
+// query. The Rust request log reads the totals and the slowest queries from
+// `env.__D1_TRACE` (see `logging::request_finished`). The Workers clock only advances
+// during I/O, which is what a D1 round trip is, so the timings are real.
+const D1_BINDING = "vault1";
+const D1_TRACE_PROPERTY = "__D1_TRACE";
+const D1_TRACE_SQL_MAX_LENGTH = 200;
+const D1_TRACE_DEFAULT_SLOWEST = 3;
+const D1_QUERY_METHODS = new Set(["first", "all", "run", "raw"]);
+
+function compactSql(sql) {
+  const compact = String(sql).replace(/\s+/g, " ").trim();
+  return compact.length > D1_TRACE_SQL_MAX_LENGTH
+    ? `${compact.slice(0, D1_TRACE_SQL_MAX_LENGTH)}...`
+    : compact;
+}
+
+// Rows returned, read and written by one D1 call. `first()` resolves to a row, a
+// column value or null; `raw()` to an array of rows; `all()` and `run()` to a result
+// with `results` and `meta`.
+function resultRows(result) {
+  if (result == null) return { rows: 0, rowsRead: 0, rowsWritten: 0 };
+  if (Array.isArray(result)) return { rows: result.length, rowsRead: 0, rowsWritten: 0 };
+  if (typeof result === "object" && "success" in result && "meta" in result) {
+    return {
+      rows: Array.isArray(result.results) ? result.results.length : 0,
+      rowsRead: result.meta?.rows_read ?? 0,
+      rowsWritten: result.meta?.rows_written ?? 0,
+    };
+  }
+  return { rows: 1, rowsRead: 0, rowsWritten: 0 };
+}
+
+function newD1Trace(slowestCount) {
+  const trace = { queries: 0, ms: 0, rowsRead: 0, rowsWritten: 0, slowest: [] };
+  const record = (sql, ms, results) => {
+    let rows = 0;
+    for (const result of results) {
+      const counts = resultRows(result);
+      rows += counts.rows;
+      trace.rowsRead += counts.rowsRead;
+      trace.rowsWritten += counts.rowsWritten;
+    }
+    trace.queries += 1;
+    trace.ms += ms;
+    if (slowestCount > 0) {
+      trace.slowest.push({ sql: compactSql(sql), ms, rows });
+      trace.slowest.sort((a, b) => b.ms - a.ms);
+      trace.slowest.length = Math.min(trace.slowest.length, slowestCount);
+    }
+  };
+  return { trace, record };
+}
+
+// Traced statements, mapped to the statement and SQL they wrap so that `batch()` can
+// hand the originals to D1.
+const tracedStatements = new WeakMap();
+
+function bindMethod(target, prop, value) {
+  // The constructor stays as is: workers-rs checks its name to identify bindings.
+  return typeof value === "function" && prop !== "constructor" ? value.bind(target) : value;
+}
+
+// Run one D1 call and record it; a batch resolves to one result per statement.
+async function timed(record, sql, call, isBatch = false) {
+  const started = Date.now();
+  const result = await call();
+  record(sql, Date.now() - started, isBatch ? result : [result]);
+  return result;
+}
+
+function traceStatement(statement, sql, record) {
+  const traced = new Proxy(statement, {
+    get(target, prop) {
+      const value = Reflect.get(target, prop, target);
+      if (prop === "bind") {
+        return (...args) => traceStatement(value.apply(target, args), sql, record);
+      }
+      if (D1_QUERY_METHODS.has(prop)) {
+        return (...args) => timed(record, sql, () => value.apply(target, args));
+      }
+      return bindMethod(target, prop, value);
+    },
+  });
+  tracedStatements.set(traced, { statement, sql });
+  return traced;
+}
+
+function traceDatabase(db, record) {
+  return new Proxy(db, {
+    get(target, prop) {
+      const value = Reflect.get(target, prop, target);
+      if (prop === "withSession") {
+        return (...args) => traceDatabase(value.apply(target, args), record);
+      }
+      if (prop === "prepare") {
+        return (sql) => traceStatement(value.call(target, sql), sql, record);
+      }
+      if (prop === "batch") {
+        return (statements) => {
+          const originals = statements.map((s) => tracedStatements.get(s)?.statement ?? s);
+          const first = tracedStatements.get(statements[0])?.sql ?? "";
+          const sql = `batch(${statements.length}) ${first}`;
+          return timed(record, sql, () => value.call(target, originals), true);
+        };
+      }
+      return bindMethod(target, prop, value);
+    },
+  });
+}
+
+// `env` with a traced D1 binding, unless tracing is off (`D1_TRACE_ENABLED = "false"`).
+function traceEnv(env) {
+  const enabled = String(env.D1_TRACE_ENABLED ?? "true").trim().toLowerCase();
+  if (!env[D1_BINDING] || ["0", "false", "no", "off"].includes(enabled)) return env;
+
+  const slowest = Number.parseInt(env.D1_TRACE_SLOWEST ?? "", 10);
+  const { trace, record } = newD1Trace(
+    Number.isFinite(slowest) && slowest >= 0 ? slowest : D1_TRACE_DEFAULT_SLOWEST
+  );
+  const db = traceDatabase(env[D1_BINDING], record);
+  return new Proxy(env, {
+    get(target, prop) {
+      if (prop === D1_BINDING) return db;
+      if (prop === D1_TRACE_PROPERTY) return trace;
+      return Reflect.get(target, prop);
+    },
+  });
+}
+
 // All routes offloaded to HEAVY_DO (centralized, aligned with src/router.rs).
 // Keyed by path, with allowed methods to avoid accidental over-routing.
 const HEAVY_DO_ROUTE_METHODS = new Map([
   // Import
   ["/api/ciphers/import", new Set(["POST"])],
+  ["/api/ciphers/import-organization", new Set(["POST"])],
 
   // Identity/Auth (password hashing / verification)
   ["/identity/accounts/register", new Set(["POST"])],
@@ -92,6 +223,9 @@ const HEAVY_DO_ROUTE_METHODS = new Map([
 
   // Password/KDF changes
   ["/api/accounts/password", new Set(["POST"])],
+  ["/api/accounts/email-token", new Set(["POST"])],
+  ["/api/accounts/email", new Set(["POST"])],
+  ["/api/accounts/update-temp-password", new Set(["PUT"])],
   ["/api/accounts/kdf", new Set(["POST"])],
 
   // Dangerous ops requiring password verification
@@ -104,19 +238,40 @@ const HEAVY_DO_ROUTE_METHODS = new Map([
 
   // Key rotation needs verify master password and update entire vault
   ["/api/accounts/key-management/rotate-user-account-keys", new Set(["POST"])],
+  ["/api/accounts/key", new Set(["POST"])],
 
   // Two-factor
   ["/api/two-factor/get-authenticator", new Set(["POST"])],
   ["/api/two-factor/authenticator", new Set(["POST", "PUT", "DELETE"])],
   ["/api/two-factor/disable", new Set(["POST", "PUT"])],
   ["/api/two-factor/get-recover", new Set(["POST"])],
+
+  // Passkeys: starting a registration or assertion verifies the master password
+  ["/api/webauthn/attestation-options", new Set(["POST"])],
+  ["/api/webauthn/assertion-options", new Set(["POST"])],
 ]);
 
+// Same as above, for routes with path parameters.
+const HEAVY_DO_ROUTE_PATTERNS = [
+  // Organization deletion requires password verification
+  [/^\/api\/organizations\/[^/]+$/, new Set(["DELETE"])],
+  [/^\/api\/organizations\/[^/]+\/delete$/, new Set(["POST"])],
+  // Emergency access takeover re-hashes the grantor's new master password
+  [/^\/api\/emergency-access\/[^/]+\/password$/, new Set(["POST"])],
+  // Account recovery: enrollment verifies the master password, reset re-hashes it
+  [/^\/api\/organizations\/[^/]+\/users\/[^/]+\/reset-password-enrollment$/, new Set(["PUT"])],
+  [/^\/api\/organizations\/[^/]+\/users\/[^/]+\/reset-password$/, new Set(["PUT"])],
+  // Removing a passkey verifies the master password
+  [/^\/api\/webauthn\/[^/]+\/delete$/, new Set(["POST"])],
+];
+
 function shouldOffloadToHeavyDo(request, url) {
-  const methods = HEAVY_DO_ROUTE_METHODS.get(url.pathname);
-  if (!methods) return false;
   const method = (request.method || "GET").toUpperCase();
-  return methods.has(method);
+  const methods = HEAVY_DO_ROUTE_METHODS.get(url.pathname);
+  if (methods) return methods.has(method);
+  return HEAVY_DO_ROUTE_PATTERNS.some(
+    ([pattern, patternMethods]) => patternMethods.has(method) && pattern.test(url.pathname)
+  );
 }
 
 // Main fetch handler
@@ -170,7 +325,7 @@ export default {
     }
 
     // Pass all other requests to Rust WASM (streaming routes are intercepted in Rust)
-    const worker = new RustWorker(ctx, env);
+    const worker = new RustWorker(ctx, traceEnv(env));
     return worker.fetch(request);
   },
 
@@ -1,7 +1,9 @@
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
 use axum::Json;
-use serde_json::{json, Value};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -32,43 +34,171 @@ pub enum AppError {
 
     #[error("Two factor authentication required")]
     TwoFactorRequired(Value),
+
+    /// A captcha must be solved first; carries the site key the client renders.
+    #[error("Captcha required")]
+    CaptchaRequired(String),
+
+    /// A login from an unknown device must be confirmed with the code emailed to the account.
+    #[error("New device verification required")]
+    NewDeviceVerificationRequired,
 }
 
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
-        match self {
-            AppError::TwoFactorRequired(json_body) => {
-                // Return 400 Bad Request with the 2FA required JSON response as expected by clients
-                (StatusCode::BAD_REQUEST, Json(json_body)).into_response()
+/// The error body the Bitwarden clients parse, built from an [`AppError`].
+///
+/// API clients read `message` and `validationErrors`; the identity endpoint is read
+/// through `error`, `error_description` and `errorModel`. Every response carries
+/// both shapes, plus the provider data of 2FA- and captcha-required responses.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiError {
+    #[serde(skip)]
+    status: StatusCode,
+    message: String,
+    validation_errors: BTreeMap<String, Vec<String>>,
+    error_model: ErrorModel,
+    error: String,
+    #[serde(rename = "error_description")]
+    error_description: String,
+    exception_message: Option<String>,
+    exception_stack_trace: Option<String>,
+    inner_exception_message: Option<String>,
+    object: &'static str,
+    #[serde(flatten)]
+    extra: Map<String, Value>,
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorModel {
+    message: String,
+    object: &'static str,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        let message = message.into();
+        Self {
+            status,
+            validation_errors: BTreeMap::from([(String::new(), vec![message.clone()])]),
+            error_model: ErrorModel {
+                message: message.clone(),
+                object: "error",
+            },
+            error: message.clone(),
+            error_description: message.clone(),
+            message,
+            exception_message: None,
+            exception_stack_trace: None,
+            inner_exception_message: None,
+            object: "error",
+            extra: Map::new(),
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// An OAuth error of the identity endpoint, e.g. `invalid_grant`.
+    fn identity(mut self, error: &str) -> Self {
+        self.error = error.to_string();
+        self
+    }
+
+    /// Report the error against `field` instead of the request as a whole.
+    fn field(mut self, field: &str, message: impl Into<String>) -> Self {
+        self.validation_errors = BTreeMap::from([(field.to_string(), vec![message.into()])]);
+        self
+    }
+
+    /// Extra top-level properties; the model's own fields cannot be replaced.
+    fn extend(mut self, extra: Map<String, Value>) -> Self {
+        let own = [
+            "message",
+            "validationErrors",
+            "errorModel",
+            "error",
+            "error_description",
+            "exceptionMessage",
+            "exceptionStackTrace",
+            "innerExceptionMessage",
+            "object",
+        ];
+        self.extra.extend(
+            extra
+                .into_iter()
+                .filter(|(key, _)| !own.contains(&key.as_str())),
+        );
+        self
+    }
+}
+
+impl From<AppError> for ApiError {
+    fn from(error: AppError) -> Self {
+        match error {
+            AppError::Worker(e) => ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Worker error: {}", e),
+            ),
+            AppError::Database => {
+                ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+            }
+            AppError::NotFound(msg) => ApiError::new(StatusCode::NOT_FOUND, msg),
+            AppError::BadRequest(msg) => ApiError::new(StatusCode::BAD_REQUEST, msg),
+            AppError::Unauthorized(msg) => ApiError::new(StatusCode::UNAUTHORIZED, msg),
+            AppError::TooManyRequests(msg) => ApiError::new(StatusCode::TOO_MANY_REQUESTS, msg),
+            AppError::Crypto(msg) => ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Crypto error: {}", msg),
+            ),
+            AppError::Internal => {
+                ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
             }
-            other => {
-                let (status, error_message) = match other {
-                    AppError::Worker(e) => (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        format!("Worker error: {}", e),
-                    ),
-                    AppError::Database => (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        "Database error".to_string(),
-                    ),
-                    AppError::NotFound(msg) => (StatusCode::NOT_FOUND, msg),
-                    AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg),
-                    AppError::Unauthorized(msg) => (StatusCode::UNAUTHORIZED, msg),
-                    AppError::TooManyRequests(msg) => (StatusCode::TOO_MANY_REQUESTS, msg),
-                    AppError::Crypto(msg) => (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        format!("Crypto error: {}", msg),
-                    ),
-                    AppError::Internal => (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        "Internal server error".to_string(),
-                    ),
-                    AppError::TwoFactorRequired(_) => unreachable!(),
+            // The providers (`TwoFactorProviders`, `TwoFactorProviders2`, ...) as the
+            // clients expect them next to the error.
+            AppError::TwoFactorRequired(providers) => {
+                let providers = match providers {
+                    Value::Object(map) => map,
+                    _ => Map::new(),
                 };
-
-                let body = Json(json!({ "error": error_message }));
-                (status, body).into_response()
+                ApiError::new(StatusCode::BAD_REQUEST, "Two factor required.")
+                    .identity("invalid_grant")
+                    .extend(providers)
+            }
+            // The site key is read from the top level on login and from the
+            // validation errors on registration.
+            AppError::CaptchaRequired(site_key) => {
+                let mut extra = Map::new();
+                extra.insert("HCaptcha_SiteKey".to_string(), json!(site_key));
+                ApiError::new(StatusCode::BAD_REQUEST, "Captcha required.")
+                    .identity("invalid_grant")
+                    .field("HCaptcha_SiteKey", site_key)
+                    .extend(extra)
+            }
+            // The clients recognize this one by the message of a PascalCase `ErrorModel`.
+            AppError::NewDeviceVerificationRequired => {
+                let message = "new device verification required";
+                let mut extra = Map::new();
+                extra.insert(
+                    "ErrorModel".to_string(),
+                    json!({ "Message": message, "Object": "error" }),
+                );
+                ApiError::new(StatusCode::BAD_REQUEST, message)
+                    .identity("invalid_grant")
+                    .extend(extra)
             }
         }
     }
 }
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(self)).into_response()
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        ApiError::from(self).into_response()
+    }
+}
@@ -1,5 +1,9 @@
-use axum::{extract::State, http::HeaderMap, Json};
+use axum::{extract::State, http::HeaderMap, response::Response, Extension, Json};
+use chrono::{Duration, Utc};
 use glob_match::glob_match;
+use jwt_compact::AlgorithmExt;
+use jwt_compact::{alg::Hs256Key, Claims as JwtClaims, Header, UntrustedToken};
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::sync::Arc;
 use uuid::Uuid;
@@ -7,25 +11,47 @@ use worker::{D1PreparedStatement, Env};
 
 use crate::d1_query;
 
-use super::{get_batch_size, server_password_iterations, two_factor_enabled};
+use super::{get_env_usize, server_password_iterations, two_factor_enabled};
 use crate::{
-    auth::Claims,
-    crypto::{generate_salt, hash_password_for_storage},
+    auth::{jwt_time_options, Claims},
+    client_context::request_ip_from_headers,
+    crypto::{
+        generate_api_key, generate_email_token, generate_salt, hash_password_for_storage,
+        sha256_hex,
+    },
     db,
     error::AppError,
-    handlers::{attachments, sends},
+    handlers::{
+        attachments, get_env_bool,
+        policies::master_password_policy_json,
+        sends, storage, sync,
+        twofactor::{verify_email_token, EMAIL_TOKEN_RESEND_SECS, EMAIL_TOKEN_TTL_SECS},
+    },
+    mail,
     models::{
-        cipher::CipherData,
+        cipher::{CipherData, CipherRequestData},
         device::Device,
+        emergency_access::EmergencyAccess,
+        invitation::Invitation,
+        organization::Membership,
+        passkey::Passkey,
+        refresh_token::RefreshToken,
+        registration_token::RegistrationToken,
+        send::SendRequestData,
         sync::Profile,
+        twofactor::EmailTokenData,
         user::{
-            AvatarData, ChangeKdfRequest, ChangePasswordRequest, MasterPasswordUnlockData,
+            AvatarData, ChangeEmailRequest, ChangeKdfRequest, ChangePasswordRequest,
+            EmailTokenRequest, LegacyRotateKeyRequest, MasterPasswordUnlockData,
             PasswordHintRequest, PasswordOrOtpData, PreloginResponse, ProfileData, RegisterRequest,
-            RotateKeyRequest, User,
+            ResendNewDeviceOtpRequest, RotateFolderData, RotateKeyRequest,
+            UpdateTempPasswordRequest, User, VerifyEmailTokenRequest, VerifyOtpRequest,
         },
     },
     notifications::{self, UpdateType},
-    push,
+    prelogin_cache, push,
+    rate_limit::{self, LoginBackoff},
+    turnstile, BaseUrl,
 };
 
 const KDF_TYPE_PBKDF2: i32 = 0;
@@ -148,6 +174,21 @@ pub async fn prelogin(
         }
     }
 
+    // Addresses backing off after failed logins cannot probe accounts either.
+    let ip = request_ip_from_headers(&headers);
+    let retry_after = LoginBackoff::from_env(&env)
+        .retry_after(&env, &format!("ip:{ip}"))
+        .await;
+    if retry_after > 0 {
+        return Err(AppError::TooManyRequests(format!(
+            "Too many failed login attempts. Please try again in {retry_after} seconds."
+        )));
+    }
+
+    if let Some(cached) = prelogin_cache::get(&env, email).await {
+        return Ok(Json(cached));
+    }
+
     let db = db::get_db(&env)?;
 
     let stmt = db.prepare(
@@ -156,6 +197,7 @@ pub async fn prelogin(
     let query = stmt.bind(&[email.into()])?;
     let row: Option<Value> = query.first(None).await.map_err(|_| AppError::Database)?;
 
+    let found = row.is_some();
     let (kdf_type, kdf_iterations, kdf_memory, kdf_parallelism) = if let Some(row) = row {
         let kdf_type = row
             .get("kdf_type")
@@ -178,17 +220,66 @@ pub async fn prelogin(
         (None, None, None, None)
     };
 
-    Ok(Json(PreloginResponse {
+    let response = PreloginResponse {
         kdf: kdf_type.unwrap_or(KDF_TYPE_PBKDF2),
         kdf_iterations: kdf_iterations.unwrap_or(DEFAULT_PBKDF2_ITERATIONS),
         kdf_memory,
         kdf_parallelism,
-    }))
+    };
+    if found {
+        prelogin_cache::put(&env, email, &response).await;
+    }
+    Ok(Json(response))
+}
+
+/// Whether `email` matches one of the comma-separated globs in the `ALLOWED_EMAILS` secret.
+fn email_allowed(env: &Env, email: &str) -> bool {
+    let allowed_emails = env
+        .secret("ALLOWED_EMAILS")
+        .ok()
+        .and_then(|secret| secret.as_ref().as_string())
+        .unwrap_or_default();
+    allowed_emails
+        .split(',')
+        .filter(|pattern| !pattern.trim().is_empty())
+        .any(|pattern| glob_match(pattern.trim(), email))
+}
+
+/// Whether the domain of `email` is listed in `SIGNUPS_DOMAINS_WHITELIST` (comma-separated).
+fn email_domain_allowed(env: &Env, email: &str) -> bool {
+    let Some((_, domain)) = email.rsplit_once('@') else {
+        return false;
+    };
+    env.var("SIGNUPS_DOMAINS_WHITELIST")
+        .map(|value| value.to_string())
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .any(|allowed| !allowed.is_empty() && allowed.eq_ignore_ascii_case(domain))
+}
+
+/// Sign-up rules, checked in order:
+/// - addresses invited through the admin API can always register;
+/// - with `SIGNUPS_ALLOWED=false` nobody else can (invite-only);
+/// - otherwise the address must match `ALLOWED_EMAILS` or have a domain listed in
+///   `SIGNUPS_DOMAINS_WHITELIST`.
+///
+/// Addresses these rules turn away can still register with a registration token from
+/// the admin API (see [`RegistrationToken`]).
+async fn signup_allowed(env: &Env, db: &db::Db, email: &str) -> Result<bool, AppError> {
+    if Invitation::exists(db, email).await? {
+        return Ok(true);
+    }
+    if !get_env_bool(env, "SIGNUPS_ALLOWED", true) {
+        return Ok(false);
+    }
+    Ok(email_allowed(env, email) || email_domain_allowed(env, email))
 }
 
 #[worker::send]
 pub async fn register(
     State(env): State<Arc<Env>>,
+    Extension(BaseUrl(base_url)): Extension<BaseUrl>,
     headers: HeaderMap,
     Json(payload): Json<RegisterRequest>,
 ) -> Result<Json<Value>, AppError> {
@@ -208,19 +299,25 @@ pub async fn register(
         }
     }
 
-    let allowed_emails = env
-        .secret("ALLOWED_EMAILS")
-        .map_err(|_| AppError::Internal)?;
-    let allowed_emails = allowed_emails
-        .as_ref()
-        .as_string()
-        .ok_or_else(|| AppError::Internal)?;
-    if !allowed_emails
-        .split(',')
-        .any(|pattern| glob_match(pattern.trim(), &payload.email))
-    {
-        return Err(AppError::Unauthorized("Not allowed to signup".to_string()));
-    }
+    turnstile::verify(
+        &env,
+        payload.captcha_response.as_deref(),
+        None,
+        &request_ip_from_headers(&headers),
+    )
+    .await?;
+
+    let db = db::get_db(&env)?;
+
+    let email = payload.email.to_lowercase();
+    let registration_token = if signup_allowed(&env, &db, &email).await? {
+        None
+    } else {
+        match payload.registration_token.as_deref().map(str::trim) {
+            Some(token) if !token.is_empty() => Some(token.to_string()),
+            _ => return Err(AppError::Unauthorized("Not allowed to signup".to_string())),
+        }
+    };
 
     ensure_supported_kdf(
         payload.kdf,
@@ -239,7 +336,6 @@ pub async fn register(
     )
     .await?;
 
-    let db = db::get_db(&env)?;
     let now = db::now_string();
 
     // Only store kdf_memory and kdf_parallelism for Argon2id, clear for PBKDF2
@@ -253,10 +349,10 @@ pub async fn register(
         id: Uuid::new_v4().to_string(),
         name: payload.name,
         avatar_color: None,
-        email: payload.email.to_lowercase(),
+        email,
         email_verified: false,
         master_password_hash: hashed_password,
-        master_password_hint: payload.master_password_hint,
+        master_password_hint: stored_hint(&env, payload.master_password_hint),
         password_salt: Some(password_salt),
         password_iterations,
         key: payload.user_symmetric_key,
@@ -270,11 +366,25 @@ pub async fn register(
         equivalent_domains: "[]".to_string(),
         excluded_globals: "[]".to_string(),
         totp_recover: None,
+        api_key: None,
+        deletion_requested_at: None,
+        disabled: false,
+        force_password_reset: false,
+        last_login_at: None,
+        last_sync_at: None,
         created_at: now.clone(),
         updated_at: now,
     };
 
-    d1_query!(
+    if let Some(token) = &registration_token {
+        if !RegistrationToken::claim(&db, token, &user.email).await? {
+            return Err(AppError::Unauthorized(
+                "Invalid or expired registration token".to_string(),
+            ));
+        }
+    }
+
+    let inserted = d1_query!(
         &db,
         "INSERT INTO users (id, name, email, master_password_hash, master_password_hint, password_salt, password_iterations, key, private_key, public_key, kdf_type, kdf_iterations, kdf_memory, kdf_parallelism, security_stamp, equivalent_domains, excluded_globals, totp_recover, created_at, updated_at)
          VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20)",
@@ -305,7 +415,23 @@ pub async fn register(
     .await
     .map_err(|_|{
         AppError::Database
-    })?;
+    });
+    if let Some(token) = &registration_token {
+        match &inserted {
+            Ok(_) => RegistrationToken::record_user(&db, token, &user.id).await?,
+            Err(_) => RegistrationToken::release(&db, token).await?,
+        }
+    }
+    inserted?;
+
+    // Link invitations that were sent to this address before the account existed.
+    Membership::accept_invites_for_new_user(&db, &user.id, &user.email).await?;
+    EmergencyAccess::accept_invites_for_new_user(&db, &user.id, &user.email).await?;
+    Invitation::delete(&db, &user.email).await?;
+
+    if mail::mail_configured(&env) {
+        send_verification_link(&env, &base_url, &user)?;
+    }
 
     Ok(Json(json!({})))
 }
@@ -315,60 +441,259 @@ pub async fn send_verification_email() -> Result<Json<String>, AppError> {
     Ok(Json("fixed-token-to-mock".to_string()))
 }
 
+const VERIFY_EMAIL_TOKEN_ISSUER: &str = "warden-worker-verify-email";
+const VERIFY_EMAIL_TOKEN_TTL_HOURS: i64 = 24;
+/// Minimum delay between two verification emails sent on a refused login.
+const VERIFY_EMAIL_RESEND_SECS: u64 = 3600;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct VerifyEmailClaims {
+    sub: String,
+    email: String,
+    iss: String,
+}
+
+/// Whether unverified accounts are refused at login (`SIGNUPS_VERIFY`). Without email
+/// delivery nobody could verify, so the flag is ignored until mail is configured.
+pub(crate) fn email_verification_required(env: &Env) -> bool {
+    get_env_bool(env, "SIGNUPS_VERIFY", false) && mail::mail_configured(env)
+}
+
+fn build_verify_email_token(env: &Env, user: &User) -> Result<String, AppError> {
+    let claims = JwtClaims::new(VerifyEmailClaims {
+        sub: user.id.clone(),
+        email: user.email.clone(),
+        iss: VERIFY_EMAIL_TOKEN_ISSUER.to_string(),
+    })
+    .set_duration_and_issuance(
+        &jwt_time_options(),
+        Duration::hours(VERIFY_EMAIL_TOKEN_TTL_HOURS),
+    )
+    .set_not_before(Utc::now());
+
+    let secret = env.secret("JWT_SECRET")?.to_string();
+    let key = Hs256Key::new(secret.as_bytes());
+    jwt_compact::alg::Hs256
+        .token(&Header::empty(), &claims, &key)
+        .map_err(|_| AppError::Crypto("Failed to create verification token".to_string()))
+}
+
+/// Whether `raw_token` is an unexpired verification token for the current address of `user`.
+fn check_verify_email_token(env: &Env, user: &User, raw_token: &str) -> Result<bool, AppError> {
+    let secret = env.secret("JWT_SECRET")?.to_string();
+    let key = Hs256Key::new(secret.as_bytes());
+    let Ok(token) = UntrustedToken::new(raw_token) else {
+        return Ok(false);
+    };
+    let Ok(token) = jwt_compact::alg::Hs256
+        .validator::<VerifyEmailClaims>(&key)
+        .validate(&token)
+    else {
+        return Ok(false);
+    };
+    let time_options = jwt_time_options();
+    if token.claims().validate_expiration(&time_options).is_err()
+        || token.claims().validate_maturity(&time_options).is_err()
+    {
+        return Ok(false);
+    }
+
+    let claims = token.into_parts().1.custom;
+    Ok(claims.iss == VERIFY_EMAIL_TOKEN_ISSUER
+        && claims.sub == user.id
+        && claims.email == user.email)
+}
+
+/// Email `user` a link that verifies their address.
+pub(crate) fn send_verification_link(
+    env: &Env,
+    base_url: &str,
+    user: &User,
+) -> Result<(), AppError> {
+    let token = build_verify_email_token(env, user)?;
+    let link = format!(
+        "{}/#/verify-email?userId={}&token={}",
+        base_url.trim_end_matches('/'),
+        user.id,
+        token
+    );
+    mail::send_in_background(
+        env.clone(),
+        user.email.clone(),
+        mail::Template::VerifyEmail { link: &link },
+    );
+    Ok(())
+}
+
+/// Refuse logins of unverified accounts when `SIGNUPS_VERIFY` is set. A new link is
+/// sent at most once per hour so a user who lost the first one can still verify.
+pub(crate) async fn ensure_email_verified(
+    env: &Env,
+    base_url: &str,
+    user: &User,
+) -> Result<(), AppError> {
+    if user.email_verified || !email_verification_required(env) {
+        return Ok(());
+    }
+    let key = format!("verify-email:{}", user.id);
+    if rate_limit::kv_limit_exceeded(env, &key, 1, VERIFY_EMAIL_RESEND_SECS).await != Some(true) {
+        send_verification_link(env, base_url, user)?;
+    }
+    Err(AppError::BadRequest(
+        "Please verify your email address before logging in. A verification link has been \
+         sent to your inbox."
+            .to_string(),
+    ))
+}
+
+/// POST /api/accounts/verify-email
+#[worker::send]
+pub async fn post_verify_email(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Extension(BaseUrl(base_url)): Extension<BaseUrl>,
+) -> Result<Json<Value>, AppError> {
+    if !mail::mail_configured(&env) {
+        return Err(AppError::BadRequest(
+            "Email delivery is not configured on this server".to_string(),
+        ));
+    }
+    let db = db::get_db(&env)?;
+    let user = User::find_by_id(&db, &claims.sub)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+    if user.email_verified {
+        return Err(AppError::BadRequest(
+            "Your email address is already verified".to_string(),
+        ));
+    }
+
+    send_verification_link(&env, &base_url, &user)?;
+    Ok(Json(json!({})))
+}
+
+/// POST /api/accounts/verify-email-token
+#[worker::send]
+pub async fn post_verify_email_token(
+    State(env): State<Arc<Env>>,
+    Json(payload): Json<VerifyEmailTokenRequest>,
+) -> Result<Json<Value>, AppError> {
+    let invalid = || AppError::BadRequest("Invalid or expired verification link".to_string());
+    let db = db::get_db(&env)?;
+    let user = User::find_by_id(&db, &payload.user_id)
+        .await?
+        .ok_or_else(invalid)?;
+    if !check_verify_email_token(&env, &user, &payload.token)? {
+        return Err(invalid());
+    }
+    if user.email_verified {
+        return Ok(Json(json!({})));
+    }
+
+    let now = db::now_string();
+    d1_query!(
+        &db,
+        "UPDATE users SET email_verified = 1, updated_at = ?1 WHERE id = ?2",
+        &now,
+        &user.id
+    )
+    .map_err(|_| AppError::Database)?
+    .run()
+    .await
+    .map_err(|_| AppError::Database)?;
+
+    Ok(Json(json!({})))
+}
+
+/// Whether master password hints may be stored and emailed (`PASSWORD_HINTS_ALLOWED`).
+fn password_hints_allowed(env: &Env) -> bool {
+    get_env_bool(env, "PASSWORD_HINTS_ALLOWED", true)
+}
+
+/// The hint to store from a client request: none while hints are disabled.
+fn stored_hint(env: &Env, hint: Option<String>) -> Option<String> {
+    hint.filter(|_| password_hints_allowed(env))
+}
+
+/// Default number of password hint requests allowed per IP and hour.
+const DEFAULT_PASSWORD_HINT_RATE_LIMIT: usize = 5;
+
 /// POST /api/accounts/password-hint
 ///
-/// Bitwarden normally sends the master password hint via email. This project does not implement
-/// email delivery, so we return the hint directly.
+/// Email the master password hint to the account owner.
+///
+/// The hint is never part of the response, and the response is the same whether or not
+/// the account exists, so the endpoint cannot be used to enumerate accounts.
 #[worker::send]
 pub async fn password_hint(
     State(env): State<Arc<Env>>,
     headers: HeaderMap,
     Json(payload): Json<PasswordHintRequest>,
 ) -> Result<Json<Value>, AppError> {
-    // Basic rate limit by IP to slow down bulk email enumeration attempts.
-    if let Ok(rate_limiter) = env.rate_limiter("LOGIN_RATE_LIMITER") {
-        let ip = headers
-            .get("cf-connecting-ip")
-            .and_then(|v| v.to_str().ok())
-            .unwrap_or("unknown");
-        let rate_limit_key = format!("password-hint:{}", ip);
-        if let Ok(outcome) = rate_limiter.limit(rate_limit_key).await {
-            if !outcome.success {
-                return Err(AppError::TooManyRequests(
-                    "Too many requests. Please try again later.".to_string(),
-                ));
-            }
-        }
+    if !password_hints_allowed(&env) {
+        return Err(AppError::BadRequest(
+            "Password hints are disabled on this server".to_string(),
+        ));
+    }
+    if !mail::mail_configured(&env) {
+        return Err(AppError::BadRequest(
+            "Password hints can't be sent because email delivery is not configured".to_string(),
+        ));
     }
 
-    const NO_HINT: &str = "Sorry, you have no password hint...";
+    // Rate limit by IP to slow down bulk email enumeration and mail bombing.
+    let ip = request_ip_from_headers(&headers);
+    let rate_limit_key = format!("password-hint:{ip}");
+    let limit = get_env_usize(
+        &env,
+        "PASSWORD_HINT_RATE_LIMIT",
+        DEFAULT_PASSWORD_HINT_RATE_LIMIT,
+    );
+    let limited =
+        match rate_limit::kv_limit_exceeded(&env, &rate_limit_key, limit as u32, 3600).await {
+            Some(limited) => limited,
+            None => match env.rate_limiter("LOGIN_RATE_LIMITER") {
+                Ok(rate_limiter) => rate_limiter
+                    .limit(rate_limit_key)
+                    .await
+                    .is_ok_and(|outcome| !outcome.success),
+                Err(_) => false,
+            },
+        };
+    if limited {
+        return Err(AppError::TooManyRequests(
+            "Too many requests. Please try again later.".to_string(),
+        ));
+    }
 
     let db = db::get_db(&env)?;
-    let email = payload.email.to_lowercase();
+    let email = payload.email.trim().to_lowercase();
 
-    let hint: Option<String> = db
+    let hint: Option<Option<String>> = db
         .prepare("SELECT master_password_hint FROM users WHERE email = ?1")
-        .bind(&[email.into()])?
-        .first(Some("master_password_hint"))
+        .bind(&[email.clone().into()])?
+        .first::<Value>(None)
         .await
-        .map_err(|_| AppError::Database)?;
-
-    let hint = hint.and_then(|h| {
-        let trimmed = h.trim();
-        if trimmed.is_empty() {
-            None
-        } else {
-            Some(trimmed.to_string())
-        }
-    });
+        .map_err(|_| AppError::Database)?
+        .map(|row| {
+            row["master_password_hint"]
+                .as_str()
+                .map(str::trim)
+                .filter(|h| !h.is_empty())
+                .map(str::to_string)
+        });
 
     if let Some(hint) = hint {
-        return Err(AppError::BadRequest(format!(
-            "Your password hint is: {hint}"
-        )));
+        mail::send_in_background(
+            (*env).clone(),
+            email,
+            mail::Template::PasswordHint {
+                hint: hint.as_deref(),
+            },
+        );
     }
 
-    Err(AppError::BadRequest(NO_HINT.to_string()))
+    Ok(Json(json!({})))
 }
 
 #[worker::send]
@@ -411,10 +736,16 @@ pub async fn get_tasks() -> Result<Json<Value>, AppError> {
 pub async fn get_profile(
     claims: Claims,
     State(env): State<Arc<Env>>,
-) -> Result<Json<Profile>, AppError> {
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
     let db = db::get_db(&env)?;
     let user_id = claims.sub;
 
+    let etag = sync::user_etag(&db, &user_id, "profile").await?;
+    if sync::etag_matches(&headers, &etag) {
+        return Ok(sync::with_etag(&headers, &etag, ()));
+    }
+
     let user: User = db
         .prepare("SELECT * FROM users WHERE id = ?1")
         .bind(&[user_id.clone().into()])?
@@ -423,9 +754,12 @@ pub async fn get_profile(
         .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
 
     let two_factor_enabled = two_factor_enabled(&db, &user_id).await?;
-    let profile = Profile::from_user(user, two_factor_enabled)?;
+    let mut profile =
+        Profile::from_user(user, two_factor_enabled, email_verification_required(&env))?;
+    profile.organizations = Membership::profile_organizations_json(&db, &user_id).await?;
+    storage::fill_profile_storage(&env, &db, &mut profile).await?;
 
-    Ok(Json(profile))
+    Ok(sync::with_etag(&headers, &etag, Json(profile)))
 }
 
 #[worker::send]
@@ -439,6 +773,15 @@ pub async fn post_profile(
             "The field Name must be a string with a maximum length of 50.".to_string(),
         ));
     }
+    let hint = payload
+        .master_password_hint
+        .map(|hint| hint.trim().to_string());
+    if hint.as_ref().is_some_and(|hint| hint.chars().count() > 50) {
+        return Err(AppError::BadRequest(
+            "The field MasterPasswordHint must be a string with a maximum length of 50."
+                .to_string(),
+        ));
+    }
 
     let db = db::get_db(&env)?;
     let user_id = &claims.sub;
@@ -455,12 +798,19 @@ pub async fn post_profile(
     let now = db::now_string();
 
     user.name = Some(payload.name);
+    if !password_hints_allowed(&env) {
+        // Drop a hint stored before hints were disabled.
+        user.master_password_hint = None;
+    } else if let Some(hint) = hint {
+        user.master_password_hint = Some(hint).filter(|hint| !hint.is_empty());
+    }
     user.updated_at = now.clone();
 
     d1_query!(
         &db,
-        "UPDATE users SET name = ?1, updated_at = ?2 WHERE id = ?3",
+        "UPDATE users SET name = ?1, master_password_hint = ?2, updated_at = ?3 WHERE id = ?4",
         user.name,
+        user.master_password_hint,
         now,
         user_id
     )
@@ -470,7 +820,10 @@ pub async fn post_profile(
     .map_err(|_| AppError::Database)?;
 
     let two_factor_enabled = two_factor_enabled(&db, user_id).await?;
-    let profile = Profile::from_user(user, two_factor_enabled)?;
+    let mut profile =
+        Profile::from_user(user, two_factor_enabled, email_verification_required(&env))?;
+    profile.organizations = Membership::profile_organizations_json(&db, user_id).await?;
+    storage::fill_profile_storage(&env, &db, &mut profile).await?;
 
     notifications::publish_user_update(
         (*env).clone(),
@@ -499,7 +852,10 @@ pub async fn put_avatar(
     Json(payload): Json<AvatarData>,
 ) -> Result<Json<Profile>, AppError> {
     if let Some(color) = &payload.avatar_color {
-        if color.len() != 7 {
+        let is_hex_color = color.len() == 7
+            && color.starts_with('#')
+            && color[1..].chars().all(|c| c.is_ascii_hexdigit());
+        if !is_hex_color {
             return Err(AppError::BadRequest(
                 "The field AvatarColor must be a HTML/Hex color code with a length of 7 characters"
                     .to_string(),
@@ -537,7 +893,10 @@ pub async fn put_avatar(
     .map_err(|_| AppError::Database)?;
 
     let two_factor_enabled = two_factor_enabled(&db, user_id).await?;
-    let profile = Profile::from_user(user, two_factor_enabled)?;
+    let mut profile =
+        Profile::from_user(user, two_factor_enabled, email_verification_required(&env))?;
+    profile.organizations = Membership::profile_organizations_json(&db, user_id).await?;
+    storage::fill_profile_storage(&env, &db, &mut profile).await?;
 
     notifications::publish_user_update(
         (*env).clone(),
@@ -568,47 +927,72 @@ pub async fn delete_account(
         .map_err(|_| AppError::Database)?
         .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
     let user: User = serde_json::from_value(user).map_err(|_| AppError::Internal)?;
+    verify_user(&db, &user, &payload).await?;
 
-    // Verify the master password hash
-    let provided_hash = payload
-        .master_password_hash
-        .ok_or_else(|| AppError::BadRequest("Missing master password hash".to_string()))?;
+    // The account is disabled right away and its data wiped by the `deleted_accounts`
+    // job once the grace period has passed.
+    let now = db::now_string();
+    d1_query!(
+        &db,
+        "UPDATE users SET deletion_requested_at = ?1, security_stamp = ?2, updated_at = ?1 WHERE id = ?3",
+        &now,
+        Uuid::new_v4().to_string(),
+        user_id
+    )
+    .map_err(|_| AppError::Database)?
+    .run()
+    .await?;
 
-    let verification = user.verify_master_password(&provided_hash).await?;
+    push::unregister_push_devices_by_user(&env, user_id).await;
+    Device::delete_all_by_user(&db, user_id).await?;
 
-    if !verification.is_valid() {
-        return Err(AppError::Unauthorized("Invalid password".to_string()));
-    }
+    notifications::publish_user_logout((*env).clone(), claims.sub, now, None);
 
-    push::unregister_push_devices_by_user(&env, user_id).await;
+    Ok(Json(json!({})))
+}
 
-    if attachments::attachments_enabled(env.as_ref()) {
-        let keys = attachments::list_attachment_keys_for_user(&db, user_id).await?;
-        attachments::delete_storage_objects(env.as_ref(), &keys).await?;
+/// Permanently delete a user and everything they own, including stored files.
+pub(crate) async fn delete_user_data(
+    env: &Env,
+    db: &db::Db,
+    user_id: &str,
+) -> Result<(), AppError> {
+    if attachments::attachments_enabled(env) {
+        let keys = attachments::list_attachment_keys_for_user(db, user_id).await?;
+        attachments::delete_storage_objects(env, &keys).await?;
     }
 
     // Delete all user's sends and associated storage objects
-    sends::delete_user_sends(&db, env.as_ref(), user_id).await?;
+    sends::delete_user_sends(db, env, user_id).await?;
 
     // Delete all user's ciphers
-    d1_query!(&db, "DELETE FROM ciphers WHERE user_id = ?1", user_id)
+    d1_query!(db, "DELETE FROM ciphers WHERE user_id = ?1", user_id)
         .map_err(|_| AppError::Database)?
         .run()
         .await?;
 
     // Delete all user's folders
-    d1_query!(&db, "DELETE FROM folders WHERE user_id = ?1", user_id)
+    d1_query!(db, "DELETE FROM folders WHERE user_id = ?1", user_id)
         .map_err(|_| AppError::Database)?
         .run()
         .await?;
 
-    // Delete the user
-    d1_query!(&db, "DELETE FROM users WHERE id = ?1", user_id)
+    let email: Option<String> = d1_query!(db, "SELECT email FROM users WHERE id = ?1", user_id)
+        .map_err(|_| AppError::Database)?
+        .first(Some("email"))
+        .await
+        .map_err(|_| AppError::Database)?;
+
+    // Delete the user; devices, tokens and memberships cascade
+    d1_query!(db, "DELETE FROM users WHERE id = ?1", user_id)
         .map_err(|_| AppError::Database)?
         .run()
         .await?;
+    if let Some(email) = email {
+        prelogin_cache::invalidate(env, &email).await;
+    }
 
-    Ok(Json(json!({})))
+    Ok(())
 }
 
 /// POST /accounts/password - Change master password
@@ -657,12 +1041,12 @@ pub async fn post_password(
     // Update user record
     d1_query!(
         &db,
-        "UPDATE users SET master_password_hash = ?1, password_salt = ?2, password_iterations = ?3, key = ?4, master_password_hint = ?5, security_stamp = ?6, updated_at = ?7 WHERE id = ?8",
+        "UPDATE users SET master_password_hash = ?1, password_salt = ?2, password_iterations = ?3, key = ?4, master_password_hint = ?5, security_stamp = ?6, force_password_reset = 0, updated_at = ?7 WHERE id = ?8",
         new_hashed_password,
         new_salt,
         password_iterations,
         payload.key,
-        payload.master_password_hint,
+        stored_hint(&env, payload.master_password_hint),
         new_security_stamp,
         now,
         user_id
@@ -671,73 +1055,795 @@ pub async fn post_password(
     .run()
     .await?;
 
+    // The new security stamp invalidates every session; drop their refresh tokens too.
+    RefreshToken::revoke_all_by_user(&db, user_id).await?;
+
     notifications::publish_user_logout((*env).clone(), claims.sub, now, Some(claims.device));
 
     Ok(Json(json!({})))
 }
 
-/// POST /accounts/key-management/rotate-user-account-keys - Rotate user encryption keys
+/// PUT /accounts/update-temp-password - Set a new master password after an admin forced a reset
+///
+/// The client is already logged in with the old password, so it is not asked for again.
 #[worker::send]
-pub async fn post_rotatekey(
+pub async fn put_update_temp_password(
     claims: Claims,
     State(env): State<Arc<Env>>,
-    Json(payload): Json<RotateKeyRequest>,
+    Json(payload): Json<UpdateTempPasswordRequest>,
 ) -> Result<Json<Value>, AppError> {
     let db = db::get_db(&env)?;
     let user_id = &claims.sub;
-    let batch_size = get_batch_size(&env);
 
-    // Get the user from the database
-    let user: Value = db
+    let user: User = db
         .prepare("SELECT * FROM users WHERE id = ?1")
         .bind(&[user_id.clone().into()])?
         .first(None)
         .await
         .map_err(|_| AppError::Database)?
         .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
-    let user: User = serde_json::from_value(user).map_err(|_| AppError::Internal)?;
-
-    // Verify the current master password
-    let verification = user
-        .verify_master_password(&payload.old_master_key_authentication_hash)
-        .await?;
-
-    if !verification.is_valid() {
-        return Err(AppError::Unauthorized("Invalid password".to_string()));
+    if !user.force_password_reset {
+        return Err(AppError::BadRequest(
+            "No password reset was requested for this account".to_string(),
+        ));
     }
 
-    let unlock_data = &payload.account_unlock_data.master_password_unlock_data;
+    let new_salt = generate_salt()?;
+    let password_iterations = server_password_iterations(&env) as i32;
+    let new_hashed_password = hash_password_for_storage(
+        &payload.new_master_password_hash,
+        &new_salt,
+        password_iterations as u32,
+    )
+    .await?;
+    let now = db::now_string();
 
-    validate_rotation_metadata(&user, unlock_data, &payload.account_keys.account_public_key)?;
+    d1_query!(
+        &db,
+        "UPDATE users SET master_password_hash = ?1, password_salt = ?2, password_iterations = ?3, key = ?4, master_password_hint = ?5, security_stamp = ?6, force_password_reset = 0, updated_at = ?7 WHERE id = ?8",
+        new_hashed_password,
+        new_salt,
+        password_iterations,
+        payload.key,
+        stored_hint(&env, payload.master_password_hint),
+        Uuid::new_v4().to_string(),
+        now,
+        user_id
+    )
+    .map_err(|_| AppError::Database)?
+    .run()
+    .await?;
 
-    // Validate KDF parameters
-    ensure_supported_kdf(
-        unlock_data.kdf_type,
-        unlock_data.kdf_iterations,
-        unlock_data.kdf_memory,
-        unlock_data.kdf_parallelism,
-    )?;
+    RefreshToken::revoke_all_by_user(&db, user_id).await?;
 
-    // Validate data integrity using D1 batch operations
-    // Step 1: Ensure all personal ciphers have id (required for key rotation)
-    // Step 2: Count check - ensure request has exactly the same number of items as DB
-    // Step 3: EXCEPT check - ensure request has exactly the same IDs as DB
-    let personal_ciphers: Vec<_> = payload
-        .account_data
-        .ciphers
-        .into_iter()
-        .filter(|c| c.organization_id.is_none())
-        .collect();
+    notifications::publish_user_logout((*env).clone(), claims.sub, now, Some(claims.device));
 
-    let request_cipher_ids: Vec<String> = personal_ciphers
-        .iter()
-        .filter_map(|c| c.id.clone())
-        .collect();
+    Ok(Json(json!({})))
+}
 
-    // All personal ciphers must have an id for key rotation
-    if personal_ciphers.len() != request_cipher_ids.len() {
-        log::error!(
-            "All ciphers must have an id for key rotation: {:?} != {:?}",
+/// Load the user and verify their current master password hash.
+async fn verified_user(
+    db: &db::Db,
+    user_id: &str,
+    master_password_hash: &str,
+) -> Result<User, AppError> {
+    let user: Value = db
+        .prepare("SELECT * FROM users WHERE id = ?1")
+        .bind(&[user_id.into()])?
+        .first(None)
+        .await
+        .map_err(|_| AppError::Database)?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+    let user: User = serde_json::from_value(user).map_err(|_| AppError::Internal)?;
+
+    if !user
+        .verify_master_password(master_password_hash)
+        .await?
+        .is_valid()
+    {
+        return Err(AppError::Unauthorized("Invalid password".to_string()));
+    }
+    Ok(user)
+}
+
+/// Verify the user before a sensitive operation, with their master password hash or a
+/// code from `/accounts/request-otp`. A matching code is used up.
+pub(crate) async fn verify_user(
+    db: &db::Db,
+    user: &User,
+    data: &PasswordOrOtpData,
+) -> Result<(), AppError> {
+    if let Some(master_password_hash) = data.master_password_hash.as_deref() {
+        if user
+            .verify_master_password(master_password_hash)
+            .await?
+            .is_valid()
+        {
+            return Ok(());
+        }
+        return Err(AppError::Unauthorized("Invalid password".to_string()));
+    }
+    if let Some(otp) = data.otp.as_deref() {
+        return check_verification_otp(db, &user.id, otp, true).await;
+    }
+    Err(AppError::BadRequest(
+        "Master password or verification code required".to_string(),
+    ))
+}
+
+/// Check that `new_email` may replace the user's current address.
+async fn ensure_email_available(
+    env: &Env,
+    db: &db::Db,
+    user: &User,
+    new_email: &str,
+) -> Result<(), AppError> {
+    if new_email == user.email {
+        return Err(AppError::BadRequest(
+            "The new email is the same as the current one".to_string(),
+        ));
+    }
+    if User::find_by_email(db, new_email).await?.is_some() {
+        return Err(AppError::BadRequest("Email already in use".to_string()));
+    }
+    if !email_allowed(env, new_email) && !email_domain_allowed(env, new_email) {
+        return Err(AppError::BadRequest(
+            "Email address is not allowed".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+async fn load_email_change(db: &db::Db, user_id: &str) -> Result<Option<EmailTokenData>, AppError> {
+    let pending: Option<String> =
+        d1_query!(db, "SELECT email_change FROM users WHERE id = ?1", user_id)
+            .map_err(|_| AppError::Database)?
+            .first(Some("email_change"))
+            .await
+            .map_err(|_| AppError::Database)?;
+    pending
+        .map(|json| serde_json::from_str(&json).map_err(|_| AppError::Internal))
+        .transpose()
+}
+
+async fn save_email_change(
+    db: &db::Db,
+    user_id: &str,
+    pending: Option<&EmailTokenData>,
+) -> Result<(), AppError> {
+    let json = pending
+        .map(|p| serde_json::to_string(p).map_err(|_| AppError::Internal))
+        .transpose()?;
+    d1_query!(
+        db,
+        "UPDATE users SET email_change = ?1 WHERE id = ?2",
+        json,
+        user_id
+    )
+    .map_err(|_| AppError::Database)?
+    .run()
+    .await?;
+    Ok(())
+}
+
+/// Whether logins from unknown devices are confirmed with an emailed code
+/// (`NEW_DEVICE_VERIFICATION`, which needs email delivery).
+pub(crate) fn new_device_verification_enabled(env: &Env) -> bool {
+    get_env_bool(env, "NEW_DEVICE_VERIFICATION", false) && mail::mail_configured(env)
+}
+
+async fn load_new_device_otp(
+    db: &db::Db,
+    user_id: &str,
+) -> Result<Option<EmailTokenData>, AppError> {
+    let pending: Option<String> = d1_query!(
+        db,
+        "SELECT new_device_otp FROM users WHERE id = ?1",
+        user_id
+    )
+    .map_err(|_| AppError::Database)?
+    .first(Some("new_device_otp"))
+    .await
+    .map_err(|_| AppError::Database)?;
+    pending
+        .map(|json| serde_json::from_str(&json).map_err(|_| AppError::Internal))
+        .transpose()
+}
+
+async fn save_new_device_otp(
+    db: &db::Db,
+    user_id: &str,
+    pending: Option<&EmailTokenData>,
+) -> Result<(), AppError> {
+    let json = pending
+        .map(|p| serde_json::to_string(p).map_err(|_| AppError::Internal))
+        .transpose()?;
+    d1_query!(
+        db,
+        "UPDATE users SET new_device_otp = ?1 WHERE id = ?2",
+        json,
+        user_id
+    )
+    .map_err(|_| AppError::Database)?
+    .run()
+    .await?;
+    Ok(())
+}
+
+/// Email a new device verification code, unless one was sent less than a minute ago.
+pub(crate) async fn send_new_device_otp(
+    env: &Env,
+    db: &db::Db,
+    user: &User,
+) -> Result<(), AppError> {
+    let now = Utc::now().timestamp();
+    if let Some(pending) = load_new_device_otp(db, &user.id).await? {
+        if pending.token_hash.is_some() && now - pending.token_sent_at < EMAIL_TOKEN_RESEND_SECS {
+            return Ok(());
+        }
+    }
+
+    let token = generate_email_token()?;
+    mail::send(
+        env,
+        &user.email,
+        mail::Template::NewDeviceCode {
+            token: &token,
+            ttl_minutes: EMAIL_TOKEN_TTL_SECS / 60,
+        },
+    )
+    .await?;
+
+    let pending = EmailTokenData {
+        email: user.email.clone(),
+        token_hash: Some(sha256_hex(&token)),
+        token_sent_at: now,
+        attempts: 0,
+    };
+    save_new_device_otp(db, &user.id, Some(&pending)).await
+}
+
+/// Check the new device verification code of a login; a matching code is used up.
+pub(crate) async fn verify_new_device_otp(
+    db: &db::Db,
+    user_id: &str,
+    otp: &str,
+) -> Result<(), AppError> {
+    let mut pending = load_new_device_otp(db, user_id)
+        .await?
+        .ok_or_else(|| AppError::BadRequest("No verification code was sent".to_string()))?;
+    let verification = verify_email_token(&mut pending, otp);
+    save_new_device_otp(db, user_id, Some(&pending)).await?;
+    verification
+}
+
+/// POST /accounts/resend-new-device-otp - Email another new device verification code
+#[worker::send]
+pub async fn resend_new_device_otp(
+    State(env): State<Arc<Env>>,
+    Json(payload): Json<ResendNewDeviceOtpRequest>,
+) -> Result<Json<Value>, AppError> {
+    if !new_device_verification_enabled(&env) {
+        return Err(AppError::BadRequest(
+            "New device verification is not enabled".to_string(),
+        ));
+    }
+    let db = db::get_db(&env)?;
+    let invalid = || AppError::Unauthorized("Username or password is incorrect".to_string());
+
+    let user = User::find_by_email(&db, &payload.email.trim().to_lowercase())
+        .await?
+        .ok_or_else(invalid)?;
+    if !user
+        .verify_master_password(&payload.master_password_hash)
+        .await?
+        .is_valid()
+    {
+        return Err(invalid());
+    }
+
+    send_new_device_otp(&env, &db, &user).await?;
+    Ok(Json(json!({})))
+}
+
+async fn load_verification_otp(
+    db: &db::Db,
+    user_id: &str,
+) -> Result<Option<EmailTokenData>, AppError> {
+    let pending: Option<String> = d1_query!(
+        db,
+        "SELECT verification_otp FROM users WHERE id = ?1",
+        user_id
+    )
+    .map_err(|_| AppError::Database)?
+    .first(Some("verification_otp"))
+    .await
+    .map_err(|_| AppError::Database)?;
+    pending
+        .map(|json| serde_json::from_str(&json).map_err(|_| AppError::Internal))
+        .transpose()
+}
+
+async fn save_verification_otp(
+    db: &db::Db,
+    user_id: &str,
+    pending: Option<&EmailTokenData>,
+) -> Result<(), AppError> {
+    let json = pending
+        .map(|p| serde_json::to_string(p).map_err(|_| AppError::Internal))
+        .transpose()?;
+    d1_query!(
+        db,
+        "UPDATE users SET verification_otp = ?1 WHERE id = ?2",
+        json,
+        user_id
+    )
+    .map_err(|_| AppError::Database)?
+    .run()
+    .await?;
+    Ok(())
+}
+
+/// Check a user verification code. Unless `consume` is set, a matching code stays
+/// valid for the operation it was requested for.
+async fn check_verification_otp(
+    db: &db::Db,
+    user_id: &str,
+    otp: &str,
+    consume: bool,
+) -> Result<(), AppError> {
+    let mut pending = load_verification_otp(db, user_id)
+        .await?
+        .ok_or_else(|| AppError::BadRequest("No verification code was sent".to_string()))?;
+    let verification = verify_email_token(&mut pending, otp);
+    if verification.is_ok() && !consume {
+        return Ok(());
+    }
+    save_verification_otp(db, user_id, Some(&pending)).await?;
+    verification
+}
+
+/// POST /accounts/verify-password - Check the master password before a client-side
+/// operation such as an export
+#[worker::send]
+pub async fn post_verify_password(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Json(payload): Json<PasswordOrOtpData>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let master_password_hash = payload
+        .master_password_hash
+        .ok_or_else(|| AppError::BadRequest("Missing master password hash".to_string()))?;
+    verified_user(&db, &claims.sub, &master_password_hash).await?;
+    Ok(Json(master_password_policy_json(&db, &claims.sub).await?))
+}
+
+/// POST /accounts/request-otp - Email a code that verifies the user instead of the master password
+#[worker::send]
+pub async fn post_request_otp(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+) -> Result<Json<Value>, AppError> {
+    if !mail::mail_configured(&env) {
+        return Err(AppError::BadRequest(
+            "Email delivery is not configured".to_string(),
+        ));
+    }
+    let db = db::get_db(&env)?;
+    let user = User::find_by_id(&db, &claims.sub)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+    let now = Utc::now().timestamp();
+    if let Some(pending) = load_verification_otp(&db, &user.id).await? {
+        if pending.token_hash.is_some() && now - pending.token_sent_at < EMAIL_TOKEN_RESEND_SECS {
+            return Ok(Json(json!({})));
+        }
+    }
+
+    let token = generate_email_token()?;
+    mail::send(
+        &env,
+        &user.email,
+        mail::Template::VerificationCode {
+            token: &token,
+            ttl_minutes: EMAIL_TOKEN_TTL_SECS / 60,
+        },
+    )
+    .await?;
+
+    let pending = EmailTokenData {
+        email: user.email.clone(),
+        token_hash: Some(sha256_hex(&token)),
+        token_sent_at: now,
+        attempts: 0,
+    };
+    save_verification_otp(&db, &user.id, Some(&pending)).await?;
+    Ok(Json(json!({})))
+}
+
+/// POST /accounts/verify-otp - Check a code from `/accounts/request-otp`; it stays valid
+/// for the operation that follows
+#[worker::send]
+pub async fn post_verify_otp(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Json(payload): Json<VerifyOtpRequest>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    check_verification_otp(&db, &claims.sub, &payload.otp, false).await?;
+    Ok(Json(json!({})))
+}
+
+/// POST /accounts/email-token - Email a verification code to the address the user wants to switch to
+#[worker::send]
+pub async fn post_email_token(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Json(payload): Json<EmailTokenRequest>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let user = verified_user(&db, &claims.sub, &payload.master_password_hash).await?;
+    let new_email = payload.new_email.trim().to_lowercase();
+    ensure_email_available(&env, &db, &user, &new_email).await?;
+
+    if !mail::mail_configured(&env) {
+        return Err(AppError::BadRequest(
+            "Email delivery is not configured".to_string(),
+        ));
+    }
+
+    let now = Utc::now().timestamp();
+    if let Some(pending) = load_email_change(&db, &user.id).await? {
+        if pending.email == new_email
+            && pending.token_hash.is_some()
+            && now - pending.token_sent_at < EMAIL_TOKEN_RESEND_SECS
+        {
+            return Err(AppError::TooManyRequests(
+                "Please wait before requesting another code".to_string(),
+            ));
+        }
+    }
+
+    let token = generate_email_token()?;
+    mail::send(
+        &env,
+        &new_email,
+        mail::Template::EmailChange {
+            token: &token,
+            ttl_minutes: EMAIL_TOKEN_TTL_SECS / 60,
+        },
+    )
+    .await?;
+
+    let pending = EmailTokenData {
+        email: new_email,
+        token_hash: Some(sha256_hex(&token)),
+        token_sent_at: now,
+        attempts: 0,
+    };
+    save_email_change(&db, &user.id, Some(&pending)).await?;
+
+    Ok(Json(json!({})))
+}
+
+/// POST /accounts/email - Switch to the verified address
+///
+/// An email change is also a master key change (the email is the KDF salt), so the new
+/// address, password hash and user key are written in one statement.
+#[worker::send]
+pub async fn post_email(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Json(payload): Json<ChangeEmailRequest>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let user_id = &claims.sub;
+    let user = verified_user(&db, user_id, &payload.master_password_hash).await?;
+    let new_email = payload.new_email.trim().to_lowercase();
+
+    let mut pending = load_email_change(&db, user_id)
+        .await?
+        .filter(|pending| pending.email == new_email)
+        .ok_or_else(|| {
+            AppError::BadRequest("No email change is pending for this address".to_string())
+        })?;
+    if let Err(e) = verify_email_token(&mut pending, &payload.token) {
+        save_email_change(&db, user_id, Some(&pending)).await?;
+        return Err(e);
+    }
+    ensure_email_available(&env, &db, &user, &new_email).await?;
+
+    let new_salt = generate_salt()?;
+    let password_iterations = server_password_iterations(&env) as i32;
+    let new_hashed_password = hash_password_for_storage(
+        &payload.new_master_password_hash,
+        &new_salt,
+        password_iterations as u32,
+    )
+    .await?;
+    let now = db::now_string();
+
+    db.batch(vec![
+        d1_query!(
+            &db,
+            "UPDATE users SET email = ?1, email_verified = 1, email_change = NULL,
+                 master_password_hash = ?2, password_salt = ?3, password_iterations = ?4,
+                 key = ?5, security_stamp = ?6, updated_at = ?7
+             WHERE id = ?8",
+            &new_email,
+            new_hashed_password,
+            new_salt,
+            password_iterations,
+            payload.key,
+            Uuid::new_v4().to_string(),
+            &now,
+            user_id
+        )
+        .map_err(|_| AppError::Database)?,
+        d1_query!(
+            &db,
+            "UPDATE users_organizations SET email = ?1 WHERE user_id = ?2",
+            &new_email,
+            user_id
+        )
+        .map_err(|_| AppError::Database)?,
+        d1_query!(
+            &db,
+            "UPDATE emergency_access SET email = ?1 WHERE grantee_id = ?2",
+            &new_email,
+            user_id
+        )
+        .map_err(|_| AppError::Database)?,
+    ])
+    .await?;
+    prelogin_cache::invalidate(&env, &user.email).await;
+
+    RefreshToken::revoke_all_by_user(&db, user_id).await?;
+
+    notifications::publish_user_logout((*env).clone(), claims.sub, now, Some(claims.device));
+
+    Ok(Json(json!({})))
+}
+
+/// POST /accounts/key-management/rotate-user-account-keys - Rotate user encryption keys
+#[worker::send]
+pub async fn post_rotatekey(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Json(payload): Json<RotateKeyRequest>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let user_id = &claims.sub;
+
+    // Get the user from the database
+    let user: Value = db
+        .prepare("SELECT * FROM users WHERE id = ?1")
+        .bind(&[user_id.clone().into()])?
+        .first(None)
+        .await
+        .map_err(|_| AppError::Database)?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+    let user: User = serde_json::from_value(user).map_err(|_| AppError::Internal)?;
+
+    // Verify the current master password
+    let verification = user
+        .verify_master_password(&payload.old_master_key_authentication_hash)
+        .await?;
+
+    if !verification.is_valid() {
+        return Err(AppError::Unauthorized("Invalid password".to_string()));
+    }
+
+    let unlock_data = &payload.account_unlock_data.master_password_unlock_data;
+
+    validate_rotation_metadata(&user, unlock_data, &payload.account_keys.account_public_key)?;
+
+    // Validate KDF parameters
+    ensure_supported_kdf(
+        unlock_data.kdf_type,
+        unlock_data.kdf_iterations,
+        unlock_data.kdf_memory,
+        unlock_data.kdf_parallelism,
+    )?;
+
+    let now = db::now_string();
+    let mut statements = rotation_statements(
+        &db,
+        user_id,
+        payload.account_data.ciphers,
+        &payload.account_data.folders,
+        &payload.account_data.sends,
+        &now,
+    )
+    .await?;
+    // Passkeys that unlock the vault wrap the user key; re-wrap them with the new one.
+    statements.extend(Passkey::rotation_statements(
+        &db,
+        user_id,
+        &payload.account_unlock_data.passkey_unlock_data,
+        &now,
+    )?);
+    // Account recovery enrollments wrap the user key with each organization's key.
+    statements.extend(
+        Membership::reset_password_rotation_statements(
+            &db,
+            user_id,
+            &payload
+                .account_unlock_data
+                .organization_account_recovery_unlock_data,
+            &now,
+        )
+        .await?,
+    );
+    // Emergency access grants wrap the user key with each grantee's public key.
+    statements.extend(
+        EmergencyAccess::rotation_statements(
+            &db,
+            user_id,
+            &payload.account_unlock_data.emergency_access_unlock_data,
+            &now,
+        )
+        .await?,
+    );
+
+    // Generate new salt and hash the new password
+    let new_salt = generate_salt()?;
+    let password_iterations = server_password_iterations(&env) as i32;
+    let new_hashed_password = hash_password_for_storage(
+        &unlock_data.master_key_authentication_hash,
+        &new_salt,
+        password_iterations as u32,
+    )
+    .await?;
+
+    // Generate new security stamp
+    let new_security_stamp = Uuid::new_v4().to_string();
+
+    // Only store kdf_memory and kdf_parallelism for Argon2id, clear for PBKDF2
+    let (kdf_memory, kdf_parallelism) = if unlock_data.kdf_type == KDF_TYPE_ARGON2ID {
+        (unlock_data.kdf_memory, unlock_data.kdf_parallelism)
+    } else {
+        (None, None)
+    };
+
+    // Update user record with new keys and password
+    statements.push(d1_query!(
+        &db,
+        "UPDATE users SET master_password_hash = ?1, password_salt = ?2, password_iterations = ?3, key = ?4, private_key = ?5, kdf_type = ?6, kdf_iterations = ?7, kdf_memory = ?8, kdf_parallelism = ?9, security_stamp = ?10, updated_at = ?11 WHERE id = ?12",
+        new_hashed_password,
+        new_salt,
+        password_iterations,
+        unlock_data.master_key_encrypted_user_key,
+        payload.account_keys.user_key_encrypted_account_private_key,
+        unlock_data.kdf_type,
+        unlock_data.kdf_iterations,
+        kdf_memory,
+        kdf_parallelism,
+        new_security_stamp,
+        now,
+        user_id
+    )
+    .map_err(|_| AppError::Database)?);
+
+    // D1 runs a batch as a single transaction, so either every item is re-encrypted
+    // under the new key or nothing changes.
+    db.batch(statements).await?;
+    prelogin_cache::invalidate(&env, &user.email).await;
+
+    // The new security stamp invalidates every session; drop their refresh tokens too.
+    RefreshToken::revoke_all_by_user(&db, user_id).await?;
+
+    notifications::publish_user_logout((*env).clone(), claims.sub, now, Some(claims.device));
+
+    Ok(Json(json!({})))
+}
+
+/// POST /api/accounts/key - legacy key rotation used by older clients
+///
+/// Re-encrypts the vault under a new user key while keeping the master password.
+#[worker::send]
+pub async fn post_key(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Json(payload): Json<LegacyRotateKeyRequest>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let user_id = &claims.sub;
+
+    let user: User = db
+        .prepare("SELECT * FROM users WHERE id = ?1")
+        .bind(&[user_id.clone().into()])?
+        .first(None)
+        .await
+        .map_err(|_| AppError::Database)?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+    let verification = user
+        .verify_master_password(&payload.master_password_hash)
+        .await?;
+    if !verification.is_valid() {
+        return Err(AppError::Unauthorized("Invalid password".to_string()));
+    }
+
+    let now = db::now_string();
+    let mut statements = rotation_statements(
+        &db,
+        user_id,
+        payload.ciphers,
+        &payload.folders,
+        &payload.sends,
+        &now,
+    )
+    .await?;
+    // Older clients cannot re-wrap passkey keys, so those passkeys stop unlocking the vault.
+    statements.extend(Passkey::rotation_statements(&db, user_id, &[], &now)?);
+    statements.extend(
+        Membership::reset_password_rotation_statements(
+            &db,
+            user_id,
+            &payload.reset_password_keys,
+            &now,
+        )
+        .await?,
+    );
+    statements.extend(
+        EmergencyAccess::rotation_statements(&db, user_id, &payload.emergency_access_keys, &now)
+            .await?,
+    );
+
+    statements.push(
+        d1_query!(
+            &db,
+            "UPDATE users SET key = ?1, private_key = ?2, security_stamp = ?3, updated_at = ?4 WHERE id = ?5",
+            payload.key,
+            payload.private_key,
+            Uuid::new_v4().to_string(),
+            now,
+            user_id
+        )
+        .map_err(|_| AppError::Database)?,
+    );
+
+    // Single batch: D1 applies it as one transaction.
+    db.batch(statements).await?;
+
+    RefreshToken::revoke_all_by_user(&db, user_id).await?;
+
+    notifications::publish_user_logout((*env).clone(), claims.sub, now, Some(claims.device));
+
+    Ok(Json(json!({})))
+}
+
+/// Validate that a key rotation request covers every personal cipher, folder and send,
+/// and build the statements that re-encrypt them.
+async fn rotation_statements(
+    db: &db::Db,
+    user_id: &str,
+    ciphers: Vec<CipherRequestData>,
+    folders: &[RotateFolderData],
+    sends: &[SendRequestData],
+    now: &str,
+) -> Result<Vec<D1PreparedStatement>, AppError> {
+    // Validate data integrity using D1 batch operations
+    // Step 1: Ensure all personal ciphers have id (required for key rotation)
+    // Step 2: Count check - ensure request has exactly the same number of items as DB
+    // Step 3: EXCEPT check - ensure request has exactly the same IDs as DB
+    let personal_ciphers: Vec<_> = ciphers
+        .into_iter()
+        .filter(|c| c.organization_id.is_none())
+        .collect();
+
+    let request_cipher_ids: Vec<String> = personal_ciphers
+        .iter()
+        .filter_map(|c| c.id.clone())
+        .collect();
+
+    // All personal ciphers must have an id for key rotation
+    if personal_ciphers.len() != request_cipher_ids.len() {
+        log::error!(
+            "All ciphers must have an id for key rotation: {:?} != {:?}",
             personal_ciphers.len(),
             request_cipher_ids.len()
         );
@@ -747,12 +1853,7 @@ pub async fn post_rotatekey(
     }
 
     // Filter out null folder IDs (Bitwarden client bug: https://github.com/bitwarden/clients/issues/8453)
-    let request_folder_ids: Vec<String> = payload
-        .account_data
-        .folders
-        .iter()
-        .filter_map(|f| f.id.clone())
-        .collect();
+    let request_folder_ids: Vec<String> = folders.iter().filter_map(|f| f.id.clone()).collect();
 
     let cipher_ids_json =
         serde_json::to_string(&request_cipher_ids).map_err(|_| AppError::Internal)?;
@@ -766,24 +1867,24 @@ pub async fn post_rotatekey(
             db.prepare(
                 "SELECT COUNT(*) AS cnt FROM ciphers WHERE user_id = ?1 AND organization_id IS NULL",
             )
-            .bind(&[user_id.clone().into()])?,
+            .bind(&[user_id.into()])?,
             // Count folders in DB
             db.prepare("SELECT COUNT(*) AS cnt FROM folders WHERE user_id = ?1")
-                .bind(&[user_id.clone().into()])?,
+                .bind(&[user_id.into()])?,
             // DB cipher IDs EXCEPT request cipher IDs (finds missing)
             db.prepare(
                 "SELECT id FROM ciphers WHERE user_id = ?1 AND organization_id IS NULL
                  EXCEPT
                  SELECT value FROM json_each(?2) LIMIT 1",
             )
-            .bind(&[user_id.clone().into(), cipher_ids_json.into()])?,
+            .bind(&[user_id.into(), cipher_ids_json.into()])?,
             // DB folder IDs EXCEPT request folder IDs (finds missing)
             db.prepare(
                 "SELECT id FROM folders WHERE user_id = ?1
                  EXCEPT
                  SELECT value FROM json_each(?2) LIMIT 1",
             )
-            .bind(&[user_id.clone().into(), folder_ids_json.into()])?,
+            .bind(&[user_id.into(), folder_ids_json.into()])?,
         ])
         .await?;
 
@@ -827,19 +1928,16 @@ pub async fn post_rotatekey(
         ));
     }
 
-    let now = db::now_string();
-
     // Update all folders with new encrypted names (batch operation)
     // Skip null folder IDs (Bitwarden client bug: https://github.com/bitwarden/clients/issues/8453)
-    let mut folder_statements: Vec<D1PreparedStatement> =
-        Vec::with_capacity(payload.account_data.folders.len());
-    for folder in &payload.account_data.folders {
+    let mut statements: Vec<D1PreparedStatement> = Vec::new();
+    for folder in folders {
         // Skip null folder id entries
         let Some(folder_id) = &folder.id else {
             continue;
         };
         let stmt = d1_query!(
-            &db,
+            db,
             "UPDATE folders SET name = ?1, updated_at = ?2 WHERE id = ?3 AND user_id = ?4",
             folder.name,
             now,
@@ -847,15 +1945,11 @@ pub async fn post_rotatekey(
             user_id
         )
         .map_err(|_| AppError::Database)?;
-        folder_statements.push(stmt);
+        statements.push(stmt);
     }
-    db::execute_in_batches(&db, folder_statements, batch_size).await?;
 
     // Update all ciphers with new encrypted data (batch operation)
     // Only update personal ciphers (organization_id is None)
-    let mut cipher_statements: Vec<D1PreparedStatement> =
-        Vec::with_capacity(personal_ciphers.len());
-    let mut attachment_statements: Vec<D1PreparedStatement> = Vec::new();
     for cipher in personal_ciphers {
         // id is guaranteed to exist (validated above)
         let cipher_id = cipher.id.as_ref().unwrap();
@@ -865,7 +1959,7 @@ pub async fn post_rotatekey(
         let data = serde_json::to_string(&cipher_data).map_err(|_| AppError::Internal)?;
 
         let stmt = d1_query!(
-            &db,
+            db,
             "UPDATE ciphers SET data = ?1, folder_id = ?2, favorite = ?3, updated_at = ?4 WHERE id = ?5 AND user_id = ?6",
             data,
             cipher.folder_id,
@@ -875,14 +1969,14 @@ pub async fn post_rotatekey(
             user_id
         )
         .map_err(|_| AppError::Database)?;
-        cipher_statements.push(stmt);
+        statements.push(stmt);
 
         // Update attachments key and encrypted filename when rotating.
         // The Bitwarden clients send `attachments2` only during key rotation.
         if let Some(attachments2) = &cipher.attachments2 {
             for (attachment_id, attachment) in attachments2 {
                 let stmt = d1_query!(
-                    &db,
+                    db,
                     "UPDATE attachments SET file_name = ?1, akey = ?2, updated_at = ?3 WHERE id = ?4 AND cipher_id = ?5",
                     attachment.file_name,
                     attachment.key,
@@ -891,68 +1985,14 @@ pub async fn post_rotatekey(
                     cipher_id
                 )
                 .map_err(|_| AppError::Database)?;
-                attachment_statements.push(stmt);
+                statements.push(stmt);
             }
         }
     }
-    db::execute_in_batches(&db, cipher_statements, batch_size).await?;
-    db::execute_in_batches(&db, attachment_statements, batch_size).await?;
-
-    // Rotate sends
-    sends::rotate_user_sends(
-        &db,
-        env.as_ref(),
-        user_id,
-        &payload.account_data.sends,
-        &now,
-        batch_size,
-    )
-    .await?;
-
-    // Generate new salt and hash the new password
-    let new_salt = generate_salt()?;
-    let password_iterations = server_password_iterations(&env) as i32;
-    let new_hashed_password = hash_password_for_storage(
-        &unlock_data.master_key_authentication_hash,
-        &new_salt,
-        password_iterations as u32,
-    )
-    .await?;
 
-    // Generate new security stamp
-    let new_security_stamp = Uuid::new_v4().to_string();
+    statements.extend(sends::send_rotation_statements(db, user_id, sends, now).await?);
 
-    // Only store kdf_memory and kdf_parallelism for Argon2id, clear for PBKDF2
-    let (kdf_memory, kdf_parallelism) = if unlock_data.kdf_type == KDF_TYPE_ARGON2ID {
-        (unlock_data.kdf_memory, unlock_data.kdf_parallelism)
-    } else {
-        (None, None)
-    };
-
-    // Update user record with new keys and password
-    d1_query!(
-        &db,
-        "UPDATE users SET master_password_hash = ?1, password_salt = ?2, password_iterations = ?3, key = ?4, private_key = ?5, kdf_type = ?6, kdf_iterations = ?7, kdf_memory = ?8, kdf_parallelism = ?9, security_stamp = ?10, updated_at = ?11 WHERE id = ?12",
-        new_hashed_password,
-        new_salt,
-        password_iterations,
-        unlock_data.master_key_encrypted_user_key,
-        payload.account_keys.user_key_encrypted_account_private_key,
-        unlock_data.kdf_type,
-        unlock_data.kdf_iterations,
-        kdf_memory,
-        kdf_parallelism,
-        new_security_stamp,
-        now,
-        user_id
-    )
-    .map_err(|_| AppError::Database)?
-    .run()
-    .await?;
-
-    notifications::publish_user_logout((*env).clone(), claims.sub, now, Some(claims.device));
-
-    Ok(Json(json!({})))
+    Ok(statements)
 }
 
 /// POST /accounts/kdf - Change KDF settings (PBKDF2 <-> Argon2id)
@@ -1045,6 +2085,10 @@ pub async fn post_kdf(
     .map_err(|_| AppError::Database)?
     .run()
     .await?;
+    prelogin_cache::invalidate(&env, &user.email).await;
+
+    // The new security stamp invalidates every session; drop their refresh tokens too.
+    RefreshToken::revoke_all_by_user(&db, user_id).await?;
 
     notifications::publish_user_logout((*env).clone(), claims.sub, now, Some(claims.device));
 
@@ -1069,16 +2113,7 @@ pub async fn post_sstamp(
         .await
         .map_err(|_| AppError::Database)?
         .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
-
-    // Require master password hash (OTP not supported)
-    let provided_hash = payload
-        .master_password_hash
-        .ok_or_else(|| AppError::BadRequest("Missing master password hash".to_string()))?;
-
-    let verification = user.verify_master_password(&provided_hash).await?;
-    if !verification.is_valid() {
-        return Err(AppError::Unauthorized("Invalid password".to_string()));
-    }
+    verify_user(&db, &user, &payload).await?;
 
     push::unregister_push_devices_by_user(&env, user_id).await;
 
@@ -1086,19 +2121,8 @@ pub async fn post_sstamp(
     Device::delete_all_by_user(&db, user_id).await?;
 
     // Rotate the security stamp so all existing access tokens become invalid immediately
-    let new_security_stamp = Uuid::new_v4().to_string();
     let now = db::now_string();
-
-    d1_query!(
-        &db,
-        "UPDATE users SET security_stamp = ?1, updated_at = ?2 WHERE id = ?3",
-        new_security_stamp,
-        now,
-        user_id
-    )
-    .map_err(|_| AppError::Database)?
-    .run()
-    .await?;
+    User::rotate_security_stamp(&db, user_id, &now).await?;
 
     // Known issue: Logout push for mobile devices will be skiped since the records of devices are deleted.
     // Notifications are sent in background via waitUntil,
@@ -1107,3 +2131,65 @@ pub async fn post_sstamp(
 
     Ok(Json(json!({})))
 }
+
+/// POST /api/accounts/api-key - view the personal API key, creating it on first use
+#[worker::send]
+pub async fn post_api_key(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Json(payload): Json<PasswordOrOtpData>,
+) -> Result<Json<Value>, AppError> {
+    api_key_response(&env, &claims.sub, payload, false).await
+}
+
+/// POST /api/accounts/rotate-api-key - replace the personal API key
+#[worker::send]
+pub async fn post_rotate_api_key(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Json(payload): Json<PasswordOrOtpData>,
+) -> Result<Json<Value>, AppError> {
+    api_key_response(&env, &claims.sub, payload, true).await
+}
+
+async fn api_key_response(
+    env: &Env,
+    user_id: &str,
+    payload: PasswordOrOtpData,
+    rotate: bool,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(env)?;
+    let user: User = db
+        .prepare("SELECT * FROM users WHERE id = ?1")
+        .bind(&[user_id.into()])?
+        .first(None)
+        .await
+        .map_err(|_| AppError::Database)?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+    verify_user(&db, &user, &payload).await?;
+
+    let (api_key, revision_date) = match user.api_key {
+        Some(api_key) if !rotate => (api_key, user.updated_at),
+        _ => {
+            let api_key = generate_api_key()?;
+            let now = db::now_string();
+            d1_query!(
+                &db,
+                "UPDATE users SET api_key = ?1, updated_at = ?2 WHERE id = ?3",
+                &api_key,
+                &now,
+                user_id
+            )
+            .map_err(|_| AppError::Database)?
+            .run()
+            .await?;
+            (api_key, now)
+        }
+    };
+
+    Ok(Json(json!({
+        "apiKey": api_key,
+        "revisionDate": revision_date,
+        "object": "apiKey"
+    })))
+}
@@ -0,0 +1,696 @@
+//! Token-protected admin API for user management.
+//!
+//! Every route under `/admin` requires `Authorization: Bearer <ADMIN_TOKEN>`. When the
+//! `ADMIN_TOKEN` secret is not set the whole API answers 404, so it stays invisible on
+//! deployments that do not opt in.
+
+use axum::{
+    extract::{Path, Query, Request, State},
+    middleware::{self, Next},
+    response::Response,
+    routing::{delete, get, post},
+    Extension, Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use uuid::Uuid;
+use web_sys::UrlSearchParams;
+use worker::Env;
+
+use crate::backup;
+use crate::client_context::request_ip_from_headers;
+use crate::crypto::ct_eq;
+use crate::d1_query;
+use crate::db;
+use crate::diagnostics::{self, Diagnostics};
+use crate::error::AppError;
+use crate::handlers::storage::{self, StorageOwner};
+use crate::handlers::{accounts, get_env_usize};
+use crate::mail;
+use crate::migrations;
+use crate::models::organization::{Membership, Organization, OrganizationPlan};
+use crate::models::policy::OrgPolicy;
+use crate::models::{
+    device::Device, invitation::Invitation, registration_token::RegistrationToken,
+};
+use crate::notifications;
+use crate::push;
+use crate::BaseUrl;
+
+pub fn router(state: Arc<Env>) -> Router<Arc<Env>> {
+    Router::new()
+        .route("/admin/users", get(list_users))
+        .route("/admin/users/inactive", get(list_inactive_users))
+        .route("/admin/users/{user_id}", get(get_user).delete(delete_user))
+        .route("/admin/users/{user_id}/disable", post(disable_user))
+        .route("/admin/users/{user_id}/enable", post(enable_user))
+        .route(
+            "/admin/users/{user_id}/force-password-reset",
+            post(force_password_reset),
+        )
+        .route(
+            "/admin/users/{user_id}/storage",
+            get(get_user_storage).put(put_user_storage),
+        )
+        .route(
+            "/admin/organizations/{org_id}/storage",
+            get(get_organization_storage).put(put_organization_storage),
+        )
+        .route(
+            "/admin/organizations/{org_id}/plan",
+            get(get_organization_plan).put(put_organization_plan),
+        )
+        .route("/admin/invite", post(invite_user))
+        .route(
+            "/admin/migrations",
+            get(get_migrations).post(apply_migrations),
+        )
+        .route("/admin/backups", get(list_backups).post(create_backup))
+        .route("/admin/diagnostics", get(get_diagnostics))
+        .route(
+            "/admin/registration-tokens",
+            get(list_registration_tokens).post(create_registration_token),
+        )
+        .route(
+            "/admin/registration-tokens/{token_id}",
+            delete(delete_registration_token),
+        )
+        .route("/admin/backups/restore", post(restore_backup))
+        .route_layer(middleware::from_fn_with_state(state, require_admin_token))
+}
+
+/// Reject requests that do not carry the configured `ADMIN_TOKEN`.
+#[worker::send]
+async fn require_admin_token(
+    State(env): State<Arc<Env>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let admin_token = env
+        .secret("ADMIN_TOKEN")
+        .ok()
+        .map(|v| v.to_string())
+        .filter(|v| !v.trim().is_empty())
+        .ok_or_else(|| AppError::NotFound("Not found".to_string()))?;
+
+    // Admin tokens are long-lived secrets, so throttle guessing per IP.
+    if let Ok(rate_limiter) = env.rate_limiter("LOGIN_RATE_LIMITER") {
+        let ip = request_ip_from_headers(request.headers());
+        if let Ok(outcome) = rate_limiter.limit(format!("admin:{ip}")).await {
+            if !outcome.success {
+                return Err(AppError::TooManyRequests(
+                    "Too many requests. Please try again later.".to_string(),
+                ));
+            }
+        }
+    }
+
+    let provided = request
+        .headers()
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(crate::auth::bearer_token_from_header_value)
+        .ok_or_else(|| AppError::Unauthorized("Missing admin token".to_string()))?;
+    if !ct_eq(&provided, &admin_token) {
+        return Err(AppError::Unauthorized("Invalid admin token".to_string()));
+    }
+
+    Ok(next.run(request).await)
+}
+
+/// One row of the admin user listing.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdminUser {
+    pub id: String,
+    pub email: String,
+    pub name: Option<String>,
+    #[serde(alias = "email_verified", with = "crate::models::user::bool_from_int")]
+    pub email_verified: bool,
+    #[serde(with = "crate::models::user::bool_from_int")]
+    pub disabled: bool,
+    #[serde(
+        alias = "force_password_reset",
+        with = "crate::models::user::bool_from_int"
+    )]
+    pub force_password_reset: bool,
+    #[serde(alias = "deletion_requested_at")]
+    pub deletion_requested_at: Option<String>,
+    #[serde(
+        alias = "two_factor_enabled",
+        with = "crate::models::user::bool_from_int"
+    )]
+    pub two_factor_enabled: bool,
+    #[serde(alias = "cipher_count")]
+    pub cipher_count: i64,
+    #[serde(alias = "attachment_count")]
+    pub attachment_count: i64,
+    #[serde(alias = "attachment_size")]
+    pub attachment_size: i64,
+    #[serde(alias = "device_count")]
+    pub device_count: i64,
+    #[serde(alias = "last_active")]
+    pub last_active: Option<String>,
+    #[serde(alias = "last_login_at")]
+    pub last_login_at: Option<String>,
+    #[serde(alias = "last_sync_at")]
+    pub last_sync_at: Option<String>,
+    #[serde(alias = "created_at")]
+    pub created_at: String,
+    #[serde(alias = "updated_at")]
+    pub updated_at: String,
+}
+
+const ADMIN_USER_SELECT: &str = "SELECT
+        u.id, u.email, u.name, u.email_verified, u.disabled, u.force_password_reset,
+        u.deletion_requested_at, u.last_login_at, u.last_sync_at, u.created_at, u.updated_at,
+        EXISTS (SELECT 1 FROM twofactor t WHERE t.user_uuid = u.id AND t.enabled = 1 AND t.atype < 1000) AS two_factor_enabled,
+        (SELECT COUNT(*) FROM ciphers c WHERE c.user_id = u.id) AS cipher_count,
+        (SELECT COUNT(*) FROM attachments a JOIN ciphers c ON c.id = a.cipher_id WHERE c.user_id = u.id) AS attachment_count,
+        (SELECT COALESCE(SUM(a.file_size), 0) FROM attachments a JOIN ciphers c ON c.id = a.cipher_id WHERE c.user_id = u.id) AS attachment_size,
+        (SELECT COUNT(*) FROM devices d WHERE d.user_id = u.id) AS device_count,
+        (SELECT MAX(d.updated_at) FROM devices d WHERE d.user_id = u.id) AS last_active
+    FROM users u";
+
+/// Latest login or sync of `users u`; accounts that never did either count from their
+/// creation.
+const LAST_SEEN_SQL: &str =
+    "MAX(COALESCE(u.last_login_at, u.created_at), COALESCE(u.last_sync_at, u.created_at))";
+
+/// Timestamp `days` days ago, in the format of the stored timestamps.
+fn days_ago(days: usize) -> String {
+    (chrono::Utc::now() - chrono::Duration::days(days as i64))
+        .format("%Y-%m-%dT%H:%M:%S%.3fZ")
+        .to_string()
+}
+
+async fn find_admin_user(db: &db::Db, user_id: &str) -> Result<AdminUser, AppError> {
+    let row: Value = db
+        .prepare(format!("{ADMIN_USER_SELECT} WHERE u.id = ?1"))
+        .bind(&[user_id.into()])?
+        .first(None)
+        .await
+        .map_err(|_| AppError::Database)?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+    serde_json::from_value(row).map_err(|_| AppError::Internal)
+}
+
+#[worker::send]
+pub async fn list_users(State(env): State<Arc<Env>>) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let users: Vec<AdminUser> = db
+        .prepare(format!("{ADMIN_USER_SELECT} ORDER BY u.email"))
+        .all()
+        .await
+        .map_err(|_| AppError::Database)?
+        .results()
+        .map_err(|_| AppError::Database)?;
+
+    Ok(Json(json!({
+        "data": users,
+        "object": "list",
+        "continuationToken": null,
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InactiveQuery {
+    /// Days without a login or sync; defaults to `INACTIVE_ACCOUNT_DISABLE_DAYS`, or 90.
+    days: Option<usize>,
+}
+
+/// GET /admin/users/inactive?days=N - accounts without a login or sync for `days` days,
+/// least recently seen first
+#[worker::send]
+pub async fn list_inactive_users(
+    State(env): State<Arc<Env>>,
+    Query(query): Query<InactiveQuery>,
+) -> Result<Json<Value>, AppError> {
+    let days = query
+        .days
+        .unwrap_or_else(|| match inactive_disable_days(&env) {
+            0 => DEFAULT_INACTIVE_DAYS,
+            days => days,
+        });
+    let db = db::get_db(&env)?;
+    let users: Vec<AdminUser> = db
+        .prepare(format!(
+            "{ADMIN_USER_SELECT} WHERE {LAST_SEEN_SQL} < ?1 ORDER BY {LAST_SEEN_SQL}, u.email"
+        ))
+        .bind(&[days_ago(days).into()])?
+        .all()
+        .await
+        .map_err(|_| AppError::Database)?
+        .results()
+        .map_err(|_| AppError::Database)?;
+
+    Ok(Json(json!({
+        "data": users,
+        "days": days,
+        "object": "list",
+        "continuationToken": null,
+    })))
+}
+
+#[worker::send]
+pub async fn get_user(
+    State(env): State<Arc<Env>>,
+    Path(user_id): Path<String>,
+) -> Result<Json<AdminUser>, AppError> {
+    let db = db::get_db(&env)?;
+    Ok(Json(find_admin_user(&db, &user_id).await?))
+}
+
+#[worker::send]
+pub async fn delete_user(
+    State(env): State<Arc<Env>>,
+    Path(user_id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    find_admin_user(&db, &user_id).await?;
+
+    push::unregister_push_devices_by_user(&env, &user_id).await;
+    accounts::delete_user_data(&env, &db, &user_id).await?;
+    notifications::publish_user_logout((*env).clone(), user_id, db::now_string(), None);
+
+    Ok(Json(json!({})))
+}
+
+#[worker::send]
+pub async fn disable_user(
+    State(env): State<Arc<Env>>,
+    Path(user_id): Path<String>,
+) -> Result<Json<AdminUser>, AppError> {
+    let db = db::get_db(&env)?;
+    find_admin_user(&db, &user_id).await?;
+    disable_account(&env, &db, &user_id).await?;
+    Ok(Json(find_admin_user(&db, &user_id).await?))
+}
+
+/// Disable an account and sign out all of its devices.
+async fn disable_account(env: &Env, db: &db::Db, user_id: &str) -> Result<(), AppError> {
+    // Rotating the security stamp invalidates every access token already handed out.
+    let now = db::now_string();
+    d1_query!(
+        db,
+        "UPDATE users SET disabled = 1, security_stamp = ?1, updated_at = ?2 WHERE id = ?3",
+        Uuid::new_v4().to_string(),
+        &now,
+        user_id
+    )
+    .map_err(|_| AppError::Database)?
+    .run()
+    .await?;
+
+    push::unregister_push_devices_by_user(env, user_id).await;
+    Device::delete_all_by_user(db, user_id).await?;
+    notifications::publish_user_logout(env.clone(), user_id.to_string(), now, None);
+    Ok(())
+}
+
+const DEFAULT_INACTIVE_DAYS: usize = 90;
+
+/// `INACTIVE_ACCOUNT_DISABLE_DAYS`; 0 (the default) keeps inactive accounts enabled.
+fn inactive_disable_days(env: &Env) -> usize {
+    get_env_usize(env, "INACTIVE_ACCOUNT_DISABLE_DAYS", 0)
+}
+
+/// Disable accounts without a login or sync for `INACTIVE_ACCOUNT_DISABLE_DAYS` days.
+/// An admin can enable them again; accounts pending deletion are left to that job.
+pub async fn disable_inactive_accounts(env: &Env) -> Result<u32, worker::Error> {
+    let days = inactive_disable_days(env);
+    if days == 0 {
+        return Ok(0);
+    }
+    let db = db::get_db(env).map_err(|e| worker::Error::RustError(e.to_string()))?;
+
+    #[derive(Deserialize)]
+    struct Row {
+        id: String,
+    }
+    let user_ids: Vec<Row> = db
+        .prepare(format!(
+            "SELECT u.id FROM users u
+             WHERE u.disabled = 0 AND u.deletion_requested_at IS NULL AND {LAST_SEEN_SQL} < ?1"
+        ))
+        .bind(&[days_ago(days).into()])?
+        .all()
+        .await?
+        .results()?;
+
+    let mut count = 0;
+    for Row { id } in &user_ids {
+        // Keep going on failure; the account is retried on the next run.
+        match disable_account(env, &db, id).await {
+            Ok(()) => count += 1,
+            Err(e) => log::error!("Failed to disable inactive account {id}: {e}"),
+        }
+    }
+    if count > 0 {
+        log::info!("Disabled {count} accounts inactive for {days} days");
+    }
+    Ok(count)
+}
+
+#[worker::send]
+pub async fn enable_user(
+    State(env): State<Arc<Env>>,
+    Path(user_id): Path<String>,
+) -> Result<Json<AdminUser>, AppError> {
+    let db = db::get_db(&env)?;
+    find_admin_user(&db, &user_id).await?;
+
+    d1_query!(
+        &db,
+        "UPDATE users SET disabled = 0, updated_at = ?1 WHERE id = ?2",
+        db::now_string(),
+        &user_id
+    )
+    .map_err(|_| AppError::Database)?
+    .run()
+    .await?;
+
+    Ok(Json(find_admin_user(&db, &user_id).await?))
+}
+
+/// Require the user to choose a new master password on their next login.
+#[worker::send]
+pub async fn force_password_reset(
+    State(env): State<Arc<Env>>,
+    Path(user_id): Path<String>,
+) -> Result<Json<AdminUser>, AppError> {
+    let db = db::get_db(&env)?;
+    find_admin_user(&db, &user_id).await?;
+
+    d1_query!(
+        &db,
+        "UPDATE users SET force_password_reset = 1, updated_at = ?1 WHERE id = ?2",
+        db::now_string(),
+        &user_id
+    )
+    .map_err(|_| AppError::Database)?
+    .run()
+    .await?;
+
+    Ok(Json(find_admin_user(&db, &user_id).await?))
+}
+
+/// PUT /admin/{users|organizations}/{id}/storage
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageQuotaRequest {
+    /// Quota in KB; `null` returns to the default.
+    pub quota_kb: Option<i64>,
+}
+
+#[worker::send]
+pub async fn get_user_storage(
+    State(env): State<Arc<Env>>,
+    Path(user_id): Path<String>,
+) -> Result<Json<storage::StorageUsage>, AppError> {
+    let db = db::get_db(&env)?;
+    let usage = storage::storage_usage(&env, &db, StorageOwner::User(&user_id)).await?;
+    Ok(Json(usage))
+}
+
+/// Set an individual storage quota for a user.
+#[worker::send]
+pub async fn put_user_storage(
+    State(env): State<Arc<Env>>,
+    Path(user_id): Path<String>,
+    Json(payload): Json<StorageQuotaRequest>,
+) -> Result<Json<storage::StorageUsage>, AppError> {
+    let db = db::get_db(&env)?;
+    let owner = StorageOwner::User(&user_id);
+    storage::set_storage_quota(&db, owner, payload.quota_kb).await?;
+    Ok(Json(storage::storage_usage(&env, &db, owner).await?))
+}
+
+#[worker::send]
+pub async fn get_organization_storage(
+    State(env): State<Arc<Env>>,
+    Path(org_id): Path<String>,
+) -> Result<Json<storage::StorageUsage>, AppError> {
+    let db = db::get_db(&env)?;
+    let usage = storage::storage_usage(&env, &db, StorageOwner::Organization(&org_id)).await?;
+    Ok(Json(usage))
+}
+
+/// Set an individual storage quota for an organization.
+#[worker::send]
+pub async fn put_organization_storage(
+    State(env): State<Arc<Env>>,
+    Path(org_id): Path<String>,
+    Json(payload): Json<StorageQuotaRequest>,
+) -> Result<Json<storage::StorageUsage>, AppError> {
+    let db = db::get_db(&env)?;
+    let owner = StorageOwner::Organization(&org_id);
+    storage::set_storage_quota(&db, owner, payload.quota_kb).await?;
+    Ok(Json(storage::storage_usage(&env, &db, owner).await?))
+}
+
+/// Plan of an organization plus the seats it currently occupies.
+async fn organization_plan_json(db: &db::Db, org: &Organization) -> Result<Value, AppError> {
+    let mut json = serde_json::to_value(org.plan()).map_err(|_| AppError::Internal)?;
+    json["occupiedSeats"] = json!(Membership::count_by_org(db, &org.id).await?);
+    Ok(json)
+}
+
+#[worker::send]
+pub async fn get_organization_plan(
+    State(env): State<Arc<Env>>,
+    Path(org_id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let org = Organization::find_by_id(&db, &org_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Organization not found".to_string()))?;
+    Ok(Json(organization_plan_json(&db, &org).await?))
+}
+
+/// Replace the plan of an organization.
+///
+/// Lowering the seat limit below the current members only blocks new invitations.
+/// Dropping policies turns the organization's policies off.
+#[worker::send]
+pub async fn put_organization_plan(
+    State(env): State<Arc<Env>>,
+    Path(org_id): Path<String>,
+    Json(payload): Json<OrganizationPlan>,
+) -> Result<Json<Value>, AppError> {
+    if payload.seats.is_some_and(|seats| seats < 1) {
+        return Err(AppError::BadRequest("Seats must be at least 1".to_string()));
+    }
+
+    let db = db::get_db(&env)?;
+    let mut org = Organization::find_by_id(&db, &org_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Organization not found".to_string()))?;
+    if org.use_policies && !payload.use_policies {
+        OrgPolicy::disable_all_for_org(&db, &org_id).await?;
+    }
+    org.update_plan(&db, &payload).await?;
+    // Members' profiles carry the plan's flags.
+    Membership::touch_confirmed_users(&db, &org_id, &org.updated_at).await?;
+
+    Ok(Json(organization_plan_json(&db, &org).await?))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InviteUserRequest {
+    pub email: String,
+}
+
+/// Allow an email address to register even if it is not covered by `ALLOWED_EMAILS`.
+#[worker::send]
+pub async fn invite_user(
+    State(env): State<Arc<Env>>,
+    Json(payload): Json<InviteUserRequest>,
+) -> Result<Json<Value>, AppError> {
+    let email = payload.email.trim().to_lowercase();
+    if email.is_empty() || !email.contains('@') {
+        return Err(AppError::BadRequest("Invalid email address".to_string()));
+    }
+
+    let db = db::get_db(&env)?;
+    let existing: Option<String> = d1_query!(&db, "SELECT id FROM users WHERE email = ?1", &email)
+        .map_err(|_| AppError::Database)?
+        .first(Some("id"))
+        .await
+        .map_err(|_| AppError::Database)?;
+    if existing.is_some() {
+        return Err(AppError::BadRequest("User already exists".to_string()));
+    }
+
+    Invitation::save(&db, &email).await?;
+
+    mail::send_in_background((*env).clone(), email.clone(), mail::Template::Invite);
+
+    Ok(Json(json!({ "email": email })))
+}
+
+/// Validity of a registration token when the request does not set `expiresInHours`.
+const DEFAULT_REGISTRATION_TOKEN_HOURS: i64 = 7 * 24;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateRegistrationTokenRequest {
+    /// Restrict the token to this address.
+    pub email: Option<String>,
+    pub note: Option<String>,
+    pub expires_in_hours: Option<i64>,
+}
+
+/// Issue a single-use registration token. The token is only shown in this response;
+/// when it is bound to an email, `signupUrl` is a web vault link that registers with it.
+#[worker::send]
+pub async fn create_registration_token(
+    State(env): State<Arc<Env>>,
+    Extension(BaseUrl(base_url)): Extension<BaseUrl>,
+    Json(payload): Json<CreateRegistrationTokenRequest>,
+) -> Result<Json<Value>, AppError> {
+    let email = payload
+        .email
+        .map(|email| email.trim().to_lowercase())
+        .filter(|email| !email.is_empty());
+    if email.as_deref().is_some_and(|email| !email.contains('@')) {
+        return Err(AppError::BadRequest("Invalid email address".to_string()));
+    }
+    let hours = payload
+        .expires_in_hours
+        .unwrap_or(DEFAULT_REGISTRATION_TOKEN_HOURS);
+    if hours <= 0 {
+        return Err(AppError::BadRequest(
+            "expiresInHours must be positive".to_string(),
+        ));
+    }
+
+    let db = db::get_db(&env)?;
+    let (token, raw) = RegistrationToken::issue(&db, email, payload.note, hours).await?;
+    let signup_url = match token.email.as_deref() {
+        Some(email) => {
+            let params = UrlSearchParams::new().map_err(|_| AppError::Internal)?;
+            params.append("email", email);
+            params.append("token", &raw);
+            let query: String = params.to_string().into();
+            Some(format!(
+                "{}/#/finish-signup?{query}",
+                base_url.trim_end_matches('/')
+            ))
+        }
+        None => None,
+    };
+
+    let mut json = serde_json::to_value(&token).map_err(|_| AppError::Internal)?;
+    json["token"] = Value::String(raw);
+    json["signupUrl"] = signup_url.map(Value::String).unwrap_or(Value::Null);
+    Ok(Json(json))
+}
+
+/// List the registration tokens, newest first, with the account each used one created.
+#[worker::send]
+pub async fn list_registration_tokens(
+    State(env): State<Arc<Env>>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    Ok(Json(json!({
+        "data": RegistrationToken::list(&db).await?,
+        "object": "list",
+        "continuationToken": null,
+    })))
+}
+
+/// Revoke a registration token (or drop the record of a used one).
+#[worker::send]
+pub async fn delete_registration_token(
+    State(env): State<Arc<Env>>,
+    Path(token_id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    if !RegistrationToken::delete(&db, &token_id).await? {
+        return Err(AppError::NotFound(
+            "Registration token not found".to_string(),
+        ));
+    }
+    Ok(Json(json!({})))
+}
+
+/// List the bundled schema migrations and whether each has been applied.
+#[worker::send]
+pub async fn get_migrations(State(env): State<Arc<Env>>) -> Result<Json<Value>, AppError> {
+    let migrations = migrations::status(&env).await?;
+    Ok(Json(json!({
+        "data": migrations,
+        "object": "list",
+        "continuationToken": null,
+    })))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ApplyMigrationsQuery {
+    #[serde(default)]
+    pub baseline: bool,
+}
+
+/// Apply pending schema migrations now.
+#[worker::send]
+pub async fn apply_migrations(
+    State(env): State<Arc<Env>>,
+    Query(query): Query<ApplyMigrationsQuery>,
+) -> Result<Json<migrations::MigrationRun>, AppError> {
+    let run = migrations::apply_pending(&env, query.baseline).await?;
+    if let Some(failure) = &run.failed {
+        log::error!("Migration {} failed: {}", failure.name, failure.error);
+    }
+    Ok(Json(run))
+}
+
+/// Overview of the deployment for debugging: table sizes, bindings, settings (without
+/// secret values), migrations and the last run of every job.
+#[worker::send]
+pub async fn get_diagnostics(State(env): State<Arc<Env>>) -> Result<Json<Diagnostics>, AppError> {
+    Ok(Json(diagnostics::collect(&env).await?))
+}
+
+/// List the database backups in `BACKUP_BUCKET`, oldest first.
+#[worker::send]
+pub async fn list_backups(State(env): State<Arc<Env>>) -> Result<Json<Value>, AppError> {
+    let backups = backup::list_backups(&env).await?;
+    Ok(Json(json!({
+        "data": backups,
+        "object": "list",
+        "continuationToken": null,
+    })))
+}
+
+/// Back up the database now, then apply the retention limit.
+#[worker::send]
+pub async fn create_backup(State(env): State<Arc<Env>>) -> Result<Json<backup::Backup>, AppError> {
+    Ok(Json(backup::create_backup(&env).await?))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreBackupRequest {
+    /// Bucket key as listed by `GET /admin/backups`.
+    pub key: String,
+    /// Only validate the backup; the database is replaced only with `false`.
+    #[serde(default = "default_true")]
+    pub dry_run: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Replace the database contents with a backup, or check that it would restore.
+#[worker::send]
+pub async fn restore_backup(
+    State(env): State<Arc<Env>>,
+    Json(payload): Json<RestoreBackupRequest>,
+) -> Result<Json<backup::RestoreReport>, AppError> {
+    Ok(Json(
+        backup::restore_backup(&env, &payload.key, payload.dry_run).await?,
+    ))
+}
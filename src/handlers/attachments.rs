@@ -20,6 +20,7 @@ use crate::{
     auth::{Claims, JWT_VALIDATION_LEEWAY_SECS},
     db::{self, touch_user_updated_at},
     error::AppError,
+    handlers::storage::{ensure_storage_available, StorageOwner},
     models::{
         attachment::{AttachmentDB, AttachmentResponse},
         cipher::{Cipher, CipherDBModel},
@@ -28,8 +29,8 @@ use crate::{
     BaseUrl,
 };
 
-const ATTACHMENTS_BUCKET: &str = "ATTACHMENTS_BUCKET";
-const ATTACHMENTS_KV: &str = "ATTACHMENTS_KV";
+pub(crate) const ATTACHMENTS_BUCKET: &str = "ATTACHMENTS_BUCKET";
+pub(crate) const ATTACHMENTS_KV: &str = "ATTACHMENTS_KV";
 
 const DEFAULT_ATTACHMENT_TTL_SECS: i64 = 300; // 5 minutes
 const KV_MAX_VALUE_BYTES: i64 = 25 * 1024 * 1024; // 25 MiB (KV hard limit)
@@ -577,6 +578,7 @@ fn map_rows_to_keys(rows: Vec<AttachmentKeyRow>) -> Vec<String> {
 /// List attachment keys for given cipher IDs.
 /// - `json_body`: JSON text containing the ids array
 /// - `ids_path`: path to ids array within json_body (e.g. "$.ids" or "$" if top-level)
+/// - `user_id`: when set, only ciphers this user may modify are included
 pub(crate) async fn list_attachment_keys_for_cipher_ids_json(
     db: &crate::db::Db,
     json_body: &str,
@@ -588,7 +590,8 @@ pub(crate) async fn list_attachment_keys_for_cipher_ids_json(
         vec![json_body.to_owned().into(), ids_path.to_owned().into()];
 
     if let Some(uid) = user_id {
-        sql.push_str(" AND c.user_id = ?3");
+        sql.push_str(" AND ");
+        sql.push_str(&crate::handlers::ciphers::cipher_write_filter("c", 3));
         params.push(uid.into());
     }
 
@@ -624,16 +627,36 @@ pub(crate) async fn list_attachment_keys_for_user(
     Ok(map_rows_to_keys(rows))
 }
 
-pub(crate) async fn list_attachment_keys_for_soft_deleted_before(
+pub(crate) async fn list_attachment_keys_for_organization(
     db: &crate::db::Db,
-    cutoff_exclusive: &str,
+    organization_id: &str,
 ) -> Result<Vec<String>, AppError> {
     let rows: Vec<AttachmentKeyRow> = db
         .prepare(
             "SELECT a.cipher_id, a.id FROM attachments a \
              JOIN ciphers c ON a.cipher_id = c.id \
-             WHERE c.deleted_at IS NOT NULL AND c.deleted_at < ?1",
+             WHERE c.organization_id = ?1",
         )
+        .bind(&[organization_id.into()])?
+        .all()
+        .await
+        .map_err(|_| AppError::Database)?
+        .results()
+        .map_err(|_| AppError::Database)?;
+
+    Ok(map_rows_to_keys(rows))
+}
+
+/// List storage keys of pending (unfinalized) uploads created before the cutoff.
+///
+/// An upload may have written its blob before the finalize step failed, so the
+/// objects must be removed together with the stale pending rows.
+pub(crate) async fn list_pending_attachment_keys_created_before(
+    db: &crate::db::Db,
+    cutoff_exclusive: &str,
+) -> Result<Vec<String>, AppError> {
+    let rows: Vec<AttachmentKeyRow> = db
+        .prepare("SELECT cipher_id, id FROM attachments_pending WHERE created_at < ?1")
         .bind(&[cutoff_exclusive.into()])?
         .all()
         .await
@@ -923,7 +946,7 @@ async fn enforce_limits(
         }
     }
 
-    Ok(())
+    ensure_storage_available(env, db, StorageOwner::User(user_id), new_size).await
 }
 
 fn attachment_max_bytes(env: &Env) -> Result<Option<u64>, AppError> {
@@ -962,7 +985,7 @@ fn total_limit_bytes(env: &Env) -> Result<Option<u64>, AppError> {
     }
 }
 
-async fn user_attachment_usage(
+pub(crate) async fn user_attachment_usage(
     db: &crate::db::Db,
     user_id: &str,
     exclude_attachment: Option<&str>,
@@ -13,7 +13,7 @@ use crate::{
     client_context::{request_device_type_from_headers, request_ip_from_headers},
     db,
     error::AppError,
-    models::{auth_request::AuthRequest, device::Device, user::User},
+    models::{auth_request::AuthRequest, user::User},
     notifications, BaseUrl,
 };
 
@@ -59,13 +59,9 @@ pub async fn post_auth_request(
     let request_device_type = request_device_type_from_headers(&headers);
     let request_ip = request_ip_from_headers(&headers);
 
-    let device =
-        Device::find_by_identifier_and_user(&db, &payload.device_identifier, &user.id).await?;
-    let device = device.ok_or_else(bad_request)?;
-
-    if device.r#type != request_device_type {
-        return Err(bad_request());
-    }
+    // The requesting device is usually new to the account, so it does not need to be
+    // known. A new request replaces the device's previous unanswered one.
+    AuthRequest::delete_pending_for_device(&db, &user.id, &payload.device_identifier).await?;
 
     let auth_request = AuthRequest::new(
         user.id.clone(),
@@ -156,32 +152,36 @@ pub async fn put_auth_request(
             "An authentication request with the same device already exists".to_string(),
         ));
     }
+    if auth_request.is_expired() {
+        return Err(AppError::BadRequest(
+            "This authentication request has expired".to_string(),
+        ));
+    }
 
     auth_request.response_date = Some(db::now_string());
-
+    auth_request.response_device_id = Some(payload.device_identifier);
+    auth_request.set_approved(payload.request_approved);
     if payload.request_approved {
-        auth_request.set_approved(true);
         auth_request.enc_key = Some(payload.key);
         auth_request.master_password_hash = payload.master_password_hash;
-        auth_request.response_device_id = Some(payload.device_identifier);
-        auth_request.update(&db).await?;
-
-        notifications::publish_anonymous_update(
-            (*env).clone(),
-            auth_request.id.clone(),
-            auth_request.user_id.clone(),
-            auth_request.id.clone(),
-        );
-        notifications::publish_auth_update(
-            (*env).clone(),
-            auth_request.user_id.clone(),
-            notifications::UpdateType::AuthRequestResponse,
-            auth_request.id.clone(),
-            Some(claims.device),
-        );
-    } else {
-        auth_request.delete(&db).await?;
     }
+    // A denial is kept until the request expires, so the requesting device learns
+    // the answer instead of waiting for it.
+    auth_request.update(&db).await?;
+
+    notifications::publish_anonymous_update(
+        (*env).clone(),
+        auth_request.id.clone(),
+        auth_request.user_id.clone(),
+        auth_request.id.clone(),
+    );
+    notifications::publish_auth_update(
+        (*env).clone(),
+        auth_request.user_id.clone(),
+        notifications::UpdateType::AuthRequestResponse,
+        auth_request.id.clone(),
+        Some(claims.device),
+    );
 
     Ok(Json(auth_request.to_json(&base_url)))
 }
@@ -203,6 +203,7 @@ pub async fn get_auth_request_response(
     if auth_request.device_type != request_device_type_from_headers(&headers)
         || auth_request.request_ip != request_ip_from_headers(&headers)
         || !auth_request.check_access_code(&query.code)
+        || auth_request.is_expired()
     {
         return Err(bad_request());
     }
@@ -1,6 +1,6 @@
 use crate::d1_query;
-use axum::extract::Path;
-use axum::http::header;
+use axum::extract::{Path, Query};
+use axum::http::{header, HeaderMap};
 use axum::response::{IntoResponse, Response};
 use axum::{extract::State, Extension, Json};
 use chrono::{DateTime, Utc};
@@ -9,19 +9,44 @@ use serde::Deserialize;
 use serde_json::Value;
 use std::sync::Arc;
 use uuid::Uuid;
-use worker::{wasm_bindgen::JsValue, Env};
+use worker::{wasm_bindgen::JsValue, D1PreparedStatement, Env};
 
 use crate::auth::Claims;
+use crate::client_context::{client_supports_cipher_keys, client_supports_ssh_keys};
 use crate::db;
 use crate::error::AppError;
-use crate::handlers::attachments;
+use crate::handlers::accounts::verify_user;
+use crate::handlers::storage::{ensure_storage_available, StorageOwner};
+use crate::handlers::{attachments, organizations};
 use crate::models::cipher::{
     Cipher, CipherDBModel, CipherData, CipherRequestData, CreateCipherRequest, PartialCipherData,
+    ShareCipherRequest, ShareCiphersBulkRequest, UserCipherState,
 };
+use crate::models::collection::{CipherCollectionsRequest, Collection};
+use crate::models::event::{Event, EventActor, EventType};
+use crate::models::organization::{Membership, MembershipType, Permission};
 use crate::models::user::{PasswordOrOtpData, User};
 use crate::notifications::{self, UpdateType};
 use crate::BaseUrl;
 
+/// Record an event for changes to organization ciphers; personal ciphers are not audited.
+async fn record_cipher_event(
+    db: &db::Db,
+    event_type: EventType,
+    organization_id: Option<&str>,
+    cipher_id: &str,
+    claims: &Claims,
+    headers: &HeaderMap,
+) {
+    let Some(org_id) = organization_id else {
+        return;
+    };
+    let actor = EventActor::from_request(claims, headers);
+    let mut event = Event::new(event_type, org_id, &actor);
+    event.cipher_id = Some(cipher_id.to_string());
+    event.record(db).await;
+}
+
 /// A wrapper for raw JSON strings that implements IntoResponse.
 /// Use this to return pre-built JSON without re-parsing/re-serializing.
 pub struct RawJson(pub String);
@@ -32,13 +57,132 @@ impl IntoResponse for RawJson {
     }
 }
 
-/// Helper to fetch a cipher by id for a user or return NotFound.
-async fn fetch_cipher_for_user(
+/// SQL predicate matching ciphers the user (bound at `?{param}`) can see:
+/// their own ciphers, every cipher of organizations they own or administer, and
+/// ciphers in collections they are assigned to, directly or through a group.
+pub(crate) fn cipher_access_filter(alias: &str, param: usize) -> String {
+    access_filter(alias, param, "")
+}
+
+/// Like [`cipher_access_filter`], but excludes collections assigned read-only.
+pub(crate) fn cipher_write_filter(alias: &str, param: usize) -> String {
+    access_filter(alias, param, " AND uc.read_only = 0")
+}
+
+fn access_filter(alias: &str, param: usize, assignment_condition: &str) -> String {
+    format!(
+        "({alias}.user_id = ?{param} \
+         OR {alias}.organization_id IN (SELECT organization_id FROM users_organizations \
+             WHERE user_id = ?{param} AND status = 2 AND type IN (0, 1)) \
+         OR {alias}.id IN (SELECT cc.cipher_id FROM ciphers_collections cc \
+             JOIN member_collection_access uc ON uc.collection_id = cc.collection_id \
+             JOIN users_organizations uo ON uo.id = uc.membership_id \
+             WHERE uo.user_id = ?{param} AND uo.status = 2{assignment_condition}))"
+    )
+}
+
+/// SQL condition: the viewer (`?1`) may use `c` with the given assignment flag
+/// (`read_only` or `hide_passwords`) unset. Always true for personal ciphers.
+fn org_permission_condition(flag_column: &str) -> String {
+    format!(
+        "(c.organization_id IS NULL \
+         OR EXISTS (SELECT 1 FROM users_organizations uo WHERE uo.organization_id = c.organization_id \
+             AND uo.user_id = ?1 AND uo.status = 2 AND uo.type IN (0, 1)) \
+         OR EXISTS (SELECT 1 FROM ciphers_collections cc \
+             JOIN member_collection_access uc ON uc.collection_id = cc.collection_id \
+             JOIN users_organizations uo ON uo.id = uc.membership_id \
+             WHERE cc.cipher_id = c.id AND uo.user_id = ?1 AND uo.status = 2 AND uc.{flag_column} = 0))"
+    )
+}
+
+/// The viewer's (`?1`) `users_ciphers` row of `c`, aliased `ucs`.
+const USER_STATE_JOIN: &str =
+    "LEFT JOIN users_ciphers ucs ON ucs.cipher_id = c.id AND ucs.user_id = ?1";
+
+/// Condition hiding SSH keys (type 5) from clients too old to know them, appended to
+/// the `WHERE` clause of cipher listings.
+pub(crate) fn client_cipher_type_filter(headers: &HeaderMap) -> &'static str {
+    if client_supports_ssh_keys(headers) {
+        ""
+    } else {
+        " AND c.type != 5"
+    }
+}
+
+fn stale_cipher() -> AppError {
+    AppError::BadRequest(
+        "The client copy of this cipher is out of date. Resync the client and try again."
+            .to_string(),
+    )
+}
+
+/// Reject a write based on a client copy older than the stored cipher, like the
+/// official server: `lastKnownRevisionDate` may trail the stored revision by a second.
+fn ensure_not_stale(
+    cipher_id: &str,
+    stored_revision: &str,
+    last_known_revision: Option<&str>,
+) -> Result<(), AppError> {
+    let Some(last_known) = last_known_revision else {
+        return Ok(());
+    };
+    match DateTime::parse_from_rfc3339(last_known) {
+        Ok(client_dt) => match DateTime::parse_from_rfc3339(stored_revision) {
+            Ok(server_dt) => {
+                if server_dt
+                    .signed_duration_since(client_dt)
+                    .num_milliseconds()
+                    > 1000
+                {
+                    return Err(stale_cipher());
+                }
+            }
+            Err(err) => log::warn!(
+                "Error parsing server revisionDate '{}' for cipher {}: {}",
+                stored_revision,
+                cipher_id,
+                err
+            ),
+        },
+        Err(err) => log::warn!(
+            "Error parsing lastKnownRevisionDate '{}': {}",
+            last_known,
+            err
+        ),
+    }
+    Ok(())
+}
+
+/// Reject folders that do not exist or belong to another user.
+async fn ensure_own_folder(
+    db: &crate::db::Db,
+    user_id: &str,
+    folder_id: Option<&str>,
+) -> Result<(), AppError> {
+    let Some(folder_id) = folder_id else {
+        return Ok(());
+    };
+    let folder_exists: Option<serde_json::Value> = db
+        .prepare("SELECT id FROM folders WHERE id = ?1 AND user_id = ?2")
+        .bind(&[folder_id.into(), user_id.into()])?
+        .first(None)
+        .await?;
+
+    if folder_exists.is_none() {
+        return Err(AppError::BadRequest(
+            "Invalid folder: Folder does not exist or belongs to another user".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+async fn fetch_cipher_with_filter(
     db: &crate::db::Db,
     cipher_id: &str,
     user_id: &str,
+    filter: String,
 ) -> Result<CipherDBModel, AppError> {
-    db.prepare("SELECT * FROM ciphers WHERE id = ?1 AND user_id = ?2")
+    db.prepare(format!("SELECT * FROM ciphers WHERE id = ?1 AND {filter}"))
         .bind(&[cipher_id.to_string().into(), user_id.to_string().into()])?
         .first(None)
         .await
@@ -46,10 +190,250 @@ async fn fetch_cipher_for_user(
         .ok_or_else(|| AppError::NotFound("Cipher not found".to_string()))
 }
 
+/// Helper to fetch a cipher by id for a user or return NotFound.
+async fn fetch_cipher_for_user(
+    db: &crate::db::Db,
+    cipher_id: &str,
+    user_id: &str,
+) -> Result<CipherDBModel, AppError> {
+    fetch_cipher_with_filter(db, cipher_id, user_id, cipher_access_filter("ciphers", 2)).await
+}
+
+/// Fetch a cipher the user may modify (not only through read-only collections).
+async fn fetch_cipher_for_write(
+    db: &crate::db::Db,
+    cipher_id: &str,
+    user_id: &str,
+) -> Result<CipherDBModel, AppError> {
+    match fetch_cipher_with_filter(db, cipher_id, user_id, cipher_write_filter("ciphers", 2)).await
+    {
+        Err(AppError::NotFound(_))
+            if fetch_cipher_for_user(db, cipher_id, user_id).await.is_ok() =>
+        {
+            Err(AppError::BadRequest(
+                "You don't have permission to edit this cipher".to_string(),
+            ))
+        }
+        result => result,
+    }
+}
+
+/// Attachments plus the viewer-dependent fields (edit, viewPassword, collectionIds,
+/// and the member's own favorite and folder) of a single cipher response.
+async fn hydrate_cipher(
+    db: &crate::db::Db,
+    env: &Env,
+    cipher: &mut Cipher,
+    user_id: &str,
+) -> Result<(), AppError> {
+    attachments::hydrate_cipher_attachments(db, env, cipher).await?;
+
+    if cipher.organization_id.is_none() {
+        return Ok(());
+    }
+
+    #[derive(Deserialize)]
+    struct Row {
+        collection_ids: String,
+        edit: i32,
+        view_password: i32,
+        favorite: i32,
+        folder_id: Option<String>,
+    }
+
+    let row: Option<Row> = db
+        .prepare(format!(
+            "SELECT (SELECT json_group_array(cc.collection_id) FROM ciphers_collections cc WHERE cc.cipher_id = c.id) AS collection_ids, \
+             {} AS edit, {} AS view_password, \
+             COALESCE(ucs.favorite, 0) AS favorite, ucs.folder_id AS folder_id \
+             FROM ciphers c {USER_STATE_JOIN} WHERE c.id = ?2",
+            org_permission_condition("read_only"),
+            org_permission_condition("hide_passwords"),
+        ))
+        .bind(&[user_id.into(), cipher.id.clone().into()])?
+        .first(None)
+        .await
+        .map_err(|_| AppError::Database)?;
+
+    if let Some(row) = row {
+        cipher.collection_ids = Some(serde_json::from_str(&row.collection_ids).unwrap_or_default());
+        cipher.edit = row.edit != 0;
+        cipher.view_password = row.view_password != 0;
+        cipher.favorite = row.favorite != 0;
+        cipher.folder_id = row.folder_id;
+        cipher.organization_use_totp = true;
+    }
+    Ok(())
+}
+
+/// Statement bumping the revision dates of everyone who can see a changed cipher:
+/// the acting user for personal ciphers, all confirmed members for org ciphers.
+/// Handlers batch it with the cipher write, so clients never miss a saved change.
+fn cipher_change_statement(
+    db: &crate::db::Db,
+    claims: &Claims,
+    organization_id: Option<&str>,
+    now: &str,
+) -> Result<D1PreparedStatement, AppError> {
+    match organization_id {
+        Some(org_id) => Membership::touch_confirmed_users_statement(db, org_id, now),
+        None => db::touch_user_statement(db, &claims.sub, now),
+    }
+}
+
+/// Notify everyone whose revision date [`cipher_change_statement`] bumped.
+async fn publish_cipher_change(
+    db: &crate::db::Db,
+    env: &Env,
+    claims: &Claims,
+    organization_id: Option<&str>,
+    update_type: UpdateType,
+    cipher_id: &str,
+    now: &str,
+) -> Result<(), AppError> {
+    let user_ids = match organization_id {
+        Some(org_id) => Membership::confirmed_user_ids(db, org_id).await?,
+        None => vec![claims.sub.clone()],
+    };
+
+    for user_id in user_ids {
+        notifications::publish_cipher_update(
+            env.clone(),
+            user_id,
+            update_type,
+            cipher_id.to_string(),
+            now.to_string(),
+            Some(claims.device.clone()),
+        );
+    }
+    Ok(())
+}
+
+/// Organizations owning any of the ciphers in `{"ids": [...]}` that the user can access.
+async fn organizations_for_cipher_ids_json(
+    db: &crate::db::Db,
+    body: &str,
+    user_id: &str,
+) -> Result<Vec<String>, AppError> {
+    #[derive(Deserialize)]
+    struct Row {
+        organization_id: String,
+    }
+
+    let rows: Vec<Row> = db
+        .prepare(format!(
+            "SELECT DISTINCT c.organization_id FROM ciphers c \
+             WHERE c.organization_id IS NOT NULL AND {} \
+             AND c.id IN (SELECT value FROM json_each(?1, '$.ids'))",
+            cipher_access_filter("c", 2)
+        ))
+        .bind(&[body.into(), user_id.into()])?
+        .all()
+        .await
+        .map_err(db::map_d1_json_error)?
+        .results()
+        .map_err(|_| AppError::Database)?;
+
+    Ok(rows.into_iter().map(|r| r.organization_id).collect())
+}
+
+/// Bulk variant of [`cipher_change_statement`]: the acting user and every confirmed
+/// member of the affected organizations.
+fn ciphers_change_statements(
+    db: &crate::db::Db,
+    claims: &Claims,
+    organization_ids: &[String],
+    now: &str,
+) -> Result<Vec<D1PreparedStatement>, AppError> {
+    let mut statements = vec![db::touch_user_statement(db, &claims.sub, now)?];
+    for org_id in organization_ids {
+        statements.push(Membership::touch_confirmed_users_statement(
+            db, org_id, now,
+        )?);
+    }
+    Ok(statements)
+}
+
+/// Bulk variant of [`publish_cipher_change`]: a full cipher sync for everyone
+/// [`ciphers_change_statements`] covers.
+async fn publish_ciphers_change(
+    db: &crate::db::Db,
+    env: &Env,
+    claims: &Claims,
+    organization_ids: &[String],
+    now: &str,
+) -> Result<(), AppError> {
+    let mut user_ids = vec![claims.sub.clone()];
+
+    for org_id in organization_ids {
+        for user_id in Membership::confirmed_user_ids(db, org_id).await? {
+            if !user_ids.contains(&user_id) {
+                user_ids.push(user_id);
+            }
+        }
+    }
+
+    for user_id in user_ids {
+        notifications::publish_user_update(
+            env.clone(),
+            user_id,
+            UpdateType::SyncCiphers,
+            now.to_string(),
+            Some(claims.device.clone()),
+        );
+    }
+    Ok(())
+}
+
+/// New org ciphers must go into collections of that organization the user can write to;
+/// owners and admins may also create them outside any collection.
+async fn ensure_can_create_in(
+    db: &crate::db::Db,
+    user_id: &str,
+    organization_id: Option<&str>,
+    collection_ids: &[String],
+) -> Result<(), AppError> {
+    let Some(org_id) = organization_id else {
+        return Ok(());
+    };
+
+    let membership =
+        organizations::require_member_role(db, org_id, user_id, MembershipType::User).await?;
+    if membership
+        .membership_type()
+        .is_at_least(MembershipType::Admin)
+        || membership.has_permission(Permission::EditAnyCollection)
+    {
+        if !Collection::foreign_ids(db, org_id, collection_ids)
+            .await?
+            .is_empty()
+        {
+            return Err(AppError::BadRequest(
+                "Collection does not belong to this organization".to_string(),
+            ));
+        }
+        return Ok(());
+    }
+
+    if collection_ids.is_empty() {
+        return Err(AppError::BadRequest(
+            "You must select at least one collection".to_string(),
+        ));
+    }
+    let writable = Collection::writable_ids_for_user(db, org_id, user_id).await?;
+    if collection_ids.iter().any(|id| !writable.contains(id)) {
+        return Err(AppError::BadRequest(
+            "You don't have permission to add items to this collection".to_string(),
+        ));
+    }
+    Ok(())
+}
+
 #[worker::send]
 pub async fn create_cipher(
     claims: Claims,
     State(env): State<Arc<Env>>,
+    headers: HeaderMap,
     Json(payload): Json<CreateCipherRequest>,
 ) -> Result<Json<Cipher>, AppError> {
     let db = db::get_db(&env)?;
@@ -64,14 +448,29 @@ pub async fn create_cipher(
 
     let data_value = serde_json::to_value(&cipher_data).map_err(|_| AppError::Internal)?;
 
+    let organization_id = cipher_data_req.organization_id.clone();
+    ensure_can_create_in(
+        &db,
+        &claims.sub,
+        organization_id.as_deref(),
+        &payload.collection_ids,
+    )
+    .await?;
+    ensure_own_folder(&db, &claims.sub, cipher_data_req.folder_id.as_deref()).await?;
+    // Org ciphers are shared rows: no owning user, and the creator's folder/favorite
+    // go to `users_ciphers`.
+    let personal = organization_id.is_none();
+    let favorite = cipher_data_req.favorite.unwrap_or(false);
+    let folder_id = cipher_data_req.folder_id;
+
     let mut cipher = Cipher {
         id: Uuid::new_v4().to_string(),
-        user_id: Some(claims.sub.clone()),
-        organization_id: cipher_data_req.organization_id.clone(),
+        user_id: personal.then(|| claims.sub.clone()),
+        organization_id,
         r#type: cipher_data_req.r#type,
         data: data_value,
-        favorite: cipher_data_req.favorite.unwrap_or(false),
-        folder_id: cipher_data_req.folder_id.clone(),
+        favorite: personal && favorite,
+        folder_id: folder_id.clone().filter(|_| personal),
         deleted_at: None,
         archived_at: None,
         created_at: now.clone(),
@@ -90,7 +489,7 @@ pub async fn create_cipher(
 
     let data = serde_json::to_string(&cipher.data).map_err(|_| AppError::Internal)?;
 
-    d1_query!(
+    let mut statements = vec![d1_query!(
         &db,
         "INSERT INTO ciphers (id, user_id, organization_id, type, data, favorite, folder_id, created_at, updated_at)
          VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
@@ -104,21 +503,54 @@ pub async fn create_cipher(
          cipher.folder_id,
          cipher.created_at,
          cipher.updated_at,
-    ).map_err(|_|AppError::Database)?
-    .run()
-    .await?;
+    ).map_err(|_|AppError::Database)?];
 
-    attachments::hydrate_cipher_attachments(&db, env.as_ref(), &mut cipher).await?;
-    db::touch_user_updated_at(&db, &claims.sub, &cipher.updated_at).await?;
-
-    notifications::publish_cipher_update(
-        (*env).clone(),
-        claims.sub,
+    if cipher.organization_id.is_some() {
+        let collection_ids = cipher.collection_ids.as_deref().unwrap_or_default();
+        statements.extend(Collection::set_for_cipher_statements(
+            &db,
+            &cipher.id,
+            collection_ids,
+        )?);
+        if favorite || folder_id.is_some() {
+            statements.push(UserCipherState::save_statement(
+                &db,
+                &claims.sub,
+                &cipher.id,
+                favorite,
+                folder_id.as_deref(),
+                None,
+            )?);
+        }
+    }
+    statements.push(cipher_change_statement(
+        &db,
+        &claims,
+        cipher.organization_id.as_deref(),
+        &now,
+    )?);
+    db.batch(statements).await?;
+
+    hydrate_cipher(&db, env.as_ref(), &mut cipher, &claims.sub).await?;
+    record_cipher_event(
+        &db,
+        EventType::CipherCreated,
+        cipher.organization_id.as_deref(),
+        &cipher.id,
+        &claims,
+        &headers,
+    )
+    .await;
+    publish_cipher_change(
+        &db,
+        env.as_ref(),
+        &claims,
+        cipher.organization_id.as_deref(),
         UpdateType::SyncCipherCreate,
-        cipher.id.clone(),
-        cipher.updated_at.clone(),
-        Some(claims.device),
-    );
+        &cipher.id,
+        &cipher.updated_at,
+    )
+    .await?;
 
     Ok(Json(cipher))
 }
@@ -127,6 +559,7 @@ pub async fn create_cipher(
 pub async fn update_cipher(
     claims: Claims,
     State(env): State<Arc<Env>>,
+    headers: HeaderMap,
     Extension(BaseUrl(_base_url)): Extension<BaseUrl>,
     Path(id): Path<String>,
     Json(payload): Json<CipherRequestData>,
@@ -134,57 +567,57 @@ pub async fn update_cipher(
     let db = db::get_db(&env)?;
     let now = db::now_string();
 
-    let existing_cipher = fetch_cipher_for_user(&db, &id, &claims.sub).await?;
-
-    // Validate folder ownership if provided
-    if let Some(ref folder_id) = payload.folder_id {
-        let folder_exists: Option<serde_json::Value> = db
-            .prepare("SELECT id FROM folders WHERE id = ?1 AND user_id = ?2")
-            .bind(&[folder_id.clone().into(), claims.sub.clone().into()])?
-            .first(None)
-            .await?;
+    let existing_cipher = fetch_cipher_for_write(&db, &id, &claims.sub).await?;
 
-        if folder_exists.is_none() {
-            return Err(AppError::BadRequest(
-                "Invalid folder: Folder does not exist or belongs to another user".to_string(),
-            ));
-        }
+    // Moving a cipher between owners goes through the share flow, not a plain update.
+    if payload.organization_id.is_some()
+        && payload.organization_id != existing_cipher.organization_id
+    {
+        return Err(AppError::BadRequest(
+            "The cipher's organization cannot be changed by an update".to_string(),
+        ));
     }
-
-    // Reject updates based on stale client data when the last known revision is provided
-    if let Some(dt) = payload.last_known_revision_date.as_deref() {
-        match DateTime::parse_from_rfc3339(dt) {
-            Ok(client_dt) => match DateTime::parse_from_rfc3339(&existing_cipher.updated_at) {
-                Ok(server_dt) => {
-                    if server_dt.signed_duration_since(client_dt).num_seconds() > 1 {
-                        return Err(AppError::BadRequest(
-                            "The client copy of this cipher is out of date. Resync the client and try again.".to_string(),
-                        ));
-                    }
-                }
-                Err(err) => log::warn!(
-                    "Error parsing server revisionDate '{}' for cipher {}: {}",
-                    existing_cipher.updated_at,
-                    existing_cipher.id,
-                    err
-                ),
-            },
-            Err(err) => log::warn!("Error parsing lastKnownRevisionDate '{}': {}", dt, err),
-        }
+    let personal = existing_cipher.organization_id.is_none();
+    ensure_own_folder(&db, &claims.sub, payload.folder_id.as_deref()).await?;
+    let favorite = payload.favorite.unwrap_or(false);
+    let folder_id = payload.folder_id;
+
+    ensure_not_stale(
+        &existing_cipher.id,
+        &existing_cipher.updated_at,
+        payload.last_known_revision_date.as_deref(),
+    )?;
+
+    let previous = serde_json::from_str::<CipherData>(&existing_cipher.data).ok();
+    // A client without cipher key support would save the item without its key, leaving
+    // the other fields encrypted with a key nobody has any more.
+    if previous
+        .as_ref()
+        .is_some_and(|p| p.type_fields.key.is_some())
+        && !client_supports_cipher_keys(&headers)
+    {
+        return Err(AppError::BadRequest(
+            "Cannot edit item. Update to the latest version of Bitwarden and try again."
+                .to_string(),
+        ));
     }
 
-    let cipher_data = CipherData::new(payload.name, payload.notes, payload.type_fields);
+    let mut type_fields = payload.type_fields;
+    if let Some(previous) = &previous {
+        type_fields.carry_password_history(&previous.type_fields, &now);
+    }
+    let cipher_data = CipherData::new(payload.name, payload.notes, type_fields);
 
     let data_value = serde_json::to_value(&cipher_data).map_err(|_| AppError::Internal)?;
 
     let mut cipher = Cipher {
         id: id.clone(),
-        user_id: Some(claims.sub.clone()),
-        organization_id: payload.organization_id.clone(),
+        user_id: existing_cipher.user_id,
+        organization_id: existing_cipher.organization_id,
         r#type: payload.r#type,
         data: data_value,
-        favorite: payload.favorite.unwrap_or(false),
-        folder_id: payload.folder_id.clone(),
+        favorite: personal && favorite,
+        folder_id: folder_id.clone().filter(|_| personal),
         deleted_at: None,
         archived_at: existing_cipher.archived_at,
         created_at: existing_cipher.created_at,
@@ -199,58 +632,81 @@ pub async fn update_cipher(
 
     let data = serde_json::to_string(&cipher.data).map_err(|_| AppError::Internal)?;
 
-    d1_query!(
+    // Only write over the revision checked above, so that an edit saved by another
+    // device in the meantime is not lost either. The other writes of the batch only
+    // apply once the cipher is at the new revision, i.e. when this one did.
+    let mut statements = vec![d1_query!(
         &db,
-        "UPDATE ciphers SET organization_id = ?1, type = ?2, data = ?3, favorite = ?4, folder_id = ?5, updated_at = ?6 WHERE id = ?7 AND user_id = ?8",
-        cipher.organization_id,
+        "UPDATE ciphers SET type = ?1, data = ?2, favorite = ?3, folder_id = ?4, updated_at = ?5 WHERE id = ?6 AND updated_at = ?7",
         cipher.r#type,
         data,
         cipher.favorite,
         cipher.folder_id,
         cipher.updated_at,
         id,
-        claims.sub,
-    ).map_err(|_|AppError::Database)?
-    .run()
-    .await?;
-
-    if let Some(attachments2) = &payload.attachments2 {
-        for (attachment_id, attachment) in attachments2 {
-            let result = d1_query!(
+        existing_cipher.updated_at,
+    ).map_err(|_|AppError::Database)?];
+    if !personal {
+        statements.push(UserCipherState::save_statement(
+            &db,
+            &claims.sub,
+            &id,
+            favorite,
+            folder_id.as_deref(),
+            Some(&now),
+        )?);
+    }
+    for (attachment_id, attachment) in payload.attachments2.iter().flatten() {
+        statements.push(
+            d1_query!(
                 &db,
-                "UPDATE attachments SET file_name = ?1, akey = ?2, updated_at = ?3 WHERE id = ?4 AND cipher_id = ?5",
+                "UPDATE attachments SET file_name = ?1, akey = ?2, updated_at = ?3
+                 WHERE id = ?4 AND cipher_id = ?5
+                   AND EXISTS (SELECT 1 FROM ciphers WHERE id = ?5 AND updated_at = ?3)",
                 attachment.file_name,
                 attachment.key,
                 now,
                 attachment_id,
                 id
             )
-            .map_err(|_| AppError::Database)?
-            .run()
-            .await;
-
-            if let Err(e) = result {
-                log::warn!(
-                    "Failed to update attachment {} for cipher {}: {:?}",
-                    attachment_id,
-                    id,
-                    e
-                );
-            }
-        }
+            .map_err(|_| AppError::Database)?,
+        );
+    }
+    statements.push(cipher_change_statement(
+        &db,
+        &claims,
+        cipher.organization_id.as_deref(),
+        &now,
+    )?);
+    let results = db.batch(statements).await?;
+    let changes = match results.first() {
+        Some(result) => result.meta()?.and_then(|m| m.changes).unwrap_or(0),
+        None => 0,
+    };
+    if changes == 0 {
+        return Err(stale_cipher());
     }
 
-    attachments::hydrate_cipher_attachments(&db, env.as_ref(), &mut cipher).await?;
-    db::touch_user_updated_at(&db, &claims.sub, &cipher.updated_at).await?;
-
-    notifications::publish_cipher_update(
-        (*env).clone(),
-        claims.sub,
+    hydrate_cipher(&db, env.as_ref(), &mut cipher, &claims.sub).await?;
+    record_cipher_event(
+        &db,
+        EventType::CipherUpdated,
+        cipher.organization_id.as_deref(),
+        &cipher.id,
+        &claims,
+        &headers,
+    )
+    .await;
+    publish_cipher_change(
+        &db,
+        env.as_ref(),
+        &claims,
+        cipher.organization_id.as_deref(),
         UpdateType::SyncCipherUpdate,
-        cipher.id.clone(),
-        cipher.updated_at.clone(),
-        Some(claims.device),
-    );
+        &cipher.id,
+        &cipher.updated_at,
+    )
+    .await?;
 
     Ok(Json(cipher))
 }
@@ -260,18 +716,61 @@ pub async fn update_cipher(
 pub async fn list_ciphers(
     claims: Claims,
     State(env): State<Arc<Env>>,
+    headers: HeaderMap,
 ) -> Result<RawJson, AppError> {
     let db = db::get_db(&env)?;
     build_cipher_list_response(
         &db,
         env.as_ref(),
-        "WHERE c.user_id = ?1 AND c.deleted_at IS NULL",
+        &format!(
+            "WHERE {} AND c.deleted_at IS NULL{}",
+            cipher_access_filter("c", 1),
+            client_cipher_type_filter(&headers)
+        ),
         &[claims.sub.clone().into()],
         "ORDER BY c.updated_at DESC",
     )
     .await
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrganizationCiphersQuery {
+    pub organization_id: String,
+}
+
+/// GET /api/ciphers/organization-details?organizationId=... - all ciphers of an organization
+#[worker::send]
+pub async fn list_organization_ciphers(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Query(query): Query<OrganizationCiphersQuery>,
+) -> Result<RawJson, AppError> {
+    let db = db::get_db(&env)?;
+    organizations::require_member_access(
+        &db,
+        &query.organization_id,
+        &claims.sub,
+        MembershipType::Manager,
+        &[
+            Permission::AccessImportExport,
+            Permission::AccessReports,
+            Permission::EditAnyCollection,
+            Permission::DeleteAnyCollection,
+        ],
+    )
+    .await?;
+
+    build_cipher_list_response(
+        &db,
+        env.as_ref(),
+        "WHERE c.organization_id = ?2",
+        &[claims.sub.into(), query.organization_id.into()],
+        "ORDER BY c.updated_at DESC",
+    )
+    .await
+}
+
 /// GET /api/ciphers/{id}
 #[worker::send]
 pub async fn get_cipher(
@@ -283,7 +782,7 @@ pub async fn get_cipher(
     let cipher = fetch_cipher_for_user(&db, &id, &claims.sub).await?;
     let mut cipher: Cipher = cipher.into();
 
-    attachments::hydrate_cipher_attachments(&db, env.as_ref(), &mut cipher).await?;
+    hydrate_cipher(&db, env.as_ref(), &mut cipher, &claims.sub).await?;
 
     Ok(Json(cipher))
 }
@@ -298,57 +797,147 @@ pub async fn get_cipher_details(
     get_cipher(claims, state, id).await
 }
 
-/// PUT/POST /api/ciphers/{id}/partial
+/// PUT/POST /api/ciphers/{id}/collections - replace the collections of an org cipher
+///
+/// Members other than owners/admins can only add or remove collections they can
+/// write to; assignments to other collections are kept unchanged.
 #[worker::send]
-pub async fn update_cipher_partial(
+pub async fn update_cipher_collections(
     claims: Claims,
     State(env): State<Arc<Env>>,
     Path(id): Path<String>,
-    Json(payload): Json<PartialCipherData>,
+    Json(payload): Json<CipherCollectionsRequest>,
 ) -> Result<Json<Cipher>, AppError> {
     let db = db::get_db(&env)?;
-    let user_id = &claims.sub;
+    let existing = fetch_cipher_for_write(&db, &id, &claims.sub).await?;
+    let org_id = existing.organization_id.clone().ok_or_else(|| {
+        AppError::BadRequest("Cipher doesn't belong to an organization".to_string())
+    })?;
 
-    // Validate folder ownership if provided
-    if let Some(ref folder_id) = payload.folder_id {
-        let folder_exists: Option<serde_json::Value> = db
-            .prepare("SELECT id FROM folders WHERE id = ?1 AND user_id = ?2")
-            .bind(&[folder_id.clone().into(), user_id.clone().into()])?
-            .first(None)
-            .await?;
+    let membership =
+        organizations::require_member_role(&db, &org_id, &claims.sub, MembershipType::User).await?;
+    if !Collection::foreign_ids(&db, &org_id, &payload.collection_ids)
+        .await?
+        .is_empty()
+    {
+        return Err(AppError::BadRequest(
+            "Collection does not belong to this organization".to_string(),
+        ));
+    }
 
-        if folder_exists.is_none() {
+    let mut collection_ids = payload.collection_ids;
+    if !membership
+        .membership_type()
+        .is_at_least(MembershipType::Admin)
+        && !membership.has_permission(Permission::EditAnyCollection)
+    {
+        let writable = Collection::writable_ids_for_user(&db, &org_id, &claims.sub).await?;
+        let current = Collection::ids_for_cipher(&db, &id).await?;
+        if collection_ids
+            .iter()
+            .any(|cid| !writable.contains(cid) && !current.contains(cid))
+        {
             return Err(AppError::BadRequest(
-                "Invalid folder: Folder does not exist or belongs to another user".to_string(),
+                "You don't have permission to add items to this collection".to_string(),
             ));
         }
+        collection_ids.retain(|cid| writable.contains(cid));
+        collection_ids.extend(current.into_iter().filter(|cid| !writable.contains(cid)));
     }
 
-    // Ensure cipher exists and belongs to user
-    fetch_cipher_for_user(&db, &id, user_id).await?;
-
     let now = db::now_string();
+    let mut statements = Collection::set_for_cipher_statements(&db, &id, &collection_ids)?;
+    statements.push(
+        d1_query!(
+            &db,
+            "UPDATE ciphers SET updated_at = ?1 WHERE id = ?2",
+            now,
+            id
+        )
+        .map_err(|_| AppError::Database)?,
+    );
+    statements.push(cipher_change_statement(&db, &claims, Some(&org_id), &now)?);
+    db.batch(statements).await?;
+
+    let mut cipher: Cipher = fetch_cipher_for_user(&db, &id, &claims.sub).await?.into();
+    hydrate_cipher(&db, env.as_ref(), &mut cipher, &claims.sub).await?;
 
-    d1_query!(
+    publish_cipher_change(
         &db,
-        "UPDATE ciphers SET folder_id = ?1, favorite = ?2, updated_at = ?3 WHERE id = ?4 AND user_id = ?5",
-        payload.folder_id,
-        payload.favorite,
-        now,
-        id,
-        user_id,
+        env.as_ref(),
+        &claims,
+        Some(&org_id),
+        UpdateType::SyncCipherUpdate,
+        &id,
+        &now,
     )
-    .map_err(|_| AppError::Database)?
-    .run()
     .await?;
 
+    Ok(Json(cipher))
+}
+
+/// PUT/POST /api/ciphers/{id}/collections_v2 - newer clients expect a wrapped response
+#[worker::send]
+pub async fn update_cipher_collections_v2(
+    claims: Claims,
+    state: State<Arc<Env>>,
+    id: Path<String>,
+    payload: Json<CipherCollectionsRequest>,
+) -> Result<Json<Value>, AppError> {
+    let Json(cipher) = update_cipher_collections(claims, state, id, payload).await?;
+    Ok(Json(serde_json::json!({
+        "cipher": cipher,
+        "unavailable": false,
+        "object": "optionalCipherDetails"
+    })))
+}
+
+/// PUT/POST /api/ciphers/{id}/partial
+#[worker::send]
+pub async fn update_cipher_partial(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(id): Path<String>,
+    Json(payload): Json<PartialCipherData>,
+) -> Result<Json<Cipher>, AppError> {
+    let db = db::get_db(&env)?;
+    let user_id = &claims.sub;
+
+    ensure_own_folder(&db, user_id, payload.folder_id.as_deref()).await?;
+
+    // Ensure cipher exists and belongs to user
+    let existing = fetch_cipher_for_user(&db, &id, user_id).await?;
     let now = db::now_string();
-    db::touch_user_updated_at(&db, user_id, &now).await?;
+
+    // Folder and favorite are the user's own state; org ciphers keep it in `users_ciphers`.
+    let state = if existing.organization_id.is_none() {
+        d1_query!(
+            &db,
+            "UPDATE ciphers SET folder_id = ?1, favorite = ?2, updated_at = ?3 WHERE id = ?4 AND user_id = ?5",
+            payload.folder_id,
+            payload.favorite,
+            now,
+            id,
+            user_id,
+        )
+        .map_err(|_| AppError::Database)?
+    } else {
+        UserCipherState::save_statement(
+            &db,
+            user_id,
+            &id,
+            payload.favorite,
+            payload.folder_id.as_deref(),
+            None,
+        )?
+    };
+    db.batch(vec![state, db::touch_user_statement(&db, user_id, &now)?])
+        .await?;
 
     let cipher = fetch_cipher_for_user(&db, &id, user_id).await?;
     let mut cipher: Cipher = cipher.into();
 
-    attachments::hydrate_cipher_attachments(&db, env.as_ref(), &mut cipher).await?;
+    hydrate_cipher(&db, env.as_ref(), &mut cipher, &claims.sub).await?;
 
     Ok(Json(cipher))
 }
@@ -359,33 +948,44 @@ pub async fn update_cipher_partial(
 pub async fn soft_delete_cipher(
     claims: Claims,
     State(env): State<Arc<Env>>,
+    headers: HeaderMap,
     Path(id): Path<String>,
 ) -> Result<Json<()>, AppError> {
     let db = db::get_db(&env)?;
-    fetch_cipher_for_user(&db, &id, &claims.sub).await?;
+    let cipher = fetch_cipher_for_write(&db, &id, &claims.sub).await?;
     let now = db::now_string();
 
-    d1_query!(
-        &db,
-        "UPDATE ciphers SET deleted_at = ?1, updated_at = ?1 WHERE id = ?2 AND user_id = ?3",
-        now,
-        id,
-        claims.sub
-    )
-    .map_err(|_| AppError::Database)?
-    .run()
+    db.batch(vec![
+        d1_query!(
+            &db,
+            "UPDATE ciphers SET deleted_at = ?1, updated_at = ?1 WHERE id = ?2",
+            now,
+            id
+        )
+        .map_err(|_| AppError::Database)?,
+        cipher_change_statement(&db, &claims, cipher.organization_id.as_deref(), &now)?,
+    ])
     .await?;
 
-    db::touch_user_updated_at(&db, &claims.sub, &now).await?;
-
-    notifications::publish_cipher_update(
-        (*env).clone(),
-        claims.sub,
+    record_cipher_event(
+        &db,
+        EventType::CipherSoftDeleted,
+        cipher.organization_id.as_deref(),
+        &cipher.id,
+        &claims,
+        &headers,
+    )
+    .await;
+    publish_cipher_change(
+        &db,
+        env.as_ref(),
+        &claims,
+        cipher.organization_id.as_deref(),
         UpdateType::SyncCipherUpdate,
-        id,
-        now,
-        Some(claims.device),
-    );
+        &id,
+        &now,
+    )
+    .await?;
 
     Ok(Json(()))
 }
@@ -401,28 +1001,28 @@ pub async fn soft_delete_ciphers_bulk(
 ) -> Result<Json<()>, AppError> {
     let db = db::get_db(&env)?;
     let now = db::now_string();
+    let organization_ids = organizations_for_cipher_ids_json(&db, &body, &claims.sub).await?;
 
-    d1_query!(
-        &db,
-        "UPDATE ciphers SET deleted_at = ?1, updated_at = ?1 WHERE user_id = ?2 AND id IN (SELECT value FROM json_each(?3, '$.ids'))",
-        now,
-        claims.sub,
-        body
-    )
-    .map_err(|_| AppError::Database)?
-    .run()
-    .await
-    .map_err(db::map_d1_json_error)?;
-
-    db::touch_user_updated_at(&db, &claims.sub, &now).await?;
-
-    notifications::publish_user_update(
-        (*env).clone(),
-        claims.sub,
-        UpdateType::SyncCiphers,
+    let mut statements = vec![d1_query!(
+        &db,
+        &format!(
+            "UPDATE ciphers SET deleted_at = ?1, updated_at = ?1 WHERE {} AND id IN (SELECT value FROM json_each(?3, '$.ids'))",
+            cipher_write_filter("ciphers", 2)
+        ),
         now,
-        Some(claims.device),
-    );
+        claims.sub,
+        body
+    )
+    .map_err(|_| AppError::Database)?];
+    statements.extend(ciphers_change_statements(
+        &db,
+        &claims,
+        &organization_ids,
+        &now,
+    )?);
+    db.batch(statements).await.map_err(db::map_d1_json_error)?;
+
+    publish_ciphers_change(&db, env.as_ref(), &claims, &organization_ids, &now).await?;
 
     Ok(Json(()))
 }
@@ -433,10 +1033,11 @@ pub async fn soft_delete_ciphers_bulk(
 pub async fn hard_delete_cipher(
     claims: Claims,
     State(env): State<Arc<Env>>,
+    headers: HeaderMap,
     Path(id): Path<String>,
 ) -> Result<Json<()>, AppError> {
     let db = db::get_db(&env)?;
-    fetch_cipher_for_user(&db, &id, &claims.sub).await?;
+    let cipher = fetch_cipher_for_write(&db, &id, &claims.sub).await?;
     let now = db::now_string();
 
     if attachments::attachments_enabled(env.as_ref()) {
@@ -451,26 +1052,31 @@ pub async fn hard_delete_cipher(
         attachments::delete_storage_objects(env.as_ref(), &keys).await?;
     }
 
-    d1_query!(
-        &db,
-        "DELETE FROM ciphers WHERE id = ?1 AND user_id = ?2",
-        id,
-        claims.sub
-    )
-    .map_err(|_| AppError::Database)?
-    .run()
+    db.batch(vec![
+        d1_query!(&db, "DELETE FROM ciphers WHERE id = ?1", id).map_err(|_| AppError::Database)?,
+        cipher_change_statement(&db, &claims, cipher.organization_id.as_deref(), &now)?,
+    ])
     .await?;
 
-    db::touch_user_updated_at(&db, &claims.sub, &now).await?;
-
-    notifications::publish_cipher_update(
-        (*env).clone(),
-        claims.sub,
+    record_cipher_event(
+        &db,
+        EventType::CipherDeleted,
+        cipher.organization_id.as_deref(),
+        &cipher.id,
+        &claims,
+        &headers,
+    )
+    .await;
+    publish_cipher_change(
+        &db,
+        env.as_ref(),
+        &claims,
+        cipher.organization_id.as_deref(),
         UpdateType::SyncLoginDelete,
-        id,
-        now,
-        Some(claims.device),
-    );
+        &id,
+        &now,
+    )
+    .await?;
 
     Ok(Json(()))
 }
@@ -486,6 +1092,7 @@ pub async fn hard_delete_ciphers_bulk(
 ) -> Result<Json<()>, AppError> {
     let db = db::get_db(&env)?;
     let now = db::now_string();
+    let organization_ids = organizations_for_cipher_ids_json(&db, &body, &claims.sub).await?;
 
     if attachments::attachments_enabled(env.as_ref()) {
         let keys = attachments::list_attachment_keys_for_cipher_ids_json(
@@ -498,26 +1105,25 @@ pub async fn hard_delete_ciphers_bulk(
         attachments::delete_storage_objects(env.as_ref(), &keys).await?;
     }
 
-    d1_query!(
+    let mut statements = vec![d1_query!(
         &db,
-        "DELETE FROM ciphers WHERE user_id = ?1 AND id IN (SELECT value FROM json_each(?2, '$.ids'))",
+        &format!(
+            "DELETE FROM ciphers WHERE {} AND id IN (SELECT value FROM json_each(?2, '$.ids'))",
+            cipher_write_filter("ciphers", 1)
+        ),
         claims.sub,
         body
     )
-    .map_err(|_| AppError::Database)?
-    .run()
-    .await
-    .map_err(db::map_d1_json_error)?;
-
-    db::touch_user_updated_at(&db, &claims.sub, &now).await?;
+    .map_err(|_| AppError::Database)?];
+    statements.extend(ciphers_change_statements(
+        &db,
+        &claims,
+        &organization_ids,
+        &now,
+    )?);
+    db.batch(statements).await.map_err(db::map_d1_json_error)?;
 
-    notifications::publish_user_update(
-        (*env).clone(),
-        claims.sub,
-        UpdateType::SyncCiphers,
-        now,
-        Some(claims.device),
-    );
+    publish_ciphers_change(&db, env.as_ref(), &claims, &organization_ids, &now).await?;
 
     Ok(Json(()))
 }
@@ -528,38 +1134,49 @@ pub async fn hard_delete_ciphers_bulk(
 pub async fn restore_cipher(
     claims: Claims,
     State(env): State<Arc<Env>>,
+    headers: HeaderMap,
     Path(id): Path<String>,
 ) -> Result<Json<Cipher>, AppError> {
     let db = db::get_db(&env)?;
-    fetch_cipher_for_user(&db, &id, &claims.sub).await?;
+    let existing = fetch_cipher_for_write(&db, &id, &claims.sub).await?;
     let now = db::now_string();
 
     // Update the cipher to clear deleted_at
-    d1_query!(
-        &db,
-        "UPDATE ciphers SET deleted_at = NULL, updated_at = ?1 WHERE id = ?2 AND user_id = ?3",
-        now,
-        id,
-        claims.sub
-    )
-    .map_err(|_| AppError::Database)?
-    .run()
+    db.batch(vec![
+        d1_query!(
+            &db,
+            "UPDATE ciphers SET deleted_at = NULL, updated_at = ?1 WHERE id = ?2",
+            now,
+            id
+        )
+        .map_err(|_| AppError::Database)?,
+        cipher_change_statement(&db, &claims, existing.organization_id.as_deref(), &now)?,
+    ])
     .await?;
 
     let restored = fetch_cipher_for_user(&db, &id, &claims.sub).await?;
     let mut cipher: Cipher = restored.into();
-    attachments::hydrate_cipher_attachments(&db, env.as_ref(), &mut cipher).await?;
+    hydrate_cipher(&db, env.as_ref(), &mut cipher, &claims.sub).await?;
 
-    db::touch_user_updated_at(&db, &claims.sub, &cipher.updated_at).await?;
-
-    notifications::publish_cipher_update(
-        (*env).clone(),
-        claims.sub,
+    record_cipher_event(
+        &db,
+        EventType::CipherRestored,
+        cipher.organization_id.as_deref(),
+        &cipher.id,
+        &claims,
+        &headers,
+    )
+    .await;
+    publish_cipher_change(
+        &db,
+        env.as_ref(),
+        &claims,
+        cipher.organization_id.as_deref(),
         UpdateType::SyncCipherUpdate,
-        cipher.id.clone(),
-        cipher.updated_at.clone(),
-        Some(claims.device),
-    );
+        &cipher.id,
+        &cipher.updated_at,
+    )
+    .await?;
 
     Ok(Json(cipher))
 }
@@ -575,40 +1192,81 @@ pub async fn restore_ciphers_bulk(
 ) -> Result<RawJson, AppError> {
     let db = db::get_db(&env)?;
     let now = db::now_string();
+    let organization_ids = organizations_for_cipher_ids_json(&db, &body, &claims.sub).await?;
 
     // Single bulk UPDATE using json_each() with path
-    d1_query!(
+    let mut statements = vec![d1_query!(
         &db,
-        "UPDATE ciphers SET deleted_at = NULL, updated_at = ?1 WHERE user_id = ?2 AND id IN (SELECT value FROM json_each(?3, '$.ids'))",
+        &format!(
+            "UPDATE ciphers SET deleted_at = NULL, updated_at = ?1 WHERE {} AND id IN (SELECT value FROM json_each(?3, '$.ids'))",
+            cipher_write_filter("ciphers", 2)
+        ),
         now,
         claims.sub,
         body
     )
-    .map_err(|_| AppError::Database)?
-    .run()
-    .await
-    .map_err(db::map_d1_json_error)?;
-
-    db::touch_user_updated_at(&db, &claims.sub, &now).await?;
+    .map_err(|_| AppError::Database)?];
+    statements.extend(ciphers_change_statements(
+        &db,
+        &claims,
+        &organization_ids,
+        &now,
+    )?);
+    db.batch(statements).await.map_err(db::map_d1_json_error)?;
 
-    notifications::publish_user_update(
-        (*env).clone(),
-        claims.sub.clone(),
-        UpdateType::SyncCiphers,
-        now,
-        Some(claims.device),
-    );
+    publish_ciphers_change(&db, env.as_ref(), &claims, &organization_ids, &now).await?;
 
     build_cipher_list_response(
         &db,
         env.as_ref(),
-        "WHERE c.user_id = ?1 AND c.id IN (SELECT value FROM json_each(?2, '$.ids'))",
+        &format!(
+            "WHERE {} AND c.id IN (SELECT value FROM json_each(?2, '$.ids'))",
+            cipher_access_filter("c", 1)
+        ),
         &[claims.sub.into(), body.into()],
         "",
     )
     .await
 }
 
+/// `{"ids": [...]}` of the user's own soft-deleted ciphers, in the body format of the
+/// bulk endpoints. Organization items in the trash are left to the organization.
+async fn personal_trash_ids_json(db: &db::Db, user_id: &str) -> Result<String, AppError> {
+    let body: Option<String> = d1_query!(
+        db,
+        "SELECT json_object('ids', json_group_array(id)) AS body
+         FROM ciphers WHERE user_id = ?1 AND deleted_at IS NOT NULL",
+        user_id
+    )
+    .map_err(|_| AppError::Database)?
+    .first(Some("body"))
+    .await
+    .map_err(|_| AppError::Database)?;
+    Ok(body.unwrap_or_else(|| r#"{"ids":[]}"#.to_string()))
+}
+
+/// Restore every cipher in the user's trash (PUT /api/ciphers/trash/restore)
+#[worker::send]
+pub async fn restore_trash(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+) -> Result<RawJson, AppError> {
+    let db = db::get_db(&env)?;
+    let body = personal_trash_ids_json(&db, &claims.sub).await?;
+    restore_ciphers_bulk(claims, State(env), body).await
+}
+
+/// Permanently delete every cipher in the user's trash (DELETE /api/ciphers/trash)
+#[worker::send]
+pub async fn empty_trash(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+) -> Result<Json<()>, AppError> {
+    let db = db::get_db(&env)?;
+    let body = personal_trash_ids_json(&db, &claims.sub).await?;
+    hard_delete_ciphers_bulk(claims, State(env), body).await
+}
+
 /// Archive a single cipher (PUT /api/ciphers/{id}/archive)
 #[worker::send]
 pub async fn archive_cipher(
@@ -617,38 +1275,39 @@ pub async fn archive_cipher(
     Path(id): Path<String>,
 ) -> Result<Json<Cipher>, AppError> {
     let db = db::get_db(&env)?;
-    fetch_cipher_for_user(&db, &id, &claims.sub).await.map_err(|_| {
+    let existing = fetch_cipher_for_write(&db, &id, &claims.sub).await.map_err(|_| {
         AppError::BadRequest(
             "Cipher was not archived. Ensure the provided ID is correct and you have permission to archive it.".to_string(),
         )
     })?;
     let now = db::now_string();
 
-    d1_query!(
-        &db,
-        "UPDATE ciphers SET archived_at = ?1, updated_at = ?1 WHERE id = ?2 AND user_id = ?3",
-        now,
-        id,
-        claims.sub
-    )
-    .map_err(|_| AppError::Database)?
-    .run()
+    db.batch(vec![
+        d1_query!(
+            &db,
+            "UPDATE ciphers SET archived_at = ?1, updated_at = ?1 WHERE id = ?2",
+            now,
+            id
+        )
+        .map_err(|_| AppError::Database)?,
+        cipher_change_statement(&db, &claims, existing.organization_id.as_deref(), &now)?,
+    ])
     .await?;
 
     let updated = fetch_cipher_for_user(&db, &id, &claims.sub).await?;
     let mut cipher: Cipher = updated.into();
-    attachments::hydrate_cipher_attachments(&db, env.as_ref(), &mut cipher).await?;
-
-    db::touch_user_updated_at(&db, &claims.sub, &cipher.updated_at).await?;
+    hydrate_cipher(&db, env.as_ref(), &mut cipher, &claims.sub).await?;
 
-    notifications::publish_cipher_update(
-        (*env).clone(),
-        claims.sub,
+    publish_cipher_change(
+        &db,
+        env.as_ref(),
+        &claims,
+        cipher.organization_id.as_deref(),
         UpdateType::SyncCipherUpdate,
-        cipher.id.clone(),
-        cipher.updated_at.clone(),
-        Some(claims.device),
-    );
+        &cipher.id,
+        &cipher.updated_at,
+    )
+    .await?;
 
     Ok(Json(cipher))
 }
@@ -661,38 +1320,39 @@ pub async fn unarchive_cipher(
     Path(id): Path<String>,
 ) -> Result<Json<Cipher>, AppError> {
     let db = db::get_db(&env)?;
-    fetch_cipher_for_user(&db, &id, &claims.sub).await.map_err(|_| {
+    let existing = fetch_cipher_for_write(&db, &id, &claims.sub).await.map_err(|_| {
         AppError::BadRequest(
             "Cipher was not unarchived. Ensure the provided ID is correct and you have permission to unarchive it.".to_string(),
         )
     })?;
     let now = db::now_string();
 
-    d1_query!(
-        &db,
-        "UPDATE ciphers SET archived_at = NULL, updated_at = ?1 WHERE id = ?2 AND user_id = ?3",
-        now,
-        id,
-        claims.sub
-    )
-    .map_err(|_| AppError::Database)?
-    .run()
+    db.batch(vec![
+        d1_query!(
+            &db,
+            "UPDATE ciphers SET archived_at = NULL, updated_at = ?1 WHERE id = ?2",
+            now,
+            id
+        )
+        .map_err(|_| AppError::Database)?,
+        cipher_change_statement(&db, &claims, existing.organization_id.as_deref(), &now)?,
+    ])
     .await?;
 
     let updated = fetch_cipher_for_user(&db, &id, &claims.sub).await?;
     let mut cipher: Cipher = updated.into();
-    attachments::hydrate_cipher_attachments(&db, env.as_ref(), &mut cipher).await?;
-
-    db::touch_user_updated_at(&db, &claims.sub, &cipher.updated_at).await?;
+    hydrate_cipher(&db, env.as_ref(), &mut cipher, &claims.sub).await?;
 
-    notifications::publish_cipher_update(
-        (*env).clone(),
-        claims.sub,
+    publish_cipher_change(
+        &db,
+        env.as_ref(),
+        &claims,
+        cipher.organization_id.as_deref(),
         UpdateType::SyncCipherUpdate,
-        cipher.id.clone(),
-        cipher.updated_at.clone(),
-        Some(claims.device),
-    );
+        &cipher.id,
+        &cipher.updated_at,
+    )
+    .await?;
 
     Ok(Json(cipher))
 }
@@ -707,33 +1367,36 @@ pub async fn archive_ciphers_bulk(
 ) -> Result<RawJson, AppError> {
     let db = db::get_db(&env)?;
     let now = db::now_string();
+    let organization_ids = organizations_for_cipher_ids_json(&db, &body, &claims.sub).await?;
 
-    d1_query!(
+    let mut statements = vec![d1_query!(
         &db,
-        "UPDATE ciphers SET archived_at = ?1, updated_at = ?1 WHERE user_id = ?2 AND id IN (SELECT value FROM json_each(?3, '$.ids'))",
+        &format!(
+            "UPDATE ciphers SET archived_at = ?1, updated_at = ?1 WHERE {} AND id IN (SELECT value FROM json_each(?3, '$.ids'))",
+            cipher_write_filter("ciphers", 2)
+        ),
         now,
         claims.sub,
         body
     )
-    .map_err(|_| AppError::Database)?
-    .run()
-    .await
-    .map_err(db::map_d1_json_error)?;
-
-    db::touch_user_updated_at(&db, &claims.sub, &now).await?;
+    .map_err(|_| AppError::Database)?];
+    statements.extend(ciphers_change_statements(
+        &db,
+        &claims,
+        &organization_ids,
+        &now,
+    )?);
+    db.batch(statements).await.map_err(db::map_d1_json_error)?;
 
-    notifications::publish_user_update(
-        (*env).clone(),
-        claims.sub.clone(),
-        UpdateType::SyncCiphers,
-        now,
-        Some(claims.device),
-    );
+    publish_ciphers_change(&db, env.as_ref(), &claims, &organization_ids, &now).await?;
 
     build_cipher_list_response(
         &db,
         env.as_ref(),
-        "WHERE c.user_id = ?1 AND c.id IN (SELECT value FROM json_each(?2, '$.ids'))",
+        &format!(
+            "WHERE {} AND c.id IN (SELECT value FROM json_each(?2, '$.ids'))",
+            cipher_access_filter("c", 1)
+        ),
         &[claims.sub.into(), body.into()],
         "",
     )
@@ -750,33 +1413,36 @@ pub async fn unarchive_ciphers_bulk(
 ) -> Result<RawJson, AppError> {
     let db = db::get_db(&env)?;
     let now = db::now_string();
+    let organization_ids = organizations_for_cipher_ids_json(&db, &body, &claims.sub).await?;
 
-    d1_query!(
+    let mut statements = vec![d1_query!(
         &db,
-        "UPDATE ciphers SET archived_at = NULL, updated_at = ?1 WHERE user_id = ?2 AND id IN (SELECT value FROM json_each(?3, '$.ids'))",
+        &format!(
+            "UPDATE ciphers SET archived_at = NULL, updated_at = ?1 WHERE {} AND id IN (SELECT value FROM json_each(?3, '$.ids'))",
+            cipher_write_filter("ciphers", 2)
+        ),
         now,
         claims.sub,
         body
     )
-    .map_err(|_| AppError::Database)?
-    .run()
-    .await
-    .map_err(db::map_d1_json_error)?;
-
-    db::touch_user_updated_at(&db, &claims.sub, &now).await?;
+    .map_err(|_| AppError::Database)?];
+    statements.extend(ciphers_change_statements(
+        &db,
+        &claims,
+        &organization_ids,
+        &now,
+    )?);
+    db.batch(statements).await.map_err(db::map_d1_json_error)?;
 
-    notifications::publish_user_update(
-        (*env).clone(),
-        claims.sub.clone(),
-        UpdateType::SyncCiphers,
-        now,
-        Some(claims.device),
-    );
+    publish_ciphers_change(&db, env.as_ref(), &claims, &organization_ids, &now).await?;
 
     build_cipher_list_response(
         &db,
         env.as_ref(),
-        "WHERE c.user_id = ?1 AND c.id IN (SELECT value FROM json_each(?2, '$.ids'))",
+        &format!(
+            "WHERE {} AND c.id IN (SELECT value FROM json_each(?2, '$.ids'))",
+            cipher_access_filter("c", 1)
+        ),
         &[claims.sub.into(), body.into()],
         "",
     )
@@ -790,6 +1456,7 @@ pub async fn unarchive_ciphers_bulk(
 pub async fn create_cipher_simple(
     claims: Claims,
     State(env): State<Arc<Env>>,
+    headers: HeaderMap,
     Json(payload): Json<CipherRequestData>,
 ) -> Result<Json<Cipher>, AppError> {
     let db = db::get_db(&env)?;
@@ -798,14 +1465,21 @@ pub async fn create_cipher_simple(
 
     let data_value = serde_json::to_value(&cipher_data).map_err(|_| AppError::Internal)?;
 
+    let organization_id = payload.organization_id.clone();
+    ensure_can_create_in(&db, &claims.sub, organization_id.as_deref(), &[]).await?;
+    ensure_own_folder(&db, &claims.sub, payload.folder_id.as_deref()).await?;
+    let personal = organization_id.is_none();
+    let favorite = payload.favorite.unwrap_or(false);
+    let folder_id = payload.folder_id;
+
     let mut cipher = Cipher {
         id: Uuid::new_v4().to_string(),
-        user_id: Some(claims.sub.clone()),
-        organization_id: payload.organization_id.clone(),
+        user_id: personal.then(|| claims.sub.clone()),
+        organization_id,
         r#type: payload.r#type,
         data: data_value,
-        favorite: payload.favorite.unwrap_or(false),
-        folder_id: payload.folder_id.clone(),
+        favorite: personal && favorite,
+        folder_id: folder_id.clone().filter(|_| personal),
         deleted_at: None,
         archived_at: None,
         created_at: now.clone(),
@@ -820,7 +1494,7 @@ pub async fn create_cipher_simple(
 
     let data = serde_json::to_string(&cipher.data).map_err(|_| AppError::Internal)?;
 
-    d1_query!(
+    let mut statements = vec![d1_query!(
         &db,
         "INSERT INTO ciphers (id, user_id, organization_id, type, data, favorite, folder_id, created_at, updated_at)
          VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
@@ -833,21 +1507,46 @@ pub async fn create_cipher_simple(
          cipher.folder_id,
          cipher.created_at,
          cipher.updated_at,
-    ).map_err(|_| AppError::Database)?
-    .run()
-    .await?;
+    ).map_err(|_| AppError::Database)?];
 
-    attachments::hydrate_cipher_attachments(&db, env.as_ref(), &mut cipher).await?;
-    db::touch_user_updated_at(&db, &claims.sub, &cipher.updated_at).await?;
-
-    notifications::publish_cipher_update(
-        (*env).clone(),
-        claims.sub,
+    if !personal && (favorite || folder_id.is_some()) {
+        statements.push(UserCipherState::save_statement(
+            &db,
+            &claims.sub,
+            &cipher.id,
+            favorite,
+            folder_id.as_deref(),
+            None,
+        )?);
+    }
+    statements.push(cipher_change_statement(
+        &db,
+        &claims,
+        cipher.organization_id.as_deref(),
+        &now,
+    )?);
+    db.batch(statements).await?;
+
+    hydrate_cipher(&db, env.as_ref(), &mut cipher, &claims.sub).await?;
+    record_cipher_event(
+        &db,
+        EventType::CipherCreated,
+        cipher.organization_id.as_deref(),
+        &cipher.id,
+        &claims,
+        &headers,
+    )
+    .await;
+    publish_cipher_change(
+        &db,
+        env.as_ref(),
+        &claims,
+        cipher.organization_id.as_deref(),
         UpdateType::SyncCipherCreate,
-        cipher.id.clone(),
-        cipher.updated_at.clone(),
-        Some(claims.device),
-    );
+        &cipher.id,
+        &cipher.updated_at,
+    )
+    .await?;
 
     Ok(Json(cipher))
 }
@@ -887,19 +1586,31 @@ pub async fn move_cipher_selected(
         ));
     }
 
-    // Update folder_id for all ciphers that belong to the user and are in the ids list
+    // Update folder_id for all ciphers that belong to the user and are in the ids list,
+    // and the user's folder of the org ciphers among them.
     // Uses json_extract for folderId and json_each for ids array
-    db.prepare(
-        "UPDATE ciphers SET folder_id = NULLIF(json_extract(?1, '$.folderId'), ''), updated_at = ?2 
-         WHERE user_id = ?3 AND id IN (SELECT value FROM json_each(?1, '$.ids'))",
-    )
-    .bind(&[body.into(), now.clone().into(), user_id.clone().into()])?
-    .run()
+    let params: Vec<JsValue> = vec![body.into(), now.clone().into(), user_id.clone().into()];
+    db.batch(vec![
+        db.prepare(
+            "UPDATE ciphers SET folder_id = NULLIF(json_extract(?1, '$.folderId'), ''), updated_at = ?2 
+             WHERE user_id = ?3 AND id IN (SELECT value FROM json_each(?1, '$.ids'))",
+        )
+        .bind(&params)?,
+        db.prepare(format!(
+            "INSERT INTO users_ciphers (user_id, cipher_id, folder_id)
+             SELECT ?3, c.id, NULLIF(json_extract(?1, '$.folderId'), '') FROM ciphers c
+             WHERE c.organization_id IS NOT NULL AND {}
+             AND c.id IN (SELECT value FROM json_each(?1, '$.ids'))
+             ON CONFLICT (user_id, cipher_id) DO UPDATE SET folder_id = excluded.folder_id",
+            cipher_access_filter("c", 3)
+        ))
+        .bind(&params)?,
+        // Update user's revision date
+        db::touch_user_statement(&db, user_id, &now)?,
+    ])
     .await
     .map_err(db::map_d1_json_error)?;
 
-    // Update user's revision date
-    db::touch_user_updated_at(&db, user_id, &now).await?;
     notifications::publish_user_update(
         (*env).clone(),
         claims.sub,
@@ -911,6 +1622,255 @@ pub async fn move_cipher_selected(
     Ok(Json(()))
 }
 
+/// Move personal ciphers of the user into their organization with the data the client
+/// re-encrypted under the organization key. All rows are written in one batch, so a
+/// failure leaves every cipher personal. Returns the organization id and cipher ids.
+async fn share_ciphers(
+    env: &Env,
+    db: &db::Db,
+    claims: &Claims,
+    ciphers: Vec<CipherRequestData>,
+    collection_ids: &[String],
+    now: &str,
+) -> Result<(String, Vec<String>), AppError> {
+    let org_id = ciphers
+        .first()
+        .and_then(|c| c.organization_id.clone())
+        .filter(|org_id| {
+            ciphers
+                .iter()
+                .all(|c| c.organization_id.as_deref() == Some(org_id.as_str()))
+        })
+        .ok_or_else(|| {
+            AppError::BadRequest("Ciphers must be shared with a single organization".to_string())
+        })?;
+    ensure_can_create_in(db, &claims.sub, Some(&org_id), collection_ids).await?;
+
+    let ids: Vec<String> = ciphers
+        .iter()
+        .map(|c| c.id.clone())
+        .collect::<Option<_>>()
+        .ok_or_else(|| AppError::BadRequest("Every shared cipher needs an id".to_string()))?;
+    let mut unique_ids = ids.clone();
+    unique_ids.sort();
+    unique_ids.dedup();
+    let ids_json = serde_json::to_string(&ids).map_err(|_| AppError::Internal)?;
+    #[derive(Deserialize)]
+    struct Revision {
+        id: String,
+        updated_at: String,
+    }
+    let personal: Vec<Revision> = d1_query!(
+        db,
+        "SELECT id, updated_at FROM ciphers
+         WHERE user_id = ?1 AND organization_id IS NULL AND id IN (SELECT value FROM json_each(?2))",
+        claims.sub,
+        ids_json
+    )
+    .map_err(|_| AppError::Database)?
+    .all()
+    .await
+    .map_err(|_| AppError::Database)?
+    .results()
+    .map_err(|_| AppError::Database)?;
+    if unique_ids.len() != ids.len() || personal.len() != ids.len() {
+        return Err(AppError::BadRequest(
+            "Only personal ciphers you own can be shared".to_string(),
+        ));
+    }
+    for stored in &personal {
+        let last_known = ciphers
+            .iter()
+            .find(|c| c.id.as_deref() == Some(stored.id.as_str()))
+            .and_then(|c| c.last_known_revision_date.as_deref());
+        ensure_not_stale(&stored.id, &stored.updated_at, last_known)?;
+    }
+
+    // Attachments move with the ciphers and count against the organization's quota.
+    let attachment_bytes: Option<i64> = d1_query!(
+        db,
+        "SELECT COALESCE(SUM(file_size), 0) AS total FROM attachments
+         WHERE cipher_id IN (SELECT value FROM json_each(?1))",
+        ids_json
+    )
+    .map_err(|_| AppError::Database)?
+    .first(Some("total"))
+    .await
+    .map_err(|_| AppError::Database)?;
+    let attachment_bytes = attachment_bytes.unwrap_or(0);
+    if attachment_bytes > 0 {
+        ensure_storage_available(
+            env,
+            db,
+            StorageOwner::Organization(&org_id),
+            attachment_bytes,
+        )
+        .await?;
+    }
+
+    let mut statements = Vec::new();
+    for (id, cipher) in ids.iter().zip(ciphers) {
+        let cipher_data = CipherData::new(cipher.name, cipher.notes, cipher.type_fields);
+        let data = serde_json::to_string(&cipher_data).map_err(|_| AppError::Internal)?;
+        // Org ciphers have no owning user; the sharer's folder/favorite move to
+        // `users_ciphers`.
+        statements.push(
+            d1_query!(
+                db,
+                "INSERT INTO users_ciphers (user_id, cipher_id, favorite, folder_id)
+                 SELECT user_id, id, favorite, folder_id FROM ciphers
+                 WHERE id = ?1 AND user_id = ?2 AND organization_id IS NULL
+                   AND (favorite != 0 OR folder_id IS NOT NULL)
+                 ON CONFLICT (user_id, cipher_id) DO UPDATE
+                 SET favorite = excluded.favorite, folder_id = excluded.folder_id",
+                id,
+                claims.sub
+            )
+            .map_err(|_| AppError::Database)?,
+        );
+        statements.push(
+            d1_query!(
+                db,
+                "UPDATE ciphers SET user_id = NULL, organization_id = ?1, type = ?2, data = ?3,
+                 favorite = 0, folder_id = NULL, updated_at = ?4
+                 WHERE id = ?5 AND user_id = ?6 AND organization_id IS NULL",
+                org_id,
+                cipher.r#type,
+                data,
+                now,
+                id,
+                claims.sub
+            )
+            .map_err(|_| AppError::Database)?,
+        );
+        for (attachment_id, attachment) in cipher.attachments2.iter().flatten() {
+            statements.push(
+                d1_query!(
+                    db,
+                    "UPDATE attachments SET file_name = ?1, akey = ?2, updated_at = ?3 WHERE id = ?4 AND cipher_id = ?5",
+                    attachment.file_name,
+                    attachment.key,
+                    now,
+                    attachment_id,
+                    id
+                )
+                .map_err(|_| AppError::Database)?,
+            );
+        }
+        statements.extend(Collection::set_for_cipher_statements(
+            db,
+            id,
+            collection_ids,
+        )?);
+    }
+    // The owner sees the ciphers change in place; other members see new ones.
+    statements.extend(ciphers_change_statements(
+        db,
+        claims,
+        std::slice::from_ref(&org_id),
+        now,
+    )?);
+    db.batch(statements).await?;
+
+    Ok((org_id, ids))
+}
+
+/// Share a personal cipher with an organization (PUT/POST /api/ciphers/{id}/share)
+#[worker::send]
+pub async fn share_cipher(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Json(payload): Json<ShareCipherRequest>,
+) -> Result<Json<Cipher>, AppError> {
+    let db = db::get_db(&env)?;
+    let now = db::now_string();
+
+    let mut request = payload.cipher;
+    request.id = Some(id.clone());
+    let (org_id, _) = share_ciphers(
+        env.as_ref(),
+        &db,
+        &claims,
+        vec![request],
+        &payload.collection_ids,
+        &now,
+    )
+    .await?;
+
+    let mut cipher: Cipher = fetch_cipher_for_user(&db, &id, &claims.sub).await?.into();
+    hydrate_cipher(&db, env.as_ref(), &mut cipher, &claims.sub).await?;
+    record_cipher_event(
+        &db,
+        EventType::CipherShared,
+        Some(&org_id),
+        &id,
+        &claims,
+        &headers,
+    )
+    .await;
+    publish_ciphers_change(&db, env.as_ref(), &claims, &[org_id], &now).await?;
+
+    Ok(Json(cipher))
+}
+
+/// Share several personal ciphers with an organization (PUT/POST /api/ciphers/share)
+/// Expected JSON: {"ciphers": [{"id": "...", "organizationId": "...", ...}], "collectionIds": [...]}
+#[worker::send]
+pub async fn share_ciphers_bulk(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    headers: HeaderMap,
+    Json(payload): Json<ShareCiphersBulkRequest>,
+) -> Result<RawJson, AppError> {
+    let db = db::get_db(&env)?;
+    let now = db::now_string();
+
+    let (org_id, ids) = share_ciphers(
+        env.as_ref(),
+        &db,
+        &claims,
+        payload.ciphers,
+        &payload.collection_ids,
+        &now,
+    )
+    .await?;
+
+    for id in &ids {
+        record_cipher_event(
+            &db,
+            EventType::CipherShared,
+            Some(&org_id),
+            id,
+            &claims,
+            &headers,
+        )
+        .await;
+    }
+    publish_ciphers_change(
+        &db,
+        env.as_ref(),
+        &claims,
+        std::slice::from_ref(&org_id),
+        &now,
+    )
+    .await?;
+
+    let body = serde_json::json!({ "ids": ids }).to_string();
+    build_cipher_list_response(
+        &db,
+        env.as_ref(),
+        &format!(
+            "WHERE {} AND c.id IN (SELECT value FROM json_each(?2, '$.ids'))",
+            cipher_access_filter("c", 1)
+        ),
+        &[claims.sub.into(), body.into()],
+        "",
+    )
+    .await
+}
+
 /// Purge the user's vault - delete all ciphers and folders
 /// POST /api/ciphers/purge
 ///
@@ -935,38 +1895,25 @@ pub async fn purge_vault(
         .map_err(|_| AppError::Database)?
         .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
     let user: User = serde_json::from_value(user).map_err(|_| AppError::Internal)?;
-
-    // Validate password (OTP not supported in this simplified version)
-    let provided_hash = payload
-        .master_password_hash
-        .ok_or_else(|| AppError::BadRequest("Missing master password hash".to_string()))?;
-
-    let verification = user.verify_master_password(&provided_hash).await?;
-
-    if !verification.is_valid() {
-        return Err(AppError::Unauthorized("Invalid password".to_string()));
-    }
+    verify_user(&db, &user, &payload).await?;
 
     if attachments::attachments_enabled(env.as_ref()) {
         let keys = attachments::list_attachment_keys_for_user(&db, user_id).await?;
         attachments::delete_storage_objects(env.as_ref(), &keys).await?;
     }
 
-    // Delete all user's ciphers (both active and soft-deleted)
-    d1_query!(&db, "DELETE FROM ciphers WHERE user_id = ?1", user_id)
-        .map_err(|_| AppError::Database)?
-        .run()
-        .await?;
-
-    // Delete all user's folders
-    d1_query!(&db, "DELETE FROM folders WHERE user_id = ?1", user_id)
-        .map_err(|_| AppError::Database)?
-        .run()
-        .await?;
-
-    // Update user's revision date to trigger client sync
     let now = db::now_string();
-    db::touch_user_updated_at(&db, user_id, &now).await?;
+    db.batch(vec![
+        // Delete all user's ciphers (both active and soft-deleted)
+        d1_query!(&db, "DELETE FROM ciphers WHERE user_id = ?1", user_id)
+            .map_err(|_| AppError::Database)?,
+        // Delete all user's folders
+        d1_query!(&db, "DELETE FROM folders WHERE user_id = ?1", user_id)
+            .map_err(|_| AppError::Database)?,
+        // Update user's revision date to trigger client sync
+        db::touch_user_statement(&db, user_id, &now)?,
+    ])
+    .await?;
 
     notifications::publish_user_update(
         (*env).clone(),
@@ -985,6 +1932,8 @@ struct CipherJsonArrayRow {
 }
 
 /// Build the SQL expression for a single cipher as JSON.
+/// Expects the viewing user's id bound at `?1` for the per-user fields, with
+/// [`USER_STATE_JOIN`] in the `FROM` clause.
 fn cipher_json_expr(attachments_enabled: bool) -> String {
     let attachments_expr = if attachments_enabled {
         "
@@ -1021,14 +1970,16 @@ fn cipher_json_expr(attachments_enabled: bool) -> String {
             'id', c.id,
             'userId', c.user_id,
             'organizationId', c.organization_id,
-            'folderId', c.folder_id,
+            'folderId', CASE WHEN c.organization_id IS NULL THEN c.folder_id ELSE ucs.folder_id END,
             'type', c.type,
-            'favorite', CASE WHEN c.favorite THEN json('true') ELSE json('false') END,
-            'edit', json('true'),
-            'viewPassword', json('true'),
-            'permissions', json_object('delete', json('true'), 'restore', json('true')),
-            'organizationUseTotp', json('false'),
-            'collectionIds', json('[]'),
+            'favorite', CASE WHEN c.organization_id IS NULL THEN
+                CASE WHEN c.favorite THEN json('true') ELSE json('false') END
+                ELSE CASE WHEN ucs.favorite THEN json('true') ELSE json('false') END END,
+            'edit', {edit},
+            'viewPassword', {view_password},
+            'permissions', json_object('delete', {edit}, 'restore', {edit}),
+            'organizationUseTotp', CASE WHEN c.organization_id IS NULL THEN json('false') ELSE json('true') END,
+            'collectionIds', json((SELECT json_group_array(cc.collection_id) FROM ciphers_collections cc WHERE cc.cipher_id = c.id)),
             'revisionDate', c.updated_at,
             'creationDate', c.created_at,
             'deletedDate', c.deleted_at,
@@ -1047,9 +1998,15 @@ fn cipher_json_expr(attachments_enabled: bool) -> String {
             'key', json_extract(c.data, '$.key')
         )",
         attachments_expr = attachments_expr,
+        edit = bool_json(&org_permission_condition("read_only")),
+        view_password = bool_json(&org_permission_condition("hide_passwords")),
     )
 }
 
+fn bool_json(condition: &str) -> String {
+    format!("CASE WHEN {condition} THEN json('true') ELSE json('false') END")
+}
+
 /// Build SQL that returns ciphers as a JSON array string (using json_group_array).
 fn cipher_json_array_sql(
     attachments_enabled: bool,
@@ -1062,7 +2019,7 @@ fn cipher_json_array_sql(
         "SELECT COALESCE(json_group_array(json(sub.cipher_json)), '[]') AS ciphers_json
         FROM (
             SELECT {cipher_expr} AS cipher_json
-            FROM ciphers c
+            FROM ciphers c {USER_STATE_JOIN}
             {where_clause}
             {order_clause}
         ) sub",
@@ -1080,7 +2037,7 @@ fn cipher_json_rows_sql(
     let cipher_expr = cipher_json_expr(attachments_enabled);
     format!(
         "SELECT {cipher_expr} AS cipher_json
-        FROM ciphers c
+        FROM ciphers c {USER_STATE_JOIN}
         {where_clause}
         {order_clause}",
         cipher_expr = cipher_expr,
@@ -1123,6 +2080,7 @@ async fn build_cipher_list_response(
 
 /// Append ciphers JSON array to an existing buffer.
 /// This avoids JSON parsing in Rust, significantly reducing CPU time.
+/// `params[0]` (`?1`) must be the requesting user's id; see [`cipher_json_expr`].
 pub(crate) async fn append_cipher_json_array_raw(
     out: &mut String,
     db: &crate::db::Db,
@@ -0,0 +1,503 @@
+//! Organization collections and member collection assignments.
+//!
+//! Owners and admins can access every collection of their organization. Other
+//! members only see the collections they are assigned to, directly or through a
+//! group, with per-assignment read-only / hide-passwords flags that are enforced by
+//! the cipher handlers.
+
+use axum::extract::{Path, State};
+use axum::http::HeaderMap;
+use axum::Json;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use worker::Env;
+
+use crate::auth::Claims;
+use crate::db;
+use crate::error::AppError;
+use crate::handlers::groups::{ensure_org_groups, ensure_org_members};
+use crate::handlers::organizations::{require_member_access, require_member_role};
+use crate::models::collection::{
+    BulkCollectionAccessRequest, Collection, CollectionAccess, CollectionAccessData,
+    CollectionRequest,
+};
+use crate::models::event::{Event, EventActor, EventType};
+use crate::models::group::{Group, GroupAccess};
+use crate::models::organization::{Membership, MembershipType, Permission};
+
+fn list_json(data: Vec<Value>) -> Value {
+    json!({
+        "data": data,
+        "object": "list",
+        "continuationToken": null,
+    })
+}
+
+async fn fetch_collection(
+    db: &db::Db,
+    org_id: &str,
+    collection_id: &str,
+) -> Result<Collection, AppError> {
+    Collection::find_by_id_and_org(db, collection_id, org_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Collection not found".to_string()))
+}
+
+/// Validate the `users` of a collection request and convert them to rows.
+async fn collection_user_access(
+    db: &db::Db,
+    org_id: &str,
+    collection_id: &str,
+    users: &[CollectionAccessData],
+) -> Result<Vec<CollectionAccess>, AppError> {
+    let ids: Vec<String> = users.iter().map(|u| u.id.clone()).collect();
+    ensure_org_members(db, org_id, &ids).await?;
+    Ok(users
+        .iter()
+        .map(|user| user.clone().into_access(&user.id, collection_id))
+        .collect())
+}
+
+/// Validate the `groups` of a collection request and convert them to rows.
+async fn collection_group_access(
+    db: &db::Db,
+    org_id: &str,
+    collection_id: &str,
+    groups: &[CollectionAccessData],
+) -> Result<Vec<GroupAccess>, AppError> {
+    let ids: Vec<String> = groups.iter().map(|g| g.id.clone()).collect();
+    ensure_org_groups(db, org_id, &ids).await?;
+    Ok(groups
+        .iter()
+        .map(|group| GroupAccess::from_data(group.clone(), &group.id, collection_id))
+        .collect())
+}
+
+fn collection_event(
+    event_type: EventType,
+    collection: &Collection,
+    claims: &Claims,
+    headers: &HeaderMap,
+) -> Event {
+    let actor = EventActor::from_request(claims, headers);
+    let mut event = Event::new(event_type, &collection.organization_id, &actor);
+    event.collection_id = Some(collection.id.clone());
+    event
+}
+
+async fn collection_details_json(
+    db: &db::Db,
+    collection: &Collection,
+    viewer: &Membership,
+) -> Result<Value, AppError> {
+    let users = CollectionAccess::list_by_collection(db, &collection.id).await?;
+    let groups = GroupAccess::list_by_collection(db, &collection.id).await?;
+    let viewer_groups = Group::ids_for_membership(db, &viewer.id).await?;
+    let assigned = users.iter().any(|u| u.membership_id == viewer.id)
+        || groups.iter().any(|g| viewer_groups.contains(&g.group_id));
+    Ok(collection.to_access_details_json(&users, &groups, assigned))
+}
+
+/// GET /api/collections - collections the current user can access
+#[worker::send]
+pub async fn list_user_collections(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let collections = Collection::list_for_user(&db, &claims.sub).await?;
+    Ok(Json(list_json(
+        collections.iter().map(|c| c.to_details_json()).collect(),
+    )))
+}
+
+/// GET /api/organizations/{org_id}/collections
+///
+/// Managers and above see every collection; other members see their assigned ones.
+#[worker::send]
+pub async fn list_collections(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(org_id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let membership = require_member_role(&db, &org_id, &claims.sub, MembershipType::User).await?;
+
+    let data = if membership
+        .membership_type()
+        .is_at_least(MembershipType::Manager)
+        || membership.has_permission(Permission::EditAnyCollection)
+        || membership.has_permission(Permission::DeleteAnyCollection)
+    {
+        Collection::list_by_org(&db, &org_id)
+            .await?
+            .iter()
+            .map(Collection::to_json)
+            .collect()
+    } else {
+        Collection::list_for_user(&db, &claims.sub)
+            .await?
+            .into_iter()
+            .filter(|c| c.organization_id == org_id)
+            .map(|c| {
+                json!({
+                    "id": c.id,
+                    "organizationId": c.organization_id,
+                    "name": c.name,
+                    "externalId": c.external_id,
+                    "object": "collection"
+                })
+            })
+            .collect()
+    };
+
+    Ok(Json(list_json(data)))
+}
+
+/// GET /api/organizations/{org_id}/collections/details
+#[worker::send]
+pub async fn list_collection_details(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(org_id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let membership = require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Manager,
+        &[
+            Permission::CreateNewCollections,
+            Permission::EditAnyCollection,
+            Permission::DeleteAnyCollection,
+        ],
+    )
+    .await?;
+
+    let collections = Collection::list_by_org(&db, &org_id).await?;
+    let mut data = Vec::with_capacity(collections.len());
+    for collection in &collections {
+        data.push(collection_details_json(&db, collection, &membership).await?);
+    }
+
+    Ok(Json(list_json(data)))
+}
+
+/// GET /api/organizations/{org_id}/collections/{collection_id}
+#[worker::send]
+pub async fn get_collection(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path((org_id, collection_id)): Path<(String, String)>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Manager,
+        &[
+            Permission::CreateNewCollections,
+            Permission::EditAnyCollection,
+            Permission::DeleteAnyCollection,
+        ],
+    )
+    .await?;
+    let collection = fetch_collection(&db, &org_id, &collection_id).await?;
+    Ok(Json(collection.to_json()))
+}
+
+/// GET /api/organizations/{org_id}/collections/{collection_id}/details
+#[worker::send]
+pub async fn get_collection_details(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path((org_id, collection_id)): Path<(String, String)>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let membership = require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Manager,
+        &[
+            Permission::CreateNewCollections,
+            Permission::EditAnyCollection,
+            Permission::DeleteAnyCollection,
+        ],
+    )
+    .await?;
+    let collection = fetch_collection(&db, &org_id, &collection_id).await?;
+    Ok(Json(
+        collection_details_json(&db, &collection, &membership).await?,
+    ))
+}
+
+/// POST /api/organizations/{org_id}/collections
+#[worker::send]
+pub async fn create_collection(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    headers: HeaderMap,
+    Path(org_id): Path<String>,
+    Json(payload): Json<CollectionRequest>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let membership = require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Admin,
+        &[Permission::CreateNewCollections],
+    )
+    .await?;
+
+    let collection = Collection::new(
+        org_id.clone(),
+        payload.name.clone(),
+        payload.external_id.clone(),
+    );
+    let access = collection_user_access(&db, &org_id, &collection.id, &payload.users).await?;
+    let group_access =
+        collection_group_access(&db, &org_id, &collection.id, &payload.groups).await?;
+
+    collection.insert(&db).await?;
+    CollectionAccess::replace_for_collection(&db, &collection.id, &access).await?;
+    GroupAccess::replace_for_collection(&db, &collection.id, &group_access).await?;
+    Membership::touch_confirmed_users(&db, &org_id, &collection.updated_at).await?;
+    collection_event(EventType::CollectionCreated, &collection, &claims, &headers)
+        .record(&db)
+        .await;
+
+    Ok(Json(
+        collection_details_json(&db, &collection, &membership).await?,
+    ))
+}
+
+/// PUT/POST /api/organizations/{org_id}/collections/{collection_id}
+#[worker::send]
+pub async fn update_collection(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    headers: HeaderMap,
+    Path((org_id, collection_id)): Path<(String, String)>,
+    Json(payload): Json<CollectionRequest>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let membership = require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Admin,
+        &[Permission::EditAnyCollection],
+    )
+    .await?;
+    let mut collection = fetch_collection(&db, &org_id, &collection_id).await?;
+    let access = collection_user_access(&db, &org_id, &collection.id, &payload.users).await?;
+    let group_access =
+        collection_group_access(&db, &org_id, &collection.id, &payload.groups).await?;
+
+    collection.name = payload.name;
+    collection.external_id = payload.external_id;
+    collection.update(&db).await?;
+    CollectionAccess::replace_for_collection(&db, &collection.id, &access).await?;
+    GroupAccess::replace_for_collection(&db, &collection.id, &group_access).await?;
+    Membership::touch_confirmed_users(&db, &org_id, &collection.updated_at).await?;
+    collection_event(EventType::CollectionUpdated, &collection, &claims, &headers)
+        .record(&db)
+        .await;
+
+    Ok(Json(
+        collection_details_json(&db, &collection, &membership).await?,
+    ))
+}
+
+/// DELETE /api/organizations/{org_id}/collections/{collection_id} (also POST .../delete)
+///
+/// Ciphers in the collection stay in the organization; only the assignment is removed.
+#[worker::send]
+pub async fn delete_collection(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    headers: HeaderMap,
+    Path((org_id, collection_id)): Path<(String, String)>,
+) -> Result<Json<()>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Admin,
+        &[Permission::DeleteAnyCollection],
+    )
+    .await?;
+    let collection = fetch_collection(&db, &org_id, &collection_id).await?;
+
+    collection.delete(&db).await?;
+    Membership::touch_confirmed_users(&db, &org_id, &db::now_string()).await?;
+    collection_event(EventType::CollectionDeleted, &collection, &claims, &headers)
+        .record(&db)
+        .await;
+
+    Ok(Json(()))
+}
+
+/// GET /api/organizations/{org_id}/collections/{collection_id}/users
+#[worker::send]
+pub async fn get_collection_users(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path((org_id, collection_id)): Path<(String, String)>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Manager,
+        &[Permission::EditAnyCollection],
+    )
+    .await?;
+    let collection = fetch_collection(&db, &org_id, &collection_id).await?;
+    let users = CollectionAccess::list_by_collection(&db, &collection.id).await?;
+    Ok(Json(Value::Array(
+        users.iter().map(CollectionAccess::to_json).collect(),
+    )))
+}
+
+/// PUT /api/organizations/{org_id}/collections/{collection_id}/users
+///
+/// Replaces the collection's member assignments; group assignments are kept.
+#[worker::send]
+pub async fn put_collection_users(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    headers: HeaderMap,
+    Path((org_id, collection_id)): Path<(String, String)>,
+    Json(users): Json<Vec<CollectionAccessData>>,
+) -> Result<Json<()>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Admin,
+        &[Permission::EditAnyCollection],
+    )
+    .await?;
+    let collection = fetch_collection(&db, &org_id, &collection_id).await?;
+    let access = collection_user_access(&db, &org_id, &collection.id, &users).await?;
+
+    CollectionAccess::replace_for_collection(&db, &collection.id, &access).await?;
+    Membership::touch_confirmed_users(&db, &org_id, &db::now_string()).await?;
+    collection_event(EventType::CollectionUpdated, &collection, &claims, &headers)
+        .record(&db)
+        .await;
+
+    Ok(Json(()))
+}
+
+/// DELETE /api/organizations/{org_id}/collections/{collection_id}/user/{member_id}
+/// (also POST .../delete-user/{member_id})
+#[worker::send]
+pub async fn delete_collection_user(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    headers: HeaderMap,
+    Path((org_id, collection_id, member_id)): Path<(String, String, String)>,
+) -> Result<Json<()>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Admin,
+        &[Permission::EditAnyCollection],
+    )
+    .await?;
+    let collection = fetch_collection(&db, &org_id, &collection_id).await?;
+
+    if !CollectionAccess::delete(&db, &collection.id, &member_id).await? {
+        return Err(AppError::NotFound(
+            "User is not assigned to this collection".to_string(),
+        ));
+    }
+    Membership::touch_confirmed_users(&db, &org_id, &db::now_string()).await?;
+    collection_event(EventType::CollectionUpdated, &collection, &claims, &headers)
+        .record(&db)
+        .await;
+
+    Ok(Json(()))
+}
+
+/// POST /api/organizations/{org_id}/collections/bulk-access
+///
+/// Gives every listed collection exactly the given member and group assignments,
+/// in a single batch.
+#[worker::send]
+pub async fn bulk_collection_access(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    headers: HeaderMap,
+    Path(org_id): Path<String>,
+    Json(payload): Json<BulkCollectionAccessRequest>,
+) -> Result<Json<()>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Admin,
+        &[Permission::EditAnyCollection],
+    )
+    .await?;
+    if payload.collection_ids.is_empty() {
+        return Err(AppError::BadRequest("No collections selected".to_string()));
+    }
+    if !Collection::foreign_ids(&db, &org_id, &payload.collection_ids)
+        .await?
+        .is_empty()
+    {
+        return Err(AppError::NotFound("Collection not found".to_string()));
+    }
+    let member_ids: Vec<String> = payload.users.iter().map(|u| u.id.clone()).collect();
+    ensure_org_members(&db, &org_id, &member_ids).await?;
+    let group_ids: Vec<String> = payload.groups.iter().map(|g| g.id.clone()).collect();
+    ensure_org_groups(&db, &org_id, &group_ids).await?;
+
+    let mut statements = Vec::new();
+    for collection_id in &payload.collection_ids {
+        let users: Vec<CollectionAccess> = payload
+            .users
+            .iter()
+            .map(|user| user.clone().into_access(&user.id, collection_id))
+            .collect();
+        let groups: Vec<GroupAccess> = payload
+            .groups
+            .iter()
+            .map(|group| GroupAccess::from_data(group.clone(), &group.id, collection_id))
+            .collect();
+        statements.extend(CollectionAccess::replace_for_collection_statements(
+            &db,
+            collection_id,
+            &users,
+        )?);
+        statements.extend(GroupAccess::replace_for_collection_statements(
+            &db,
+            collection_id,
+            &groups,
+        )?);
+    }
+    db.batch(statements).await?;
+    Membership::touch_confirmed_users(&db, &org_id, &db::now_string()).await?;
+
+    let actor = EventActor::from_request(&claims, &headers);
+    for collection_id in &payload.collection_ids {
+        let mut event = Event::new(EventType::CollectionUpdated, &org_id, &actor);
+        event.collection_id = Some(collection_id.clone());
+        event.record(&db).await;
+    }
+
+    Ok(Json(()))
+}
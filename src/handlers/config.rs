@@ -1,5 +1,5 @@
-use axum::{extract::State, Extension, Json};
-use serde_json::{json, Value};
+use axum::{extract::State, http::header, response::IntoResponse, Extension, Json};
+use serde_json::{json, Map, Value};
 use std::sync::Arc;
 use worker::Env;
 
@@ -14,24 +14,61 @@ fn get_disable_user_registration(env: &Env) -> bool {
         .unwrap_or(true)
 }
 
+/// Feature flags sent to every client unless `CLIENT_FEATURE_FLAGS` overrides them.
+const DEFAULT_FEATURE_STATES: &[(&str, bool)] = &[
+    ("duo-redirect", true),
+    ("pm-19051-send-email-verification", false),
+    ("pm-19148-innovation-archive", true),
+    ("cxp-import-mobile", true),
+    ("cxp-export-mobile", true),
+    ("ssh-key-vault-item", true),
+    ("ssh-agent", true),
+];
+
+/// The `featureStates` of the config: the defaults, then the flags listed in
+/// `CLIENT_FEATURE_FLAGS` (comma-separated; `name` or `name=true` turns a flag on,
+/// `name=false` off).
+///
+/// Official available feature flags can be found here:
+/// Server (v2025.6.2): https://github.com/bitwarden/server/blob/d094be3267f2030bd0dc62106bc6871cf82682f5/src/Core/Constants.cs#L103
+/// Client (web-v2025.6.1): https://github.com/bitwarden/clients/blob/747c2fd6a1c348a57a76e4a7de8128466ffd3c01/libs/common/src/enums/feature-flag.enum.ts#L12
+/// Android (v2025.6.0): https://github.com/bitwarden/android/blob/b5b022caaad33390c31b3021b2c1205925b0e1a2/app/src/main/kotlin/com/x8bit/bitwarden/data/platform/manager/model/FlagKey.kt#L22
+/// iOS (v2025.6.0): https://github.com/bitwarden/ios/blob/ff06d9c6cc8da89f78f37f376495800201d7261a/BitwardenShared/Core/Platform/Models/Enum/FeatureFlag.swift#L7
+fn feature_states(env: &Env) -> Map<String, Value> {
+    let mut states: Map<String, Value> = DEFAULT_FEATURE_STATES
+        .iter()
+        .map(|(name, enabled)| (name.to_string(), Value::Bool(*enabled)))
+        .collect();
+    let configured = env
+        .var("CLIENT_FEATURE_FLAGS")
+        .map(|v| v.to_string())
+        .unwrap_or_default();
+    for flag in configured
+        .split(',')
+        .map(str::trim)
+        .filter(|f| !f.is_empty())
+    {
+        let (name, enabled) = match flag.split_once('=') {
+            Some((name, value)) => match value.trim().to_ascii_lowercase().as_str() {
+                "true" | "1" => (name.trim(), true),
+                "false" | "0" => (name.trim(), false),
+                _ => {
+                    log::warn!("Ignoring client feature flag with an invalid value: {flag}");
+                    continue;
+                }
+            },
+            None => (flag, true),
+        };
+        states.insert(name.to_string(), Value::Bool(enabled));
+    }
+    states
+}
+
 #[worker::send]
 pub async fn config(
     State(env): State<Arc<Env>>,
     Extension(BaseUrl(domain)): Extension<BaseUrl>,
 ) -> Json<Value> {
-    // Official available feature flags can be found here:
-    // Server (v2025.6.2): https://github.com/bitwarden/server/blob/d094be3267f2030bd0dc62106bc6871cf82682f5/src/Core/Constants.cs#L103
-    // Client (web-v2025.6.1): https://github.com/bitwarden/clients/blob/747c2fd6a1c348a57a76e4a7de8128466ffd3c01/libs/common/src/enums/feature-flag.enum.ts#L12
-    // Android (v2025.6.0): https://github.com/bitwarden/android/blob/b5b022caaad33390c31b3021b2c1205925b0e1a2/app/src/main/kotlin/com/x8bit/bitwarden/data/platform/manager/model/FlagKey.kt#L22
-    // iOS (v2025.6.0): https://github.com/bitwarden/ios/blob/ff06d9c6cc8da89f78f37f376495800201d7261a/BitwardenShared/Core/Platform/Models/Enum/FeatureFlag.swift#L7
-    // let mut feature_states =
-    //     parse_experimental_client_feature_flags(&crate::CONFIG.experimental_client_feature_flags());
-    // feature_states.insert("duo-redirect".to_string(), true);
-    // feature_states.insert("email-verification".to_string(), true);
-    // feature_states.insert("unauth-ui-refresh".to_string(), true);
-    // feature_states.insert("enable-pm-flight-recorder".to_string(), true);
-    // feature_states.insert("mobile-error-reporting".to_string(), true);
-
     let disable_user_registration = get_disable_user_registration(&env);
 
     Json(json!({
@@ -63,12 +100,28 @@ pub async fn config(
           "pushTechnology": 0,
           "vapidPublicKey": null
         },
-        "featureStates": {
-            "pm-19051-send-email-verification": false,
-            "pm-19148-innovation-archive": true,
-            "cxp-import-mobile": true,
-            "cxp-export-mobile": true,
-        },
+        "featureStates": feature_states(&env),
         "object": "config",
     }))
 }
+
+/// GET /app-id.json - FIDO trusted facets of the web vault and the mobile apps
+///
+/// Served by the worker rather than as a static asset because the web vault's facet
+/// is the base URL of the deployment.
+#[worker::send]
+pub async fn app_id(Extension(BaseUrl(domain)): Extension<BaseUrl>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "application/fido.trusted-apps+json")],
+        Json(json!({
+            "trustedFacets": [{
+                "version": { "major": 1, "minor": 0 },
+                "ids": [
+                    domain,
+                    "ios:bundle-id:com.8bit.bitwarden",
+                    "android:apk-key-hash:dUGFzUzf3lmHSLBDBIv+WaFyZMI",
+                ]
+            }]
+        })),
+    )
+}
@@ -88,25 +88,43 @@ pub async fn get_known_device(
 ) -> Result<Json<bool>, AppError> {
     let encoded_email = required_header(&headers, "X-Request-Email")?;
     let identifier = required_header(&headers, "X-Device-Identifier")?;
-    let email = decode_base64url_email(&encoded_email)?.to_lowercase();
+    let email = decode_base64url_email(&encoded_email)?;
     let db = db::get_db(&env)?;
 
+    Ok(Json(is_known_device(&db, &email, &identifier).await?))
+}
+
+/// GET /devices/knowndevice/{email}/{identifier}, as called by clients before 2023.2
+#[worker::send]
+pub async fn get_known_device_from_path(
+    State(env): State<Arc<Env>>,
+    Path((email, identifier)): Path<(String, String)>,
+) -> Result<Json<bool>, AppError> {
+    let db = db::get_db(&env)?;
+    Ok(Json(is_known_device(&db, &email, &identifier).await?))
+}
+
+/// Whether the account of `email` has logged in from the device `identifier`.
+async fn is_known_device(
+    db: &crate::db::Db,
+    email: &str,
+    identifier: &str,
+) -> Result<bool, AppError> {
     let user_id: Option<String> = db
         .prepare("SELECT id FROM users WHERE email = ?1")
-        .bind(&[email.into()])?
+        .bind(&[email.trim().to_lowercase().into()])?
         .first(Some("id"))
         .await
         .map_err(|_| AppError::Database)?;
 
-    let is_known = if let Some(user_id) = user_id {
-        Device::find_by_identifier_and_user(&db, &identifier, &user_id)
-            .await?
-            .is_some()
-    } else {
-        false
-    };
-
-    Ok(Json(is_known))
+    match user_id {
+        Some(user_id) => Ok(
+            Device::find_by_identifier_and_user(db, identifier, &user_id)
+                .await?
+                .is_some(),
+        ),
+        None => Ok(false),
+    }
 }
 
 /// GET /devices/identifier/{device_id}
@@ -124,6 +142,62 @@ pub async fn get_device(
     Ok(Json(device.to_json()))
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceUpdate {
+    name: String,
+}
+
+/// PUT /devices/{device_id} - Rename a device
+#[worker::send]
+pub async fn put_device(
+    State(env): State<Arc<Env>>,
+    claims: Claims,
+    Path(device_id): Path<String>,
+    Json(data): Json<DeviceUpdate>,
+) -> Result<Json<Value>, AppError> {
+    let name = data.name.trim().to_string();
+    if name.is_empty() {
+        return Err(AppError::BadRequest("Device name is required".to_string()));
+    }
+
+    let db = db::get_db(&env)?;
+    let mut device = Device::find_by_identifier_and_user(&db, &device_id, &claims.sub)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Device not found".to_string()))?;
+    device.rename(&db, name).await?;
+
+    Ok(Json(device.to_json()))
+}
+
+/// DELETE /devices/{device_id} (also POST .../deactivate) - Remove a device
+///
+/// Revokes the device's refresh token and push registration; the session ends
+/// once its current access token expires.
+#[worker::send]
+pub async fn delete_device(
+    State(env): State<Arc<Env>>,
+    claims: Claims,
+    Path(device_id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let device = Device::find_by_identifier_and_user(&db, &device_id, &claims.sub)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Device not found".to_string()))?;
+
+    device.delete(&db).await?;
+
+    if device.push_uuid.is_some() {
+        if let Some(cfg) = push::push_config(&env)? {
+            if let Err(e) = push::unregister_push_device(&cfg, device.push_uuid.as_deref()).await {
+                log::warn!("Push unregistration for removed device failed: {e}");
+            }
+        }
+    }
+
+    Ok(Json(json!({})))
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PushToken {
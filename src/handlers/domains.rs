@@ -155,9 +155,27 @@ pub struct EquivDomainData {
     pub equivalent_domains: Option<Vec<Vec<String>>>,
 }
 
+/// Trim and lowercase custom domains, dropping blank entries and groups left empty.
+fn normalize_equivalent_domains(groups: Vec<Vec<String>>) -> Vec<Vec<String>> {
+    groups
+        .into_iter()
+        .map(|group| {
+            let mut domains: Vec<String> = Vec::with_capacity(group.len());
+            for domain in group {
+                let domain = domain.trim().to_lowercase();
+                if !domain.is_empty() && !domains.contains(&domain) {
+                    domains.push(domain);
+                }
+            }
+            domains
+        })
+        .filter(|group| !group.is_empty())
+        .collect()
+}
+
 /// POST /api/settings/domains
 ///
-/// Persist per-user eq_domains settings (no notifications/push).
+/// Persist per-user eq_domains settings and tell the user's other devices to sync.
 #[worker::send]
 pub async fn post_domains(
     claims: Claims,
@@ -169,7 +187,8 @@ pub async fn post_domains(
     let excluded_globals = payload
         .excluded_global_equivalent_domains
         .unwrap_or_default();
-    let equivalent_domains = payload.equivalent_domains.unwrap_or_default();
+    let equivalent_domains =
+        normalize_equivalent_domains(payload.equivalent_domains.unwrap_or_default());
 
     let excluded_globals_json = serde_json::to_string(&excluded_globals)
         .map_err(|_| AppError::BadRequest("Invalid excluded globals".to_string()))?;
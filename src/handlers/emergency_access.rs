@@ -1,32 +1,710 @@
-use axum::Json;
+//! Emergency access: a grantor designates trusted contacts (grantees) who can
+//! request access to the grantor's vault. Access is granted when the grantor
+//! approves the request, or automatically once the configured wait time elapses
+//! (see the `emergency_access_timeouts` cron job).
+//!
+//! Inviting an existing account accepts the invitation right away. An address without
+//! an account is emailed a link to accept it (when [email delivery](crate::mail) is
+//! configured), and is accepted when it registers either way. Grantors are emailed
+//! when a recovery is requested, and reminded daily while it waits.
+
+use axum::extract::{Path, State};
+use axum::{Extension, Json};
+use chrono::{Duration, Utc};
 use serde_json::{json, Value};
+use std::sync::Arc;
+use uuid::Uuid;
+use web_sys::UrlSearchParams;
+use worker::Env;
+
+use crate::auth::Claims;
+use crate::crypto::{generate_salt, hash_password_for_storage};
+use crate::d1_query;
+use crate::db;
+use crate::error::AppError;
+use crate::handlers::ciphers::{self, RawJson};
+use crate::handlers::{
+    attachments, ciphers_default_row_query, policies, server_password_iterations,
+};
+use crate::mail;
+use crate::models::emergency_access::{
+    EmergencyAccess, EmergencyAccessConfirmRequest, EmergencyAccessInviteRequest,
+    EmergencyAccessPasswordRequest, EmergencyAccessStatus, EmergencyAccessType,
+    EmergencyAccessUpdateRequest, EmergencyContact,
+};
+use crate::models::refresh_token::RefreshToken;
+use crate::models::user::User;
+use crate::notifications;
+use crate::BaseUrl;
+
+/// Recovery requests still waiting for a decision get a reminder at most this often.
+const REMINDER_INTERVAL_HOURS: i64 = 24;
+
+fn list_json(data: Vec<Value>) -> Value {
+    json!({
+        "data": data,
+        "object": "list",
+        "continuationToken": null,
+    })
+}
+
+async fn fetch_grant(db: &db::Db, id: &str) -> Result<EmergencyAccess, AppError> {
+    EmergencyAccess::find_by_id(db, id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Emergency access not valid.".to_string()))
+}
+
+/// Load a grant where the current user is the grantor.
+async fn fetch_as_grantor(
+    db: &db::Db,
+    id: &str,
+    user_id: &str,
+) -> Result<EmergencyAccess, AppError> {
+    let grant = fetch_grant(db, id).await?;
+    if grant.grantor_id != user_id {
+        return Err(AppError::NotFound(
+            "Emergency access not valid.".to_string(),
+        ));
+    }
+    Ok(grant)
+}
+
+/// Load a grant where the current user is the grantee.
+async fn fetch_as_grantee(
+    db: &db::Db,
+    id: &str,
+    user_id: &str,
+) -> Result<EmergencyAccess, AppError> {
+    let grant = fetch_grant(db, id).await?;
+    if grant.grantee_id.as_deref() != Some(user_id) {
+        return Err(AppError::NotFound(
+            "Emergency access not valid.".to_string(),
+        ));
+    }
+    Ok(grant)
+}
+
+/// Load an approved grant of the given type for the grantee, applying the
+/// wait time on the fly so access does not depend on the cron schedule.
+async fn fetch_approved(
+    db: &db::Db,
+    id: &str,
+    user_id: &str,
+    r#type: EmergencyAccessType,
+) -> Result<EmergencyAccess, AppError> {
+    let mut grant = fetch_as_grantee(db, id, user_id).await?;
+
+    if grant.has_status(EmergencyAccessStatus::RecoveryInitiated)
+        && grant.wait_time_elapsed(Utc::now())
+    {
+        grant.status = EmergencyAccessStatus::RecoveryApproved as i32;
+        grant.update(db).await?;
+    }
+
+    if !grant.has_status(EmergencyAccessStatus::RecoveryApproved) || !grant.has_type(r#type) {
+        return Err(AppError::BadRequest(
+            "Emergency access not valid.".to_string(),
+        ));
+    }
+    Ok(grant)
+}
+
+async fn load_user(db: &db::Db, user_id: &str) -> Result<User, AppError> {
+    let user: Value = db
+        .prepare("SELECT * FROM users WHERE id = ?1")
+        .bind(&[user_id.into()])?
+        .first(None)
+        .await
+        .map_err(|_| AppError::Database)?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+    serde_json::from_value(user).map_err(|_| AppError::Internal)
+}
+
+fn validate_grant_settings(r#type: i32, wait_time_days: i32) -> Result<(), AppError> {
+    if EmergencyAccessType::from_i32(r#type).is_none() {
+        return Err(AppError::BadRequest(
+            "Invalid emergency access type".to_string(),
+        ));
+    }
+    if !(1..=90).contains(&wait_time_days) {
+        return Err(AppError::BadRequest(
+            "Wait time must be between 1 and 90 days".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+// ── Listing ─────────────────────────────────────────────────────────
+
+/// GET /api/emergency-access/trusted - grants where the current user is the grantor
+#[worker::send]
+pub async fn get_trusted_contacts(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let grants = EmergencyAccess::list_by_grantor(&db, &claims.sub).await?;
+
+    let mut data = Vec::with_capacity(grants.len());
+    for grant in &grants {
+        let grantee = match grant.grantee_id.as_deref() {
+            Some(id) => EmergencyContact::find_by_id(&db, id).await?,
+            None => None,
+        };
+        data.push(grant.to_grantee_details_json(grantee.as_ref()));
+    }
+
+    Ok(Json(list_json(data)))
+}
+
+/// GET /api/emergency-access/granted - grants where the current user is the grantee
+#[worker::send]
+pub async fn get_granted_access(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let grants = EmergencyAccess::list_by_grantee(&db, &claims.sub).await?;
+
+    let mut data = Vec::with_capacity(grants.len());
+    for grant in &grants {
+        if let Some(grantor) = EmergencyContact::find_by_id(&db, &grant.grantor_id).await? {
+            data.push(grant.to_grantor_details_json(&grantor));
+        }
+    }
+
+    Ok(Json(list_json(data)))
+}
 
-/// GET /emergency-access/trusted
+/// GET /api/emergency-access/{id}
+#[worker::send]
+pub async fn get_emergency_access(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let grant = fetch_as_grantor(&db, &id, &claims.sub).await?;
+    let grantee = match grant.grantee_id.as_deref() {
+        Some(grantee_id) => EmergencyContact::find_by_id(&db, grantee_id).await?,
+        None => None,
+    };
+    Ok(Json(grant.to_grantee_details_json(grantee.as_ref())))
+}
+
+// ── Grantor management ──────────────────────────────────────────────
+
+/// PUT/POST /api/emergency-access/{id}
+#[worker::send]
+pub async fn update_emergency_access(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(id): Path<String>,
+    Json(payload): Json<EmergencyAccessUpdateRequest>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let mut grant = fetch_as_grantor(&db, &id, &claims.sub).await?;
+    validate_grant_settings(payload.r#type, payload.wait_time_days)?;
+
+    grant.r#type = payload.r#type;
+    grant.wait_time_days = payload.wait_time_days;
+    if let Some(key_encrypted) = payload.key_encrypted.filter(|k| !k.is_empty()) {
+        grant.key_encrypted = Some(key_encrypted);
+    }
+    grant.update(&db).await?;
+
+    Ok(Json(grant.to_json()))
+}
+
+/// DELETE /api/emergency-access/{id} (also POST .../delete) - by either party
+#[worker::send]
+pub async fn delete_emergency_access(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(id): Path<String>,
+) -> Result<Json<()>, AppError> {
+    let db = db::get_db(&env)?;
+    let grant = fetch_grant(&db, &id).await?;
+    if grant.grantor_id != claims.sub && grant.grantee_id.as_deref() != Some(claims.sub.as_str()) {
+        return Err(AppError::NotFound(
+            "Emergency access not valid.".to_string(),
+        ));
+    }
+    grant.delete(&db).await?;
+    Ok(Json(()))
+}
+
+/// How the grantor is named in emails: their name, or their email without one.
+fn grantor_display_name(claims: &Claims) -> &str {
+    if claims.name.trim().is_empty() {
+        &claims.email
+    } else {
+        &claims.name
+    }
+}
+
+/// Email the invitee of `grant`: the web vault link accepting a pending invitation, or
+/// a notice when the account was added right away. Does nothing when email delivery
+/// is not configured.
+fn send_invite_email(
+    env: &Env,
+    base_url: &str,
+    grantor: &str,
+    grant: &EmergencyAccess,
+) -> Result<(), AppError> {
+    if !mail::mail_configured(env) {
+        return Ok(());
+    }
+
+    let link = if grant.has_status(EmergencyAccessStatus::Invited) {
+        let params = UrlSearchParams::new().map_err(|_| AppError::Internal)?;
+        params.append("id", &grant.id);
+        params.append("name", grantor);
+        params.append("email", &grant.email);
+        // The web vault requires a token, but accepting checks that the logged-in
+        // account has the invited email instead.
+        params.append("token", &grant.id);
+        let query: String = params.to_string().into();
+        Some(format!(
+            "{}/#/accept-emergency?{query}",
+            base_url.trim_end_matches('/')
+        ))
+    } else {
+        None
+    };
+
+    mail::send_in_background(
+        env.clone(),
+        grant.email.clone(),
+        mail::Template::EmergencyAccessInvite {
+            grantor,
+            link: link.as_deref(),
+        },
+    );
+    Ok(())
+}
+
+/// POST /api/emergency-access/invite
+#[worker::send]
+pub async fn invite_emergency_contact(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Extension(BaseUrl(base_url)): Extension<BaseUrl>,
+    Json(payload): Json<EmergencyAccessInviteRequest>,
+) -> Result<Json<()>, AppError> {
+    let db = db::get_db(&env)?;
+    validate_grant_settings(payload.r#type, payload.wait_time_days)?;
+
+    let email = payload.email.trim().to_lowercase();
+    if email.is_empty() {
+        return Err(AppError::BadRequest("Email is required".to_string()));
+    }
+    if email == claims.email.to_lowercase() {
+        return Err(AppError::BadRequest(
+            "You can not set yourself as an emergency contact.".to_string(),
+        ));
+    }
+    if EmergencyAccess::find_by_grantor_and_email(&db, &claims.sub, &email)
+        .await?
+        .is_some()
+    {
+        return Err(AppError::BadRequest(
+            "Emergency contact already invited.".to_string(),
+        ));
+    }
+
+    let (grantee_id, status) = match User::find_by_email(&db, &email).await? {
+        Some(user) => (Some(user.id), EmergencyAccessStatus::Accepted),
+        None => (None, EmergencyAccessStatus::Invited),
+    };
+    let r#type = EmergencyAccessType::from_i32(payload.r#type).ok_or(AppError::Internal)?;
+
+    let grant = EmergencyAccess::new(
+        claims.sub.clone(),
+        grantee_id,
+        email,
+        r#type,
+        status,
+        payload.wait_time_days,
+    );
+    grant.insert(&db).await?;
+    send_invite_email(&env, &base_url, grantor_display_name(&claims), &grant)?;
+
+    Ok(Json(()))
+}
+
+/// POST /api/emergency-access/{id}/reinvite
 ///
-/// Returns the list of trusted emergency access contacts (grantees) for the current user.
-/// This is a stub implementation that always returns an empty list since emergency access
-/// is not supported in this minimal Bitwarden-compatible implementation.
+/// Emails the invitation link again. If the invited address has registered in the
+/// meantime, the invitation is accepted on its behalf instead.
+#[worker::send]
+pub async fn reinvite_emergency_contact(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Extension(BaseUrl(base_url)): Extension<BaseUrl>,
+    Path(id): Path<String>,
+) -> Result<Json<()>, AppError> {
+    let db = db::get_db(&env)?;
+    let mut grant = fetch_as_grantor(&db, &id, &claims.sub).await?;
+
+    if !grant.has_status(EmergencyAccessStatus::Invited) {
+        return Err(AppError::BadRequest(
+            "The grantee user is already accepted or confirmed.".to_string(),
+        ));
+    }
+
+    match User::find_by_email(&db, &grant.email).await? {
+        Some(user) => {
+            grant.grantee_id = Some(user.id);
+            grant.status = EmergencyAccessStatus::Accepted as i32;
+            grant.update(&db).await?;
+        }
+        None => send_invite_email(&env, &base_url, grantor_display_name(&claims), &grant)?,
+    }
+
+    Ok(Json(()))
+}
+
+/// POST /api/emergency-access/{id}/accept - the invited user accepts
+#[worker::send]
+pub async fn accept_emergency_invite(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(id): Path<String>,
+) -> Result<Json<()>, AppError> {
+    let db = db::get_db(&env)?;
+    let mut grant = fetch_grant(&db, &id).await?;
+    let user = load_user(&db, &claims.sub).await?;
+
+    if !grant.email.eq_ignore_ascii_case(&user.email) {
+        return Err(AppError::BadRequest(
+            "This invitation was sent to a different email address".to_string(),
+        ));
+    }
+    if !grant.has_status(EmergencyAccessStatus::Invited) {
+        return Err(AppError::BadRequest(
+            "The invitation has already been accepted".to_string(),
+        ));
+    }
+
+    grant.grantee_id = Some(user.id);
+    grant.status = EmergencyAccessStatus::Accepted as i32;
+    grant.update(&db).await?;
+
+    Ok(Json(()))
+}
+
+/// POST /api/emergency-access/{id}/confirm - the grantor hands over the encrypted user key
+#[worker::send]
+pub async fn confirm_emergency_contact(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(id): Path<String>,
+    Json(payload): Json<EmergencyAccessConfirmRequest>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let mut grant = fetch_as_grantor(&db, &id, &claims.sub).await?;
+
+    if !grant.has_status(EmergencyAccessStatus::Accepted) || grant.grantee_id.is_none() {
+        return Err(AppError::BadRequest(
+            "Emergency access not valid.".to_string(),
+        ));
+    }
+    if payload.key.is_empty() {
+        return Err(AppError::BadRequest("Missing key".to_string()));
+    }
+
+    grant.key_encrypted = Some(payload.key);
+    grant.status = EmergencyAccessStatus::Confirmed as i32;
+    grant.update(&db).await?;
+
+    Ok(Json(grant.to_json()))
+}
+
+// ── Recovery ────────────────────────────────────────────────────────
+
+/// POST /api/emergency-access/{id}/initiate - the grantee requests access
+#[worker::send]
+pub async fn initiate_recovery(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let mut grant = fetch_as_grantee(&db, &id, &claims.sub).await?;
+
+    if !grant.has_status(EmergencyAccessStatus::Confirmed) {
+        return Err(AppError::BadRequest(
+            "Emergency access not valid.".to_string(),
+        ));
+    }
+
+    let now = db::now_string();
+    grant.status = EmergencyAccessStatus::RecoveryInitiated as i32;
+    grant.recovery_initiated_at = Some(now.clone());
+    grant.last_notification_at = Some(now);
+    grant.update(&db).await?;
+
+    let grantor = load_user(&db, &grant.grantor_id).await?;
+    mail::send_in_background(
+        (*env).clone(),
+        grantor.email,
+        mail::Template::EmergencyAccessRequested {
+            grantee: &grant.email,
+            wait_time_days: grant.wait_time_days,
+        },
+    );
+
+    Ok(Json(grant.to_json()))
+}
+
+/// POST /api/emergency-access/{id}/approve - the grantor approves before the wait time elapses
+#[worker::send]
+pub async fn approve_recovery(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let mut grant = fetch_as_grantor(&db, &id, &claims.sub).await?;
+
+    if !grant.has_status(EmergencyAccessStatus::RecoveryInitiated) {
+        return Err(AppError::BadRequest(
+            "Emergency access not valid.".to_string(),
+        ));
+    }
+
+    grant.status = EmergencyAccessStatus::RecoveryApproved as i32;
+    grant.update(&db).await?;
+
+    Ok(Json(grant.to_json()))
+}
+
+/// POST /api/emergency-access/{id}/reject - the grantor rejects (or revokes) a recovery
+#[worker::send]
+pub async fn reject_recovery(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let mut grant = fetch_as_grantor(&db, &id, &claims.sub).await?;
+
+    if !grant.has_status(EmergencyAccessStatus::RecoveryInitiated)
+        && !grant.has_status(EmergencyAccessStatus::RecoveryApproved)
+    {
+        return Err(AppError::BadRequest(
+            "Emergency access not valid.".to_string(),
+        ));
+    }
+
+    grant.status = EmergencyAccessStatus::Confirmed as i32;
+    grant.recovery_initiated_at = None;
+    grant.update(&db).await?;
+
+    Ok(Json(grant.to_json()))
+}
+
+/// POST /api/emergency-access/{id}/view - read-only access to the grantor's vault
+#[worker::send]
+pub async fn view_grantor_vault(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(id): Path<String>,
+) -> Result<RawJson, AppError> {
+    let db = db::get_db(&env)?;
+    let grant = fetch_approved(&db, &id, &claims.sub, EmergencyAccessType::View).await?;
+
+    let mut response = String::from("{\"ciphers\":");
+    ciphers::append_cipher_json_array_raw(
+        &mut response,
+        &db,
+        attachments::attachments_enabled(env.as_ref()),
+        "WHERE c.user_id = ?1 AND c.deleted_at IS NULL",
+        &[grant.grantor_id.clone().into()],
+        "",
+        ciphers_default_row_query(env.as_ref()),
+    )
+    .await?;
+    response.push_str(",\"keyEncrypted\":");
+    response
+        .push_str(&serde_json::to_string(&grant.key_encrypted).map_err(|_| AppError::Internal)?);
+    response.push_str(",\"object\":\"emergencyAccessView\"}");
+
+    Ok(RawJson(response))
+}
+
+/// POST /api/emergency-access/{id}/takeover - KDF settings and key for a takeover
+#[worker::send]
+pub async fn takeover_grantor_account(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let grant = fetch_approved(&db, &id, &claims.sub, EmergencyAccessType::Takeover).await?;
+    let grantor = load_user(&db, &grant.grantor_id).await?;
+
+    Ok(Json(json!({
+        "kdf": grantor.kdf_type,
+        "kdfIterations": grantor.kdf_iterations,
+        "kdfMemory": grantor.kdf_memory,
+        "kdfParallelism": grantor.kdf_parallelism,
+        "keyEncrypted": grant.key_encrypted,
+        "object": "emergencyAccessTakeover",
+    })))
+}
+
+/// POST /api/emergency-access/{id}/password - the grantee sets a new master password
 ///
-/// In vaultwarden, when `emergency_access_allowed` is disabled, it returns an empty list.
-/// We follow the same pattern here.
+/// Logs the grantor out everywhere and removes them from organizations they don't own,
+/// since the new password was chosen by someone else.
 #[worker::send]
-pub async fn get_trusted_contacts() -> Json<Value> {
-    Json(json!({
-        "data": [],
-        "object": "list",
-        "continuationToken": null
-    }))
+pub async fn takeover_set_password(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(id): Path<String>,
+    Json(payload): Json<EmergencyAccessPasswordRequest>,
+) -> Result<Json<()>, AppError> {
+    let db = db::get_db(&env)?;
+    let grant = fetch_approved(&db, &id, &claims.sub, EmergencyAccessType::Takeover).await?;
+
+    let new_salt = generate_salt()?;
+    let password_iterations = server_password_iterations(&env) as i32;
+    let new_hashed_password = hash_password_for_storage(
+        &payload.new_master_password_hash,
+        &new_salt,
+        password_iterations as u32,
+    )
+    .await?;
+
+    let now = db::now_string();
+    d1_query!(
+        &db,
+        "UPDATE users SET master_password_hash = ?1, password_salt = ?2, password_iterations = ?3, key = ?4, security_stamp = ?5, updated_at = ?6 WHERE id = ?7",
+        new_hashed_password,
+        new_salt,
+        password_iterations,
+        payload.key,
+        Uuid::new_v4().to_string(),
+        now,
+        grant.grantor_id
+    )
+    .map_err(|_| AppError::Database)?
+    .run()
+    .await?;
+
+    d1_query!(
+        &db,
+        "DELETE FROM users_organizations WHERE user_id = ?1 AND type != 0",
+        grant.grantor_id
+    )
+    .map_err(|_| AppError::Database)?
+    .run()
+    .await?;
+
+    RefreshToken::revoke_all_by_user(&db, &grant.grantor_id).await?;
+
+    notifications::publish_user_logout((*env).clone(), grant.grantor_id, now, None);
+
+    Ok(Json(()))
 }
 
-/// GET /emergency-access/granted
+/// GET /api/emergency-access/{id}/policies - policies of the grantor's organizations
 ///
-/// Returns the list of emergency access grants where the current user is a grantee.
-/// This is a stub implementation that always returns an empty list.
+/// The grantee needs them to pick a new master password that satisfies the grantor's
+/// MasterPassword policies.
 #[worker::send]
-pub async fn get_granted_access() -> Json<Value> {
-    Json(json!({
-        "data": [],
-        "object": "list",
-        "continuationToken": null
-    }))
+pub async fn get_grantor_policies(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let grant = fetch_approved(&db, &id, &claims.sub, EmergencyAccessType::Takeover).await?;
+    Ok(Json(list_json(
+        policies::user_policies_json(&db, &grant.grantor_id).await?,
+    )))
+}
+
+// ── Scheduled jobs ──────────────────────────────────────────────────
+
+/// Approve recovery requests whose wait time has elapsed.
+pub async fn approve_elapsed_recoveries(env: &Env) -> Result<u32, worker::Error> {
+    let db = db::get_db(env).map_err(|e| worker::Error::RustError(e.to_string()))?;
+    let pending = EmergencyAccess::list_recovery_initiated(&db)
+        .await
+        .map_err(|e| worker::Error::RustError(e.to_string()))?;
+
+    let now = Utc::now();
+    let mut count = 0;
+    for mut grant in pending {
+        if !grant.wait_time_elapsed(now) {
+            continue;
+        }
+        grant.status = EmergencyAccessStatus::RecoveryApproved as i32;
+        grant
+            .update(&db)
+            .await
+            .map_err(|e| worker::Error::RustError(e.to_string()))?;
+        count += 1;
+    }
+
+    if count > 0 {
+        log::info!("Approved {} emergency access recovery request(s)", count);
+    }
+    Ok(count)
+}
+
+/// Remind grantors of pending recovery requests by email, at most once per interval.
+///
+/// Without email delivery the reminder is recorded and logged only. A reminder that
+/// could not be sent is tried again on the next run.
+pub async fn remind_pending_recoveries(env: &Env) -> Result<u32, worker::Error> {
+    let db = db::get_db(env).map_err(|e| worker::Error::RustError(e.to_string()))?;
+    let pending = EmergencyAccess::list_recovery_initiated(&db)
+        .await
+        .map_err(|e| worker::Error::RustError(e.to_string()))?;
+
+    let cutoff = (Utc::now() - Duration::hours(REMINDER_INTERVAL_HOURS))
+        .format("%Y-%m-%dT%H:%M:%S%.3fZ")
+        .to_string();
+
+    let mut count = 0;
+    for mut grant in pending {
+        if grant
+            .last_notification_at
+            .as_deref()
+            .is_some_and(|last| last > cutoff.as_str())
+        {
+            continue;
+        }
+        log::info!(
+            "Emergency access {}: recovery requested by {} is awaiting grantor {}",
+            grant.id,
+            grant.email,
+            grant.grantor_id
+        );
+        if mail::mail_configured(env) {
+            let grantor = load_user(&db, &grant.grantor_id)
+                .await
+                .map_err(|e| worker::Error::RustError(e.to_string()))?;
+            let template = mail::Template::EmergencyAccessRequested {
+                grantee: &grant.email,
+                wait_time_days: grant.wait_time_days,
+            };
+            if let Err(e) = mail::send(env, &grantor.email, template).await {
+                log::warn!("Emergency access {}: reminder email failed: {e}", grant.id);
+                continue;
+            }
+        }
+        grant.last_notification_at = Some(db::now_string());
+        grant
+            .update(&db)
+            .await
+            .map_err(|e| worker::Error::RustError(e.to_string()))?;
+        count += 1;
+    }
+
+    Ok(count)
 }
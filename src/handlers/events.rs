@@ -0,0 +1,309 @@
+//! Organization event log.
+//!
+//! Events are recorded by the handlers that perform the audited actions (see
+//! [`crate::models::event`]) and trimmed by the `expired_events` job. Organizations
+//! whose plan does not include event logs record none.
+
+use axum::body::Body;
+use axum::extract::{Path, Query, State};
+use axum::http::header;
+use axum::response::Response;
+use axum::Json;
+use bytes::Bytes;
+use chrono::{DateTime, Duration, Utc};
+use futures_util::stream;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use worker::send::{SendFuture, SendWrapper};
+use worker::Env;
+
+use crate::auth::Claims;
+use crate::db;
+use crate::error::AppError;
+use crate::handlers::organizations::{require_member_access, require_org_feature};
+use crate::models::event::{Event, EventCursor, EventExportRow};
+use crate::models::organization::{MembershipType, OrgFeature, Permission};
+
+/// Events returned per page.
+const EVENTS_PAGE_SIZE: u32 = 50;
+/// Range shown when the client does not send `start`.
+const DEFAULT_RANGE_DAYS: i64 = 30;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventRangeQuery {
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub continuation_token: Option<String>,
+}
+
+/// Parse a client-supplied date into the format stored in D1.
+fn normalize_date(value: &str, field: &str) -> Result<String, AppError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|t| {
+            t.with_timezone(&Utc)
+                .format("%Y-%m-%dT%H:%M:%S%.3fZ")
+                .to_string()
+        })
+        .map_err(|_| AppError::BadRequest(format!("Invalid {field} date")))
+}
+
+/// `[start, end]` of the query, by default the last [`DEFAULT_RANGE_DAYS`] days.
+fn event_range(query: &EventRangeQuery) -> Result<(String, String), AppError> {
+    let end = match query.end.as_deref() {
+        Some(end) => normalize_date(end, "end")?,
+        None => db::now_string(),
+    };
+    let start = match query.start.as_deref() {
+        Some(start) => normalize_date(start, "start")?,
+        None => (Utc::now() - Duration::days(DEFAULT_RANGE_DAYS))
+            .format("%Y-%m-%dT%H:%M:%S%.3fZ")
+            .to_string(),
+    };
+    Ok((start, end))
+}
+
+/// Check that the user may read the organization's event log.
+async fn require_event_log_access(
+    db: &db::Db,
+    org_id: &str,
+    user_id: &str,
+) -> Result<(), AppError> {
+    require_member_access(
+        db,
+        org_id,
+        user_id,
+        MembershipType::Admin,
+        &[Permission::AccessEventLogs],
+    )
+    .await?;
+    require_org_feature(db, org_id, OrgFeature::Events).await
+}
+
+/// GET /api/organizations/{org_id}/events?start=...&end=...&continuationToken=...
+#[worker::send]
+pub async fn get_organization_events(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(org_id): Path<String>,
+    Query(query): Query<EventRangeQuery>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    require_event_log_access(&db, &org_id, &claims.sub).await?;
+
+    let (start, end) = event_range(&query)?;
+    let before = query
+        .continuation_token
+        .as_deref()
+        .map(|token| normalize_date(token, "continuation token"))
+        .transpose()?;
+
+    let events = Event::list_by_org(
+        &db,
+        &org_id,
+        &start,
+        &end,
+        before.as_deref(),
+        EVENTS_PAGE_SIZE,
+    )
+    .await?;
+
+    let continuation_token = (events.len() as u32 == EVENTS_PAGE_SIZE)
+        .then(|| events.last().map(|e| e.event_date.clone()))
+        .flatten();
+
+    Ok(Json(json!({
+        "data": events.iter().map(Event::to_json).collect::<Vec<_>>(),
+        "object": "list",
+        "continuationToken": continuation_token,
+    })))
+}
+
+/// Events read per D1 query of the CSV export.
+const EXPORT_PAGE_SIZE: u32 = 500;
+/// Events per CSV download, which keeps one request within the Worker limits.
+const EXPORT_MAX_EVENTS: u32 = 10_000;
+/// Response header with the `continuationToken` of the next download.
+const EXPORT_CONTINUATION_HEADER: &str = "x-continuation-token";
+
+const EXPORT_CSV_HEADER: &str = "date,type,actingUserId,actingUserEmail,userId,userEmail,\
+organizationUserId,cipherId,collectionId,groupId,policyId,deviceType,ipAddress\r\n";
+
+/// GET /api/organizations/{org_id}/events/export?start=...&end=...&continuationToken=...
+///
+/// Streams the events of the range as CSV, newest first. A download holds at most
+/// [`EXPORT_MAX_EVENTS`] events; when more remain, the `X-Continuation-Token` header
+/// holds the `continuationToken` that continues where this download stops.
+#[worker::send]
+pub async fn export_organization_events(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(org_id): Path<String>,
+    Query(query): Query<EventRangeQuery>,
+) -> Result<Response, AppError> {
+    let db = db::get_db(&env)?;
+    require_event_log_access(&db, &org_id, &claims.sub).await?;
+
+    let (start, end) = event_range(&query)?;
+    let from = query
+        .continuation_token
+        .as_deref()
+        .map(parse_export_token)
+        .transpose()?;
+    let stop =
+        Event::export_cursor_at(&db, &org_id, &start, &end, from.as_ref(), EXPORT_MAX_EVENTS)
+            .await?;
+    log::info!("User {} exported the events of {org_id}", claims.sub);
+
+    let mut response = Response::builder()
+        .header(header::CONTENT_TYPE, "text/csv; charset=utf-8")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"events_{org_id}.csv\""),
+        );
+    if let Some(stop) = &stop {
+        response = response.header(
+            EXPORT_CONTINUATION_HEADER,
+            format!("{}_{}", stop.event_date, stop.id),
+        );
+    }
+
+    let export = EventExport {
+        db,
+        org_id,
+        start,
+        end,
+        inclusive: true,
+        from,
+        stop,
+        header_written: false,
+        done: false,
+    };
+    let chunks = stream::unfold(SendWrapper::new(export), |mut export| {
+        SendFuture::new(async move {
+            match export.next_chunk().await {
+                Ok(Some(chunk)) => Some((Ok(Bytes::from(chunk)), export)),
+                Ok(None) => None,
+                Err(e) => {
+                    // The status is already sent; cutting the body short marks the
+                    // download as failed.
+                    log::error!("Event export of {} failed: {e}", export.org_id);
+                    export.done = true;
+                    Some((Err(std::io::Error::other(e.to_string())), export))
+                }
+            }
+        })
+    });
+    response
+        .body(Body::from_stream(chunks))
+        .map_err(|_| AppError::Internal)
+}
+
+/// Parse a `continuationToken` of the export: `{event_date}_{id}` of its first event.
+fn parse_export_token(token: &str) -> Result<EventCursor, AppError> {
+    let invalid = || AppError::BadRequest("Invalid continuation token".to_string());
+    let (date, id) = token.split_once('_').ok_or_else(invalid)?;
+    if id.is_empty() {
+        return Err(invalid());
+    }
+    Ok(EventCursor {
+        event_date: normalize_date(date, "continuation token")?,
+        id: id.to_string(),
+    })
+}
+
+/// State of a streamed CSV export.
+struct EventExport {
+    db: db::Db,
+    org_id: String,
+    start: String,
+    end: String,
+    /// Where the next page starts; `inclusive` only for the first page of a download.
+    from: Option<EventCursor>,
+    inclusive: bool,
+    /// First event of the next download, which this one stops before.
+    stop: Option<EventCursor>,
+    header_written: bool,
+    done: bool,
+}
+
+impl EventExport {
+    /// The next part of the CSV, or `None` at the end.
+    async fn next_chunk(&mut self) -> Result<Option<String>, AppError> {
+        if self.done {
+            return Ok(None);
+        }
+        let mut csv = String::new();
+        if !self.header_written {
+            csv.push_str(EXPORT_CSV_HEADER);
+            self.header_written = true;
+        }
+
+        let rows = Event::export_page(
+            &self.db,
+            &self.org_id,
+            &self.start,
+            &self.end,
+            self.from.as_ref(),
+            self.inclusive,
+            EXPORT_PAGE_SIZE,
+        )
+        .await?;
+        self.done = (rows.len() as u32) < EXPORT_PAGE_SIZE;
+        for row in &rows {
+            let cursor = row.cursor();
+            if self.stop.as_ref().is_some_and(|stop| cursor <= *stop) {
+                self.done = true;
+                break;
+            }
+            write_csv_row(&mut csv, row);
+            self.from = Some(cursor);
+        }
+        self.inclusive = false;
+
+        Ok((!csv.is_empty()).then_some(csv))
+    }
+}
+
+fn write_csv_row(csv: &mut String, row: &EventExportRow) {
+    let device_type = row.device_type.map(|t| t.to_string());
+    let fields = [
+        Some(row.event_date.as_str()),
+        Some(&row.event_type.to_string()),
+        row.acting_user_id.as_deref(),
+        row.acting_user_email.as_deref(),
+        row.user_id.as_deref(),
+        row.user_email.as_deref(),
+        row.member_id.as_deref(),
+        row.cipher_id.as_deref(),
+        row.collection_id.as_deref(),
+        row.group_id.as_deref(),
+        row.policy_id.as_deref(),
+        device_type.as_deref(),
+        row.ip_address.as_deref(),
+    ];
+    for (i, field) in fields.into_iter().enumerate() {
+        if i > 0 {
+            csv.push(',');
+        }
+        push_csv_field(csv, field.unwrap_or_default());
+    }
+    csv.push_str("\r\n");
+}
+
+/// Quote a CSV field when needed. Fields a spreadsheet would read as a formula get a
+/// leading `'`, since emails are chosen by the users.
+fn push_csv_field(csv: &mut String, value: &str) {
+    let formula = value.starts_with(['=', '+', '-', '@', '\t', '\r']);
+    if !formula && !value.contains([',', '"', '\r', '\n']) {
+        csv.push_str(value);
+        return;
+    }
+    csv.push('"');
+    if formula {
+        csv.push('\'');
+    }
+    csv.push_str(&value.replace('"', "\"\""));
+    csv.push('"');
+}
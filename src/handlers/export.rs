@@ -0,0 +1,166 @@
+//! Vault export in Bitwarden's encrypted JSON format.
+//!
+//! The server only ever holds encrypted data, so it can produce the "encrypted JSON"
+//! export but not a plaintext one: names, notes and every secret stay encrypted with
+//! the account's user key, and the file can be imported into any Bitwarden client
+//! logged into the same account. Plaintext exports have to be made by a client.
+//!
+//! Organization exports hand the clients the organization's collections and ciphers,
+//! still encrypted with the organization key; the client builds the export file.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use worker::Env;
+
+use crate::auth::Claims;
+use crate::d1_query;
+use crate::db;
+use crate::error::AppError;
+use crate::handlers::organizations::require_member_access;
+use crate::models::collection::Collection;
+use crate::models::event::{Event, EventActor, EventType};
+use crate::models::organization::{MembershipType, Permission};
+use crate::models::policy::{OrgPolicy, PolicyType};
+
+use super::attachments;
+use super::ciphers;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ExportQuery {
+    /// `encrypted_json` (default); `json` is refused since the server cannot decrypt.
+    #[serde(default)]
+    pub format: Option<String>,
+}
+
+/// GET /api/ciphers/export - the user's personal vault (no organization items, no trash)
+#[worker::send]
+pub async fn export_vault(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, AppError> {
+    match query.format.as_deref().unwrap_or("encrypted_json") {
+        "encrypted_json" => {}
+        "json" | "csv" => {
+            return Err(AppError::BadRequest(
+                "The server cannot decrypt the vault; create plaintext exports from a client"
+                    .to_string(),
+            ))
+        }
+        _ => return Err(AppError::BadRequest("Invalid export format".to_string())),
+    }
+
+    let db = db::get_db(&env)?;
+    if !OrgPolicy::list_enforced_for_user(&db, &claims.sub, PolicyType::DisablePersonalVaultExport)
+        .await?
+        .is_empty()
+    {
+        return Err(AppError::BadRequest(
+            "An organization policy prevents you from exporting your personal vault".to_string(),
+        ));
+    }
+
+    let folders: Vec<Value> = d1_query!(
+        &db,
+        "SELECT id, name FROM folders WHERE user_id = ?1 ORDER BY created_at",
+        &claims.sub
+    )
+    .map_err(|_| AppError::Database)?
+    .all()
+    .await
+    .map_err(|_| AppError::Database)?
+    .results()
+    .map_err(|_| AppError::Database)?;
+    let folders_json = serde_json::to_string(&json!(folders)).map_err(|_| AppError::Internal)?;
+
+    // Built as a string so the ciphers array is never parsed; the items are the API's
+    // cipher objects, which the Bitwarden importer reads like its own export items.
+    let mut out = String::new();
+    out.push_str("{\"encrypted\":true,\"passwordProtected\":false,\"folders\":");
+    out.push_str(&folders_json);
+    out.push_str(",\"items\":");
+    ciphers::append_cipher_json_array_raw(
+        &mut out,
+        &db,
+        attachments::attachments_enabled(&env),
+        "WHERE c.user_id = ?1 AND c.organization_id IS NULL AND c.deleted_at IS NULL",
+        &[claims.sub.clone().into()],
+        "ORDER BY c.created_at",
+        super::ciphers_default_row_query(&env),
+    )
+    .await?;
+    out.push('}');
+
+    let filename = format!(
+        "bitwarden_encrypted_export_{}.json",
+        chrono::Utc::now().format("%Y%m%d%H%M%S")
+    );
+    log::info!("User {} exported their vault", claims.sub);
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/json".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{filename}\""),
+            ),
+        ],
+        out,
+    )
+        .into_response())
+}
+
+/// GET /api/organizations/{org_id}/export - collections and ciphers of an organization
+/// (no trash), for owners, admins and members allowed to import and export.
+#[worker::send]
+pub async fn export_organization(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    headers: HeaderMap,
+    Path(org_id): Path<String>,
+) -> Result<ciphers::RawJson, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Admin,
+        &[Permission::AccessImportExport],
+    )
+    .await?;
+
+    let collections: Vec<Value> = Collection::list_by_org(&db, &org_id)
+        .await?
+        .iter()
+        .map(Collection::to_json)
+        .collect();
+    let collections_json = serde_json::to_string(&collections).map_err(|_| AppError::Internal)?;
+
+    let mut out = String::new();
+    out.push_str("{\"collections\":");
+    out.push_str(&collections_json);
+    out.push_str(",\"ciphers\":");
+    ciphers::append_cipher_json_array_raw(
+        &mut out,
+        &db,
+        attachments::attachments_enabled(&env),
+        "WHERE c.organization_id = ?2 AND c.deleted_at IS NULL",
+        &[claims.sub.clone().into(), org_id.clone().into()],
+        "ORDER BY c.created_at",
+        super::ciphers_default_row_query(&env),
+    )
+    .await?;
+    out.push('}');
+
+    let actor = EventActor::from_request(&claims, &headers);
+    Event::new(EventType::OrganizationClientExportedVault, &org_id, &actor)
+        .record(&db)
+        .await;
+    log::info!("User {} exported organization {}", claims.sub, org_id);
+    Ok(ciphers::RawJson(out))
+}
@@ -1,5 +1,6 @@
-use axum::extract::{Path, State};
+use axum::extract::{Path, Query, State};
 use axum::Json;
+use serde::Deserialize;
 use serde_json::{json, Value};
 use std::sync::Arc;
 use uuid::Uuid;
@@ -114,27 +115,119 @@ pub async fn create_folder(
     Ok(Json(response))
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteFolderQuery {
+    #[serde(default)]
+    pub delete_contents: bool,
+}
+
+/// Delete a folder. Its ciphers are unfiled in the same batch (rather than left to
+/// the foreign key), so they get a new revision and show up in the next delta sync.
+///
+/// With `?deleteContents=true`, the user's own ciphers in the folder are moved to the
+/// trash as well. Organization ciphers filed in it are only unfiled, since the folder
+/// is just the user's view of them.
 #[worker::send]
 pub async fn delete_folder(
     claims: Claims,
     State(env): State<Arc<Env>>,
     Path(id): Path<String>,
+    Query(query): Query<DeleteFolderQuery>,
 ) -> Result<Json<()>, AppError> {
     let db = db::get_db(&env)?;
     let now = db::now_string();
 
-    d1_query!(
-        &db,
-        "DELETE FROM folders WHERE id = ?1 AND user_id = ?2",
-        id,
-        claims.sub
-    )
-    .map_err(|_| AppError::Database)?
-    .run()
-    .await?;
+    let personal_ciphers = if query.delete_contents {
+        d1_query!(
+            &db,
+            "UPDATE ciphers SET folder_id = NULL, deleted_at = COALESCE(deleted_at, ?1), updated_at = ?1
+             WHERE folder_id = ?2 AND user_id = ?3
+             RETURNING id, deleted_at = ?1 AS trashed",
+            &now,
+            &id,
+            &claims.sub
+        )
+    } else {
+        d1_query!(
+            &db,
+            "UPDATE ciphers SET folder_id = NULL, updated_at = ?1
+             WHERE folder_id = ?2 AND user_id = ?3
+             RETURNING id, 0 AS trashed",
+            &now,
+            &id,
+            &claims.sub
+        )
+    }
+    .map_err(|_| AppError::Database)?;
+
+    let results = db
+        .batch(vec![
+            personal_ciphers,
+            d1_query!(
+                &db,
+                "UPDATE users_ciphers SET folder_id = NULL WHERE folder_id = ?1 AND user_id = ?2",
+                &id,
+                &claims.sub
+            )
+            .map_err(|_| AppError::Database)?,
+            d1_query!(
+                &db,
+                "DELETE FROM folders WHERE id = ?1 AND user_id = ?2",
+                &id,
+                &claims.sub
+            )
+            .map_err(|_| AppError::Database)?,
+        ])
+        .await?;
+    let changes = |index: usize| {
+        results
+            .get(index)
+            .and_then(|r| r.meta().ok().flatten())
+            .and_then(|m| m.changes)
+            .unwrap_or(0)
+    };
+    if changes(2) == 0 {
+        return Err(AppError::NotFound("Folder not found".to_string()));
+    }
+
+    #[derive(Deserialize)]
+    struct ChangedCipher {
+        id: String,
+        trashed: i64,
+    }
+    let changed: Vec<ChangedCipher> = match results.first() {
+        Some(result) => result.results().map_err(|_| AppError::Database)?,
+        None => Vec::new(),
+    };
+    let trashed: Vec<String> = changed
+        .iter()
+        .filter(|cipher| cipher.trashed != 0)
+        .map(|cipher| cipher.id.clone())
+        .collect();
+    let unfiled = changed.len() - trashed.len() + changes(1);
 
     touch_user_updated_at(&db, &claims.sub, &now).await?;
 
+    for cipher_id in trashed {
+        notifications::publish_cipher_update(
+            (*env).clone(),
+            claims.sub.clone(),
+            UpdateType::SyncCipherUpdate,
+            cipher_id,
+            now.clone(),
+            Some(claims.device.clone()),
+        );
+    }
+    if unfiled > 0 {
+        notifications::publish_user_update(
+            (*env).clone(),
+            claims.sub.clone(),
+            UpdateType::SyncCiphers,
+            now.clone(),
+            Some(claims.device.clone()),
+        );
+    }
     notifications::publish_folder_update(
         (*env).clone(),
         claims.sub,
@@ -146,6 +239,7 @@ pub async fn delete_folder(
 
     Ok(Json(()))
 }
+
 #[worker::send]
 pub async fn update_folder(
     claims: Claims,
@@ -0,0 +1,450 @@
+//! Organization groups.
+//!
+//! A group bundles members and grants each of them the group's collection access,
+//! with the same read-only / hide-passwords / manage flags as a direct assignment.
+//! Access checks combine both sources (see the `member_collection_access` view).
+
+use axum::extract::{Path, State};
+use axum::http::HeaderMap;
+use axum::Json;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use worker::Env;
+
+use crate::auth::Claims;
+use crate::db;
+use crate::error::AppError;
+use crate::handlers::organizations::{require_member_access, require_org_feature};
+use crate::models::collection::{Collection, CollectionAccessData};
+use crate::models::event::{Event, EventActor, EventType};
+use crate::models::group::{BulkGroupIds, Group, GroupAccess, GroupRequest};
+use crate::models::organization::{Membership, MembershipType, OrgFeature, Permission};
+
+fn list_json(data: Vec<Value>) -> Value {
+    json!({
+        "data": data,
+        "object": "list",
+        "continuationToken": null,
+    })
+}
+
+async fn fetch_group(db: &db::Db, org_id: &str, group_id: &str) -> Result<Group, AppError> {
+    Group::find_by_id_and_org(db, group_id, org_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Group not found".to_string()))
+}
+
+/// Validate requested collection assignments for a group and convert them to rows.
+async fn group_collection_access(
+    db: &db::Db,
+    org_id: &str,
+    group_id: &str,
+    collections: Vec<CollectionAccessData>,
+) -> Result<Vec<GroupAccess>, AppError> {
+    let ids: Vec<String> = collections.iter().map(|c| c.id.clone()).collect();
+    if !Collection::foreign_ids(db, org_id, &ids).await?.is_empty() {
+        return Err(AppError::BadRequest(
+            "Collection does not belong to this organization".to_string(),
+        ));
+    }
+    Ok(collections
+        .into_iter()
+        .map(|c| {
+            let collection_id = c.id.clone();
+            GroupAccess::from_data(c, group_id, &collection_id)
+        })
+        .collect())
+}
+
+/// Reject group ids that do not belong to `org_id`.
+async fn ensure_own_groups(
+    db: &db::Db,
+    org_id: &str,
+    group_ids: &[String],
+) -> Result<(), AppError> {
+    if !Group::foreign_ids(db, org_id, group_ids).await?.is_empty() {
+        return Err(AppError::BadRequest(
+            "Group does not belong to this organization".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Reject group ids to assign that do not belong to `org_id`, or any at all when the
+/// organization's plan does not include groups.
+pub(crate) async fn ensure_org_groups(
+    db: &db::Db,
+    org_id: &str,
+    group_ids: &[String],
+) -> Result<(), AppError> {
+    if !group_ids.is_empty() {
+        require_org_feature(db, org_id, OrgFeature::Groups).await?;
+    }
+    ensure_own_groups(db, org_id, group_ids).await
+}
+
+/// Reject membership ids that do not belong to `org_id`.
+pub(crate) async fn ensure_org_members(
+    db: &db::Db,
+    org_id: &str,
+    membership_ids: &[String],
+) -> Result<(), AppError> {
+    let members: Vec<String> = Membership::list_by_org(db, org_id)
+        .await?
+        .into_iter()
+        .map(|m| m.id)
+        .collect();
+    if membership_ids.iter().any(|id| !members.contains(id)) {
+        return Err(AppError::BadRequest(
+            "User is not a member of this organization".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn group_event(
+    event_type: EventType,
+    group: &Group,
+    claims: &Claims,
+    headers: &HeaderMap,
+) -> Event {
+    let actor = EventActor::from_request(claims, headers);
+    let mut event = Event::new(event_type, &group.organization_id, &actor);
+    event.group_id = Some(group.id.clone());
+    event
+}
+
+async fn group_details_json(db: &db::Db, group: &Group) -> Result<Value, AppError> {
+    let collections = GroupAccess::list_by_group(db, &group.id).await?;
+    Ok(group.to_details_json(&collections))
+}
+
+/// Validated collection access and members (if given) of a create/update request.
+struct GroupAssignments {
+    access: Vec<GroupAccess>,
+    users: Option<Vec<String>>,
+}
+
+impl GroupAssignments {
+    async fn from_request(
+        db: &db::Db,
+        org_id: &str,
+        group_id: &str,
+        payload: GroupRequest,
+    ) -> Result<Self, AppError> {
+        let access = group_collection_access(db, org_id, group_id, payload.collections).await?;
+        if let Some(users) = &payload.users {
+            ensure_org_members(db, org_id, users).await?;
+        }
+        Ok(Self {
+            access,
+            users: payload.users,
+        })
+    }
+
+    async fn save(self, db: &db::Db, group: &Group) -> Result<(), AppError> {
+        GroupAccess::replace_for_group(db, &group.id, &self.access).await?;
+        if let Some(users) = self.users {
+            Group::set_members(db, &group.id, &users).await?;
+        }
+        Membership::touch_confirmed_users(db, &group.organization_id, &group.updated_at).await
+    }
+}
+
+/// GET /api/organizations/{org_id}/groups
+#[worker::send]
+pub async fn list_groups(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(org_id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Manager,
+        &[Permission::ManageGroups, Permission::ManageUsers],
+    )
+    .await?;
+
+    let groups = Group::list_by_org(&db, &org_id).await?;
+    Ok(Json(list_json(groups.iter().map(Group::to_json).collect())))
+}
+
+/// GET /api/organizations/{org_id}/groups/details
+#[worker::send]
+pub async fn list_group_details(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(org_id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Manager,
+        &[Permission::ManageGroups, Permission::ManageUsers],
+    )
+    .await?;
+
+    let groups = Group::list_by_org(&db, &org_id).await?;
+    let mut data = Vec::with_capacity(groups.len());
+    for group in &groups {
+        data.push(group_details_json(&db, group).await?);
+    }
+    Ok(Json(list_json(data)))
+}
+
+/// GET /api/organizations/{org_id}/groups/{group_id}
+#[worker::send]
+pub async fn get_group(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path((org_id, group_id)): Path<(String, String)>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Manager,
+        &[Permission::ManageGroups, Permission::ManageUsers],
+    )
+    .await?;
+    let group = fetch_group(&db, &org_id, &group_id).await?;
+    Ok(Json(group.to_json()))
+}
+
+/// GET /api/organizations/{org_id}/groups/{group_id}/details
+#[worker::send]
+pub async fn get_group_details(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path((org_id, group_id)): Path<(String, String)>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Manager,
+        &[Permission::ManageGroups, Permission::ManageUsers],
+    )
+    .await?;
+    let group = fetch_group(&db, &org_id, &group_id).await?;
+    Ok(Json(group_details_json(&db, &group).await?))
+}
+
+/// POST /api/organizations/{org_id}/groups
+#[worker::send]
+pub async fn create_group(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    headers: HeaderMap,
+    Path(org_id): Path<String>,
+    Json(payload): Json<GroupRequest>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Admin,
+        &[Permission::ManageGroups],
+    )
+    .await?;
+    require_org_feature(&db, &org_id, OrgFeature::Groups).await?;
+
+    let group = Group::new(
+        org_id.clone(),
+        payload.name.clone(),
+        payload.external_id.clone(),
+    );
+    let assignments = GroupAssignments::from_request(&db, &org_id, &group.id, payload).await?;
+    group.insert(&db).await?;
+    assignments.save(&db, &group).await?;
+    group_event(EventType::GroupCreated, &group, &claims, &headers)
+        .record(&db)
+        .await;
+
+    Ok(Json(group_details_json(&db, &group).await?))
+}
+
+/// PUT/POST /api/organizations/{org_id}/groups/{group_id}
+#[worker::send]
+pub async fn update_group(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    headers: HeaderMap,
+    Path((org_id, group_id)): Path<(String, String)>,
+    Json(payload): Json<GroupRequest>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Admin,
+        &[Permission::ManageGroups],
+    )
+    .await?;
+    require_org_feature(&db, &org_id, OrgFeature::Groups).await?;
+    let mut group = fetch_group(&db, &org_id, &group_id).await?;
+
+    group.name = payload.name.clone();
+    group.external_id = payload.external_id.clone();
+    let assignments = GroupAssignments::from_request(&db, &org_id, &group.id, payload).await?;
+    group.update(&db).await?;
+    assignments.save(&db, &group).await?;
+    group_event(EventType::GroupUpdated, &group, &claims, &headers)
+        .record(&db)
+        .await;
+
+    Ok(Json(group_details_json(&db, &group).await?))
+}
+
+/// DELETE /api/organizations/{org_id}/groups/{group_id} (also POST .../delete)
+#[worker::send]
+pub async fn delete_group(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    headers: HeaderMap,
+    Path((org_id, group_id)): Path<(String, String)>,
+) -> Result<Json<()>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Admin,
+        &[Permission::ManageGroups],
+    )
+    .await?;
+    let group = fetch_group(&db, &org_id, &group_id).await?;
+
+    group.delete(&db).await?;
+    Membership::touch_confirmed_users(&db, &org_id, &db::now_string()).await?;
+    group_event(EventType::GroupDeleted, &group, &claims, &headers)
+        .record(&db)
+        .await;
+
+    Ok(Json(()))
+}
+
+/// DELETE /api/organizations/{org_id}/groups (body: `{"ids": [...]}`)
+#[worker::send]
+pub async fn bulk_delete_groups(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    headers: HeaderMap,
+    Path(org_id): Path<String>,
+    Json(payload): Json<BulkGroupIds>,
+) -> Result<Json<()>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Admin,
+        &[Permission::ManageGroups],
+    )
+    .await?;
+    ensure_own_groups(&db, &org_id, &payload.ids).await?;
+
+    for group_id in &payload.ids {
+        let group = fetch_group(&db, &org_id, group_id).await?;
+        group.delete(&db).await?;
+        group_event(EventType::GroupDeleted, &group, &claims, &headers)
+            .record(&db)
+            .await;
+    }
+    Membership::touch_confirmed_users(&db, &org_id, &db::now_string()).await?;
+
+    Ok(Json(()))
+}
+
+/// GET /api/organizations/{org_id}/groups/{group_id}/users - membership ids
+#[worker::send]
+pub async fn get_group_users(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path((org_id, group_id)): Path<(String, String)>,
+) -> Result<Json<Vec<String>>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Manager,
+        &[Permission::ManageGroups, Permission::ManageUsers],
+    )
+    .await?;
+    let group = fetch_group(&db, &org_id, &group_id).await?;
+    Ok(Json(Group::member_ids(&db, &group.id).await?))
+}
+
+/// PUT/POST /api/organizations/{org_id}/groups/{group_id}/users (body: membership ids)
+#[worker::send]
+pub async fn put_group_users(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    headers: HeaderMap,
+    Path((org_id, group_id)): Path<(String, String)>,
+    Json(membership_ids): Json<Vec<String>>,
+) -> Result<Json<()>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Admin,
+        &[Permission::ManageGroups],
+    )
+    .await?;
+    require_org_feature(&db, &org_id, OrgFeature::Groups).await?;
+    let group = fetch_group(&db, &org_id, &group_id).await?;
+    ensure_org_members(&db, &org_id, &membership_ids).await?;
+
+    Group::set_members(&db, &group.id, &membership_ids).await?;
+    Membership::touch_confirmed_users(&db, &org_id, &db::now_string()).await?;
+    group_event(EventType::GroupUpdated, &group, &claims, &headers)
+        .record(&db)
+        .await;
+
+    Ok(Json(()))
+}
+
+/// DELETE /api/organizations/{org_id}/groups/{group_id}/users/{member_id}
+/// (also POST .../delete-user/{member_id})
+#[worker::send]
+pub async fn delete_group_user(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    headers: HeaderMap,
+    Path((org_id, group_id, member_id)): Path<(String, String, String)>,
+) -> Result<Json<()>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Admin,
+        &[Permission::ManageGroups],
+    )
+    .await?;
+    let group = fetch_group(&db, &org_id, &group_id).await?;
+
+    Group::remove_member(&db, &group.id, &member_id).await?;
+    if let Some(membership) = Membership::find_by_id_and_org(&db, &member_id, &org_id).await? {
+        if let Some(user_id) = membership.user_id.as_deref() {
+            db::touch_user_updated_at(&db, user_id, &db::now_string()).await?;
+        }
+    }
+    group_event(EventType::GroupUpdated, &group, &claims, &headers)
+        .record(&db)
+        .await;
+
+    Ok(Json(()))
+}
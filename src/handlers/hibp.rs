@@ -0,0 +1,160 @@
+//! Proxy for the Have I Been Pwned breach lookups of the web vault's "Data breach
+//! report".
+//!
+//! The HIBP API needs a paid key, kept in the `HIBP_API_KEY` secret so clients never
+//! see it. Answers are cached in `CACHE_KV` for `HIBP_CACHE_TTL_SECONDS` (default one
+//! day), keyed by a hash of the username, and each user may look up
+//! `HIBP_REQUESTS_PER_HOUR` usernames per hour (default 10). Without a key, the report
+//! shows a single placeholder breach pointing the admin at the setting.
+
+use axum::extract::{Query, State};
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+use worker::{Env, Fetch, Method, Request, RequestInit, Url};
+
+use crate::auth::Claims;
+use crate::crypto::sha256_hex;
+use crate::error::AppError;
+use crate::handlers::ciphers::RawJson;
+use crate::handlers::get_env_usize;
+use crate::rate_limit;
+use crate::webauthn::CACHE_KV;
+
+const BREACHED_ACCOUNT_URL: &str = "https://haveibeenpwned.com/api/v3/breachedaccount/";
+const USER_AGENT: &str = "warden-worker breach report";
+/// Smallest `expiration_ttl` accepted by KV.
+const MIN_KV_TTL: u64 = 60;
+const RATE_LIMIT_WINDOW_SECS: u64 = 3600;
+
+#[derive(Deserialize)]
+pub struct BreachQuery {
+    username: String,
+}
+
+fn cache_key(username: &str) -> String {
+    format!("hibp:{}", sha256_hex(&username.to_lowercase()))
+}
+
+/// The breach shown instead of real results when no API key is configured.
+fn missing_key_breach(username: &str) -> RawJson {
+    let mut check_url = Url::parse("https://haveibeenpwned.com/account/").expect("valid URL");
+    if let Ok(mut segments) = check_url.path_segments_mut() {
+        segments.pop_if_empty().push(username);
+    }
+    RawJson(
+        json!([{
+            "Name": "HaveIBeenPwned",
+            "Title": "Manual HIBP Check",
+            "Domain": "haveibeenpwned.com",
+            "BreachDate": "2019-08-18T00:00:00Z",
+            "AddedDate": "2019-08-18T00:00:00Z",
+            "Description": format!(
+                "Go to <a href=\"{check_url}\" target=\"_blank\" rel=\"noreferrer\">\
+                 https://haveibeenpwned.com</a> to check this username manually. The server \
+                 has no HIBP_API_KEY configured to look it up automatically."
+            ),
+            "LogoPath": "https://haveibeenpwned.com/Content/Images/PwnedLogo.svg",
+            "PwnCount": 0,
+            "DataClasses": ["Error - No API key"],
+            "IsVerified": true,
+            "IsFabricated": false,
+            "IsSensitive": false,
+            "IsRetired": false,
+            "IsSpamList": false,
+        }])
+        .to_string(),
+    )
+}
+
+/// GET /api/hibp/breach?username=... - the breaches HIBP knows for `username`
+#[worker::send]
+pub async fn get_breach(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Query(query): Query<BreachQuery>,
+) -> Result<RawJson, AppError> {
+    let username = query.username.trim();
+    if username.is_empty() {
+        return Err(AppError::BadRequest("Missing username".to_string()));
+    }
+    let Some(api_key) = env
+        .secret("HIBP_API_KEY")
+        .ok()
+        .map(|v| v.to_string())
+        .filter(|v| !v.trim().is_empty())
+    else {
+        return Ok(missing_key_breach(username));
+    };
+
+    let kv = env.kv(CACHE_KV).ok();
+    let key = cache_key(username);
+    if let Some(kv) = &kv {
+        match kv.get(&key).text().await {
+            Ok(Some(cached)) => return Ok(RawJson(cached)),
+            Ok(None) => {}
+            Err(e) => log::warn!("HIBP cache lookup failed: {e}"),
+        }
+    }
+
+    let limit = get_env_usize(&env, "HIBP_REQUESTS_PER_HOUR", 10) as u32;
+    if rate_limit::kv_limit_exceeded(
+        &env,
+        &format!("hibp:{}", claims.sub),
+        limit,
+        RATE_LIMIT_WINDOW_SECS,
+    )
+    .await
+        == Some(true)
+    {
+        return Err(AppError::TooManyRequests(
+            "Too many breach lookups. Please try again later.".to_string(),
+        ));
+    }
+
+    let body = fetch_breaches(api_key.trim(), username).await?;
+
+    let ttl = get_env_usize(&env, "HIBP_CACHE_TTL_SECONDS", 86_400) as u64;
+    if let (Some(kv), true) = (&kv, ttl > 0) {
+        let stored = match kv.put(&key, body.as_str()) {
+            Ok(put) => put.expiration_ttl(ttl.max(MIN_KV_TTL)).execute().await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = stored {
+            log::warn!("HIBP cache update failed: {e}");
+        }
+    }
+    Ok(RawJson(body))
+}
+
+/// The JSON array of breaches HIBP returns for `username`, empty when it knows none.
+async fn fetch_breaches(api_key: &str, username: &str) -> Result<String, AppError> {
+    let mut url = Url::parse(BREACHED_ACCOUNT_URL).map_err(|_| AppError::Internal)?;
+    url.path_segments_mut()
+        .map_err(|_| AppError::Internal)?
+        .pop_if_empty()
+        .push(username);
+    url.query_pairs_mut()
+        .append_pair("truncateResponse", "false")
+        .append_pair("includeUnverified", "false");
+
+    let mut init = RequestInit::new();
+    init.with_method(Method::Get);
+    let mut request = Request::new_with_init(url.as_str(), &init)?;
+    let headers = request.headers_mut()?;
+    headers.set("hibp-api-key", api_key)?;
+    headers.set("User-Agent", USER_AGENT)?;
+
+    let mut response = Fetch::Request(request).send().await?;
+    match response.status_code() {
+        200 => Ok(response.text().await?),
+        404 => Ok("[]".to_string()),
+        429 => Err(AppError::TooManyRequests(
+            "Have I Been Pwned is rate limiting lookups. Please try again later.".to_string(),
+        )),
+        status => {
+            log::error!("HIBP breach lookup failed with status {status}");
+            Err(AppError::Internal)
+        }
+    }
+}
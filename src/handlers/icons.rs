@@ -0,0 +1,374 @@
+//! Website icon proxy (`/icons/{domain}/icon.png`).
+//!
+//! Like vaultwarden, the icon is looked up on the site itself: the home page is
+//! scanned for `<link rel="icon">` style tags, falling back to `/favicon.ico`.
+//! Results, including misses, are cached in the `CACHE_KV` namespace when it is
+//! bound. Set `DISABLE_ICON_DOWNLOAD` to stop all outgoing requests.
+
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use futures_util::future::{select, Either};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use worker::{
+    AbortController, Delay, Env, Fetch, Method, Request, RequestInit, RequestRedirect, Url,
+};
+
+use crate::handlers::get_env_usize;
+use crate::webauthn::CACHE_KV;
+
+/// Cache successful lookups for 30 days by default (`ICON_CACHE_TTL`, seconds).
+const DEFAULT_ICON_CACHE_TTL: usize = 30 * 24 * 60 * 60;
+/// Cache misses for 3 days by default (`ICON_CACHE_NEGTTL`, seconds).
+const DEFAULT_ICON_CACHE_NEGTTL: usize = 3 * 24 * 60 * 60;
+/// Give up on a remote request after this many seconds (`ICON_DOWNLOAD_TIMEOUT`).
+const DEFAULT_ICON_DOWNLOAD_TIMEOUT: usize = 10;
+/// KV rejects expiration TTLs below 60 seconds.
+const MIN_KV_TTL: u64 = 60;
+/// Icons larger than this are discarded.
+const MAX_ICON_BYTES: usize = 512 * 1024;
+/// Only this much of a home page is scanned for icon links.
+const MAX_HTML_BYTES: usize = 1024 * 1024;
+const MAX_REDIRECTS: usize = 5;
+const USER_AGENT: &str = "Mozilla/5.0 (compatible; warden-worker icon fetcher)";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct IconMetadata {
+    /// `None` marks a cached miss.
+    content_type: Option<String>,
+}
+
+fn icon_downloads_disabled(env: &Env) -> bool {
+    env.var("DISABLE_ICON_DOWNLOAD")
+        .ok()
+        .map(|v| v.to_string().to_lowercase())
+        .is_some_and(|v| matches!(v.as_str(), "1" | "true" | "yes" | "on"))
+}
+
+/// GET /icons/{domain}/icon.png
+#[worker::send]
+pub async fn get_icon(State(env): State<Arc<Env>>, Path(domain): Path<String>) -> Response {
+    let domain = domain.trim().trim_end_matches('.').to_ascii_lowercase();
+    if !is_valid_domain(&domain) || icon_downloads_disabled(&env) {
+        return not_found(0);
+    }
+
+    let ttl = get_env_usize(&env, "ICON_CACHE_TTL", DEFAULT_ICON_CACHE_TTL) as u64;
+    let neg_ttl = get_env_usize(&env, "ICON_CACHE_NEGTTL", DEFAULT_ICON_CACHE_NEGTTL) as u64;
+    let cache_key = format!("icon:{domain}");
+    let kv = env.kv(CACHE_KV).ok();
+
+    if let Some(kv) = &kv {
+        if let Ok((Some(bytes), Some(meta))) = kv
+            .get(&cache_key)
+            .bytes_with_metadata::<IconMetadata>()
+            .await
+        {
+            return match meta.content_type {
+                Some(content_type) => icon_response(bytes, &content_type, ttl),
+                None => not_found(neg_ttl),
+            };
+        }
+    }
+
+    let timeout = Duration::from_secs(get_env_usize(
+        &env,
+        "ICON_DOWNLOAD_TIMEOUT",
+        DEFAULT_ICON_DOWNLOAD_TIMEOUT,
+    ) as u64);
+    let icon = match download_icon(&domain, timeout).await {
+        Ok(icon) => icon,
+        Err(e) => {
+            log::debug!("Icon download for {domain} failed: {e}");
+            None
+        }
+    };
+
+    if let Some(kv) = &kv {
+        let (bytes, meta, expiration_ttl) = match &icon {
+            Some((bytes, content_type)) => (
+                bytes.clone(),
+                IconMetadata {
+                    content_type: Some(content_type.to_string()),
+                },
+                ttl,
+            ),
+            None => (Vec::new(), IconMetadata { content_type: None }, neg_ttl),
+        };
+        if expiration_ttl > 0 {
+            let stored = match kv.put_bytes(&cache_key, &bytes) {
+                Ok(put) => match put.metadata(meta) {
+                    Ok(put) => {
+                        put.expiration_ttl(expiration_ttl.max(MIN_KV_TTL))
+                            .execute()
+                            .await
+                    }
+                    Err(e) => Err(e),
+                },
+                Err(e) => Err(e),
+            };
+            if let Err(e) = stored {
+                log::warn!("Caching icon for {domain} failed: {e}");
+            }
+        }
+    }
+
+    match icon {
+        Some((bytes, content_type)) => icon_response(bytes, content_type, ttl),
+        None => not_found(neg_ttl),
+    }
+}
+
+fn icon_response(bytes: Vec<u8>, content_type: &str, ttl: u64) -> Response {
+    (
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CACHE_CONTROL, format!("public, max-age={ttl}")),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+        ],
+        bytes,
+    )
+        .into_response()
+}
+
+fn not_found(ttl: u64) -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        [(header::CACHE_CONTROL, format!("public, max-age={ttl}"))],
+    )
+        .into_response()
+}
+
+/// Accept plain public host names only: no IP literals, ports or local names.
+fn is_valid_domain(domain: &str) -> bool {
+    if domain.is_empty() || domain.len() > 253 || !domain.contains('.') {
+        return false;
+    }
+    if domain.parse::<std::net::IpAddr>().is_ok() {
+        return false;
+    }
+    if domain == "localhost"
+        || [".localhost", ".local", ".internal", ".lan", ".home.arpa"]
+            .iter()
+            .any(|suffix| domain.ends_with(suffix))
+    {
+        return false;
+    }
+    // A numeric top-level label means an IPv4 address in some notation.
+    if domain
+        .rsplit('.')
+        .next()
+        .is_some_and(|tld| tld.chars().all(|c| c.is_ascii_digit()))
+    {
+        return false;
+    }
+    domain.split('.').all(|label| {
+        !label.is_empty()
+            && label.len() <= 63
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    })
+}
+
+/// Find and download the icon for `domain`. `Ok(None)` means the site has no usable icon.
+async fn download_icon(
+    domain: &str,
+    timeout: Duration,
+) -> Result<Option<(Vec<u8>, &'static str)>, worker::Error> {
+    let mut candidates = Vec::new();
+    if let Some((base, html)) = fetch_home_page(domain, timeout).await? {
+        candidates = icon_links(&base, &html);
+    }
+    if let Ok(favicon) = Url::parse(&format!("https://{domain}/favicon.ico")) {
+        candidates.push(favicon);
+    }
+
+    for url in candidates {
+        let Some((response_url, mut response)) = fetch(url, timeout).await? else {
+            continue;
+        };
+        if !is_success(response.status_code()) || too_large(&response, MAX_ICON_BYTES) {
+            continue;
+        }
+        let bytes = response.bytes().await?;
+        if bytes.len() > MAX_ICON_BYTES {
+            continue;
+        }
+        if let Some(content_type) = sniff_image_type(&bytes) {
+            return Ok(Some((bytes, content_type)));
+        }
+        log::debug!("Ignoring non-image icon candidate {response_url}");
+    }
+    Ok(None)
+}
+
+async fn fetch_home_page(
+    domain: &str,
+    timeout: Duration,
+) -> Result<Option<(Url, String)>, worker::Error> {
+    let url = Url::parse(&format!("https://{domain}/"))?;
+    let Some((base, mut response)) = fetch(url, timeout).await? else {
+        return Ok(None);
+    };
+    let is_html = response
+        .headers()
+        .get("content-type")?
+        .is_some_and(|ct| ct.to_ascii_lowercase().contains("html"));
+    if !is_success(response.status_code()) || !is_html || too_large(&response, MAX_HTML_BYTES) {
+        return Ok(None);
+    }
+    let mut html = response.text().await?;
+    if html.len() > MAX_HTML_BYTES {
+        let mut end = MAX_HTML_BYTES;
+        while !html.is_char_boundary(end) {
+            end -= 1;
+        }
+        html.truncate(end);
+    }
+    Ok(Some((base, html)))
+}
+
+/// GET `url`, following redirects to public hosts only. Returns the final URL and response.
+async fn fetch(
+    mut url: Url,
+    timeout: Duration,
+) -> Result<Option<(Url, worker::Response)>, worker::Error> {
+    for _ in 0..=MAX_REDIRECTS {
+        if !matches!(url.scheme(), "http" | "https")
+            || url.port().is_some()
+            || !url.host_str().is_some_and(is_valid_domain)
+        {
+            return Ok(None);
+        }
+
+        let mut init = RequestInit::new();
+        init.with_method(Method::Get)
+            .with_redirect(RequestRedirect::Manual);
+        let mut request = Request::new_with_init(url.as_str(), &init)?;
+        request.headers_mut()?.set("User-Agent", USER_AGENT)?;
+
+        let controller = AbortController::default();
+        let signal = controller.signal();
+        let fetcher = Fetch::Request(request);
+        let send = fetcher.send_with_signal(&signal);
+        let response = match select(Box::pin(send), Delay::from(timeout)).await {
+            Either::Left((response, _)) => response?,
+            Either::Right(_) => {
+                controller.abort();
+                return Ok(None);
+            }
+        };
+
+        if !(300..400).contains(&response.status_code()) {
+            return Ok(Some((url, response)));
+        }
+        let Some(location) = response.headers().get("location")? else {
+            return Ok(None);
+        };
+        url = match url.join(&location) {
+            Ok(next) => next,
+            Err(_) => return Ok(None),
+        };
+    }
+    Ok(None)
+}
+
+fn is_success(status: u16) -> bool {
+    (200..300).contains(&status)
+}
+
+fn too_large(response: &worker::Response, limit: usize) -> bool {
+    response
+        .headers()
+        .get("content-length")
+        .ok()
+        .flatten()
+        .and_then(|len| len.parse::<usize>().ok())
+        .is_some_and(|len| len > limit)
+}
+
+/// Icon URLs declared in `html`, best candidates first.
+fn icon_links(base: &Url, html: &str) -> Vec<Url> {
+    let lower = html.to_ascii_lowercase();
+    let mut found: Vec<(u8, Url)> = Vec::new();
+
+    let mut rest = 0;
+    while let Some(start) = lower[rest..].find("<link") {
+        let start = rest + start;
+        let end = lower[start..].find('>').map_or(lower.len(), |e| start + e);
+        let tag = &html[start..end];
+        rest = end;
+
+        let Some(rel) = attribute(tag, "rel").map(|r| r.to_ascii_lowercase()) else {
+            continue;
+        };
+        let rels: Vec<&str> = rel.split_whitespace().collect();
+        let priority = if rels.contains(&"icon") {
+            match attribute(tag, "sizes").as_deref() {
+                Some("32x32") | Some("16x16") => 0,
+                _ => 1,
+            }
+        } else if rels.contains(&"apple-touch-icon") {
+            2
+        } else {
+            continue;
+        };
+
+        if let Some(href) = attribute(tag, "href") {
+            if let Ok(url) = base.join(href.trim()) {
+                found.push((priority, url));
+            }
+        }
+    }
+
+    found.sort_by_key(|(priority, _)| *priority);
+    found.into_iter().map(|(_, url)| url).take(5).collect()
+}
+
+/// Value of attribute `name` in an HTML tag, quoted or not.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase();
+    let mut from = 0;
+    while let Some(pos) = lower[from..].find(name) {
+        let pos = from + pos;
+        from = pos + name.len();
+        let preceded_ok = lower[..pos]
+            .chars()
+            .last()
+            .is_some_and(|c| c.is_ascii_whitespace());
+        let after = lower[from..].trim_start();
+        if !preceded_ok || !after.starts_with('=') {
+            continue;
+        }
+        let value_start = tag.len() - after.len() + 1;
+        let value = tag[value_start..].trim_start();
+        return Some(match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => value[1..].split(quote).next().unwrap_or("").to_string(),
+            _ => value
+                .split(|c: char| c.is_ascii_whitespace())
+                .next()
+                .unwrap_or("")
+                .to_string(),
+        });
+    }
+    None
+}
+
+/// Detect the image format from its magic bytes. SVG is rejected as it can carry scripts.
+fn sniff_image_type(bytes: &[u8]) -> Option<&'static str> {
+    match bytes {
+        [0x89, b'P', b'N', b'G', ..] => Some("image/png"),
+        [0x00, 0x00, 0x01, 0x00, ..] => Some("image/x-icon"),
+        [0xFF, 0xD8, 0xFF, ..] => Some("image/jpeg"),
+        [b'G', b'I', b'F', b'8', ..] => Some("image/gif"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+        [b'B', b'M', ..] => Some("image/bmp"),
+        _ => None,
+    }
+}
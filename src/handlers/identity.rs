@@ -1,4 +1,4 @@
-use axum::{extract::State, http::HeaderMap, Form, Json};
+use axum::{extract::State, http::HeaderMap, Extension, Form, Json};
 use chrono::{Duration, Utc};
 use constant_time_eq::constant_time_eq;
 use jwt_compact::AlgorithmExt;
@@ -12,23 +12,42 @@ use crate::d1_query;
 use crate::{
     auth::{jwt_time_options, Claims},
     client_context::{parse_required_device_type, request_ip_from_headers},
-    crypto::{ct_eq, generate_salt, hash_password_for_storage, validate_totp},
+    crypto::{ct_eq, generate_salt, hash_password_for_storage, sha256_hex, validate_totp},
     db,
+    duo::{self, DuoConfig},
     error::AppError,
     handlers::{
-        allow_totp_drift, server_password_iterations,
-        twofactor::{is_twofactor_enabled, list_user_twofactors},
+        self, accounts, allow_totp_drift, get_env_bool, get_env_usize,
+        policies::{master_password_policy_json, max_vault_timeout_minutes},
+        server_password_iterations,
+        twofactor::{
+            email_login_challenge, enabled_twofactor_providers, list_user_twofactors,
+            recovery_code_matches, reset_twofactor, verify_email_login, yubikey_metadata,
+        },
     },
+    mail,
+    metrics::{self, Metric},
     models::{
         auth_request::AuthRequest,
         device::{Device, DeviceType},
-        twofactor::{TwoFactor, TwoFactorType},
+        event::{Event, EventActor},
+        passkey::Passkey,
+        refresh_token::{RefreshToken, RefreshTokenCheck, SessionLimits},
+        sso_user::SsoUser,
+        twofactor::{OrgTwoFactor, TwoFactor, TwoFactorType},
         user::User,
     },
     push,
+    rate_limit::LoginBackoff,
+    sso::{self, SsoConfig},
+    turnstile,
+    webauthn::{self, RelyingParty, WebauthnCredential},
+    yubikey::{self, YubicoConfig},
+    BaseUrl,
 };
 
 const PASSWORD_SCOPE: &str = "api offline_access";
+const API_KEY_SCOPE: &str = "api";
 const REMEMBER_TOKEN_ISSUER: &str = "warden-worker-device-remember";
 
 /// Deserialize an Option<i32> that may have trailing/leading whitespace.
@@ -63,9 +82,19 @@ pub struct TokenRequest {
     refresh_token: Option<String>,
     #[serde(rename = "client_id", alias = "clientId")]
     client_id: Option<String>,
+    #[serde(rename = "client_secret", alias = "clientSecret")]
+    client_secret: Option<String>,
     scope: Option<String>,
     #[serde(rename = "authrequest", alias = "authRequest")]
     auth_request: Option<String>,
+    // Passkey login: the ceremony token and the JSON-encoded assertion
+    token: Option<String>,
+    #[serde(rename = "deviceResponse", alias = "device_response")]
+    device_response: Option<String>,
+    // SSO login: the code from the SSO callback and the client's PKCE verifier
+    code: Option<String>,
+    code_verifier: Option<String>,
+    redirect_uri: Option<String>,
     // 2FA fields
     #[serde(rename = "twoFactorToken")]
     two_factor_token: Option<String>,
@@ -81,6 +110,12 @@ pub struct TokenRequest {
         deserialize_with = "deserialize_trimmed_i32"
     )]
     two_factor_remember: Option<i32>,
+    /// Turnstile token, or the bypass token of a login that passed the challenge.
+    #[serde(rename = "captchaResponse", alias = "captcha_response")]
+    captcha_response: Option<String>,
+    /// Code emailed to confirm a login from an unknown device.
+    #[serde(rename = "newDeviceOtp", alias = "new_device_otp")]
+    new_device_otp: Option<String>,
     #[serde(rename = "device_identifier", alias = "deviceIdentifier")]
     device_identifier: Option<String>,
     #[serde(rename = "device_name", alias = "deviceName")]
@@ -103,6 +138,8 @@ struct PasswordGrantAuthContext {
     device_request: DeviceAuthRequest,
     password_hash: Option<String>,
     needs_migration: bool,
+    /// The approved auth request of a "login with device" grant.
+    auth_request: Option<AuthRequest>,
 }
 
 #[derive(Debug, Serialize)]
@@ -138,6 +175,8 @@ pub struct TokenResponse {
     user_decryption_options: UserDecryptionOptions,
     #[serde(rename = "AccountKeys")]
     account_keys: serde_json::Value,
+    #[serde(rename = "MasterPasswordPolicy")]
+    master_password_policy: Value,
     #[serde(rename = "TwoFactorToken", skip_serializing_if = "Option::is_none")]
     two_factor_token: Option<String>,
 }
@@ -148,6 +187,8 @@ pub struct UserDecryptionOptions {
     pub has_master_password: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub master_password_unlock: Option<serde_json::Value>,
+    #[serde(rename = "WebAuthnPrfOption", skip_serializing_if = "Option::is_none")]
+    pub web_authn_prf_option: Option<serde_json::Value>,
     pub object: String,
 }
 
@@ -155,11 +196,15 @@ pub struct UserDecryptionOptions {
 #[serde(rename_all = "snake_case")]
 enum RefreshAuthMethod {
     Password,
+    UserApiKey,
 }
 
 impl RefreshAuthMethod {
     fn scope(self) -> &'static str {
-        PASSWORD_SCOPE
+        match self {
+            RefreshAuthMethod::Password => PASSWORD_SCOPE,
+            RefreshAuthMethod::UserApiKey => API_KEY_SCOPE,
+        }
     }
 
     fn scope_vec(self) -> Vec<String> {
@@ -199,10 +244,10 @@ fn optional_field(value: Option<&str>) -> Option<String> {
         .map(str::to_owned)
 }
 
-fn validate_password_scope(value: Option<&str>, required: bool) -> Result<(), AppError> {
+fn validate_scope(value: Option<&str>, expected: &str, required: bool) -> Result<(), AppError> {
     let scope = optional_field(value);
     match scope {
-        Some(scope) if scope == PASSWORD_SCOPE => Ok(()),
+        Some(scope) if scope == expected => Ok(()),
         Some(scope) => Err(AppError::BadRequest(format!("Unsupported scope: {scope}"))),
         None if required => Err(AppError::BadRequest("Missing scope".to_string())),
         None => Ok(()),
@@ -210,8 +255,11 @@ fn validate_password_scope(value: Option<&str>, required: bool) -> Result<(), Ap
 }
 
 fn parse_password_device_request(payload: &TokenRequest) -> Result<DeviceAuthRequest, AppError> {
-    validate_password_scope(payload.scope.as_deref(), true)?;
+    validate_scope(payload.scope.as_deref(), PASSWORD_SCOPE, true)?;
+    parse_device_request(payload)
+}
 
+fn parse_device_request(payload: &TokenRequest) -> Result<DeviceAuthRequest, AppError> {
     Ok(DeviceAuthRequest {
         client_id: required_field(payload.client_id.as_deref(), "client_id")?,
         identifier: required_field(payload.device_identifier.as_deref(), "device_identifier")?,
@@ -220,6 +268,91 @@ fn parse_password_device_request(payload: &TokenRequest) -> Result<DeviceAuthReq
     })
 }
 
+/// Authenticate a `client_credentials` grant: `client_id` is `user.<user id>` and
+/// `client_secret` is the user's personal API key.
+async fn authenticate_api_key_grant(
+    db: &crate::db::Db,
+    payload: &TokenRequest,
+) -> Result<(User, DeviceAuthRequest), AppError> {
+    validate_scope(payload.scope.as_deref(), API_KEY_SCOPE, true)?;
+    let device_request = parse_device_request(payload)?;
+    let client_secret = required_field(payload.client_secret.as_deref(), "client_secret")?;
+    let invalid_client = || AppError::Unauthorized("invalid_client".to_string());
+
+    let user_id = device_request
+        .client_id
+        .strip_prefix("user.")
+        .ok_or_else(invalid_client)?;
+    let user_value: Option<Value> = d1_query!(db, "SELECT * FROM users WHERE id = ?1", user_id)
+        .map_err(|_| AppError::Database)?
+        .first(None)
+        .await
+        .map_err(|_| AppError::Database)?;
+    let user: User = user_value
+        .map(serde_json::from_value)
+        .transpose()
+        .map_err(|_| AppError::Internal)?
+        .ok_or_else(invalid_client)?;
+
+    let valid = user
+        .api_key
+        .as_deref()
+        .is_some_and(|api_key| ct_eq(api_key, &client_secret));
+    if !valid {
+        return Err(invalid_client());
+    }
+
+    Ok((user, device_request))
+}
+
+/// Resolve the user of an SSO login: the account linked to the identity, or else the
+/// account with its email, which is linked from then on. SSO does not create accounts.
+async fn authenticate_sso_grant(
+    env: &Env,
+    db: &crate::db::Db,
+    payload: &TokenRequest,
+) -> Result<User, AppError> {
+    let identity = sso::redeem_code(
+        env,
+        &required_field(payload.code.as_deref(), "code")?,
+        &required_field(payload.code_verifier.as_deref(), "code_verifier")?,
+        &required_field(payload.redirect_uri.as_deref(), "redirect_uri")?,
+    )?;
+    if let Some(link) = SsoUser::find_by_identifier(db, &identity.identifier).await? {
+        return load_user_by_id(db, &link.user_id).await;
+    }
+
+    // Linking by email hands the account to whoever controls the address at the
+    // provider, so it must vouch for it. `SSO_ALLOW_UNVERIFIED_EMAIL` trusts providers
+    // that leave out the `email_verified` claim.
+    let verified = match identity.email_verified {
+        Some(verified) => verified,
+        None => get_env_bool(env, "SSO_ALLOW_UNVERIFIED_EMAIL", false),
+    };
+    let email = identity.email.filter(|_| verified).ok_or_else(|| {
+        AppError::BadRequest(
+            "The SSO identity provider did not return a verified email".to_string(),
+        )
+    })?;
+    let user = User::find_by_email(db, &email.trim().to_lowercase())
+        .await?
+        .ok_or_else(|| {
+            AppError::BadRequest(
+                "No account exists for this email; create one before logging in with SSO"
+                    .to_string(),
+            )
+        })?;
+    if SsoUser::find_by_user(db, &user.id).await?.is_some() {
+        return Err(AppError::BadRequest(
+            "This account is linked to another SSO identity".to_string(),
+        ));
+    }
+    SsoUser::new(user.id.clone(), identity.identifier)
+        .insert(db)
+        .await?;
+    Ok(user)
+}
+
 async fn authenticate_password_grant(
     db: &crate::db::Db,
     headers: &HeaderMap,
@@ -245,6 +378,7 @@ async fn authenticate_password_grant(
 
         if !auth_request.is_approved()
             || auth_request.is_expired()
+            || auth_request.is_used()
             || auth_request.request_ip != request_ip_from_headers(headers)
             || auth_request.request_device_identifier != device_request.identifier
             || auth_request.device_type != device_request.r#type
@@ -254,12 +388,12 @@ async fn authenticate_password_grant(
                 "Username or access code is incorrect. Try again".to_string(),
             ));
         }
-
         return Ok(PasswordGrantAuthContext {
             user,
             device_request,
             password_hash: None,
             needs_migration: false,
+            auth_request: Some(auth_request),
         });
     }
 
@@ -273,9 +407,78 @@ async fn authenticate_password_grant(
         device_request,
         password_hash: Some(password_hash),
         needs_migration: verification.needs_migration(),
+        auth_request: None,
     })
 }
 
+/// Require the emailed code for a login from a device the account has not used yet.
+/// The first device of an account is trusted, there being nothing to compare it with.
+async fn verify_new_device(
+    env: &Env,
+    db: &crate::db::Db,
+    user: &User,
+    device_identifier: &str,
+    otp: Option<&str>,
+) -> Result<(), AppError> {
+    if Device::find_by_identifier_and_user(db, device_identifier, &user.id)
+        .await?
+        .is_some()
+        || Device::list_by_user(db, &user.id).await?.is_empty()
+    {
+        return Ok(());
+    }
+    match otp.map(str::trim).filter(|otp| !otp.is_empty()) {
+        Some(otp) => accounts::verify_new_device_otp(db, &user.id, otp).await,
+        None => {
+            accounts::send_new_device_otp(env, db, user).await?;
+            Err(AppError::NewDeviceVerificationRequired)
+        }
+    }
+}
+
+/// Count a failed login (wrong password or two-step code) against the account and the
+/// client address, and warn the account owner when the failures reach
+/// `LOGIN_FAILURE_ALERT_THRESHOLD`.
+async fn record_failed_login(
+    env: &Env,
+    db: &crate::db::Db,
+    backoff: &LoginBackoff,
+    email: &str,
+    ip: &str,
+) {
+    backoff.record_failure(env, &format!("ip:{ip}")).await;
+    let failures = backoff.record_failure(env, &format!("email:{email}")).await;
+
+    let threshold = get_env_usize(env, "LOGIN_FAILURE_ALERT_THRESHOLD", 0) as u32;
+    if threshold == 0 || failures != threshold {
+        return;
+    }
+    match User::find_by_email(db, email).await {
+        Ok(Some(user)) => mail::send_in_background(
+            env.clone(),
+            user.email,
+            mail::Template::FailedLogins { failures, ip },
+        ),
+        Ok(None) => {}
+        Err(e) => log::warn!("Failed login alert lookup failed: {e}"),
+    }
+}
+
+/// Refuse logins to accounts that are scheduled for deletion or disabled by an admin.
+fn ensure_account_active(user: &User) -> Result<(), AppError> {
+    if user.deletion_requested_at.is_some() {
+        return Err(AppError::Unauthorized(
+            "This account has been deleted".to_string(),
+        ));
+    }
+    if user.disabled {
+        return Err(AppError::Unauthorized(
+            "This account has been disabled".to_string(),
+        ));
+    }
+    Ok(())
+}
+
 async fn load_user_by_id(db: &crate::db::Db, user_id: &str) -> Result<User, AppError> {
     let user_value: Option<Value> = db
         .prepare("SELECT * FROM users WHERE id = ?1")
@@ -330,7 +533,39 @@ async fn maybe_upgrade_password_hash(
     })
 }
 
-fn generate_remember_token(env: &Env, user: &User, device: &Device) -> Result<String, AppError> {
+/// Refresh the push registration of a device that logs in again.
+async fn reregister_push_device(env: &Env, db: &crate::db::Db, device: &mut Device) {
+    if device.push_token.is_none() || !device.is_push_device() {
+        return;
+    }
+    if let Ok(Some(cfg)) = push::push_config(env) {
+        match push::register_push_device(&cfg, device).await {
+            Ok(push_uuid_created) => {
+                if push_uuid_created {
+                    if let Err(e) = device.persist_push_uuid(db).await {
+                        log::warn!("Push uuid persistence on login failed: {e}");
+                    }
+                }
+            }
+            Err(e) => {
+                log::warn!("Push re-registration on login failed: {e}");
+            }
+        }
+    }
+}
+
+/// Days a remember-device token skips the 2FA challenge, from `TWOFACTOR_REMEMBER_DAYS`
+/// (default 30). 0 turns the "remember me" option off.
+fn twofactor_remember_days(env: &Env) -> i64 {
+    get_env_usize(env, "TWOFACTOR_REMEMBER_DAYS", 30) as i64
+}
+
+fn generate_remember_token(
+    env: &Env,
+    user: &User,
+    device: &Device,
+    days: i64,
+) -> Result<String, AppError> {
     let now = Utc::now();
     let time_options = jwt_time_options();
     let claims = JwtClaims::new(RememberJwtClaims {
@@ -338,7 +573,7 @@ fn generate_remember_token(env: &Env, user: &User, device: &Device) -> Result<St
         user_uuid: user.id.clone(),
         iss: REMEMBER_TOKEN_ISSUER.to_string(),
     })
-    .set_duration_and_issuance(&time_options, Duration::days(30))
+    .set_duration_and_issuance(&time_options, Duration::days(days))
     .set_not_before(now);
 
     let secret = env.secret("JWT_REFRESH_SECRET")?.to_string();
@@ -348,65 +583,84 @@ fn generate_remember_token(env: &Env, user: &User, device: &Device) -> Result<St
         .map_err(|_| AppError::Crypto("Failed to create remember token".to_string()))
 }
 
+/// Whether `raw_token` is a valid remember-device token for this user and device.
+///
+/// The device keeps only a hash of the token it was last issued.
 fn validate_remember_token(
     env: &Env,
     user: &User,
     device: &Device,
     raw_token: &str,
-    twofactor_ids: &[i32],
-) -> Result<(), AppError> {
+) -> Result<bool, AppError> {
     let secret = env.secret("JWT_REFRESH_SECRET")?.to_string();
     let key = Hs256Key::new(secret.as_bytes());
-    let token = UntrustedToken::new(raw_token)
-        .map_err(|_| AppError::TwoFactorRequired(json_err_twofactor(twofactor_ids)))?;
-    let token = jwt_compact::alg::Hs256
+    let Ok(token) = UntrustedToken::new(raw_token) else {
+        return Ok(false);
+    };
+    let Ok(token) = jwt_compact::alg::Hs256
         .validator::<RememberJwtClaims>(&key)
         .validate(&token)
-        .map_err(|_| AppError::TwoFactorRequired(json_err_twofactor(twofactor_ids)))?;
+    else {
+        return Ok(false);
+    };
     let time_options = jwt_time_options();
-    token
-        .claims()
-        .validate_expiration(&time_options)
-        .map_err(|_| AppError::TwoFactorRequired(json_err_twofactor(twofactor_ids)))?;
-    token
-        .claims()
-        .validate_maturity(&time_options)
-        .map_err(|_| AppError::TwoFactorRequired(json_err_twofactor(twofactor_ids)))?;
+    if token.claims().validate_expiration(&time_options).is_err()
+        || token.claims().validate_maturity(&time_options).is_err()
+    {
+        return Ok(false);
+    }
 
     let remember_claims = token.into_parts().1.custom;
     if remember_claims.iss != REMEMBER_TOKEN_ISSUER
         || remember_claims.sub.as_str() != device.identifier.as_str()
         || remember_claims.user_uuid.as_str() != user.id.as_str()
     {
-        return Err(AppError::TwoFactorRequired(json_err_twofactor(
-            twofactor_ids,
-        )));
+        return Ok(false);
     }
 
-    let stored_token = device
+    Ok(device
         .twofactor_remember
         .as_deref()
-        .ok_or_else(|| AppError::TwoFactorRequired(json_err_twofactor(twofactor_ids)))?;
-    if !constant_time_eq(stored_token.as_bytes(), raw_token.as_bytes()) {
-        return Err(AppError::TwoFactorRequired(json_err_twofactor(
-            twofactor_ids,
-        )));
-    }
+        .is_some_and(|stored| ct_eq(stored, &sha256_hex(raw_token))))
+}
 
-    Ok(())
+/// How long an access token is valid.
+const ACCESS_TOKEN_LIFETIME_HOURS: i64 = 1;
+
+/// How long the sessions of `user_id` may last. Refresh tokens expire after the
+/// shortest vault timeout the user's organizations allow, so that a client left
+/// alone for longer has to log in again, and sessions end after
+/// `SESSION_MAX_AGE_HOURS` (default 0, unlimited). Clients only refresh when their
+/// access token runs out, so the timeout never shortens tokens below its lifetime.
+async fn session_limits(env: &Env, db: &db::Db, user_id: &str) -> Result<SessionLimits, AppError> {
+    let token_lifetime = max_vault_timeout_minutes(db, user_id)
+        .await?
+        .map(|minutes| {
+            Duration::minutes(minutes).max(Duration::hours(ACCESS_TOKEN_LIFETIME_HOURS))
+        });
+    let max_age_hours = get_env_usize(env, "SESSION_MAX_AGE_HOURS", 0) as i64;
+    Ok(SessionLimits {
+        token_lifetime,
+        max_age: (max_age_hours > 0).then(|| Duration::hours(max_age_hours)),
+    })
 }
 
-fn generate_tokens_and_response(
+/// Issue an access token and a rotating refresh token.
+///
+/// `previous` is the refresh token being rotated, whose family (login session) the
+/// new token continues; `None` starts a new one for a fresh login.
+async fn generate_tokens_and_response(
     user: User,
     device: &Device,
     client_id: &str,
     env: &Arc<Env>,
     two_factor_token: Option<String>,
+    previous: Option<&RefreshToken>,
+    auth_method: RefreshAuthMethod,
 ) -> Result<Json<TokenResponse>, AppError> {
     let now = Utc::now();
-    let expires_in = Duration::hours(1);
+    let expires_in = Duration::hours(ACCESS_TOKEN_LIFETIME_HOURS);
     let time_options = jwt_time_options();
-    let auth_method = RefreshAuthMethod::Password;
 
     let access_claims = JwtClaims::new(Claims {
         sub: user.id.clone(),
@@ -432,12 +686,16 @@ fn generate_tokens_and_response(
         .token(&Header::empty(), &access_claims, &access_key)
         .map_err(|_| AppError::Crypto("Failed to create access token".to_string()))?;
 
+    let db = db::get_db(env)?;
+    let limits = session_limits(env, &db, &user.id).await?;
+    let (stored_refresh_token, raw_refresh_token) =
+        RefreshToken::issue(&db, &user.id, &device.identifier, previous, limits).await?;
     let refresh_claims = JwtClaims::new(RefreshClaims {
         sub: auth_method,
-        device_token: device.refresh_token.clone(),
+        device_token: raw_refresh_token,
         sstamp: user.security_stamp.clone(),
     })
-    .set_duration_and_issuance(&time_options, Duration::days(30))
+    .set_duration_and_issuance(&time_options, stored_refresh_token.expires_in(now))
     .set_not_before(now);
     let jwt_refresh_secret = env.secret("JWT_REFRESH_SECRET")?.to_string();
     let refresh_key = Hs256Key::new(jwt_refresh_secret.as_bytes());
@@ -472,6 +730,7 @@ fn generate_tokens_and_response(
         },
         "Object": "privateKeys"
     });
+    let master_password_policy = master_password_policy_json(&db, &user.id).await?;
 
     Ok(Json(TokenResponse {
         access_token,
@@ -485,14 +744,16 @@ fn generate_tokens_and_response(
         kdf_iterations: user.kdf_iterations,
         kdf_memory: user.kdf_memory,
         kdf_parallelism: user.kdf_parallelism,
-        force_password_reset: false,
+        force_password_reset: user.force_password_reset,
         reset_master_password: false,
         user_decryption_options: UserDecryptionOptions {
             has_master_password,
             master_password_unlock,
+            web_authn_prf_option: None,
             object: "userDecryptionOptions".to_string(),
         },
         account_keys,
+        master_password_policy,
         two_factor_token,
     }))
 }
@@ -500,8 +761,28 @@ fn generate_tokens_and_response(
 #[worker::send]
 pub async fn token(
     State(env): State<Arc<Env>>,
+    Extension(BaseUrl(base_url)): Extension<BaseUrl>,
     headers: HeaderMap,
     Form(payload): Form<TokenRequest>,
+) -> Result<Json<TokenResponse>, AppError> {
+    let grant_type = payload.grant_type.clone();
+    let result = grant_token(env, base_url, headers, payload).await;
+    let outcome = match &result {
+        Ok(_) => "success",
+        Err(AppError::TwoFactorRequired(_)) => "two_factor_required",
+        Err(AppError::TooManyRequests(_)) => "rate_limited",
+        Err(AppError::Unauthorized(_) | AppError::BadRequest(_)) => "failure",
+        Err(_) => "error",
+    };
+    metrics::count(Metric::Login, &[&grant_type, outcome]);
+    result
+}
+
+async fn grant_token(
+    env: Arc<Env>,
+    base_url: String,
+    headers: HeaderMap,
+    payload: TokenRequest,
 ) -> Result<Json<TokenResponse>, AppError> {
     let db = db::get_db(&env)?;
 
@@ -521,14 +802,56 @@ pub async fn token(
                 }
             }
 
+            // Back off after repeated failures for this account or from this address.
+            let email = username.to_lowercase();
+            let ip = request_ip_from_headers(&headers);
+            let backoff = LoginBackoff::from_env(&env);
+            let retry_after = backoff
+                .retry_after(&env, &format!("email:{email}"))
+                .await
+                .max(backoff.retry_after(&env, &format!("ip:{ip}")).await);
+            if retry_after > 0 {
+                return Err(AppError::TooManyRequests(format!(
+                    "Too many failed login attempts. Please try again in {retry_after} seconds."
+                )));
+            }
+            turnstile::verify(&env, payload.captcha_response.as_deref(), Some(&email), &ip).await?;
+
             let PasswordGrantAuthContext {
-                user,
+                mut user,
                 device_request,
                 password_hash,
                 needs_migration,
-            } = authenticate_password_grant(&db, &headers, &payload, &username).await?;
+                auth_request,
+            } = match authenticate_password_grant(&db, &headers, &payload, &username).await {
+                Err(AppError::Unauthorized(message)) => {
+                    record_failed_login(&env, &db, &backoff, &email, &ip).await;
+                    return Err(AppError::Unauthorized(message));
+                }
+                result => result?,
+            };
+            ensure_account_active(&user)?;
+            accounts::ensure_email_verified(&env, &base_url, &user).await?;
+
+            let (twofactors, twofactor_ids) = login_twofactors(&db, &user.id).await?;
+
+            // Two-step login and approved device logins already prove more than an
+            // emailed code would.
+            if twofactor_ids.is_empty()
+                && auth_request.is_none()
+                && accounts::new_device_verification_enabled(&env)
+            {
+                verify_new_device(
+                    &env,
+                    &db,
+                    &user,
+                    &device_request.identifier,
+                    payload.new_device_otp.as_deref(),
+                )
+                .await?;
+            }
 
-            let mut device = Device::get_or_create(
+            let (device, new_device) = Device::find_or_build(
                 &db,
                 device_request.identifier,
                 user.id.clone(),
@@ -536,99 +859,30 @@ pub async fn token(
                 device_request.r#type,
             )
             .await?;
-
-            let twofactors: Vec<TwoFactor> = list_user_twofactors(&db, &user.id).await?;
-            let twofactor_ids = vec![TwoFactorType::Authenticator as i32];
-            let mut should_issue_remember = false;
-
-            if is_twofactor_enabled(&twofactors) {
-                let selected_id = payload.two_factor_provider.unwrap_or(twofactor_ids[0]);
-                let twofactor_code = payload.two_factor_token.as_deref().ok_or_else(|| {
-                    AppError::TwoFactorRequired(json_err_twofactor(&twofactor_ids))
-                })?;
-
-                match TwoFactorType::from_i32(selected_id) {
-                    Some(TwoFactorType::Authenticator) => {
-                        let tf = twofactors
-                            .iter()
-                            .find(|tf| {
-                                tf.enabled && tf.atype == TwoFactorType::Authenticator as i32
-                            })
-                            .ok_or_else(|| {
-                                AppError::BadRequest("TOTP not configured".to_string())
-                            })?;
-
-                        let allow_drift = allow_totp_drift(&env);
-                        let new_last_used =
-                            validate_totp(twofactor_code, &tf.data, tf.last_used, allow_drift)
-                                .await?;
-
-                        d1_query!(
-                            &db,
-                            "UPDATE twofactor SET last_used = ?1 WHERE uuid = ?2",
-                            new_last_used,
-                            &tf.uuid
-                        )
-                        .map_err(|_| AppError::Database)?
-                        .run()
-                        .await
-                        .map_err(|_| AppError::Database)?;
-
-                        should_issue_remember = payload.two_factor_remember == Some(1);
-                    }
-                    Some(TwoFactorType::Remember) => {
-                        validate_remember_token(
-                            env.as_ref(),
-                            &user,
-                            &device,
-                            twofactor_code,
-                            &twofactor_ids,
-                        )?;
-                        should_issue_remember = payload.two_factor_remember == Some(1);
-                    }
-                    Some(TwoFactorType::RecoveryCode) => {
-                        if let Some(ref stored_code) = user.totp_recover {
-                            if !ct_eq(&stored_code.to_uppercase(), &twofactor_code.to_uppercase()) {
-                                return Err(AppError::BadRequest(
-                                    "Recovery code is incorrect".to_string(),
-                                ));
-                            }
-
-                            d1_query!(&db, "DELETE FROM twofactor WHERE user_uuid = ?1", &user.id)
-                                .map_err(|_| AppError::Database)?
-                                .run()
-                                .await
-                                .map_err(|_| AppError::Database)?;
-                            d1_query!(
-                                &db,
-                                "UPDATE users SET totp_recover = NULL WHERE id = ?1",
-                                &user.id
-                            )
-                            .map_err(|_| AppError::Database)?
-                            .run()
-                            .await
-                            .map_err(|_| AppError::Database)?;
-                            d1_query!(
-                                &db,
-                                "UPDATE devices SET twofactor_remember = NULL WHERE user_id = ?1",
-                                &user.id
-                            )
-                            .map_err(|_| AppError::Database)?
-                            .run()
-                            .await
-                            .map_err(|_| AppError::Database)?;
-                        } else {
-                            return Err(AppError::BadRequest(
-                                "Recovery code is incorrect".to_string(),
-                            ));
-                        }
-                    }
-                    _ => {
-                        return Err(AppError::BadRequest(
-                            "Invalid two factor provider".to_string(),
-                        ));
-                    }
+            let remember = match verify_twofactor(
+                &env,
+                &db,
+                &base_url,
+                &payload,
+                &mut user,
+                &device,
+                &twofactors,
+            )
+            .await
+            {
+                // A wrong code counts like a wrong password; being asked for one does not.
+                Err(AppError::BadRequest(message)) => {
+                    record_failed_login(&env, &db, &backoff, &email, &ip).await;
+                    return Err(AppError::BadRequest(message));
                 }
+                result => result?,
+            };
+
+            backoff.reset(&env, &format!("email:{email}")).await;
+            // Each approved auth request logs in once.
+            if let Some(mut auth_request) = auth_request {
+                auth_request.authentication_date = Some(db::now_string());
+                auth_request.update(&db).await?;
             }
 
             let user = if let Some(password_hash) = password_hash {
@@ -643,41 +897,226 @@ pub async fn token(
             } else {
                 user
             };
-            let mut two_factor_remember_token = None;
-            if should_issue_remember {
-                let remember_token = generate_remember_token(env.as_ref(), &user, &device)?;
-                device
-                    .set_twofactor_remember(&db, Some(&remember_token))
-                    .await?;
-                two_factor_remember_token = Some(remember_token);
-            } else {
-                device.touch(&db).await?;
+            finish_login(
+                &env,
+                &db,
+                &headers,
+                user,
+                (device, new_device),
+                &device_request.client_id,
+                remember,
+            )
+            .await
+        }
+        "client_credentials" => {
+            if let Ok(rate_limiter) = env.rate_limiter("LOGIN_RATE_LIMITER") {
+                let client_id = optional_field(payload.client_id.as_deref()).unwrap_or_default();
+                if let Ok(outcome) = rate_limiter.limit(format!("login:{client_id}")).await {
+                    if !outcome.success {
+                        return Err(AppError::TooManyRequests(
+                            "Too many login attempts. Please try again later.".to_string(),
+                        ));
+                    }
+                }
             }
 
-            if device.push_token.is_some() && device.is_push_device() {
-                if let Ok(Some(cfg)) = push::push_config(&env) {
-                    match push::register_push_device(&cfg, &mut device).await {
-                        Ok(push_uuid_created) => {
-                            if push_uuid_created {
-                                if let Err(e) = device.persist_push_uuid(&db).await {
-                                    log::warn!("Push uuid persistence on login failed: {e}");
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            log::warn!("Push re-registration on login failed: {e}");
-                        }
+            // API key logins skip two-factor authentication, matching Bitwarden.
+            let (user, device_request) = authenticate_api_key_grant(&db, &payload).await?;
+            ensure_account_active(&user)?;
+            accounts::ensure_email_verified(&env, &base_url, &user).await?;
+
+            let (mut device, new_device) = Device::get_or_create(
+                &db,
+                device_request.identifier,
+                user.id.clone(),
+                device_request.name,
+                device_request.r#type,
+            )
+            .await?;
+            if new_device {
+                mail::send_in_background(
+                    (*env).clone(),
+                    user.email.clone(),
+                    mail::Template::NewDevice {
+                        device_type: DeviceType::from_i32(device.r#type).display_name(),
+                        ip: &request_ip_from_headers(&headers),
+                    },
+                );
+            }
+            device.touch(&db).await?;
+            User::record_login(&db, &user.id).await;
+
+            generate_tokens_and_response(
+                user,
+                &device,
+                &device_request.client_id,
+                &env,
+                None,
+                None,
+                RefreshAuthMethod::UserApiKey,
+            )
+            .await
+        }
+        "webauthn" => {
+            let ip = request_ip_from_headers(&headers);
+            if let Ok(rate_limiter) = env.rate_limiter("LOGIN_RATE_LIMITER") {
+                if let Ok(outcome) = rate_limiter.limit(format!("login:webauthn:{ip}")).await {
+                    if !outcome.success {
+                        return Err(AppError::TooManyRequests(
+                            "Too many login attempts. Please try again later.".to_string(),
+                        ));
                     }
                 }
             }
 
-            generate_tokens_and_response(
+            // A passkey verifies the user itself, so it stands in for both the master
+            // password and the second factor.
+            let device_request = parse_password_device_request(&payload)?;
+            let token = required_field(payload.token.as_deref(), "token")?;
+            let assertion = webauthn::parse_assertion(&required_field(
+                payload.device_response.as_deref(),
+                "deviceResponse",
+            )?)?;
+            let invalid_passkey = || AppError::Unauthorized("Invalid passkey".to_string());
+            let mut passkey = Passkey::find_by_credential_id(
+                &db,
+                &webauthn::assertion_credential_id(&assertion)?,
+            )
+            .await?
+            .ok_or_else(invalid_passkey)?;
+            let counter = webauthn::finish_passkey_assertion(
+                &env,
+                &RelyingParty::from_base_url(&base_url),
+                None,
+                &token,
+                &passkey.key(),
+                &assertion,
+            )
+            .await
+            .map_err(|e| match e {
+                AppError::BadRequest(message) => AppError::Unauthorized(message),
+                e => e,
+            })?;
+            passkey.set_counter(&db, counter).await?;
+
+            let user = load_user_by_id(&db, &passkey.user_id).await?;
+            ensure_account_active(&user)?;
+            accounts::ensure_email_verified(&env, &base_url, &user).await?;
+
+            let (mut device, new_device) = Device::get_or_create(
+                &db,
+                device_request.identifier,
+                user.id.clone(),
+                device_request.name,
+                device_request.r#type,
+            )
+            .await?;
+            if new_device {
+                mail::send_in_background(
+                    (*env).clone(),
+                    user.email.clone(),
+                    mail::Template::NewDevice {
+                        device_type: DeviceType::from_i32(device.r#type).display_name(),
+                        ip: &ip,
+                    },
+                );
+            }
+            device.touch(&db).await?;
+            reregister_push_device(&env, &db, &mut device).await;
+
+            let actor = EventActor {
+                user_id: user.id.clone(),
+                device_type: device.r#type,
+                ip_address: ip,
+            };
+            Event::record_login(&db, &actor).await;
+            User::record_login(&db, &user.id).await;
+
+            let mut response = generate_tokens_and_response(
                 user,
                 &device,
                 &device_request.client_id,
                 &env,
-                two_factor_remember_token,
+                None,
+                None,
+                RefreshAuthMethod::Password,
+            )
+            .await?;
+            response.user_decryption_options.web_authn_prf_option = passkey.prf_option_json();
+            Ok(response)
+        }
+        "authorization_code" => {
+            let ip = request_ip_from_headers(&headers);
+            if let Ok(rate_limiter) = env.rate_limiter("LOGIN_RATE_LIMITER") {
+                if let Ok(outcome) = rate_limiter.limit(format!("login:sso:{ip}")).await {
+                    if !outcome.success {
+                        return Err(AppError::TooManyRequests(
+                            "Too many login attempts. Please try again later.".to_string(),
+                        ));
+                    }
+                }
+            }
+
+            // The identity provider stands in for the master password; two-step login
+            // still applies.
+            let config = SsoConfig::from_env(&env)?;
+            let device_request = parse_password_device_request(&payload)?;
+            let mut user = authenticate_sso_grant(&env, &db, &payload).await?;
+            ensure_account_active(&user)?;
+            accounts::ensure_email_verified(&env, &base_url, &user).await?;
+            handlers::sso::provision_member(&db, &config, &user).await?;
+
+            // Wrong two-step codes back off as in the password grant.
+            let email = user.email.to_lowercase();
+            let backoff = LoginBackoff::from_env(&env);
+            let retry_after = backoff
+                .retry_after(&env, &format!("email:{email}"))
+                .await
+                .max(backoff.retry_after(&env, &format!("ip:{ip}")).await);
+            if retry_after > 0 {
+                return Err(AppError::TooManyRequests(format!(
+                    "Too many failed login attempts. Please try again in {retry_after} seconds."
+                )));
+            }
+
+            let (twofactors, _) = login_twofactors(&db, &user.id).await?;
+            let (device, new_device) = Device::find_or_build(
+                &db,
+                device_request.identifier,
+                user.id.clone(),
+                device_request.name,
+                device_request.r#type,
+            )
+            .await?;
+            let remember = match verify_twofactor(
+                &env,
+                &db,
+                &base_url,
+                &payload,
+                &mut user,
+                &device,
+                &twofactors,
+            )
+            .await
+            {
+                Err(AppError::BadRequest(message)) => {
+                    record_failed_login(&env, &db, &backoff, &email, &ip).await;
+                    return Err(AppError::BadRequest(message));
+                }
+                result => result?,
+            };
+            backoff.reset(&env, &format!("email:{email}")).await;
+
+            finish_login(
+                &env,
+                &db,
+                &headers,
+                user,
+                (device, new_device),
+                &device_request.client_id,
+                remember,
             )
+            .await
         }
         "refresh_token" => {
             // When a refresh token is invalid or missing we need to respond with an HTTP BadRequest (400)
@@ -686,8 +1125,6 @@ pub async fn token(
             // https://github.com/bitwarden/clients/blob/2ee158e720a5e7dbe3641caf80b569e97a1dd91b/libs/common/src/services/api.service.ts#L1786-L1797
             let refresh_token = required_field(payload.refresh_token.as_deref(), "refresh_token")
                 .map_err(|_| AppError::BadRequest("invalid_grant".to_string()))?;
-            validate_password_scope(payload.scope.as_deref(), false)
-                .map_err(|_| AppError::BadRequest("invalid_grant".to_string()))?;
 
             let jwt_refresh_secret = env.secret("JWT_REFRESH_SECRET")?.to_string();
             let refresh_key = Hs256Key::new(jwt_refresh_secret.as_bytes());
@@ -708,9 +1145,44 @@ pub async fn token(
                 .map_err(|_| AppError::BadRequest("invalid_grant".to_string()))?;
 
             let refresh_claims = token.into_parts().1.custom;
-            let mut device = Device::find_by_refresh_token(&db, &refresh_claims.device_token)
-                .await?
-                .ok_or_else(|| AppError::BadRequest("invalid_grant".to_string()))?;
+            let now = Utc::now();
+            validate_scope(payload.scope.as_deref(), refresh_claims.sub.scope(), false)
+                .map_err(|_| AppError::BadRequest("invalid_grant".to_string()))?;
+            let (mut device, previous) =
+                match RefreshToken::find_by_token(&db, &refresh_claims.device_token).await? {
+                    Some(stored) => match stored.check_and_consume(&db, now).await? {
+                        RefreshTokenCheck::Valid(stored) | RefreshTokenCheck::Grace(stored) => {
+                            // The policies or SESSION_MAX_AGE_HOURS may have been
+                            // tightened since the token was issued.
+                            let limits = session_limits(&env, &db, &stored.user_id).await?;
+                            if !stored.within_limits(limits, now) {
+                                return Err(AppError::BadRequest("invalid_grant".to_string()));
+                            }
+                            let device = Device::find_by_identifier_and_user(
+                                &db,
+                                &stored.device_identifier,
+                                &stored.user_id,
+                            )
+                            .await?
+                            .ok_or_else(|| AppError::BadRequest("invalid_grant".to_string()))?;
+                            (device, Some(stored))
+                        }
+                        RefreshTokenCheck::Reused | RefreshTokenCheck::Invalid => {
+                            return Err(AppError::BadRequest("invalid_grant".to_string()));
+                        }
+                    },
+                    None => {
+                        // Tokens issued before rotation was introduced carry the device's
+                        // static refresh token. Accept it once and move the session over
+                        // to a new token family.
+                        let mut device =
+                            Device::find_by_refresh_token(&db, &refresh_claims.device_token)
+                                .await?
+                                .ok_or_else(|| AppError::BadRequest("invalid_grant".to_string()))?;
+                        device.rotate_refresh_token(&db).await?;
+                        (device, None)
+                    }
+                };
             let user = load_user_by_id(&db, &device.user_id).await?;
 
             if !constant_time_eq(
@@ -724,17 +1196,312 @@ pub async fn token(
 
             let client_id = optional_field(payload.client_id.as_deref())
                 .unwrap_or_else(|| "undefined".to_string());
-            generate_tokens_and_response(user, &device, &client_id, &env, None)
+            generate_tokens_and_response(
+                user,
+                &device,
+                &client_id,
+                &env,
+                None,
+                previous.as_ref(),
+                refresh_claims.sub,
+            )
+            .await
         }
         _ => Err(AppError::BadRequest("Unsupported grant_type".to_string())),
     }
 }
 
+/// Two-step login providers of `user`, with the IDs of the enabled ones.
+async fn login_twofactors(
+    db: &db::Db,
+    user_id: &str,
+) -> Result<(Vec<TwoFactor>, Vec<i32>), AppError> {
+    let mut twofactors: Vec<TwoFactor> = list_user_twofactors(db, user_id).await?;
+    // Duo required by an organization is offered like the user's own providers.
+    if let Some(org_duo) =
+        OrgTwoFactor::find_enabled_for_user(db, user_id, TwoFactorType::OrganizationDuo).await?
+    {
+        twofactors.push(org_duo.to_member_twofactor(user_id));
+    }
+    let twofactor_ids = enabled_twofactor_providers(&twofactors);
+    Ok((twofactors, twofactor_ids))
+}
+
+/// Check the second factor sent with a login, or answer with the providers to choose
+/// from when none was sent. Returns whether the client asked to remember the device.
+async fn verify_twofactor(
+    env: &Arc<Env>,
+    db: &db::Db,
+    base_url: &str,
+    payload: &TokenRequest,
+    user: &mut User,
+    device: &Device,
+    twofactors: &[TwoFactor],
+) -> Result<bool, AppError> {
+    let twofactor_ids = enabled_twofactor_providers(twofactors);
+    if twofactor_ids.is_empty() {
+        return Ok(false);
+    }
+    let mut should_issue_remember = false;
+    let rp = RelyingParty::from_base_url(base_url);
+    let client_id = optional_field(payload.client_id.as_deref()).unwrap_or_default();
+    let duo_redirect_uri = duo::redirect_uri(base_url, &client_id);
+    let selected_id = payload.two_factor_provider.unwrap_or(twofactor_ids[0]);
+    let Some(twofactor_code) = payload.two_factor_token.as_deref() else {
+        return Err(twofactor_required(
+            env,
+            db,
+            &rp,
+            user,
+            twofactors,
+            &twofactor_ids,
+            &duo_redirect_uri,
+        )
+        .await?);
+    };
+
+    match TwoFactorType::from_i32(selected_id) {
+        Some(TwoFactorType::Authenticator) => {
+            let tf = twofactors
+                .iter()
+                .find(|tf| tf.enabled && tf.atype == TwoFactorType::Authenticator as i32)
+                .ok_or_else(|| AppError::BadRequest("TOTP not configured".to_string()))?;
+
+            let allow_drift = allow_totp_drift(env);
+            let new_last_used =
+                validate_totp(twofactor_code, &tf.data, tf.last_used, allow_drift).await?;
+
+            d1_query!(
+                db,
+                "UPDATE twofactor SET last_used = ?1 WHERE uuid = ?2",
+                new_last_used,
+                &tf.uuid
+            )
+            .map_err(|_| AppError::Database)?
+            .run()
+            .await
+            .map_err(|_| AppError::Database)?;
+
+            should_issue_remember = payload.two_factor_remember == Some(1);
+        }
+        Some(TwoFactorType::Email) => {
+            let tf = twofactors
+                .iter()
+                .find(|tf| tf.enabled && tf.atype == TwoFactorType::Email as i32)
+                .ok_or_else(|| AppError::BadRequest("Email 2FA not configured".to_string()))?;
+            verify_email_login(db, tf, twofactor_code).await?;
+            should_issue_remember = payload.two_factor_remember == Some(1);
+        }
+        Some(duo_type @ (TwoFactorType::Duo | TwoFactorType::OrganizationDuo)) => {
+            let tf = twofactors
+                .iter()
+                .find(|tf| tf.enabled && tf.atype == duo_type as i32)
+                .ok_or_else(|| AppError::BadRequest("Duo not configured".to_string()))?;
+            duo::finish_login(
+                env,
+                &DuoConfig::from_data(&tf.data)?,
+                &user.id,
+                &user.email,
+                twofactor_code,
+                &duo_redirect_uri,
+            )
+            .await?;
+            should_issue_remember = payload.two_factor_remember == Some(1);
+        }
+        Some(TwoFactorType::YubiKey) => {
+            let tf = twofactors
+                .iter()
+                .find(|tf| tf.enabled && tf.atype == TwoFactorType::YubiKey as i32)
+                .ok_or_else(|| AppError::BadRequest("YubiKey not configured".to_string()))?;
+            let metadata = yubikey_metadata(tf)?;
+            let otp = twofactor_code.trim().to_ascii_lowercase();
+            if !yubikey::public_id(&otp).is_some_and(|id| metadata.keys.iter().any(|key| key == id))
+            {
+                return Err(AppError::BadRequest(
+                    "This YubiKey is not registered".to_string(),
+                ));
+            }
+            yubikey::verify_otp(&YubicoConfig::from_env(env)?, &otp).await?;
+            should_issue_remember = payload.two_factor_remember == Some(1);
+        }
+        Some(TwoFactorType::Webauthn) => {
+            let tf = twofactors
+                .iter()
+                .find(|tf| tf.enabled && tf.atype == TwoFactorType::Webauthn as i32)
+                .ok_or_else(|| AppError::BadRequest("WebAuthn not configured".to_string()))?;
+
+            let mut credentials = WebauthnCredential::list_from_data(&tf.data)?;
+            webauthn::finish_login(env, &rp, &user.id, &mut credentials, twofactor_code).await?;
+
+            // Persist the advanced signature counter for clone detection
+            let data = serde_json::to_string(&credentials).map_err(|_| AppError::Internal)?;
+            d1_query!(
+                db,
+                "UPDATE twofactor SET data = ?1 WHERE uuid = ?2",
+                data,
+                &tf.uuid
+            )
+            .map_err(|_| AppError::Database)?
+            .run()
+            .await
+            .map_err(|_| AppError::Database)?;
+
+            should_issue_remember = payload.two_factor_remember == Some(1);
+        }
+        Some(TwoFactorType::Remember) => {
+            if !validate_remember_token(env, user, device, twofactor_code)? {
+                return Err(twofactor_required(
+                    env,
+                    db,
+                    &rp,
+                    user,
+                    twofactors,
+                    &twofactor_ids,
+                    &duo_redirect_uri,
+                )
+                .await?);
+            }
+            should_issue_remember = payload.two_factor_remember == Some(1);
+        }
+        Some(TwoFactorType::RecoveryCode) => {
+            if !recovery_code_matches(user, twofactor_code) {
+                return Err(AppError::BadRequest(
+                    "Recovery code is incorrect".to_string(),
+                ));
+            }
+            // 2FA was reset: end the other sessions, this login gets the new stamp.
+            user.security_stamp = reset_twofactor(env, db, user).await?;
+        }
+        _ => {
+            return Err(AppError::BadRequest(
+                "Invalid two factor provider".to_string(),
+            ));
+        }
+    }
+    Ok(should_issue_remember)
+}
+
+/// Issue the tokens of a login that passed every check, giving out a remember-device
+/// token when the client asked for one. The device is only stored, and the new device
+/// alert only sent, here.
+async fn finish_login(
+    env: &Arc<Env>,
+    db: &db::Db,
+    headers: &HeaderMap,
+    user: User,
+    (mut device, new_device): (Device, bool),
+    client_id: &str,
+    remember: bool,
+) -> Result<Json<TokenResponse>, AppError> {
+    device.save_for_login(db, new_device).await?;
+    if new_device {
+        mail::send_in_background(
+            (**env).clone(),
+            user.email.clone(),
+            mail::Template::NewDevice {
+                device_type: DeviceType::from_i32(device.r#type).display_name(),
+                ip: &request_ip_from_headers(headers),
+            },
+        );
+    }
+
+    let mut two_factor_remember_token = None;
+    let remember_days = twofactor_remember_days(env);
+    if remember && remember_days > 0 {
+        let remember_token = generate_remember_token(env, &user, &device, remember_days)?;
+        device
+            .set_twofactor_remember(db, Some(&sha256_hex(&remember_token)))
+            .await?;
+        two_factor_remember_token = Some(remember_token);
+    } else {
+        device.touch(db).await?;
+    }
+
+    reregister_push_device(env, db, &mut device).await;
+
+    let actor = EventActor {
+        user_id: user.id.clone(),
+        device_type: device.r#type,
+        ip_address: request_ip_from_headers(headers),
+    };
+    Event::record_login(db, &actor).await;
+    User::record_login(db, &user.id).await;
+
+    generate_tokens_and_response(
+        user,
+        &device,
+        client_id,
+        env,
+        two_factor_remember_token,
+        None,
+        RefreshAuthMethod::Password,
+    )
+    .await
+}
+
+/// Build the "two factor required" error, including the provider specific data
+/// (masked email address, WebAuthn challenge, Duo prompt URL) the clients need to continue.
+async fn twofactor_required(
+    env: &Env,
+    db: &db::Db,
+    rp: &RelyingParty,
+    user: &User,
+    twofactors: &[TwoFactor],
+    providers: &[i32],
+    duo_redirect_uri: &str,
+) -> Result<AppError, AppError> {
+    let mut result = json_err_twofactor(providers);
+
+    if let Some(tf) = twofactors
+        .iter()
+        .find(|tf| tf.enabled && tf.atype == TwoFactorType::Email as i32)
+    {
+        result["TwoFactorProviders2"][(TwoFactorType::Email as i32).to_string()] =
+            email_login_challenge(env, db, tf, providers.len() == 1).await?;
+    }
+
+    if let Some(tf) = twofactors
+        .iter()
+        .find(|tf| tf.enabled && tf.atype == TwoFactorType::Webauthn as i32)
+    {
+        let credentials = WebauthnCredential::list_from_data(&tf.data)?;
+        result["TwoFactorProviders2"][(TwoFactorType::Webauthn as i32).to_string()] =
+            webauthn::start_login(env, rp, &user.id, &credentials).await?;
+    }
+
+    if let Some(tf) = twofactors
+        .iter()
+        .find(|tf| tf.enabled && tf.atype == TwoFactorType::YubiKey as i32)
+    {
+        result["TwoFactorProviders2"][(TwoFactorType::YubiKey as i32).to_string()] =
+            serde_json::json!({ "Nfc": yubikey_metadata(tf)?.nfc });
+    }
+
+    for tf in twofactors.iter().filter(|tf| {
+        tf.enabled
+            && (tf.atype == TwoFactorType::Duo as i32
+                || tf.atype == TwoFactorType::OrganizationDuo as i32)
+    }) {
+        result["TwoFactorProviders2"][tf.atype.to_string()] = duo::start_login(
+            env,
+            &DuoConfig::from_data(&tf.data)?,
+            &user.id,
+            &user.email,
+            duo_redirect_uri,
+        )
+        .await?;
+    }
+
+    if let Some(token) = turnstile::bypass_token(env, &user.email)? {
+        result["CaptchaBypassToken"] = Value::String(token);
+    }
+
+    Ok(AppError::TwoFactorRequired(result))
+}
+
 /// Generates the JSON error response for 2FA required
 fn json_err_twofactor(providers: &[i32]) -> Value {
     let mut result = serde_json::json!({
-        "error": "invalid_grant",
-        "error_description": "Two factor required.",
         "TwoFactorProviders": providers.iter().map(|p| p.to_string()).collect::<Vec<String>>(),
         "TwoFactorProviders2": {},
         "MasterPasswordPolicy": {
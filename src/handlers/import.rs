@@ -1,4 +1,5 @@
-use axum::{extract::State, Json};
+use axum::extract::{Query, State};
+use axum::Json;
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use uuid::Uuid;
@@ -10,11 +11,15 @@ use crate::auth::Claims;
 use crate::db::{self, touch_user_updated_at};
 use crate::error::AppError;
 use crate::models::cipher::{Cipher, CipherData};
+use crate::models::collection::Collection;
 use crate::models::folder::Folder;
-use crate::models::import::ImportRequest;
+use crate::models::import::{ImportRequest, OrganizationImportRequest};
+use crate::models::organization::{Membership, MembershipType, Permission};
 use crate::notifications::{self, UpdateType};
 
+use super::ciphers::OrganizationCiphersQuery;
 use super::get_batch_size;
+use super::organizations::require_member_access;
 
 /// Import ciphers and folders.
 /// Aligned with vaultwarden's POST /ciphers/import implementation.
@@ -43,6 +48,7 @@ pub async fn import_data(
         existing_folder_rows.into_iter().map(|row| row.id).collect();
 
     // Process folders and build the folder_id list
+    let mut imported = ImportedRows::default();
     let mut folder_statements: Vec<D1PreparedStatement> = Vec::new();
     let mut folders: Vec<String> = Vec::with_capacity(data.folders.len());
 
@@ -73,6 +79,7 @@ pub async fn import_data(
                 .map_err(|_| AppError::Database)?;
 
                 folder_statements.push(stmt);
+                imported.folder_ids.push(id.clone());
                 id.clone()
             }
         } else {
@@ -98,27 +105,29 @@ pub async fn import_data(
             .map_err(|_| AppError::Database)?;
 
             folder_statements.push(stmt);
+            imported.folder_ids.push(new_id.clone());
             new_id
         };
 
         folders.push(folder_id);
     }
 
-    // Execute folder inserts in batches
-    if !folder_statements.is_empty() {
-        db::execute_in_batches(&db, folder_statements, batch_size).await?;
-    }
-
     // Build the relations map: cipher_index -> folder_index
     // Each cipher can only be in one folder at a time
     let mut relations_map: HashMap<usize, usize> =
         HashMap::with_capacity(data.folder_relationships.len());
     for relation in data.folder_relationships {
+        if relation.key >= data.ciphers.len() || relation.value >= folders.len() {
+            return Err(AppError::BadRequest(
+                "Invalid folder relationship in import data".to_string(),
+            ));
+        }
         relations_map.insert(relation.key, relation.value);
     }
 
-    // Prepare all cipher insert statements
-    let mut cipher_statements: Vec<D1PreparedStatement> = Vec::with_capacity(data.ciphers.len());
+    // Folders go first so the ciphers' folder references resolve.
+    let mut statements = folder_statements;
+    statements.reserve(data.ciphers.len());
 
     for (index, import_cipher) in data.ciphers.into_iter().enumerate() {
         // Determine folder_id from folder_relationships
@@ -137,7 +146,8 @@ pub async fn import_data(
         let cipher = Cipher {
             id: Uuid::new_v4().to_string(),
             user_id: Some(claims.sub.clone()),
-            organization_id: import_cipher.organization_id,
+            // Personal imports always land in the personal vault.
+            organization_id: None,
             r#type: import_cipher.r#type,
             data: data_value,
             favorite: import_cipher.favorite.unwrap_or(false),
@@ -171,13 +181,11 @@ pub async fn import_data(
              cipher.updated_at,
         ).map_err(|_| AppError::Database)?;
 
-        cipher_statements.push(stmt);
+        statements.push(stmt);
+        imported.cipher_ids.push(cipher.id);
     }
 
-    // Execute cipher inserts in batches
-    if !cipher_statements.is_empty() {
-        db::execute_in_batches(&db, cipher_statements, batch_size).await?;
-    }
+    execute_import(&db, statements, batch_size, &claims.sub, &imported).await?;
 
     touch_user_updated_at(&db, &claims.sub, &now).await?;
 
@@ -192,8 +200,186 @@ pub async fn import_data(
     Ok(Json(()))
 }
 
+/// Import ciphers and collections into an organization vault (owners and admins only).
+/// Aligned with vaultwarden's POST /ciphers/import-organization implementation.
+#[worker::send]
+pub async fn import_organization_data(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Query(query): Query<OrganizationCiphersQuery>,
+    Json(data): Json<OrganizationImportRequest>,
+) -> Result<Json<()>, AppError> {
+    let db = db::get_db(&env)?;
+    let now = db::now_string();
+    let batch_size = get_batch_size(&env);
+    let org_id = query.organization_id;
+    require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Admin,
+        &[Permission::AccessImportExport],
+    )
+    .await?;
+
+    let existing_collections: HashSet<String> = Collection::list_by_org(&db, &org_id)
+        .await?
+        .into_iter()
+        .map(|c| c.id)
+        .collect();
+
+    // Process collections and build the collection_id list
+    let mut imported = ImportedRows::default();
+    let mut statements: Vec<D1PreparedStatement> = Vec::new();
+    let mut collections: Vec<String> = Vec::with_capacity(data.collections.len());
+
+    for import_collection in data.collections {
+        let collection_id = match import_collection
+            .id
+            .filter(|id| existing_collections.contains(id))
+        {
+            Some(id) => id,
+            None => {
+                let collection = Collection::new(
+                    org_id.clone(),
+                    import_collection.name,
+                    import_collection.external_id,
+                );
+                statements.push(collection.insert_statement(&db)?);
+                imported.collection_ids.push(collection.id.clone());
+                collection.id
+            }
+        };
+        collections.push(collection_id);
+    }
+
+    // Build the relations map: cipher_index -> collection_indexes
+    let mut relations_map: HashMap<usize, Vec<usize>> = HashMap::new();
+    for relation in data.collection_relationships {
+        if relation.key >= data.ciphers.len() || relation.value >= collections.len() {
+            return Err(AppError::BadRequest(
+                "Invalid collection relationship in import data".to_string(),
+            ));
+        }
+        relations_map
+            .entry(relation.key)
+            .or_default()
+            .push(relation.value);
+    }
+
+    for (index, import_cipher) in data.ciphers.into_iter().enumerate() {
+        let cipher_data = CipherData::new(
+            import_cipher.name,
+            import_cipher.notes,
+            import_cipher.type_fields,
+        );
+        let data = serde_json::to_string(&cipher_data).map_err(|_| AppError::Internal)?;
+        let cipher_id = Uuid::new_v4().to_string();
+
+        // Org ciphers are shared rows: no owning user and no per-user folder/favorite.
+        statements.push(
+            d1_query!(
+                &db,
+                "INSERT INTO ciphers (id, user_id, organization_id, type, data, favorite, folder_id, created_at, updated_at)
+                 VALUES (?1, NULL, ?2, ?3, ?4, 0, NULL, ?5, ?5)",
+                cipher_id,
+                org_id,
+                import_cipher.r#type,
+                data,
+                now
+            )
+            .map_err(|_| AppError::Database)?,
+        );
+        for collection_index in relations_map.get(&index).into_iter().flatten() {
+            statements.push(
+                d1_query!(
+                    &db,
+                    "INSERT OR IGNORE INTO ciphers_collections (cipher_id, collection_id) VALUES (?1, ?2)",
+                    cipher_id,
+                    collections[*collection_index]
+                )
+                .map_err(|_| AppError::Database)?,
+            );
+        }
+        imported.cipher_ids.push(cipher_id);
+    }
+
+    execute_import(&db, statements, batch_size, &claims.sub, &imported).await?;
+
+    Membership::touch_confirmed_users(&db, &org_id, &now).await?;
+    for user_id in Membership::confirmed_user_ids(&db, &org_id).await? {
+        notifications::publish_user_update(
+            (*env).clone(),
+            user_id,
+            UpdateType::SyncVault,
+            now.clone(),
+            Some(claims.device.clone()),
+        );
+    }
+
+    Ok(Json(()))
+}
+
 /// Helper struct for querying existing folder IDs
 #[derive(serde::Deserialize)]
 struct FolderIdRow {
     id: String,
 }
+
+/// Rows created by an import, deleted again when a later batch fails.
+#[derive(Default)]
+struct ImportedRows {
+    folder_ids: Vec<String>,
+    collection_ids: Vec<String>,
+    cipher_ids: Vec<String>,
+}
+
+/// Run the import statements in chunks of `batch_size`.
+///
+/// Each D1 batch is atomic on its own, but an import may span several. When a chunk
+/// fails, the rows written by the earlier chunks are deleted again so the vault is
+/// left as it was before the import.
+async fn execute_import(
+    db: &db::Db,
+    statements: Vec<D1PreparedStatement>,
+    batch_size: usize,
+    user_id: &str,
+    imported: &ImportedRows,
+) -> Result<(), AppError> {
+    let Err(err) = db::execute_in_batches(db, statements, batch_size).await else {
+        return Ok(());
+    };
+    log::warn!("Import for user {user_id} failed, rolling back: {err}");
+
+    let cipher_ids = serde_json::to_string(&imported.cipher_ids).map_err(|_| AppError::Internal)?;
+    let collection_ids =
+        serde_json::to_string(&imported.collection_ids).map_err(|_| AppError::Internal)?;
+    let folder_ids = serde_json::to_string(&imported.folder_ids).map_err(|_| AppError::Internal)?;
+    // Cipher and collection ids were generated here; folder ids may come from the
+    // client, so only this user's folders are removed.
+    let rollback = vec![
+        d1_query!(
+            db,
+            "DELETE FROM ciphers WHERE id IN (SELECT value FROM json_each(?1))",
+            cipher_ids
+        )
+        .map_err(|_| AppError::Database)?,
+        d1_query!(
+            db,
+            "DELETE FROM collections WHERE id IN (SELECT value FROM json_each(?1))",
+            collection_ids
+        )
+        .map_err(|_| AppError::Database)?,
+        d1_query!(
+            db,
+            "DELETE FROM folders WHERE user_id = ?1 AND id IN (SELECT value FROM json_each(?2))",
+            user_id,
+            folder_ids
+        )
+        .map_err(|_| AppError::Database)?,
+    ];
+    if let Err(rollback_err) = db.batch(rollback).await {
+        log::error!("Rolling back the import for user {user_id} failed: {rollback_err}");
+    }
+    Err(err)
+}
@@ -1,11 +1,22 @@
-use axum::{extract::State, Json};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
 use chrono::{SecondsFormat, Utc};
-use serde::Deserialize;
 use serde_json::{json, Value};
+use std::future::Future;
 use std::sync::Arc;
 use worker::Env;
 
-use crate::{db, error::AppError};
+use crate::{
+    db,
+    error::AppError,
+    handlers::attachments::{ATTACHMENTS_BUCKET, ATTACHMENTS_KV},
+    metrics,
+    webauthn::CACHE_KV,
+};
 
 /// GET /api/now
 ///
@@ -29,26 +40,114 @@ pub async fn alive(State(env): State<Arc<Env>>) -> Result<Json<String>, AppError
     Ok(now().await)
 }
 
-/// GET /api/version
+/// GET /alive
 ///
-/// Returns a Bitwarden-server-like version string. Clients sometimes call this endpoint.
+/// Liveness probe: answers as long as the worker runs, without touching any binding.
 #[worker::send]
-pub async fn version() -> Json<&'static str> {
-    // Keep this in sync with `src/handlers/config.rs`'s `version`.
-    Json("2025.12.0")
+pub async fn liveness() -> Json<String> {
+    now().await
 }
 
-#[derive(Debug, Deserialize)]
-pub struct HibpBreachQuery {
-    #[allow(dead_code)] // stub endpoint doesn't use it yet
-    pub username: String,
+/// Run one dependency check and describe its outcome. `None` means the binding is
+/// not configured, which is not an error.
+async fn check<F>(name: &str, probe: Option<F>) -> (bool, Value)
+where
+    F: Future<Output = Result<(), String>>,
+{
+    let Some(probe) = probe else {
+        return (true, json!({ "status": "not_configured" }));
+    };
+    let started = js_sys::Date::now();
+    let result = probe.await;
+    let latency_ms = metrics::elapsed_ms(started) as u64;
+    match result {
+        Ok(()) => (true, json!({ "status": "ok", "latencyMs": latency_ms })),
+        Err(e) => {
+            log::error!("Health check {name} failed: {e}");
+            (false, json!({ "status": "error", "latencyMs": latency_ms }))
+        }
+    }
 }
 
-/// GET /api/hibp/breach?username=...
+/// GET /api/health
 ///
-/// Vaultwarden can proxy HaveIBeenPwned if configured. This minimal server doesn't.
-/// We return an empty array to indicate "no breach data" without surfacing an error in clients.
+/// Readiness probe checking D1, the attachment storage (R2 or KV) and the cache KV
+/// namespace. Responds 503 when a configured dependency fails; error details are only
+/// logged.
 #[worker::send]
-pub async fn hibp_breach(_query: axum::extract::Query<HibpBreachQuery>) -> Json<Value> {
-    Json(json!([]))
+pub async fn health(State(env): State<Arc<Env>>) -> Response {
+    let d1 = check(
+        "d1",
+        Some(async {
+            let db = db::get_db(&env).map_err(|e| e.to_string())?;
+            db.prepare("SELECT 1 as ok")
+                .first::<i32>(Some("ok"))
+                .await
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        }),
+    )
+    .await;
+    let r2 = check(
+        "r2",
+        env.bucket(ATTACHMENTS_BUCKET)
+            .ok()
+            .map(|bucket| async move {
+                bucket
+                    .head("healthcheck")
+                    .await
+                    .map(|_| ())
+                    .map_err(|e| e.to_string())
+            }),
+    )
+    .await;
+    let attachments_kv = check(
+        "attachments_kv",
+        env.kv(ATTACHMENTS_KV).ok().map(|kv| async move {
+            kv.get("healthcheck")
+                .text()
+                .await
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        }),
+    )
+    .await;
+    let cache_kv = check(
+        "cache_kv",
+        env.kv(CACHE_KV).ok().map(|kv| async move {
+            kv.get("healthcheck")
+                .text()
+                .await
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        }),
+    )
+    .await;
+
+    let healthy = d1.0 && r2.0 && attachments_kv.0 && cache_kv.0;
+    let body = json!({
+        "status": if healthy { "ok" } else { "error" },
+        "time": Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        "checks": {
+            "d1": d1.1,
+            "r2": r2.1,
+            "attachmentsKv": attachments_kv.1,
+            "cacheKv": cache_kv.1,
+        },
+    });
+    let status = if healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(body)).into_response()
+}
+
+/// GET /api/version
+///
+/// Returns a Bitwarden-server-like version string. Clients sometimes call this endpoint.
+#[worker::send]
+pub async fn version() -> Json<&'static str> {
+    // Keep this in sync with `src/handlers/config.rs`'s `version`.
+    Json("2025.12.0")
 }
@@ -1,17 +1,30 @@
 pub mod accounts;
+pub mod admin;
 pub mod attachments;
 pub mod auth_requests;
 pub mod ciphers;
+pub mod collections;
 pub mod config;
 pub mod devices;
 pub mod domains;
 pub mod emergency_access;
+pub mod events;
+pub mod export;
 pub mod folders;
+pub mod groups;
+pub mod hibp;
+pub mod icons;
 pub mod identity;
 pub mod import;
 pub mod meta;
+pub mod org_domains;
+pub mod organizations;
+pub mod policies;
 pub mod purge;
+pub mod reports;
 pub mod sends;
+pub mod sso;
+pub mod storage;
 pub mod streaming;
 pub mod sync;
 pub mod twofactor;
@@ -25,6 +38,15 @@ pub(crate) fn get_env_usize(env: &worker::Env, var_name: &str, default: usize) -
         .unwrap_or(default)
 }
 
+/// Shared helper for reading a boolean flag ("1", "true", "yes" or "on" are true).
+pub(crate) fn get_env_bool(env: &worker::Env, var_name: &str, default: bool) -> bool {
+    env.var(var_name)
+        .ok()
+        .map(|value| value.to_string().to_lowercase())
+        .map(|value| matches!(value.trim(), "1" | "true" | "yes" | "on"))
+        .unwrap_or(default)
+}
+
 /// Convenience helper for cipher batch size using IMPORT_BATCH_SIZE.
 pub(crate) fn get_batch_size(env: &worker::Env) -> usize {
     get_env_usize(env, "IMPORT_BATCH_SIZE", 30)
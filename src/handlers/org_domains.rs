@@ -0,0 +1,277 @@
+//! Organization domain verification (claimed domains).
+//!
+//! An admin claims a domain and adds the returned `bw=...` value as a TXT record of
+//! it; verifying then looks the record up over DNS-over-HTTPS (see [`crate::dns`]).
+//! A domain can be verified by one organization only. The clients also use verified
+//! domains to discover SSO from the login email: SSO is offered for the domains of
+//! the organization SSO users join (`SSO_ORGANIZATION_ID`), or of any organization
+//! when none is set.
+
+use axum::extract::{Path, State};
+use axum::Json;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use worker::Env;
+
+use crate::auth::Claims;
+use crate::crypto::random_bytes;
+use crate::db;
+use crate::dns;
+use crate::error::AppError;
+use crate::handlers::organizations::require_member_access;
+use crate::models::org_domain::{
+    DomainSsoDetailsRequest, OrganizationDomain, OrganizationDomainRequest,
+};
+use crate::models::organization::{MembershipType, Organization, Permission};
+use crate::sso::SsoConfig;
+
+fn list_json(data: Vec<Value>) -> Value {
+    json!({
+        "data": data,
+        "object": "list",
+        "continuationToken": null,
+    })
+}
+
+/// Lowercased domain name, or `BadRequest` when it is not a plain host name.
+fn normalize_domain(domain: &str) -> Result<String, AppError> {
+    let domain = domain.trim().trim_end_matches('.').to_lowercase();
+    let valid_label = |label: &str| {
+        (1..=63).contains(&label.len())
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    };
+    if domain.len() > 253 || !domain.contains('.') || !domain.split('.').all(valid_label) {
+        return Err(AppError::BadRequest("Invalid domain name".to_string()));
+    }
+    Ok(domain)
+}
+
+/// Domain part of an email address.
+fn email_domain(email: &str) -> Result<String, AppError> {
+    email
+        .trim()
+        .rsplit_once('@')
+        .map(|(_, domain)| domain.to_lowercase())
+        .filter(|domain| !domain.is_empty())
+        .ok_or_else(|| AppError::BadRequest("Invalid email address".to_string()))
+}
+
+/// Whether the organization's verified domains lead to SSO.
+fn sso_available(env: &Env, org_id: &str) -> bool {
+    SsoConfig::from_env(env).is_ok_and(|config| {
+        config
+            .organization_id
+            .is_none_or(|sso_org_id| sso_org_id == org_id)
+    })
+}
+
+async fn require_domain_manager(
+    db: &db::Db,
+    org_id: &str,
+    claims: &Claims,
+) -> Result<(), AppError> {
+    require_member_access(
+        db,
+        org_id,
+        &claims.sub,
+        MembershipType::Admin,
+        &[Permission::ManageSso],
+    )
+    .await
+    .map(|_| ())
+}
+
+async fn fetch_domain(
+    db: &db::Db,
+    org_id: &str,
+    domain_id: &str,
+) -> Result<OrganizationDomain, AppError> {
+    OrganizationDomain::find_by_id_and_org(db, domain_id, org_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Domain not found".to_string()))
+}
+
+/// Refuse a domain another organization has already verified.
+async fn ensure_unclaimed(db: &db::Db, org_id: &str, domain_name: &str) -> Result<(), AppError> {
+    if OrganizationDomain::find_verified_by_name(db, domain_name)
+        .await?
+        .is_some_and(|claim| claim.organization_id != org_id)
+    {
+        return Err(AppError::BadRequest(
+            "The domain is already claimed by another organization".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// GET /api/organizations/{org_id}/domain
+#[worker::send]
+pub async fn list_domains(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(org_id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    require_domain_manager(&db, &org_id, &claims).await?;
+
+    let domains = OrganizationDomain::list_by_org(&db, &org_id).await?;
+    Ok(Json(list_json(
+        domains.iter().map(OrganizationDomain::to_json).collect(),
+    )))
+}
+
+/// GET /api/organizations/{org_id}/domain/{domain_id}
+#[worker::send]
+pub async fn get_domain(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path((org_id, domain_id)): Path<(String, String)>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    require_domain_manager(&db, &org_id, &claims).await?;
+    Ok(Json(
+        fetch_domain(&db, &org_id, &domain_id).await?.to_json(),
+    ))
+}
+
+/// POST /api/organizations/{org_id}/domain - claim a domain and get its TXT record
+#[worker::send]
+pub async fn create_domain(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(org_id): Path<String>,
+    Json(payload): Json<OrganizationDomainRequest>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    require_domain_manager(&db, &org_id, &claims).await?;
+
+    let domain_name = normalize_domain(&payload.domain_name)?;
+    if OrganizationDomain::find_by_org_and_name(&db, &org_id, &domain_name)
+        .await?
+        .is_some()
+    {
+        return Err(AppError::BadRequest(
+            "The domain is already claimed by this organization".to_string(),
+        ));
+    }
+    ensure_unclaimed(&db, &org_id, &domain_name).await?;
+
+    let txt_record = format!("bw={}", hex::encode(random_bytes(24)?));
+    let domain = OrganizationDomain::new(org_id, domain_name, txt_record);
+    domain.insert(&db).await?;
+    Ok(Json(domain.to_json()))
+}
+
+/// POST /api/organizations/{org_id}/domain/{domain_id}/verify - look up the TXT record
+///
+/// A missing record is not an error: the domain comes back unverified with a new
+/// `lastCheckedDate`, and the admin can try again once DNS has caught up.
+#[worker::send]
+pub async fn verify_domain(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path((org_id, domain_id)): Path<(String, String)>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    require_domain_manager(&db, &org_id, &claims).await?;
+    let mut domain = fetch_domain(&db, &org_id, &domain_id).await?;
+    if domain.is_verified() {
+        return Err(AppError::BadRequest(
+            "The domain is already verified".to_string(),
+        ));
+    }
+    ensure_unclaimed(&db, &org_id, &domain.domain_name).await?;
+
+    let records = dns::txt_records(&env, &domain.domain_name).await?;
+    let now = db::now_string();
+    if records.iter().any(|record| record == &domain.txt_record) {
+        domain.verified_at = Some(now.clone());
+    }
+    domain.last_checked_at = Some(now);
+    domain.update_check(&db).await?;
+    Ok(Json(domain.to_json()))
+}
+
+/// DELETE /api/organizations/{org_id}/domain/{domain_id}
+/// (also POST .../remove)
+#[worker::send]
+pub async fn delete_domain(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path((org_id, domain_id)): Path<(String, String)>,
+) -> Result<Json<()>, AppError> {
+    let db = db::get_db(&env)?;
+    require_domain_manager(&db, &org_id, &claims).await?;
+    fetch_domain(&db, &org_id, &domain_id)
+        .await?
+        .delete(&db)
+        .await?;
+    Ok(Json(()))
+}
+
+/// GET /api/organizations/{org_id}/domain-sso-details - SSO details of the
+/// organization's verified domains
+#[worker::send]
+pub async fn get_domain_sso_details(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(org_id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_access(&db, &org_id, &claims.sub, MembershipType::User, &[]).await?;
+
+    let sso_available = sso_available(&env, &org_id);
+    let domains = OrganizationDomain::list_by_org(&db, &org_id).await?;
+    Ok(Json(list_json(
+        domains
+            .iter()
+            .filter(|d| d.is_verified())
+            .map(|d| d.to_sso_details_json(sso_available))
+            .collect(),
+    )))
+}
+
+/// POST /api/organizations/domain/sso/details - SSO details for the domain of a login email
+///
+/// Answers 404 when no organization has verified the domain, like Bitwarden.
+#[worker::send]
+pub async fn post_sso_details(
+    State(env): State<Arc<Env>>,
+    Json(payload): Json<DomainSsoDetailsRequest>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let domain_name = email_domain(&payload.email)?;
+    let domain = OrganizationDomain::find_verified_by_name(&db, &domain_name)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Claimed org domain not found".to_string()))?;
+    let sso_available = sso_available(&env, &domain.organization_id);
+    Ok(Json(domain.to_sso_details_json(sso_available)))
+}
+
+/// POST /api/organizations/domain/sso/verified - organizations offering SSO for the
+/// domain of a login email
+#[worker::send]
+pub async fn post_sso_verified(
+    State(env): State<Arc<Env>>,
+    Json(payload): Json<DomainSsoDetailsRequest>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let domain_name = email_domain(&payload.email)?;
+    let Some(domain) = OrganizationDomain::find_verified_by_name(&db, &domain_name)
+        .await?
+        .filter(|d| sso_available(&env, &d.organization_id))
+    else {
+        return Ok(Json(list_json(Vec::new())));
+    };
+    let Some(org) = Organization::find_by_id(&db, &domain.organization_id).await? else {
+        return Ok(Json(list_json(Vec::new())));
+    };
+    Ok(Json(list_json(vec![json!({
+        "organizationName": org.name,
+        "organizationIdentifier": org.id,
+        "domainName": domain.domain_name,
+        "object": "verifiedOrganizationDomainSsoDetails"
+    })])))
+}
@@ -0,0 +1,1552 @@
+//! Organizations: creation, membership (invite -> accept -> confirm) and org keys.
+//!
+//! Inviting an address that already has an account accepts the invitation right
+//! away. An address without an account is emailed a link to accept it (when email
+//! delivery is configured), and is also accepted when it registers; invitations
+//! nobody accepted expire after `ORG_INVITATION_EXPIRATION_HOURS`. Either way an
+//! admin still has to confirm the member, which is the step that hands over the
+//! (encrypted) organization key.
+
+use axum::extract::{Path, State};
+use axum::http::HeaderMap;
+use axum::{Extension, Json};
+use chrono::{Duration, Utc};
+use jwt_compact::AlgorithmExt;
+use jwt_compact::{alg::Hs256Key, Claims as JwtClaims, Header, UntrustedToken};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use web_sys::UrlSearchParams;
+use worker::Env;
+
+use crate::auth::{jwt_time_options, Claims};
+use crate::crypto::{generate_salt, hash_password_for_storage};
+use crate::d1_query;
+use crate::db;
+use crate::error::AppError;
+use crate::handlers::accounts::verify_user;
+use crate::handlers::groups::ensure_org_groups;
+use crate::handlers::policies::{
+    ensure_can_create_organization, ensure_user_allowed_in_org, policy_enabled,
+    reset_password_auto_enroll,
+};
+use crate::handlers::{attachments, get_env_usize, server_password_iterations, two_factor_enabled};
+use crate::mail;
+use crate::models::collection::{Collection, CollectionAccess, CollectionAccessData};
+use crate::models::event::{Event, EventActor, EventType};
+use crate::models::group::{Group, MemberGroupsRequest};
+use crate::models::organization::{
+    AcceptInviteRequest, AdminResetPasswordRequest, BulkConfirmRequest, BulkMemberIds,
+    ConfirmMemberRequest, CreateOrganizationRequest, EditMemberRequest, InviteRequest, MemberUser,
+    Membership, MembershipStatus, MembershipType, OrgFeature, OrgKeyData, Organization, Permission,
+    ResetPasswordEnrollmentRequest, UpdateOrganizationRequest,
+};
+use crate::models::policy::PolicyType;
+use crate::models::refresh_token::RefreshToken;
+use crate::models::user::{PasswordOrOtpData, User};
+use crate::notifications::{self, UpdateType};
+use crate::BaseUrl;
+
+async fn fetch_organization(db: &db::Db, org_id: &str) -> Result<Organization, AppError> {
+    Organization::find_by_id(db, org_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Organization not found".to_string()))
+}
+
+/// Load the organization and refuse requests for a feature its plan does not include.
+pub(crate) async fn require_org_feature(
+    db: &db::Db,
+    org_id: &str,
+    feature: OrgFeature,
+) -> Result<(), AppError> {
+    fetch_organization(db, org_id)
+        .await?
+        .ensure_feature(feature)
+}
+
+/// Load the caller's confirmed membership and require at least `required` role.
+pub(crate) async fn require_member_role(
+    db: &db::Db,
+    org_id: &str,
+    user_id: &str,
+    required: MembershipType,
+) -> Result<Membership, AppError> {
+    require_member_access(db, org_id, user_id, required, &[]).await
+}
+
+/// Like [`require_member_role`], but a `Custom` member granted any of `permissions`
+/// passes as well.
+pub(crate) async fn require_member_access(
+    db: &db::Db,
+    org_id: &str,
+    user_id: &str,
+    required: MembershipType,
+    permissions: &[Permission],
+) -> Result<Membership, AppError> {
+    let membership = Membership::find_by_user_and_org(db, user_id, org_id)
+        .await?
+        .filter(Membership::is_confirmed)
+        .ok_or_else(|| AppError::NotFound("Organization not found".to_string()))?;
+
+    if !membership.membership_type().is_at_least(required)
+        && !permissions.iter().any(|p| membership.has_permission(*p))
+    {
+        return Err(AppError::BadRequest(format!(
+            "You need to be {} of the organization",
+            match required {
+                MembershipType::Owner => "an owner",
+                MembershipType::Admin => "an admin",
+                MembershipType::Manager => "a manager",
+                MembershipType::User | MembershipType::Custom => "a member",
+            }
+        )));
+    }
+
+    Ok(membership)
+}
+
+async fn fetch_member(db: &db::Db, org_id: &str, member_id: &str) -> Result<Membership, AppError> {
+    Membership::find_by_id_and_org(db, member_id, org_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Member not found".to_string()))
+}
+
+async fn load_user(db: &db::Db, user_id: &str) -> Result<User, AppError> {
+    let user: Value = db
+        .prepare("SELECT * FROM users WHERE id = ?1")
+        .bind(&[user_id.into()])?
+        .first(None)
+        .await
+        .map_err(|_| AppError::Database)?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+    serde_json::from_value(user).map_err(|_| AppError::Internal)
+}
+
+async fn member_details_json(db: &db::Db, membership: &Membership) -> Result<Value, AppError> {
+    let (user, two_factor) = match membership.user_id.as_deref() {
+        Some(user_id) => {
+            let user: Option<MemberUser> = d1_query!(
+                db,
+                "SELECT name, avatar_color FROM users WHERE id = ?1",
+                user_id
+            )
+            .map_err(|_| AppError::Database)?
+            .first(None)
+            .await
+            .map_err(|_| AppError::Database)?;
+            (user, two_factor_enabled(db, user_id).await?)
+        }
+        None => (None, false),
+    };
+    let collections = CollectionAccess::list_by_membership(db, &membership.id)
+        .await?
+        .iter()
+        .map(CollectionAccess::to_member_json)
+        .collect();
+    let groups = Group::ids_for_membership(db, &membership.id).await?;
+    Ok(membership.to_details_json(user.as_ref(), two_factor, collections, groups))
+}
+
+/// Reject collection assignments outside of `org_id`.
+async fn ensure_org_collections(
+    db: &db::Db,
+    org_id: &str,
+    collections: &[CollectionAccessData],
+) -> Result<(), AppError> {
+    let ids: Vec<String> = collections.iter().map(|c| c.id.clone()).collect();
+    if !Collection::foreign_ids(db, org_id, &ids).await?.is_empty() {
+        return Err(AppError::BadRequest(
+            "Collection does not belong to this organization".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn collection_access_rows(
+    membership_id: &str,
+    collections: Vec<CollectionAccessData>,
+) -> Vec<CollectionAccess> {
+    collections
+        .into_iter()
+        .map(|c| {
+            let collection_id = c.id.clone();
+            c.into_access(membership_id, &collection_id)
+        })
+        .collect()
+}
+
+/// Validate requested collection assignments for a member and convert them to rows.
+async fn member_collection_access(
+    db: &db::Db,
+    org_id: &str,
+    membership_id: &str,
+    collections: Vec<CollectionAccessData>,
+) -> Result<Vec<CollectionAccess>, AppError> {
+    ensure_org_collections(db, org_id, &collections).await?;
+    Ok(collection_access_rows(membership_id, collections))
+}
+
+/// Keep at least one confirmed owner when `membership` is demoted or removed.
+async fn ensure_not_last_owner(db: &db::Db, membership: &Membership) -> Result<(), AppError> {
+    if membership.membership_type() == MembershipType::Owner
+        && membership.is_confirmed()
+        && Membership::count_confirmed_owners(db, &membership.organization_id).await? <= 1
+    {
+        return Err(AppError::BadRequest(
+            "The organization must keep at least one confirmed owner".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Only owners may grant, change or revoke the owner role, and only owners and admins
+/// the admin role.
+fn ensure_can_manage(actor: &Membership, target_type: MembershipType) -> Result<(), AppError> {
+    if target_type == MembershipType::Owner && actor.membership_type() != MembershipType::Owner {
+        return Err(AppError::BadRequest(
+            "Only owners can manage other owners".to_string(),
+        ));
+    }
+    if target_type == MembershipType::Admin
+        && !actor.membership_type().is_at_least(MembershipType::Admin)
+    {
+        return Err(AppError::BadRequest(
+            "Only owners and admins can manage admins".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Permission bits to store for a member of `member_type`. A custom member managing
+/// others can only hand out permissions they hold themselves.
+fn member_permissions(
+    actor: &Membership,
+    member_type: MembershipType,
+    permissions: Option<&Value>,
+) -> Result<i64, AppError> {
+    if member_type != MembershipType::Custom {
+        return Ok(0);
+    }
+    let bits = permissions.map(Permission::bits_from_json).unwrap_or(0);
+    if actor.membership_type() == MembershipType::Custom && bits & !actor.permissions != 0 {
+        return Err(AppError::BadRequest(
+            "You can only grant permissions you have yourself".to_string(),
+        ));
+    }
+    Ok(bits)
+}
+
+/// Tell a member's clients that their organization membership changed.
+fn publish_membership_change(env: &Env, membership: &Membership, now: String, claims: &Claims) {
+    if let Some(user_id) = membership.user_id.clone() {
+        notifications::publish_user_update(
+            env.clone(),
+            user_id,
+            UpdateType::SyncOrgKeys,
+            now,
+            Some(claims.device.clone()),
+        );
+    }
+}
+
+fn member_event(event_type: EventType, membership: &Membership, actor: &EventActor) -> Event {
+    let mut event = Event::new(event_type, &membership.organization_id, actor);
+    event.member_id = Some(membership.id.clone());
+    event.user_id = membership.user_id.clone();
+    event
+}
+
+/// One entry of the answer to a bulk member request; `error` is empty on success.
+fn bulk_entry(id: Option<&str>, error: Option<String>) -> Value {
+    json!({
+        "object": "OrganizationBulkConfirmResponseModel",
+        "id": id,
+        "error": error.unwrap_or_default(),
+    })
+}
+
+fn bulk_response(entries: Vec<Value>) -> Json<Value> {
+    Json(json!({
+        "data": entries,
+        "object": "list",
+        "continuationToken": null,
+    }))
+}
+
+/// Separate the failures that only concern one entry of a bulk request from those
+/// (such as the database failing) that end the whole request.
+fn entry_result<T>(result: Result<T, AppError>) -> Result<Result<T, String>, AppError> {
+    match result {
+        Ok(value) => Ok(Ok(value)),
+        Err(AppError::BadRequest(message) | AppError::NotFound(message)) => Ok(Err(message)),
+        Err(e) => Err(e),
+    }
+}
+
+/// Whether `email` has the shape of an email address.
+fn is_email_address(email: &str) -> bool {
+    match email.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && !domain.is_empty()
+                && !domain.contains('@')
+                && !email.chars().any(char::is_whitespace)
+        }
+        None => false,
+    }
+}
+
+fn org_keys_json(org: &Organization) -> Value {
+    json!({
+        "object": "organizationKeys",
+        "publicKey": &org.public_key,
+        "privateKey": &org.private_key,
+    })
+}
+
+// ── Organizations ───────────────────────────────────────────────────
+
+/// POST /api/organizations
+#[worker::send]
+pub async fn create_organization(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Json(payload): Json<CreateOrganizationRequest>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    ensure_can_create_organization(&db, &claims.sub).await?;
+
+    let name = payload.name.trim().to_string();
+    if name.is_empty() {
+        return Err(AppError::BadRequest(
+            "Organization name is required".to_string(),
+        ));
+    }
+
+    let mut org = Organization::new(name, payload.billing_email.trim().to_lowercase());
+    if let Some(OrgKeyData {
+        encrypted_private_key,
+        public_key,
+    }) = payload.keys
+    {
+        org.private_key = Some(encrypted_private_key);
+        org.public_key = Some(public_key);
+    }
+
+    let mut owner = Membership::new(
+        org.id.clone(),
+        Some(claims.sub.clone()),
+        claims.email.to_lowercase(),
+        MembershipType::Owner,
+        MembershipStatus::Confirmed,
+    );
+    owner.akey = Some(payload.key);
+
+    org.insert(&db).await?;
+    owner.insert(&db).await?;
+
+    db::touch_user_updated_at(&db, &claims.sub, &org.created_at).await?;
+
+    Ok(Json(org.to_json()))
+}
+
+/// GET /api/organizations/{org_id}
+#[worker::send]
+pub async fn get_organization(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(org_id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Admin,
+        Permission::ALL,
+    )
+    .await?;
+    let org = fetch_organization(&db, &org_id).await?;
+    Ok(Json(org.to_json()))
+}
+
+/// PUT/POST /api/organizations/{org_id}
+#[worker::send]
+pub async fn update_organization(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    headers: HeaderMap,
+    Path(org_id): Path<String>,
+    Json(payload): Json<UpdateOrganizationRequest>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_role(&db, &org_id, &claims.sub, MembershipType::Owner).await?;
+    let mut org = fetch_organization(&db, &org_id).await?;
+
+    let name = payload.name.trim().to_string();
+    if name.is_empty() {
+        return Err(AppError::BadRequest(
+            "Organization name is required".to_string(),
+        ));
+    }
+    org.name = name;
+    if let Some(billing_email) = payload.billing_email {
+        org.billing_email = billing_email.trim().to_lowercase();
+    }
+    org.update(&db).await?;
+
+    Membership::touch_confirmed_users(&db, &org.id, &org.updated_at).await?;
+    let actor = EventActor::from_request(&claims, &headers);
+    Event::new(EventType::OrganizationUpdated, &org.id, &actor)
+        .record(&db)
+        .await;
+
+    Ok(Json(org.to_json()))
+}
+
+/// DELETE /api/organizations/{org_id} (also POST /api/organizations/{org_id}/delete)
+///
+/// Requires the owner's master password; removes all org ciphers and memberships.
+#[worker::send]
+pub async fn delete_organization(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(org_id): Path<String>,
+    Json(payload): Json<PasswordOrOtpData>,
+) -> Result<Json<()>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_role(&db, &org_id, &claims.sub, MembershipType::Owner).await?;
+    let org = fetch_organization(&db, &org_id).await?;
+
+    let user = load_user(&db, &claims.sub).await?;
+    verify_user(&db, &user, &payload).await?;
+
+    let member_ids = Membership::confirmed_user_ids(&db, &org.id).await?;
+
+    if attachments::attachments_enabled(env.as_ref()) {
+        let keys = attachments::list_attachment_keys_for_organization(&db, &org.id).await?;
+        attachments::delete_storage_objects(env.as_ref(), &keys).await?;
+    }
+
+    org.delete(&db).await?;
+
+    let now = db::now_string();
+    for user_id in member_ids {
+        db::touch_user_updated_at(&db, &user_id, &now).await?;
+        notifications::publish_user_update(
+            (*env).clone(),
+            user_id,
+            UpdateType::SyncVault,
+            now.clone(),
+            Some(claims.device.clone()),
+        );
+    }
+
+    Ok(Json(()))
+}
+
+/// POST /api/organizations/{org_id}/leave
+///
+/// Removes the caller's membership like [`delete_member`] does, except that the last
+/// confirmed owner cannot leave.
+#[worker::send]
+pub async fn leave_organization(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    headers: HeaderMap,
+    Path(org_id): Path<String>,
+) -> Result<Json<()>, AppError> {
+    let db = db::get_db(&env)?;
+    let membership = Membership::find_by_user_and_org(&db, &claims.sub, &org_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Organization not found".to_string()))?;
+
+    ensure_not_last_owner(&db, &membership).await?;
+    membership.delete(&db).await?;
+    member_event(
+        EventType::OrganizationUserLeft,
+        &membership,
+        &EventActor::from_request(&claims, &headers),
+    )
+    .record(&db)
+    .await;
+
+    let now = db::now_string();
+    db::touch_user_updated_at(&db, &claims.sub, &now).await?;
+    notifications::publish_user_update(
+        (*env).clone(),
+        claims.sub,
+        UpdateType::SyncVault,
+        now,
+        Some(claims.device),
+    );
+
+    Ok(Json(()))
+}
+
+/// GET /api/organizations/{org_id}/keys
+#[worker::send]
+pub async fn get_organization_keys(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(org_id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_role(&db, &org_id, &claims.sub, MembershipType::User).await?;
+    let org = fetch_organization(&db, &org_id).await?;
+    Ok(Json(org_keys_json(&org)))
+}
+
+/// POST /api/organizations/{org_id}/keys
+///
+/// Lets an admin upload the org key pair for organizations created without one.
+#[worker::send]
+pub async fn post_organization_keys(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(org_id): Path<String>,
+    Json(payload): Json<OrgKeyData>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_role(&db, &org_id, &claims.sub, MembershipType::Admin).await?;
+    let mut org = fetch_organization(&db, &org_id).await?;
+
+    if org.has_keys() {
+        return Err(AppError::BadRequest(
+            "Organization keys already exist".to_string(),
+        ));
+    }
+
+    org.private_key = Some(payload.encrypted_private_key);
+    org.public_key = Some(payload.public_key);
+    org.update(&db).await?;
+
+    Ok(Json(org_keys_json(&org)))
+}
+
+/// GET /api/organizations/{org_id}/public-key
+#[worker::send]
+pub async fn get_organization_public_key(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(org_id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_role(&db, &org_id, &claims.sub, MembershipType::User).await?;
+    let org = fetch_organization(&db, &org_id).await?;
+    Ok(Json(json!({
+        "object": "organizationPublicKey",
+        "publicKey": org.public_key,
+    })))
+}
+
+/// GET /api/organizations/{org_id}/auto-enroll-status
+///
+/// Whether members are enrolled in account recovery when they accept an invitation.
+#[worker::send]
+pub async fn get_auto_enroll_status(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(org_id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    Membership::find_by_user_and_org(&db, &claims.sub, &org_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Organization not found".to_string()))?;
+    Ok(Json(json!({
+        "id": org_id,
+        "resetPasswordEnabled": reset_password_auto_enroll(&db, &org_id).await?,
+    })))
+}
+
+// ── Members ─────────────────────────────────────────────────────────
+
+/// GET /api/organizations/{org_id}/users
+#[worker::send]
+pub async fn list_members(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(org_id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Manager,
+        &[
+            Permission::ManageUsers,
+            Permission::ManageGroups,
+            Permission::ManageResetPassword,
+        ],
+    )
+    .await?;
+
+    let memberships = Membership::list_by_org(&db, &org_id).await?;
+    let mut data = Vec::with_capacity(memberships.len());
+    for membership in &memberships {
+        data.push(member_details_json(&db, membership).await?);
+    }
+
+    Ok(Json(json!({
+        "data": data,
+        "object": "list",
+        "continuationToken": null,
+    })))
+}
+
+/// GET /api/organizations/{org_id}/users/{member_id}
+#[worker::send]
+pub async fn get_member(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path((org_id, member_id)): Path<(String, String)>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Manager,
+        &[
+            Permission::ManageUsers,
+            Permission::ManageGroups,
+            Permission::ManageResetPassword,
+        ],
+    )
+    .await?;
+    let membership = fetch_member(&db, &org_id, &member_id).await?;
+    Ok(Json(member_details_json(&db, &membership).await?))
+}
+
+/// POST /api/organizations/{org_id}/users/invite
+///
+/// Answers with one entry per address. An address that cannot be invited, because it
+/// is not an email address or already belongs to the organization, gets an error in
+/// its entry without stopping the others.
+#[worker::send]
+pub async fn invite_members(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    headers: HeaderMap,
+    Extension(BaseUrl(base_url)): Extension<BaseUrl>,
+    Path(org_id): Path<String>,
+    Json(payload): Json<InviteRequest>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let actor = require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Admin,
+        &[Permission::ManageUsers],
+    )
+    .await?;
+    let org = fetch_organization(&db, &org_id).await?;
+
+    let member_type = MembershipType::from_i32(payload.r#type)
+        .ok_or_else(|| AppError::BadRequest("Invalid member type".to_string()))?;
+    ensure_can_manage(&actor, member_type)?;
+    let permissions = member_permissions(&actor, member_type, payload.permissions.as_ref())?;
+    ensure_org_groups(&db, &org_id, &payload.groups).await?;
+    ensure_org_collections(&db, &org_id, &payload.collections).await?;
+    if let Some(seats) = org.seats {
+        let mut emails: Vec<String> = payload
+            .emails
+            .iter()
+            .map(|e| e.trim().to_lowercase())
+            .filter(|e| !e.is_empty())
+            .collect();
+        emails.sort();
+        emails.dedup();
+        let occupied = Membership::count_by_org(&db, &org_id).await? as i64;
+        if occupied + emails.len() as i64 > seats {
+            return Err(AppError::BadRequest(format!(
+                "The organization's plan is limited to {seats} seats"
+            )));
+        }
+    }
+    let event_actor = EventActor::from_request(&claims, &headers);
+
+    let mut results = Vec::with_capacity(payload.emails.len());
+    for email in &payload.emails {
+        let email = email.trim().to_lowercase();
+        if email.is_empty() {
+            continue;
+        }
+        let refused = |error: String| {
+            let mut entry = bulk_entry(None, Some(error));
+            entry["email"] = Value::String(email.clone());
+            entry
+        };
+
+        if !is_email_address(&email) {
+            results.push(refused(format!("Invalid email address: {email}")));
+            continue;
+        }
+        if Membership::find_by_email_and_org(&db, &email, &org_id)
+            .await?
+            .is_some()
+        {
+            results.push(refused(format!("User already invited: {email}")));
+            continue;
+        }
+
+        // Existing users are accepted on their behalf unless a policy stands in the way;
+        // then the invitation waits until they can accept it themselves.
+        let (user_id, status) = match User::find_by_email(&db, &email).await? {
+            Some(user) => {
+                match ensure_user_allowed_in_org(&db, &user.id, &org_id, member_type).await {
+                    Ok(()) => (Some(user.id), MembershipStatus::Accepted),
+                    Err(AppError::BadRequest(_)) => (None, MembershipStatus::Invited),
+                    Err(e) => return Err(e),
+                }
+            }
+            None => (None, MembershipStatus::Invited),
+        };
+
+        let mut membership =
+            Membership::new(org_id.clone(), user_id, email.clone(), member_type, status);
+        membership.permissions = permissions;
+        let access = collection_access_rows(&membership.id, payload.collections.clone());
+        membership.insert(&db).await?;
+        CollectionAccess::replace_for_membership(&db, &membership.id, &access).await?;
+        Group::set_for_membership(&db, &membership.id, &payload.groups).await?;
+        member_event(
+            EventType::OrganizationUserInvited,
+            &membership,
+            &event_actor,
+        )
+        .record(&db)
+        .await;
+        if status == MembershipStatus::Invited {
+            send_invite_email(&env, &base_url, &org, &membership)?;
+        }
+        let mut entry = bulk_entry(Some(&membership.id), None);
+        entry["email"] = Value::String(email);
+        results.push(entry);
+    }
+
+    Ok(bulk_response(results))
+}
+
+/// POST /api/organizations/{org_id}/users/{member_id}/reinvite
+///
+/// Emails a new accept link, which also restarts the expiration of the invitation.
+/// If the invited address has registered in the meantime, the invitation is
+/// accepted on its behalf instead.
+#[worker::send]
+pub async fn reinvite_member(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Extension(BaseUrl(base_url)): Extension<BaseUrl>,
+    Path((org_id, member_id)): Path<(String, String)>,
+) -> Result<Json<()>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Admin,
+        &[Permission::ManageUsers],
+    )
+    .await?;
+    let org = fetch_organization(&db, &org_id).await?;
+    reinvite(&env, &db, &base_url, &org, &member_id).await?;
+    Ok(Json(()))
+}
+
+/// POST /api/organizations/{org_id}/users/reinvite - [`reinvite_member`] for several
+/// members, with one entry per member
+#[worker::send]
+pub async fn reinvite_members(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Extension(BaseUrl(base_url)): Extension<BaseUrl>,
+    Path(org_id): Path<String>,
+    Json(payload): Json<BulkMemberIds>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Admin,
+        &[Permission::ManageUsers],
+    )
+    .await?;
+    let org = fetch_organization(&db, &org_id).await?;
+
+    let mut results = Vec::with_capacity(payload.ids.len());
+    for member_id in &payload.ids {
+        let error = entry_result(reinvite(&env, &db, &base_url, &org, member_id).await)?.err();
+        results.push(bulk_entry(Some(member_id), error));
+    }
+    Ok(bulk_response(results))
+}
+
+async fn reinvite(
+    env: &Env,
+    db: &db::Db,
+    base_url: &str,
+    org: &Organization,
+    member_id: &str,
+) -> Result<(), AppError> {
+    let mut membership = fetch_member(db, &org.id, member_id).await?;
+
+    if membership.status != MembershipStatus::Invited as i32 {
+        return Err(AppError::BadRequest(
+            "The user is already accepted or confirmed to the organization".to_string(),
+        ));
+    }
+
+    if let Some(user) = User::find_by_email(db, &membership.email).await? {
+        ensure_user_allowed_in_org(db, &user.id, &org.id, membership.membership_type()).await?;
+        membership.user_id = Some(user.id);
+        membership.status = MembershipStatus::Accepted as i32;
+        membership.update(db).await?;
+    } else {
+        membership.update(db).await?;
+        send_invite_email(env, base_url, org, &membership)?;
+    }
+    Ok(())
+}
+
+// ── Invitation emails ───────────────────────────────────────────────
+
+const INVITE_TOKEN_ISSUER: &str = "warden-worker-org-invite";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct InviteClaims {
+    /// Membership id of the invitation.
+    sub: String,
+    org_id: String,
+    email: String,
+    iss: String,
+}
+
+/// Hours an invitation stays valid, from `ORG_INVITATION_EXPIRATION_HOURS` (default 120).
+fn invitation_expiration_hours(env: &Env) -> i64 {
+    get_env_usize(env, "ORG_INVITATION_EXPIRATION_HOURS", 120).max(1) as i64
+}
+
+fn build_invite_token(env: &Env, membership: &Membership) -> Result<String, AppError> {
+    let claims = JwtClaims::new(InviteClaims {
+        sub: membership.id.clone(),
+        org_id: membership.organization_id.clone(),
+        email: membership.email.clone(),
+        iss: INVITE_TOKEN_ISSUER.to_string(),
+    })
+    .set_duration_and_issuance(
+        &jwt_time_options(),
+        Duration::hours(invitation_expiration_hours(env)),
+    )
+    .set_not_before(Utc::now());
+
+    let secret = env.secret("JWT_SECRET")?.to_string();
+    let key = Hs256Key::new(secret.as_bytes());
+    jwt_compact::alg::Hs256
+        .token(&Header::empty(), &claims, &key)
+        .map_err(|_| AppError::Crypto("Failed to create invitation token".to_string()))
+}
+
+/// Whether `raw_token` is an unexpired accept token for this invitation.
+fn check_invite_token(
+    env: &Env,
+    membership: &Membership,
+    raw_token: &str,
+) -> Result<bool, AppError> {
+    let secret = env.secret("JWT_SECRET")?.to_string();
+    let key = Hs256Key::new(secret.as_bytes());
+    let Ok(token) = UntrustedToken::new(raw_token) else {
+        return Ok(false);
+    };
+    let Ok(token) = jwt_compact::alg::Hs256
+        .validator::<InviteClaims>(&key)
+        .validate(&token)
+    else {
+        return Ok(false);
+    };
+    let time_options = jwt_time_options();
+    if token.claims().validate_expiration(&time_options).is_err()
+        || token.claims().validate_maturity(&time_options).is_err()
+    {
+        return Ok(false);
+    }
+
+    let claims = token.into_parts().1.custom;
+    Ok(claims.iss == INVITE_TOKEN_ISSUER
+        && claims.sub == membership.id
+        && claims.org_id == membership.organization_id
+        && claims.email.eq_ignore_ascii_case(&membership.email))
+}
+
+/// Email the web vault link accepting `membership`. Does nothing when email delivery
+/// is not configured.
+fn send_invite_email(
+    env: &Env,
+    base_url: &str,
+    org: &Organization,
+    membership: &Membership,
+) -> Result<(), AppError> {
+    if !mail::mail_configured(env) {
+        return Ok(());
+    }
+
+    let params = UrlSearchParams::new().map_err(|_| AppError::Internal)?;
+    params.append("organizationId", &membership.organization_id);
+    params.append("organizationUserId", &membership.id);
+    params.append("email", &membership.email);
+    params.append("organizationName", &org.name);
+    params.append("token", &build_invite_token(env, membership)?);
+    let query: String = params.to_string().into();
+    let link = format!(
+        "{}/#/accept-organization/?{query}",
+        base_url.trim_end_matches('/')
+    );
+
+    mail::send_in_background(
+        env.clone(),
+        membership.email.clone(),
+        mail::Template::OrgInvite {
+            org_name: &org.name,
+            link: &link,
+            expiration_hours: invitation_expiration_hours(env),
+        },
+    );
+    Ok(())
+}
+
+/// Delete invitations nobody accepted within `ORG_INVITATION_EXPIRATION_HOURS`.
+pub async fn expire_stale_invites(env: &Env) -> Result<u32, worker::Error> {
+    let db = db::get_db(env).map_err(|e| worker::Error::RustError(e.to_string()))?;
+    let cutoff = (Utc::now() - Duration::hours(invitation_expiration_hours(env)))
+        .format("%Y-%m-%dT%H:%M:%S%.3fZ")
+        .to_string();
+
+    let count = Membership::delete_invited_before(&db, &cutoff)
+        .await
+        .map_err(|e| worker::Error::RustError(e.to_string()))?;
+    if count > 0 {
+        log::info!("Expired {} organization invitation(s)", count);
+    }
+    Ok(count)
+}
+
+/// POST /api/organizations/{org_id}/users/{member_id}/accept
+///
+/// The logged-in user accepts an invitation addressed to their own email, with the
+/// token of the emailed link. With an auto-enrolling ResetPassword policy the body
+/// must carry the `resetPasswordKey`.
+#[worker::send]
+pub async fn accept_invite(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path((org_id, member_id)): Path<(String, String)>,
+    payload: Option<Json<AcceptInviteRequest>>,
+) -> Result<Json<()>, AppError> {
+    let payload = payload.map(|Json(p)| p).unwrap_or_default();
+    let db = db::get_db(&env)?;
+    let mut membership = fetch_member(&db, &org_id, &member_id).await?;
+    let user = load_user(&db, &claims.sub).await?;
+
+    if !membership.email.eq_ignore_ascii_case(&user.email) {
+        return Err(AppError::BadRequest(
+            "This invitation was sent to a different email address".to_string(),
+        ));
+    }
+    if membership.status != MembershipStatus::Invited as i32 {
+        return Err(AppError::BadRequest(
+            "The invitation has already been accepted".to_string(),
+        ));
+    }
+    // Only emailed invitations are still pending here: SSO logins and registration with
+    // the invited address link their memberships as accepted without this endpoint.
+    let valid_token = match payload.token.as_deref().filter(|t| !t.is_empty()) {
+        Some(token) => check_invite_token(&env, &membership, token)?,
+        None => false,
+    };
+    if !valid_token {
+        return Err(AppError::BadRequest(
+            "The invitation link is invalid or has expired".to_string(),
+        ));
+    }
+    ensure_user_allowed_in_org(&db, &user.id, &org_id, membership.membership_type()).await?;
+
+    let reset_password_key = payload.reset_password_key.filter(|k| !k.is_empty());
+    if reset_password_key.is_none() && reset_password_auto_enroll(&db, &org_id).await? {
+        return Err(AppError::BadRequest(
+            "This organization requires enrolling in account recovery".to_string(),
+        ));
+    }
+
+    membership.user_id = Some(user.id);
+    membership.status = MembershipStatus::Accepted as i32;
+    if reset_password_key.is_some() {
+        membership.reset_password_key = reset_password_key;
+    }
+    membership.update(&db).await?;
+
+    Ok(Json(()))
+}
+
+/// POST /api/organizations/{org_id}/users/{member_id}/confirm
+///
+/// Stores the org key encrypted for the member, granting access to org ciphers.
+#[worker::send]
+pub async fn confirm_member(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    headers: HeaderMap,
+    Path((org_id, member_id)): Path<(String, String)>,
+    Json(payload): Json<ConfirmMemberRequest>,
+) -> Result<Json<()>, AppError> {
+    let db = db::get_db(&env)?;
+    let actor = require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Admin,
+        &[Permission::ManageUsers],
+    )
+    .await?;
+    let event_actor = EventActor::from_request(&claims, &headers);
+    confirm(
+        &env,
+        &db,
+        &claims,
+        &actor,
+        &event_actor,
+        &member_id,
+        payload.key,
+    )
+    .await?;
+    Ok(Json(()))
+}
+
+/// POST /api/organizations/{org_id}/users/confirm - [`confirm_member`] for several
+/// members, with one entry per member
+#[worker::send]
+pub async fn confirm_members(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    headers: HeaderMap,
+    Path(org_id): Path<String>,
+    Json(payload): Json<BulkConfirmRequest>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let actor = require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Admin,
+        &[Permission::ManageUsers],
+    )
+    .await?;
+    let event_actor = EventActor::from_request(&claims, &headers);
+
+    let mut results = Vec::with_capacity(payload.keys.len());
+    for entry in payload.keys {
+        let confirmed = confirm(
+            &env,
+            &db,
+            &claims,
+            &actor,
+            &event_actor,
+            &entry.id,
+            entry.key,
+        )
+        .await;
+        results.push(bulk_entry(Some(&entry.id), entry_result(confirmed)?.err()));
+    }
+    Ok(bulk_response(results))
+}
+
+/// Confirm the accepted member `member_id` of the actor's organization with the org
+/// key encrypted for them.
+async fn confirm(
+    env: &Env,
+    db: &db::Db,
+    claims: &Claims,
+    actor: &Membership,
+    event_actor: &EventActor,
+    member_id: &str,
+    key: String,
+) -> Result<(), AppError> {
+    let org_id = &actor.organization_id;
+    let mut membership = fetch_member(db, org_id, member_id).await?;
+    ensure_can_manage(actor, membership.membership_type())?;
+
+    if membership.status != MembershipStatus::Accepted as i32 || membership.user_id.is_none() {
+        return Err(AppError::BadRequest(
+            "The user has not accepted the invitation yet".to_string(),
+        ));
+    }
+    if key.is_empty() {
+        return Err(AppError::BadRequest("Missing organization key".to_string()));
+    }
+    if let Some(user_id) = membership.user_id.as_deref() {
+        ensure_user_allowed_in_org(db, user_id, org_id, membership.membership_type()).await?;
+    }
+
+    membership.akey = Some(key);
+    membership.status = MembershipStatus::Confirmed as i32;
+    membership.update(db).await?;
+    member_event(
+        EventType::OrganizationUserConfirmed,
+        &membership,
+        event_actor,
+    )
+    .record(db)
+    .await;
+
+    if let Some(user_id) = membership.user_id.as_deref() {
+        db::touch_user_updated_at(db, user_id, &membership.updated_at).await?;
+    }
+    publish_membership_change(env, &membership, membership.updated_at.clone(), claims);
+    Ok(())
+}
+
+/// POST /api/organizations/{org_id}/users/public-keys
+///
+/// Public keys of the given members that have an account, which the admin console
+/// encrypts the org key with before a bulk confirmation.
+#[worker::send]
+pub async fn member_public_keys(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(org_id): Path<String>,
+    Json(payload): Json<BulkMemberIds>,
+) -> Result<Json<Value>, AppError> {
+    #[derive(Deserialize)]
+    struct MemberKey {
+        id: String,
+        user_id: String,
+        public_key: String,
+    }
+
+    let db = db::get_db(&env)?;
+    require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Admin,
+        &[Permission::ManageUsers],
+    )
+    .await?;
+    let ids = serde_json::to_string(&payload.ids).map_err(|_| AppError::Internal)?;
+    let keys: Vec<MemberKey> = d1_query!(
+        &db,
+        "SELECT uo.id, uo.user_id, u.public_key
+         FROM users_organizations uo
+         JOIN users u ON u.id = uo.user_id
+         WHERE uo.organization_id = ?1 AND uo.id IN (SELECT value FROM json_each(?2))",
+        org_id,
+        ids
+    )
+    .map_err(|_| AppError::Database)?
+    .all()
+    .await
+    .map_err(|_| AppError::Database)?
+    .results()
+    .map_err(|_| AppError::Database)?;
+
+    let data: Vec<Value> = keys
+        .into_iter()
+        .map(|key| {
+            json!({
+                "object": "organizationUserPublicKeyResponseModel",
+                "id": key.id,
+                "userId": key.user_id,
+                "key": key.public_key,
+            })
+        })
+        .collect();
+    Ok(bulk_response(data))
+}
+
+// ── Account recovery ────────────────────────────────────────────────
+
+/// PUT /api/organizations/{org_id}/users/{user_id}/reset-password-enrollment
+///
+/// The member enrolls in account recovery by handing over their user key encrypted
+/// with the org public key, or withdraws with a `null` key. Unlike the other member
+/// routes, the path carries the user id rather than the membership id.
+#[worker::send]
+pub async fn put_reset_password_enrollment(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    headers: HeaderMap,
+    Path((org_id, user_id)): Path<(String, String)>,
+    Json(payload): Json<ResetPasswordEnrollmentRequest>,
+) -> Result<Json<()>, AppError> {
+    if user_id != claims.sub {
+        return Err(AppError::BadRequest(
+            "Members can only change their own enrollment".to_string(),
+        ));
+    }
+    let db = db::get_db(&env)?;
+    let mut membership = Membership::find_by_user_and_org(&db, &claims.sub, &org_id)
+        .await?
+        .filter(|m| m.status >= MembershipStatus::Accepted as i32)
+        .ok_or_else(|| AppError::NotFound("Organization not found".to_string()))?;
+
+    let reset_password_key = payload.reset_password_key.filter(|k| !k.is_empty());
+    let event_type = match &reset_password_key {
+        Some(_) => {
+            let user = load_user(&db, &claims.sub).await?;
+            let provided_hash = payload
+                .master_password_hash
+                .ok_or_else(|| AppError::BadRequest("Missing master password hash".to_string()))?;
+            if !user
+                .verify_master_password(&provided_hash)
+                .await?
+                .is_valid()
+            {
+                return Err(AppError::Unauthorized("Invalid password".to_string()));
+            }
+            EventType::OrganizationUserResetPasswordEnroll
+        }
+        None => {
+            if reset_password_auto_enroll(&db, &org_id).await? {
+                return Err(AppError::BadRequest(
+                    "A policy of this organization does not allow withdrawing from account recovery"
+                        .to_string(),
+                ));
+            }
+            EventType::OrganizationUserResetPasswordWithdraw
+        }
+    };
+
+    membership.reset_password_key = reset_password_key;
+    membership.update(&db).await?;
+    member_event(
+        event_type,
+        &membership,
+        &EventActor::from_request(&claims, &headers),
+    )
+    .record(&db)
+    .await;
+
+    db::touch_user_updated_at(&db, &claims.sub, &membership.updated_at).await?;
+    publish_membership_change(&env, &membership, membership.updated_at.clone(), &claims);
+
+    Ok(Json(()))
+}
+
+/// Load an enrolled member whose password the caller may reset.
+async fn recovery_target(
+    db: &db::Db,
+    org_id: &str,
+    member_id: &str,
+    claims: &Claims,
+) -> Result<(Organization, Membership, User), AppError> {
+    let actor = require_member_access(
+        db,
+        org_id,
+        &claims.sub,
+        MembershipType::Admin,
+        &[Permission::ManageResetPassword],
+    )
+    .await?;
+    let org = fetch_organization(db, org_id).await?;
+    if !policy_enabled(db, org_id, PolicyType::ResetPassword).await? {
+        return Err(AppError::BadRequest(
+            "Account recovery is not enabled for this organization".to_string(),
+        ));
+    }
+    let membership = fetch_member(db, org_id, member_id).await?;
+    ensure_can_manage(&actor, membership.membership_type())?;
+    let user_id = match (&membership.user_id, &membership.reset_password_key) {
+        (Some(user_id), Some(_)) if membership.is_confirmed() => user_id.clone(),
+        _ => {
+            return Err(AppError::BadRequest(
+                "The member is not enrolled in account recovery".to_string(),
+            ))
+        }
+    };
+    if user_id == claims.sub {
+        return Err(AppError::BadRequest(
+            "Use the account settings to change your own master password".to_string(),
+        ));
+    }
+    let user = load_user(db, &user_id).await?;
+    Ok((org, membership, user))
+}
+
+/// GET /api/organizations/{org_id}/users/{member_id}/reset-password-details
+///
+/// What the admin's client needs to derive the new master password: the member's KDF
+/// settings, their recovery key and the (encrypted) org private key.
+#[worker::send]
+pub async fn get_reset_password_details(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path((org_id, member_id)): Path<(String, String)>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let (org, membership, user) = recovery_target(&db, &org_id, &member_id, &claims).await?;
+    Ok(Json(json!({
+        "organizationUserId": &membership.id,
+        "kdf": user.kdf_type,
+        "kdfIterations": user.kdf_iterations,
+        "kdfMemory": user.kdf_memory,
+        "kdfParallelism": user.kdf_parallelism,
+        "resetPasswordKey": &membership.reset_password_key,
+        "encryptedPrivateKey": &org.private_key,
+        "object": "organizationUserResetPasswordDetails",
+    })))
+}
+
+/// PUT /api/organizations/{org_id}/users/{member_id}/reset-password
+///
+/// Sets a new master password for an enrolled member. The member is logged out
+/// everywhere and has to choose their own password at the next login.
+#[worker::send]
+pub async fn put_reset_password(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    headers: HeaderMap,
+    Path((org_id, member_id)): Path<(String, String)>,
+    Json(payload): Json<AdminResetPasswordRequest>,
+) -> Result<Json<()>, AppError> {
+    let db = db::get_db(&env)?;
+    let (org, membership, user) = recovery_target(&db, &org_id, &member_id, &claims).await?;
+    if payload.new_master_password_hash.is_empty() || payload.key.is_empty() {
+        return Err(AppError::BadRequest(
+            "Missing new master password or key".to_string(),
+        ));
+    }
+
+    let new_salt = generate_salt()?;
+    let password_iterations = server_password_iterations(&env) as i32;
+    let new_hashed_password = hash_password_for_storage(
+        &payload.new_master_password_hash,
+        &new_salt,
+        password_iterations as u32,
+    )
+    .await?;
+    let now = db::now_string();
+
+    d1_query!(
+        &db,
+        "UPDATE users SET master_password_hash = ?1, password_salt = ?2, password_iterations = ?3, key = ?4, security_stamp = ?5, force_password_reset = 1, updated_at = ?6 WHERE id = ?7",
+        new_hashed_password,
+        new_salt,
+        password_iterations,
+        payload.key,
+        uuid::Uuid::new_v4().to_string(),
+        now,
+        &user.id
+    )
+    .map_err(|_| AppError::Database)?
+    .run()
+    .await?;
+
+    RefreshToken::revoke_all_by_user(&db, &user.id).await?;
+    notifications::publish_user_logout((*env).clone(), user.id.clone(), now, None);
+
+    member_event(
+        EventType::OrganizationUserAdminResetPassword,
+        &membership,
+        &EventActor::from_request(&claims, &headers),
+    )
+    .record(&db)
+    .await;
+    mail::send_in_background(
+        (*env).clone(),
+        user.email.clone(),
+        mail::Template::AdminResetPassword {
+            org_name: &org.name,
+        },
+    );
+
+    Ok(Json(()))
+}
+
+/// PUT/POST /api/organizations/{org_id}/users/{member_id}
+#[worker::send]
+pub async fn edit_member(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    headers: HeaderMap,
+    Path((org_id, member_id)): Path<(String, String)>,
+    Json(payload): Json<EditMemberRequest>,
+) -> Result<Json<()>, AppError> {
+    let db = db::get_db(&env)?;
+    let actor = require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Admin,
+        &[Permission::ManageUsers],
+    )
+    .await?;
+    let mut membership = fetch_member(&db, &org_id, &member_id).await?;
+
+    let new_type = MembershipType::from_i32(payload.r#type)
+        .ok_or_else(|| AppError::BadRequest("Invalid member type".to_string()))?;
+    ensure_can_manage(&actor, membership.membership_type())?;
+    ensure_can_manage(&actor, new_type)?;
+    let permissions = member_permissions(&actor, new_type, payload.permissions.as_ref())?;
+
+    if new_type != MembershipType::Owner {
+        ensure_not_last_owner(&db, &membership).await?;
+    }
+    // Losing the admin role also loses the policy exemption.
+    if let Some(user_id) = membership.user_id.as_deref() {
+        ensure_user_allowed_in_org(&db, user_id, &org_id, new_type).await?;
+    }
+
+    membership.r#type = new_type as i32;
+    membership.permissions = permissions;
+    membership.update(&db).await?;
+
+    if let Some(collections) = payload.collections {
+        let access = member_collection_access(&db, &org_id, &membership.id, collections).await?;
+        CollectionAccess::replace_for_membership(&db, &membership.id, &access).await?;
+    }
+    if let Some(groups) = payload.groups {
+        ensure_org_groups(&db, &org_id, &groups).await?;
+        Group::set_for_membership(&db, &membership.id, &groups).await?;
+    }
+    member_event(
+        EventType::OrganizationUserUpdated,
+        &membership,
+        &EventActor::from_request(&claims, &headers),
+    )
+    .record(&db)
+    .await;
+
+    if let Some(user_id) = membership.user_id.as_deref() {
+        db::touch_user_updated_at(&db, user_id, &membership.updated_at).await?;
+    }
+    publish_membership_change(&env, &membership, membership.updated_at.clone(), &claims);
+
+    Ok(Json(()))
+}
+
+/// GET /api/organizations/{org_id}/users/{member_id}/groups - group ids of a member
+#[worker::send]
+pub async fn get_member_groups(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path((org_id, member_id)): Path<(String, String)>,
+) -> Result<Json<Vec<String>>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Manager,
+        &[Permission::ManageUsers, Permission::ManageGroups],
+    )
+    .await?;
+    let membership = fetch_member(&db, &org_id, &member_id).await?;
+    Ok(Json(Group::ids_for_membership(&db, &membership.id).await?))
+}
+
+/// PUT/POST /api/organizations/{org_id}/users/{member_id}/groups
+#[worker::send]
+pub async fn put_member_groups(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    headers: HeaderMap,
+    Path((org_id, member_id)): Path<(String, String)>,
+    Json(payload): Json<MemberGroupsRequest>,
+) -> Result<Json<()>, AppError> {
+    let db = db::get_db(&env)?;
+    let actor = require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Admin,
+        &[Permission::ManageUsers, Permission::ManageGroups],
+    )
+    .await?;
+    let membership = fetch_member(&db, &org_id, &member_id).await?;
+    ensure_can_manage(&actor, membership.membership_type())?;
+    ensure_org_groups(&db, &org_id, &payload.group_ids).await?;
+
+    Group::set_for_membership(&db, &membership.id, &payload.group_ids).await?;
+    member_event(
+        EventType::OrganizationUserUpdatedGroups,
+        &membership,
+        &EventActor::from_request(&claims, &headers),
+    )
+    .record(&db)
+    .await;
+
+    let now = db::now_string();
+    if let Some(user_id) = membership.user_id.as_deref() {
+        db::touch_user_updated_at(&db, user_id, &now).await?;
+    }
+    publish_membership_change(&env, &membership, now, &claims);
+
+    Ok(Json(()))
+}
+
+/// DELETE /api/organizations/{org_id}/users/{member_id} (also POST .../delete)
+#[worker::send]
+pub async fn delete_member(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    headers: HeaderMap,
+    Path((org_id, member_id)): Path<(String, String)>,
+) -> Result<Json<()>, AppError> {
+    let db = db::get_db(&env)?;
+    let actor = require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Admin,
+        &[Permission::ManageUsers],
+    )
+    .await?;
+    let membership = fetch_member(&db, &org_id, &member_id).await?;
+    ensure_can_manage(&actor, membership.membership_type())?;
+    ensure_not_last_owner(&db, &membership).await?;
+
+    membership.delete(&db).await?;
+    member_event(
+        EventType::OrganizationUserRemoved,
+        &membership,
+        &EventActor::from_request(&claims, &headers),
+    )
+    .record(&db)
+    .await;
+
+    let now = db::now_string();
+    if let Some(user_id) = membership.user_id.as_deref() {
+        db::touch_user_updated_at(&db, user_id, &now).await?;
+    }
+    publish_membership_change(&env, &membership, now, &claims);
+
+    Ok(Json(()))
+}
+
+/// GET /api/users/{user_id}/public-key
+///
+/// Used by admins to encrypt the org key for a member during confirmation.
+#[worker::send]
+pub async fn get_user_public_key(
+    _claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(user_id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let public_key: Option<String> =
+        d1_query!(&db, "SELECT public_key FROM users WHERE id = ?1", user_id)
+            .map_err(|_| AppError::Database)?
+            .first(Some("public_key"))
+            .await
+            .map_err(|_| AppError::Database)?;
+    let public_key =
+        public_key.ok_or_else(|| AppError::NotFound("User doesn't exist".to_string()))?;
+
+    Ok(Json(json!({
+        "userId": user_id,
+        "publicKey": public_key,
+        "object": "userKey",
+    })))
+}
@@ -0,0 +1,418 @@
+//! Organization policies.
+//!
+//! Policies are stored per organization and handed to clients in `/api/sync`, which
+//! apply the client-side ones (password generator, vault export, ...). The rules the
+//! server can check itself are enforced here:
+//!
+//! - TwoFactorAuthentication: members need 2FA to be accepted or confirmed, enabling
+//!   the policy removes members without it, and removing the last 2FA method removes
+//!   the user from the organization.
+//! - SingleOrg: members cannot belong to another organization or create one.
+//! - MasterPassword: the combined requirements are returned at login so clients can
+//!   force a password change (`enforceOnLogin`).
+//! - ResetPassword: admins can only reset the master password of enrolled members
+//!   while it is enabled; with `autoEnrollEnabled`, accepting an invitation requires
+//!   enrolling and members cannot withdraw.
+//!
+//! Owners and admins are exempt from every policy but MasterPassword.
+
+use axum::extract::{Path, State};
+use axum::http::HeaderMap;
+use axum::Json;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use worker::Env;
+
+use crate::auth::Claims;
+use crate::db;
+use crate::error::AppError;
+use crate::handlers::organizations::require_member_access;
+use crate::handlers::two_factor_enabled;
+use crate::mail;
+use crate::models::event::{Event, EventActor, EventType};
+use crate::models::organization::{
+    Membership, MembershipStatus, MembershipType, OrgFeature, Organization, Permission,
+};
+use crate::models::policy::{
+    MasterPasswordPolicyData, MaximumVaultTimeoutPolicyData, OrgPolicy, PolicyRequest, PolicyType,
+    PolicyVNextRequest, ResetPasswordPolicyData,
+};
+use crate::notifications::{self, UpdateType};
+
+const TWO_FACTOR_REASON: &str = "the organization requires two-step login";
+const SINGLE_ORG_REASON: &str =
+    "the organization does not allow its members to belong to other organizations";
+
+fn parse_policy_type(value: i32) -> Result<PolicyType, AppError> {
+    PolicyType::from_i32(value)
+        .ok_or_else(|| AppError::BadRequest("Invalid policy type".to_string()))
+}
+
+pub(crate) async fn policy_enabled(
+    db: &db::Db,
+    org_id: &str,
+    policy_type: PolicyType,
+) -> Result<bool, AppError> {
+    Ok(OrgPolicy::find_by_org_and_type(db, org_id, policy_type)
+        .await?
+        .is_some_and(|p| p.enabled))
+}
+
+/// Whether the ResetPassword policy of `org_id` is enabled with automatic enrollment.
+pub(crate) async fn reset_password_auto_enroll(
+    db: &db::Db,
+    org_id: &str,
+) -> Result<bool, AppError> {
+    let Some(policy) = OrgPolicy::find_by_org_and_type(db, org_id, PolicyType::ResetPassword)
+        .await?
+        .filter(|p| p.enabled)
+    else {
+        return Ok(false);
+    };
+    Ok(
+        serde_json::from_value::<ResetPasswordPolicyData>(policy.data_json())
+            .unwrap_or_default()
+            .auto_enroll_enabled,
+    )
+}
+
+/// Check that the policies of `org_id` and of the user's other organizations let the
+/// user hold a `member_type` membership in `org_id`.
+pub(crate) async fn ensure_user_allowed_in_org(
+    db: &db::Db,
+    user_id: &str,
+    org_id: &str,
+    member_type: MembershipType,
+) -> Result<(), AppError> {
+    let exempt = member_type.is_at_least(MembershipType::Admin);
+    if !exempt {
+        if policy_enabled(db, org_id, PolicyType::TwoFactorAuthentication).await?
+            && !two_factor_enabled(db, user_id).await?
+        {
+            return Err(AppError::BadRequest(
+                "Two-step login must be enabled to join this organization".to_string(),
+            ));
+        }
+        if policy_enabled(db, org_id, PolicyType::SingleOrg).await?
+            && Membership::count_other_memberships(db, user_id, org_id).await? > 0
+        {
+            return Err(AppError::BadRequest(
+                "Members of this organization cannot belong to other organizations".to_string(),
+            ));
+        }
+    }
+
+    let bound_elsewhere = OrgPolicy::list_enforced_for_user(db, user_id, PolicyType::SingleOrg)
+        .await?
+        .iter()
+        .any(|p| p.organization_id != org_id);
+    if bound_elsewhere {
+        return Err(AppError::BadRequest(
+            "A policy of another organization forbids joining additional organizations".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Refuse to create an organization for users bound by a SingleOrg policy.
+pub(crate) async fn ensure_can_create_organization(
+    db: &db::Db,
+    user_id: &str,
+) -> Result<(), AppError> {
+    if !OrgPolicy::list_enforced_for_user(db, user_id, PolicyType::SingleOrg)
+        .await?
+        .is_empty()
+    {
+        return Err(AppError::BadRequest(
+            "A policy of your organization forbids creating or joining other organizations"
+                .to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Remove a member who no longer meets a policy of the organization.
+async fn remove_for_policy(
+    env: &Env,
+    db: &db::Db,
+    org: &Organization,
+    membership: &Membership,
+    actor: Option<&EventActor>,
+    reason: &str,
+) -> Result<(), AppError> {
+    membership.delete(db).await?;
+    if let Some(actor) = actor {
+        let mut event = Event::new(EventType::OrganizationUserRemoved, &org.id, actor);
+        event.member_id = Some(membership.id.clone());
+        event.user_id = membership.user_id.clone();
+        event.record(db).await;
+    }
+
+    let now = db::now_string();
+    if let Some(user_id) = membership.user_id.clone() {
+        db::touch_user_updated_at(db, &user_id, &now).await?;
+        notifications::publish_user_update(
+            env.clone(),
+            user_id,
+            UpdateType::SyncOrgKeys,
+            now,
+            None,
+        );
+    }
+    mail::send_in_background(
+        env.clone(),
+        membership.email.clone(),
+        mail::Template::RemovedByPolicy {
+            org_name: &org.name,
+            reason,
+        },
+    );
+    log::info!(
+        "Removed member {} from organization {}: {reason}",
+        membership.id,
+        org.id
+    );
+    Ok(())
+}
+
+/// Remove the members of `org` that break a policy that was just enabled.
+async fn enforce_enabled_policy(
+    env: &Env,
+    db: &db::Db,
+    org: &Organization,
+    policy_type: PolicyType,
+    actor: &EventActor,
+) -> Result<(), AppError> {
+    let reason = match policy_type {
+        PolicyType::TwoFactorAuthentication => TWO_FACTOR_REASON,
+        PolicyType::SingleOrg => SINGLE_ORG_REASON,
+        _ => return Ok(()),
+    };
+
+    for membership in Membership::list_by_org(db, &org.id).await? {
+        let Some(user_id) = membership.user_id.as_deref() else {
+            continue;
+        };
+        if membership.status < MembershipStatus::Accepted as i32
+            || membership
+                .membership_type()
+                .is_at_least(MembershipType::Admin)
+        {
+            continue;
+        }
+        let violates = match policy_type {
+            PolicyType::TwoFactorAuthentication => !two_factor_enabled(db, user_id).await?,
+            _ => Membership::count_other_memberships(db, user_id, &org.id).await? > 0,
+        };
+        if violates {
+            remove_for_policy(env, db, org, &membership, Some(actor), reason).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Remove a user who just lost their last 2FA method from organizations requiring it.
+pub(crate) async fn enforce_two_factor_policy(
+    env: &Env,
+    db: &db::Db,
+    user_id: &str,
+) -> Result<(), AppError> {
+    let policies =
+        OrgPolicy::list_enforced_for_user(db, user_id, PolicyType::TwoFactorAuthentication).await?;
+    for policy in policies {
+        let Some(org) = Organization::find_by_id(db, &policy.organization_id).await? else {
+            continue;
+        };
+        if let Some(membership) = Membership::find_by_user_and_org(db, user_id, &org.id).await? {
+            remove_for_policy(env, db, &org, &membership, None, TWO_FACTOR_REASON).await?;
+        }
+    }
+    Ok(())
+}
+
+/// `policies` of the sync response: enabled policies of the user's organizations.
+pub(crate) async fn user_policies_json(db: &db::Db, user_id: &str) -> Result<Vec<Value>, AppError> {
+    Ok(OrgPolicy::list_enabled_for_user(db, user_id)
+        .await?
+        .iter()
+        .map(OrgPolicy::to_json)
+        .collect())
+}
+
+/// `MasterPasswordPolicy` of the token response: the strictest combination of the
+/// MasterPassword policies of the user's organizations.
+pub(crate) async fn master_password_policy_json(
+    db: &db::Db,
+    user_id: &str,
+) -> Result<Value, AppError> {
+    let policies =
+        OrgPolicy::list_enforced_for_user(db, user_id, PolicyType::MasterPassword).await?;
+    Ok(match MasterPasswordPolicyData::merge(&policies) {
+        Some(data) => data.to_token_json(),
+        None => json!({ "Object": "masterPasswordPolicy" }),
+    })
+}
+
+/// The shortest vault timeout, in minutes, the MaximumVaultTimeout policies of the
+/// user's organizations allow.
+pub(crate) async fn max_vault_timeout_minutes(
+    db: &db::Db,
+    user_id: &str,
+) -> Result<Option<i64>, AppError> {
+    let policies =
+        OrgPolicy::list_enforced_for_user(db, user_id, PolicyType::MaximumVaultTimeout).await?;
+    Ok(MaximumVaultTimeoutPolicyData::strictest_minutes(&policies))
+}
+
+/// GET /api/organizations/{org_id}/policies
+#[worker::send]
+pub async fn list_policies(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(org_id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Admin,
+        &[Permission::ManagePolicies],
+    )
+    .await?;
+
+    let policies = OrgPolicy::list_by_org(&db, &org_id).await?;
+    Ok(Json(json!({
+        "data": policies.iter().map(OrgPolicy::to_json).collect::<Vec<_>>(),
+        "object": "list",
+        "continuationToken": null,
+    })))
+}
+
+/// GET /api/organizations/{org_id}/policies/{type}
+#[worker::send]
+pub async fn get_policy(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path((org_id, policy_type)): Path<(String, i32)>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_access(
+        &db,
+        &org_id,
+        &claims.sub,
+        MembershipType::Admin,
+        &[Permission::ManagePolicies],
+    )
+    .await?;
+    let policy_type = parse_policy_type(policy_type)?;
+
+    let policy = OrgPolicy::find_by_org_and_type(&db, &org_id, policy_type)
+        .await?
+        .unwrap_or_else(|| OrgPolicy::new(&org_id, policy_type));
+    Ok(Json(policy.to_json()))
+}
+
+/// PUT /api/organizations/{org_id}/policies/{type}
+#[worker::send]
+pub async fn put_policy(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    headers: HeaderMap,
+    Path((org_id, policy_type)): Path<(String, i32)>,
+    Json(payload): Json<PolicyRequest>,
+) -> Result<Json<Value>, AppError> {
+    save_policy(&claims, &env, &headers, &org_id, policy_type, payload).await
+}
+
+/// PUT /api/organizations/{org_id}/policies/{type}/vnext
+#[worker::send]
+pub async fn put_policy_vnext(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    headers: HeaderMap,
+    Path((org_id, policy_type)): Path<(String, i32)>,
+    Json(payload): Json<PolicyVNextRequest>,
+) -> Result<Json<Value>, AppError> {
+    save_policy(
+        &claims,
+        &env,
+        &headers,
+        &org_id,
+        policy_type,
+        payload.policy,
+    )
+    .await
+}
+
+async fn save_policy(
+    claims: &Claims,
+    env: &Arc<Env>,
+    headers: &HeaderMap,
+    org_id: &str,
+    policy_type: i32,
+    payload: PolicyRequest,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(env)?;
+    require_member_access(
+        &db,
+        org_id,
+        &claims.sub,
+        MembershipType::Admin,
+        &[Permission::ManagePolicies],
+    )
+    .await?;
+    let policy_type = parse_policy_type(policy_type)?;
+    let org = Organization::find_by_id(&db, org_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Organization not found".to_string()))?;
+    org.ensure_feature(OrgFeature::Policies)?;
+
+    // Depends on SSO, which this server does not have.
+    if payload.enabled && policy_type == PolicyType::RequireSso {
+        return Err(AppError::BadRequest(
+            "This policy is not supported by this server".to_string(),
+        ));
+    }
+
+    let data = payload.data.filter(|d| !d.is_null());
+    if let Some(data) = &data {
+        let valid = match policy_type {
+            PolicyType::MasterPassword => {
+                serde_json::from_value::<MasterPasswordPolicyData>(data.clone()).is_ok()
+            }
+            PolicyType::ResetPassword => {
+                serde_json::from_value::<ResetPasswordPolicyData>(data.clone()).is_ok()
+            }
+            PolicyType::MaximumVaultTimeout => {
+                serde_json::from_value::<MaximumVaultTimeoutPolicyData>(data.clone())
+                    .is_ok_and(|data| data.is_valid())
+            }
+            _ => true,
+        };
+        if !valid {
+            return Err(AppError::BadRequest("Invalid policy data".to_string()));
+        }
+    }
+
+    let actor = EventActor::from_request(claims, headers);
+    if payload.enabled {
+        enforce_enabled_policy(env, &db, &org, policy_type, &actor).await?;
+    }
+
+    let mut policy = OrgPolicy::find_by_org_and_type(&db, org_id, policy_type)
+        .await?
+        .unwrap_or_else(|| OrgPolicy::new(org_id, policy_type));
+    policy.enabled = payload.enabled;
+    policy.data = data.map(|d| d.to_string());
+    policy.save(&db).await?;
+
+    let mut event = Event::new(EventType::PolicyUpdated, org_id, &actor);
+    event.policy_id = Some(policy.id.clone());
+    event.record(&db).await;
+
+    // Members pick up the new policy on their next sync.
+    Membership::touch_confirmed_users(&db, org_id, &policy.updated_at).await?;
+
+    Ok(Json(policy.to_json()))
+}
@@ -4,25 +4,61 @@
 //! soft-deleted (marked with deleted_at) for longer than the configured
 //! retention period.
 
-use crate::db::now_string;
+use crate::db::{format_time, now_string};
+use crate::error::AppError;
+use crate::handlers::accounts::delete_user_data;
 use crate::handlers::attachments::{
-    attachments_enabled, delete_storage_objects, list_attachment_keys_for_soft_deleted_before,
+    attachments_enabled, delete_storage_objects, list_attachment_keys_for_cipher_ids_json,
+    list_pending_attachment_keys_created_before, ATTACHMENTS_BUCKET,
 };
+use crate::handlers::get_env_usize;
+use crate::jobs::{load_cursor, save_cursor};
+use crate::logging;
+use crate::metrics::{self, Metric};
 use crate::models::auth_request::AuthRequest;
+use crate::models::event::Event;
+use crate::models::multipart_upload::{MultipartUpload, SESSION_LIFETIME_HOURS};
+use crate::models::refresh_token::RefreshToken;
 use crate::models::send::SendDB;
+use crate::models::sync::SyncState;
 use crate::notifications::{self, UpdateType};
-use chrono::{Duration, Utc};
+use chrono::{DateTime, Duration, Utc};
+use serde_json::{json, Value};
 
 use std::collections::HashSet;
-use worker::Env;
+use worker::{D1Result, Env};
 
 use crate::d1_query;
+/// Log a `purge` event for `count` removed records of `kind`, with any extra `fields`.
+fn log_purged(kind: &str, count: u32, fields: Value) {
+    let mut fields = match fields {
+        Value::Object(map) => map,
+        _ => serde_json::Map::new(),
+    };
+    fields.insert("kind".to_string(), json!(kind));
+    fields.insert("count".to_string(), json!(count));
+    metrics::observe(Metric::Purged, count as f64, &[kind]);
+    logging::event(log::Level::Info, "purge", Value::Object(fields));
+}
+
 /// Default number of days to keep soft-deleted items before purging
 const DEFAULT_PURGE_DAYS: i64 = 30;
+/// Default number of ciphers deleted per batch by the trash purge
+const DEFAULT_PURGE_BATCH_SIZE: usize = 500;
+/// Default seconds the trash purge may run before leaving the rest to the next run
+const DEFAULT_PURGE_TIME_BUDGET_SECS: usize = 20;
+/// `job_cursors` name of the trash purge's continuation marker
+const TRASH_CURSOR_JOB: &str = "deleted_ciphers";
 /// Retain pending attachments for at most this many days before cleanup
 const PENDING_RETENTION_DAYS: i64 = 1;
 /// Retain auth requests for at most this many minutes before cleanup
 const AUTH_REQUEST_RETENTION_MINUTES: i64 = 15;
+/// Default number of days to keep organization events
+const DEFAULT_EVENTS_RETENTION_DAYS: i64 = 365;
+/// Default number of days to keep delta sync tombstones
+const DEFAULT_SYNC_TOMBSTONE_RETENTION_DAYS: i64 = 30;
+/// Default number of days between an account deletion request and the data wipe
+const DEFAULT_ACCOUNT_DELETION_GRACE_DAYS: i64 = 7;
 
 /// Get the purge threshold days from environment variable or use default
 fn get_purge_days(env: &Env) -> i64 {
@@ -32,12 +68,42 @@ fn get_purge_days(env: &Env) -> i64 {
         .unwrap_or(DEFAULT_PURGE_DAYS)
 }
 
+/// Get the account deletion grace period from environment variable or use default
+fn get_account_deletion_grace_days(env: &Env) -> i64 {
+    env.var("ACCOUNT_DELETION_GRACE_DAYS")
+        .ok()
+        .and_then(|v| v.to_string().parse::<i64>().ok())
+        .filter(|days| *days >= 0)
+        .unwrap_or(DEFAULT_ACCOUNT_DELETION_GRACE_DAYS)
+}
+
+/// Get the event retention period from environment variable or use default
+fn get_events_retention_days(env: &Env) -> i64 {
+    env.var("EVENTS_RETENTION_DAYS")
+        .ok()
+        .and_then(|v| v.to_string().parse::<i64>().ok())
+        .unwrap_or(DEFAULT_EVENTS_RETENTION_DAYS)
+}
+
+/// Get the sync tombstone retention period from environment variable or use default
+fn get_sync_tombstone_retention_days(env: &Env) -> i64 {
+    env.var("SYNC_TOMBSTONE_RETENTION_DAYS")
+        .ok()
+        .and_then(|v| v.to_string().parse::<i64>().ok())
+        .filter(|days| *days > 0)
+        .unwrap_or(DEFAULT_SYNC_TOMBSTONE_RETENTION_DAYS)
+}
+
+/// Pending uploads with a `created_at` before this cutoff are stale: the client had
+/// [`PENDING_RETENTION_DAYS`] to finish them.
+fn pending_cutoff(now: DateTime<Utc>) -> String {
+    format_time(now - Duration::days(PENDING_RETENTION_DAYS))
+}
+
 /// Purge pending attachments older than the configured retention window.
 pub async fn purge_stale_pending_attachments(env: &Env) -> Result<u32, worker::Error> {
     let db = crate::db::get_db(env).map_err(|e| worker::Error::RustError(e.to_string()))?;
-    let now = Utc::now();
-    let pending_cutoff = now - Duration::days(PENDING_RETENTION_DAYS);
-    let pending_cutoff_str = pending_cutoff.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
+    let pending_cutoff_str = pending_cutoff(Utc::now());
 
     let pending_count_result = d1_query!(
         &db,
@@ -51,6 +117,16 @@ pub async fn purge_stale_pending_attachments(env: &Env) -> Result<u32, worker::E
     let pending_count = pending_count_result.map(|r| r.count).unwrap_or(0);
 
     if pending_count > 0 {
+        if attachments_enabled(env) {
+            let keys = list_pending_attachment_keys_created_before(&db, &pending_cutoff_str)
+                .await
+                .map_err(|e| worker::Error::RustError(e.to_string()))?;
+
+            delete_storage_objects(env, &keys)
+                .await
+                .map_err(|e| worker::Error::RustError(e.to_string()))?;
+        }
+
         d1_query!(
             &db,
             "DELETE FROM attachments_pending WHERE created_at < ?1",
@@ -59,147 +135,372 @@ pub async fn purge_stale_pending_attachments(env: &Env) -> Result<u32, worker::E
         .map_err(|e| worker::Error::RustError(e.to_string()))?
         .run()
         .await?;
-        log::info!(
-            "Purged {} pending attachment(s) older than {} day(s)",
-            pending_count,
-            PENDING_RETENTION_DAYS
-        );
-    } else {
-        log::info!("No pending attachments to purge");
     }
+    log_purged(
+        "pending_attachments",
+        pending_count,
+        json!({ "retentionDays": PENDING_RETENTION_DAYS }),
+    );
 
     Ok(pending_count)
 }
 
-/// Purge soft-deleted ciphers that are older than the configured threshold.
+/// What one run of [`purge_deleted_ciphers`] removed, per category.
+#[derive(Debug, Default)]
+pub struct CipherPurgeReport {
+    /// Soft-deleted ciphers past the retention period.
+    pub ciphers: u32,
+    /// Attachment rows of purged ciphers, plus rows whose cipher no longer exists.
+    pub attachments: u32,
+    /// Attachment objects removed from storage.
+    pub attachment_blobs: u32,
+    /// `ciphers_collections` rows of purged ciphers or pointing at a missing cipher
+    /// or collection.
+    pub collection_links: u32,
+    /// Ciphers whose `folder_id` pointed at a missing folder and was cleared.
+    pub folder_references: u32,
+}
+
+impl CipherPurgeReport {
+    pub fn total(&self) -> u32 {
+        self.ciphers
+            + self.attachments
+            + self.attachment_blobs
+            + self.collection_links
+            + self.folder_references
+    }
+}
+
+fn changes(result: &D1Result) -> u32 {
+    result
+        .meta()
+        .ok()
+        .flatten()
+        .and_then(|m| m.changes)
+        .unwrap_or(0) as u32
+}
+
+/// Purge soft-deleted ciphers that are older than the configured threshold, then
+/// clean up rows left pointing at data that no longer exists.
 ///
 /// This function:
 /// 1. Calculates the cutoff timestamp based on TRASH_AUTO_DELETE_DAYS env var (default: 30 days)
-/// 2. Deletes all ciphers where deleted_at is not null and older than the cutoff
+/// 2. Deletes the attachment blobs, attachment rows, collection links and finally the
+///    ciphers where deleted_at is not null and older than the cutoff, in batches, and
+///    stops early when the time budget is used up (see [`purge_expired_trash`])
 /// 3. Updates the affected users' updated_at to trigger client sync
 /// 4. If TRASH_AUTO_DELETE_DAYS is set to 0 or negative, skips purging (disabled)
-///
-/// Returns the number of purged records on success.
-pub async fn purge_deleted_ciphers(env: &Env) -> Result<u32, worker::Error> {
-    let purge_days = get_purge_days(env);
+/// 5. Once no expired trash is left, runs the integrity pass: orphaned attachments (and
+///    their blobs), orphaned collection links, and folder references to deleted folders
+pub async fn purge_deleted_ciphers(env: &Env) -> Result<CipherPurgeReport, worker::Error> {
+    let db = crate::db::get_db(env).map_err(|e| worker::Error::RustError(e.to_string()))?;
+    let mut report = CipherPurgeReport::default();
 
+    let purge_days = get_purge_days(env);
     // If purge_days is 0 or negative, auto-purge is disabled
-    if purge_days <= 0 {
+    let trash_done = if purge_days <= 0 {
         log::info!("Auto-purge is disabled (TRASH_AUTO_DELETE_DAYS <= 0)");
-        return Ok(0);
+        true
+    } else {
+        purge_expired_trash(env, &db, purge_days, &mut report).await?
+    };
+    // The integrity pass waits until an interrupted trash purge has caught up.
+    if trash_done {
+        purge_orphaned_cipher_data(env, &db, &mut report).await?;
     }
 
-    let db = crate::db::get_db(env).map_err(|e| worker::Error::RustError(e.to_string()))?;
-
-    // Calculate the cutoff timestamp
-    let now = Utc::now();
-    let cutoff = now - Duration::days(purge_days);
-    let cutoff_str = cutoff.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
-    let now_str = now.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
-
-    log::info!(
-        "Purging soft-deleted ciphers older than {} days (before {})",
-        purge_days,
-        cutoff_str
+    logging::event(
+        log::Level::Info,
+        "cipher_purge",
+        json!({
+            "ciphers": report.ciphers,
+            "attachments": report.attachments,
+            "attachmentBlobs": report.attachment_blobs,
+            "collectionLinks": report.collection_links,
+            "folderReferences": report.folder_references,
+            "trashDone": trash_done,
+        }),
     );
+    Ok(report)
+}
 
-    // First, get the list of affected user IDs before deletion
-    let affected_users_result: Vec<AffectedUser> = d1_query!(
-        &db,
-        "SELECT DISTINCT user_id FROM ciphers WHERE deleted_at IS NOT NULL AND deleted_at < ?1 AND user_id IS NOT NULL",
-        cutoff_str
-    )
-    .map_err(|e| worker::Error::RustError(e.to_string()))?
-    .all()
-    .await?
-    .results()?;
-
-    let affected_user_ids: HashSet<String> = affected_users_result
-        .into_iter()
-        .filter_map(|u| u.user_id)
-        .collect();
+/// Position of the last purged cipher, in `(deleted_at, id)` order.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct TrashCursor {
+    deleted_at: String,
+    id: String,
+}
 
-    // Count the records to be deleted (for logging purposes)
-    let count_result = d1_query!(
-        &db,
-        "SELECT COUNT(*) as count FROM ciphers WHERE deleted_at IS NOT NULL AND deleted_at < ?1",
-        cutoff_str
-    )
-    .map_err(|e| worker::Error::RustError(e.to_string()))?
-    .first::<CountResult>(None)
-    .await?;
+#[derive(serde::Deserialize)]
+struct TrashRow {
+    id: String,
+    user_id: Option<String>,
+    deleted_at: String,
+}
 
-    let count = count_result.map(|r| r.count).unwrap_or(0);
+/// Delete expired trash in batches of `PURGE_BATCH_SIZE` ciphers until none are left
+/// or `PURGE_TIME_BUDGET_SECONDS` have passed. An unfinished purge saves its position
+/// in `job_cursors` and the next run continues from there. Returns whether all
+/// expired ciphers were purged.
+async fn purge_expired_trash(
+    env: &Env,
+    db: &crate::db::Db,
+    purge_days: i64,
+    report: &mut CipherPurgeReport,
+) -> Result<bool, worker::Error> {
+    let to_worker_error = |e: AppError| worker::Error::RustError(e.to_string());
 
-    if count > 0 {
-        if attachments_enabled(env) {
-            let keys = list_attachment_keys_for_soft_deleted_before(&db, &cutoff_str)
-                .await
-                .map_err(|e| worker::Error::RustError(e.to_string()))?;
+    // Calculate the cutoff timestamp
+    let started = Utc::now();
+    let cutoff = started - Duration::days(purge_days);
+    let cutoff_str = cutoff.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
+    let batch_size = get_env_usize(env, "PURGE_BATCH_SIZE", DEFAULT_PURGE_BATCH_SIZE).max(1);
+    let budget = Duration::seconds(get_env_usize(
+        env,
+        "PURGE_TIME_BUDGET_SECONDS",
+        DEFAULT_PURGE_TIME_BUDGET_SECS,
+    ) as i64);
+
+    let mut cursor = load_cursor(db, TRASH_CURSOR_JOB)
+        .await
+        .map_err(to_worker_error)?
+        .and_then(|raw| serde_json::from_str::<TrashCursor>(&raw).ok());
+    match &cursor {
+        Some(c) => log::info!(
+            "Resuming purge of soft-deleted ciphers older than {} days after {} ({})",
+            purge_days,
+            c.deleted_at,
+            c.id
+        ),
+        None => log::info!(
+            "Purging soft-deleted ciphers older than {} days (before {})",
+            purge_days,
+            cutoff_str
+        ),
+    }
 
-            delete_storage_objects(env, &keys)
-                .await
-                .map_err(|e| worker::Error::RustError(e.to_string()))?;
+    let mut affected_user_ids: HashSet<String> = HashSet::new();
+    let finished = loop {
+        let (after_deleted_at, after_id) = cursor
+            .as_ref()
+            .map(|c| (c.deleted_at.as_str(), c.id.as_str()))
+            .unwrap_or(("", ""));
+        let rows: Vec<TrashRow> = d1_query!(
+            db,
+            "SELECT id, user_id, deleted_at FROM ciphers
+             WHERE deleted_at IS NOT NULL AND deleted_at < ?1 AND (deleted_at, id) > (?2, ?3)
+             ORDER BY deleted_at, id LIMIT ?4",
+            cutoff_str,
+            after_deleted_at,
+            after_id,
+            batch_size as u32
+        )
+        .map_err(|e| worker::Error::RustError(e.to_string()))?
+        .all()
+        .await?
+        .results()?;
+        let Some(last) = rows.last() else {
+            break true;
+        };
+        let next_cursor = TrashCursor {
+            deleted_at: last.deleted_at.clone(),
+            id: last.id.clone(),
+        };
+
+        let ids: Vec<&str> = rows.iter().map(|row| row.id.as_str()).collect();
+        let ids_json =
+            serde_json::to_string(&ids).map_err(|e| worker::Error::RustError(e.to_string()))?;
+        purge_cipher_batch(env, db, &ids_json, report).await?;
+        affected_user_ids.extend(rows.iter().filter_map(|row| row.user_id.clone()));
+
+        if rows.len() < batch_size {
+            break true;
+        }
+        cursor = Some(next_cursor);
+        if Utc::now() - started >= budget {
+            break false;
         }
+    };
 
-        // Delete the records
+    let saved = if finished {
+        None
+    } else {
+        cursor.as_ref().and_then(|c| serde_json::to_string(c).ok())
+    };
+    save_cursor(db, TRASH_CURSOR_JOB, saved.as_deref())
+        .await
+        .map_err(to_worker_error)?;
+    log_purged(
+        "trash",
+        report.ciphers,
+        json!({ "retentionDays": purge_days, "finished": finished }),
+    );
+
+    // Update the affected users' updated_at to trigger client sync
+    let now_str = now_string();
+    for user_id in &affected_user_ids {
         d1_query!(
-            &db,
-            "DELETE FROM ciphers WHERE deleted_at IS NOT NULL AND deleted_at < ?1",
-            cutoff_str
+            db,
+            "UPDATE users SET updated_at = ?1 WHERE id = ?2",
+            now_str,
+            user_id
         )
         .map_err(|e| worker::Error::RustError(e.to_string()))?
         .run()
         .await?;
 
-        log::info!("Successfully purged {} soft-deleted cipher(s)", count);
+        notifications::publish_user_update(
+            env.clone(),
+            user_id.clone(),
+            UpdateType::SyncVault,
+            now_str.clone(),
+            None,
+        );
+    }
+    Ok(finished)
+}
+
+/// Delete the ciphers in `ids_json` (a JSON array) with their attachments and
+/// collection links.
+async fn purge_cipher_batch(
+    env: &Env,
+    db: &crate::db::Db,
+    ids_json: &str,
+    report: &mut CipherPurgeReport,
+) -> Result<(), worker::Error> {
+    if attachments_enabled(env) {
+        let keys = list_attachment_keys_for_cipher_ids_json(db, ids_json, "$", None)
+            .await
+            .map_err(|e| worker::Error::RustError(e.to_string()))?;
+        delete_storage_objects(env, &keys)
+            .await
+            .map_err(|e| worker::Error::RustError(e.to_string()))?;
+        report.attachment_blobs += keys.len() as u32;
+    }
 
-        // Update the affected users' updated_at to trigger client sync
-        for user_id in &affected_user_ids {
+    // Dependent rows would cascade, but deleting them explicitly lets us count them.
+    let ids = "SELECT value FROM json_each(?1)";
+    let results = db
+        .batch(vec![
             d1_query!(
-                &db,
-                "UPDATE users SET updated_at = ?1 WHERE id = ?2",
-                now_str,
-                user_id
+                db,
+                &format!("DELETE FROM attachments WHERE cipher_id IN ({ids})"),
+                ids_json
             )
-            .map_err(|e| worker::Error::RustError(e.to_string()))?
-            .run()
-            .await?;
-
-            notifications::publish_user_update(
-                env.clone(),
-                user_id.clone(),
-                UpdateType::SyncVault,
-                now_str.clone(),
-                None,
-            );
+            .map_err(|e| worker::Error::RustError(e.to_string()))?,
+            d1_query!(
+                db,
+                &format!("DELETE FROM ciphers_collections WHERE cipher_id IN ({ids})"),
+                ids_json
+            )
+            .map_err(|e| worker::Error::RustError(e.to_string()))?,
+            d1_query!(
+                db,
+                &format!("DELETE FROM ciphers WHERE id IN ({ids})"),
+                ids_json
+            )
+            .map_err(|e| worker::Error::RustError(e.to_string()))?,
+        ])
+        .await?;
+    if let [attachments, links, ciphers] = results.as_slice() {
+        report.attachments += changes(attachments);
+        report.collection_links += changes(links);
+        report.ciphers += changes(ciphers);
+    }
+    Ok(())
+}
+
+/// Remove rows that reference ciphers, collections or folders that no longer exist,
+/// e.g. left behind by deletes that ran before foreign keys were enforced.
+async fn purge_orphaned_cipher_data(
+    env: &Env,
+    db: &crate::db::Db,
+    report: &mut CipherPurgeReport,
+) -> Result<(), worker::Error> {
+    let orphaned_attachments = "FROM attachments WHERE cipher_id NOT IN (SELECT id FROM ciphers)";
+
+    if attachments_enabled(env) {
+        let rows: Vec<AttachmentRow> =
+            d1_query!(db, &format!("SELECT cipher_id, id {orphaned_attachments}"))
+                .all()
+                .await?
+                .results()?;
+        let keys: Vec<String> = rows
+            .into_iter()
+            .map(|row| format!("{}/{}", row.cipher_id, row.id))
+            .collect();
+        if !keys.is_empty() {
+            delete_storage_objects(env, &keys)
+                .await
+                .map_err(|e| worker::Error::RustError(e.to_string()))?;
+            report.attachment_blobs += keys.len() as u32;
         }
+    }
 
-        log::info!(
-            "Updated revision date for {} affected user(s)",
-            affected_user_ids.len()
-        );
-    } else {
-        log::info!("No soft-deleted ciphers to purge");
+    let results = db
+        .batch(vec![
+            d1_query!(db, &format!("DELETE {orphaned_attachments}")),
+            d1_query!(
+                db,
+                "DELETE FROM ciphers_collections
+                 WHERE cipher_id NOT IN (SELECT id FROM ciphers)
+                    OR collection_id NOT IN (SELECT id FROM collections)"
+            ),
+            d1_query!(
+                db,
+                "UPDATE ciphers SET folder_id = NULL
+                 WHERE folder_id IS NOT NULL AND folder_id NOT IN (SELECT id FROM folders)"
+            ),
+        ])
+        .await?;
+    if let [attachments, links, folders] = results.as_slice() {
+        report.attachments += changes(attachments);
+        report.collection_links += changes(links);
+        report.folder_references += changes(folders);
+    }
+    Ok(())
+}
+
+/// What one run of [`purge_expired_sends`] did, per category.
+#[derive(Debug, Default)]
+pub struct SendPurgeReport {
+    /// Sends past their deletion date, deleted.
+    pub deleted: u32,
+    /// File objects removed from storage. These belong to Sends already counted
+    /// in `deleted`, so this is a breakdown of that count, not a separate category.
+    pub files: u32,
+    /// Sends past their expiration date, but not yet their deletion date, that were
+    /// disabled.
+    pub disabled: u32,
+}
+
+impl SendPurgeReport {
+    /// Number of Sends the run acted on. `files` is excluded because those Sends
+    /// are already part of `deleted`.
+    pub fn total(&self) -> u32 {
+        self.deleted + self.disabled
     }
+}
 
-    Ok(count)
+#[derive(serde::Deserialize)]
+struct DisabledSend {
+    id: String,
+    user_id: String,
 }
 
-pub async fn purge_expired_sends(env: &Env) -> Result<u32, worker::Error> {
+/// Delete Sends past their deletion date, with their files, and disable the ones past
+/// their expiration date that are not due for deletion yet, so that nothing can reach
+/// them (including file download links handed out before they expired). Owners can
+/// enable such a Send again after moving its expiration date.
+pub async fn purge_expired_sends(env: &Env) -> Result<SendPurgeReport, worker::Error> {
     let db = crate::db::get_db(env).map_err(|e| worker::Error::RustError(e.to_string()))?;
     let now = now_string();
+    let mut report = SendPurgeReport::default();
 
     let expired = SendDB::find_expired(&db)
         .await
         .map_err(|e| worker::Error::RustError(e.to_string()))?;
-
-    if expired.is_empty() {
-        log::info!("No expired sends to purge");
-        return Ok(0);
-    }
-
-    let count = expired.len() as u32;
+    report.deleted = expired.len() as u32;
 
     if attachments_enabled(env) {
         let keys: Vec<String> = expired.iter().filter_map(|s| s.storage_key()).collect();
@@ -207,31 +508,71 @@ pub async fn purge_expired_sends(env: &Env) -> Result<u32, worker::Error> {
             delete_storage_objects(env, &keys)
                 .await
                 .map_err(|e| worker::Error::RustError(e.to_string()))?;
+            report.files = keys.len() as u32;
         }
     }
 
-    let mut user_ids = std::collections::HashSet::new();
-    for send in &expired {
-        user_ids.insert(send.user_id.clone());
-    }
-
     for send in &expired {
         send.delete(&db)
             .await
             .map_err(|e| worker::Error::RustError(e.to_string()))?;
     }
 
-    for uid in &user_ids {
+    let disabled: Vec<DisabledSend> = d1_query!(
+        &db,
+        "UPDATE sends SET disabled = 1, updated_at = ?1
+         WHERE disabled = 0 AND expiration_date IS NOT NULL AND expiration_date <= ?1
+           AND deletion_date > ?1
+         RETURNING id, user_id",
+        &now
+    )?
+    .all()
+    .await?
+    .results()?;
+    report.disabled = disabled.len() as u32;
+
+    let user_ids: HashSet<&str> = expired
+        .iter()
+        .map(|send| send.user_id.as_str())
+        .chain(disabled.iter().map(|send| send.user_id.as_str()))
+        .collect();
+    for uid in user_ids {
         let _ = db
             .prepare("UPDATE users SET updated_at = ?1 WHERE id = ?2")
-            .bind(&[now.clone().into(), uid.clone().into()])
+            .bind(&[now.clone().into(), uid.into()])
             .map_err(|e| worker::Error::RustError(e.to_string()))?
             .run()
             .await;
     }
 
-    log::info!("Purged {} expired send(s)", count);
-    Ok(count)
+    // Let connected clients catch up without waiting for a full sync
+    for send in expired {
+        notifications::publish_send_update(
+            env.clone(),
+            send.user_id,
+            UpdateType::SyncSendDelete,
+            send.id,
+            now.clone(),
+            None,
+        );
+    }
+    for send in disabled {
+        notifications::publish_send_update(
+            env.clone(),
+            send.user_id,
+            UpdateType::SyncSendUpdate,
+            send.id,
+            now.clone(),
+            None,
+        );
+    }
+
+    log_purged(
+        "sends",
+        report.deleted,
+        json!({ "files": report.files, "disabled": report.disabled }),
+    );
+    Ok(report)
 }
 
 pub async fn purge_expired_auth_requests(env: &Env) -> Result<u32, worker::Error> {
@@ -244,16 +585,103 @@ pub async fn purge_expired_auth_requests(env: &Env) -> Result<u32, worker::Error
         .await
         .map_err(|e| worker::Error::RustError(e.to_string()))?;
 
-    if count > 0 {
-        log::info!(
-            "Purged {} auth request(s) older than {} minute(s)",
-            count,
-            AUTH_REQUEST_RETENTION_MINUTES
-        );
-    } else {
-        log::info!("No expired auth requests to purge");
+    log_purged(
+        "auth_requests",
+        count,
+        json!({ "retentionMinutes": AUTH_REQUEST_RETENTION_MINUTES }),
+    );
+
+    Ok(count)
+}
+
+/// Permanently remove accounts whose deletion was requested longer ago than the
+/// grace period, together with their ciphers, folders, sends, stored files and devices.
+pub async fn purge_deleted_accounts(env: &Env) -> Result<u32, worker::Error> {
+    let db = crate::db::get_db(env).map_err(|e| worker::Error::RustError(e.to_string()))?;
+    let grace_days = get_account_deletion_grace_days(env);
+    let cutoff = (Utc::now() - Duration::days(grace_days))
+        .format("%Y-%m-%dT%H:%M:%S%.3fZ")
+        .to_string();
+
+    let user_ids: Vec<String> = d1_query!(
+        &db,
+        "SELECT id FROM users WHERE deletion_requested_at IS NOT NULL AND deletion_requested_at <= ?1",
+        cutoff
+    )
+    .map_err(|e| worker::Error::RustError(e.to_string()))?
+    .all()
+    .await?
+    .results::<IdRow>()?
+    .into_iter()
+    .map(|row| row.id)
+    .collect();
+
+    let mut count = 0;
+    for user_id in &user_ids {
+        // Keep going on failure; the account is retried on the next run.
+        match delete_user_data(env, &db, user_id).await {
+            Ok(()) => count += 1,
+            Err(e) => log::error!("Failed to delete account {user_id}: {e}"),
+        }
+    }
+
+    log_purged("accounts", count, json!({ "graceDays": grace_days }));
+
+    Ok(count)
+}
+
+/// Delete refresh tokens past their expiry. Revoked tokens are kept until then so
+/// that reuse of a revoked token is still recognised.
+pub async fn purge_expired_refresh_tokens(env: &Env) -> Result<u32, worker::Error> {
+    let db = crate::db::get_db(env).map_err(|e| worker::Error::RustError(e.to_string()))?;
+    let cutoff = now_string();
+
+    let count = RefreshToken::delete_expired_before(&db, &cutoff)
+        .await
+        .map_err(|e| worker::Error::RustError(e.to_string()))?;
+
+    log_purged("refresh_tokens", count, Value::Null);
+
+    Ok(count)
+}
+
+/// Delete organization events older than `EVENTS_RETENTION_DAYS`.
+pub async fn purge_expired_events(env: &Env) -> Result<u32, worker::Error> {
+    let retention_days = get_events_retention_days(env);
+    if retention_days <= 0 {
+        log::info!("Event retention disabled (EVENTS_RETENTION_DAYS <= 0)");
+        return Ok(0);
     }
 
+    let db = crate::db::get_db(env).map_err(|e| worker::Error::RustError(e.to_string()))?;
+    let cutoff = (Utc::now() - Duration::days(retention_days))
+        .format("%Y-%m-%dT%H:%M:%S%.3fZ")
+        .to_string();
+
+    let count = Event::delete_before(&db, &cutoff)
+        .await
+        .map_err(|e| worker::Error::RustError(e.to_string()))?;
+
+    log_purged("events", count, json!({ "retentionDays": retention_days }));
+
+    Ok(count)
+}
+
+/// Delete delta sync tombstones older than `SYNC_TOMBSTONE_RETENTION_DAYS`.
+///
+/// Clients that last synced before the pruned tombstones get a full sync next time.
+pub async fn purge_sync_tombstones(env: &Env) -> Result<u32, worker::Error> {
+    let db = crate::db::get_db(env).map_err(|e| worker::Error::RustError(e.to_string()))?;
+    let cutoff = (Utc::now() - Duration::days(get_sync_tombstone_retention_days(env)))
+        .format("%Y-%m-%dT%H:%M:%S%.3fZ")
+        .to_string();
+
+    let count = SyncState::prune_tombstones(&db, &cutoff)
+        .await
+        .map_err(|e| worker::Error::RustError(e.to_string()))?;
+
+    log_purged("sync_tombstones", count, Value::Null);
+
     Ok(count)
 }
 
@@ -280,19 +708,59 @@ pub async fn purge_stale_pending_sends(env: &Env) -> Result<u32, worker::Error>
         .await
         .map_err(|e| worker::Error::RustError(e.to_string()))?;
 
-    if count > 0 {
-        log::info!("Purged {} stale pending send(s)", count);
-    } else {
-        log::info!("No stale pending sends to purge");
+    log_purged("pending_sends", count, Value::Null);
+
+    Ok(count)
+}
+
+/// Abort R2 multipart uploads whose session has expired without being completed.
+pub async fn purge_stale_multipart_uploads(env: &Env) -> Result<u32, worker::Error> {
+    let db = crate::db::get_db(env).map_err(|e| worker::Error::RustError(e.to_string()))?;
+    let cutoff = (Utc::now() - Duration::hours(SESSION_LIFETIME_HOURS))
+        .format("%Y-%m-%dT%H:%M:%S%.3fZ")
+        .to_string();
+
+    let stale = MultipartUpload::find_created_before(&db, &cutoff)
+        .await
+        .map_err(|e| worker::Error::RustError(e.to_string()))?;
+    let bucket = env.bucket(ATTACHMENTS_BUCKET).ok();
+
+    let mut count = 0;
+    for session in &stale {
+        if let Some(bucket) = &bucket {
+            // An upload R2 already dropped cannot be aborted; the row still goes.
+            let aborted =
+                match bucket.resume_multipart_upload(&session.storage_key, &session.upload_id) {
+                    Ok(upload) => upload.abort().await,
+                    Err(e) => Err(e),
+                };
+            if let Err(e) = aborted {
+                log::warn!(
+                    "Failed to abort multipart upload of '{}': {e}",
+                    session.storage_key
+                );
+            }
+        }
+        MultipartUpload::delete(&db, &session.id)
+            .await
+            .map_err(|e| worker::Error::RustError(e.to_string()))?;
+        count += 1;
     }
 
+    log_purged(
+        "multipart_uploads",
+        count,
+        json!({ "retentionHours": SESSION_LIFETIME_HOURS }),
+    );
+
     Ok(count)
 }
 
-/// Helper struct for affected user query result
+/// Helper struct for attachment storage key query result
 #[derive(serde::Deserialize)]
-struct AffectedUser {
-    user_id: Option<String>,
+struct AttachmentRow {
+    cipher_id: String,
+    id: String,
 }
 
 /// Helper struct for count query result
@@ -300,3 +768,28 @@ struct AffectedUser {
 struct CountResult {
     count: u32,
 }
+
+/// Helper struct for id query result
+#[derive(serde::Deserialize)]
+struct IdRow {
+    id: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pending_upload_is_stale_only_after_the_retention_window() {
+        let now = Utc::now();
+        let cutoff = pending_cutoff(now);
+        let window = Duration::days(PENDING_RETENTION_DAYS);
+        let ms = Duration::milliseconds(1);
+
+        // `created_at < cutoff`, compared as stored strings.
+        assert!(format_time(now - window - ms) < cutoff);
+        assert!(format_time(now - window) >= cutoff);
+        assert!(format_time(now - window + ms) >= cutoff);
+        assert!(format_time(now) >= cutoff);
+    }
+}
@@ -0,0 +1,317 @@
+//! Organization reports that need the server to aggregate access: who can reach which
+//! collections and items.
+//!
+//! The password health reports (exposed, weak and reused passwords) are computed by
+//! the clients from the decrypted vault and need nothing from the server. Owners and
+//! admins are reported with manage access to every collection, as that is what they
+//! get (see [`crate::models::collection::Collection::list_for_user`]).
+
+use axum::extract::{Path, State};
+use axum::Json;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+use worker::{D1PreparedStatement, Env};
+
+use crate::auth::Claims;
+use crate::d1_query;
+use crate::db;
+use crate::error::AppError;
+use crate::handlers::organizations::require_member_access;
+use crate::models::organization::{MembershipType, Permission};
+
+#[derive(Deserialize)]
+struct ReportMember {
+    id: String,
+    user_id: Option<String>,
+    email: String,
+    name: Option<String>,
+    r#type: i32,
+    two_factor_enabled: i64,
+    account_recovery_enabled: i64,
+}
+
+impl ReportMember {
+    fn accesses_everything(&self) -> bool {
+        matches!(
+            MembershipType::from_i32(self.r#type),
+            Some(MembershipType::Owner | MembershipType::Admin)
+        )
+    }
+}
+
+/// One collection grant of a member, direct (`group_id` unset) or through a group. A
+/// group without collections has a row without `collection_id`.
+#[derive(Deserialize)]
+struct ReportAccess {
+    membership_id: String,
+    collection_id: Option<String>,
+    group_id: Option<String>,
+    group_name: Option<String>,
+    read_only: i64,
+    hide_passwords: i64,
+    manage: i64,
+}
+
+#[derive(Deserialize)]
+struct ReportCollection {
+    id: String,
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct CollectionCipher {
+    collection_id: String,
+    cipher_id: String,
+}
+
+/// Everything the reports of an organization are built from.
+struct AccessData {
+    members: Vec<ReportMember>,
+    access: Vec<ReportAccess>,
+    collections: Vec<ReportCollection>,
+    /// Ids of the live ciphers of each collection.
+    collection_ciphers: HashMap<String, Vec<String>>,
+    /// Ids of every live cipher of the organization, including unassigned ones.
+    all_ciphers: Vec<String>,
+}
+
+async fn rows<T: DeserializeOwned>(query: D1PreparedStatement) -> Result<Vec<T>, AppError> {
+    query
+        .all()
+        .await
+        .map_err(|_| AppError::Database)?
+        .results()
+        .map_err(|_| AppError::Database)
+}
+
+impl AccessData {
+    async fn load(db: &db::Db, org_id: &str) -> Result<Self, AppError> {
+        let members: Vec<ReportMember> = rows(
+            d1_query!(
+                db,
+                "SELECT uo.id, uo.user_id, uo.email, u.name, uo.type,
+                        EXISTS (SELECT 1 FROM twofactor t
+                                WHERE t.user_uuid = uo.user_id AND t.enabled = 1 AND t.atype < 1000)
+                            AS two_factor_enabled,
+                        uo.reset_password_key IS NOT NULL AS account_recovery_enabled
+                 FROM users_organizations uo
+                 LEFT JOIN users u ON u.id = uo.user_id
+                 WHERE uo.organization_id = ?1
+                 ORDER BY uo.email",
+                org_id
+            )
+            .map_err(|_| AppError::Database)?,
+        )
+        .await?;
+
+        let access: Vec<ReportAccess> = rows(
+            d1_query!(
+                db,
+                "SELECT uc.membership_id, uc.collection_id, NULL AS group_id, NULL AS group_name,
+                        uc.read_only, uc.hide_passwords, uc.manage
+                 FROM users_collections uc
+                 JOIN users_organizations uo ON uo.id = uc.membership_id
+                 WHERE uo.organization_id = ?1
+                 UNION ALL
+                 SELECT gu.membership_id, cg.collection_id, g.id AS group_id, g.name AS group_name,
+                        COALESCE(cg.read_only, 0), COALESCE(cg.hide_passwords, 0),
+                        COALESCE(cg.manage, 0)
+                 FROM groups_users gu
+                 JOIN groups g ON g.id = gu.group_id
+                 LEFT JOIN collections_groups cg ON cg.group_id = g.id
+                 WHERE g.organization_id = ?1",
+                org_id
+            )
+            .map_err(|_| AppError::Database)?,
+        )
+        .await?;
+
+        let collections: Vec<ReportCollection> = rows(
+            d1_query!(
+                db,
+                "SELECT id, name FROM collections WHERE organization_id = ?1 ORDER BY created_at",
+                org_id
+            )
+            .map_err(|_| AppError::Database)?,
+        )
+        .await?;
+
+        let assignments: Vec<CollectionCipher> = rows(
+            d1_query!(
+                db,
+                "SELECT cc.collection_id, cc.cipher_id
+                 FROM ciphers_collections cc
+                 JOIN ciphers c ON c.id = cc.cipher_id
+                 WHERE c.organization_id = ?1 AND c.deleted_at IS NULL",
+                org_id
+            )
+            .map_err(|_| AppError::Database)?,
+        )
+        .await?;
+        let mut collection_ciphers: HashMap<String, Vec<String>> = HashMap::new();
+        for assignment in assignments {
+            collection_ciphers
+                .entry(assignment.collection_id)
+                .or_default()
+                .push(assignment.cipher_id);
+        }
+
+        let all_ciphers: Vec<Value> = rows(
+            d1_query!(
+                db,
+                "SELECT id FROM ciphers WHERE organization_id = ?1 AND deleted_at IS NULL",
+                org_id
+            )
+            .map_err(|_| AppError::Database)?,
+        )
+        .await?;
+        let all_ciphers = all_ciphers
+            .iter()
+            .filter_map(|row| row.get("id").and_then(Value::as_str).map(str::to_string))
+            .collect();
+
+        Ok(Self {
+            members,
+            access,
+            collections,
+            collection_ciphers,
+            all_ciphers,
+        })
+    }
+
+    fn ciphers_of(&self, collection_id: &str) -> &[String] {
+        self.collection_ciphers
+            .get(collection_id)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    fn grants_of<'a>(&'a self, member: &'a ReportMember) -> impl Iterator<Item = &'a ReportAccess> {
+        self.access
+            .iter()
+            .filter(move |access| access.membership_id == member.id)
+    }
+}
+
+async fn load_report_data(
+    env: &Env,
+    claims: &Claims,
+    org_id: &str,
+) -> Result<AccessData, AppError> {
+    let db = db::get_db(env)?;
+    require_member_access(
+        &db,
+        org_id,
+        &claims.sub,
+        MembershipType::Admin,
+        &[Permission::AccessReports],
+    )
+    .await?;
+    AccessData::load(&db, org_id).await
+}
+
+/// GET /api/reports/member-access/{org_id} - one row per collection each member can
+/// access and how, with the ids of the items in it
+#[worker::send]
+pub async fn member_access_report(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(org_id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let data = load_report_data(&env, &claims, &org_id).await?;
+    let collection_names: HashMap<&str, &str> = data
+        .collections
+        .iter()
+        .map(|c| (c.id.as_str(), c.name.as_str()))
+        .collect();
+
+    let mut report = Vec::new();
+    for member in &data.members {
+        let row = |collection_id: Option<&str>,
+                   group: Option<(&str, Option<&str>)>,
+                   (read_only, hide_passwords, manage): (bool, bool, bool)| {
+            json!({
+                "userGuid": &member.user_id,
+                "userName": &member.name,
+                "email": &member.email,
+                "twoFactorEnabled": member.two_factor_enabled != 0,
+                "accountRecoveryEnabled": member.account_recovery_enabled != 0,
+                "usesKeyConnector": false,
+                "collectionId": collection_id,
+                "collectionName": collection_id.and_then(|id| collection_names.get(id)),
+                "groupId": group.map(|(id, _)| id),
+                "groupName": group.and_then(|(_, name)| name),
+                "readOnly": read_only,
+                "hidePasswords": hide_passwords,
+                "manage": manage,
+                "cipherIds": collection_id.map(|id| data.ciphers_of(id)).unwrap_or_default(),
+            })
+        };
+
+        let before = report.len();
+        if member.accesses_everything() {
+            for collection in &data.collections {
+                report.push(row(Some(&collection.id), None, (false, false, true)));
+            }
+        } else {
+            for access in data.grants_of(member) {
+                report.push(row(
+                    access.collection_id.as_deref(),
+                    access
+                        .group_id
+                        .as_deref()
+                        .map(|id| (id, access.group_name.as_deref())),
+                    (
+                        access.read_only != 0,
+                        access.hide_passwords != 0,
+                        access.manage != 0,
+                    ),
+                ));
+            }
+        }
+        if report.len() == before {
+            report.push(row(None, None, (false, false, false)));
+        }
+    }
+
+    Ok(Json(Value::Array(report)))
+}
+
+/// GET /api/reports/member-cipher-details/{org_id} - the items each member can access,
+/// which the risk insights match against the at-risk passwords
+#[worker::send]
+pub async fn member_cipher_details(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(org_id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let data = load_report_data(&env, &claims, &org_id).await?;
+
+    let report: Vec<Value> = data
+        .members
+        .iter()
+        .filter(|member| member.user_id.is_some())
+        .map(|member| {
+            let cipher_ids: BTreeSet<&String> = if member.accesses_everything() {
+                data.all_ciphers.iter().collect()
+            } else {
+                data.grants_of(member)
+                    .filter_map(|access| access.collection_id.as_deref())
+                    .flat_map(|id| data.ciphers_of(id))
+                    .collect()
+            };
+            json!({
+                "userGuid": &member.user_id,
+                "userName": &member.name,
+                "email": &member.email,
+                "useKeyConnector": false,
+                "cipherIds": cipher_ids,
+            })
+        })
+        .collect();
+
+    Ok(Json(Value::Array(report)))
+}
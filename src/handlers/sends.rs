@@ -3,6 +3,7 @@ use std::sync::Arc;
 use axum::{
     body::Bytes,
     extract::{Multipart, Path, State},
+    http::HeaderMap,
     Extension, Json,
 };
 use chrono::{TimeZone, Utc};
@@ -10,21 +11,24 @@ use jwt_compact::AlgorithmExt;
 use jwt_compact::{alg::Hs256Key, Claims as JwtClaims, Header};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use worker::Env;
+use worker::{D1PreparedStatement, Env};
 
 use crate::d1_query;
 
 use crate::{
     auth::{Claims, JWT_VALIDATION_LEEWAY_SECS},
+    client_context::request_ip_from_headers,
     db,
     error::AppError,
     handlers::attachments::{
         attachments_enabled, delete_storage_objects, is_kv_backend, upload_to_storage,
     },
     handlers::get_env_usize,
+    handlers::storage::{ensure_storage_available, StorageOwner},
     models::attachment::display_size,
     models::send::{validate_send_dates, SendDB, SendRequestData, SendType, SEND_INACCESSIBLE_MSG},
     notifications::{self, UpdateType},
+    rate_limit::LoginBackoff,
     BaseUrl,
 };
 
@@ -356,6 +360,7 @@ pub async fn create_file_send_v2(
             return Err(AppError::BadRequest("Send storage limit reached".into()));
         }
     }
+    ensure_storage_available(&env, &db, StorageOwner::User(&claims.sub), declared_size).await?;
 
     let file_id = uuid::Uuid::new_v4().to_string();
 
@@ -461,6 +466,7 @@ pub async fn create_file_send_legacy(
             return Err(AppError::BadRequest("Send storage limit reached".into()));
         }
     }
+    ensure_storage_available(&env, &db, StorageOwner::User(&claims.sub), actual_size).await?;
 
     let file_id = uuid::Uuid::new_v4().to_string();
 
@@ -698,9 +704,40 @@ pub struct SendAccessRequest {
     pub password: Option<String>,
 }
 
+/// Check the password of a protected send. Failures are counted per send and client
+/// address, and after too many the address is locked out of the send for a while.
+async fn check_access_password(
+    env: &Env,
+    headers: &HeaderMap,
+    send: &SendDB,
+    password: Option<&str>,
+) -> Result<(), AppError> {
+    if !send.has_password() {
+        return Ok(());
+    }
+    let password =
+        password.ok_or_else(|| AppError::Unauthorized("Password not provided".into()))?;
+
+    let backoff = LoginBackoff::for_send_passwords(env);
+    let key = format!("send:{}:{}", send.id, request_ip_from_headers(headers));
+    let retry_after = backoff.retry_after(env, &key).await;
+    if retry_after > 0 {
+        return Err(AppError::Unauthorized(format!(
+            "Too many incorrect passwords. Try again in {retry_after} seconds."
+        )));
+    }
+    if !send.check_password(password).await? {
+        backoff.record_failure(env, &key).await;
+        return Err(AppError::BadRequest("Invalid password".into()));
+    }
+    backoff.reset(env, &key).await;
+    Ok(())
+}
+
 #[worker::send]
 pub async fn access_send(
     State(env): State<Arc<Env>>,
+    headers: HeaderMap,
     Path(access_id): Path<String>,
     Json(payload): Json<SendAccessRequest>,
 ) -> Result<Json<Value>, AppError> {
@@ -711,15 +748,7 @@ pub async fn access_send(
 
     send.validate_access()?;
 
-    if send.has_password() {
-        let pw = payload
-            .password
-            .as_deref()
-            .ok_or_else(|| AppError::Unauthorized("Password not provided".into()))?;
-        if !send.check_password(pw).await? {
-            return Err(AppError::BadRequest("Invalid password".into()));
-        }
-    }
+    check_access_password(&env, &headers, &send, payload.password.as_deref()).await?;
 
     // Text sends increment access count here; file sends increment on download.
     // Both types get a revision bump and sync notification (aligns with Vaultwarden).
@@ -750,6 +779,7 @@ pub async fn access_send(
 #[worker::send]
 pub async fn access_file_send(
     State(env): State<Arc<Env>>,
+    headers: HeaderMap,
     Path((send_id, file_id)): Path<(String, String)>,
     Extension(BaseUrl(base_url)): Extension<BaseUrl>,
     Json(payload): Json<SendAccessRequest>,
@@ -761,19 +791,11 @@ pub async fn access_file_send(
 
     send.validate_access()?;
 
-    if send.send_type != SendType::File as i32 {
+    if send.file_id().as_deref() != Some(file_id.as_str()) {
         return Err(AppError::NotFound(SEND_INACCESSIBLE_MSG.into()));
     }
 
-    if send.has_password() {
-        let pw = payload
-            .password
-            .as_deref()
-            .ok_or_else(|| AppError::Unauthorized("Password not provided".into()))?;
-        if !send.check_password(pw).await? {
-            return Err(AppError::BadRequest("Invalid password".into()));
-        }
-    }
+    check_access_password(&env, &headers, &send, payload.password.as_deref()).await?;
 
     send.increment_access_count(&db).await?;
     db::touch_user_updated_at(&db, &send.user_id, &send.updated_at).await?;
@@ -799,14 +821,14 @@ pub async fn access_file_send(
 
 // ── Key rotation support ────────────────────────────────────────────
 
-pub async fn rotate_user_sends(
+/// Validate that a key rotation covers every send of the user and build the
+/// statements that re-encrypt them.
+pub async fn send_rotation_statements(
     db: &crate::db::Db,
-    _env: &Env,
     user_id: &str,
     sends: &[SendRequestData],
     now: &str,
-    batch_size: usize,
-) -> Result<(), AppError> {
+) -> Result<Vec<D1PreparedStatement>, AppError> {
     let db_sends = SendDB::find_by_user(db, user_id).await?;
 
     let db_ids: std::collections::HashSet<&str> = db_sends.iter().map(|s| s.id.as_str()).collect();
@@ -850,8 +872,7 @@ pub async fn rotate_user_sends(
         statements.push(stmt);
     }
 
-    db::execute_in_batches(db, statements, batch_size).await?;
-    Ok(())
+    Ok(statements)
 }
 
 // ── Cleanup helpers ─────────────────────────────────────────────────
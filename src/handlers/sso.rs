@@ -0,0 +1,121 @@
+//! Browser side of SSO logins (see [`crate::sso`] for the flow), and adding SSO users
+//! to the configured organization.
+
+use axum::extract::{Query, State};
+use axum::response::Redirect;
+use axum::{Extension, Json};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use worker::Env;
+
+use crate::crypto::random_bytes;
+use crate::db;
+use crate::error::AppError;
+use crate::handlers::policies::ensure_user_allowed_in_org;
+use crate::models::organization::{Membership, MembershipStatus, MembershipType, Organization};
+use crate::models::user::User;
+use crate::sso::{self, AuthorizeRequest, SsoConfig};
+use crate::BaseUrl;
+
+/// GET /identity/sso/prevalidate - check that SSO can be used before the clients
+/// open the authorize page
+///
+/// The clients send the organization identifier the user typed; every identifier
+/// leads to the one identity provider, so it is not checked. Neither is the returned
+/// token, which the clients only pass along.
+#[worker::send]
+pub async fn prevalidate(State(env): State<Arc<Env>>) -> Result<Json<Value>, AppError> {
+    SsoConfig::from_env(&env)?;
+    Ok(Json(json!({ "token": hex::encode(random_bytes(16)?) })))
+}
+
+/// GET /identity/connect/authorize - send the browser to the identity provider
+#[worker::send]
+pub async fn authorize(
+    State(env): State<Arc<Env>>,
+    Extension(BaseUrl(base_url)): Extension<BaseUrl>,
+    Query(request): Query<AuthorizeRequest>,
+) -> Result<Redirect, AppError> {
+    let url = sso::authorize_url(&env, &base_url, &request).await?;
+    Ok(Redirect::to(&url))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OidcSigninQuery {
+    code: Option<String>,
+    state: String,
+    error: Option<String>,
+}
+
+/// GET /identity/connect/oidc-signin - the identity provider's callback, which sends
+/// the browser on to the client
+#[worker::send]
+pub async fn oidc_signin(
+    State(env): State<Arc<Env>>,
+    Extension(BaseUrl(base_url)): Extension<BaseUrl>,
+    Query(query): Query<OidcSigninQuery>,
+) -> Result<Redirect, AppError> {
+    let url = sso::finish_login(
+        &env,
+        &base_url,
+        query.code.as_deref(),
+        &query.state,
+        query.error.as_deref(),
+    )
+    .await?;
+    Ok(Redirect::to(&url))
+}
+
+/// Make `user` an accepted member of the SSO organization, waiting for an admin to
+/// confirm them; a pending invitation to their email is used when there is one.
+///
+/// Users the organization has no seat for, or whose policies keep them out, still
+/// log in, just without the membership.
+pub(crate) async fn provision_member(
+    db: &db::Db,
+    config: &SsoConfig,
+    user: &User,
+) -> Result<(), AppError> {
+    let Some(org_id) = config.organization_id.as_deref() else {
+        return Ok(());
+    };
+    if Membership::find_by_user_and_org(db, &user.id, org_id)
+        .await?
+        .is_some()
+    {
+        return Ok(());
+    }
+    let Some(org) = Organization::find_by_id(db, org_id).await? else {
+        log::warn!("SSO_ORGANIZATION_ID {org_id} is not an organization");
+        return Ok(());
+    };
+    if let Err(e) = ensure_user_allowed_in_org(db, &user.id, org_id, MembershipType::User).await {
+        log::info!("SSO user {} not added to {org_id}: {e}", user.id);
+        return Ok(());
+    }
+
+    if let Some(mut invite) = Membership::find_by_email_and_org(db, &user.email, org_id).await? {
+        if invite.user_id.is_none() {
+            invite.user_id = Some(user.id.clone());
+            invite.status = MembershipStatus::Accepted as i32;
+            invite.update(db).await?;
+        }
+        return Ok(());
+    }
+    if let Some(seats) = org.seats {
+        if i64::from(Membership::count_by_org(db, org_id).await?) >= seats {
+            log::info!("SSO user {} not added to {org_id}: no seat left", user.id);
+            return Ok(());
+        }
+    }
+    Membership::new(
+        org.id,
+        Some(user.id.clone()),
+        user.email.clone(),
+        MembershipType::User,
+        MembershipStatus::Accepted,
+    )
+    .insert(db)
+    .await
+}
@@ -0,0 +1,198 @@
+//! Storage quotas for users and organizations.
+//!
+//! Usage is summed from the file sizes recorded in D1: attachments (including
+//! pending uploads) and file Sends of a user, attachments of an organization's
+//! ciphers. The quota of a user or organization is its `storage_quota_kb` column,
+//! set through the admin API, or `USER_STORAGE_QUOTA_KB` / `ORG_STORAGE_QUOTA_KB`
+//! when that is NULL. Without either, storage is not limited beyond the per-feature
+//! limits (`ATTACHMENT_TOTAL_LIMIT_KB`, `USER_SEND_LIMIT_KB`).
+
+use serde::Serialize;
+use worker::Env;
+
+use crate::d1_query;
+use crate::db;
+use crate::error::AppError;
+use crate::handlers::attachments::user_attachment_usage;
+use crate::models::send::SendDB;
+use crate::models::sync::Profile;
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum StorageOwner<'a> {
+    User(&'a str),
+    Organization(&'a str),
+}
+
+impl StorageOwner<'_> {
+    fn table(self) -> &'static str {
+        match self {
+            StorageOwner::User(_) => "users",
+            StorageOwner::Organization(_) => "organizations",
+        }
+    }
+
+    fn id(&self) -> &str {
+        match self {
+            StorageOwner::User(id) | StorageOwner::Organization(id) => id,
+        }
+    }
+
+    fn default_quota_var(self) -> &'static str {
+        match self {
+            StorageOwner::User(_) => "USER_STORAGE_QUOTA_KB",
+            StorageOwner::Organization(_) => "ORG_STORAGE_QUOTA_KB",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct StorageUsage {
+    pub attachments_bytes: i64,
+    pub sends_bytes: i64,
+    pub used_bytes: i64,
+    /// `None` when storage is not limited.
+    pub quota_bytes: Option<i64>,
+    /// Whether the quota is an individual override rather than the default.
+    pub custom_quota: bool,
+}
+
+impl StorageUsage {
+    /// Whether `additional` more bytes still fit in the quota.
+    pub fn fits(&self, additional: i64) -> bool {
+        self.quota_bytes
+            .is_none_or(|quota| self.used_bytes.saturating_add(additional) <= quota)
+    }
+}
+
+fn kb_to_bytes(kb: i64) -> i64 {
+    kb.max(0).saturating_mul(1024)
+}
+
+/// The owner's individual quota in KB. `Err(NotFound)` when the owner does not exist.
+async fn custom_quota_kb(db: &db::Db, owner: StorageOwner<'_>) -> Result<Option<i64>, AppError> {
+    let row: Option<serde_json::Value> = d1_query!(
+        db,
+        &format!(
+            "SELECT storage_quota_kb FROM {} WHERE id = ?1",
+            owner.table()
+        ),
+        owner.id()
+    )
+    .map_err(|_| AppError::Database)?
+    .first(None)
+    .await
+    .map_err(|_| AppError::Database)?;
+    let row = row.ok_or_else(|| AppError::NotFound("Not found".to_string()))?;
+    Ok(row.get("storage_quota_kb").and_then(|v| v.as_i64()))
+}
+
+async fn organization_attachment_usage(db: &db::Db, org_id: &str) -> Result<i64, AppError> {
+    let total: Option<i64> = d1_query!(
+        db,
+        "SELECT COALESCE(SUM(file_size), 0) AS total FROM (
+            SELECT a.file_size AS file_size FROM attachments a
+            JOIN ciphers c ON c.id = a.cipher_id
+            WHERE c.organization_id = ?1
+            UNION ALL
+            SELECT p.file_size AS file_size FROM attachments_pending p
+            WHERE p.organization_id = ?1
+        ) AS files",
+        org_id
+    )
+    .map_err(|_| AppError::Database)?
+    .first(Some("total"))
+    .await
+    .map_err(|_| AppError::Database)?;
+    Ok(total.unwrap_or(0))
+}
+
+/// Current usage and quota of `owner`.
+pub(crate) async fn storage_usage(
+    env: &Env,
+    db: &db::Db,
+    owner: StorageOwner<'_>,
+) -> Result<StorageUsage, AppError> {
+    let custom = custom_quota_kb(db, owner).await?;
+    let quota_kb = custom.or_else(|| {
+        env.var(owner.default_quota_var())
+            .ok()
+            .and_then(|v| v.to_string().trim().parse::<i64>().ok())
+    });
+
+    let (attachments_bytes, sends_bytes) = match owner {
+        StorageOwner::User(user_id) => (
+            user_attachment_usage(db, user_id, None).await?,
+            SendDB::file_usage_by_user(db, user_id).await?,
+        ),
+        StorageOwner::Organization(org_id) => (organization_attachment_usage(db, org_id).await?, 0),
+    };
+
+    Ok(StorageUsage {
+        attachments_bytes,
+        sends_bytes,
+        used_bytes: attachments_bytes.saturating_add(sends_bytes),
+        quota_bytes: quota_kb.map(kb_to_bytes),
+        custom_quota: custom.is_some(),
+    })
+}
+
+/// Refuse an upload of `additional` bytes that would exceed the owner's quota.
+pub(crate) async fn ensure_storage_available(
+    env: &Env,
+    db: &db::Db,
+    owner: StorageOwner<'_>,
+    additional: i64,
+) -> Result<(), AppError> {
+    let usage = storage_usage(env, db, owner).await?;
+    if !usage.fits(additional) {
+        return Err(AppError::BadRequest(match owner {
+            StorageOwner::User(_) => "Storage quota exceeded".to_string(),
+            StorageOwner::Organization(_) => {
+                "The organization's storage quota is exceeded".to_string()
+            }
+        }));
+    }
+    Ok(())
+}
+
+/// Set the owner's individual quota in KB, or return to the default with `None`.
+pub(crate) async fn set_storage_quota(
+    db: &db::Db,
+    owner: StorageOwner<'_>,
+    quota_kb: Option<i64>,
+) -> Result<(), AppError> {
+    if quota_kb.is_some_and(|kb| kb < 0) {
+        return Err(AppError::BadRequest("Quota cannot be negative".to_string()));
+    }
+    custom_quota_kb(db, owner).await?;
+    d1_query!(
+        db,
+        &format!(
+            "UPDATE {} SET storage_quota_kb = ?1 WHERE id = ?2",
+            owner.table()
+        ),
+        quota_kb,
+        owner.id()
+    )
+    .map_err(|_| AppError::Database)?
+    .run()
+    .await?;
+    Ok(())
+}
+
+fn bytes_to_gb(bytes: i64) -> f64 {
+    (bytes as f64 / (1024.0 * 1024.0 * 1024.0) * 1000.0).round() / 1000.0
+}
+
+/// Fill `storageGb` / `maxStorageGb` of the user's profile.
+pub(crate) async fn fill_profile_storage(
+    env: &Env,
+    db: &db::Db,
+    profile: &mut Profile,
+) -> Result<(), AppError> {
+    let usage = storage_usage(env, db, StorageOwner::User(&profile.id)).await?;
+    profile.storage_gb = Some(bytes_to_gb(usage.used_bytes));
+    profile.max_storage_gb = usage.quota_bytes.map(bytes_to_gb);
+    Ok(())
+}
@@ -1,16 +1,25 @@
-use jwt_compact::{alg::Hs256Key, AlgorithmExt, UntrustedToken};
+use chrono::{Duration, Utc};
+use jwt_compact::{alg::Hs256Key, AlgorithmExt, Claims as JwtClaims, Header, UntrustedToken};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
 use web_sys::ReadableStream;
-use worker::{Env, Headers, HttpMetadata, Method, Request, Response, Url};
+use worker::{Env, Headers, HttpMetadata, Method, Request, Response, UploadedPart, Url};
 
 use crate::{
     auth::jwt_time_options,
     db::{self, touch_user_updated_at},
-    error::AppError,
+    error::{ApiError, AppError},
     handlers::attachments::{
-        self, get_storage_backend, jwt_secret, AttachmentClaims, StorageBackend,
+        self, delete_storage_objects, get_storage_backend, jwt_secret, AttachmentClaims,
+        StorageBackend,
     },
+    handlers::get_env_usize,
     handlers::sends::{SendDownloadClaims, SendUploadClaims},
+    models::attachment::AttachmentDB,
+    models::multipart_upload::{
+        MultipartUpload, SESSION_LIFETIME_HOURS, TARGET_ATTACHMENT, TARGET_SEND,
+    },
     models::send::SendDB,
     notifications::{self, UpdateType},
 };
@@ -27,6 +36,34 @@ struct KvFileMetadata {
     file_size: i64,
 }
 
+// ── Multipart sessions ──────────────────────────────────────────────
+
+/// Files at least this large may be uploaded in parts.
+const DEFAULT_MULTIPART_THRESHOLD_BYTES: usize = 64 * 1024 * 1024;
+const DEFAULT_MULTIPART_PART_BYTES: usize = 16 * 1024 * 1024;
+/// R2's minimum size of every part but the last.
+const MIN_MULTIPART_PART_BYTES: usize = 5 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct MultipartClaims {
+    sub: String,
+    device: String,
+    session_id: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CompleteMultipartRequest {
+    parts: Vec<CompletedPart>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CompletedPart {
+    part_number: u16,
+    etag: String,
+}
+
 // ── Routing ─────────────────────────────────────────────────────────
 
 pub fn is_streaming_route(method: &Method, path: &str) -> bool {
@@ -37,8 +74,18 @@ pub fn is_streaming_route(method: &Method, path: &str) -> bool {
                 segs.as_slice(),
                 ["api", "ciphers", _, "attachment", _, "azure-upload"]
                     | ["api", "sends", _, "file", _, "azure-upload"]
+                    | ["api", "multipart", _, _]
+            )
+        }
+        Method::Post => {
+            matches!(
+                segs.as_slice(),
+                ["api", "ciphers", _, "attachment", _, "multipart"]
+                    | ["api", "sends", _, "file", _, "multipart"]
+                    | ["api", "multipart", _, "complete"]
             )
         }
+        Method::Delete => matches!(segs.as_slice(), ["api", "multipart", _]),
         Method::Get => {
             matches!(
                 segs.as_slice(),
@@ -54,6 +101,12 @@ pub fn is_streaming_route(method: &Method, path: &str) -> bool {
 
 pub async fn handle(req: Request, env: &Env, method: &Method, path: &str, url: &Url) -> Response {
     let segs: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let range = req
+        .headers()
+        .get("range")
+        .ok()
+        .flatten()
+        .and_then(|v| ByteRange::parse(&v));
 
     let result = match (method, segs.as_slice()) {
         (&Method::Put, ["api", "ciphers", cid, "attachment", aid, "azure-upload"]) => {
@@ -64,7 +117,7 @@ pub async fn handle(req: Request, env: &Env, method: &Method, path: &str, url: &
         }
         (&Method::Get, ["api", "ciphers", cid, "attachment", aid, "download"]) => {
             match query_param(url, "token") {
-                Some(token) => handle_attachment_download(env, cid, aid, &token).await,
+                Some(token) => handle_attachment_download(env, cid, aid, &token, range).await,
                 None => Err(bad("Missing query parameter: token")),
             }
         }
@@ -76,14 +129,38 @@ pub async fn handle(req: Request, env: &Env, method: &Method, path: &str, url: &
         }
         (&Method::Get, ["api", "sends", sid, fid]) if *sid != "access" && *sid != "file" => {
             let token = query_param(url, "t").unwrap_or_default();
-            handle_send_download(env, sid, fid, &token).await
+            handle_send_download(env, sid, fid, &token, range).await
+        }
+        (&Method::Post, ["api", "ciphers", cid, "attachment", aid, "multipart"]) => {
+            match query_param(url, "token") {
+                Some(token) => handle_attachment_multipart_start(env, cid, aid, &token).await,
+                None => Err(bad("Missing query parameter: token")),
+            }
+        }
+        (&Method::Post, ["api", "sends", sid, "file", fid, "multipart"]) => {
+            match query_param(url, "token") {
+                Some(token) => handle_send_multipart_start(env, sid, fid, &token).await,
+                None => Err(bad("Missing query parameter: token")),
+            }
         }
+        (&Method::Put, ["api", "multipart", id, part]) => match query_param(url, "token") {
+            Some(token) => handle_multipart_part(req, env, id, part, &token).await,
+            None => Err(bad("Missing query parameter: token")),
+        },
+        (&Method::Post, ["api", "multipart", id, "complete"]) => match query_param(url, "token") {
+            Some(token) => handle_multipart_complete(req, env, id, &token).await,
+            None => Err(bad("Missing query parameter: token")),
+        },
+        (&Method::Delete, ["api", "multipart", id]) => match query_param(url, "token") {
+            Some(token) => handle_multipart_abort(env, id, &token).await,
+            None => Err(bad("Missing query parameter: token")),
+        },
         _ => Err(AppError::NotFound("Not found".into())),
     };
 
     match result {
         Ok(resp) => resp,
-        Err(e) => app_error_to_response(&e),
+        Err(e) => app_error_to_response(e),
     }
 }
 
@@ -104,31 +181,10 @@ async fn handle_attachment_upload(
 ) -> Result<Response, AppError> {
     let backend = get_storage_backend(env).ok_or_else(|| bad("Attachments are not enabled"))?;
     let db = db::get_db(env)?;
-
-    let claims = verify_token::<AttachmentClaims>(env, token)?;
-    if claims.cipher_id != cipher_id || claims.attachment_id != attachment_id {
-        log::warn!("Attachment upload token claims mismatch: expected cipher={cipher_id} att={attachment_id}");
-        return Err(AppError::Unauthorized("Invalid token".into()));
-    }
-
-    let user_id = &claims.sub;
-    let context_id = if claims.device.is_empty() {
-        None
-    } else {
-        Some(claims.device.as_str())
-    };
-
-    attachments::ensure_cipher_for_user(&db, cipher_id, user_id).await?;
-    let pending = attachments::fetch_pending_attachment(&db, attachment_id).await?;
-    if pending.cipher_id != cipher_id {
-        return Err(bad("Attachment does not belong to cipher"));
-    }
+    let (claims, pending) =
+        authorize_attachment_upload(env, &db, cipher_id, attachment_id, token).await?;
 
     let declared_size = pending.file_size;
-    if declared_size <= 0 {
-        return Err(bad("Invalid pending attachment size"));
-    }
-
     let content_length = parse_content_length(req.headers())?;
     if content_length != declared_size {
         return Err(bad(&format!(
@@ -150,20 +206,64 @@ async fn handle_attachment_upload(
     )
     .await?;
 
-    let mut pending = pending;
-    let now = pending.finalize_pending(&db).await?;
-    touch_user_updated_at(&db, user_id, &now).await?;
+    finish_attachment_upload(env, &db, pending, &claims.sub, &claims.device).await?;
+    ok_empty(201)
+}
+
+/// Check an attachment upload token, returning its claims and the pending attachment.
+async fn authorize_attachment_upload(
+    env: &Env,
+    db: &db::Db,
+    cipher_id: &str,
+    attachment_id: &str,
+    token: &str,
+) -> Result<(AttachmentClaims, AttachmentDB), AppError> {
+    let claims = verify_token::<AttachmentClaims>(env, token)?;
+    if claims.cipher_id != cipher_id || claims.attachment_id != attachment_id {
+        log::warn!("Attachment upload token claims mismatch: expected cipher={cipher_id} att={attachment_id}");
+        return Err(AppError::Unauthorized("Invalid token".into()));
+    }
+    let pending = pending_attachment(db, cipher_id, attachment_id, &claims.sub).await?;
+    Ok((claims, pending))
+}
+
+async fn pending_attachment(
+    db: &db::Db,
+    cipher_id: &str,
+    attachment_id: &str,
+    user_id: &str,
+) -> Result<AttachmentDB, AppError> {
+    attachments::ensure_cipher_for_user(db, cipher_id, user_id).await?;
+    let pending = attachments::fetch_pending_attachment(db, attachment_id).await?;
+    if pending.cipher_id != cipher_id {
+        return Err(bad("Attachment does not belong to cipher"));
+    }
+    if pending.file_size <= 0 {
+        return Err(bad("Invalid pending attachment size"));
+    }
+    Ok(pending)
+}
+
+/// Turn a stored pending attachment into a regular one and notify the user's devices.
+async fn finish_attachment_upload(
+    env: &Env,
+    db: &db::Db,
+    mut pending: AttachmentDB,
+    user_id: &str,
+    device: &str,
+) -> Result<(), AppError> {
+    let now = pending.finalize_pending(db).await?;
+    touch_user_updated_at(db, user_id, &now).await?;
 
     notifications::publish_cipher_update(
         env.clone(),
         user_id.to_string(),
         UpdateType::SyncCipherUpdate,
-        cipher_id.to_string(),
+        pending.cipher_id.clone(),
         now,
-        context_id.map(|s| s.to_string()),
+        (!device.is_empty()).then(|| device.to_string()),
     );
-
-    ok_empty(201)
+    Ok(())
 }
 
 // ── Attachment download ─────────────────────────────────────────────
@@ -173,6 +273,7 @@ async fn handle_attachment_download(
     cipher_id: &str,
     attachment_id: &str,
     token: &str,
+    range: Option<ByteRange>,
 ) -> Result<Response, AppError> {
     let backend = get_storage_backend(env).ok_or_else(|| bad("Attachments are not enabled"))?;
     let db = db::get_db(env)?;
@@ -190,7 +291,14 @@ async fn handle_attachment_download(
     }
 
     let storage_key = format!("{cipher_id}/{attachment_id}");
-    stream_download_from_storage(env, backend, &storage_key, Some(attachment.file_size)).await
+    stream_download_from_storage(
+        env,
+        backend,
+        &storage_key,
+        Some(attachment.file_size),
+        range,
+    )
+    .await
 }
 
 // ── Send upload ─────────────────────────────────────────────────────
@@ -204,15 +312,58 @@ async fn handle_send_upload(
 ) -> Result<Response, AppError> {
     let backend = get_storage_backend(env).ok_or_else(|| bad("File storage is not enabled"))?;
     let db = db::get_db(env)?;
+    let (claims, pending, declared_size) =
+        authorize_send_upload(env, &db, send_id, file_id, token).await?;
+
+    let content_length = parse_content_length(req.headers())?;
+    if content_length != declared_size {
+        return Err(bad(&format!(
+            "Uploaded size ({content_length}) does not match declared size ({declared_size})"
+        )));
+    }
+
+    let body_stream = request_body(&req)?;
+    let content_type = req.headers().get("content-type").ok().flatten();
 
+    let storage_key = format!("sends/{send_id}/{file_id}");
+    put_stream_to_storage(
+        env,
+        backend,
+        &storage_key,
+        body_stream,
+        content_type.as_deref(),
+        declared_size,
+    )
+    .await?;
+
+    finish_send_upload(env, &db, pending, &claims.sub, &claims.device).await?;
+    ok_empty(201)
+}
+
+/// Check a Send upload token, returning its claims, the pending Send and its file size.
+async fn authorize_send_upload(
+    env: &Env,
+    db: &db::Db,
+    send_id: &str,
+    file_id: &str,
+    token: &str,
+) -> Result<(SendUploadClaims, SendDB, i64), AppError> {
     let claims = verify_token::<SendUploadClaims>(env, token)?;
     if claims.send_id != send_id || claims.file_id != file_id {
         log::warn!("Send upload token claims mismatch: expected send={send_id} file={file_id}");
         return Err(AppError::Unauthorized("Invalid token".into()));
     }
-    let user_id = &claims.sub;
+    let (pending, declared_size) = pending_send_file(db, send_id, file_id, &claims.sub).await?;
+    Ok((claims, pending, declared_size))
+}
 
-    let pending = SendDB::find_pending_by_id_and_user(&db, send_id, user_id)
+async fn pending_send_file(
+    db: &db::Db,
+    send_id: &str,
+    file_id: &str,
+    user_id: &str,
+) -> Result<(SendDB, i64), AppError> {
+    let pending = SendDB::find_pending_by_id_and_user(db, send_id, user_id)
         .await?
         .ok_or_else(|| AppError::NotFound("Pending send not found or already uploaded".into()))?;
 
@@ -233,47 +384,327 @@ async fn handle_send_upload(
     if declared_size < 0 {
         return Err(bad("Invalid declared file size in pending send"));
     }
+    Ok((pending, declared_size))
+}
+
+/// Move a stored pending Send to the Sends and notify the user's devices.
+async fn finish_send_upload(
+    env: &Env,
+    db: &db::Db,
+    mut pending: SendDB,
+    user_id: &str,
+    device: &str,
+) -> Result<(), AppError> {
+    pending.finalize(db).await?;
+    let now = &pending.updated_at;
+
+    db::touch_user_updated_at(db, user_id, now).await?;
+
+    notifications::publish_send_update(
+        env.clone(),
+        user_id.to_string(),
+        UpdateType::SyncSendCreate,
+        pending.id.clone(),
+        now.to_string(),
+        Some(device.to_string()),
+    );
+    Ok(())
+}
+
+// ── Multipart upload ────────────────────────────────────────────────
+
+async fn handle_attachment_multipart_start(
+    env: &Env,
+    cipher_id: &str,
+    attachment_id: &str,
+    token: &str,
+) -> Result<Response, AppError> {
+    let db = db::get_db(env)?;
+    let (claims, pending) =
+        authorize_attachment_upload(env, &db, cipher_id, attachment_id, token).await?;
+    start_multipart_upload(
+        env,
+        &db,
+        MultipartUpload {
+            id: Uuid::new_v4().to_string(),
+            upload_id: String::new(),
+            storage_key: format!("{cipher_id}/{attachment_id}"),
+            user_id: claims.sub,
+            target: TARGET_ATTACHMENT.to_string(),
+            parent_id: cipher_id.to_string(),
+            file_id: attachment_id.to_string(),
+            file_size: pending.file_size,
+            part_size: multipart_part_bytes(env),
+            created_at: db::now_string(),
+        },
+        claims.device,
+    )
+    .await
+}
+
+async fn handle_send_multipart_start(
+    env: &Env,
+    send_id: &str,
+    file_id: &str,
+    token: &str,
+) -> Result<Response, AppError> {
+    let db = db::get_db(env)?;
+    let (claims, _pending, declared_size) =
+        authorize_send_upload(env, &db, send_id, file_id, token).await?;
+    start_multipart_upload(
+        env,
+        &db,
+        MultipartUpload {
+            id: Uuid::new_v4().to_string(),
+            upload_id: String::new(),
+            storage_key: format!("sends/{send_id}/{file_id}"),
+            user_id: claims.sub,
+            target: TARGET_SEND.to_string(),
+            parent_id: send_id.to_string(),
+            file_id: file_id.to_string(),
+            file_size: declared_size,
+            part_size: multipart_part_bytes(env),
+            created_at: db::now_string(),
+        },
+        claims.device,
+    )
+    .await
+}
+
+/// Open the R2 multipart upload of `session` and record it, returning the session
+/// ID, the part layout and the token for the part, complete and abort requests.
+async fn start_multipart_upload(
+    env: &Env,
+    db: &db::Db,
+    mut session: MultipartUpload,
+    device: String,
+) -> Result<Response, AppError> {
+    if get_storage_backend(env) != Some(StorageBackend::R2) {
+        return Err(bad("Multipart uploads require R2 storage"));
+    }
+    let threshold = get_env_usize(
+        env,
+        "MULTIPART_UPLOAD_THRESHOLD_BYTES",
+        DEFAULT_MULTIPART_THRESHOLD_BYTES,
+    ) as i64;
+    if session.file_size < threshold {
+        return Err(bad(&format!(
+            "Files smaller than {threshold} bytes are uploaded in a single request"
+        )));
+    }
+    let part_count = MultipartUpload::part_count_for(session.file_size, session.part_size)
+        .ok_or_else(|| bad("The file needs more parts than R2 allows"))?;
+
+    let bucket = env
+        .bucket(ATTACHMENTS_BUCKET)
+        .map_err(|_| AppError::Internal)?;
+    let upload = bucket
+        .create_multipart_upload(&session.storage_key)
+        .execute()
+        .await
+        .map_err(|e| {
+            log::error!(
+                "R2 multipart create failed for key '{}': {e}",
+                session.storage_key
+            );
+            AppError::Internal
+        })?;
+    session.upload_id = upload.upload_id().await;
+    session.insert(db).await?;
+
+    let token = multipart_token(
+        env,
+        MultipartClaims {
+            sub: session.user_id.clone(),
+            device,
+            session_id: session.id.clone(),
+        },
+    )?;
+    Response::from_json(&json!({
+        "object": "multipartUpload",
+        "id": session.id,
+        "partSize": session.part_size,
+        "partCount": part_count,
+        "token": token,
+    }))
+    .map_err(AppError::Worker)
+}
+
+/// The session of a multipart request, after checking its token.
+async fn multipart_session(
+    env: &Env,
+    db: &db::Db,
+    session_id: &str,
+    token: &str,
+) -> Result<(MultipartClaims, MultipartUpload), AppError> {
+    let claims = verify_token::<MultipartClaims>(env, token)?;
+    if claims.session_id != session_id {
+        log::warn!("Multipart token claims mismatch: expected session={session_id}");
+        return Err(AppError::Unauthorized("Invalid token".into()));
+    }
+    let session = MultipartUpload::find_for_user(db, session_id, &claims.sub)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Upload session not found".into()))?;
+    Ok((claims, session))
+}
 
+fn resume_multipart_upload(
+    env: &Env,
+    session: &MultipartUpload,
+) -> Result<worker::MultipartUpload, AppError> {
+    env.bucket(ATTACHMENTS_BUCKET)
+        .map_err(|_| AppError::Internal)?
+        .resume_multipart_upload(&session.storage_key, &session.upload_id)
+        .map_err(AppError::Worker)
+}
+
+async fn handle_multipart_part(
+    req: Request,
+    env: &Env,
+    session_id: &str,
+    part_number: &str,
+    token: &str,
+) -> Result<Response, AppError> {
+    let db = db::get_db(env)?;
+    let (_claims, session) = multipart_session(env, &db, session_id, token).await?;
+
+    let invalid_part = || bad("Invalid part number");
+    let number = part_number.parse::<i64>().map_err(|_| invalid_part())?;
+    let expected = session.part_length(number).ok_or_else(invalid_part)?;
     let content_length = parse_content_length(req.headers())?;
-    if content_length != declared_size {
+    if content_length != expected {
         return Err(bad(&format!(
-            "Uploaded size ({content_length}) does not match declared size ({declared_size})"
+            "Part {number} must be {expected} bytes, got {content_length}"
         )));
     }
 
     let body_stream = request_body(&req)?;
-    let content_type = req.headers().get("content-type").ok().flatten();
+    let part = resume_multipart_upload(env, &session)?
+        .upload_part(number as u16, body_stream)
+        .await
+        .map_err(|e| {
+            log::error!(
+                "R2 part {number} upload failed for key '{}': {e}",
+                session.storage_key
+            );
+            AppError::Internal
+        })?;
 
-    let storage_key = format!("sends/{send_id}/{file_id}");
-    put_stream_to_storage(
-        env,
-        backend,
-        &storage_key,
-        body_stream,
-        content_type.as_deref(),
-        declared_size,
-    )
-    .await?;
+    Response::from_json(&json!({
+        "partNumber": part.part_number(),
+        "etag": part.etag(),
+    }))
+    .map_err(AppError::Worker)
+}
 
-    let mut pending = pending;
-    pending.data = serde_json::to_string(&pending_data).map_err(|_| AppError::Internal)?;
-    pending.finalize(&db).await?;
-    let now = &pending.updated_at;
+async fn handle_multipart_complete(
+    mut req: Request,
+    env: &Env,
+    session_id: &str,
+    token: &str,
+) -> Result<Response, AppError> {
+    let db = db::get_db(env)?;
+    let (claims, session) = multipart_session(env, &db, session_id, token).await?;
+
+    let body: CompleteMultipartRequest =
+        req.json().await.map_err(|_| bad("Invalid request body"))?;
+    let mut parts = body.parts;
+    parts.sort_by_key(|part| part.part_number);
+    let all_listed = parts.len() as i64 == session.part_count()
+        && parts
+            .iter()
+            .enumerate()
+            .all(|(i, part)| part.part_number as usize == i + 1);
+    if !all_listed {
+        return Err(bad("Every part must be listed exactly once"));
+    }
 
-    db::touch_user_updated_at(&db, user_id, now).await?;
+    // The upload may only complete while its attachment or Send is still pending.
+    enum Pending {
+        Attachment(AttachmentDB),
+        Send(SendDB),
+    }
+    let pending = match session.target.as_str() {
+        TARGET_ATTACHMENT => Pending::Attachment(
+            pending_attachment(&db, &session.parent_id, &session.file_id, &claims.sub).await?,
+        ),
+        TARGET_SEND => Pending::Send(
+            pending_send_file(&db, &session.parent_id, &session.file_id, &claims.sub)
+                .await?
+                .0,
+        ),
+        _ => return Err(AppError::Internal),
+    };
 
-    notifications::publish_send_update(
-        env.clone(),
-        user_id.to_string(),
-        UpdateType::SyncSendCreate,
-        send_id.to_string(),
-        now.to_string(),
-        Some(claims.device),
-    );
+    let object = resume_multipart_upload(env, &session)?
+        .complete(
+            parts
+                .into_iter()
+                .map(|part| UploadedPart::new(part.part_number, part.etag)),
+        )
+        .await
+        .map_err(|e| {
+            log::warn!(
+                "R2 multipart complete failed for key '{}': {e}",
+                session.storage_key
+            );
+            bad("The upload could not be completed; check the listed parts")
+        })?;
+    MultipartUpload::delete(&db, &session.id).await?;
+
+    if object.size() != session.file_size as u64 {
+        delete_storage_objects(env, std::slice::from_ref(&session.storage_key)).await?;
+        return Err(bad(&format!(
+            "Uploaded size ({}) does not match declared size ({})",
+            object.size(),
+            session.file_size
+        )));
+    }
 
+    match pending {
+        Pending::Attachment(pending) => {
+            finish_attachment_upload(env, &db, pending, &claims.sub, &claims.device).await?
+        }
+        Pending::Send(pending) => {
+            finish_send_upload(env, &db, pending, &claims.sub, &claims.device).await?
+        }
+    }
     ok_empty(201)
 }
 
+async fn handle_multipart_abort(
+    env: &Env,
+    session_id: &str,
+    token: &str,
+) -> Result<Response, AppError> {
+    let db = db::get_db(env)?;
+    let (_claims, session) = multipart_session(env, &db, session_id, token).await?;
+    if let Err(e) = resume_multipart_upload(env, &session)?.abort().await {
+        log::warn!(
+            "R2 multipart abort failed for key '{}': {e}",
+            session.storage_key
+        );
+    }
+    MultipartUpload::delete(&db, &session.id).await?;
+    ok_empty(200)
+}
+
+fn multipart_part_bytes(env: &Env) -> i64 {
+    get_env_usize(env, "MULTIPART_PART_BYTES", DEFAULT_MULTIPART_PART_BYTES)
+        .max(MIN_MULTIPART_PART_BYTES) as i64
+}
+
+fn multipart_token(env: &Env, claims: MultipartClaims) -> Result<String, AppError> {
+    let mut claims = JwtClaims::new(claims);
+    claims.expiration = Some(Utc::now() + Duration::hours(SESSION_LIFETIME_HOURS));
+
+    let secret = jwt_secret(env)?;
+    let key = Hs256Key::new(secret.as_bytes());
+    jwt_compact::alg::Hs256
+        .token(&Header::empty(), &claims, &key)
+        .map_err(|_| AppError::Crypto("Failed to create multipart upload token".into()))
+}
+
 // ── Send download ───────────────────────────────────────────────────
 
 async fn handle_send_download(
@@ -281,6 +712,7 @@ async fn handle_send_download(
     send_id: &str,
     file_id: &str,
     token: &str,
+    range: Option<ByteRange>,
 ) -> Result<Response, AppError> {
     let backend = get_storage_backend(env).ok_or_else(|| bad("File storage is not enabled"))?;
     let db = db::get_db(env)?;
@@ -294,8 +726,11 @@ async fn handle_send_download(
         return Err(AppError::NotFound("Not found".into()));
     }
 
+    // The token outlives the access check; a Send disabled since (by its owner or
+    // because it expired) is no longer served.
     let send = SendDB::find_by_id(&db, send_id)
         .await?
+        .filter(|send| send.disabled == 0)
         .ok_or_else(|| AppError::NotFound("Not found".into()))?;
 
     let storage_key = format!("sends/{send_id}/{file_id}");
@@ -303,7 +738,7 @@ async fn handle_send_download(
         .ok()
         .and_then(|v| v.get("size").and_then(|s| s.as_i64()));
 
-    stream_download_from_storage(env, backend, &storage_key, fallback_size).await
+    stream_download_from_storage(env, backend, &storage_key, fallback_size, range).await
 }
 
 // ── Storage helpers ─────────────────────────────────────────────────
@@ -372,23 +807,50 @@ async fn put_stream_to_storage(
     }
 }
 
+/// Stream a stored file to the client. R2 honours a single `Range` (206, or 416
+/// when it starts past the end); KV cannot read part of a value, so it always
+/// answers with the whole file.
 async fn stream_download_from_storage(
     env: &Env,
     backend: StorageBackend,
     key: &str,
     fallback_size: Option<i64>,
+    range: Option<ByteRange>,
 ) -> Result<Response, AppError> {
     match backend {
         StorageBackend::R2 => {
             let bucket = env
                 .bucket(ATTACHMENTS_BUCKET)
                 .map_err(|_| AppError::Internal)?;
-            let obj = bucket
-                .get(key)
+            let not_found = || AppError::NotFound("Not found in storage".into());
+
+            let mut get = bucket.get(key);
+            let mut partial = None;
+            if let Some(range) = range {
+                let total = bucket
+                    .head(key)
+                    .await
+                    .map_err(AppError::Worker)?
+                    .ok_or_else(not_found)?
+                    .size();
+                let Some((start, end)) = range.resolve(total) else {
+                    return Ok(Response::builder()
+                        .with_status(416)
+                        .with_header("content-range", &format!("bytes */{total}"))?
+                        .with_header("accept-ranges", "bytes")?
+                        .empty());
+                };
+                get = get.range(worker::Range::OffsetWithLength {
+                    offset: start,
+                    length: end - start + 1,
+                });
+                partial = Some((start, end, total));
+            }
+            let obj = get
                 .execute()
                 .await
                 .map_err(AppError::Worker)?
-                .ok_or_else(|| AppError::NotFound("Not found in storage".into()))?;
+                .ok_or_else(not_found)?;
 
             let ct = obj
                 .http_metadata()
@@ -401,12 +863,19 @@ async fn stream_download_from_storage(
                 .response_body()
                 .map_err(AppError::Worker)?;
 
-            let resp = Response::builder()
-                .with_status(200)
+            let builder = Response::builder()
                 .with_header("content-type", &ct)?
-                .with_header("content-length", &size.to_string())?
-                .body(body);
-            Ok(resp)
+                .with_header("accept-ranges", "bytes")?;
+            let builder = match partial {
+                Some((start, end, total)) => builder
+                    .with_status(206)
+                    .with_header("content-range", &format!("bytes {start}-{end}/{total}"))?
+                    .with_header("content-length", &(end - start + 1).to_string())?,
+                None => builder
+                    .with_status(200)
+                    .with_header("content-length", &size.to_string())?,
+            };
+            Ok(builder.body(body))
         }
         StorageBackend::KV => {
             let kv = env.kv(ATTACHMENTS_KV).map_err(|_| AppError::Internal)?;
@@ -441,6 +910,54 @@ async fn stream_download_from_storage(
     }
 }
 
+// ── Range requests ──────────────────────────────────────────────────
+
+/// A single byte range of a `Range: bytes=...` header.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ByteRange {
+    /// `bytes=start-` or `bytes=start-end`.
+    From { start: u64, end: Option<u64> },
+    /// `bytes=-length`: the last `length` bytes.
+    Suffix(u64),
+}
+
+impl ByteRange {
+    /// The requested range, or `None` for malformed or multi-range headers, which are
+    /// answered with the whole file.
+    fn parse(value: &str) -> Option<Self> {
+        let spec = value.trim().strip_prefix("bytes=")?.trim();
+        if spec.contains(',') {
+            return None;
+        }
+        let (start, end) = spec.split_once('-')?;
+        let (start, end) = (start.trim(), end.trim());
+        if start.is_empty() {
+            return end.parse().ok().map(Self::Suffix);
+        }
+        let start = start.parse().ok()?;
+        let end = match end {
+            "" => None,
+            end => Some(end.parse().ok()?),
+        };
+        if end.is_some_and(|end| end < start) {
+            return None;
+        }
+        Some(Self::From { start, end })
+    }
+
+    /// First and last byte offsets within a file of `size` bytes, or `None` when
+    /// nothing of it is requested.
+    fn resolve(self, size: u64) -> Option<(u64, u64)> {
+        match self {
+            Self::From { start, .. } if start >= size => None,
+            Self::From { start, end } => Some((start, end.map_or(size - 1, |e| e.min(size - 1)))),
+            Self::Suffix(0) => None,
+            Self::Suffix(_) if size == 0 => None,
+            Self::Suffix(length) => Some((size - length.min(size), size - 1)),
+        }
+    }
+}
+
 // ── JWT verification ────────────────────────────────────────────────
 
 fn verify_token<T: Clone + for<'de> Deserialize<'de>>(
@@ -494,16 +1011,11 @@ fn bad(msg: &str) -> AppError {
     AppError::BadRequest(msg.to_string())
 }
 
-fn app_error_to_response(err: &AppError) -> Response {
-    let (status, msg) = match err {
-        AppError::NotFound(msg) => (404u16, msg.as_str()),
-        AppError::BadRequest(msg) => (400, msg.as_str()),
-        AppError::Unauthorized(msg) => (401, msg.as_str()),
-        AppError::TooManyRequests(msg) => (429, msg.as_str()),
-        _ => (500, "Internal server error"),
-    };
-    Response::from_json(&serde_json::json!({ "error": msg }))
-        .unwrap_or_else(|_| Response::error(msg, status).unwrap())
+fn app_error_to_response(err: AppError) -> Response {
+    let error = ApiError::from(err);
+    let status = error.status().as_u16();
+    Response::from_json(&error)
+        .unwrap_or_else(|_| Response::error("Internal server error", status).unwrap())
         .with_status(status)
 }
 
@@ -512,3 +1024,65 @@ fn ok_empty(status: u16) -> Result<Response, AppError> {
         .map(|r| r.with_status(status))
         .map_err(AppError::Worker)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn open_ended_range_runs_to_the_end() {
+        let range = ByteRange::parse("bytes=0-");
+        assert_eq!(
+            range,
+            Some(ByteRange::From {
+                start: 0,
+                end: None
+            })
+        );
+        assert_eq!(range.unwrap().resolve(10), Some((0, 9)));
+        assert_eq!(
+            ByteRange::parse("bytes=4-").unwrap().resolve(10),
+            Some((4, 9))
+        );
+    }
+
+    #[test]
+    fn suffix_range_takes_the_last_bytes() {
+        let range = ByteRange::parse("bytes=-3");
+        assert_eq!(range, Some(ByteRange::Suffix(3)));
+        assert_eq!(range.unwrap().resolve(10), Some((7, 9)));
+        // A suffix longer than the file is the whole file.
+        assert_eq!(ByteRange::Suffix(20).resolve(10), Some((0, 9)));
+        assert_eq!(ByteRange::Suffix(0).resolve(10), None);
+    }
+
+    #[test]
+    fn end_past_the_file_is_clamped() {
+        let range = ByteRange::parse("bytes=5-100").unwrap();
+        assert_eq!(range.resolve(10), Some((5, 9)));
+        assert_eq!(ByteRange::parse("bytes=10-20").unwrap().resolve(10), None);
+    }
+
+    #[test]
+    fn start_after_end_is_ignored() {
+        assert_eq!(ByteRange::parse("bytes=5-4"), None);
+        assert_eq!(
+            ByteRange::parse("bytes=5-5").unwrap().resolve(10),
+            Some((5, 5))
+        );
+    }
+
+    #[test]
+    fn empty_file_has_no_satisfiable_range() {
+        assert_eq!(ByteRange::parse("bytes=0-").unwrap().resolve(0), None);
+        assert_eq!(ByteRange::parse("bytes=-5").unwrap().resolve(0), None);
+    }
+
+    #[test]
+    fn multi_and_malformed_ranges_are_ignored() {
+        assert_eq!(ByteRange::parse("bytes=0-1,4-5"), None);
+        assert_eq!(ByteRange::parse("bytes=-"), None);
+        assert_eq!(ByteRange::parse("bytes=a-b"), None);
+        assert_eq!(ByteRange::parse("items=0-1"), None);
+    }
+}
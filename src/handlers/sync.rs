@@ -1,23 +1,32 @@
-use axum::extract::{Query, State};
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
 use std::sync::Arc;
 use worker::Env;
 
 use crate::{
     auth::Claims,
+    crypto::sha256_hex,
     db,
     error::AppError,
     handlers::{
-        attachments, ciphers, ciphers_default_row_query, domains, sends,
-        sync_response_prealloc_bytes, two_factor_enabled,
+        accounts::email_verification_required, attachments, ciphers, ciphers_default_row_query,
+        domains, policies, sends, storage, sync_response_prealloc_bytes, two_factor_enabled,
     },
     models::{
+        collection::Collection,
         folder::{Folder, FolderResponse},
-        sync::Profile,
+        organization::{Membership, MembershipStatus},
+        sync::{Profile, SyncState, TombstoneType},
         user::User,
     },
 };
 
 use ciphers::RawJson;
+
+use crate::metrics::{self, Metric};
 use serde::Deserialize;
 use serde_json::{json, Value};
 
@@ -26,16 +35,126 @@ pub struct SyncQuery {
     /// If true, set `domains` to null (vaultwarden behavior).
     #[serde(rename = "excludeDomains", default)]
     pub exclude_domains: bool,
+    /// `revision` of a previous sync response. When set and still servable, only
+    /// ciphers and folders changed since then are returned, plus the deleted ids.
+    #[serde(default)]
+    pub since: Option<i64>,
+}
+
+/// Ids of deleted objects of `object_type` the user could see, recorded after `since`.
+///
+/// Org cipher tombstones are also written when a cipher leaves a collection, so ciphers
+/// the user can still access are filtered out.
+async fn deleted_ids_since(
+    db: &db::Db,
+    user_id: &str,
+    object_type: TombstoneType,
+    since: i64,
+) -> Result<Vec<String>, AppError> {
+    #[derive(Deserialize)]
+    struct IdRow {
+        object_id: String,
+    }
+
+    let sql = format!(
+        "SELECT DISTINCT t.object_id FROM sync_tombstones t
+         WHERE t.object_type = ?2 AND t.revision > ?3
+           AND (t.user_id = ?1 OR t.organization_id IN
+                (SELECT organization_id FROM users_organizations WHERE user_id = ?1 AND status = ?4))
+           AND (t.object_type != 0
+                OR NOT EXISTS (SELECT 1 FROM ciphers c WHERE c.id = t.object_id AND {}))",
+        ciphers::cipher_access_filter("c", 1)
+    );
+    let rows: Vec<IdRow> = db
+        .prepare(&sql)
+        .bind(&[
+            user_id.into(),
+            (object_type as i32).into(),
+            (since as f64).into(),
+            (MembershipStatus::Confirmed as i32).into(),
+        ])?
+        .all()
+        .await
+        .map_err(|_| AppError::Database)?
+        .results()
+        .map_err(|_| AppError::Database)?;
+    Ok(rows.into_iter().map(|r| r.object_id).collect())
+}
+
+/// Weak ETag of the user's `variant` response (e.g. the sync query), from
+/// [`SyncState::user_fingerprint`].
+pub(crate) async fn user_etag(
+    db: &db::Db,
+    user_id: &str,
+    variant: &str,
+) -> Result<String, AppError> {
+    let fingerprint = SyncState::user_fingerprint(db, user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+    Ok(format!(
+        "W/\"{}\"",
+        &sha256_hex(&format!("{variant}|{fingerprint}"))[..32]
+    ))
+}
+
+/// Whether the request's `If-None-Match` matches `etag` (weak comparison).
+pub(crate) fn etag_matches(headers: &HeaderMap, etag: &str) -> bool {
+    let strip = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|tag| tag.trim() == "*" || strip(tag) == strip(etag))
+}
+
+/// `response` with `etag`, or an empty `304 Not Modified` when the client has it.
+pub(crate) fn with_etag(headers: &HeaderMap, etag: &str, response: impl IntoResponse) -> Response {
+    let mut response = if etag_matches(headers, etag) {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        response.into_response()
+    };
+    if let Ok(value) = HeaderValue::from_str(etag) {
+        response.headers_mut().insert(header::ETAG, value);
+    }
+    response.headers_mut().insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static("private, no-cache"),
+    );
+    response
 }
 
 #[worker::send]
 pub async fn get_sync_data(
     claims: Claims,
     State(env): State<Arc<Env>>,
+    headers: HeaderMap,
     Query(query): Query<SyncQuery>,
-) -> Result<RawJson, AppError> {
+) -> Result<Response, AppError> {
     let user_id = claims.sub;
     let db = db::get_db(&env)?;
+    User::record_sync(&db, &user_id).await;
+
+    // Clients polling an unchanged vault get a 304 before any vault data is read.
+    // Clients too old for SSH keys get a different cipher list, under their own ETag.
+    let type_filter = ciphers::client_cipher_type_filter(&headers);
+    let etag = user_etag(
+        &db,
+        &user_id,
+        &format!(
+            "sync:{}:{:?}{type_filter}",
+            query.exclude_domains, query.since
+        ),
+    )
+    .await?;
+    if etag_matches(&headers, &etag) {
+        return Ok(with_etag(&headers, &etag, ()));
+    }
+
+    // Read the revision before any data, so changes racing this request are sent again
+    // on the next delta rather than lost.
+    let sync_state = SyncState::load(&db).await?;
 
     // Fetch profile
     let user: User = db
@@ -47,6 +166,17 @@ pub async fn get_sync_data(
 
     let two_factor_enabled = two_factor_enabled(&db, &user_id).await?;
 
+    // Membership or collection-access changes can reveal ciphers with old revisions, so
+    // they force a full sync.
+    let access_revision: Option<f64> = db
+        .prepare("SELECT access_revision FROM users WHERE id = ?1")
+        .bind(&[user_id.clone().into()])?
+        .first(Some("access_revision"))
+        .await?;
+    let delta_since = query
+        .since
+        .filter(|since| sync_state.can_serve_delta(*since, access_revision.unwrap_or(0.0) as i64));
+
     let has_master_password = !user.master_password_hash.is_empty();
     let equivalent_domains = user.equivalent_domains.clone();
     let excluded_globals = user.excluded_globals.clone();
@@ -71,12 +201,17 @@ pub async fn get_sync_data(
     };
 
     // Fetch folders
-    let folders_db: Vec<Folder> = db
-        .prepare("SELECT * FROM folders WHERE user_id = ?1")
-        .bind(&[user_id.clone().into()])?
-        .all()
-        .await?
-        .results()?;
+    let folders_db: Vec<Folder> = match delta_since {
+        Some(since) => db
+            .prepare("SELECT * FROM folders WHERE user_id = ?1 AND revision > ?2")
+            .bind(&[user_id.clone().into(), (since as f64).into()])?,
+        None => db
+            .prepare("SELECT * FROM folders WHERE user_id = ?1")
+            .bind(&[user_id.clone().into()])?,
+    }
+    .all()
+    .await?
+    .results()?;
 
     let folders: Vec<FolderResponse> = folders_db.into_iter().map(|f| f.into()).collect();
 
@@ -85,12 +220,26 @@ pub async fn get_sync_data(
     let force_row_query = ciphers_default_row_query(env.as_ref());
 
     // Serialize profile and folders (small data, acceptable CPU cost)
-    let mut profile = Profile::from_user(user, two_factor_enabled)?;
+    let mut profile = Profile::from_user(
+        user,
+        two_factor_enabled,
+        email_verification_required(env.as_ref()),
+    )?;
+    profile.organizations = Membership::profile_organizations_json(&db, &user_id).await?;
+    storage::fill_profile_storage(env.as_ref(), &db, &mut profile).await?;
     // Match vaultwarden semantics: `_status` is `Invited` when no master password is set.
-    // We don't implement org invitations, but this helps clients interpret the account state.
+    // This helps clients interpret the account state.
     profile.status = if has_master_password { 0 } else { 1 };
     let profile_json = serde_json::to_string(&profile).map_err(|_| AppError::Internal)?;
     let folders_json = serde_json::to_string(&folders).map_err(|_| AppError::Internal)?;
+    let collections: Vec<Value> = Collection::list_for_user(&db, &user_id)
+        .await?
+        .iter()
+        .map(|c| c.to_details_json())
+        .collect();
+    let collections_json = serde_json::to_string(&collections).map_err(|_| AppError::Internal)?;
+    let policies_json = serde_json::to_string(&policies::user_policies_json(&db, &user_id).await?)
+        .map_err(|_| AppError::Internal)?;
 
     // Build response JSON via string concatenation (ciphers already raw JSON)
     let user_decryption_json = serde_json::to_string(&json!({
@@ -108,12 +257,15 @@ pub async fn get_sync_data(
     // {
     //   "profile": {...},
     //   "folders": [...],
-    //   "collections": [],
-    //   "policies": [],
+    //   "collections": [...],
+    //   "policies": [...],
     //   "ciphers": [...],
     //   "domains": {...} | null, // null when excludeDomains=true
     //   "sends": [],
     //   "userDecryption": {...},
+    //   "deletedCipherIds": [...], "deletedFolderIds": [...], // only when delta=true
+    //   "revision": 123,
+    //   "delta": false,
     //   "object": "sync"
     // }
     //
@@ -123,13 +275,33 @@ pub async fn get_sync_data(
     response.push_str(&profile_json);
     response.push_str(",\"folders\":");
     response.push_str(&folders_json);
-    response.push_str(",\"collections\":[],\"policies\":[],\"ciphers\":");
+    response.push_str(",\"collections\":");
+    response.push_str(&collections_json);
+    response.push_str(",\"policies\":");
+    response.push_str(&policies_json);
+    response.push_str(",\"ciphers\":");
+    let (cipher_where, cipher_params) = match delta_since {
+        Some(since) => (
+            format!(
+                "WHERE {} AND c.revision > ?2{type_filter}",
+                ciphers::cipher_access_filter("c", 1)
+            ),
+            vec![user_id.clone().into(), (since as f64).into()],
+        ),
+        None => (
+            format!(
+                "WHERE {}{type_filter}",
+                ciphers::cipher_access_filter("c", 1)
+            ),
+            vec![user_id.clone().into()],
+        ),
+    };
     ciphers::append_cipher_json_array_raw(
         &mut response,
         &db,
         include_attachments,
-        "WHERE c.user_id = ?1",
-        &[user_id.clone().into()],
+        &cipher_where,
+        &cipher_params,
         "",
         force_row_query,
     )
@@ -155,7 +327,36 @@ pub async fn get_sync_data(
     sends::append_sends_json_array(&mut response, &db, &user_id).await?;
     response.push_str(",\"userDecryption\":");
     response.push_str(&user_decryption_json);
+
+    // Delta sync extension: `revision` is the `since` for the next request; `delta`
+    // tells whether ciphers/folders are complete or only the changes.
+    if let Some(since) = delta_since {
+        let deleted_ciphers =
+            deleted_ids_since(&db, &user_id, TombstoneType::Cipher, since).await?;
+        let deleted_folders =
+            deleted_ids_since(&db, &user_id, TombstoneType::Folder, since).await?;
+        response.push_str(",\"deletedCipherIds\":");
+        response
+            .push_str(&serde_json::to_string(&deleted_ciphers).map_err(|_| AppError::Internal)?);
+        response.push_str(",\"deletedFolderIds\":");
+        response
+            .push_str(&serde_json::to_string(&deleted_folders).map_err(|_| AppError::Internal)?);
+    }
+    response.push_str(&format!(
+        ",\"revision\":{},\"delta\":{}",
+        sync_state.revision,
+        delta_since.is_some()
+    ));
     response.push_str(",\"object\":\"sync\"}");
 
-    Ok(RawJson(response))
+    metrics::observe(
+        Metric::SyncBytes,
+        response.len() as f64,
+        &[if delta_since.is_some() {
+            "delta"
+        } else {
+            "full"
+        }],
+    );
+    Ok(with_etag(&headers, &etag, RawJson(response)))
 }
@@ -1,22 +1,49 @@
-use axum::{extract::State, Json};
+use axum::{
+    extract::{Path, State},
+    Extension, Json,
+};
+use chrono::Utc;
 use serde_json::Value;
 use std::sync::Arc;
 use worker::Env;
 
 use crate::d1_query;
 use crate::{
-    auth::AuthUser,
-    crypto::{base32_decode, ct_eq, generate_recovery_code, generate_totp_secret, validate_totp},
+    auth::{AuthUser, Claims},
+    crypto::{
+        base32_decode, ct_eq, generate_email_token, generate_recovery_code, generate_totp_secret,
+        hash_recovery_code, sha256_hex, validate_totp,
+    },
     db,
+    duo::{self, DuoConfig},
     error::AppError,
-    handlers::allow_totp_drift,
+    handlers::{accounts::verify_user, allow_totp_drift, organizations::require_member_access},
+    mail,
+    models::organization::MembershipType,
     models::twofactor::{
-        DisableAuthenticatorData, DisableTwoFactorData, EnableAuthenticatorData, TwoFactor,
-        TwoFactorType,
+        slot_id, DeleteWebauthnData, DisableAuthenticatorData, DisableTwoFactorData, EmailData,
+        EmailTokenData, EnableAuthenticatorData, EnableDuoData, EnableWebauthnData,
+        EnableYubikeyData, OrgTwoFactor, RecoverTwoFactorData, SendEmailData, SendEmailLoginData,
+        TwoFactor, TwoFactorType, YubikeyMetadata,
     },
     models::user::{PasswordOrOtpData, User},
+    notifications,
+    rate_limit::LoginBackoff,
+    webauthn::{self, RelyingParty, WebauthnCredential},
+    yubikey::{self, YubicoConfig},
+    BaseUrl,
 };
 
+/// Maximum number of security keys per user (matches the client UI).
+const MAX_WEBAUTHN_KEYS: usize = 5;
+
+/// How long an emailed two-factor code stays valid.
+pub(crate) const EMAIL_TOKEN_TTL_SECS: i64 = 600;
+/// Minimum delay between two emailed codes for the same account.
+pub(crate) const EMAIL_TOKEN_RESEND_SECS: i64 = 60;
+/// Failed attempts after which an emailed code is invalidated.
+const EMAIL_TOKEN_MAX_ATTEMPTS: u32 = 3;
+
 /// List all 2FA records for a user (excludes atype >= 1000).
 pub(crate) async fn list_user_twofactors(
     db: &crate::db::Db,
@@ -31,14 +58,27 @@ pub(crate) async fn list_user_twofactors(
         .map_err(|_| AppError::Database)
 }
 
-/// Whether the user has 2FA enabled.
+/// Two-factor providers a user can log in with, in the order offered to clients.
 ///
-/// For now, we intentionally only treat Authenticator (TOTP) as a real 2FA provider.
 /// Remember-device tokens are never considered a 2FA method by themselves.
+pub(crate) fn enabled_twofactor_providers(twofactors: &[TwoFactor]) -> Vec<i32> {
+    [
+        TwoFactorType::Authenticator,
+        TwoFactorType::Email,
+        TwoFactorType::Duo,
+        TwoFactorType::YubiKey,
+        TwoFactorType::Webauthn,
+        TwoFactorType::OrganizationDuo,
+    ]
+    .into_iter()
+    .map(|t| t as i32)
+    .filter(|&atype| twofactors.iter().any(|tf| tf.enabled && tf.atype == atype))
+    .collect()
+}
+
+/// Whether the user has 2FA enabled.
 pub(crate) fn is_twofactor_enabled(twofactors: &[TwoFactor]) -> bool {
-    twofactors
-        .iter()
-        .any(|tf| tf.enabled && tf.atype == TwoFactorType::Authenticator as i32)
+    !enabled_twofactor_providers(twofactors).is_empty()
 }
 
 /// GET /api/two-factor - Get all enabled 2FA providers for current user
@@ -78,7 +118,7 @@ pub async fn get_authenticator(
         .ok_or_else(|| AppError::Unauthorized("User not found".to_string()))?;
     let user: User = serde_json::from_value(user_value).map_err(|_| AppError::Internal)?;
 
-    validate_password_or_otp(&user, &data).await?;
+    verify_user(&db, &user, &data).await?;
 
     // Check if TOTP is already configured
     let existing: Option<Value> = db
@@ -125,7 +165,8 @@ pub async fn activate_authenticator(
         .ok_or_else(|| AppError::Unauthorized("User not found".to_string()))?;
     let user: User = serde_json::from_value(user_value).map_err(|_| AppError::Internal)?;
 
-    validate_password_or_otp(
+    verify_user(
+        &db,
         &user,
         &PasswordOrOtpData {
             master_password_hash: data.master_password_hash,
@@ -223,17 +264,15 @@ pub async fn disable_twofactor(
 ) -> Result<Json<Value>, AppError> {
     let db = db::get_db(&env)?;
 
-    // Verify master password
-    let user_value: Value = db
-        .prepare("SELECT * FROM users WHERE id = ?1")
-        .bind(&[user_id.clone().into()])?
-        .first(None)
-        .await
-        .map_err(|_| AppError::Database)?
-        .ok_or_else(|| AppError::Unauthorized("User not found".to_string()))?;
-    let user: User = serde_json::from_value(user_value).map_err(|_| AppError::Internal)?;
+    let type_ = data.r#type;
+    let provider = TwoFactorType::from_i32(type_)
+        .filter(|provider| provider.provider_name().is_some())
+        .ok_or_else(|| AppError::BadRequest("Invalid two factor type".to_string()))?;
 
-    validate_password_or_otp(
+    // Verify master password
+    let user = load_user(&db, &user_id).await?;
+    verify_user(
+        &db,
         &user,
         &PasswordOrOtpData {
             master_password_hash: data.master_password_hash,
@@ -242,10 +281,8 @@ pub async fn disable_twofactor(
     )
     .await?;
 
-    let type_ = data.r#type;
-
     // Delete the specified 2FA type
-    d1_query!(
+    let result = d1_query!(
         &db,
         "DELETE FROM twofactor WHERE user_uuid = ?1 AND atype = ?2",
         &user_id,
@@ -255,10 +292,19 @@ pub async fn disable_twofactor(
     .run()
     .await
     .map_err(|_| AppError::Database)?;
+    let removed = result
+        .meta()
+        .ok()
+        .flatten()
+        .and_then(|meta| meta.changes)
+        .unwrap_or(0)
+        > 0;
 
-    log::info!("User {} disabled 2FA type {}", user_id, type_);
-
-    clear_recovery_if_no_twofactor(&db, &user_id).await?;
+    // Disabling a method that is not set up changes nothing.
+    if removed {
+        log::info!("User {} disabled 2FA type {}", user_id, type_);
+        on_twofactor_removed(&env, &db, &user, provider).await?;
+    }
 
     Ok(Json(serde_json::json!({
         "enabled": false,
@@ -280,17 +326,11 @@ pub async fn disable_authenticator(
         return Err(AppError::BadRequest("Invalid two factor type".to_string()));
     }
 
-    // Verify master password (OTP not supported in this minimal implementation)
-    let user_value: Value = db
-        .prepare("SELECT * FROM users WHERE id = ?1")
-        .bind(&[user_id.clone().into()])?
-        .first(None)
-        .await
-        .map_err(|_| AppError::Database)?
-        .ok_or_else(|| AppError::Unauthorized("User not found".to_string()))?;
-    let user: User = serde_json::from_value(user_value).map_err(|_| AppError::Internal)?;
+    // Verify master password or emailed code
+    let user = load_user(&db, &user_id).await?;
 
-    validate_password_or_otp(
+    verify_user(
+        &db,
         &user,
         &PasswordOrOtpData {
             master_password_hash: data.master_password_hash,
@@ -332,7 +372,7 @@ pub async fn disable_authenticator(
         data.r#type
     );
 
-    clear_recovery_if_no_twofactor(&db, &user_id).await?;
+    on_twofactor_removed(&env, &db, &user, TwoFactorType::Authenticator).await?;
 
     Ok(Json(serde_json::json!({
         "enabled": false,
@@ -352,6 +392,9 @@ pub async fn disable_twofactor_put(
 }
 
 /// POST /api/two-factor/get-recover - Get recovery code
+///
+/// Only a hash of the code is stored, so every call issues a new code and the
+/// previous one stops working.
 #[worker::send]
 pub async fn get_recover(
     State(env): State<Arc<Env>>,
@@ -359,78 +402,1126 @@ pub async fn get_recover(
     Json(data): Json<PasswordOrOtpData>,
 ) -> Result<Json<Value>, AppError> {
     let db = db::get_db(&env)?;
+    let user = load_user(&db, &user_id).await?;
+    verify_user(&db, &user, &data).await?;
 
-    // Verify master password
-    let user_value: Value = db
-        .prepare("SELECT * FROM users WHERE id = ?1")
-        .bind(&[user_id.clone().into()])?
-        .first(None)
-        .await
-        .map_err(|_| AppError::Database)?
-        .ok_or_else(|| AppError::Unauthorized("User not found".to_string()))?;
-    let user: User = serde_json::from_value(user_value).map_err(|_| AppError::Internal)?;
-
-    validate_password_or_otp(&user, &data).await?;
+    let code = match user.totp_recover {
+        Some(_) => Some(store_new_recovery_code(&db, &user_id).await?),
+        None => None,
+    };
 
     Ok(Json(serde_json::json!({
-        "code": user.totp_recover,
+        "code": code,
         "object": "twoFactorRecover"
     })))
 }
 
-// Helper functions
+/// POST /api/two-factor/recover - Turn off 2FA with the master password and recovery code
+#[worker::send]
+pub async fn recover(
+    State(env): State<Arc<Env>>,
+    Json(data): Json<RecoverTwoFactorData>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let email = data.email.trim().to_lowercase();
+    let backoff_key = format!("email:{email}");
+    let backoff = LoginBackoff::from_env(&env);
+    let retry_after = backoff.retry_after(&env, &backoff_key).await;
+    if retry_after > 0 {
+        return Err(AppError::TooManyRequests(format!(
+            "Too many failed attempts. Please try again in {retry_after} seconds."
+        )));
+    }
+
+    let invalid = || {
+        AppError::BadRequest(
+            "Username, password or recovery code is incorrect. Try again.".to_string(),
+        )
+    };
+    let Some(user) = User::find_by_email(&db, &email).await? else {
+        backoff.record_failure(&env, &backoff_key).await;
+        return Err(invalid());
+    };
+    let password_valid = user
+        .verify_master_password(&data.master_password_hash)
+        .await?
+        .is_valid();
+    if !password_valid || !recovery_code_matches(&user, &data.recovery_code) {
+        backoff.record_failure(&env, &backoff_key).await;
+        return Err(invalid());
+    }
+
+    backoff.reset(&env, &backoff_key).await;
+    reset_twofactor(&env, &db, &user).await?;
+    log::info!("User {} turned off 2FA with the recovery code", user.id);
+
+    Ok(Json(serde_json::json!({})))
+}
+
+/// POST /api/two-factor/get-webauthn - List registered security keys
+#[worker::send]
+pub async fn get_webauthn(
+    State(env): State<Arc<Env>>,
+    AuthUser(user_id, _): AuthUser,
+    Json(data): Json<PasswordOrOtpData>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let user = load_user(&db, &user_id).await?;
+    verify_user(&db, &user, &data).await?;
+
+    let (existing, credentials) = load_webauthn(&db, &user_id).await?;
+    Ok(Json(webauthn_keys_json(
+        existing.is_some_and(|tf| tf.enabled),
+        &credentials,
+    )))
+}
+
+/// POST /api/two-factor/get-webauthn-challenge - Start registering a security key
+#[worker::send]
+pub async fn get_webauthn_challenge(
+    State(env): State<Arc<Env>>,
+    Extension(BaseUrl(base_url)): Extension<BaseUrl>,
+    AuthUser(user_id, _): AuthUser,
+    Json(data): Json<PasswordOrOtpData>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let user = load_user(&db, &user_id).await?;
+    verify_user(&db, &user, &data).await?;
+
+    let (_, credentials) = load_webauthn(&db, &user_id).await?;
+    let mut challenge = webauthn::start_registration(
+        &env,
+        &RelyingParty::from_base_url(&base_url),
+        &user.id,
+        &user.email,
+        user.name.as_deref(),
+        &credentials,
+    )
+    .await?;
+    challenge["status"] = "ok".into();
+    challenge["errorMessage"] = "".into();
+
+    Ok(Json(challenge))
+}
+
+/// POST /api/two-factor/webauthn - Finish registering a security key
+#[worker::send]
+pub async fn activate_webauthn(
+    State(env): State<Arc<Env>>,
+    Extension(BaseUrl(base_url)): Extension<BaseUrl>,
+    AuthUser(user_id, _): AuthUser,
+    Json(data): Json<EnableWebauthnData>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let user = load_user(&db, &user_id).await?;
+    verify_user(
+        &db,
+        &user,
+        &PasswordOrOtpData {
+            master_password_hash: data.master_password_hash,
+            otp: data.otp,
+        },
+    )
+    .await?;
+
+    let id = slot_id(&data.id)
+        .ok_or_else(|| AppError::BadRequest("Invalid security key id".to_string()))?;
+    let (existing, mut credentials) = load_webauthn(&db, &user_id).await?;
+    credentials.retain(|c| c.id != id);
+    if credentials.len() >= MAX_WEBAUTHN_KEYS {
+        return Err(AppError::BadRequest(
+            "Maximum number of security keys reached".to_string(),
+        ));
+    }
+
+    let credential = webauthn::finish_registration(
+        &env,
+        &RelyingParty::from_base_url(&base_url),
+        &user_id,
+        id,
+        data.name,
+        &data.device_response,
+    )
+    .await?;
+    if credentials
+        .iter()
+        .any(|c| c.credential_id == credential.credential_id)
+    {
+        return Err(AppError::BadRequest(
+            "This security key is already registered".to_string(),
+        ));
+    }
+    credentials.push(credential);
+    credentials.sort_by_key(|c| c.id);
+
+    save_webauthn(&db, &user_id, existing, &credentials).await?;
+    generate_recovery_code_for_user(&db, &user_id).await?;
+
+    log::info!("User {} registered security key {}", user_id, id);
+
+    Ok(Json(webauthn_keys_json(true, &credentials)))
+}
+
+/// PUT /api/two-factor/webauthn - Same as POST
+#[worker::send]
+pub async fn activate_webauthn_put(
+    state: State<Arc<Env>>,
+    base_url: Extension<BaseUrl>,
+    auth_user: AuthUser,
+    json: Json<EnableWebauthnData>,
+) -> Result<Json<Value>, AppError> {
+    activate_webauthn(state, base_url, auth_user, json).await
+}
+
+/// DELETE /api/two-factor/webauthn - Remove a security key
+#[worker::send]
+pub async fn delete_webauthn(
+    State(env): State<Arc<Env>>,
+    AuthUser(user_id, _): AuthUser,
+    Json(data): Json<DeleteWebauthnData>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let user = load_user(&db, &user_id).await?;
+    verify_user(
+        &db,
+        &user,
+        &PasswordOrOtpData {
+            master_password_hash: data.master_password_hash,
+            otp: data.otp,
+        },
+    )
+    .await?;
+
+    let id = slot_id(&data.id)
+        .ok_or_else(|| AppError::BadRequest("Invalid security key id".to_string()))?;
+    let (existing, mut credentials) = load_webauthn(&db, &user_id).await?;
+    let Some(existing) = existing else {
+        return Err(AppError::BadRequest("WebAuthn not configured".to_string()));
+    };
+
+    let before = credentials.len();
+    credentials.retain(|c| c.id != id);
+    if credentials.len() == before {
+        return Err(AppError::BadRequest("Security key not found".to_string()));
+    }
+
+    if credentials.is_empty() {
+        d1_query!(&db, "DELETE FROM twofactor WHERE uuid = ?1", &existing.uuid)
+            .map_err(|_| AppError::Database)?
+            .run()
+            .await
+            .map_err(|_| AppError::Database)?;
+        on_twofactor_removed(&env, &db, &user, TwoFactorType::Webauthn).await?;
+    } else {
+        save_webauthn(&db, &user_id, Some(existing), &credentials).await?;
+    }
+
+    log::info!("User {} removed security key {}", user_id, id);
+
+    Ok(Json(webauthn_keys_json(
+        !credentials.is_empty(),
+        &credentials,
+    )))
+}
+
+/// POST /api/two-factor/get-email - Get the email provider status
+#[worker::send]
+pub async fn get_email(
+    State(env): State<Arc<Env>>,
+    AuthUser(user_id, _): AuthUser,
+    Json(data): Json<PasswordOrOtpData>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let user = load_user(&db, &user_id).await?;
+    verify_user(&db, &user, &data).await?;
+
+    let (enabled, email) = match find_twofactor(&db, &user_id, TwoFactorType::Email).await? {
+        Some(tf) => (tf.enabled, email_token_data(&tf)?.email),
+        None => (false, user.email),
+    };
+
+    Ok(Json(email_json(enabled, &email)))
+}
+
+/// POST /api/two-factor/send-email - Send a setup code to the address being enabled
+#[worker::send]
+pub async fn send_email(
+    State(env): State<Arc<Env>>,
+    AuthUser(user_id, _): AuthUser,
+    Json(data): Json<SendEmailData>,
+) -> Result<Json<()>, AppError> {
+    let db = db::get_db(&env)?;
+    let user = load_user(&db, &user_id).await?;
+    verify_user(
+        &db,
+        &user,
+        &PasswordOrOtpData {
+            master_password_hash: data.master_password_hash,
+            otp: data.otp,
+        },
+    )
+    .await?;
+
+    if !mail::mail_configured(&env) {
+        return Err(AppError::BadRequest(
+            "Email delivery is not configured on this server".to_string(),
+        ));
+    }
+
+    let email = data.email.trim().to_lowercase();
+    let pending = find_twofactor(&db, &user_id, TwoFactorType::EmailVerificationChallenge).await?;
+    let mut token_data = match pending {
+        Some(ref tf) => {
+            let mut token_data = email_token_data(tf)?;
+            if token_data.email != email {
+                token_data = EmailTokenData {
+                    token_sent_at: token_data.token_sent_at,
+                    ..EmailTokenData::new(email)
+                };
+            }
+            token_data
+        }
+        None => EmailTokenData::new(email),
+    };
 
-async fn validate_password_or_otp(user: &User, data: &PasswordOrOtpData) -> Result<(), AppError> {
-    if let Some(ref password_hash) = data.master_password_hash {
-        let verification = user.verify_master_password(password_hash).await?;
-        if verification.is_valid() {
-            return Ok(());
+    ensure_can_resend(&token_data)?;
+    issue_email_token(&env, &mut token_data).await?;
+
+    let payload = serde_json::to_string(&token_data).map_err(|_| AppError::Internal)?;
+    match pending {
+        Some(tf) => update_twofactor_data(&db, &tf.uuid, &payload).await?,
+        None => {
+            let mut tf = TwoFactor::new(
+                user_id.clone(),
+                TwoFactorType::EmailVerificationChallenge,
+                payload,
+            );
+            tf.enabled = false;
+            insert_twofactor(&db, &tf).await?;
         }
     }
 
-    // OTP validation would be handled here if we had protected actions support
-    // For now, master password is required
+    Ok(Json(()))
+}
+
+/// PUT /api/two-factor/email - Enable email 2FA with the setup code
+#[worker::send]
+pub async fn activate_email(
+    State(env): State<Arc<Env>>,
+    AuthUser(user_id, _): AuthUser,
+    Json(data): Json<EmailData>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let user = load_user(&db, &user_id).await?;
+    verify_user(
+        &db,
+        &user,
+        &PasswordOrOtpData {
+            master_password_hash: data.master_password_hash,
+            otp: data.otp,
+        },
+    )
+    .await?;
+
+    let pending = find_twofactor(&db, &user_id, TwoFactorType::EmailVerificationChallenge)
+        .await?
+        .ok_or_else(|| AppError::BadRequest("No verification code was sent".to_string()))?;
+    let mut token_data = email_token_data(&pending)?;
+    if token_data.email != data.email.trim().to_lowercase() {
+        return Err(AppError::BadRequest(
+            "Email does not match the address the code was sent to".to_string(),
+        ));
+    }
+
+    let verification = verify_email_token(&mut token_data, &data.token);
+    if verification.is_err() {
+        let payload = serde_json::to_string(&token_data).map_err(|_| AppError::Internal)?;
+        update_twofactor_data(&db, &pending.uuid, &payload).await?;
+    }
+    verification?;
+
+    d1_query!(
+        &db,
+        "DELETE FROM twofactor WHERE user_uuid = ?1 AND atype IN (?2, ?3)",
+        &user_id,
+        TwoFactorType::Email as i32,
+        TwoFactorType::EmailVerificationChallenge as i32
+    )
+    .map_err(|_| AppError::Database)?
+    .run()
+    .await
+    .map_err(|_| AppError::Database)?;
+
+    let email = token_data.email.clone();
+    let payload = serde_json::to_string(&EmailTokenData::new(email.clone()))
+        .map_err(|_| AppError::Internal)?;
+    insert_twofactor(
+        &db,
+        &TwoFactor::new(user_id.clone(), TwoFactorType::Email, payload),
+    )
+    .await?;
+    generate_recovery_code_for_user(&db, &user_id).await?;
+
+    log::info!("User {} enabled email 2FA", user_id);
 
-    Err(AppError::Unauthorized("Invalid password".to_string()))
+    Ok(Json(email_json(true, &email)))
 }
 
-async fn generate_recovery_code_for_user(
-    db: &crate::db::Db,
-    user_id: &str,
-) -> Result<(), AppError> {
-    // Check if recovery code already exists
-    let user_value: Value = db
-        .prepare("SELECT totp_recover FROM users WHERE id = ?1")
-        .bind(&[user_id.into()])?
-        .first(None)
-        .await
-        .map_err(|_| AppError::Database)?
-        .ok_or_else(|| AppError::Unauthorized("User not found".to_string()))?;
+/// POST /api/two-factor/email - Same as PUT
+#[worker::send]
+pub async fn activate_email_post(
+    state: State<Arc<Env>>,
+    auth_user: AuthUser,
+    json: Json<EmailData>,
+) -> Result<Json<Value>, AppError> {
+    activate_email(state, auth_user, json).await
+}
 
-    let totp_recover: Option<String> = user_value
-        .get("totp_recover")
-        .and_then(|v| v.as_str())
-        .map(|s| s.to_string());
+/// POST /api/two-factor/send-email-login - Send a login code
+///
+/// Called by the clients on the two-step login screen, before the user has a token,
+/// so the master password is verified here.
+#[worker::send]
+pub async fn send_email_login(
+    State(env): State<Arc<Env>>,
+    Json(data): Json<SendEmailLoginData>,
+) -> Result<Json<()>, AppError> {
+    let db = db::get_db(&env)?;
+    let invalid = || AppError::Unauthorized("Username or password is incorrect".to_string());
 
-    if totp_recover.is_none() {
-        let recovery_code = generate_recovery_code()?;
-        d1_query!(
-            db,
-            "UPDATE users SET totp_recover = ?1 WHERE id = ?2",
-            &recovery_code,
-            user_id
-        )
-        .map_err(|_| AppError::Database)?
-        .run()
-        .await
-        .map_err(|_| AppError::Database)?;
+    let user = User::find_by_email(&db, &data.email.trim().to_lowercase())
+        .await?
+        .ok_or_else(invalid)?;
+    if !user
+        .verify_master_password(&data.master_password_hash)
+        .await?
+        .is_valid()
+    {
+        return Err(invalid());
     }
 
-    Ok(())
+    let tf = find_twofactor(&db, &user.id, TwoFactorType::Email)
+        .await?
+        .filter(|tf| tf.enabled)
+        .ok_or_else(|| AppError::BadRequest("Email 2FA is not enabled".to_string()))?;
+    let mut token_data = email_token_data(&tf)?;
+
+    ensure_can_resend(&token_data)?;
+    issue_email_token(&env, &mut token_data).await?;
+    let payload = serde_json::to_string(&token_data).map_err(|_| AppError::Internal)?;
+    update_twofactor_data(&db, &tf.uuid, &payload).await?;
+
+    Ok(Json(()))
+}
+
+/// Login payload for the email provider, sending a code right away when email is
+/// the only provider (the clients then don't offer a "send code" step).
+pub(crate) async fn email_login_challenge(
+    env: &Env,
+    db: &crate::db::Db,
+    tf: &TwoFactor,
+    only_provider: bool,
+) -> Result<Value, AppError> {
+    let mut token_data = email_token_data(tf)?;
+
+    if only_provider && ensure_can_resend(&token_data).is_ok() {
+        match issue_email_token(env, &mut token_data).await {
+            Ok(()) => {
+                let payload = serde_json::to_string(&token_data).map_err(|_| AppError::Internal)?;
+                update_twofactor_data(db, &tf.uuid, &payload).await?;
+            }
+            Err(e) => log::warn!("Failed to send email 2FA code: {e}"),
+        }
+    }
+
+    Ok(serde_json::json!({ "Email": obscure_email(&token_data.email) }))
 }
 
-/// Clear recovery code when no real 2FA providers remain.
-async fn clear_recovery_if_no_twofactor(db: &crate::db::Db, user_id: &str) -> Result<(), AppError> {
+/// Verify an emailed login code, consuming it on success.
+pub(crate) async fn verify_email_login(
+    db: &crate::db::Db,
+    tf: &TwoFactor,
+    token: &str,
+) -> Result<(), AppError> {
+    let mut token_data = email_token_data(tf)?;
+    let verification = verify_email_token(&mut token_data, token);
+    let payload = serde_json::to_string(&token_data).map_err(|_| AppError::Internal)?;
+    update_twofactor_data(db, &tf.uuid, &payload).await?;
+    verification
+}
+
+/// POST /api/two-factor/get-duo - Get the Duo provider configuration
+#[worker::send]
+pub async fn get_duo(
+    State(env): State<Arc<Env>>,
+    AuthUser(user_id, _): AuthUser,
+    Json(data): Json<PasswordOrOtpData>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let user = load_user(&db, &user_id).await?;
+    verify_user(&db, &user, &data).await?;
+
+    let existing = find_twofactor(&db, &user_id, TwoFactorType::Duo).await?;
+    Ok(Json(match existing {
+        Some(tf) => duo_json(tf.enabled, Some(&DuoConfig::from_data(&tf.data)?)),
+        None => duo_json(false, None),
+    }))
+}
+
+/// PUT /api/two-factor/duo - Enable Duo with the credentials of a Duo Web SDK application
+#[worker::send]
+pub async fn activate_duo(
+    State(env): State<Arc<Env>>,
+    AuthUser(user_id, _): AuthUser,
+    Json(data): Json<EnableDuoData>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let user = load_user(&db, &user_id).await?;
+    verify_user(
+        &db,
+        &user,
+        &PasswordOrOtpData {
+            master_password_hash: data.master_password_hash.clone(),
+            otp: data.otp.clone(),
+        },
+    )
+    .await?;
+
+    let existing = find_twofactor(&db, &user_id, TwoFactorType::Duo)
+        .await?
+        .map(|tf| DuoConfig::from_data(&tf.data))
+        .transpose()?;
+    let config = duo_config_from_request(&data, existing.as_ref()).await?;
+    let payload = serde_json::to_string(&config).map_err(|_| AppError::Internal)?;
+
+    d1_query!(
+        &db,
+        "DELETE FROM twofactor WHERE user_uuid = ?1 AND atype = ?2",
+        &user_id,
+        TwoFactorType::Duo as i32
+    )
+    .map_err(|_| AppError::Database)?
+    .run()
+    .await
+    .map_err(|_| AppError::Database)?;
+    insert_twofactor(
+        &db,
+        &TwoFactor::new(user_id.clone(), TwoFactorType::Duo, payload),
+    )
+    .await?;
+    generate_recovery_code_for_user(&db, &user_id).await?;
+
+    log::info!("User {} enabled Duo 2FA", user_id);
+
+    Ok(Json(duo_json(true, Some(&config))))
+}
+
+/// POST /api/two-factor/duo - Same as PUT
+#[worker::send]
+pub async fn activate_duo_post(
+    state: State<Arc<Env>>,
+    auth_user: AuthUser,
+    json: Json<EnableDuoData>,
+) -> Result<Json<Value>, AppError> {
+    activate_duo(state, auth_user, json).await
+}
+
+/// POST /api/two-factor/get-yubikey - Get the registered YubiKeys
+#[worker::send]
+pub async fn get_yubikey(
+    State(env): State<Arc<Env>>,
+    AuthUser(user_id, _): AuthUser,
+    Json(data): Json<PasswordOrOtpData>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let user = load_user(&db, &user_id).await?;
+    verify_user(&db, &user, &data).await?;
+
+    let existing = find_twofactor(&db, &user_id, TwoFactorType::YubiKey).await?;
+    Ok(Json(match existing {
+        Some(tf) => yubikey_json(tf.enabled, &yubikey_metadata(&tf)?),
+        None => yubikey_json(false, &YubikeyMetadata::default()),
+    }))
+}
+
+/// PUT /api/two-factor/yubikey - Enable YubiKey OTP with up to five keys
+///
+/// New keys are given as an OTP and validated with YubiCloud; keys already
+/// registered come back as their public ID and are kept as they are.
+#[worker::send]
+pub async fn activate_yubikey(
+    State(env): State<Arc<Env>>,
+    AuthUser(user_id, _): AuthUser,
+    Json(data): Json<EnableYubikeyData>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let user = load_user(&db, &user_id).await?;
+    verify_user(
+        &db,
+        &user,
+        &PasswordOrOtpData {
+            master_password_hash: data.master_password_hash.clone(),
+            otp: data.otp.clone(),
+        },
+    )
+    .await?;
+
+    let existing = find_twofactor(&db, &user_id, TwoFactorType::YubiKey)
+        .await?
+        .map(|tf| yubikey_metadata(&tf))
+        .transpose()?
+        .unwrap_or_default();
+
+    let is_registered = |key: &str| existing.keys.iter().any(|id| id == key);
+    let config = data
+        .keys()
+        .into_iter()
+        .any(|key| !is_registered(key))
+        .then(|| YubicoConfig::from_env(&env))
+        .transpose()?;
+
+    let mut keys: Vec<String> = Vec::new();
+    for key in data.keys() {
+        let id = match &config {
+            Some(config) if !is_registered(key) => yubikey::verify_otp(config, key).await?,
+            _ => key.to_string(),
+        };
+        if !keys.contains(&id) {
+            keys.push(id);
+        }
+    }
+    if keys.is_empty() {
+        return Err(AppError::BadRequest("No YubiKey provided".to_string()));
+    }
+
+    let metadata = YubikeyMetadata {
+        keys,
+        nfc: data.nfc,
+    };
+    let payload = serde_json::to_string(&metadata).map_err(|_| AppError::Internal)?;
+
+    d1_query!(
+        &db,
+        "DELETE FROM twofactor WHERE user_uuid = ?1 AND atype = ?2",
+        &user_id,
+        TwoFactorType::YubiKey as i32
+    )
+    .map_err(|_| AppError::Database)?
+    .run()
+    .await
+    .map_err(|_| AppError::Database)?;
+    insert_twofactor(
+        &db,
+        &TwoFactor::new(user_id.clone(), TwoFactorType::YubiKey, payload),
+    )
+    .await?;
+    generate_recovery_code_for_user(&db, &user_id).await?;
+
+    log::info!("User {} enabled YubiKey OTP 2FA", user_id);
+
+    Ok(Json(yubikey_json(true, &metadata)))
+}
+
+/// POST /api/two-factor/yubikey - Same as PUT
+#[worker::send]
+pub async fn activate_yubikey_post(
+    state: State<Arc<Env>>,
+    auth_user: AuthUser,
+    json: Json<EnableYubikeyData>,
+) -> Result<Json<Value>, AppError> {
+    activate_yubikey(state, auth_user, json).await
+}
+
+/// GET /api/organizations/{org_id}/two-factor - The organization's 2FA providers
+#[worker::send]
+pub async fn get_organization_twofactor(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(org_id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_access(&db, &org_id, &claims.sub, MembershipType::Owner, &[]).await?;
+
+    let providers = OrgTwoFactor::list_by_org(&db, &org_id).await?;
+    Ok(Json(serde_json::json!({
+        "data": providers.iter().map(OrgTwoFactor::to_json_provider).collect::<Vec<_>>(),
+        "object": "list",
+        "continuationToken": null,
+    })))
+}
+
+/// POST /api/organizations/{org_id}/two-factor/get-duo - The organization's Duo configuration
+#[worker::send]
+pub async fn get_organization_duo(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(org_id): Path<String>,
+    Json(data): Json<PasswordOrOtpData>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_access(&db, &org_id, &claims.sub, MembershipType::Owner, &[]).await?;
+    let user = load_user(&db, &claims.sub).await?;
+    verify_user(&db, &user, &data).await?;
+
+    let existing =
+        OrgTwoFactor::find_by_org_and_type(&db, &org_id, TwoFactorType::OrganizationDuo).await?;
+    Ok(Json(match existing {
+        Some(tf) => duo_json(tf.enabled, Some(&DuoConfig::from_data(&tf.data)?)),
+        None => duo_json(false, None),
+    }))
+}
+
+/// PUT /api/organizations/{org_id}/two-factor/duo - Require Duo for the organization's members
+#[worker::send]
+pub async fn activate_organization_duo(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(org_id): Path<String>,
+    Json(data): Json<EnableDuoData>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_access(&db, &org_id, &claims.sub, MembershipType::Owner, &[]).await?;
+    let user = load_user(&db, &claims.sub).await?;
+    verify_user(
+        &db,
+        &user,
+        &PasswordOrOtpData {
+            master_password_hash: data.master_password_hash.clone(),
+            otp: data.otp.clone(),
+        },
+    )
+    .await?;
+
+    let existing =
+        OrgTwoFactor::find_by_org_and_type(&db, &org_id, TwoFactorType::OrganizationDuo).await?;
+    let existing_config = existing
+        .as_ref()
+        .map(|tf| DuoConfig::from_data(&tf.data))
+        .transpose()?;
+    let config = duo_config_from_request(&data, existing_config.as_ref()).await?;
+    let payload = serde_json::to_string(&config).map_err(|_| AppError::Internal)?;
+
+    let mut provider = existing.unwrap_or_else(|| {
+        OrgTwoFactor::new(&org_id, TwoFactorType::OrganizationDuo, String::new())
+    });
+    provider.enabled = true;
+    provider.data = payload;
+    provider.save(&db).await?;
+
+    log::info!("Organization {} enabled Duo 2FA", org_id);
+
+    Ok(Json(duo_json(true, Some(&config))))
+}
+
+/// POST /api/organizations/{org_id}/two-factor/duo - Same as PUT
+#[worker::send]
+pub async fn activate_organization_duo_post(
+    claims: Claims,
+    state: State<Arc<Env>>,
+    path: Path<String>,
+    json: Json<EnableDuoData>,
+) -> Result<Json<Value>, AppError> {
+    activate_organization_duo(claims, state, path, json).await
+}
+
+/// PUT /api/organizations/{org_id}/two-factor/disable - Remove an organization 2FA provider
+#[worker::send]
+pub async fn disable_organization_twofactor(
+    claims: Claims,
+    State(env): State<Arc<Env>>,
+    Path(org_id): Path<String>,
+    Json(data): Json<DisableTwoFactorData>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    require_member_access(&db, &org_id, &claims.sub, MembershipType::Owner, &[]).await?;
+    let user = load_user(&db, &claims.sub).await?;
+    verify_user(
+        &db,
+        &user,
+        &PasswordOrOtpData {
+            master_password_hash: data.master_password_hash,
+            otp: data.otp,
+        },
+    )
+    .await?;
+
+    OrgTwoFactor::delete_by_org_and_type(&db, &org_id, data.r#type).await?;
+    log::info!("Organization {} disabled 2FA type {}", org_id, data.r#type);
+
+    Ok(Json(serde_json::json!({
+        "enabled": false,
+        "type": data.r#type,
+        "object": "twoFactorProvider"
+    })))
+}
+
+/// POST /api/organizations/{org_id}/two-factor/disable - Same as PUT
+#[worker::send]
+pub async fn disable_organization_twofactor_post(
+    claims: Claims,
+    state: State<Arc<Env>>,
+    path: Path<String>,
+    json: Json<DisableTwoFactorData>,
+) -> Result<Json<Value>, AppError> {
+    disable_organization_twofactor(claims, state, path, json).await
+}
+
+/// Validate Duo credentials from a settings form against Duo. The form sends the
+/// masked secret back when only the other fields changed.
+async fn duo_config_from_request(
+    data: &EnableDuoData,
+    existing: Option<&DuoConfig>,
+) -> Result<DuoConfig, AppError> {
+    let client_secret = match existing {
+        Some(config) if data.client_secret == config.masked_secret() => {
+            config.client_secret.as_str()
+        }
+        _ => data.client_secret.as_str(),
+    };
+    let config = DuoConfig::new(&data.client_id, client_secret, &data.host)?;
+    duo::health_check(&config).await?;
+    Ok(config)
+}
+
+fn duo_json(enabled: bool, config: Option<&DuoConfig>) -> Value {
+    let client_id = config.map(|c| c.client_id.clone());
+    let client_secret = config.map(DuoConfig::masked_secret);
+    serde_json::json!({
+        "enabled": enabled,
+        "host": config.map(|c| c.host.clone()),
+        "clientId": client_id,
+        "clientSecret": client_secret,
+        "integrationKey": client_id,
+        "secretKey": client_secret,
+        "object": "twoFactorDuo"
+    })
+}
+
+pub(crate) fn yubikey_metadata(tf: &TwoFactor) -> Result<YubikeyMetadata, AppError> {
+    serde_json::from_str(&tf.data).map_err(|_| AppError::Internal)
+}
+
+fn yubikey_json(enabled: bool, metadata: &YubikeyMetadata) -> Value {
+    let key = |i: usize| metadata.keys.get(i);
+    serde_json::json!({
+        "enabled": enabled,
+        "key1": key(0),
+        "key2": key(1),
+        "key3": key(2),
+        "key4": key(3),
+        "key5": key(4),
+        "nfc": metadata.nfc,
+        "object": "twoFactorU2f"
+    })
+}
+
+// Helper functions
+
+pub(crate) async fn find_twofactor(
+    db: &crate::db::Db,
+    user_id: &str,
+    atype: TwoFactorType,
+) -> Result<Option<TwoFactor>, AppError> {
+    db.prepare("SELECT * FROM twofactor WHERE user_uuid = ?1 AND atype = ?2")
+        .bind(&[user_id.into(), (atype as i32).into()])?
+        .first(None)
+        .await
+        .map_err(|_| AppError::Database)?
+        .map(|value: Value| serde_json::from_value(value).map_err(|_| AppError::Internal))
+        .transpose()
+}
+
+async fn insert_twofactor(db: &crate::db::Db, twofactor: &TwoFactor) -> Result<(), AppError> {
+    d1_query!(
+        db,
+        "INSERT INTO twofactor (uuid, user_uuid, atype, enabled, data, last_used) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        &twofactor.uuid,
+        &twofactor.user_uuid,
+        twofactor.atype,
+        twofactor.enabled as i32,
+        &twofactor.data,
+        twofactor.last_used
+    )
+    .map_err(|_| AppError::Database)?
+    .run()
+    .await
+    .map_err(|_| AppError::Database)?;
+    Ok(())
+}
+
+async fn update_twofactor_data(db: &crate::db::Db, uuid: &str, data: &str) -> Result<(), AppError> {
+    d1_query!(
+        db,
+        "UPDATE twofactor SET data = ?1 WHERE uuid = ?2",
+        data,
+        uuid
+    )
+    .map_err(|_| AppError::Database)?
+    .run()
+    .await
+    .map_err(|_| AppError::Database)?;
+    Ok(())
+}
+
+fn email_token_data(tf: &TwoFactor) -> Result<EmailTokenData, AppError> {
+    serde_json::from_str(&tf.data).map_err(|_| AppError::Internal)
+}
+
+fn email_json(enabled: bool, email: &str) -> Value {
+    serde_json::json!({
+        "email": email,
+        "enabled": enabled,
+        "object": "twoFactorEmail"
+    })
+}
+
+fn ensure_can_resend(data: &EmailTokenData) -> Result<(), AppError> {
+    if Utc::now().timestamp() - data.token_sent_at < EMAIL_TOKEN_RESEND_SECS {
+        return Err(AppError::TooManyRequests(
+            "Please wait a minute before requesting another code.".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Generate a new code, email it, and record its hash in `data`.
+async fn issue_email_token(env: &Env, data: &mut EmailTokenData) -> Result<(), AppError> {
+    let token = generate_email_token()?;
+    mail::send(
+        env,
+        &data.email,
+        mail::Template::TwoFactorCode {
+            token: &token,
+            ttl_minutes: EMAIL_TOKEN_TTL_SECS / 60,
+        },
+    )
+    .await?;
+
+    data.token_hash = Some(sha256_hex(&token));
+    data.token_sent_at = Utc::now().timestamp();
+    data.attempts = 0;
+    Ok(())
+}
+
+/// Check `token` against `data`, updating the attempt counter. The code is
+/// cleared when it is used, expires, or has been guessed wrong too often.
+pub(crate) fn verify_email_token(data: &mut EmailTokenData, token: &str) -> Result<(), AppError> {
+    let Some(expected) = data.token_hash.clone() else {
+        return Err(AppError::BadRequest(
+            "No verification code was sent".to_string(),
+        ));
+    };
+
+    if Utc::now().timestamp() - data.token_sent_at > EMAIL_TOKEN_TTL_SECS {
+        data.token_hash = None;
+        return Err(AppError::BadRequest(
+            "Verification code has expired".to_string(),
+        ));
+    }
+
+    if !ct_eq(&expected, &sha256_hex(token.trim())) {
+        data.attempts += 1;
+        if data.attempts >= EMAIL_TOKEN_MAX_ATTEMPTS {
+            data.token_hash = None;
+        }
+        return Err(AppError::BadRequest(
+            "Verification code is incorrect".to_string(),
+        ));
+    }
+
+    data.token_hash = None;
+    data.attempts = 0;
+    Ok(())
+}
+
+/// Mask the local part of an address, e.g. `j*******@example.com`.
+fn obscure_email(email: &str) -> String {
+    let (name, domain) = email.split_once('@').unwrap_or((email, ""));
+    let masked = match name.chars().count() {
+        0..=2 => "*".repeat(name.chars().count()),
+        n => format!(
+            "{}{}",
+            name.chars().next().unwrap_or('*'),
+            "*".repeat(n - 1)
+        ),
+    };
+    format!("{masked}@{domain}")
+}
+
+pub(crate) async fn load_user(db: &crate::db::Db, user_id: &str) -> Result<User, AppError> {
+    let user_value: Value = db
+        .prepare("SELECT * FROM users WHERE id = ?1")
+        .bind(&[user_id.into()])?
+        .first(None)
+        .await
+        .map_err(|_| AppError::Database)?
+        .ok_or_else(|| AppError::Unauthorized("User not found".to_string()))?;
+    serde_json::from_value(user_value).map_err(|_| AppError::Internal)
+}
+
+/// Load the WebAuthn record of a user and its registered security keys.
+async fn load_webauthn(
+    db: &crate::db::Db,
+    user_id: &str,
+) -> Result<(Option<TwoFactor>, Vec<WebauthnCredential>), AppError> {
+    let existing = find_twofactor(db, user_id, TwoFactorType::Webauthn).await?;
+    let credentials = match existing {
+        Some(ref tf) => WebauthnCredential::list_from_data(&tf.data)?,
+        None => Vec::new(),
+    };
+    Ok((existing, credentials))
+}
+
+async fn save_webauthn(
+    db: &crate::db::Db,
+    user_id: &str,
+    existing: Option<TwoFactor>,
+    credentials: &[WebauthnCredential],
+) -> Result<(), AppError> {
+    let data = serde_json::to_string(credentials).map_err(|_| AppError::Internal)?;
+    match existing {
+        Some(tf) => update_twofactor_data(db, &tf.uuid, &data).await,
+        None => {
+            insert_twofactor(
+                db,
+                &TwoFactor::new(user_id.to_string(), TwoFactorType::Webauthn, data),
+            )
+            .await
+        }
+    }
+}
+
+fn webauthn_keys_json(enabled: bool, credentials: &[WebauthnCredential]) -> Value {
+    serde_json::json!({
+        "enabled": enabled,
+        "keys": credentials.iter().map(WebauthnCredential::to_key_json).collect::<Vec<_>>(),
+        "object": "twoFactorWebAuthn"
+    })
+}
+
+async fn generate_recovery_code_for_user(
+    db: &crate::db::Db,
+    user_id: &str,
+) -> Result<(), AppError> {
+    // Check if recovery code already exists
+    let user_value: Value = db
+        .prepare("SELECT totp_recover FROM users WHERE id = ?1")
+        .bind(&[user_id.into()])?
+        .first(None)
+        .await
+        .map_err(|_| AppError::Database)?
+        .ok_or_else(|| AppError::Unauthorized("User not found".to_string()))?;
+
+    let totp_recover: Option<String> = user_value
+        .get("totp_recover")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string());
+
+    if totp_recover.is_none() {
+        store_new_recovery_code(db, user_id).await?;
+    }
+
+    Ok(())
+}
+
+/// Replace the user's recovery code, storing its hash and returning the new code.
+async fn store_new_recovery_code(db: &crate::db::Db, user_id: &str) -> Result<String, AppError> {
+    let recovery_code = generate_recovery_code()?;
+    d1_query!(
+        db,
+        "UPDATE users SET totp_recover = ?1 WHERE id = ?2",
+        hash_recovery_code(&recovery_code),
+        user_id
+    )
+    .map_err(|_| AppError::Database)?
+    .run()
+    .await
+    .map_err(|_| AppError::Database)?;
+    Ok(recovery_code)
+}
+
+/// Whether `code` is the user's recovery code. Codes issued before they were
+/// hashed are still stored as entered.
+pub(crate) fn recovery_code_matches(user: &User, code: &str) -> bool {
+    match user.totp_recover.as_deref() {
+        Some(stored) if stored.len() == 64 => ct_eq(stored, &hash_recovery_code(code)),
+        Some(stored) => ct_eq(&stored.to_uppercase(), &code.trim().to_uppercase()),
+        None => false,
+    }
+}
+
+/// Turn off every 2FA method of a user after a recovery code was used: the
+/// providers, the recovery code and the remembered devices are removed, the other
+/// sessions end, and the user is told by email. Returns the new security stamp.
+pub(crate) async fn reset_twofactor(
+    env: &Env,
+    db: &crate::db::Db,
+    user: &User,
+) -> Result<String, AppError> {
+    let user_id = user.id.as_str();
+    db.batch(vec![
+        d1_query!(db, "DELETE FROM twofactor WHERE user_uuid = ?1", user_id)
+            .map_err(|_| AppError::Database)?,
+        d1_query!(
+            db,
+            "UPDATE users SET totp_recover = NULL WHERE id = ?1",
+            user_id
+        )
+        .map_err(|_| AppError::Database)?,
+        d1_query!(
+            db,
+            "UPDATE devices SET twofactor_remember = NULL WHERE user_id = ?1",
+            user_id
+        )
+        .map_err(|_| AppError::Database)?,
+    ])
+    .await
+    .map_err(|_| AppError::Database)?;
+    crate::handlers::policies::enforce_two_factor_policy(env, db, user_id).await?;
+
+    let now = db::now_string();
+    let security_stamp = User::rotate_security_stamp(db, user_id, &now).await?;
+    notifications::publish_user_logout(env.clone(), user_id.to_string(), now, None);
+    mail::send_in_background(
+        env.clone(),
+        user.email.clone(),
+        mail::Template::TwoFactorRemoved { provider: None },
+    );
+    Ok(security_stamp)
+}
+
+/// After the 2FA method `removed` was removed, end every session so tokens taken
+/// before the change stop working, and tell the user by email. When no real 2FA
+/// providers remain, also clear the recovery code and enforce the organizations'
+/// two-step login policies.
+///
+/// The session that removed the method is logged out too, on purpose. Its token
+/// carries the old stamp, and these endpoints answer with the provider state rather
+/// than a new token. The logout notification therefore goes to every device,
+/// including the caller's, so that client drops the dead session right away instead
+/// of failing on its next request.
+///
+/// Enabling a method keeps the sessions: the client that enabled it still needs to
+/// fetch the recovery code.
+async fn on_twofactor_removed(
+    env: &Env,
+    db: &crate::db::Db,
+    user: &User,
+    removed: TwoFactorType,
+) -> Result<(), AppError> {
+    let user_id = user.id.as_str();
+    let now = db::now_string();
+    User::rotate_security_stamp(db, user_id, &now).await?;
+    // No device is skipped: the caller's token is invalid now as well.
+    notifications::publish_user_logout(env.clone(), user_id.to_string(), now, None);
+    mail::send_in_background(
+        env.clone(),
+        user.email.clone(),
+        mail::Template::TwoFactorRemoved {
+            provider: removed.provider_name(),
+        },
+    );
+
     let remaining: Vec<TwoFactor> = db
         .prepare("SELECT * FROM twofactor WHERE user_uuid = ?1 AND atype < 1000 AND atype != ?2")
         .bind(&[
@@ -453,6 +1544,7 @@ async fn clear_recovery_if_no_twofactor(db: &crate::db::Db, user_id: &str) -> Re
         .run()
         .await
         .map_err(|_| AppError::Database)?;
+        crate::handlers::policies::enforce_two_factor_policy(env, db, user_id).await?;
     }
 
     Ok(())
@@ -1,16 +1,214 @@
-use axum::Json;
+//! Passkeys for passwordless login, managed from the web vault's account settings.
+//!
+//! Registering and removing passkeys requires the master password. A passkey whose
+//! authenticator supports the PRF extension can also carry a key pair created by the
+//! client, which unlocks the vault after a passkey login; see [`Passkey`].
+
+use axum::{
+    extract::{Path, State},
+    Extension, Json,
+};
 use serde_json::{json, Value};
+use std::sync::Arc;
+use worker::Env;
+
+use crate::{
+    auth::AuthUser,
+    db,
+    error::AppError,
+    handlers::{accounts::verify_user, twofactor::load_user},
+    models::passkey::{CreatePasskeyRequest, Passkey, UpdatePasskeyRequest, MAX_PASSKEYS},
+    models::user::PasswordOrOtpData,
+    webauthn::{self, RelyingParty},
+    BaseUrl,
+};
 
-/// GET /webauthn
-///
-/// Returns an empty list of WebAuthn credentials.
-/// This prevents 404 errors and key-rotation issues when passkey login is enabled.
-/// Vaultwarden does not yet support passkey login, so we return an empty list.
+/// GET /api/webauthn - List the user's passkeys
 #[worker::send]
-pub async fn get_webauthn_credentials() -> Json<Value> {
-    Json(json!({
+pub async fn get_webauthn_credentials(
+    State(env): State<Arc<Env>>,
+    AuthUser(user_id, _): AuthUser,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let passkeys = Passkey::find_by_user(&db, &user_id).await?;
+    Ok(Json(json!({
         "object": "list",
-        "data": [],
+        "data": passkeys.iter().map(Passkey::to_json).collect::<Vec<_>>(),
         "continuationToken": null
-    }))
+    })))
+}
+
+/// POST /api/webauthn/attestation-options - Start registering a passkey
+#[worker::send]
+pub async fn post_attestation_options(
+    State(env): State<Arc<Env>>,
+    Extension(BaseUrl(base_url)): Extension<BaseUrl>,
+    AuthUser(user_id, _): AuthUser,
+    Json(data): Json<PasswordOrOtpData>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let user = load_user(&db, &user_id).await?;
+    verify_user(&db, &user, &data).await?;
+
+    let passkeys = Passkey::find_by_user(&db, &user_id).await?;
+    if passkeys.len() >= MAX_PASSKEYS {
+        return Err(AppError::BadRequest(
+            "Maximum number of passkeys reached".to_string(),
+        ));
+    }
+    let existing: Vec<&str> = passkeys.iter().map(|p| p.credential_id.as_str()).collect();
+    let (options, token) = webauthn::start_passkey_registration(
+        &env,
+        &RelyingParty::from_base_url(&base_url),
+        &user.id,
+        &user.email,
+        user.name.as_deref(),
+        &existing,
+    )
+    .await?;
+
+    Ok(Json(json!({
+        "options": options,
+        "token": token,
+        "object": "webauthnCredentialCreateOptions",
+    })))
+}
+
+/// POST /api/webauthn - Finish registering a passkey
+#[worker::send]
+pub async fn post_webauthn_credential(
+    State(env): State<Arc<Env>>,
+    Extension(BaseUrl(base_url)): Extension<BaseUrl>,
+    AuthUser(user_id, _): AuthUser,
+    Json(data): Json<CreatePasskeyRequest>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let name = data.name.trim().to_string();
+    if name.is_empty() {
+        return Err(AppError::BadRequest("Passkey name is required".to_string()));
+    }
+    if Passkey::find_by_user(&db, &user_id).await?.len() >= MAX_PASSKEYS {
+        return Err(AppError::BadRequest(
+            "Maximum number of passkeys reached".to_string(),
+        ));
+    }
+
+    let key = webauthn::finish_passkey_registration(
+        &env,
+        &RelyingParty::from_base_url(&base_url),
+        &user_id,
+        &data.token,
+        &data.device_response,
+    )
+    .await?;
+    if Passkey::find_by_credential_id(&db, &key.credential_id)
+        .await?
+        .is_some()
+    {
+        return Err(AppError::BadRequest(
+            "This passkey is already registered".to_string(),
+        ));
+    }
+
+    let passkey = Passkey::new(&user_id, name, key, data.supports_prf, data.keys)?;
+    passkey.insert(&db).await?;
+    log::info!("User {} registered passkey {}", user_id, passkey.id);
+
+    Ok(Json(passkey.to_json()))
+}
+
+/// POST /api/webauthn/assertion-options - Start proving possession of a passkey,
+/// before its PRF keys are stored
+#[worker::send]
+pub async fn post_assertion_options(
+    State(env): State<Arc<Env>>,
+    Extension(BaseUrl(base_url)): Extension<BaseUrl>,
+    AuthUser(user_id, _): AuthUser,
+    Json(data): Json<PasswordOrOtpData>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let user = load_user(&db, &user_id).await?;
+    verify_user(&db, &user, &data).await?;
+
+    let passkeys = Passkey::find_by_user(&db, &user_id).await?;
+    let allowed: Vec<&str> = passkeys.iter().map(|p| p.credential_id.as_str()).collect();
+    let (options, token) = webauthn::start_passkey_assertion(
+        &env,
+        &RelyingParty::from_base_url(&base_url),
+        Some(&user_id),
+        &allowed,
+    )
+    .await?;
+
+    Ok(Json(login_assertion_options_json(options, token)))
+}
+
+/// PUT /api/webauthn - Store the PRF keys of a passkey, proven by an assertion
+#[worker::send]
+pub async fn put_webauthn_credential(
+    State(env): State<Arc<Env>>,
+    Extension(BaseUrl(base_url)): Extension<BaseUrl>,
+    AuthUser(user_id, _): AuthUser,
+    Json(data): Json<UpdatePasskeyRequest>,
+) -> Result<Json<Value>, AppError> {
+    let db = db::get_db(&env)?;
+    let credential_id = webauthn::assertion_credential_id(&data.device_response)?;
+    let mut passkey = Passkey::find_by_credential_id(&db, &credential_id)
+        .await?
+        .filter(|p| p.user_id == user_id)
+        .ok_or_else(|| AppError::NotFound("Passkey not found".to_string()))?;
+
+    let counter = webauthn::finish_passkey_assertion(
+        &env,
+        &RelyingParty::from_base_url(&base_url),
+        Some(&user_id),
+        &data.token,
+        &passkey.key(),
+        &data.device_response,
+    )
+    .await?;
+    passkey.set_counter(&db, counter).await?;
+    passkey.set_prf_keys(&db, data.keys).await?;
+
+    Ok(Json(passkey.to_json()))
+}
+
+/// POST /api/webauthn/{id}/delete - Remove a passkey
+#[worker::send]
+pub async fn delete_webauthn_credential(
+    State(env): State<Arc<Env>>,
+    AuthUser(user_id, _): AuthUser,
+    Path(id): Path<String>,
+    Json(data): Json<PasswordOrOtpData>,
+) -> Result<Json<()>, AppError> {
+    let db = db::get_db(&env)?;
+    let user = load_user(&db, &user_id).await?;
+    verify_user(&db, &user, &data).await?;
+
+    if !Passkey::delete_by_id_and_user(&db, &id, &user_id).await? {
+        return Err(AppError::NotFound("Passkey not found".to_string()));
+    }
+    log::info!("User {} removed passkey {}", user_id, id);
+
+    Ok(Json(()))
+}
+
+/// GET /identity/accounts/webauthn/assertion-options - Start a passkey login
+#[worker::send]
+pub async fn get_login_assertion_options(
+    State(env): State<Arc<Env>>,
+    Extension(BaseUrl(base_url)): Extension<BaseUrl>,
+) -> Result<Json<Value>, AppError> {
+    let (options, token) =
+        webauthn::start_passkey_assertion(&env, &RelyingParty::from_base_url(&base_url), None, &[])
+            .await?;
+    Ok(Json(login_assertion_options_json(options, token)))
+}
+
+fn login_assertion_options_json(options: Value, token: String) -> Value {
+    json!({
+        "options": options,
+        "token": token,
+        "object": "webAuthnLoginAssertionOptions",
+    })
 }
@@ -0,0 +1,88 @@
+//! Locks that keep overlapping cron invocations (several triggers, or an old and a new
+//! deployment) from running the same job at once.
+//!
+//! Before running, a job claims its row in the `job_locks` D1 table with a single
+//! conditional upsert, which only writes when there is no row or the row has expired.
+//! D1 runs statements one at a time against the primary, so of two invocations racing
+//! for the same job exactly one sees its write take effect; the other skips the job.
+//! The row is deleted when the job is done. A lock left behind by an invocation that
+//! died is taken over once it has expired, after `JOB_LOCK_TTL_SECONDS` (default 15
+//! minutes, the longest a cron invocation may run; a lower value lets a slow run
+//! overlap with the next one).
+
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+use worker::Env;
+
+use crate::d1_query;
+use crate::db;
+use crate::error::AppError;
+use crate::handlers::get_env_usize;
+
+const DEFAULT_LOCK_TTL_SECS: usize = 900;
+
+/// A held lock, released with [`JobLock::release`].
+pub(super) struct JobLock {
+    name: String,
+    owner: String,
+}
+
+/// Take the lock of job `name`. Returns `None` while another invocation holds it.
+pub(super) async fn acquire(env: &Env, name: &str) -> Result<Option<JobLock>, AppError> {
+    let db = db::get_db(env)?;
+    let ttl = get_env_usize(env, "JOB_LOCK_TTL_SECONDS", DEFAULT_LOCK_TTL_SECS) as i64;
+    let now = Utc::now();
+    let owner = Uuid::new_v4().to_string();
+
+    let result = d1_query!(
+        &db,
+        "INSERT INTO job_locks (name, owner, acquired_at, expires_at) VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(name) DO UPDATE SET
+           owner = excluded.owner,
+           acquired_at = excluded.acquired_at,
+           expires_at = excluded.expires_at
+         WHERE job_locks.expires_at <= excluded.acquired_at",
+        name,
+        &owner,
+        db::format_time(now),
+        db::format_time(now + Duration::seconds(ttl))
+    )
+    .map_err(|_| AppError::Database)?
+    .run()
+    .await
+    .map_err(|_| AppError::Database)?;
+    let changes = result
+        .meta()
+        .map_err(|_| AppError::Database)?
+        .and_then(|meta| meta.changes)
+        .unwrap_or(0);
+
+    Ok((changes > 0).then(|| JobLock {
+        name: name.to_string(),
+        owner,
+    }))
+}
+
+impl JobLock {
+    /// Delete the lock, unless it expired and was taken over in the meantime.
+    pub(super) async fn release(self, env: &Env) {
+        if let Err(e) = self.delete(env).await {
+            log::warn!("Job {}: lock release failed: {e}", self.name);
+        }
+    }
+
+    async fn delete(&self, env: &Env) -> Result<(), AppError> {
+        let db = db::get_db(env)?;
+        d1_query!(
+            &db,
+            "DELETE FROM job_locks WHERE name = ?1 AND owner = ?2",
+            &self.name,
+            &self.owner
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,215 @@
+//! Scheduled (cron-triggered) job framework.
+//!
+//! The Worker's `scheduled` event dispatches into [`run_scheduled`], which runs
+//! every registered [`Job`] in order. Each job:
+//! - can be disabled with `JOB_<NAME>_ENABLED=false` (enabled by default),
+//! - records its last run (start/finish time, status, error, affected count) in
+//!   the `job_runs` D1 table,
+//! - is isolated from the others: a failing job is logged and recorded, and the
+//!   remaining jobs still run,
+//! - is skipped while an overlapping invocation runs it (see [`lock`]).
+
+mod lock;
+mod runs;
+
+pub(crate) use runs::{load_cursor, save_cursor, JobRun};
+
+use serde::Serialize;
+use serde_json::json;
+use worker::Env;
+
+use crate::backup;
+use crate::db;
+use crate::error::AppError;
+use crate::handlers::{admin, emergency_access, organizations, purge};
+use crate::logging;
+
+/// All periodic jobs known to the scheduler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Job {
+    StalePendingAttachments,
+    DeletedCiphers,
+    StalePendingSends,
+    StaleMultipartUploads,
+    ExpiredSends,
+    ExpiredAuthRequests,
+    ExpiredRefreshTokens,
+    DeletedAccounts,
+    ExpiredEvents,
+    SyncTombstones,
+    EmergencyAccessTimeouts,
+    EmergencyAccessReminders,
+    ExpiredOrgInvites,
+    InactiveAccounts,
+    DatabaseBackup,
+}
+
+impl Job {
+    /// Jobs in the order they are executed on every cron tick.
+    pub const ALL: &'static [Job] = &[
+        Job::StalePendingAttachments,
+        Job::DeletedCiphers,
+        Job::StalePendingSends,
+        Job::StaleMultipartUploads,
+        Job::ExpiredSends,
+        Job::ExpiredAuthRequests,
+        Job::ExpiredRefreshTokens,
+        Job::DeletedAccounts,
+        Job::ExpiredEvents,
+        Job::SyncTombstones,
+        Job::EmergencyAccessTimeouts,
+        Job::EmergencyAccessReminders,
+        Job::ExpiredOrgInvites,
+        Job::InactiveAccounts,
+        Job::DatabaseBackup,
+    ];
+
+    /// Stable identifier, used as the `job_runs` primary key and in the env toggle.
+    pub fn name(self) -> &'static str {
+        match self {
+            Job::StalePendingAttachments => "stale_pending_attachments",
+            Job::DeletedCiphers => "deleted_ciphers",
+            Job::StalePendingSends => "stale_pending_sends",
+            Job::StaleMultipartUploads => "stale_multipart_uploads",
+            Job::ExpiredSends => "expired_sends",
+            Job::ExpiredAuthRequests => "expired_auth_requests",
+            Job::ExpiredRefreshTokens => "expired_refresh_tokens",
+            Job::DeletedAccounts => "deleted_accounts",
+            Job::ExpiredEvents => "expired_events",
+            Job::SyncTombstones => "sync_tombstones",
+            Job::EmergencyAccessTimeouts => "emergency_access_timeouts",
+            Job::EmergencyAccessReminders => "emergency_access_reminders",
+            Job::ExpiredOrgInvites => "expired_org_invites",
+            Job::InactiveAccounts => "inactive_accounts",
+            Job::DatabaseBackup => "database_backup",
+        }
+    }
+
+    /// Name of the env var that toggles this job (e.g. `JOB_EXPIRED_SENDS_ENABLED`).
+    fn enable_var(self) -> String {
+        format!("JOB_{}_ENABLED", self.name().to_ascii_uppercase())
+    }
+
+    /// Whether the job is enabled. Jobs run unless explicitly switched off.
+    pub fn is_enabled(self, env: &Env) -> bool {
+        env.var(&self.enable_var())
+            .ok()
+            .map(|value| value.to_string().to_lowercase())
+            .map(|value| !matches!(value.as_str(), "0" | "false" | "no" | "off"))
+            .unwrap_or(true)
+    }
+
+    /// Run the job, returning the number of affected records.
+    async fn execute(self, env: &Env) -> Result<u32, worker::Error> {
+        match self {
+            Job::StalePendingAttachments => purge::purge_stale_pending_attachments(env).await,
+            Job::DeletedCiphers => purge::purge_deleted_ciphers(env)
+                .await
+                .map(|report| report.total()),
+            Job::StalePendingSends => purge::purge_stale_pending_sends(env).await,
+            Job::StaleMultipartUploads => purge::purge_stale_multipart_uploads(env).await,
+            Job::ExpiredSends => purge::purge_expired_sends(env)
+                .await
+                .map(|report| report.total()),
+            Job::ExpiredAuthRequests => purge::purge_expired_auth_requests(env).await,
+            Job::ExpiredRefreshTokens => purge::purge_expired_refresh_tokens(env).await,
+            Job::DeletedAccounts => purge::purge_deleted_accounts(env).await,
+            Job::ExpiredEvents => purge::purge_expired_events(env).await,
+            Job::SyncTombstones => purge::purge_sync_tombstones(env).await,
+            Job::EmergencyAccessTimeouts => emergency_access::approve_elapsed_recoveries(env).await,
+            Job::EmergencyAccessReminders => emergency_access::remind_pending_recoveries(env).await,
+            Job::ExpiredOrgInvites => organizations::expire_stale_invites(env).await,
+            Job::InactiveAccounts => admin::disable_inactive_accounts(env).await,
+            Job::DatabaseBackup => backup::run_scheduled_backup(env).await,
+        }
+    }
+}
+
+/// A job as reported by the admin diagnostics.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobStatus {
+    pub name: &'static str,
+    pub enabled: bool,
+    pub last_run: Option<JobRun>,
+}
+
+/// Every job in execution order, with its last recorded run.
+pub async fn status(env: &Env) -> Result<Vec<JobStatus>, AppError> {
+    let mut runs = runs::list_runs(&db::get_db(env)?).await?;
+    Ok(Job::ALL
+        .iter()
+        .map(|&job| JobStatus {
+            name: job.name(),
+            enabled: job.is_enabled(env),
+            last_run: runs
+                .iter()
+                .position(|run| run.name == job.name())
+                .map(|index| runs.swap_remove(index)),
+        })
+        .collect())
+}
+
+/// Run every enabled job once, isolating failures between jobs.
+pub async fn run_scheduled(env: &Env) {
+    for &job in Job::ALL {
+        run_job(env, job).await;
+    }
+}
+
+async fn run_job(env: &Env, job: Job) {
+    let name = job.name();
+
+    if !job.is_enabled(env) {
+        logging::event(
+            log::Level::Info,
+            "job",
+            json!({ "job": name, "outcome": "skipped", "enableVar": job.enable_var() }),
+        );
+        return;
+    }
+
+    let lock = match lock::acquire(env, name).await {
+        Ok(Some(lock)) => lock,
+        Ok(None) => {
+            logging::event(
+                log::Level::Info,
+                "job",
+                json!({ "job": name, "outcome": "locked" }),
+            );
+            return;
+        }
+        // Without the lock the job cannot be sure it runs alone.
+        Err(e) => {
+            logging::event(
+                log::Level::Error,
+                "job",
+                json!({ "job": name, "outcome": "error", "error": format!("lock: {e}") }),
+            );
+            return;
+        }
+    };
+
+    let started_at = crate::db::now_string();
+    let started = js_sys::Date::now();
+    let result = job.execute(env).await;
+    let duration_ms = (js_sys::Date::now() - started).max(0.0).round() as u64;
+
+    match &result {
+        Ok(count) => logging::event(
+            log::Level::Info,
+            "job",
+            json!({ "job": name, "outcome": "ok", "affected": count, "durationMs": duration_ms }),
+        ),
+        Err(e) => logging::event(
+            log::Level::Error,
+            "job",
+            json!({ "job": name, "outcome": "error", "error": format!("{e:?}"), "durationMs": duration_ms }),
+        ),
+    }
+
+    if let Err(e) = runs::record_run(env, name, &started_at, &result).await {
+        log::warn!("Job {name}: failed to record last run: {e}");
+    }
+    lock.release(env).await;
+}
@@ -0,0 +1,120 @@
+//! Last-run bookkeeping for scheduled jobs (`job_runs` table) and continuation
+//! markers of jobs that work in several runs (`job_cursors` table).
+
+use serde::{Deserialize, Serialize};
+use worker::Env;
+
+use crate::d1_query;
+use crate::db;
+use crate::error::AppError;
+
+/// Maximum length of the stored error message, to keep rows small.
+const MAX_ERROR_LEN: usize = 1024;
+
+/// Upsert the outcome of a job run into `job_runs`.
+pub(super) async fn record_run(
+    env: &Env,
+    name: &str,
+    started_at: &str,
+    result: &Result<u32, worker::Error>,
+) -> Result<(), AppError> {
+    let db = db::get_db(env)?;
+    let finished_at = db::now_string();
+
+    let (status, error, count) = match result {
+        Ok(count) => ("ok", None, *count),
+        Err(e) => {
+            let mut message = e.to_string();
+            if message.len() > MAX_ERROR_LEN {
+                let mut end = MAX_ERROR_LEN;
+                while !message.is_char_boundary(end) {
+                    end -= 1;
+                }
+                message.truncate(end);
+            }
+            ("error", Some(message), 0)
+        }
+    };
+
+    d1_query!(
+        &db,
+        "INSERT INTO job_runs (name, last_started_at, last_finished_at, last_status, last_error, last_count, run_count)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, 1)
+         ON CONFLICT(name) DO UPDATE SET
+           last_started_at = excluded.last_started_at,
+           last_finished_at = excluded.last_finished_at,
+           last_status = excluded.last_status,
+           last_error = excluded.last_error,
+           last_count = excluded.last_count,
+           run_count = job_runs.run_count + 1",
+        name,
+        started_at,
+        finished_at,
+        status,
+        error,
+        count
+    )
+    .map_err(|_| AppError::Database)?
+    .run()
+    .await?;
+
+    Ok(())
+}
+
+/// Last recorded run of a job.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobRun {
+    pub name: String,
+    #[serde(alias = "last_started_at")]
+    pub last_started_at: String,
+    #[serde(alias = "last_finished_at")]
+    pub last_finished_at: Option<String>,
+    #[serde(alias = "last_status")]
+    pub last_status: String,
+    #[serde(alias = "last_error")]
+    pub last_error: Option<String>,
+    #[serde(alias = "last_count")]
+    pub last_count: i64,
+    #[serde(alias = "run_count")]
+    pub run_count: i64,
+}
+
+pub(super) async fn list_runs(db: &db::Db) -> Result<Vec<JobRun>, AppError> {
+    db.prepare("SELECT * FROM job_runs")
+        .all()
+        .await
+        .map_err(|_| AppError::Database)?
+        .results()
+        .map_err(|_| AppError::Database)
+}
+
+/// Continuation marker saved by the last run of job `name`, if it stopped early.
+pub(crate) async fn load_cursor(db: &db::Db, name: &str) -> Result<Option<String>, AppError> {
+    d1_query!(db, "SELECT cursor FROM job_cursors WHERE name = ?1", name)
+        .map_err(|_| AppError::Database)?
+        .first(Some("cursor"))
+        .await
+        .map_err(|_| AppError::Database)
+}
+
+/// Save the continuation marker of job `name`, or clear it once the work is done.
+pub(crate) async fn save_cursor(
+    db: &db::Db,
+    name: &str,
+    cursor: Option<&str>,
+) -> Result<(), AppError> {
+    let query = match cursor {
+        Some(cursor) => d1_query!(
+            db,
+            "INSERT INTO job_cursors (name, cursor, updated_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT(name) DO UPDATE SET cursor = excluded.cursor, updated_at = excluded.updated_at",
+            name,
+            cursor,
+            db::now_string()
+        ),
+        None => d1_query!(db, "DELETE FROM job_cursors WHERE name = ?1", name),
+    };
+    query.map_err(|_| AppError::Database)?.run().await?;
+    Ok(())
+}
@@ -1,22 +1,39 @@
 use std::sync::Arc;
 
 use axum::{extract::DefaultBodyLimit, Extension};
-use tower_http::cors::{Any, CorsLayer};
 use tower_service::Service;
 use worker::*;
 
 mod auth;
 mod background;
+mod backup;
 mod client_context;
+mod compression;
+mod cors;
 mod crypto;
 mod db;
+mod diagnostics;
+mod dns;
+mod duo;
 mod durable;
 mod error;
 mod handlers;
+mod jobs;
+mod logging;
+mod mail;
+mod metrics;
+mod migrations;
 mod models;
 mod notifications;
+mod prelogin_cache;
 mod push;
+mod rate_limit;
+mod request_limits;
 mod router;
+mod sso;
+mod turnstile;
+mod webauthn;
+mod yubikey;
 
 /// Base URL extracted from the incoming request, used for config endpoint.
 #[derive(Clone)]
@@ -25,16 +42,30 @@ pub struct BaseUrl(pub String);
 #[event(fetch)]
 pub async fn main(req: Request, env: Env, _ctx: Context) -> Result<web_sys::Response> {
     console_error_panic_hook::set_once();
-    let _ = console_log::init_with_level(log::Level::Debug);
+    logging::init(&env);
+    metrics::init(&env);
+    migrations::ensure_applied(&env).await;
 
     let url = req.url()?;
     let method = req.method();
     let path = url.path().to_string();
 
     if handlers::streaming::is_streaming_route(&method, &path) {
-        return Ok(handlers::streaming::handle(req, &env, &method, &path, &url)
-            .await
-            .into());
+        let started = js_sys::Date::now();
+        let request_id = logging::request_id(req.headers().get("cf-ray").ok().flatten().as_deref());
+        let origin = req.headers().get("origin").ok().flatten();
+        let mut resp = handlers::streaming::handle(req, &env, &method, &path, &url).await;
+        cors::apply(&env, origin.as_deref(), &mut resp);
+        logging::request_finished(
+            &env,
+            &request_id,
+            method.as_ref(),
+            &path,
+            None,
+            resp.status_code(),
+            started,
+        );
+        return Ok(resp.into());
     }
 
     let http_req: HttpRequest = req.try_into()?;
@@ -54,17 +85,26 @@ pub async fn main(req: Request, env: Env, _ctx: Context) -> Result<web_sys::Resp
 
     let env = Arc::new(env);
 
-    let cors = CorsLayer::new()
-        .allow_methods(Any)
-        .allow_headers(Any)
-        .allow_origin(Any);
+    let cors = cors::layer(&env);
 
-    const BODY_LIMIT: usize = 5 * 1024 * 1024;
+    let body_limit = request_limits::max_body_bytes(&env);
 
     let mut app = router::api_router((*env).clone())
         .layer(Extension(BaseUrl(base_url)))
+        .layer(axum::middleware::from_fn_with_state(
+            env.clone(),
+            request_limits::limit_request_bodies,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            env.clone(),
+            compression::compress_responses,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            env.clone(),
+            logging::log_requests,
+        ))
         .layer(cors)
-        .layer(DefaultBodyLimit::max(BODY_LIMIT));
+        .layer(DefaultBodyLimit::max(body_limit));
 
     let resp = app.call(http_req).await?;
     worker::response_to_wasm(resp)
@@ -72,39 +112,15 @@ pub async fn main(req: Request, env: Env, _ctx: Context) -> Result<web_sys::Resp
 
 /// Scheduled event handler for cron-triggered tasks.
 ///
-/// This handler is triggered by Cloudflare's cron triggers configured in wrangler.toml.
-/// It performs automatic cleanup of soft-deleted ciphers that have exceeded the
-/// retention period (default: 30 days, configurable via TRASH_AUTO_DELETE_DAYS env var).
+/// This handler is triggered by Cloudflare's cron triggers configured in wrangler.toml
+/// and dispatches into the job framework in [`jobs`], which runs every enabled job
+/// (trash purge, stale uploads, expired sends, ...) with per-job error isolation.
 #[event(scheduled)]
 pub async fn scheduled(_event: ScheduledEvent, env: Env, _ctx: ScheduleContext) {
     console_error_panic_hook::set_once();
-    let _ = console_log::init_with_level(log::Level::Debug);
+    logging::init(&env);
+    metrics::init(&env);
+    migrations::ensure_applied(&env).await;
 
-    fn log_purge_result(name: &str, result: Result<u32, worker::Error>) {
-        match result {
-            Ok(count) => log::info!("Purge {name} completed: {count} record(s) removed"),
-            Err(e) => log::error!("Purge {name} failed: {e:?}"),
-        }
-    }
-
-    log_purge_result(
-        "stale pending attachments",
-        handlers::purge::purge_stale_pending_attachments(&env).await,
-    );
-    log_purge_result(
-        "soft-deleted ciphers",
-        handlers::purge::purge_deleted_ciphers(&env).await,
-    );
-    log_purge_result(
-        "stale pending sends",
-        handlers::purge::purge_stale_pending_sends(&env).await,
-    );
-    log_purge_result(
-        "expired sends",
-        handlers::purge::purge_expired_sends(&env).await,
-    );
-    log_purge_result(
-        "expired auth requests",
-        handlers::purge::purge_expired_auth_requests(&env).await,
-    );
+    jobs::run_scheduled(&env).await;
 }
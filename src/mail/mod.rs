@@ -0,0 +1,89 @@
+//! Outgoing email.
+//!
+//! Messages are rendered from a [`Template`] and handed to the configured
+//! [`provider`]: Resend, MailChannels, a generic HTTP relay, or a no-op backend that
+//! only logs. Mail is enabled when `MAIL_FROM` is set and the provider has its
+//! credentials. Features that need email check [`mail_configured`] first and degrade
+//! gracefully when it is not.
+
+mod provider;
+mod templates;
+
+pub use templates::Template;
+
+use worker::Env;
+
+use crate::error::AppError;
+use provider::{MailProvider, Provider};
+
+/// A rendered email.
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub to: String,
+    pub subject: String,
+    pub text: String,
+}
+
+impl Message {
+    pub fn new(to: impl Into<String>, template: &Template<'_>) -> Self {
+        let (subject, text) = template.render();
+        Self {
+            to: to.into(),
+            subject,
+            text,
+        }
+    }
+}
+
+// ── MailConfig ──────────────────────────────────────────────────────
+
+pub(crate) struct MailConfig {
+    pub from: String,
+    pub provider: Provider,
+}
+
+/// Build a `MailConfig` from the environment, or `None` when mail is not configured.
+pub(crate) fn mail_config(env: &Env) -> Option<MailConfig> {
+    let from = env
+        .var("MAIL_FROM")
+        .ok()
+        .map(|v| v.to_string())
+        .filter(|v| !v.trim().is_empty())?;
+    let provider = Provider::from_env(env)?;
+    Some(MailConfig { from, provider })
+}
+
+pub fn mail_configured(env: &Env) -> bool {
+    mail_config(env).is_some()
+}
+
+/// Send `template` to `to`.
+pub async fn send(env: &Env, to: &str, template: Template<'_>) -> Result<(), AppError> {
+    send_message(env, &Message::new(to, &template)).await
+}
+
+/// Send an already rendered message.
+pub async fn send_message(env: &Env, message: &Message) -> Result<(), AppError> {
+    let config = mail_config(env)
+        .ok_or_else(|| AppError::BadRequest("Email delivery is not configured".to_string()))?;
+    config.provider.send(&config.from, message).await
+}
+
+/// Send `template` from a background task, logging failures instead of returning them.
+///
+/// Does nothing when mail is not configured.
+pub fn send_in_background(env: Env, to: String, template: Template<'_>) {
+    if !mail_configured(&env) {
+        return;
+    }
+    let message = Message::new(to, &template);
+    crate::background::spawn_background(async move {
+        if let Err(e) = send_message(&env, &message).await {
+            log::warn!(
+                "Background email \"{}\" to {} failed: {e}",
+                message.subject,
+                message.to
+            );
+        }
+    });
+}
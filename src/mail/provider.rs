@@ -0,0 +1,225 @@
+//! Mail delivery backends.
+//!
+//! Every backend takes a rendered [`Message`] and delivers it with one HTTP request.
+//! The backend is picked by [`Provider::from_env`].
+
+use serde_json::{json, Value};
+use worker::{wasm_bindgen::JsValue, Env, Fetch, Method, Request, RequestInit};
+
+use super::Message;
+use crate::error::AppError;
+
+const RESEND_API_URL: &str = "https://api.resend.com/emails";
+const MAILCHANNELS_API_URL: &str = "https://api.mailchannels.net/tx/v1/send";
+
+pub(crate) trait MailProvider {
+    /// Deliver `message` from `from` (an address, optionally as `Name <address>`).
+    async fn send(&self, from: &str, message: &Message) -> Result<(), AppError>;
+}
+
+/// Split `Name <address>` into its parts; a bare address has no name.
+fn parse_mailbox(from: &str) -> (Option<&str>, &str) {
+    match from.trim().rsplit_once('<') {
+        Some((name, address)) => {
+            let name = name.trim().trim_matches('"').trim();
+            let address = address.trim_end_matches('>').trim();
+            ((!name.is_empty()).then_some(name), address)
+        }
+        None => (None, from.trim()),
+    }
+}
+
+/// POST `body` as JSON with the given extra headers and fail on a non-2xx response.
+async fn post_json(
+    url: &str,
+    headers: &[(&str, String)],
+    body: &Value,
+    subject: &str,
+) -> Result<(), AppError> {
+    let mut init = RequestInit::new();
+    init.with_method(Method::Post)
+        .with_body(Some(JsValue::from_str(&body.to_string())));
+
+    let mut req = Request::new_with_init(url, &init).map_err(AppError::Worker)?;
+    let req_headers = req.headers_mut().map_err(AppError::Worker)?;
+    req_headers
+        .set("Content-Type", "application/json")
+        .map_err(AppError::Worker)?;
+    for (name, value) in headers {
+        req_headers.set(name, value).map_err(AppError::Worker)?;
+    }
+
+    let mut response = Fetch::Request(req).send().await.map_err(AppError::Worker)?;
+    if !(200..300).contains(&response.status_code()) {
+        let body = response.text().await.unwrap_or_default();
+        log::error!(
+            "Sending email \"{subject}\" failed ({}): {body}",
+            response.status_code()
+        );
+        return Err(AppError::Internal);
+    }
+    Ok(())
+}
+
+/// [Resend](https://resend.com) HTTP API (`RESEND_API_KEY` secret).
+pub(crate) struct Resend {
+    api_key: String,
+}
+
+impl MailProvider for Resend {
+    async fn send(&self, from: &str, message: &Message) -> Result<(), AppError> {
+        let body = json!({
+            "from": from,
+            "to": [&message.to],
+            "subject": &message.subject,
+            "text": &message.text,
+        });
+        let auth = format!("Bearer {}", self.api_key);
+        post_json(
+            RESEND_API_URL,
+            &[("Authorization", auth)],
+            &body,
+            &message.subject,
+        )
+        .await
+    }
+}
+
+/// [MailChannels](https://www.mailchannels.com) Email API (`MAILCHANNELS_API_KEY` secret).
+pub(crate) struct MailChannels {
+    api_key: String,
+}
+
+impl MailProvider for MailChannels {
+    async fn send(&self, from: &str, message: &Message) -> Result<(), AppError> {
+        let (name, address) = parse_mailbox(from);
+        let mut from = json!({ "email": address });
+        if let Some(name) = name {
+            from["name"] = json!(name);
+        }
+        let body = json!({
+            "personalizations": [{ "to": [{ "email": &message.to }] }],
+            "from": from,
+            "subject": &message.subject,
+            "content": [{ "type": "text/plain", "value": &message.text }],
+        });
+        post_json(
+            MAILCHANNELS_API_URL,
+            &[("X-Api-Key", self.api_key.clone())],
+            &body,
+            &message.subject,
+        )
+        .await
+    }
+}
+
+/// Any endpoint accepting `{"from", "to", "subject", "text"}` as JSON, e.g. a small
+/// HTTP-to-SMTP relay (`MAIL_HTTP_URL`, optional `MAIL_HTTP_TOKEN` bearer secret).
+pub(crate) struct HttpRelay {
+    url: String,
+    token: Option<String>,
+}
+
+impl MailProvider for HttpRelay {
+    async fn send(&self, from: &str, message: &Message) -> Result<(), AppError> {
+        let body = json!({
+            "from": from,
+            "to": &message.to,
+            "subject": &message.subject,
+            "text": &message.text,
+        });
+        let headers: Vec<(&str, String)> = self
+            .token
+            .iter()
+            .map(|token| ("Authorization", format!("Bearer {token}")))
+            .collect();
+        post_json(&self.url, &headers, &body, &message.subject).await
+    }
+}
+
+/// Logs messages instead of sending them, for local development and tests. The body
+/// is not logged because it usually carries a code or link.
+pub(crate) struct Noop;
+
+impl MailProvider for Noop {
+    async fn send(&self, from: &str, message: &Message) -> Result<(), AppError> {
+        log::info!(
+            "Not sending email \"{}\" from {from} to {} (MAIL_PROVIDER=noop)",
+            message.subject,
+            message.to
+        );
+        Ok(())
+    }
+}
+
+/// The configured backend.
+pub(crate) enum Provider {
+    Resend(Resend),
+    MailChannels(MailChannels),
+    Http(HttpRelay),
+    Noop(Noop),
+}
+
+fn non_empty_secret(env: &Env, name: &str) -> Option<String> {
+    env.secret(name)
+        .ok()
+        .map(|v| v.to_string())
+        .filter(|v| !v.trim().is_empty())
+}
+
+fn non_empty_var(env: &Env, name: &str) -> Option<String> {
+    env.var(name)
+        .ok()
+        .map(|v| v.to_string())
+        .filter(|v| !v.trim().is_empty())
+}
+
+impl Provider {
+    /// Backend named by `MAIL_PROVIDER` (`resend`, `mailchannels`, `http` or `noop`), or
+    /// the first one whose credentials are set when the variable is missing. `None` when
+    /// the chosen backend is not fully configured.
+    pub(crate) fn from_env(env: &Env) -> Option<Self> {
+        let resend = || {
+            non_empty_secret(env, "RESEND_API_KEY")
+                .map(|api_key| Provider::Resend(Resend { api_key }))
+        };
+        let mailchannels = || {
+            non_empty_secret(env, "MAILCHANNELS_API_KEY")
+                .map(|api_key| Provider::MailChannels(MailChannels { api_key }))
+        };
+        let http = || {
+            non_empty_var(env, "MAIL_HTTP_URL").map(|url| {
+                Provider::Http(HttpRelay {
+                    url,
+                    token: non_empty_secret(env, "MAIL_HTTP_TOKEN"),
+                })
+            })
+        };
+
+        match non_empty_var(env, "MAIL_PROVIDER")
+            .map(|v| v.trim().to_ascii_lowercase())
+            .as_deref()
+        {
+            Some("resend") => resend(),
+            Some("mailchannels") => mailchannels(),
+            Some("http") => http(),
+            Some("noop") => Some(Provider::Noop(Noop)),
+            Some(other) => {
+                log::warn!("Unknown MAIL_PROVIDER \"{other}\"; email is disabled");
+                None
+            }
+            None => resend().or_else(mailchannels).or_else(http),
+        }
+    }
+}
+
+impl MailProvider for Provider {
+    async fn send(&self, from: &str, message: &Message) -> Result<(), AppError> {
+        match self {
+            Provider::Resend(p) => p.send(from, message).await,
+            Provider::MailChannels(p) => p.send(from, message).await,
+            Provider::Http(p) => p.send(from, message).await,
+            Provider::Noop(p) => p.send(from, message).await,
+        }
+    }
+}
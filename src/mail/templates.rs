@@ -0,0 +1,225 @@
+//! Text of every email the server sends.
+
+/// A message the server can send, with the values it is filled in with.
+pub enum Template<'a> {
+    /// Invitation created through the admin API.
+    Invite,
+    /// Invitation to join an organization, with the web vault link that accepts it.
+    OrgInvite {
+        org_name: &'a str,
+        link: &'a str,
+        expiration_hours: i64,
+    },
+    /// Link that verifies the address of a new account.
+    VerifyEmail { link: &'a str },
+    /// Login from a device not seen before.
+    NewDevice { device_type: &'a str, ip: &'a str },
+    /// Email two-step login code.
+    TwoFactorCode { token: &'a str, ttl_minutes: i64 },
+    /// Code confirming a login from a device not seen before.
+    NewDeviceCode { token: &'a str, ttl_minutes: i64 },
+    /// Code confirming the user's identity before a sensitive operation.
+    VerificationCode { token: &'a str, ttl_minutes: i64 },
+    /// Code confirming a change of the account email.
+    EmailChange { token: &'a str, ttl_minutes: i64 },
+    /// Master password hint, or a note that the account has none.
+    PasswordHint { hint: Option<&'a str> },
+    /// Removal from an organization for not meeting one of its policies.
+    RemovedByPolicy { org_name: &'a str, reason: &'a str },
+    /// Repeated failed logins to the account.
+    FailedLogins { failures: u32, ip: &'a str },
+    /// Master password reset by an organization admin through account recovery.
+    AdminResetPassword { org_name: &'a str },
+    /// Designation as an emergency contact, with the link accepting it when the address
+    /// has no account yet.
+    EmergencyAccessInvite {
+        grantor: &'a str,
+        link: Option<&'a str>,
+    },
+    /// A trusted contact asked for emergency access; sent to the grantor when the
+    /// request is made and as a reminder while it waits.
+    EmergencyAccessRequested {
+        grantee: &'a str,
+        wait_time_days: i32,
+    },
+    /// A two-step login method was removed; `None` when the recovery code turned off
+    /// every method.
+    TwoFactorRemoved { provider: Option<&'a str> },
+}
+
+fn now_display() -> String {
+    chrono::Utc::now()
+        .format("%A, %B %-d, %Y %H:%M UTC")
+        .to_string()
+}
+
+impl Template<'_> {
+    /// Subject and plain-text body.
+    pub fn render(&self) -> (String, String) {
+        match self {
+            Template::Invite => (
+                "You have been invited".to_string(),
+                "You have been invited to create an account on this password manager server.\n\n\
+                 Register with this email address from any Bitwarden client to get started."
+                    .to_string(),
+            ),
+            Template::OrgInvite {
+                org_name,
+                link,
+                expiration_hours,
+            } => (
+                format!("Join {org_name}"),
+                format!(
+                    "You have been invited to join the organization \"{org_name}\".\n\n\
+                     Accept the invitation by opening the link below, then log in or create an account \
+                     with this email address:\n\n\
+                     {link}\n\n\
+                     The link expires in {expiration_hours} hours. An administrator of the organization \
+                     still has to confirm you before shared items become available."
+                ),
+            ),
+            Template::VerifyEmail { link } => (
+                "Verify Your Email".to_string(),
+                format!(
+                    "Verify this email address for your account by opening the link below:\n\n\
+                     {link}\n\n\
+                     The link expires in 24 hours. If you did not create an account you can safely \
+                     ignore this email."
+                ),
+            ),
+            Template::NewDevice { device_type, ip } => (
+                format!("New Device Logged In From {device_type}"),
+                format!(
+                    "Your account was just logged into from a new device.\n\n\
+                     Date: {}\n\
+                     IP address: {ip}\n\
+                     Device type: {device_type}\n\n\
+                     If this was not you, change your master password right away and remove the device \
+                     under Settings > Security > Devices.",
+                    now_display()
+                ),
+            ),
+            Template::TwoFactorCode { token, ttl_minutes } => (
+                "Your Two-step Login Verification Code".to_string(),
+                format!(
+                    "Your two-step verification code is: {token}\n\n\
+                     Use this code to complete logging in. It expires in {ttl_minutes} minutes.\n\n\
+                     If you did not try to log in, someone may know your master password; change it right away."
+                ),
+            ),
+            Template::NewDeviceCode { token, ttl_minutes } => (
+                "Your Account Verification Code".to_string(),
+                format!(
+                    "Your account is being logged into from a new device. To confirm it is you, \
+                     enter this code: {token}\n\n\
+                     The code expires in {ttl_minutes} minutes. If you did not try to log in, someone may \
+                     know your master password; change it right away."
+                ),
+            ),
+            Template::VerificationCode { token, ttl_minutes } => (
+                "Your Verification Code".to_string(),
+                format!(
+                    "Your verification code is: {token}\n\n\
+                     Enter it in the app to confirm it is you before continuing. It expires in \
+                     {ttl_minutes} minutes. If you did not request it, you can safely ignore this email."
+                ),
+            ),
+            Template::EmailChange { token, ttl_minutes } => (
+                "Your Email Change".to_string(),
+                format!(
+                    "To finalize changing your email address enter the following code in the web vault: {token}\n\n\
+                     The code expires in {ttl_minutes} minutes. If you did not try to change your email address, \
+                     you can safely ignore this email."
+                ),
+            ),
+            Template::PasswordHint { hint } => (
+                "Your Master Password Hint".to_string(),
+                match hint {
+                    Some(hint) => format!(
+                        "You (or someone) recently requested your master password hint.\n\n\
+                         Your hint is: \"{hint}\"\n\n\
+                         If you did not request your master password hint you can safely ignore this email."
+                    ),
+                    None => "You (or someone) recently requested your master password hint, but your \
+                             account does not have one.\n\n\
+                             If you did not request your master password hint you can safely ignore this email."
+                        .to_string(),
+                },
+            ),
+            Template::RemovedByPolicy { org_name, reason } => (
+                format!("Removed from {org_name}"),
+                format!(
+                    "You have been removed from the organization \"{org_name}\" because {reason}.\n\n\
+                     Ask an administrator of the organization to invite you again once you meet its policies."
+                ),
+            ),
+            Template::FailedLogins { failures, ip } => (
+                "Failed Login Attempts".to_string(),
+                format!(
+                    "There were {failures} failed attempts to log into your account.\n\n\
+                     Date: {}\n\
+                     Last IP address: {ip}\n\n\
+                     Further attempts are being slowed down. If this was not you, make sure your master \
+                     password is strong and unique, and consider enabling two-step login.",
+                    now_display()
+                ),
+            ),
+            Template::AdminResetPassword { org_name } => (
+                "Master Password Has Been Changed".to_string(),
+                format!(
+                    "An administrator of the organization \"{org_name}\" reset the master password of your \
+                     account through account recovery. All your sessions were logged out.\n\n\
+                     Log in with the new password you received from the administrator; you will be asked \
+                     to choose your own master password right away."
+                ),
+            ),
+            Template::EmergencyAccessInvite { grantor, link } => (
+                "Emergency Access Contact Invite".to_string(),
+                match link {
+                    Some(link) => format!(
+                        "{grantor} invited you to become one of their emergency contacts, who can ask \
+                         for access to their vault in an emergency.\n\n\
+                         Accept the invitation by opening the link below, then create an account with \
+                         this email address:\n\n\
+                         {link}\n\n\
+                         {grantor} still has to confirm you before you can ask for access."
+                    ),
+                    None => format!(
+                        "{grantor} added you as one of their emergency contacts, who can ask for access \
+                         to their vault in an emergency.\n\n\
+                         Once they confirm you, you can ask for access under Settings > Emergency access."
+                    ),
+                },
+            ),
+            Template::EmergencyAccessRequested {
+                grantee,
+                wait_time_days,
+            } => (
+                "Emergency Access Requested".to_string(),
+                format!(
+                    "Your emergency contact {grantee} asked for access to your vault.\n\n\
+                     Access is granted automatically once {wait_time_days} day(s) have passed since the \
+                     request, unless you reject it under Settings > Emergency access. If you do not \
+                     trust this request, reject it right away."
+                ),
+            ),
+            Template::TwoFactorRemoved { provider } => (
+                "Two-step Login Turned Off".to_string(),
+                format!(
+                    "{}\n\n\
+                     Date: {}\n\n\
+                     All your sessions were logged out. If this was not you, change your master password \
+                     right away and set up two-step login again.",
+                    match provider {
+                        Some(provider) => format!(
+                            "The {provider} two-step login method was removed from your account."
+                        ),
+                        None => "Two-step login was turned off for your account with its recovery code."
+                            .to_string(),
+                    },
+                    now_display()
+                ),
+            ),
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! Metrics written to a Workers Analytics Engine dataset.
+//!
+//! Enabled when the `METRICS` dataset binding exists, unless `METRICS_ENABLED` is
+//! `false`. Every data point has the metric name as its index and first blob, its
+//! labels as the following blobs and the value as `double1`: 1 for counters, the
+//! observed amount (milliseconds, bytes, records) for histograms. Writes never fail
+//! a request; errors are logged and dropped.
+//!
+//! Example query for failed logins per grant type over the last day:
+//!
+//! ```sql
+//! SELECT blob2 AS grant_type, SUM(_sample_interval * double1) AS logins
+//! FROM warden_metrics
+//! WHERE blob1 = 'login' AND blob3 = 'failure' AND timestamp > NOW() - INTERVAL '1' DAY
+//! GROUP BY grant_type
+//! ```
+
+use std::cell::RefCell;
+
+use worker::{AnalyticsEngineDataPointBuilder, AnalyticsEngineDataset, Env};
+
+use crate::handlers::get_env_bool;
+
+/// Name of the Analytics Engine dataset binding.
+const METRICS_BINDING: &str = "METRICS";
+
+thread_local! {
+    static DATASET: RefCell<Option<AnalyticsEngineDataset>> = const { RefCell::new(None) };
+}
+
+/// Everything the server measures.
+#[derive(Debug, Clone, Copy)]
+pub enum Metric {
+    /// Counter per login attempt; labels: grant type, outcome.
+    Login,
+    /// Histogram of full and delta sync response sizes in bytes; label: `full` / `delta`.
+    SyncBytes,
+    /// Histogram of records removed per purge; label: purge kind.
+    Purged,
+    /// Histogram of D1 batch latency in milliseconds; label: statement count bucket.
+    D1BatchMs,
+    /// Histogram of API request latency in milliseconds; labels: route, status class.
+    RequestMs,
+}
+
+impl Metric {
+    fn name(self) -> &'static str {
+        match self {
+            Metric::Login => "login",
+            Metric::SyncBytes => "sync_bytes",
+            Metric::Purged => "purged",
+            Metric::D1BatchMs => "d1_batch_ms",
+            Metric::RequestMs => "request_ms",
+        }
+    }
+}
+
+/// Pick up the dataset binding for this isolate. Called from every entry point.
+pub fn init(env: &Env) {
+    let dataset = if get_env_bool(env, "METRICS_ENABLED", true) {
+        env.analytics_engine(METRICS_BINDING).ok()
+    } else {
+        None
+    };
+    DATASET.with(|slot| *slot.borrow_mut() = dataset);
+}
+
+/// Record `value` for `metric` with its `labels`.
+pub fn observe(metric: Metric, value: f64, labels: &[&str]) {
+    DATASET.with(|slot| {
+        let slot = slot.borrow();
+        let Some(dataset) = slot.as_ref() else {
+            return;
+        };
+        let point = AnalyticsEngineDataPointBuilder::new()
+            .indexes([metric.name()])
+            .add_double(value)
+            .blobs(std::iter::once(metric.name()).chain(labels.iter().copied()))
+            .build();
+        if let Err(e) = dataset.write_data_point(&point) {
+            log::warn!("Writing metric {} failed: {e}", metric.name());
+        }
+    });
+}
+
+/// Count one occurrence of `metric`.
+pub fn count(metric: Metric, labels: &[&str]) {
+    observe(metric, 1.0, labels);
+}
+
+/// Milliseconds since `started`, a `Date.now()` value.
+pub fn elapsed_ms(started: f64) -> f64 {
+    (js_sys::Date::now() - started).max(0.0).round()
+}
@@ -0,0 +1,460 @@
+//! D1 schema migrations embedded in the Worker.
+//!
+//! Every file under `migrations/` is compiled into the binary and listed in
+//! [`MIGRATIONS`]; add new files there as well. On the first request (or cron tick)
+//! an isolate handles, [`ensure_applied`] applies the pending ones in order, so a
+//! deploy carries its schema changes without a separate `wrangler d1 migrations
+//! apply`. Set `AUTO_MIGRATE` to `false` to only apply them through
+//! `POST /admin/migrations`.
+//!
+//! Applied versions are recorded in `_migrations`, and also in wrangler's
+//! `d1_migrations` so both tools agree on what has run. Each migration runs in one
+//! D1 batch together with its bookkeeping rows: it applies completely or not at all,
+//! and a concurrent isolate that loses the race fails without changing anything.
+//! An empty database gets `sql/schema.sql`, which already contains every migration.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use serde::Serialize;
+use worker::{D1PreparedStatement, Env};
+
+use crate::d1_query;
+use crate::db;
+use crate::error::AppError;
+use crate::handlers::get_env_bool;
+
+macro_rules! migration {
+    ($name:literal) => {
+        ($name, include_str!(concat!("../migrations/", $name)))
+    };
+}
+
+/// Bundled migrations as `(file name, SQL)`, in the order they are applied.
+const MIGRATIONS: &[(&str, &str)] = &[
+    migration!("0001_add_password_salt.sql"),
+    migration!("0002_add_argon2_fields.sql"),
+    migration!("0003_add_twofactor.sql"),
+    migration!("0004_add_avatar_color.sql"),
+    migration!("0005_add_attachments.sql"),
+    migration!("0006_add_pending_attachments.sql"),
+    migration!("0007_add_password_iterations.sql"),
+    migration!("0008_add_eq_domains.sql"),
+    migration!("0009_add_ciphers_folders_user_id_index.sql"),
+    migration!("0010_add_devices.sql"),
+    migration!("0011_add_sends.sql"),
+    migration!("0012_add_archived_at.sql"),
+    migration!("0013_add_auth_requests.sql"),
+    migration!("0014_add_job_runs.sql"),
+    migration!("0015_add_organizations.sql"),
+    migration!("0016_add_collections.sql"),
+    migration!("0017_add_emergency_access.sql"),
+    migration!("0018_add_refresh_tokens.sql"),
+    migration!("0019_add_api_key.sql"),
+    migration!("0020_add_user_deletion_requested_at.sql"),
+    migration!("0021_add_admin_user_flags.sql"),
+    migration!("0022_add_events.sql"),
+    migration!("0023_add_sync_revisions.sql"),
+    migration!("0024_add_email_change.sql"),
+    migration!("0025_add_org_policies.sql"),
+    migration!("0026_add_groups.sql"),
+    migration!("0027_add_job_cursors.sql"),
+    migration!("0028_add_storage_quotas.sql"),
+    migration!("0029_add_reset_password_key.sql"),
+    migration!("0030_add_passkeys.sql"),
+    migration!("0031_add_member_permissions.sql"),
+    migration!("0032_add_organization_twofactor.sql"),
+    migration!("0033_add_users_ciphers.sql"),
+    migration!("0034_add_multipart_uploads.sql"),
+    migration!("0035_add_new_device_otp.sql"),
+    migration!("0036_add_organization_plan.sql"),
+    migration!("0037_add_verification_otp.sql"),
+    migration!("0038_add_organization_domains.sql"),
+    migration!("0039_add_sso_users.sql"),
+    migration!("0040_add_user_activity.sql"),
+    migration!("0041_add_registration_tokens.sql"),
+    migration!("0042_add_refresh_token_session_start.sql"),
+    migration!("0043_add_job_locks.sql"),
+];
+
+const SCHEMA: &str = include_str!("../sql/schema.sql");
+
+const BOOKKEEPING: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS _migrations (name TEXT PRIMARY KEY NOT NULL, applied_at TEXT NOT NULL)",
+    "CREATE TABLE IF NOT EXISTS d1_migrations (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT UNIQUE NOT NULL,
+        applied_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL
+    )",
+];
+
+/// Whether this isolate has already checked for pending migrations.
+static CHECKED: AtomicBool = AtomicBool::new(false);
+
+/// Result of applying the pending migrations.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationRun {
+    /// Whether the database was empty and got the full schema.
+    pub bootstrapped: bool,
+    pub applied: Vec<&'static str>,
+    /// Migration that failed, with the D1 error; later ones were not attempted.
+    pub failed: Option<MigrationFailure>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MigrationFailure {
+    pub name: &'static str,
+    pub error: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MigrationStatus {
+    pub name: &'static str,
+    pub applied: bool,
+}
+
+/// Apply pending migrations once per isolate, unless `AUTO_MIGRATE` is `false`.
+///
+/// Failures are logged and not retried until the isolate is recycled, so a broken
+/// migration does not slow down every request; `POST /admin/migrations` retries it.
+pub async fn ensure_applied(env: &Env) {
+    if CHECKED.load(Ordering::Relaxed) || !get_env_bool(env, "AUTO_MIGRATE", true) {
+        return;
+    }
+    CHECKED.store(true, Ordering::Relaxed);
+
+    match apply_pending(env, false).await {
+        Ok(run) => {
+            if run.bootstrapped {
+                log::info!("Empty database initialized from the bundled schema");
+            }
+            for name in &run.applied {
+                log::info!("Applied migration {name}");
+            }
+            if let Some(failure) = run.failed {
+                log::error!("Migration {} failed: {}", failure.name, failure.error);
+            }
+        }
+        Err(err) => log::error!("Checking for pending migrations failed: {err}"),
+    }
+}
+
+/// Every bundled migration and whether it has been applied.
+pub async fn status(env: &Env) -> Result<Vec<MigrationStatus>, AppError> {
+    let db = db::get_db(env)?;
+    let applied = applied_names(&db).await?;
+    Ok(MIGRATIONS
+        .iter()
+        .map(|(name, _)| MigrationStatus {
+            name,
+            applied: applied.iter().any(|a| a == name),
+        })
+        .collect())
+}
+
+/// Apply the pending migrations in order, stopping at the first failure.
+///
+/// A database that has tables but no record of any migration predates the
+/// bookkeeping and is left alone, since its schema version is unknown. With
+/// `baseline` set its bundled migrations are instead recorded as applied without
+/// running them, for databases created from `sql/schema.sql` by hand.
+pub async fn apply_pending(env: &Env, baseline: bool) -> Result<MigrationRun, AppError> {
+    let db = db::get_db(env)?;
+    let applied = applied_names(&db).await?;
+    let mut run = MigrationRun::default();
+    let pending: Vec<&(&str, &str)> = MIGRATIONS
+        .iter()
+        .filter(|(name, _)| !applied.iter().any(|a| a == name))
+        .collect();
+    if pending.is_empty() {
+        return Ok(run);
+    }
+
+    let mut statements: Vec<D1PreparedStatement> =
+        BOOKKEEPING.iter().map(|sql| db.prepare(*sql)).collect();
+    if applied.is_empty() {
+        if !has_tables(&db).await? {
+            statements.extend(
+                embedded_statements(SCHEMA)?
+                    .iter()
+                    .map(|s| db.prepare(s.as_str())),
+            );
+            run.bootstrapped = true;
+        } else if !baseline {
+            return Err(AppError::BadRequest(
+                "The database has tables but no recorded migrations; mark them applied with \
+                 POST /admin/migrations?baseline=true"
+                    .to_string(),
+            ));
+        }
+        statements.extend(mark_applied(&db, MIGRATIONS.iter().map(|(name, _)| *name))?);
+        db.batch(statements).await.map_err(AppError::Worker)?;
+        return Ok(run);
+    }
+    db.batch(statements).await.map_err(AppError::Worker)?;
+
+    for (name, sql) in pending {
+        let mut statements: Vec<D1PreparedStatement> = embedded_statements(sql)?
+            .iter()
+            .map(|s| db.prepare(s.as_str()))
+            .collect();
+        statements.extend(mark_applied(&db, std::iter::once(*name))?);
+        if let Err(err) = db.batch(statements).await {
+            run.failed = Some(MigrationFailure {
+                name,
+                error: err.to_string(),
+            });
+            break;
+        }
+        run.applied.push(name);
+    }
+    Ok(run)
+}
+
+/// Names recorded in `_migrations` and wrangler's `d1_migrations`, whichever exist.
+async fn applied_names(db: &db::Db) -> Result<Vec<String>, AppError> {
+    #[derive(serde::Deserialize)]
+    struct Row {
+        name: String,
+    }
+
+    let tables: Vec<Row> = db
+        .prepare(
+            "SELECT name FROM sqlite_master WHERE type = 'table'
+             AND name IN ('_migrations', 'd1_migrations')",
+        )
+        .all()
+        .await
+        .map_err(|_| AppError::Database)?
+        .results()
+        .map_err(|_| AppError::Database)?;
+    if tables.is_empty() {
+        return Ok(Vec::new());
+    }
+    let query = tables
+        .iter()
+        .map(|table| format!("SELECT name FROM {}", table.name))
+        .collect::<Vec<_>>()
+        .join(" UNION ");
+    let rows: Vec<Row> = db
+        .prepare(query)
+        .all()
+        .await
+        .map_err(|_| AppError::Database)?
+        .results()
+        .map_err(|_| AppError::Database)?;
+    Ok(rows.into_iter().map(|row| row.name).collect())
+}
+
+/// Whether the database has any application tables.
+async fn has_tables(db: &db::Db) -> Result<bool, AppError> {
+    let count: Option<f64> = db
+        .prepare(
+            "SELECT COUNT(*) AS cnt FROM sqlite_master WHERE type = 'table'
+             AND name NOT LIKE 'sqlite_%' AND name NOT LIKE '_cf_%'
+             AND name NOT IN ('d1_migrations', '_migrations')",
+        )
+        .first(Some("cnt"))
+        .await
+        .map_err(|_| AppError::Database)?;
+    Ok(count.unwrap_or(0.0) > 0.0)
+}
+
+fn mark_applied<'a>(
+    db: &db::Db,
+    names: impl Iterator<Item = &'a str>,
+) -> Result<Vec<D1PreparedStatement>, AppError> {
+    let now = db::now_string();
+    let mut statements = Vec::new();
+    for name in names {
+        statements.push(
+            d1_query!(
+                db,
+                "INSERT INTO _migrations (name, applied_at) VALUES (?1, ?2)",
+                name,
+                &now
+            )
+            .map_err(|_| AppError::Database)?,
+        );
+        statements.push(
+            d1_query!(
+                db,
+                "INSERT OR IGNORE INTO d1_migrations (name) VALUES (?1)",
+                name
+            )
+            .map_err(|_| AppError::Database)?,
+        );
+    }
+    Ok(statements)
+}
+
+/// Statements of SQL shipped with the worker; failing to split it is a packaging bug.
+fn embedded_statements(sql: &str) -> Result<Vec<String>, AppError> {
+    split_statements(sql).map_err(|_| {
+        log::error!("Embedded SQL ends inside a statement");
+        AppError::Internal
+    })
+}
+
+/// [`split_statements`] reached the end of the script inside a statement: after text
+/// with no closing `;`, or inside a quote, block comment or trigger body.
+#[derive(Debug, PartialEq, Eq)]
+pub struct UnterminatedStatement;
+
+/// Split a SQL script into statements, dropping comments.
+///
+/// Semicolons inside quotes and inside the `BEGIN ... END` body of a trigger do not
+/// end a statement. Shared by migrations and backup restores.
+pub fn split_statements(sql: &str) -> Result<Vec<String>, UnterminatedStatement> {
+    let mut statements = Vec::new();
+    let mut current = String::new();
+    let mut word = String::new();
+    // Nesting of BEGIN/CASE ... END inside a CREATE TRIGGER.
+    let mut depth = 0usize;
+    let mut chars = sql.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            word.push(c);
+            current.push(c);
+            continue;
+        }
+        end_word(&mut word, &current, &mut depth);
+        match c {
+            '-' if chars.peek() == Some(&'-') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        current.push('\n');
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut prev = '\0';
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    if prev == '*' && c == '/' {
+                        closed = true;
+                        break;
+                    }
+                    prev = c;
+                }
+                if !closed {
+                    return Err(UnterminatedStatement);
+                }
+                current.push(' ');
+            }
+            '\'' | '"' | '`' => {
+                current.push(c);
+                let mut closed = false;
+                while let Some(q) = chars.next() {
+                    current.push(q);
+                    if q == c {
+                        // A doubled quote is an escaped quote.
+                        if chars.peek() == Some(&c) {
+                            current.push(c);
+                            chars.next();
+                        } else {
+                            closed = true;
+                            break;
+                        }
+                    }
+                }
+                if !closed {
+                    return Err(UnterminatedStatement);
+                }
+            }
+            ';' if depth == 0 => {
+                let statement = current.trim();
+                if !statement.is_empty() {
+                    statements.push(statement.to_string());
+                }
+                current.clear();
+            }
+            _ => current.push(c),
+        }
+    }
+    if !current.trim().is_empty() {
+        return Err(UnterminatedStatement);
+    }
+    Ok(statements)
+}
+
+/// Track trigger-body nesting at the end of a word.
+fn end_word(word: &mut String, current: &str, depth: &mut usize) {
+    match word.to_ascii_uppercase().as_str() {
+        "BEGIN"
+            if current
+                .trim_start()
+                .to_ascii_uppercase()
+                .starts_with("CREATE") =>
+        {
+            *depth += 1
+        }
+        "CASE" if *depth > 0 => *depth += 1,
+        "END" if *depth > 0 => *depth -= 1,
+        _ => {}
+    }
+    word.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trigger_body_stays_one_statement() {
+        let sql = "CREATE TABLE t (id TEXT);
+CREATE TRIGGER trg AFTER INSERT ON t BEGIN
+  UPDATE s SET n = CASE WHEN n > 0 THEN n + 1 ELSE 1 END;
+  INSERT INTO log VALUES (NEW.id);
+END;
+SELECT 1;";
+        let statements = split_statements(sql).unwrap();
+        assert_eq!(statements.len(), 3);
+        assert!(statements[1].starts_with("CREATE TRIGGER"));
+        assert!(statements[1].ends_with("END"));
+        assert_eq!(statements[2], "SELECT 1");
+    }
+
+    #[test]
+    fn semicolons_in_quotes_do_not_split() {
+        let sql = "INSERT INTO t VALUES ('a;b', 'it''s;', \"c;d\");\nSELECT ';';";
+        assert_eq!(
+            split_statements(sql).unwrap(),
+            vec![
+                "INSERT INTO t VALUES ('a;b', 'it''s;', \"c;d\")",
+                "SELECT ';'"
+            ]
+        );
+    }
+
+    #[test]
+    fn comments_are_dropped() {
+        let sql = "-- leading; comment\nSELECT 1; -- trailing; comment\n/* block; */ SELECT 2;\n-- last line";
+        assert_eq!(split_statements(sql).unwrap(), vec!["SELECT 1", "SELECT 2"]);
+    }
+
+    #[test]
+    fn unterminated_input_is_refused() {
+        assert_eq!(split_statements("SELECT 1"), Err(UnterminatedStatement));
+        assert_eq!(split_statements("SELECT 'a;"), Err(UnterminatedStatement));
+        assert_eq!(
+            split_statements("SELECT 1; /* open"),
+            Err(UnterminatedStatement)
+        );
+        assert_eq!(
+            split_statements("CREATE TRIGGER trg AFTER INSERT ON t BEGIN SELECT 1;"),
+            Err(UnterminatedStatement)
+        );
+    }
+
+    #[test]
+    fn embedded_sql_splits() {
+        assert!(split_statements(SCHEMA).is_ok_and(|s| !s.is_empty()));
+        for (name, sql) in MIGRATIONS {
+            assert!(split_statements(sql).is_ok(), "{name}");
+        }
+    }
+}
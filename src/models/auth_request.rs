@@ -91,6 +91,11 @@ impl AuthRequest {
         self.approved == Some(1)
     }
 
+    /// Whether the request was already used to log in.
+    pub fn is_used(&self) -> bool {
+        self.authentication_date.is_some()
+    }
+
     pub fn set_approved(&mut self, approved: bool) {
         self.approved = Some(if approved { 1 } else { 0 });
     }
@@ -168,16 +173,6 @@ impl AuthRequest {
         Ok(())
     }
 
-    pub async fn delete(&self, db: &crate::db::Db) -> Result<(), AppError> {
-        d1_query!(db, "DELETE FROM auth_requests WHERE id = ?1", &self.id)
-            .map_err(|_| AppError::Database)?
-            .run()
-            .await
-            .map_err(|_| AppError::Database)?;
-
-        Ok(())
-    }
-
     pub async fn find_by_id(db: &crate::db::Db, id: &str) -> Result<Option<Self>, AppError> {
         let row: Option<Value> = d1_query!(db, "SELECT * FROM auth_requests WHERE id = ?1", id)
             .map_err(|_| AppError::Database)?
@@ -237,6 +232,27 @@ impl AuthRequest {
             .collect()
     }
 
+    /// Drop unanswered requests of the same requesting device, which a new request replaces.
+    pub async fn delete_pending_for_device(
+        db: &crate::db::Db,
+        user_id: &str,
+        device_identifier: &str,
+    ) -> Result<(), AppError> {
+        d1_query!(
+            db,
+            "DELETE FROM auth_requests
+             WHERE user_id = ?1 AND request_device_identifier = ?2 AND approved IS NULL",
+            user_id,
+            device_identifier
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+
+        Ok(())
+    }
+
     pub async fn delete_created_before(db: &crate::db::Db, cutoff: &str) -> Result<u32, AppError> {
         let result = d1_query!(
             db,
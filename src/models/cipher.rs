@@ -2,7 +2,10 @@ use std::collections::HashMap;
 
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::{json, Map, Value};
+use worker::D1PreparedStatement;
 
+use crate::d1_query;
+use crate::error::AppError;
 use crate::models::attachment::AttachmentResponse;
 
 // Cipher types:
@@ -12,6 +15,9 @@ use crate::models::attachment::AttachmentResponse;
 //   Identity = 4,
 //   SshKey = 5
 
+/// Password history entries kept per cipher, the same limit as the Bitwarden clients.
+const MAX_PASSWORD_HISTORY: usize = 5;
+
 /// Common cipher type-specific fields shared across multiple cipher structures.
 /// These represent the encrypted content fields that vary based on cipher type.
 /// Used with `#[serde(flatten)]` to embed these fields into other structs.
@@ -32,6 +38,9 @@ pub struct CipherTypeFields {
     // Common fields
     #[serde(skip_serializing_if = "Option::is_none")]
     pub fields: Option<Value>,
+    /// `None` when the field is missing; an explicit `null` is kept as `Value::Null`
+    /// so [`CipherTypeFields::carry_password_history`] can tell the two apart.
+    #[serde(default, deserialize_with = "deserialize_present")]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub password_history: Option<Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -61,12 +70,42 @@ impl CipherTypeFields {
                         .filter_map(normalize_password_history_entry)
                         .collect(),
                 )),
+                Some(Value::Null) | None => None,
                 Some(_) => Some(Value::Array(Vec::new())),
-                None => None,
             }
         };
         self
     }
+
+    /// Keep the password history of clients that do not send `passwordHistory`: the
+    /// stored entries are carried over, and the previous login password is added when
+    /// it changed. Clients that send the field (even as `null`) manage it themselves.
+    pub fn carry_password_history(&mut self, previous: &CipherTypeFields, now: &str) {
+        if self.password_history.is_some() {
+            return;
+        }
+        let mut history = match &previous.password_history {
+            Some(Value::Array(entries)) => entries.clone(),
+            _ => Vec::new(),
+        };
+        if let Some(old_password) = previous.login_password() {
+            if self.login_password() != Some(old_password) {
+                history.insert(0, json!({ "password": old_password, "lastUsedDate": now }));
+            }
+        }
+        history.truncate(MAX_PASSWORD_HISTORY);
+        if !history.is_empty() {
+            self.password_history = Some(Value::Array(history));
+        }
+    }
+
+    fn login_password(&self) -> Option<&str> {
+        self.login
+            .as_ref()
+            .and_then(|login| login.get("password"))
+            .and_then(Value::as_str)
+            .filter(|password| !password.is_empty())
+    }
 }
 
 impl CipherData {
@@ -79,6 +118,13 @@ impl CipherData {
     }
 }
 
+fn deserialize_present<'de, D>(deserializer: D) -> Result<Option<Value>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Value::deserialize(deserializer).map(Some)
+}
+
 fn normalize_password_history_entry(entry: Value) -> Option<Value> {
     match entry {
         Value::Object(map) if matches!(map.get("password"), Some(Value::String(_))) => {
@@ -190,7 +236,8 @@ pub struct Cipher {
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct CipherDBModel {
     pub id: String,
-    pub user_id: String,
+    /// `None` for organization-owned ciphers; access then goes through membership.
+    pub user_id: Option<String>,
     pub organization_id: Option<String>,
     pub r#type: i32,
     pub data: String,
@@ -202,11 +249,43 @@ pub struct CipherDBModel {
     pub updated_at: String,
 }
 
+/// A member's own favorite flag and folder for an organization cipher
+/// (`users_ciphers` row). Personal ciphers keep both on the cipher row.
+pub struct UserCipherState;
+
+impl UserCipherState {
+    /// Statement saving the user's state of a cipher, batched with the cipher write.
+    /// With `revision` set, the state is only saved while the cipher's `updated_at` is
+    /// that revision, so it can follow a conditional cipher update in the same batch.
+    pub fn save_statement(
+        db: &crate::db::Db,
+        user_id: &str,
+        cipher_id: &str,
+        favorite: bool,
+        folder_id: Option<&str>,
+        revision: Option<&str>,
+    ) -> Result<D1PreparedStatement, AppError> {
+        d1_query!(
+            db,
+            "INSERT INTO users_ciphers (user_id, cipher_id, favorite, folder_id)
+             SELECT ?1, ?2, ?3, ?4
+             WHERE ?5 IS NULL OR EXISTS (SELECT 1 FROM ciphers WHERE id = ?2 AND updated_at = ?5)
+             ON CONFLICT (user_id, cipher_id) DO UPDATE SET favorite = excluded.favorite, folder_id = excluded.folder_id",
+            user_id,
+            cipher_id,
+            favorite,
+            folder_id,
+            revision
+        )
+        .map_err(|_| AppError::Database)
+    }
+}
+
 impl From<CipherDBModel> for Cipher {
     fn from(val: CipherDBModel) -> Self {
         Cipher {
             id: val.id,
-            user_id: Some(val.user_id),
+            user_id: val.user_id,
             organization_id: val.organization_id,
             r#type: val.r#type,
             data: serde_json::from_str(&val.data).unwrap_or_default(),
@@ -403,6 +482,24 @@ pub struct CreateCipherRequest {
     pub collection_ids: Vec<String>,
 }
 
+/// PUT/POST /api/ciphers/{id}/share
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShareCipherRequest {
+    pub cipher: CipherRequestData,
+    #[serde(default)]
+    pub collection_ids: Vec<String>,
+}
+
+/// PUT/POST /api/ciphers/share - every cipher carries its `id`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShareCiphersBulkRequest {
+    pub ciphers: Vec<CipherRequestData>,
+    #[serde(default)]
+    pub collection_ids: Vec<String>,
+}
+
 /// Response for listing ciphers (GET /api/ciphers)
 /// Now we don't use this struct, we use RawJson instead. But we keep it here for reference.
 #[derive(Debug, Serialize)]
@@ -0,0 +1,526 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+use worker::D1PreparedStatement;
+
+use crate::d1_query;
+use crate::models::group::GroupAccess;
+use crate::{db, error::AppError};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Collection {
+    pub id: String,
+    pub organization_id: String,
+    pub name: String,
+    pub external_id: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// A member's access to one collection (`users_collections` row).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollectionAccess {
+    pub membership_id: String,
+    pub collection_id: String,
+    pub read_only: i32,
+    pub hide_passwords: i32,
+    pub manage: i32,
+}
+
+/// Collection as seen by one user, with the access flags that apply to them.
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserCollection {
+    pub id: String,
+    pub organization_id: String,
+    pub name: String,
+    pub external_id: Option<String>,
+    pub read_only: i32,
+    pub hide_passwords: i32,
+    pub manage: i32,
+}
+
+impl Collection {
+    pub fn new(organization_id: String, name: String, external_id: Option<String>) -> Self {
+        let now = db::now_string();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            organization_id,
+            name,
+            external_id,
+            created_at: now.clone(),
+            updated_at: now,
+        }
+    }
+
+    /// Collection entry for org management views.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": &self.id,
+            "organizationId": &self.organization_id,
+            "name": &self.name,
+            "externalId": &self.external_id,
+            "object": "collection"
+        })
+    }
+
+    /// Collection with its member and group assignments (`collectionAccessDetails`).
+    pub fn to_access_details_json(
+        &self,
+        users: &[CollectionAccess],
+        groups: &[GroupAccess],
+        assigned: bool,
+    ) -> Value {
+        json!({
+            "id": &self.id,
+            "organizationId": &self.organization_id,
+            "name": &self.name,
+            "externalId": &self.external_id,
+            "groups": groups.iter().map(GroupAccess::to_json).collect::<Vec<_>>(),
+            "users": users.iter().map(CollectionAccess::to_json).collect::<Vec<_>>(),
+            "assigned": assigned,
+            "object": "collectionAccessDetails"
+        })
+    }
+
+    pub async fn find_by_id_and_org(
+        db: &crate::db::Db,
+        id: &str,
+        organization_id: &str,
+    ) -> Result<Option<Self>, AppError> {
+        let row: Option<Value> = d1_query!(
+            db,
+            "SELECT * FROM collections WHERE id = ?1 AND organization_id = ?2",
+            id,
+            organization_id
+        )
+        .map_err(|_| AppError::Database)?
+        .first(None)
+        .await
+        .map_err(|_| AppError::Database)?;
+
+        row.map(|row| serde_json::from_value(row).map_err(|_| AppError::Internal))
+            .transpose()
+    }
+
+    pub async fn list_by_org(
+        db: &crate::db::Db,
+        organization_id: &str,
+    ) -> Result<Vec<Self>, AppError> {
+        let rows: Vec<Value> = d1_query!(
+            db,
+            "SELECT * FROM collections WHERE organization_id = ?1 ORDER BY created_at",
+            organization_id
+        )
+        .map_err(|_| AppError::Database)?
+        .all()
+        .await
+        .map_err(|_| AppError::Database)?
+        .results()
+        .map_err(|_| AppError::Database)?;
+
+        rows.into_iter()
+            .map(|row| serde_json::from_value(row).map_err(|_| AppError::Internal))
+            .collect()
+    }
+
+    /// Collections the user can access across all their confirmed memberships.
+    /// Owners and admins get every collection of their organizations with full access;
+    /// other members get their direct and group assignments, the most permissive
+    /// one winning when there are several.
+    pub async fn list_for_user(
+        db: &crate::db::Db,
+        user_id: &str,
+    ) -> Result<Vec<UserCollection>, AppError> {
+        let rows: Vec<Value> = d1_query!(
+            db,
+            "SELECT col.id, col.organization_id, col.name, col.external_id,
+                    0 AS read_only, 0 AS hide_passwords, 1 AS manage
+             FROM collections col
+             JOIN users_organizations uo ON uo.organization_id = col.organization_id
+             WHERE uo.user_id = ?1 AND uo.status = 2 AND uo.type IN (0, 1)
+             UNION ALL
+             SELECT col.id, col.organization_id, col.name, col.external_id,
+                    MIN(mca.read_only) AS read_only, MIN(mca.hide_passwords) AS hide_passwords,
+                    MAX(mca.manage) AS manage
+             FROM collections col
+             JOIN member_collection_access mca ON mca.collection_id = col.id
+             JOIN users_organizations uo ON uo.id = mca.membership_id
+             WHERE uo.user_id = ?1 AND uo.status = 2 AND uo.type NOT IN (0, 1)
+             GROUP BY col.id",
+            user_id
+        )
+        .map_err(|_| AppError::Database)?
+        .all()
+        .await
+        .map_err(|_| AppError::Database)?
+        .results()
+        .map_err(|_| AppError::Database)?;
+
+        rows.into_iter()
+            .map(|row| serde_json::from_value(row).map_err(|_| AppError::Internal))
+            .collect()
+    }
+
+    /// Ids of the collections in `organization_id` the user may add ciphers to.
+    pub async fn writable_ids_for_user(
+        db: &crate::db::Db,
+        organization_id: &str,
+        user_id: &str,
+    ) -> Result<Vec<String>, AppError> {
+        Ok(Self::list_for_user(db, user_id)
+            .await?
+            .into_iter()
+            .filter(|c| c.organization_id == organization_id && c.read_only == 0)
+            .map(|c| c.id)
+            .collect())
+    }
+
+    pub fn insert_statement(&self, db: &crate::db::Db) -> Result<D1PreparedStatement, AppError> {
+        d1_query!(
+            db,
+            "INSERT INTO collections (id, organization_id, name, external_id, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            &self.id,
+            &self.organization_id,
+            &self.name,
+            self.external_id.as_deref(),
+            &self.created_at,
+            &self.updated_at
+        )
+        .map_err(|_| AppError::Database)
+    }
+
+    pub async fn insert(&self, db: &crate::db::Db) -> Result<(), AppError> {
+        self.insert_statement(db)?
+            .run()
+            .await
+            .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+
+    pub async fn update(&mut self, db: &crate::db::Db) -> Result<(), AppError> {
+        self.updated_at = db::now_string();
+        d1_query!(
+            db,
+            "UPDATE collections SET name = ?1, external_id = ?2, updated_at = ?3 WHERE id = ?4",
+            &self.name,
+            self.external_id.as_deref(),
+            &self.updated_at,
+            &self.id
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+
+    /// Delete the collection; cipher and member assignments cascade.
+    pub async fn delete(&self, db: &crate::db::Db) -> Result<(), AppError> {
+        d1_query!(db, "DELETE FROM collections WHERE id = ?1", &self.id)
+            .map_err(|_| AppError::Database)?
+            .run()
+            .await
+            .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+
+    /// Ids from `ids` that are not collections of `organization_id`.
+    pub async fn foreign_ids(
+        db: &crate::db::Db,
+        organization_id: &str,
+        ids: &[String],
+    ) -> Result<Vec<String>, AppError> {
+        let known: Vec<String> = Self::list_by_org(db, organization_id)
+            .await?
+            .into_iter()
+            .map(|c| c.id)
+            .collect();
+        Ok(ids
+            .iter()
+            .filter(|id| !known.contains(id))
+            .cloned()
+            .collect())
+    }
+
+    pub async fn ids_for_cipher(
+        db: &crate::db::Db,
+        cipher_id: &str,
+    ) -> Result<Vec<String>, AppError> {
+        #[derive(Deserialize)]
+        struct Row {
+            collection_id: String,
+        }
+
+        let rows: Vec<Row> = d1_query!(
+            db,
+            "SELECT collection_id FROM ciphers_collections WHERE cipher_id = ?1",
+            cipher_id
+        )
+        .map_err(|_| AppError::Database)?
+        .all()
+        .await
+        .map_err(|_| AppError::Database)?
+        .results()
+        .map_err(|_| AppError::Database)?;
+
+        Ok(rows.into_iter().map(|r| r.collection_id).collect())
+    }
+
+    /// Statements replacing the set of collections a cipher belongs to, for callers
+    /// that batch them with other writes.
+    pub fn set_for_cipher_statements(
+        db: &crate::db::Db,
+        cipher_id: &str,
+        collection_ids: &[String],
+    ) -> Result<Vec<D1PreparedStatement>, AppError> {
+        let mut statements = vec![d1_query!(
+            db,
+            "DELETE FROM ciphers_collections WHERE cipher_id = ?1",
+            cipher_id
+        )
+        .map_err(|_| AppError::Database)?];
+        for collection_id in collection_ids {
+            statements.push(
+                d1_query!(
+                    db,
+                    "INSERT OR IGNORE INTO ciphers_collections (cipher_id, collection_id) VALUES (?1, ?2)",
+                    cipher_id,
+                    collection_id
+                )
+                .map_err(|_| AppError::Database)?,
+            );
+        }
+        Ok(statements)
+    }
+}
+
+impl UserCollection {
+    /// Entry of the sync `collections` section.
+    pub fn to_details_json(&self) -> Value {
+        json!({
+            "id": &self.id,
+            "organizationId": &self.organization_id,
+            "name": &self.name,
+            "externalId": &self.external_id,
+            "readOnly": self.read_only != 0,
+            "hidePasswords": self.hide_passwords != 0,
+            "manage": self.manage != 0,
+            "object": "collectionDetails"
+        })
+    }
+}
+
+impl CollectionAccess {
+    /// Entry of a collection's `users` list (keyed by membership id).
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": &self.membership_id,
+            "readOnly": self.read_only != 0,
+            "hidePasswords": self.hide_passwords != 0,
+            "manage": self.manage != 0
+        })
+    }
+
+    /// Entry of a member's `collections` list (keyed by collection id).
+    pub fn to_member_json(&self) -> Value {
+        json!({
+            "id": &self.collection_id,
+            "readOnly": self.read_only != 0,
+            "hidePasswords": self.hide_passwords != 0,
+            "manage": self.manage != 0
+        })
+    }
+
+    pub async fn list_by_collection(
+        db: &crate::db::Db,
+        collection_id: &str,
+    ) -> Result<Vec<Self>, AppError> {
+        let rows: Vec<Value> = d1_query!(
+            db,
+            "SELECT * FROM users_collections WHERE collection_id = ?1",
+            collection_id
+        )
+        .map_err(|_| AppError::Database)?
+        .all()
+        .await
+        .map_err(|_| AppError::Database)?
+        .results()
+        .map_err(|_| AppError::Database)?;
+
+        rows.into_iter()
+            .map(|row| serde_json::from_value(row).map_err(|_| AppError::Internal))
+            .collect()
+    }
+
+    pub async fn list_by_membership(
+        db: &crate::db::Db,
+        membership_id: &str,
+    ) -> Result<Vec<Self>, AppError> {
+        let rows: Vec<Value> = d1_query!(
+            db,
+            "SELECT * FROM users_collections WHERE membership_id = ?1",
+            membership_id
+        )
+        .map_err(|_| AppError::Database)?
+        .all()
+        .await
+        .map_err(|_| AppError::Database)?
+        .results()
+        .map_err(|_| AppError::Database)?;
+
+        rows.into_iter()
+            .map(|row| serde_json::from_value(row).map_err(|_| AppError::Internal))
+            .collect()
+    }
+
+    /// Statements replacing all assignments matching `scope` (`collection_id = ?1` or
+    /// `membership_id = ?1`) with `entries`.
+    fn replace_statements(
+        db: &crate::db::Db,
+        scope: &str,
+        scope_id: &str,
+        entries: &[CollectionAccess],
+    ) -> Result<Vec<D1PreparedStatement>, AppError> {
+        let mut statements = vec![d1_query!(
+            db,
+            &format!("DELETE FROM users_collections WHERE {scope} = ?1"),
+            scope_id
+        )
+        .map_err(|_| AppError::Database)?];
+        for entry in entries {
+            statements.push(
+                d1_query!(
+                    db,
+                    "INSERT OR REPLACE INTO users_collections (membership_id, collection_id, read_only, hide_passwords, manage)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    &entry.membership_id,
+                    &entry.collection_id,
+                    entry.read_only,
+                    entry.hide_passwords,
+                    entry.manage
+                )
+                .map_err(|_| AppError::Database)?,
+            );
+        }
+        Ok(statements)
+    }
+
+    /// Statements replacing a collection's member assignments, for callers that batch
+    /// them with other writes.
+    pub fn replace_for_collection_statements(
+        db: &crate::db::Db,
+        collection_id: &str,
+        entries: &[CollectionAccess],
+    ) -> Result<Vec<D1PreparedStatement>, AppError> {
+        Self::replace_statements(db, "collection_id", collection_id, entries)
+    }
+
+    pub async fn replace_for_collection(
+        db: &crate::db::Db,
+        collection_id: &str,
+        entries: &[CollectionAccess],
+    ) -> Result<(), AppError> {
+        db.batch(Self::replace_for_collection_statements(
+            db,
+            collection_id,
+            entries,
+        )?)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn replace_for_membership(
+        db: &crate::db::Db,
+        membership_id: &str,
+        entries: &[CollectionAccess],
+    ) -> Result<(), AppError> {
+        db.batch(Self::replace_statements(
+            db,
+            "membership_id",
+            membership_id,
+            entries,
+        )?)
+        .await?;
+        Ok(())
+    }
+
+    /// Remove one member's assignment to a collection, returning whether there was one.
+    pub async fn delete(
+        db: &crate::db::Db,
+        collection_id: &str,
+        membership_id: &str,
+    ) -> Result<bool, AppError> {
+        let result = d1_query!(
+            db,
+            "DELETE FROM users_collections WHERE collection_id = ?1 AND membership_id = ?2",
+            collection_id,
+            membership_id
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(result.meta()?.and_then(|m| m.changes).unwrap_or(0) > 0)
+    }
+}
+
+// ── Request payloads ────────────────────────────────────────────────
+
+/// Access flags for one collection/member pair, as sent by the clients.
+/// `id` is the membership id in collection requests and the collection id in member requests.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectionAccessData {
+    pub id: String,
+    #[serde(default)]
+    pub read_only: bool,
+    #[serde(default)]
+    pub hide_passwords: bool,
+    #[serde(default)]
+    pub manage: bool,
+}
+
+impl CollectionAccessData {
+    pub fn into_access(self, membership_id: &str, collection_id: &str) -> CollectionAccess {
+        CollectionAccess {
+            membership_id: membership_id.to_string(),
+            collection_id: collection_id.to_string(),
+            read_only: self.read_only as i32,
+            hide_passwords: self.hide_passwords as i32,
+            manage: self.manage as i32,
+        }
+    }
+}
+
+/// POST/PUT /api/organizations/{org_id}/collections[/{id}]
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectionRequest {
+    pub name: String,
+    pub external_id: Option<String>,
+    #[serde(default)]
+    pub users: Vec<CollectionAccessData>,
+    #[serde(default)]
+    pub groups: Vec<CollectionAccessData>,
+}
+
+/// POST /api/organizations/{org_id}/collections/bulk-access
+///
+/// `users` and `groups` replace the assignments of every listed collection.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkCollectionAccessRequest {
+    pub collection_ids: Vec<String>,
+    #[serde(default)]
+    pub users: Vec<CollectionAccessData>,
+    #[serde(default)]
+    pub groups: Vec<CollectionAccessData>,
+}
+
+/// PUT/POST /api/ciphers/{id}/collections
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CipherCollectionsRequest {
+    pub collection_ids: Vec<String>,
+}
@@ -15,6 +15,7 @@ pub struct Device {
     pub push_uuid: Option<String>,
     pub push_token: Option<String>,
     pub refresh_token: String,
+    /// Hex SHA-256 of the remember-device token last issued to this device.
     pub twofactor_remember: Option<String>,
     pub created_at: String,
     pub updated_at: String,
@@ -49,6 +50,7 @@ impl Device {
             "type": self.r#type,
             "identifier": &self.identifier,
             "creationDate": &self.created_at,
+            "lastActivityDate": &self.updated_at,
             "isTrusted": false,
             "encryptedPublicKey": Value::Null,
             "encryptedUserKey": Value::Null,
@@ -143,13 +145,15 @@ impl Device {
         Ok(())
     }
 
+    /// Find the device, updating its name/type if they changed, or register it.
+    /// Also returns whether the device was newly created.
     pub async fn get_or_create(
         db: &crate::db::Db,
         identifier: String,
         user_id: String,
         name: String,
         r#type: i32,
-    ) -> Result<Self, AppError> {
+    ) -> Result<(Self, bool), AppError> {
         if let Some(mut device) =
             Self::find_by_identifier_and_user(db, &identifier, &user_id).await?
         {
@@ -173,12 +177,59 @@ impl Device {
                 device.updated_at = now;
             }
 
-            return Ok(device);
+            return Ok((device, false));
         }
 
         let device = Self::new(identifier, user_id, name, r#type)?;
         device.insert(db).await?;
-        Ok(device)
+        Ok((device, true))
+    }
+
+    /// Like [`Self::get_or_create`], but without storing anything: a new device is only
+    /// built, and a changed name or type is only set on the returned row. Logins that
+    /// still have to pass two-step login use this, and [`Self::save_for_login`] once
+    /// they did, so a failed login leaves no known device behind.
+    pub async fn find_or_build(
+        db: &crate::db::Db,
+        identifier: String,
+        user_id: String,
+        name: String,
+        r#type: i32,
+    ) -> Result<(Self, bool), AppError> {
+        match Self::find_by_identifier_and_user(db, &identifier, &user_id).await? {
+            Some(mut device) => {
+                device.name = name;
+                device.r#type = r#type;
+                Ok((device, false))
+            }
+            None => Ok((Self::new(identifier, user_id, name, r#type)?, true)),
+        }
+    }
+
+    /// Store a device returned by [`Self::find_or_build`].
+    pub async fn save_for_login(
+        &mut self,
+        db: &crate::db::Db,
+        is_new: bool,
+    ) -> Result<(), AppError> {
+        if is_new {
+            return self.insert(db).await;
+        }
+        self.updated_at = db::now_string();
+        d1_query!(
+            db,
+            "UPDATE devices SET name = ?1, type = ?2, updated_at = ?3 WHERE identifier = ?4 AND user_id = ?5",
+            &self.name,
+            self.r#type,
+            &self.updated_at,
+            &self.identifier,
+            &self.user_id
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
     }
 
     pub async fn touch(&mut self, db: &crate::db::Db) -> Result<(), AppError> {
@@ -198,6 +249,28 @@ impl Device {
         Ok(())
     }
 
+    /// Replace the device's static refresh token, invalidating the old one.
+    pub async fn rotate_refresh_token(&mut self, db: &crate::db::Db) -> Result<(), AppError> {
+        let refresh_token = generate_refresh_token()?;
+        let now = db::now_string();
+        d1_query!(
+            db,
+            "UPDATE devices SET refresh_token = ?1, updated_at = ?2 WHERE identifier = ?3 AND user_id = ?4",
+            &refresh_token,
+            &now,
+            &self.identifier,
+            &self.user_id
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+
+        self.refresh_token = refresh_token;
+        self.updated_at = now;
+        Ok(())
+    }
+
     pub async fn set_push_token(
         &mut self,
         db: &crate::db::Db,
@@ -265,6 +338,41 @@ impl Device {
         Ok(())
     }
 
+    pub async fn rename(&mut self, db: &crate::db::Db, name: String) -> Result<(), AppError> {
+        let now = db::now_string();
+        d1_query!(
+            db,
+            "UPDATE devices SET name = ?1, updated_at = ?2 WHERE identifier = ?3 AND user_id = ?4",
+            &name,
+            &now,
+            &self.identifier,
+            &self.user_id
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+
+        self.name = name;
+        self.updated_at = now;
+        Ok(())
+    }
+
+    /// Delete the device row, revoking its refresh token.
+    pub async fn delete(&self, db: &crate::db::Db) -> Result<(), AppError> {
+        d1_query!(
+            db,
+            "DELETE FROM devices WHERE identifier = ?1 AND user_id = ?2",
+            &self.identifier,
+            &self.user_id
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+
     /// Delete all device rows for a user, effectively revoking all refresh tokens and
     /// logging out every active session.
     pub async fn delete_all_by_user(db: &crate::db::Db, user_id: &str) -> Result<(), AppError> {
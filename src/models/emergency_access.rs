@@ -0,0 +1,433 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::d1_query;
+use crate::{db, error::AppError};
+
+/// What the grantee may do once recovery is approved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum EmergencyAccessType {
+    View = 0,
+    Takeover = 1,
+}
+
+impl EmergencyAccessType {
+    pub fn from_i32(value: i32) -> Option<Self> {
+        match value {
+            0 => Some(EmergencyAccessType::View),
+            1 => Some(EmergencyAccessType::Takeover),
+            _ => None,
+        }
+    }
+}
+
+/// Grant lifecycle: invited -> accepted (grantee) -> confirmed (grantor) ->
+/// recovery initiated (grantee) -> recovery approved (grantor or wait time elapsed).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum EmergencyAccessStatus {
+    Invited = 0,
+    Accepted = 1,
+    Confirmed = 2,
+    RecoveryInitiated = 3,
+    RecoveryApproved = 4,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmergencyAccess {
+    pub id: String,
+    pub grantor_id: String,
+    pub grantee_id: Option<String>,
+    pub email: String,
+    pub key_encrypted: Option<String>,
+    #[serde(rename = "type")]
+    pub r#type: i32,
+    pub status: i32,
+    pub wait_time_days: i32,
+    pub recovery_initiated_at: Option<String>,
+    pub last_notification_at: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// Public details of the other party of a grant.
+#[derive(Debug, Clone, Deserialize)]
+pub struct EmergencyContact {
+    pub id: String,
+    pub name: Option<String>,
+    pub email: String,
+    pub avatar_color: Option<String>,
+}
+
+impl EmergencyContact {
+    pub async fn find_by_id(db: &crate::db::Db, user_id: &str) -> Result<Option<Self>, AppError> {
+        d1_query!(
+            db,
+            "SELECT id, name, email, avatar_color FROM users WHERE id = ?1",
+            user_id
+        )
+        .map_err(|_| AppError::Database)?
+        .first(None)
+        .await
+        .map_err(|_| AppError::Database)
+    }
+}
+
+impl EmergencyAccess {
+    pub fn new(
+        grantor_id: String,
+        grantee_id: Option<String>,
+        email: String,
+        r#type: EmergencyAccessType,
+        status: EmergencyAccessStatus,
+        wait_time_days: i32,
+    ) -> Self {
+        let now = db::now_string();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            grantor_id,
+            grantee_id,
+            email,
+            key_encrypted: None,
+            r#type: r#type as i32,
+            status: status as i32,
+            wait_time_days,
+            recovery_initiated_at: None,
+            last_notification_at: None,
+            created_at: now.clone(),
+            updated_at: now,
+        }
+    }
+
+    pub fn has_status(&self, status: EmergencyAccessStatus) -> bool {
+        self.status == status as i32
+    }
+
+    pub fn has_type(&self, r#type: EmergencyAccessType) -> bool {
+        self.r#type == r#type as i32
+    }
+
+    /// Whether the wait time since recovery was initiated has elapsed at `now`.
+    pub fn wait_time_elapsed(&self, now: DateTime<Utc>) -> bool {
+        self.recovery_initiated_at
+            .as_deref()
+            .and_then(|ts| DateTime::parse_from_rfc3339(ts).ok())
+            .map(|initiated| {
+                initiated.with_timezone(&Utc) + Duration::days(self.wait_time_days as i64) <= now
+            })
+            .unwrap_or(false)
+    }
+
+    /// Basic grant entry (GET /api/emergency-access/{id}).
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": &self.id,
+            "status": self.status,
+            "type": self.r#type,
+            "waitTimeDays": self.wait_time_days,
+            "object": "emergencyAccess"
+        })
+    }
+
+    /// Grant as seen by the grantor, with the grantee's details.
+    pub fn to_grantee_details_json(&self, grantee: Option<&EmergencyContact>) -> Value {
+        json!({
+            "id": &self.id,
+            "status": self.status,
+            "type": self.r#type,
+            "waitTimeDays": self.wait_time_days,
+            "granteeId": &self.grantee_id,
+            "email": grantee.map(|g| g.email.as_str()).unwrap_or(&self.email),
+            "name": grantee.and_then(|g| g.name.as_deref()),
+            "avatarColor": grantee.and_then(|g| g.avatar_color.as_deref()),
+            "object": "emergencyAccessGranteeDetails"
+        })
+    }
+
+    /// Grant as seen by the grantee, with the grantor's details.
+    pub fn to_grantor_details_json(&self, grantor: &EmergencyContact) -> Value {
+        json!({
+            "id": &self.id,
+            "status": self.status,
+            "type": self.r#type,
+            "waitTimeDays": self.wait_time_days,
+            "grantorId": &grantor.id,
+            "email": &grantor.email,
+            "name": &grantor.name,
+            "avatarColor": &grantor.avatar_color,
+            "object": "emergencyAccessGrantorDetails"
+        })
+    }
+
+    pub async fn find_by_id(db: &crate::db::Db, id: &str) -> Result<Option<Self>, AppError> {
+        let row: Option<Value> = d1_query!(db, "SELECT * FROM emergency_access WHERE id = ?1", id)
+            .map_err(|_| AppError::Database)?
+            .first(None)
+            .await
+            .map_err(|_| AppError::Database)?;
+
+        row.map(|row| serde_json::from_value(row).map_err(|_| AppError::Internal))
+            .transpose()
+    }
+
+    pub async fn find_by_grantor_and_email(
+        db: &crate::db::Db,
+        grantor_id: &str,
+        email: &str,
+    ) -> Result<Option<Self>, AppError> {
+        let row: Option<Value> = d1_query!(
+            db,
+            "SELECT * FROM emergency_access WHERE grantor_id = ?1 AND email = ?2",
+            grantor_id,
+            email
+        )
+        .map_err(|_| AppError::Database)?
+        .first(None)
+        .await
+        .map_err(|_| AppError::Database)?;
+
+        row.map(|row| serde_json::from_value(row).map_err(|_| AppError::Internal))
+            .transpose()
+    }
+
+    async fn list_where<T: Serialize>(
+        db: &crate::db::Db,
+        condition: &str,
+        param: T,
+    ) -> Result<Vec<Self>, AppError> {
+        let rows: Vec<Value> = d1_query!(
+            db,
+            &format!("SELECT * FROM emergency_access WHERE {condition} ORDER BY created_at"),
+            param
+        )
+        .map_err(|_| AppError::Database)?
+        .all()
+        .await
+        .map_err(|_| AppError::Database)?
+        .results()
+        .map_err(|_| AppError::Database)?;
+
+        rows.into_iter()
+            .map(|row| serde_json::from_value(row).map_err(|_| AppError::Internal))
+            .collect()
+    }
+
+    pub async fn list_by_grantor(
+        db: &crate::db::Db,
+        grantor_id: &str,
+    ) -> Result<Vec<Self>, AppError> {
+        Self::list_where(db, "grantor_id = ?1", grantor_id).await
+    }
+
+    pub async fn list_by_grantee(
+        db: &crate::db::Db,
+        grantee_id: &str,
+    ) -> Result<Vec<Self>, AppError> {
+        Self::list_where(db, "grantee_id = ?1", grantee_id).await
+    }
+
+    /// Grants with a recovery request that has not been approved or rejected yet.
+    pub async fn list_recovery_initiated(db: &crate::db::Db) -> Result<Vec<Self>, AppError> {
+        Self::list_where(
+            db,
+            "status = ?1",
+            EmergencyAccessStatus::RecoveryInitiated as i32,
+        )
+        .await
+    }
+
+    pub async fn insert(&self, db: &crate::db::Db) -> Result<(), AppError> {
+        d1_query!(
+            db,
+            "INSERT INTO emergency_access (id, grantor_id, grantee_id, email, key_encrypted, type, status, wait_time_days, recovery_initiated_at, last_notification_at, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            &self.id,
+            &self.grantor_id,
+            self.grantee_id.as_deref(),
+            &self.email,
+            self.key_encrypted.as_deref(),
+            self.r#type,
+            self.status,
+            self.wait_time_days,
+            self.recovery_initiated_at.as_deref(),
+            self.last_notification_at.as_deref(),
+            &self.created_at,
+            &self.updated_at
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+
+    pub async fn update(&mut self, db: &crate::db::Db) -> Result<(), AppError> {
+        self.updated_at = db::now_string();
+        d1_query!(
+            db,
+            "UPDATE emergency_access SET grantee_id = ?1, key_encrypted = ?2, type = ?3, status = ?4, wait_time_days = ?5,
+                recovery_initiated_at = ?6, last_notification_at = ?7, updated_at = ?8
+             WHERE id = ?9",
+            self.grantee_id.as_deref(),
+            self.key_encrypted.as_deref(),
+            self.r#type,
+            self.status,
+            self.wait_time_days,
+            self.recovery_initiated_at.as_deref(),
+            self.last_notification_at.as_deref(),
+            &self.updated_at,
+            &self.id
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+
+    pub async fn delete(&self, db: &crate::db::Db) -> Result<(), AppError> {
+        d1_query!(db, "DELETE FROM emergency_access WHERE id = ?1", &self.id)
+            .map_err(|_| AppError::Database)?
+            .run()
+            .await
+            .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+
+    /// Statements re-encrypting the grantor's emergency access keys during key rotation.
+    ///
+    /// Every key must belong to one of the grantor's confirmed grants. Grants the client
+    /// did not re-encrypt go back to accepted, dropping any recovery in progress, so the
+    /// grantor has to confirm them again: their key would unwrap the old user key.
+    pub async fn rotation_statements(
+        db: &crate::db::Db,
+        grantor_id: &str,
+        keys: &[EmergencyAccessKeyData],
+        now: &str,
+    ) -> Result<Vec<worker::D1PreparedStatement>, AppError> {
+        let rows: Vec<Value> = d1_query!(
+            db,
+            "SELECT id FROM emergency_access WHERE grantor_id = ?1 AND key_encrypted IS NOT NULL",
+            grantor_id
+        )
+        .map_err(|_| AppError::Database)?
+        .all()
+        .await
+        .map_err(|_| AppError::Database)?
+        .results()
+        .map_err(|_| AppError::Database)?;
+        let keyed: Vec<&str> = rows
+            .iter()
+            .filter_map(|row| row.get("id").and_then(|v| v.as_str()))
+            .collect();
+        if keys.iter().any(|k| !keyed.contains(&k.id.as_str())) {
+            return Err(AppError::BadRequest(
+                "Emergency access key for a grant the user has not confirmed".to_string(),
+            ));
+        }
+
+        let rotated: Vec<&str> = keys.iter().map(|k| k.id.as_str()).collect();
+        let rotated = serde_json::to_string(&rotated).map_err(|_| AppError::Internal)?;
+        let mut statements = Vec::with_capacity(keys.len() + 1);
+        statements.push(
+            d1_query!(
+                db,
+                "UPDATE emergency_access SET key_encrypted = NULL, status = ?1, recovery_initiated_at = NULL,
+                    last_notification_at = NULL, updated_at = ?2
+                 WHERE grantor_id = ?3 AND key_encrypted IS NOT NULL
+                   AND id NOT IN (SELECT value FROM json_each(?4))",
+                EmergencyAccessStatus::Accepted as i32,
+                now,
+                grantor_id,
+                rotated
+            )
+            .map_err(|_| AppError::Database)?,
+        );
+        for key in keys {
+            statements.push(
+                d1_query!(
+                    db,
+                    "UPDATE emergency_access SET key_encrypted = ?1, updated_at = ?2
+                     WHERE id = ?3 AND grantor_id = ?4",
+                    &key.key_encrypted,
+                    now,
+                    &key.id,
+                    grantor_id
+                )
+                .map_err(|_| AppError::Database)?,
+            );
+        }
+        Ok(statements)
+    }
+
+    /// Link invitations sent to `email` to a freshly registered account and accept them.
+    pub async fn accept_invites_for_new_user(
+        db: &crate::db::Db,
+        user_id: &str,
+        email: &str,
+    ) -> Result<(), AppError> {
+        d1_query!(
+            db,
+            "UPDATE emergency_access SET grantee_id = ?1, status = ?2, updated_at = ?3
+             WHERE email = ?4 AND grantee_id IS NULL AND status = ?5",
+            user_id,
+            EmergencyAccessStatus::Accepted as i32,
+            db::now_string(),
+            email,
+            EmergencyAccessStatus::Invited as i32
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+}
+
+// ── Request payloads ────────────────────────────────────────────────
+
+/// POST /api/emergency-access/invite
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmergencyAccessInviteRequest {
+    pub email: String,
+    #[serde(rename = "type")]
+    pub r#type: i32,
+    pub wait_time_days: i32,
+}
+
+/// PUT/POST /api/emergency-access/{id}
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmergencyAccessUpdateRequest {
+    #[serde(rename = "type")]
+    pub r#type: i32,
+    pub wait_time_days: i32,
+    pub key_encrypted: Option<String>,
+}
+
+/// A grant's key re-encrypted under the grantor's new user key during key rotation.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmergencyAccessKeyData {
+    pub id: String,
+    pub key_encrypted: String,
+}
+
+/// POST /api/emergency-access/{id}/confirm
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmergencyAccessConfirmRequest {
+    pub key: String,
+}
+
+/// POST /api/emergency-access/{id}/password
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmergencyAccessPasswordRequest {
+    pub new_master_password_hash: String,
+    pub key: String,
+}
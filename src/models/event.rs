@@ -0,0 +1,358 @@
+use axum::http::HeaderMap;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+use worker::wasm_bindgen::JsValue;
+
+use crate::auth::Claims;
+use crate::client_context::{request_device_type_from_headers, request_ip_from_headers};
+use crate::d1_query;
+use crate::models::organization::MembershipStatus;
+use crate::{db, error::AppError};
+
+/// Organization event codes, matching Bitwarden's `EventType`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventType {
+    UserLoggedIn = 1000,
+
+    CipherCreated = 1100,
+    CipherUpdated = 1101,
+    CipherDeleted = 1102,
+    CipherShared = 1105,
+    CipherSoftDeleted = 1115,
+    CipherRestored = 1116,
+
+    CollectionCreated = 1300,
+    CollectionUpdated = 1301,
+    CollectionDeleted = 1302,
+
+    GroupCreated = 1400,
+    GroupUpdated = 1401,
+    GroupDeleted = 1402,
+
+    OrganizationUserInvited = 1500,
+    OrganizationUserConfirmed = 1501,
+    OrganizationUserUpdated = 1502,
+    OrganizationUserRemoved = 1503,
+    OrganizationUserUpdatedGroups = 1504,
+    OrganizationUserResetPasswordEnroll = 1506,
+    OrganizationUserResetPasswordWithdraw = 1507,
+    OrganizationUserAdminResetPassword = 1508,
+    OrganizationUserLeft = 1516,
+
+    OrganizationUpdated = 1600,
+    OrganizationClientExportedVault = 1602,
+
+    PolicyUpdated = 1700,
+}
+
+/// Who performed an action, captured from the authenticated request.
+#[derive(Debug, Clone)]
+pub struct EventActor {
+    pub user_id: String,
+    pub device_type: i32,
+    pub ip_address: String,
+}
+
+impl EventActor {
+    pub fn from_request(claims: &Claims, headers: &HeaderMap) -> Self {
+        Self {
+            user_id: claims.sub.clone(),
+            device_type: request_device_type_from_headers(headers),
+            ip_address: request_ip_from_headers(headers),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct OrgIdRow {
+    organization_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Event {
+    pub id: String,
+    pub event_type: i32,
+    pub organization_id: String,
+    pub user_id: Option<String>,
+    pub acting_user_id: Option<String>,
+    pub cipher_id: Option<String>,
+    pub collection_id: Option<String>,
+    pub member_id: Option<String>,
+    pub policy_id: Option<String>,
+    pub group_id: Option<String>,
+    pub device_type: Option<i32>,
+    pub ip_address: Option<String>,
+    pub event_date: String,
+}
+
+impl Event {
+    pub fn new(event_type: EventType, organization_id: &str, actor: &EventActor) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            event_type: event_type as i32,
+            organization_id: organization_id.to_string(),
+            user_id: None,
+            acting_user_id: Some(actor.user_id.clone()),
+            cipher_id: None,
+            collection_id: None,
+            member_id: None,
+            policy_id: None,
+            group_id: None,
+            device_type: Some(actor.device_type),
+            ip_address: Some(actor.ip_address.clone()),
+            event_date: db::now_string(),
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "object": "event",
+            "type": self.event_type,
+            "userId": self.user_id,
+            "organizationId": self.organization_id,
+            "cipherId": self.cipher_id,
+            "collectionId": self.collection_id,
+            "groupId": self.group_id,
+            "policyId": self.policy_id,
+            "organizationUserId": self.member_id,
+            "actingUserId": self.acting_user_id,
+            "providerId": null,
+            "installationId": null,
+            "date": self.event_date,
+            "deviceType": self.device_type,
+            "ipAddress": self.ip_address,
+        })
+    }
+
+    /// Store the event, unless the organization's plan does not include event logs.
+    pub async fn insert(&self, db: &crate::db::Db) -> Result<(), AppError> {
+        d1_query!(
+            db,
+            "INSERT INTO events (id, event_type, organization_id, user_id, acting_user_id, cipher_id, collection_id, member_id, policy_id, group_id, device_type, ip_address, event_date)
+             SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13
+             WHERE EXISTS (SELECT 1 FROM organizations WHERE id = ?3 AND use_events = 1)",
+            &self.id,
+            self.event_type,
+            &self.organization_id,
+            &self.user_id,
+            &self.acting_user_id,
+            &self.cipher_id,
+            &self.collection_id,
+            &self.member_id,
+            &self.policy_id,
+            &self.group_id,
+            self.device_type,
+            &self.ip_address,
+            &self.event_date
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+
+    /// Store the event, logging instead of failing: the audited action already happened.
+    pub async fn record(self, db: &crate::db::Db) {
+        if let Err(e) = self.insert(db).await {
+            log::warn!(
+                "Failed to record event {} for organization {}: {e}",
+                self.event_type,
+                self.organization_id
+            );
+        }
+    }
+
+    /// Record a login in every organization the user is a confirmed member of.
+    pub async fn record_login(db: &crate::db::Db, actor: &EventActor) {
+        let org_ids: Result<Vec<OrgIdRow>, _> = match d1_query!(
+            db,
+            "SELECT organization_id FROM users_organizations WHERE user_id = ?1 AND status = ?2",
+            &actor.user_id,
+            MembershipStatus::Confirmed as i32
+        ) {
+            Ok(query) => query.all().await.and_then(|r| r.results()),
+            Err(e) => Err(e),
+        };
+        let org_ids = match org_ids {
+            Ok(rows) => rows,
+            Err(e) => {
+                log::warn!("Failed to load organizations for login event: {e}");
+                return;
+            }
+        };
+
+        for row in org_ids {
+            let mut event = Self::new(EventType::UserLoggedIn, &row.organization_id, actor);
+            event.user_id = Some(actor.user_id.clone());
+            event.record(db).await;
+        }
+    }
+
+    /// Events of an organization in `[start, end]`, newest first.
+    ///
+    /// `before` is the continuation token of the previous page (the date of its last event).
+    pub async fn list_by_org(
+        db: &crate::db::Db,
+        organization_id: &str,
+        start: &str,
+        end: &str,
+        before: Option<&str>,
+        limit: u32,
+    ) -> Result<Vec<Self>, AppError> {
+        let (end, end_op) = match before {
+            Some(before) => (before, "<"),
+            None => (end, "<="),
+        };
+        let sql = format!(
+            "SELECT * FROM events
+             WHERE organization_id = ?1 AND event_date >= ?2 AND event_date {end_op} ?3
+             ORDER BY event_date DESC LIMIT ?4"
+        );
+        db.prepare(&sql)
+            .bind(&[
+                organization_id.into(),
+                start.into(),
+                end.into(),
+                limit.into(),
+            ])?
+            .all()
+            .await
+            .map_err(|_| AppError::Database)?
+            .results()
+            .map_err(|_| AppError::Database)
+    }
+
+    /// Events of an organization in `[start, end]` for the CSV export, newest first by
+    /// `(event_date, id)` so that no event is skipped between pages sharing a date.
+    ///
+    /// The page starts at `from` when `inclusive`, otherwise right after it.
+    pub async fn export_page(
+        db: &crate::db::Db,
+        organization_id: &str,
+        start: &str,
+        end: &str,
+        from: Option<&EventCursor>,
+        inclusive: bool,
+        limit: u32,
+    ) -> Result<Vec<EventExportRow>, AppError> {
+        let (condition, mut params) = export_range(organization_id, start, end, from, inclusive);
+        params.push(limit.into());
+        let sql = format!(
+            "SELECT e.*, au.email AS acting_user_email, u.email AS user_email FROM events e
+             LEFT JOIN users au ON au.id = e.acting_user_id
+             LEFT JOIN users u ON u.id = e.user_id
+             WHERE {condition}
+             ORDER BY e.event_date DESC, e.id DESC LIMIT ?{}",
+            params.len()
+        );
+        db.prepare(&sql)
+            .bind(&params)?
+            .all()
+            .await
+            .map_err(|_| AppError::Database)?
+            .results()
+            .map_err(|_| AppError::Database)
+    }
+
+    /// Position of the event `offset` events past `from` (inclusive) in the order of
+    /// [`Self::export_page`], or `None` when there are not that many.
+    pub async fn export_cursor_at(
+        db: &crate::db::Db,
+        organization_id: &str,
+        start: &str,
+        end: &str,
+        from: Option<&EventCursor>,
+        offset: u32,
+    ) -> Result<Option<EventCursor>, AppError> {
+        let (condition, mut params) = export_range(organization_id, start, end, from, true);
+        params.push(offset.into());
+        let sql = format!(
+            "SELECT e.event_date, e.id FROM events e
+             WHERE {condition}
+             ORDER BY e.event_date DESC, e.id DESC LIMIT 1 OFFSET ?{}",
+            params.len()
+        );
+        db.prepare(&sql)
+            .bind(&params)?
+            .first(None)
+            .await
+            .map_err(|_| AppError::Database)
+    }
+
+    /// Delete events older than `cutoff`. Returns the number of deleted rows.
+    pub async fn delete_before(db: &crate::db::Db, cutoff: &str) -> Result<u32, AppError> {
+        let result = d1_query!(db, "DELETE FROM events WHERE event_date < ?1", cutoff)
+            .map_err(|_| AppError::Database)?
+            .run()
+            .await
+            .map_err(|_| AppError::Database)?;
+
+        let changes = result
+            .meta()
+            .map_err(|_| AppError::Database)?
+            .and_then(|m| m.changes)
+            .unwrap_or(0) as u32;
+
+        Ok(changes)
+    }
+}
+
+/// Position of an event in the export order: `(event_date, id)`, newest first.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+pub struct EventCursor {
+    pub event_date: String,
+    pub id: String,
+}
+
+/// An exported event, with the emails of the users it names.
+#[derive(Debug, Deserialize)]
+pub struct EventExportRow {
+    pub id: String,
+    pub event_type: i32,
+    pub event_date: String,
+    pub acting_user_id: Option<String>,
+    pub acting_user_email: Option<String>,
+    pub user_id: Option<String>,
+    pub user_email: Option<String>,
+    pub member_id: Option<String>,
+    pub cipher_id: Option<String>,
+    pub collection_id: Option<String>,
+    pub group_id: Option<String>,
+    pub policy_id: Option<String>,
+    pub device_type: Option<i32>,
+    pub ip_address: Option<String>,
+}
+
+impl EventExportRow {
+    pub fn cursor(&self) -> EventCursor {
+        EventCursor {
+            event_date: self.event_date.clone(),
+            id: self.id.clone(),
+        }
+    }
+}
+
+/// `WHERE` condition and parameters selecting the exported events of `[start, end]`
+/// from `from` on.
+fn export_range(
+    organization_id: &str,
+    start: &str,
+    end: &str,
+    from: Option<&EventCursor>,
+    inclusive: bool,
+) -> (String, Vec<JsValue>) {
+    let mut params: Vec<JsValue> = vec![organization_id.into(), start.into(), end.into()];
+    let mut condition =
+        "e.organization_id = ?1 AND e.event_date >= ?2 AND e.event_date <= ?3".to_string();
+    if let Some(from) = from {
+        let id_op = if inclusive { "<=" } else { "<" };
+        condition.push_str(&format!(
+            " AND (e.event_date < ?4 OR (e.event_date = ?4 AND e.id {id_op} ?5))"
+        ));
+        params.push(from.event_date.as_str().into());
+        params.push(from.id.as_str().into());
+    }
+    (condition, params)
+}
@@ -0,0 +1,436 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+use worker::D1PreparedStatement;
+
+use crate::d1_query;
+use crate::models::collection::CollectionAccessData;
+use crate::{db, error::AppError};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Group {
+    pub id: String,
+    pub organization_id: String,
+    pub name: String,
+    pub external_id: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// A group's access to one collection (`collections_groups` row).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupAccess {
+    pub group_id: String,
+    pub collection_id: String,
+    pub read_only: i32,
+    pub hide_passwords: i32,
+    pub manage: i32,
+}
+
+#[derive(Deserialize)]
+struct GroupIdRow {
+    group_id: String,
+}
+
+#[derive(Deserialize)]
+struct MembershipIdRow {
+    membership_id: String,
+}
+
+impl Group {
+    pub fn new(organization_id: String, name: String, external_id: Option<String>) -> Self {
+        let now = db::now_string();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            organization_id,
+            name,
+            external_id,
+            created_at: now.clone(),
+            updated_at: now,
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": &self.id,
+            "organizationId": &self.organization_id,
+            "name": &self.name,
+            "externalId": &self.external_id,
+            "accessAll": false,
+            "object": "group"
+        })
+    }
+
+    /// Group with its collection assignments (`groupDetails`).
+    pub fn to_details_json(&self, collections: &[GroupAccess]) -> Value {
+        json!({
+            "id": &self.id,
+            "organizationId": &self.organization_id,
+            "name": &self.name,
+            "externalId": &self.external_id,
+            "accessAll": false,
+            "collections": collections.iter().map(GroupAccess::to_group_json).collect::<Vec<_>>(),
+            "object": "groupDetails"
+        })
+    }
+
+    pub async fn find_by_id_and_org(
+        db: &crate::db::Db,
+        id: &str,
+        organization_id: &str,
+    ) -> Result<Option<Self>, AppError> {
+        d1_query!(
+            db,
+            "SELECT * FROM groups WHERE id = ?1 AND organization_id = ?2",
+            id,
+            organization_id
+        )
+        .map_err(|_| AppError::Database)?
+        .first(None)
+        .await
+        .map_err(|_| AppError::Database)
+    }
+
+    pub async fn list_by_org(
+        db: &crate::db::Db,
+        organization_id: &str,
+    ) -> Result<Vec<Self>, AppError> {
+        d1_query!(
+            db,
+            "SELECT * FROM groups WHERE organization_id = ?1 ORDER BY created_at",
+            organization_id
+        )
+        .map_err(|_| AppError::Database)?
+        .all()
+        .await
+        .map_err(|_| AppError::Database)?
+        .results()
+        .map_err(|_| AppError::Database)
+    }
+
+    pub async fn insert(&self, db: &crate::db::Db) -> Result<(), AppError> {
+        d1_query!(
+            db,
+            "INSERT INTO groups (id, organization_id, name, external_id, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            &self.id,
+            &self.organization_id,
+            &self.name,
+            self.external_id.as_deref(),
+            &self.created_at,
+            &self.updated_at
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+
+    pub async fn update(&mut self, db: &crate::db::Db) -> Result<(), AppError> {
+        self.updated_at = db::now_string();
+        d1_query!(
+            db,
+            "UPDATE groups SET name = ?1, external_id = ?2, updated_at = ?3 WHERE id = ?4",
+            &self.name,
+            self.external_id.as_deref(),
+            &self.updated_at,
+            &self.id
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+
+    /// Delete the group; member and collection assignments cascade.
+    pub async fn delete(&self, db: &crate::db::Db) -> Result<(), AppError> {
+        d1_query!(db, "DELETE FROM groups WHERE id = ?1", &self.id)
+            .map_err(|_| AppError::Database)?
+            .run()
+            .await
+            .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+
+    /// Ids from `ids` that are not groups of `organization_id`.
+    pub async fn foreign_ids(
+        db: &crate::db::Db,
+        organization_id: &str,
+        ids: &[String],
+    ) -> Result<Vec<String>, AppError> {
+        let known: Vec<String> = Self::list_by_org(db, organization_id)
+            .await?
+            .into_iter()
+            .map(|g| g.id)
+            .collect();
+        Ok(ids
+            .iter()
+            .filter(|id| !known.contains(id))
+            .cloned()
+            .collect())
+    }
+
+    /// Membership ids of the group's members.
+    pub async fn member_ids(db: &crate::db::Db, group_id: &str) -> Result<Vec<String>, AppError> {
+        let rows: Vec<MembershipIdRow> = d1_query!(
+            db,
+            "SELECT membership_id FROM groups_users WHERE group_id = ?1",
+            group_id
+        )
+        .map_err(|_| AppError::Database)?
+        .all()
+        .await
+        .map_err(|_| AppError::Database)?
+        .results()
+        .map_err(|_| AppError::Database)?;
+        Ok(rows.into_iter().map(|r| r.membership_id).collect())
+    }
+
+    /// Ids of the groups a member belongs to.
+    pub async fn ids_for_membership(
+        db: &crate::db::Db,
+        membership_id: &str,
+    ) -> Result<Vec<String>, AppError> {
+        let rows: Vec<GroupIdRow> = d1_query!(
+            db,
+            "SELECT group_id FROM groups_users WHERE membership_id = ?1",
+            membership_id
+        )
+        .map_err(|_| AppError::Database)?
+        .all()
+        .await
+        .map_err(|_| AppError::Database)?
+        .results()
+        .map_err(|_| AppError::Database)?;
+        Ok(rows.into_iter().map(|r| r.group_id).collect())
+    }
+
+    /// Replace all `groups_users` rows matching `scope` (`group_id = ?1` or
+    /// `membership_id = ?1`) with `pairs` of `(group_id, membership_id)`.
+    async fn replace_members(
+        db: &crate::db::Db,
+        scope: &str,
+        scope_id: &str,
+        pairs: &[(&str, &str)],
+    ) -> Result<(), AppError> {
+        let mut statements = vec![d1_query!(
+            db,
+            &format!("DELETE FROM groups_users WHERE {scope} = ?1"),
+            scope_id
+        )
+        .map_err(|_| AppError::Database)?];
+        for (group_id, membership_id) in pairs {
+            statements.push(
+                d1_query!(
+                    db,
+                    "INSERT OR IGNORE INTO groups_users (group_id, membership_id) VALUES (?1, ?2)",
+                    group_id,
+                    membership_id
+                )
+                .map_err(|_| AppError::Database)?,
+            );
+        }
+        db.batch(statements).await?;
+        Ok(())
+    }
+
+    pub async fn set_members(
+        db: &crate::db::Db,
+        group_id: &str,
+        membership_ids: &[String],
+    ) -> Result<(), AppError> {
+        let pairs: Vec<(&str, &str)> = membership_ids
+            .iter()
+            .map(|m| (group_id, m.as_str()))
+            .collect();
+        Self::replace_members(db, "group_id", group_id, &pairs).await
+    }
+
+    pub async fn set_for_membership(
+        db: &crate::db::Db,
+        membership_id: &str,
+        group_ids: &[String],
+    ) -> Result<(), AppError> {
+        let pairs: Vec<(&str, &str)> = group_ids
+            .iter()
+            .map(|g| (g.as_str(), membership_id))
+            .collect();
+        Self::replace_members(db, "membership_id", membership_id, &pairs).await
+    }
+
+    pub async fn remove_member(
+        db: &crate::db::Db,
+        group_id: &str,
+        membership_id: &str,
+    ) -> Result<(), AppError> {
+        d1_query!(
+            db,
+            "DELETE FROM groups_users WHERE group_id = ?1 AND membership_id = ?2",
+            group_id,
+            membership_id
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+}
+
+impl GroupAccess {
+    /// Entry of a collection's `groups` list (keyed by group id).
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": &self.group_id,
+            "readOnly": self.read_only != 0,
+            "hidePasswords": self.hide_passwords != 0,
+            "manage": self.manage != 0
+        })
+    }
+
+    /// Entry of a group's `collections` list (keyed by collection id).
+    pub fn to_group_json(&self) -> Value {
+        json!({
+            "id": &self.collection_id,
+            "readOnly": self.read_only != 0,
+            "hidePasswords": self.hide_passwords != 0,
+            "manage": self.manage != 0
+        })
+    }
+
+    pub fn from_data(data: CollectionAccessData, group_id: &str, collection_id: &str) -> Self {
+        Self {
+            group_id: group_id.to_string(),
+            collection_id: collection_id.to_string(),
+            read_only: data.read_only as i32,
+            hide_passwords: data.hide_passwords as i32,
+            manage: data.manage as i32,
+        }
+    }
+
+    pub async fn list_by_collection(
+        db: &crate::db::Db,
+        collection_id: &str,
+    ) -> Result<Vec<Self>, AppError> {
+        d1_query!(
+            db,
+            "SELECT * FROM collections_groups WHERE collection_id = ?1",
+            collection_id
+        )
+        .map_err(|_| AppError::Database)?
+        .all()
+        .await
+        .map_err(|_| AppError::Database)?
+        .results()
+        .map_err(|_| AppError::Database)
+    }
+
+    pub async fn list_by_group(db: &crate::db::Db, group_id: &str) -> Result<Vec<Self>, AppError> {
+        d1_query!(
+            db,
+            "SELECT * FROM collections_groups WHERE group_id = ?1",
+            group_id
+        )
+        .map_err(|_| AppError::Database)?
+        .all()
+        .await
+        .map_err(|_| AppError::Database)?
+        .results()
+        .map_err(|_| AppError::Database)
+    }
+
+    /// Statements replacing all assignments matching `scope` (`collection_id = ?1` or
+    /// `group_id = ?1`) with `entries`.
+    fn replace_statements(
+        db: &crate::db::Db,
+        scope: &str,
+        scope_id: &str,
+        entries: &[GroupAccess],
+    ) -> Result<Vec<D1PreparedStatement>, AppError> {
+        let mut statements = vec![d1_query!(
+            db,
+            &format!("DELETE FROM collections_groups WHERE {scope} = ?1"),
+            scope_id
+        )
+        .map_err(|_| AppError::Database)?];
+        for entry in entries {
+            statements.push(
+                d1_query!(
+                    db,
+                    "INSERT OR REPLACE INTO collections_groups (collection_id, group_id, read_only, hide_passwords, manage)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    &entry.collection_id,
+                    &entry.group_id,
+                    entry.read_only,
+                    entry.hide_passwords,
+                    entry.manage
+                )
+                .map_err(|_| AppError::Database)?,
+            );
+        }
+        Ok(statements)
+    }
+
+    /// Statements replacing a collection's group assignments, for callers that batch
+    /// them with other writes.
+    pub fn replace_for_collection_statements(
+        db: &crate::db::Db,
+        collection_id: &str,
+        entries: &[GroupAccess],
+    ) -> Result<Vec<D1PreparedStatement>, AppError> {
+        Self::replace_statements(db, "collection_id", collection_id, entries)
+    }
+
+    pub async fn replace_for_collection(
+        db: &crate::db::Db,
+        collection_id: &str,
+        entries: &[GroupAccess],
+    ) -> Result<(), AppError> {
+        db.batch(Self::replace_for_collection_statements(
+            db,
+            collection_id,
+            entries,
+        )?)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn replace_for_group(
+        db: &crate::db::Db,
+        group_id: &str,
+        entries: &[GroupAccess],
+    ) -> Result<(), AppError> {
+        db.batch(Self::replace_statements(db, "group_id", group_id, entries)?)
+            .await?;
+        Ok(())
+    }
+}
+
+// ── Request payloads ────────────────────────────────────────────────
+
+/// POST/PUT /api/organizations/{org_id}/groups[/{id}]
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupRequest {
+    pub name: String,
+    pub external_id: Option<String>,
+    #[serde(default)]
+    pub collections: Vec<CollectionAccessData>,
+    /// Membership ids; replaces the group's members when present.
+    pub users: Option<Vec<String>>,
+}
+
+/// PUT /api/organizations/{org_id}/users/{member_id}/groups
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemberGroupsRequest {
+    pub group_ids: Vec<String>,
+}
+
+/// DELETE /api/organizations/{org_id}/groups
+#[derive(Debug, Deserialize)]
+pub struct BulkGroupIds {
+    pub ids: Vec<String>,
+}
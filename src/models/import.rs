@@ -29,7 +29,43 @@ pub struct FolderRelationship {
 #[serde(rename_all = "camelCase")]
 pub struct ImportRequest {
     pub ciphers: Vec<CipherRequestData>,
+    #[serde(default)]
     pub folders: Vec<ImportFolder>,
     #[serde(default)]
     pub folder_relationships: Vec<FolderRelationship>,
 }
+
+/// Collection data structure for organization import requests.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportCollection {
+    /// Optional collection ID - if provided and it belongs to the organization, it is reused
+    #[serde(default, deserialize_with = "super::deser_opt_nonempty_str")]
+    pub id: Option<String>,
+    pub name: String,
+    #[serde(default, deserialize_with = "super::deser_opt_nonempty_str")]
+    pub external_id: Option<String>,
+}
+
+/// Relationship between cipher index and collection index in the import arrays.
+/// A cipher may appear in several relationships, one per collection.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectionRelationship {
+    /// Cipher index in the ciphers array
+    pub key: usize,
+    /// Collection index in the collections array
+    pub value: usize,
+}
+
+/// Organization import request payload structure.
+/// Aligned with vaultwarden's ImportData used in POST /ciphers/import-organization.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct OrganizationImportRequest {
+    pub ciphers: Vec<CipherRequestData>,
+    #[serde(default)]
+    pub collections: Vec<ImportCollection>,
+    #[serde(default)]
+    pub collection_relationships: Vec<CollectionRelationship>,
+}
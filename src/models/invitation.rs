@@ -0,0 +1,44 @@
+use crate::d1_query;
+use crate::{db, error::AppError};
+
+/// An email address an admin invited to register.
+pub struct Invitation;
+
+impl Invitation {
+    pub async fn save(db: &crate::db::Db, email: &str) -> Result<(), AppError> {
+        d1_query!(
+            db,
+            "INSERT INTO invitations (email, invited_at) VALUES (?1, ?2)
+             ON CONFLICT(email) DO UPDATE SET invited_at = excluded.invited_at",
+            email,
+            db::now_string()
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+
+    pub async fn exists(db: &crate::db::Db, email: &str) -> Result<bool, AppError> {
+        let found: Option<i64> = d1_query!(
+            db,
+            "SELECT 1 AS found FROM invitations WHERE email = ?1",
+            email
+        )
+        .map_err(|_| AppError::Database)?
+        .first(Some("found"))
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(found.is_some())
+    }
+
+    pub async fn delete(db: &crate::db::Db, email: &str) -> Result<(), AppError> {
+        d1_query!(db, "DELETE FROM invitations WHERE email = ?1", email)
+            .map_err(|_| AppError::Database)?
+            .run()
+            .await
+            .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+}
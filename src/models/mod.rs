@@ -1,10 +1,23 @@
 pub mod attachment;
 pub mod auth_request;
 pub mod cipher;
+pub mod collection;
 pub mod device;
+pub mod emergency_access;
+pub mod event;
 pub mod folder;
+pub mod group;
 pub mod import;
+pub mod invitation;
+pub mod multipart_upload;
+pub mod org_domain;
+pub mod organization;
+pub mod passkey;
+pub mod policy;
+pub mod refresh_token;
+pub mod registration_token;
 pub mod send;
+pub mod sso_user;
 pub mod sync;
 pub mod twofactor;
 pub mod user;
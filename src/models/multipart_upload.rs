@@ -0,0 +1,126 @@
+use serde::{Deserialize, Serialize};
+
+use crate::d1_query;
+use crate::error::AppError;
+
+/// `target` of a session uploading a pending attachment (`parent_id` is the cipher).
+pub const TARGET_ATTACHMENT: &str = "attachment";
+/// `target` of a session uploading the file of a pending Send (`parent_id` is the Send).
+pub const TARGET_SEND: &str = "send";
+
+/// How long a session can be used; older ones are aborted by the
+/// `stale_multipart_uploads` job.
+pub const SESSION_LIFETIME_HOURS: i64 = 24;
+
+/// Most parts R2 accepts in one multipart upload.
+const MAX_PARTS: i64 = 10_000;
+
+/// An R2 multipart upload of one large file, split in parts of `part_size` bytes
+/// (the last one may be shorter).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MultipartUpload {
+    pub id: String,
+    /// R2's ID of the upload.
+    pub upload_id: String,
+    pub storage_key: String,
+    pub user_id: String,
+    pub target: String,
+    pub parent_id: String,
+    /// Attachment ID or Send file ID.
+    pub file_id: String,
+    pub file_size: i64,
+    pub part_size: i64,
+    pub created_at: String,
+}
+
+impl MultipartUpload {
+    /// Number of parts the file is split in, or `None` when R2 would not accept that many.
+    pub fn part_count_for(file_size: i64, part_size: i64) -> Option<i64> {
+        let count = (file_size + part_size - 1) / part_size;
+        (1..=MAX_PARTS).contains(&count).then_some(count)
+    }
+
+    pub fn part_count(&self) -> i64 {
+        (self.file_size + self.part_size - 1) / self.part_size
+    }
+
+    /// Length part `number` (from 1) must have, or `None` when there is no such part.
+    pub fn part_length(&self, number: i64) -> Option<i64> {
+        let count = self.part_count();
+        if !(1..=count).contains(&number) {
+            return None;
+        }
+        Some(if number == count {
+            self.file_size - self.part_size * (count - 1)
+        } else {
+            self.part_size
+        })
+    }
+
+    pub async fn insert(&self, db: &crate::db::Db) -> Result<(), AppError> {
+        d1_query!(
+            db,
+            "INSERT INTO multipart_uploads (id, upload_id, storage_key, user_id, target, parent_id, file_id, file_size, part_size, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            self.id,
+            self.upload_id,
+            self.storage_key,
+            self.user_id,
+            self.target,
+            self.parent_id,
+            self.file_id,
+            self.file_size,
+            self.part_size,
+            self.created_at
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+
+    pub async fn find_for_user(
+        db: &crate::db::Db,
+        id: &str,
+        user_id: &str,
+    ) -> Result<Option<Self>, AppError> {
+        d1_query!(
+            db,
+            "SELECT * FROM multipart_uploads WHERE id = ?1 AND user_id = ?2",
+            id,
+            user_id
+        )
+        .map_err(|_| AppError::Database)?
+        .first(None)
+        .await
+        .map_err(|_| AppError::Database)
+    }
+
+    /// Sessions started before `cutoff`, oldest first.
+    pub async fn find_created_before(
+        db: &crate::db::Db,
+        cutoff: &str,
+    ) -> Result<Vec<Self>, AppError> {
+        d1_query!(
+            db,
+            "SELECT * FROM multipart_uploads WHERE created_at < ?1 ORDER BY created_at",
+            cutoff
+        )
+        .map_err(|_| AppError::Database)?
+        .all()
+        .await
+        .map_err(|_| AppError::Database)?
+        .results()
+        .map_err(|_| AppError::Database)
+    }
+
+    pub async fn delete(db: &crate::db::Db, id: &str) -> Result<(), AppError> {
+        d1_query!(db, "DELETE FROM multipart_uploads WHERE id = ?1", id)
+            .map_err(|_| AppError::Database)?
+            .run()
+            .await
+            .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,202 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::d1_query;
+use crate::{db, error::AppError};
+
+/// A domain claimed by an organization. It counts as the organization's once the
+/// domain has a TXT record with `txt_record` as its value.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrganizationDomain {
+    pub id: String,
+    pub organization_id: String,
+    pub domain_name: String,
+    pub txt_record: String,
+    pub verified_at: Option<String>,
+    pub last_checked_at: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl OrganizationDomain {
+    pub fn new(organization_id: String, domain_name: String, txt_record: String) -> Self {
+        let now = db::now_string();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            organization_id,
+            domain_name,
+            txt_record,
+            verified_at: None,
+            last_checked_at: None,
+            created_at: now.clone(),
+            updated_at: now,
+        }
+    }
+
+    pub fn is_verified(&self) -> bool {
+        self.verified_at.is_some()
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": &self.id,
+            "organizationId": &self.organization_id,
+            "txt": &self.txt_record,
+            "domainName": &self.domain_name,
+            "creationDate": &self.created_at,
+            // Domains are checked when the admin asks, not on a schedule.
+            "nextRunDate": Value::Null,
+            "jobRunCount": 0,
+            "verifiedDate": &self.verified_at,
+            "lastCheckedDate": &self.last_checked_at,
+            "object": "organizationDomain"
+        })
+    }
+
+    /// SSO details of a verified domain (`organizationDomainSsoDetails`). SSO is never
+    /// required; when it is available the organization ID is the SSO identifier.
+    pub fn to_sso_details_json(&self, sso_available: bool) -> Value {
+        json!({
+            "ssoAvailable": sso_available,
+            "ssoRequired": false,
+            "domainName": &self.domain_name,
+            "organizationIdentifier": sso_available.then_some(&self.organization_id),
+            "verifiedDate": &self.verified_at,
+            "object": "organizationDomainSsoDetails"
+        })
+    }
+
+    pub async fn find_by_id_and_org(
+        db: &crate::db::Db,
+        id: &str,
+        organization_id: &str,
+    ) -> Result<Option<Self>, AppError> {
+        d1_query!(
+            db,
+            "SELECT * FROM organization_domains WHERE id = ?1 AND organization_id = ?2",
+            id,
+            organization_id
+        )
+        .map_err(|_| AppError::Database)?
+        .first(None)
+        .await
+        .map_err(|_| AppError::Database)
+    }
+
+    pub async fn find_by_org_and_name(
+        db: &crate::db::Db,
+        organization_id: &str,
+        domain_name: &str,
+    ) -> Result<Option<Self>, AppError> {
+        d1_query!(
+            db,
+            "SELECT * FROM organization_domains WHERE organization_id = ?1 AND domain_name = ?2",
+            organization_id,
+            domain_name
+        )
+        .map_err(|_| AppError::Database)?
+        .first(None)
+        .await
+        .map_err(|_| AppError::Database)
+    }
+
+    /// The verified claim on `domain_name`; at most one organization can hold it.
+    pub async fn find_verified_by_name(
+        db: &crate::db::Db,
+        domain_name: &str,
+    ) -> Result<Option<Self>, AppError> {
+        d1_query!(
+            db,
+            "SELECT * FROM organization_domains WHERE domain_name = ?1 AND verified_at IS NOT NULL",
+            domain_name
+        )
+        .map_err(|_| AppError::Database)?
+        .first(None)
+        .await
+        .map_err(|_| AppError::Database)
+    }
+
+    pub async fn list_by_org(
+        db: &crate::db::Db,
+        organization_id: &str,
+    ) -> Result<Vec<Self>, AppError> {
+        d1_query!(
+            db,
+            "SELECT * FROM organization_domains WHERE organization_id = ?1 ORDER BY domain_name",
+            organization_id
+        )
+        .map_err(|_| AppError::Database)?
+        .all()
+        .await
+        .map_err(|_| AppError::Database)?
+        .results()
+        .map_err(|_| AppError::Database)
+    }
+
+    pub async fn insert(&self, db: &crate::db::Db) -> Result<(), AppError> {
+        d1_query!(
+            db,
+            "INSERT INTO organization_domains (id, organization_id, domain_name, txt_record, verified_at, last_checked_at, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            &self.id,
+            &self.organization_id,
+            &self.domain_name,
+            &self.txt_record,
+            self.verified_at.as_deref(),
+            self.last_checked_at.as_deref(),
+            &self.created_at,
+            &self.updated_at
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+
+    /// Store the outcome of a verification attempt.
+    pub async fn update_check(&mut self, db: &crate::db::Db) -> Result<(), AppError> {
+        self.updated_at = db::now_string();
+        d1_query!(
+            db,
+            "UPDATE organization_domains SET verified_at = ?1, last_checked_at = ?2, updated_at = ?3 WHERE id = ?4",
+            self.verified_at.as_deref(),
+            self.last_checked_at.as_deref(),
+            &self.updated_at,
+            &self.id
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+
+    pub async fn delete(&self, db: &crate::db::Db) -> Result<(), AppError> {
+        d1_query!(
+            db,
+            "DELETE FROM organization_domains WHERE id = ?1",
+            &self.id
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+}
+
+/// POST /api/organizations/{org_id}/domain
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrganizationDomainRequest {
+    pub domain_name: String,
+}
+
+/// POST /api/organizations/domain/sso/details and .../verified
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DomainSsoDetailsRequest {
+    pub email: String,
+}
@@ -0,0 +1,1105 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+use worker::D1PreparedStatement;
+
+use crate::d1_query;
+use crate::models::collection::CollectionAccessData;
+use crate::models::user::bool_from_int;
+use crate::{db, error::AppError};
+
+/// Membership lifecycle: invited -> accepted (by the user) -> confirmed (by an admin).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum MembershipStatus {
+    Invited = 0,
+    Accepted = 1,
+    Confirmed = 2,
+}
+
+/// Membership roles, using Bitwarden's numeric values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum MembershipType {
+    Owner = 0,
+    Admin = 1,
+    User = 2,
+    Manager = 3,
+    Custom = 4,
+}
+
+impl MembershipType {
+    pub fn from_i32(value: i32) -> Option<Self> {
+        match value {
+            0 => Some(MembershipType::Owner),
+            1 => Some(MembershipType::Admin),
+            2 => Some(MembershipType::User),
+            3 => Some(MembershipType::Manager),
+            4 => Some(MembershipType::Custom),
+            _ => None,
+        }
+    }
+
+    /// Privilege rank; the numeric type values are not ordered by privilege.
+    fn rank(self) -> u8 {
+        match self {
+            MembershipType::Owner => 3,
+            MembershipType::Admin => 2,
+            MembershipType::Manager => 1,
+            MembershipType::User | MembershipType::Custom => 0,
+        }
+    }
+
+    pub fn is_at_least(self, other: MembershipType) -> bool {
+        self.rank() >= other.rank()
+    }
+}
+
+/// Permissions a `Custom` member can be granted, stored as bits of
+/// `users_organizations.permissions`. Owners and admins hold all of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i64)]
+pub enum Permission {
+    AccessEventLogs = 1,
+    AccessImportExport = 1 << 1,
+    AccessReports = 1 << 2,
+    CreateNewCollections = 1 << 3,
+    EditAnyCollection = 1 << 4,
+    DeleteAnyCollection = 1 << 5,
+    ManageGroups = 1 << 6,
+    ManagePolicies = 1 << 7,
+    ManageSso = 1 << 8,
+    ManageUsers = 1 << 9,
+    ManageResetPassword = 1 << 10,
+    ManageScim = 1 << 11,
+}
+
+impl Permission {
+    pub const ALL: &'static [Permission] = &[
+        Permission::AccessEventLogs,
+        Permission::AccessImportExport,
+        Permission::AccessReports,
+        Permission::CreateNewCollections,
+        Permission::EditAnyCollection,
+        Permission::DeleteAnyCollection,
+        Permission::ManageGroups,
+        Permission::ManagePolicies,
+        Permission::ManageSso,
+        Permission::ManageUsers,
+        Permission::ManageResetPassword,
+        Permission::ManageScim,
+    ];
+
+    /// Key in the clients' `permissions` object.
+    pub fn json_name(self) -> &'static str {
+        match self {
+            Permission::AccessEventLogs => "accessEventLogs",
+            Permission::AccessImportExport => "accessImportExport",
+            Permission::AccessReports => "accessReports",
+            Permission::CreateNewCollections => "createNewCollections",
+            Permission::EditAnyCollection => "editAnyCollection",
+            Permission::DeleteAnyCollection => "deleteAnyCollection",
+            Permission::ManageGroups => "manageGroups",
+            Permission::ManagePolicies => "managePolicies",
+            Permission::ManageSso => "manageSso",
+            Permission::ManageUsers => "manageUsers",
+            Permission::ManageResetPassword => "manageResetPassword",
+            Permission::ManageScim => "manageScim",
+        }
+    }
+
+    /// Bits of the permissions set to `true` in a client `permissions` object.
+    pub fn bits_from_json(value: &Value) -> i64 {
+        Permission::ALL
+            .iter()
+            .filter(|p| value.get(p.json_name()).and_then(Value::as_bool) == Some(true))
+            .fold(0, |bits, p| bits | *p as i64)
+    }
+
+    /// Client `permissions` object for a bitset.
+    pub fn bits_to_json(bits: i64) -> Value {
+        Value::Object(
+            Permission::ALL
+                .iter()
+                .map(|p| {
+                    (
+                        p.json_name().to_string(),
+                        Value::Bool(bits & *p as i64 != 0),
+                    )
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Optional features an organization's plan can leave out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrgFeature {
+    Groups,
+    Events,
+    Policies,
+}
+
+impl OrgFeature {
+    pub fn name(self) -> &'static str {
+        match self {
+            OrgFeature::Groups => "groups",
+            OrgFeature::Events => "event logs",
+            OrgFeature::Policies => "policies",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Organization {
+    pub id: String,
+    pub name: String,
+    pub billing_email: String,
+    pub private_key: Option<String>,
+    pub public_key: Option<String>,
+    /// Seat limit of the plan; `None` is unlimited.
+    #[serde(default)]
+    pub seats: Option<i64>,
+    #[serde(default = "enabled", with = "bool_from_int")]
+    pub use_groups: bool,
+    #[serde(default = "enabled", with = "bool_from_int")]
+    pub use_events: bool,
+    #[serde(default = "enabled", with = "bool_from_int")]
+    pub use_policies: bool,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+impl Organization {
+    pub fn new(name: String, billing_email: String) -> Self {
+        let now = db::now_string();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            name,
+            billing_email,
+            private_key: None,
+            public_key: None,
+            seats: None,
+            use_groups: true,
+            use_events: true,
+            use_policies: true,
+            created_at: now.clone(),
+            updated_at: now,
+        }
+    }
+
+    pub fn has_feature(&self, feature: OrgFeature) -> bool {
+        match feature {
+            OrgFeature::Groups => self.use_groups,
+            OrgFeature::Events => self.use_events,
+            OrgFeature::Policies => self.use_policies,
+        }
+    }
+
+    /// Refuse requests for a feature the organization's plan does not include.
+    pub fn ensure_feature(&self, feature: OrgFeature) -> Result<(), AppError> {
+        if !self.has_feature(feature) {
+            return Err(AppError::BadRequest(format!(
+                "The organization's plan does not include {}",
+                feature.name()
+            )));
+        }
+        Ok(())
+    }
+
+    /// The plan as shown and set by the admin API.
+    pub fn plan(&self) -> OrganizationPlan {
+        OrganizationPlan {
+            seats: self.seats,
+            use_groups: self.use_groups,
+            use_events: self.use_events,
+            use_policies: self.use_policies,
+        }
+    }
+
+    pub fn has_keys(&self) -> bool {
+        self.private_key.is_some() && self.public_key.is_some()
+    }
+
+    /// Organization details (GET /api/organizations/{id}).
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": &self.id,
+            "name": &self.name,
+            "businessName": &self.name,
+            "billingEmail": &self.billing_email,
+            "seats": self.seats,
+            "maxCollections": Value::Null,
+            "maxStorageGb": i16::MAX,
+            "planType": 6, // Enterprise (annually); the use* flags below decide what clients show
+            "usersGetPremium": true,
+            "use2fa": true,
+            "useCustomPermissions": true,
+            "useDirectory": false,
+            "useEvents": self.use_events,
+            "useGroups": self.use_groups,
+            "useTotp": true,
+            "usePolicies": self.use_policies,
+            "useScim": false,
+            "useSso": false,
+            "useKeyConnector": false,
+            "usePasswordManager": true,
+            "useSecretsManager": false,
+            "useResetPassword": true,
+            "useApi": false,
+            "useOrganizationDomains": true,
+            "selfHost": true,
+            "hasPublicAndPrivateKeys": self.has_keys(),
+            "allowAdminAccessToAllCollectionItems": true,
+            "limitCollectionCreation": true,
+            "limitCollectionDeletion": true,
+            "object": "organization"
+        })
+    }
+
+    pub async fn find_by_id(db: &crate::db::Db, id: &str) -> Result<Option<Self>, AppError> {
+        let row: Option<Value> = d1_query!(db, "SELECT * FROM organizations WHERE id = ?1", id)
+            .map_err(|_| AppError::Database)?
+            .first(None)
+            .await
+            .map_err(|_| AppError::Database)?;
+
+        row.map(|row| serde_json::from_value(row).map_err(|_| AppError::Internal))
+            .transpose()
+    }
+
+    pub async fn insert(&self, db: &crate::db::Db) -> Result<(), AppError> {
+        d1_query!(
+            db,
+            "INSERT INTO organizations (id, name, billing_email, private_key, public_key, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            &self.id,
+            &self.name,
+            &self.billing_email,
+            self.private_key.as_deref(),
+            self.public_key.as_deref(),
+            &self.created_at,
+            &self.updated_at
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+
+    /// Replace the organization's plan.
+    pub async fn update_plan(
+        &mut self,
+        db: &crate::db::Db,
+        plan: &OrganizationPlan,
+    ) -> Result<(), AppError> {
+        self.seats = plan.seats;
+        self.use_groups = plan.use_groups;
+        self.use_events = plan.use_events;
+        self.use_policies = plan.use_policies;
+        self.updated_at = db::now_string();
+        d1_query!(
+            db,
+            "UPDATE organizations SET seats = ?1, use_groups = ?2, use_events = ?3, use_policies = ?4, updated_at = ?5
+             WHERE id = ?6",
+            self.seats,
+            self.use_groups as i32,
+            self.use_events as i32,
+            self.use_policies as i32,
+            &self.updated_at,
+            &self.id
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+
+    pub async fn update(&mut self, db: &crate::db::Db) -> Result<(), AppError> {
+        self.updated_at = db::now_string();
+        d1_query!(
+            db,
+            "UPDATE organizations SET name = ?1, billing_email = ?2, private_key = ?3, public_key = ?4, updated_at = ?5
+             WHERE id = ?6",
+            &self.name,
+            &self.billing_email,
+            self.private_key.as_deref(),
+            self.public_key.as_deref(),
+            &self.updated_at,
+            &self.id
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+
+    /// Delete the organization and its ciphers. Memberships cascade via foreign key.
+    ///
+    /// Attachment blobs must be removed from storage by the caller beforehand.
+    pub async fn delete(&self, db: &crate::db::Db) -> Result<(), AppError> {
+        db.batch(vec![
+            d1_query!(
+                db,
+                "DELETE FROM ciphers WHERE organization_id = ?1",
+                &self.id
+            )
+            .map_err(|_| AppError::Database)?,
+            d1_query!(db, "DELETE FROM organizations WHERE id = ?1", &self.id)
+                .map_err(|_| AppError::Database)?,
+        ])
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Membership {
+    pub id: String,
+    pub organization_id: String,
+    pub user_id: Option<String>,
+    pub email: String,
+    pub akey: Option<String>,
+    pub status: i32,
+    #[serde(rename = "type")]
+    pub r#type: i32,
+    /// User key encrypted with the org public key, set while enrolled in account recovery.
+    #[serde(default)]
+    pub reset_password_key: Option<String>,
+    /// [`Permission`] bits; only meaningful for `Custom` members.
+    #[serde(default)]
+    pub permissions: i64,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl Membership {
+    pub fn new(
+        organization_id: String,
+        user_id: Option<String>,
+        email: String,
+        r#type: MembershipType,
+        status: MembershipStatus,
+    ) -> Self {
+        let now = db::now_string();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            organization_id,
+            user_id,
+            email,
+            akey: None,
+            status: status as i32,
+            r#type: r#type as i32,
+            reset_password_key: None,
+            permissions: 0,
+            created_at: now.clone(),
+            updated_at: now,
+        }
+    }
+
+    pub fn membership_type(&self) -> MembershipType {
+        MembershipType::from_i32(self.r#type).unwrap_or(MembershipType::User)
+    }
+
+    pub fn is_confirmed(&self) -> bool {
+        self.status == MembershipStatus::Confirmed as i32
+    }
+
+    /// Whether the member holds `permission`, through an admin role or a custom grant.
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        match self.membership_type() {
+            MembershipType::Owner | MembershipType::Admin => true,
+            MembershipType::Custom => self.permissions & permission as i64 != 0,
+            MembershipType::Manager | MembershipType::User => false,
+        }
+    }
+
+    /// Member entry (GET /api/organizations/{org_id}/users).
+    pub fn to_details_json(
+        &self,
+        user: Option<&MemberUser>,
+        two_factor_enabled: bool,
+        collections: Vec<Value>,
+        groups: Vec<String>,
+    ) -> Value {
+        json!({
+            "id": &self.id,
+            "userId": &self.user_id,
+            "name": user.and_then(|u| u.name.as_deref()),
+            "email": &self.email,
+            "avatarColor": user.and_then(|u| u.avatar_color.as_deref()),
+            "externalId": Value::Null,
+            "groups": groups,
+            "collections": collections,
+            "status": self.status,
+            "type": self.r#type,
+            "accessAll": self.membership_type().is_at_least(MembershipType::Admin),
+            "twoFactorEnabled": two_factor_enabled,
+            "resetPasswordEnrolled": self.reset_password_key.is_some(),
+            "hasMasterPassword": true,
+            "permissions": Permission::bits_to_json(self.permissions),
+            "ssoBound": false,
+            "usesKeyConnector": false,
+            "accessSecretsManager": false,
+            "managedByOrganization": false,
+            "claimedByOrganization": false,
+            "object": "organizationUserUserDetails"
+        })
+    }
+
+    /// Organization entry inside the user's profile (`profile.organizations` in sync).
+    pub fn to_profile_json(&self, org: &Organization) -> Value {
+        let mut json = json!({
+            "id": &org.id,
+            "name": &org.name,
+            "organizationUserId": &self.id,
+            "userId": &self.user_id,
+            "key": &self.akey,
+            "status": self.status,
+            "type": self.r#type,
+            "maxStorageGb": i16::MAX,
+            "productTierType": 3, // Enterprise
+            "hasPublicAndPrivateKeys": org.has_keys(),
+            "resetPasswordEnrolled": self.reset_password_key.is_some(),
+            "permissions": Permission::bits_to_json(self.permissions),
+            "seats": org.seats,
+            "useEvents": org.use_events,
+            "useGroups": org.use_groups,
+            "usePolicies": org.use_policies,
+            "object": "profileOrganization"
+        });
+
+        // The remaining feature flags are constant; kept out of `json!` to stay within its recursion limit.
+        const ENABLED: &[&str] = &[
+            "enabled",
+            "usersGetPremium",
+            "use2fa",
+            "useTotp",
+            "usePasswordManager",
+            "useResetPassword",
+            "useCustomPermissions",
+            "useOrganizationDomains",
+            "selfHost",
+            "allowAdminAccessToAllCollectionItems",
+            "limitCollectionCreation",
+            "limitCollectionDeletion",
+        ];
+        const DISABLED: &[&str] = &[
+            "useDirectory",
+            "useScim",
+            "useSso",
+            "useKeyConnector",
+            "useSecretsManager",
+            "useApi",
+            "useActivateAutofillPolicy",
+            "ssoBound",
+            "keyConnectorEnabled",
+            "accessSecretsManager",
+            "familySponsorshipAvailable",
+            "userIsManagedByOrganization",
+            "userIsClaimedByOrganization",
+        ];
+        const UNSET: &[&str] = &[
+            "identifier",
+            "maxCollections",
+            "keyConnectorUrl",
+            "providerId",
+            "providerName",
+            "providerType",
+            "familySponsorshipFriendlyName",
+            "familySponsorshipLastSyncDate",
+            "familySponsorshipValidUntil",
+            "familySponsorshipToDelete",
+        ];
+
+        if let Value::Object(map) = &mut json {
+            for key in ENABLED {
+                map.insert((*key).to_string(), Value::Bool(true));
+            }
+            for key in DISABLED {
+                map.insert((*key).to_string(), Value::Bool(false));
+            }
+            for key in UNSET {
+                map.insert((*key).to_string(), Value::Null);
+            }
+        }
+        json
+    }
+
+    pub async fn find_by_id_and_org(
+        db: &crate::db::Db,
+        id: &str,
+        organization_id: &str,
+    ) -> Result<Option<Self>, AppError> {
+        let row: Option<Value> = d1_query!(
+            db,
+            "SELECT * FROM users_organizations WHERE id = ?1 AND organization_id = ?2",
+            id,
+            organization_id
+        )
+        .map_err(|_| AppError::Database)?
+        .first(None)
+        .await
+        .map_err(|_| AppError::Database)?;
+
+        row.map(|row| serde_json::from_value(row).map_err(|_| AppError::Internal))
+            .transpose()
+    }
+
+    pub async fn find_by_user_and_org(
+        db: &crate::db::Db,
+        user_id: &str,
+        organization_id: &str,
+    ) -> Result<Option<Self>, AppError> {
+        let row: Option<Value> = d1_query!(
+            db,
+            "SELECT * FROM users_organizations WHERE user_id = ?1 AND organization_id = ?2",
+            user_id,
+            organization_id
+        )
+        .map_err(|_| AppError::Database)?
+        .first(None)
+        .await
+        .map_err(|_| AppError::Database)?;
+
+        row.map(|row| serde_json::from_value(row).map_err(|_| AppError::Internal))
+            .transpose()
+    }
+
+    pub async fn find_by_email_and_org(
+        db: &crate::db::Db,
+        email: &str,
+        organization_id: &str,
+    ) -> Result<Option<Self>, AppError> {
+        let row: Option<Value> = d1_query!(
+            db,
+            "SELECT * FROM users_organizations WHERE email = ?1 AND organization_id = ?2",
+            email,
+            organization_id
+        )
+        .map_err(|_| AppError::Database)?
+        .first(None)
+        .await
+        .map_err(|_| AppError::Database)?;
+
+        row.map(|row| serde_json::from_value(row).map_err(|_| AppError::Internal))
+            .transpose()
+    }
+
+    pub async fn list_by_org(
+        db: &crate::db::Db,
+        organization_id: &str,
+    ) -> Result<Vec<Self>, AppError> {
+        let rows: Vec<Value> = d1_query!(
+            db,
+            "SELECT * FROM users_organizations WHERE organization_id = ?1 ORDER BY created_at",
+            organization_id
+        )
+        .map_err(|_| AppError::Database)?
+        .all()
+        .await
+        .map_err(|_| AppError::Database)?
+        .results()
+        .map_err(|_| AppError::Database)?;
+
+        rows.into_iter()
+            .map(|row| serde_json::from_value(row).map_err(|_| AppError::Internal))
+            .collect()
+    }
+
+    pub async fn insert(&self, db: &crate::db::Db) -> Result<(), AppError> {
+        d1_query!(
+            db,
+            "INSERT INTO users_organizations (id, organization_id, user_id, email, akey, status, type, reset_password_key, permissions, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            &self.id,
+            &self.organization_id,
+            self.user_id.as_deref(),
+            &self.email,
+            self.akey.as_deref(),
+            self.status,
+            self.r#type,
+            self.reset_password_key.as_deref(),
+            self.permissions,
+            &self.created_at,
+            &self.updated_at
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+
+    pub async fn update(&mut self, db: &crate::db::Db) -> Result<(), AppError> {
+        self.updated_at = db::now_string();
+        d1_query!(
+            db,
+            "UPDATE users_organizations SET user_id = ?1, akey = ?2, status = ?3, type = ?4, reset_password_key = ?5, permissions = ?6, updated_at = ?7
+             WHERE id = ?8",
+            self.user_id.as_deref(),
+            self.akey.as_deref(),
+            self.status,
+            self.r#type,
+            self.reset_password_key.as_deref(),
+            self.permissions,
+            &self.updated_at,
+            &self.id
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+
+    /// Remove the member with everything tied to the membership, in one batch: its
+    /// collection assignments and group memberships, the user's favorites and folders
+    /// of the organization's ciphers, and its account recovery enrollment (kept on the
+    /// membership row).
+    pub async fn delete(&self, db: &crate::db::Db) -> Result<(), AppError> {
+        let mut statements = vec![
+            d1_query!(
+                db,
+                "DELETE FROM users_collections WHERE membership_id = ?1",
+                &self.id
+            )
+            .map_err(|_| AppError::Database)?,
+            d1_query!(
+                db,
+                "DELETE FROM groups_users WHERE membership_id = ?1",
+                &self.id
+            )
+            .map_err(|_| AppError::Database)?,
+        ];
+        if let Some(user_id) = self.user_id.as_deref() {
+            statements.push(
+                d1_query!(
+                    db,
+                    "DELETE FROM users_ciphers WHERE user_id = ?1
+                       AND cipher_id IN (SELECT id FROM ciphers WHERE organization_id = ?2)",
+                    user_id,
+                    &self.organization_id
+                )
+                .map_err(|_| AppError::Database)?,
+            );
+        }
+        statements.push(
+            d1_query!(
+                db,
+                "DELETE FROM users_organizations WHERE id = ?1",
+                &self.id
+            )
+            .map_err(|_| AppError::Database)?,
+        );
+        db.batch(statements).await.map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+
+    /// Number of memberships in any status; every one of them takes a seat.
+    pub async fn count_by_org(db: &crate::db::Db, organization_id: &str) -> Result<u32, AppError> {
+        let count: Option<u32> = d1_query!(
+            db,
+            "SELECT COUNT(*) AS count FROM users_organizations WHERE organization_id = ?1",
+            organization_id
+        )
+        .map_err(|_| AppError::Database)?
+        .first(Some("count"))
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(count.unwrap_or(0))
+    }
+
+    /// Number of confirmed owners; used to keep at least one owner per organization.
+    pub async fn count_confirmed_owners(
+        db: &crate::db::Db,
+        organization_id: &str,
+    ) -> Result<u32, AppError> {
+        let count: Option<u32> = d1_query!(
+            db,
+            "SELECT COUNT(*) AS count FROM users_organizations WHERE organization_id = ?1 AND type = ?2 AND status = ?3",
+            organization_id,
+            MembershipType::Owner as i32,
+            MembershipStatus::Confirmed as i32
+        )
+        .map_err(|_| AppError::Database)?
+        .first(Some("count"))
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(count.unwrap_or(0))
+    }
+
+    /// Number of other organizations the user has accepted or been confirmed to.
+    pub async fn count_other_memberships(
+        db: &crate::db::Db,
+        user_id: &str,
+        organization_id: &str,
+    ) -> Result<u32, AppError> {
+        let count: Option<u32> = d1_query!(
+            db,
+            "SELECT COUNT(*) AS count FROM users_organizations WHERE user_id = ?1 AND organization_id != ?2 AND status >= ?3",
+            user_id,
+            organization_id,
+            MembershipStatus::Accepted as i32
+        )
+        .map_err(|_| AppError::Database)?
+        .first(Some("count"))
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(count.unwrap_or(0))
+    }
+
+    /// User ids of all confirmed members, i.e. everyone who can see the org's ciphers.
+    pub async fn confirmed_user_ids(
+        db: &crate::db::Db,
+        organization_id: &str,
+    ) -> Result<Vec<String>, AppError> {
+        let rows: Vec<Value> = d1_query!(
+            db,
+            "SELECT user_id FROM users_organizations WHERE organization_id = ?1 AND status = ?2 AND user_id IS NOT NULL",
+            organization_id,
+            MembershipStatus::Confirmed as i32
+        )
+        .map_err(|_| AppError::Database)?
+        .all()
+        .await
+        .map_err(|_| AppError::Database)?
+        .results()
+        .map_err(|_| AppError::Database)?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| {
+                row.get("user_id")
+                    .and_then(|v| v.as_str())
+                    .map(String::from)
+            })
+            .collect())
+    }
+
+    /// Bump `updated_at` of every confirmed member so their clients re-sync.
+    pub async fn touch_confirmed_users(
+        db: &crate::db::Db,
+        organization_id: &str,
+        now: &str,
+    ) -> Result<(), AppError> {
+        Self::touch_confirmed_users_statement(db, organization_id, now)?
+            .run()
+            .await
+            .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+
+    /// [`Self::touch_confirmed_users`] as a statement, for callers that batch it with
+    /// the change.
+    pub fn touch_confirmed_users_statement(
+        db: &crate::db::Db,
+        organization_id: &str,
+        now: &str,
+    ) -> Result<D1PreparedStatement, AppError> {
+        d1_query!(
+            db,
+            "UPDATE users SET updated_at = ?1 WHERE id IN (
+                SELECT user_id FROM users_organizations WHERE organization_id = ?2 AND status = ?3
+             )",
+            now,
+            organization_id,
+            MembershipStatus::Confirmed as i32
+        )
+        .map_err(|_| AppError::Database)
+    }
+
+    /// Statements re-encrypting the account recovery keys during key rotation.
+    ///
+    /// Every key must belong to one of the user's enrolled memberships. Enrollments the
+    /// client did not re-encrypt are withdrawn: their key would unwrap the old user key,
+    /// so an admin reset would leave a vault that cannot be decrypted.
+    pub async fn reset_password_rotation_statements(
+        db: &crate::db::Db,
+        user_id: &str,
+        keys: &[ResetPasswordKeyData],
+        now: &str,
+    ) -> Result<Vec<D1PreparedStatement>, AppError> {
+        let rows: Vec<Value> = d1_query!(
+            db,
+            "SELECT organization_id FROM users_organizations WHERE user_id = ?1 AND reset_password_key IS NOT NULL",
+            user_id
+        )
+        .map_err(|_| AppError::Database)?
+        .all()
+        .await
+        .map_err(|_| AppError::Database)?
+        .results()
+        .map_err(|_| AppError::Database)?;
+        let enrolled: Vec<&str> = rows
+            .iter()
+            .filter_map(|row| row.get("organization_id").and_then(|v| v.as_str()))
+            .collect();
+        if keys
+            .iter()
+            .any(|k| !enrolled.contains(&k.organization_id.as_str()))
+        {
+            return Err(AppError::BadRequest(
+                "Account recovery key for an organization the user is not enrolled in".to_string(),
+            ));
+        }
+
+        let rotated: Vec<&str> = keys.iter().map(|k| k.organization_id.as_str()).collect();
+        let rotated = serde_json::to_string(&rotated).map_err(|_| AppError::Internal)?;
+        let mut statements = Vec::with_capacity(keys.len() + 1);
+        statements.push(
+            d1_query!(
+                db,
+                "UPDATE users_organizations SET reset_password_key = NULL, updated_at = ?1
+                 WHERE user_id = ?2 AND reset_password_key IS NOT NULL
+                   AND organization_id NOT IN (SELECT value FROM json_each(?3))",
+                now,
+                user_id,
+                rotated
+            )
+            .map_err(|_| AppError::Database)?,
+        );
+        for key in keys {
+            statements.push(
+                d1_query!(
+                    db,
+                    "UPDATE users_organizations SET reset_password_key = ?1, updated_at = ?2
+                     WHERE user_id = ?3 AND organization_id = ?4",
+                    &key.reset_password_key,
+                    now,
+                    user_id,
+                    &key.organization_id
+                )
+                .map_err(|_| AppError::Database)?,
+            );
+        }
+        Ok(statements)
+    }
+
+    /// Attach pending invitations for `email` to a freshly registered account.
+    ///
+    /// Registering with the invited address proves ownership of it, so the
+    /// invitations are accepted and only wait for an admin to confirm them.
+    pub async fn accept_invites_for_new_user(
+        db: &crate::db::Db,
+        user_id: &str,
+        email: &str,
+    ) -> Result<(), AppError> {
+        d1_query!(
+            db,
+            "UPDATE users_organizations SET user_id = ?1, status = ?2, updated_at = ?3
+             WHERE email = ?4 AND user_id IS NULL AND status = ?5",
+            user_id,
+            MembershipStatus::Accepted as i32,
+            db::now_string(),
+            email,
+            MembershipStatus::Invited as i32
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+
+    /// Delete invitations to addresses without an account that were last sent before
+    /// `cutoff`, returning how many were removed.
+    pub async fn delete_invited_before(db: &crate::db::Db, cutoff: &str) -> Result<u32, AppError> {
+        let result = d1_query!(
+            db,
+            "DELETE FROM users_organizations WHERE status = ?1 AND user_id IS NULL AND updated_at < ?2",
+            MembershipStatus::Invited as i32,
+            cutoff
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+
+        let changes = result
+            .meta()
+            .map_err(|_| AppError::Database)?
+            .and_then(|m| m.changes)
+            .unwrap_or(0) as u32;
+
+        Ok(changes)
+    }
+
+    /// `profile.organizations` entries for every accepted or confirmed membership of the user.
+    pub async fn profile_organizations_json(
+        db: &crate::db::Db,
+        user_id: &str,
+    ) -> Result<Vec<Value>, AppError> {
+        let memberships: Vec<Value> = d1_query!(
+            db,
+            "SELECT * FROM users_organizations WHERE user_id = ?1 AND status >= ?2",
+            user_id,
+            MembershipStatus::Accepted as i32
+        )
+        .map_err(|_| AppError::Database)?
+        .all()
+        .await
+        .map_err(|_| AppError::Database)?
+        .results()
+        .map_err(|_| AppError::Database)?;
+
+        let mut out = Vec::with_capacity(memberships.len());
+        for row in memberships {
+            let membership: Membership =
+                serde_json::from_value(row).map_err(|_| AppError::Internal)?;
+            if let Some(org) = Organization::find_by_id(db, &membership.organization_id).await? {
+                out.push(membership.to_profile_json(&org));
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Minimal user fields needed to render a member entry.
+#[derive(Debug, Deserialize)]
+pub struct MemberUser {
+    pub name: Option<String>,
+    pub avatar_color: Option<String>,
+}
+
+// ── Request payloads ────────────────────────────────────────────────
+
+/// GET/PUT /admin/organizations/{org_id}/plan
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrganizationPlan {
+    /// `null` is unlimited.
+    pub seats: Option<i64>,
+    pub use_groups: bool,
+    pub use_events: bool,
+    pub use_policies: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrgKeyData {
+    pub encrypted_private_key: String,
+    pub public_key: String,
+}
+
+/// POST /api/organizations
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateOrganizationRequest {
+    pub name: String,
+    pub billing_email: String,
+    /// Org symmetric key encrypted with the creator's public key.
+    pub key: String,
+    pub keys: Option<OrgKeyData>,
+}
+
+/// PUT /api/organizations/{org_id}
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateOrganizationRequest {
+    pub name: String,
+    pub billing_email: Option<String>,
+}
+
+/// POST /api/organizations/{org_id}/users/invite
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InviteRequest {
+    pub emails: Vec<String>,
+    #[serde(rename = "type")]
+    pub r#type: i32,
+    #[serde(default)]
+    pub collections: Vec<CollectionAccessData>,
+    #[serde(default)]
+    pub groups: Vec<String>,
+    /// Granted permissions when `type` is `Custom`.
+    #[serde(default)]
+    pub permissions: Option<Value>,
+}
+
+/// PUT /api/organizations/{org_id}/users/{member_id}
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EditMemberRequest {
+    #[serde(rename = "type")]
+    pub r#type: i32,
+    /// Replaces the member's collection assignments when present.
+    pub collections: Option<Vec<CollectionAccessData>>,
+    /// Replaces the member's groups when present.
+    pub groups: Option<Vec<String>>,
+    /// Granted permissions when `type` is `Custom`.
+    #[serde(default)]
+    pub permissions: Option<Value>,
+}
+
+/// POST /api/organizations/{org_id}/users/{member_id}/confirm
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfirmMemberRequest {
+    pub key: String,
+}
+
+/// POST /api/organizations/{org_id}/users/confirm
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkConfirmRequest {
+    pub keys: Vec<BulkConfirmKey>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkConfirmKey {
+    /// Membership id.
+    pub id: String,
+    pub key: String,
+}
+
+/// POST /api/organizations/{org_id}/users/reinvite and /users/public-keys
+#[derive(Debug, Deserialize)]
+pub struct BulkMemberIds {
+    pub ids: Vec<String>,
+}
+
+/// POST /api/organizations/{org_id}/users/{member_id}/accept
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AcceptInviteRequest {
+    /// Token of the emailed accept link; required.
+    pub token: Option<String>,
+    /// Required when the organization auto-enrolls members in account recovery.
+    pub reset_password_key: Option<String>,
+}
+
+/// PUT /api/organizations/{org_id}/users/{user_id}/reset-password-enrollment
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResetPasswordEnrollmentRequest {
+    /// `null` withdraws from account recovery.
+    pub reset_password_key: Option<String>,
+    pub master_password_hash: Option<String>,
+}
+
+/// An account recovery key re-encrypted under the new user key during key rotation.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResetPasswordKeyData {
+    pub organization_id: String,
+    pub reset_password_key: String,
+}
+
+/// PUT /api/organizations/{org_id}/users/{member_id}/reset-password
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdminResetPasswordRequest {
+    pub new_master_password_hash: String,
+    /// The member's user key encrypted with the new master key.
+    pub key: String,
+}
@@ -0,0 +1,340 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::d1_query;
+use crate::models::twofactor::{WebauthnAssertion, WebauthnAttestation};
+use crate::models::user::bool_from_int;
+use crate::webauthn::CredentialKey;
+use crate::{db, error::AppError};
+
+/// Maximum number of passkeys per user (matches the web vault).
+pub const MAX_PASSKEYS: usize = 5;
+
+/// `prfStatus` values of the web vault.
+const PRF_ENABLED: i32 = 0;
+const PRF_SUPPORTED: i32 = 1;
+const PRF_UNSUPPORTED: i32 = 2;
+
+/// A passkey registered for passwordless login.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Passkey {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    pub credential_id: String,
+    pub public_key: String,
+    pub algorithm: i64,
+    pub counter: u32,
+    #[serde(with = "bool_from_int")]
+    pub supports_prf: bool,
+    pub encrypted_user_key: Option<String>,
+    pub encrypted_public_key: Option<String>,
+    pub encrypted_private_key: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// Keys the client derives from the passkey's PRF output.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyPrfKeys {
+    pub encrypted_user_key: Option<String>,
+    pub encrypted_public_key: Option<String>,
+    pub encrypted_private_key: Option<String>,
+}
+
+impl PasskeyPrfKeys {
+    fn is_complete(&self) -> bool {
+        self.encrypted_user_key.is_some()
+            && self.encrypted_public_key.is_some()
+            && self.encrypted_private_key.is_some()
+    }
+}
+
+/// POST /api/webauthn - Save a passkey
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatePasskeyRequest {
+    pub device_response: WebauthnAttestation,
+    pub name: String,
+    pub token: String,
+    #[serde(default)]
+    pub supports_prf: bool,
+    #[serde(flatten)]
+    pub keys: PasskeyPrfKeys,
+}
+
+/// PUT /api/webauthn - Store the PRF keys of a passkey
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdatePasskeyRequest {
+    pub device_response: WebauthnAssertion,
+    pub token: String,
+    #[serde(flatten)]
+    pub keys: PasskeyPrfKeys,
+}
+
+/// Passkey PRF keys re-encrypted during key rotation.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyUnlockData {
+    pub id: String,
+    pub encrypted_user_key: String,
+    pub encrypted_public_key: String,
+}
+
+impl Passkey {
+    pub fn new(
+        user_id: &str,
+        name: String,
+        key: CredentialKey,
+        supports_prf: bool,
+        keys: PasskeyPrfKeys,
+    ) -> Result<Self, AppError> {
+        if supports_prf && keys.encrypted_user_key.is_some() && !keys.is_complete() {
+            return Err(AppError::BadRequest(
+                "Incomplete passkey encryption keys".to_string(),
+            ));
+        }
+        let now = db::now_string();
+        Ok(Self {
+            id: Uuid::new_v4().to_string(),
+            user_id: user_id.to_string(),
+            name,
+            credential_id: key.credential_id,
+            public_key: key.public_key,
+            algorithm: key.algorithm,
+            counter: key.counter,
+            supports_prf,
+            encrypted_user_key: keys.encrypted_user_key.filter(|_| supports_prf),
+            encrypted_public_key: keys.encrypted_public_key.filter(|_| supports_prf),
+            encrypted_private_key: keys.encrypted_private_key.filter(|_| supports_prf),
+            created_at: now.clone(),
+            updated_at: now,
+        })
+    }
+
+    /// Whether the passkey can also decrypt the vault.
+    pub fn prf_enabled(&self) -> bool {
+        self.supports_prf
+            && self.encrypted_user_key.is_some()
+            && self.encrypted_public_key.is_some()
+            && self.encrypted_private_key.is_some()
+    }
+
+    fn prf_status(&self) -> i32 {
+        if self.prf_enabled() {
+            PRF_ENABLED
+        } else if self.supports_prf {
+            PRF_SUPPORTED
+        } else {
+            PRF_UNSUPPORTED
+        }
+    }
+
+    pub fn key(&self) -> CredentialKey {
+        CredentialKey {
+            credential_id: self.credential_id.clone(),
+            public_key: self.public_key.clone(),
+            algorithm: self.algorithm,
+            counter: self.counter,
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "name": self.name,
+            "prfStatus": self.prf_status(),
+            "encryptedUserKey": self.encrypted_user_key,
+            "encryptedPublicKey": self.encrypted_public_key,
+            "object": "webauthnCredential",
+        })
+    }
+
+    /// The `WebAuthnPrfOption` of the token response of a login with this passkey.
+    pub fn prf_option_json(&self) -> Option<Value> {
+        self.prf_enabled().then(|| {
+            json!({
+                "EncryptedPrivateKey": self.encrypted_private_key,
+                "EncryptedUserKey": self.encrypted_user_key,
+            })
+        })
+    }
+
+    pub async fn insert(&self, db: &db::Db) -> Result<(), AppError> {
+        d1_query!(
+            db,
+            "INSERT INTO passkeys (id, user_id, name, credential_id, public_key, algorithm, counter,
+                supports_prf, encrypted_user_key, encrypted_public_key, encrypted_private_key, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+            &self.id,
+            &self.user_id,
+            &self.name,
+            &self.credential_id,
+            &self.public_key,
+            self.algorithm,
+            self.counter,
+            self.supports_prf as i32,
+            &self.encrypted_user_key,
+            &self.encrypted_public_key,
+            &self.encrypted_private_key,
+            &self.created_at,
+            &self.updated_at
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+
+    pub async fn find_by_user(db: &db::Db, user_id: &str) -> Result<Vec<Self>, AppError> {
+        d1_query!(
+            db,
+            "SELECT * FROM passkeys WHERE user_id = ?1 ORDER BY created_at",
+            user_id
+        )
+        .map_err(|_| AppError::Database)?
+        .all()
+        .await
+        .map_err(|_| AppError::Database)?
+        .results()
+        .map_err(|_| AppError::Database)
+    }
+
+    pub async fn find_by_credential_id(
+        db: &db::Db,
+        credential_id: &str,
+    ) -> Result<Option<Self>, AppError> {
+        d1_query!(
+            db,
+            "SELECT * FROM passkeys WHERE credential_id = ?1",
+            credential_id
+        )
+        .map_err(|_| AppError::Database)?
+        .first(None)
+        .await
+        .map_err(|_| AppError::Database)
+    }
+
+    /// Record the signature counter of the latest assertion.
+    pub async fn set_counter(&mut self, db: &db::Db, counter: u32) -> Result<(), AppError> {
+        self.counter = counter;
+        self.updated_at = db::now_string();
+        d1_query!(
+            db,
+            "UPDATE passkeys SET counter = ?1, updated_at = ?2 WHERE id = ?3",
+            self.counter,
+            &self.updated_at,
+            &self.id
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+
+    /// Store the PRF keys created after the passkey was registered.
+    pub async fn set_prf_keys(
+        &mut self,
+        db: &db::Db,
+        keys: PasskeyPrfKeys,
+    ) -> Result<(), AppError> {
+        if !self.supports_prf {
+            return Err(AppError::BadRequest(
+                "This passkey does not support encryption".to_string(),
+            ));
+        }
+        if !keys.is_complete() {
+            return Err(AppError::BadRequest(
+                "Incomplete passkey encryption keys".to_string(),
+            ));
+        }
+        self.encrypted_user_key = keys.encrypted_user_key;
+        self.encrypted_public_key = keys.encrypted_public_key;
+        self.encrypted_private_key = keys.encrypted_private_key;
+        self.updated_at = db::now_string();
+        d1_query!(
+            db,
+            "UPDATE passkeys SET encrypted_user_key = ?1, encrypted_public_key = ?2, encrypted_private_key = ?3, updated_at = ?4 WHERE id = ?5",
+            &self.encrypted_user_key,
+            &self.encrypted_public_key,
+            &self.encrypted_private_key,
+            &self.updated_at,
+            &self.id
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+
+    /// Delete one of the user's passkeys; returns whether it existed.
+    pub async fn delete_by_id_and_user(
+        db: &db::Db,
+        id: &str,
+        user_id: &str,
+    ) -> Result<bool, AppError> {
+        let result = d1_query!(
+            db,
+            "DELETE FROM passkeys WHERE id = ?1 AND user_id = ?2",
+            id,
+            user_id
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(result
+            .meta()
+            .ok()
+            .flatten()
+            .and_then(|meta| meta.changes)
+            .unwrap_or(0)
+            > 0)
+    }
+
+    /// Statements re-encrypting the PRF keys during key rotation. Passkeys the client
+    /// did not re-encrypt lose their keys: they would unwrap the old user key.
+    pub fn rotation_statements(
+        db: &db::Db,
+        user_id: &str,
+        unlock_data: &[PasskeyUnlockData],
+        now: &str,
+    ) -> Result<Vec<worker::d1::D1PreparedStatement>, AppError> {
+        let mut statements = Vec::with_capacity(unlock_data.len() + 1);
+        let rotated: Vec<&str> = unlock_data.iter().map(|d| d.id.as_str()).collect();
+        let rotated = serde_json::to_string(&rotated).map_err(|_| AppError::Internal)?;
+        statements.push(
+            d1_query!(
+                db,
+                "UPDATE passkeys SET encrypted_user_key = NULL, encrypted_public_key = NULL, encrypted_private_key = NULL, updated_at = ?1
+                 WHERE user_id = ?2 AND id NOT IN (SELECT value FROM json_each(?3))",
+                now,
+                user_id,
+                rotated
+            )
+            .map_err(|_| AppError::Database)?,
+        );
+        for data in unlock_data {
+            statements.push(
+                d1_query!(
+                    db,
+                    "UPDATE passkeys SET encrypted_user_key = ?1, encrypted_public_key = ?2, updated_at = ?3
+                     WHERE id = ?4 AND user_id = ?5 AND supports_prf = 1",
+                    &data.encrypted_user_key,
+                    &data.encrypted_public_key,
+                    now,
+                    &data.id,
+                    user_id
+                )
+                .map_err(|_| AppError::Database)?,
+            );
+        }
+        Ok(statements)
+    }
+}
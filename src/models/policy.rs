@@ -0,0 +1,331 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::d1_query;
+use crate::models::organization::{MembershipStatus, MembershipType};
+use crate::{db, error::AppError};
+
+/// Organization policy types, using Bitwarden's numeric values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum PolicyType {
+    TwoFactorAuthentication = 0,
+    MasterPassword = 1,
+    PasswordGenerator = 2,
+    SingleOrg = 3,
+    RequireSso = 4,
+    PersonalOwnership = 5,
+    DisableSend = 6,
+    SendOptions = 7,
+    ResetPassword = 8,
+    MaximumVaultTimeout = 9,
+    DisablePersonalVaultExport = 10,
+    ActivateAutofill = 11,
+}
+
+impl PolicyType {
+    pub fn from_i32(value: i32) -> Option<Self> {
+        match value {
+            0 => Some(PolicyType::TwoFactorAuthentication),
+            1 => Some(PolicyType::MasterPassword),
+            2 => Some(PolicyType::PasswordGenerator),
+            3 => Some(PolicyType::SingleOrg),
+            4 => Some(PolicyType::RequireSso),
+            5 => Some(PolicyType::PersonalOwnership),
+            6 => Some(PolicyType::DisableSend),
+            7 => Some(PolicyType::SendOptions),
+            8 => Some(PolicyType::ResetPassword),
+            9 => Some(PolicyType::MaximumVaultTimeout),
+            10 => Some(PolicyType::DisablePersonalVaultExport),
+            11 => Some(PolicyType::ActivateAutofill),
+            _ => None,
+        }
+    }
+
+    /// Roles the policy does not bind. Owners and admins are exempt from every policy
+    /// except the master password rules, and only owners from the maximum vault timeout.
+    pub fn exempt_roles(self) -> &'static [MembershipType] {
+        match self {
+            PolicyType::MasterPassword => &[],
+            PolicyType::MaximumVaultTimeout => &[MembershipType::Owner],
+            _ => &[MembershipType::Owner, MembershipType::Admin],
+        }
+    }
+}
+
+/// A policy of one organization. Rows are created the first time a policy is saved;
+/// a missing row is reported as a disabled policy.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrgPolicy {
+    pub id: String,
+    pub organization_id: String,
+    pub atype: i32,
+    #[serde(with = "crate::models::user::bool_from_int")]
+    pub enabled: bool,
+    pub data: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl OrgPolicy {
+    pub fn new(organization_id: &str, policy_type: PolicyType) -> Self {
+        let now = db::now_string();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            organization_id: organization_id.to_string(),
+            atype: policy_type as i32,
+            enabled: false,
+            data: None,
+            created_at: now.clone(),
+            updated_at: now,
+        }
+    }
+
+    /// Policy options, `null` when none were saved.
+    pub fn data_json(&self) -> Value {
+        self.data
+            .as_deref()
+            .and_then(|data| serde_json::from_str(data).ok())
+            .unwrap_or(Value::Null)
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": &self.id,
+            "organizationId": &self.organization_id,
+            "type": self.atype,
+            "data": self.data_json(),
+            "enabled": self.enabled,
+            "object": "policy",
+        })
+    }
+
+    pub async fn find_by_org_and_type(
+        db: &crate::db::Db,
+        organization_id: &str,
+        policy_type: PolicyType,
+    ) -> Result<Option<Self>, AppError> {
+        d1_query!(
+            db,
+            "SELECT * FROM org_policies WHERE organization_id = ?1 AND atype = ?2",
+            organization_id,
+            policy_type as i32
+        )
+        .map_err(|_| AppError::Database)?
+        .first(None)
+        .await
+        .map_err(|_| AppError::Database)
+    }
+
+    pub async fn list_by_org(
+        db: &crate::db::Db,
+        organization_id: &str,
+    ) -> Result<Vec<Self>, AppError> {
+        d1_query!(
+            db,
+            "SELECT * FROM org_policies WHERE organization_id = ?1 ORDER BY atype",
+            organization_id
+        )
+        .map_err(|_| AppError::Database)?
+        .all()
+        .await
+        .map_err(|_| AppError::Database)?
+        .results()
+        .map_err(|_| AppError::Database)
+    }
+
+    /// Enabled policies of every organization the user has accepted or been confirmed to.
+    pub async fn list_enabled_for_user(
+        db: &crate::db::Db,
+        user_id: &str,
+    ) -> Result<Vec<Self>, AppError> {
+        d1_query!(
+            db,
+            "SELECT p.* FROM org_policies p
+             JOIN users_organizations uo ON uo.organization_id = p.organization_id
+             WHERE uo.user_id = ?1 AND uo.status >= ?2 AND p.enabled = 1
+             ORDER BY p.organization_id, p.atype",
+            user_id,
+            MembershipStatus::Accepted as i32
+        )
+        .map_err(|_| AppError::Database)?
+        .all()
+        .await
+        .map_err(|_| AppError::Database)?
+        .results()
+        .map_err(|_| AppError::Database)
+    }
+
+    /// Enabled policies of `policy_type` that bind the user, i.e. those of organizations
+    /// where the user is an accepted or confirmed member without an exempt role.
+    pub async fn list_enforced_for_user(
+        db: &crate::db::Db,
+        user_id: &str,
+        policy_type: PolicyType,
+    ) -> Result<Vec<Self>, AppError> {
+        let exempt_roles: Value = policy_type
+            .exempt_roles()
+            .iter()
+            .map(|role| *role as i32)
+            .collect();
+        d1_query!(
+            db,
+            "SELECT p.* FROM org_policies p
+             JOIN users_organizations uo ON uo.organization_id = p.organization_id
+             WHERE uo.user_id = ?1 AND uo.status >= ?2 AND p.enabled = 1 AND p.atype = ?3
+               AND uo.type NOT IN (SELECT value FROM json_each(?4))",
+            user_id,
+            MembershipStatus::Accepted as i32,
+            policy_type as i32,
+            exempt_roles.to_string()
+        )
+        .map_err(|_| AppError::Database)?
+        .all()
+        .await
+        .map_err(|_| AppError::Database)?
+        .results()
+        .map_err(|_| AppError::Database)
+    }
+
+    /// Insert or update the policy of `(organization_id, atype)`.
+    pub async fn save(&mut self, db: &crate::db::Db) -> Result<(), AppError> {
+        self.updated_at = db::now_string();
+        d1_query!(
+            db,
+            "INSERT INTO org_policies (id, organization_id, atype, enabled, data, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT(organization_id, atype) DO UPDATE SET
+               enabled = excluded.enabled, data = excluded.data, updated_at = excluded.updated_at",
+            &self.id,
+            &self.organization_id,
+            self.atype,
+            self.enabled as i32,
+            self.data.as_deref(),
+            &self.created_at,
+            &self.updated_at
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+
+    /// Turn off every policy of the organization, keeping their settings.
+    pub async fn disable_all_for_org(
+        db: &crate::db::Db,
+        organization_id: &str,
+    ) -> Result<(), AppError> {
+        d1_query!(
+            db,
+            "UPDATE org_policies SET enabled = 0, updated_at = ?1 WHERE organization_id = ?2 AND enabled = 1",
+            db::now_string(),
+            organization_id
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+}
+
+/// PUT /api/organizations/{org_id}/policies/{type}
+#[derive(Debug, Deserialize)]
+pub struct PolicyRequest {
+    pub enabled: bool,
+    #[serde(default)]
+    pub data: Option<Value>,
+}
+
+/// PUT /api/organizations/{org_id}/policies/{type}/vnext
+#[derive(Debug, Deserialize)]
+pub struct PolicyVNextRequest {
+    pub policy: PolicyRequest,
+}
+
+/// `data` of the MasterPassword policy. Missing fields mean "no requirement".
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MasterPasswordPolicyData {
+    pub min_complexity: Option<i32>,
+    pub min_length: Option<i32>,
+    #[serde(default)]
+    pub require_upper: bool,
+    #[serde(default)]
+    pub require_lower: bool,
+    #[serde(default)]
+    pub require_numbers: bool,
+    #[serde(default)]
+    pub require_special: bool,
+    #[serde(default)]
+    pub enforce_on_login: bool,
+}
+
+/// `data` of the ResetPassword (account recovery) policy.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResetPasswordPolicyData {
+    /// New members must enroll when they accept their invitation, and cannot withdraw.
+    #[serde(default)]
+    pub auto_enroll_enabled: bool,
+}
+
+/// `data` of the MaximumVaultTimeout policy.
+#[derive(Debug, Deserialize)]
+pub struct MaximumVaultTimeoutPolicyData {
+    /// Longest vault timeout members may choose.
+    pub minutes: i64,
+    /// What happens on timeout, `lock` or `logOut`; members choose when unset.
+    pub action: Option<String>,
+}
+
+impl MaximumVaultTimeoutPolicyData {
+    pub fn is_valid(&self) -> bool {
+        self.minutes > 0 && matches!(self.action.as_deref(), None | Some("lock" | "logOut"))
+    }
+
+    /// The shortest timeout of several organizations' policies.
+    pub fn strictest_minutes(policies: &[OrgPolicy]) -> Option<i64> {
+        policies
+            .iter()
+            .filter_map(|policy| serde_json::from_value::<Self>(policy.data_json()).ok())
+            .filter(Self::is_valid)
+            .map(|data| data.minutes)
+            .min()
+    }
+}
+
+impl MasterPasswordPolicyData {
+    /// Combine the policies of several organizations into the strictest requirements.
+    pub fn merge(policies: &[OrgPolicy]) -> Option<Self> {
+        let mut merged: Option<Self> = None;
+        for policy in policies {
+            let data: Self = serde_json::from_value(policy.data_json()).unwrap_or_default();
+            let acc = merged.get_or_insert_with(Self::default);
+            acc.min_complexity = acc.min_complexity.max(data.min_complexity);
+            acc.min_length = acc.min_length.max(data.min_length);
+            acc.require_upper |= data.require_upper;
+            acc.require_lower |= data.require_lower;
+            acc.require_numbers |= data.require_numbers;
+            acc.require_special |= data.require_special;
+            acc.enforce_on_login |= data.enforce_on_login;
+        }
+        merged
+    }
+
+    /// `MasterPasswordPolicy` object of the identity token response.
+    pub fn to_token_json(&self) -> Value {
+        json!({
+            "MinComplexity": self.min_complexity,
+            "MinLength": self.min_length,
+            "RequireUpper": self.require_upper,
+            "RequireLower": self.require_lower,
+            "RequireNumbers": self.require_numbers,
+            "RequireSpecial": self.require_special,
+            "EnforceOnLogin": self.enforce_on_login,
+            "Object": "masterPasswordPolicy",
+        })
+    }
+}
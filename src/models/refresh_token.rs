@@ -0,0 +1,264 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::crypto::{random_bytes, sha256_hex};
+use crate::d1_query;
+use crate::{db, error::AppError};
+
+/// How long a refresh token stays valid after it was issued.
+pub const REFRESH_TOKEN_LIFETIME_DAYS: i64 = 30;
+
+/// A rotated token may still be presented for this long, so that clients racing
+/// two refreshes (e.g. several browser tabs) are not treated as token theft.
+const REUSE_GRACE_SECONDS: i64 = 30;
+
+/// One issued refresh token. The raw token is only ever returned to the client;
+/// the database keeps its SHA-256 hash.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RefreshToken {
+    pub id: String,
+    pub token_hash: String,
+    pub family_id: String,
+    pub user_id: String,
+    pub device_identifier: String,
+    pub created_at: String,
+    pub expires_at: String,
+    pub used_at: Option<String>,
+    pub revoked_at: Option<String>,
+    /// When the login session started; unset on tokens issued before it was recorded.
+    pub session_started_at: Option<String>,
+}
+
+/// Limits on how long the login sessions of one user last.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SessionLimits {
+    /// Refresh tokens expire this long after they were issued, instead of after
+    /// [`REFRESH_TOKEN_LIFETIME_DAYS`].
+    pub token_lifetime: Option<Duration>,
+    /// A session can no longer be refreshed once it is this old.
+    pub max_age: Option<Duration>,
+}
+
+/// Outcome of presenting a refresh token.
+pub enum RefreshTokenCheck {
+    /// Token is current and may be exchanged for a new one.
+    Valid(RefreshToken),
+    /// Token was rotated moments ago; issue a new token in the same family
+    /// without treating it as reuse.
+    Grace(RefreshToken),
+    /// Token was rotated earlier and presented again. The family has been revoked.
+    Reused,
+    /// Token expired or was revoked.
+    Invalid,
+}
+
+impl RefreshToken {
+    /// Persist a new token for `device_identifier`, returning the row and the raw token.
+    ///
+    /// Passing `None` as `previous` starts a new family (a new login session); otherwise
+    /// the token continues the session of the token it replaces. The token expires
+    /// within `limits`.
+    pub async fn issue(
+        db: &crate::db::Db,
+        user_id: &str,
+        device_identifier: &str,
+        previous: Option<&Self>,
+        limits: SessionLimits,
+    ) -> Result<(Self, String), AppError> {
+        let raw = URL_SAFE_NO_PAD.encode(random_bytes(48)?);
+        let now = Utc::now();
+        let session_started = previous.and_then(Self::session_start).unwrap_or(now);
+        let mut expires = now
+            + limits
+                .token_lifetime
+                .unwrap_or_else(|| Duration::days(REFRESH_TOKEN_LIFETIME_DAYS));
+        if let Some(max_age) = limits.max_age {
+            expires = expires.min(session_started + max_age);
+        }
+        let token = Self {
+            id: Uuid::new_v4().to_string(),
+            token_hash: sha256_hex(&raw),
+            family_id: previous
+                .map(|token| token.family_id.clone())
+                .unwrap_or_else(|| Uuid::new_v4().to_string()),
+            user_id: user_id.to_string(),
+            device_identifier: device_identifier.to_string(),
+            created_at: db::format_time(now),
+            expires_at: db::format_time(expires),
+            used_at: None,
+            revoked_at: None,
+            session_started_at: Some(db::format_time(session_started)),
+        };
+
+        d1_query!(
+            db,
+            "INSERT INTO refresh_tokens (id, token_hash, family_id, user_id, device_identifier, created_at, expires_at, session_started_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            &token.id,
+            &token.token_hash,
+            &token.family_id,
+            &token.user_id,
+            &token.device_identifier,
+            &token.created_at,
+            &token.expires_at,
+            &token.session_started_at
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+
+        Ok((token, raw))
+    }
+
+    pub async fn find_by_token(db: &crate::db::Db, raw: &str) -> Result<Option<Self>, AppError> {
+        let row: Option<Value> = d1_query!(
+            db,
+            "SELECT * FROM refresh_tokens WHERE token_hash = ?1",
+            sha256_hex(raw)
+        )
+        .map_err(|_| AppError::Database)?
+        .first(None)
+        .await
+        .map_err(|_| AppError::Database)?;
+
+        row.map(|row| serde_json::from_value(row).map_err(|_| AppError::Internal))
+            .transpose()
+    }
+
+    /// When the login session of the token started.
+    pub fn session_start(&self) -> Option<DateTime<Utc>> {
+        parse_time(
+            self.session_started_at
+                .as_deref()
+                .unwrap_or(&self.created_at),
+        )
+    }
+
+    /// How long after `now` the token expires.
+    pub fn expires_in(&self, now: DateTime<Utc>) -> Duration {
+        parse_time(&self.expires_at).map_or(Duration::zero(), |exp| exp - now)
+    }
+
+    /// Whether the token was issued, and its session started, recently enough for
+    /// `limits`. Tokens issued before the limits were lowered are cut short here.
+    pub fn within_limits(&self, limits: SessionLimits, now: DateTime<Utc>) -> bool {
+        let issued_ok = limits.token_lifetime.is_none_or(|lifetime| {
+            parse_time(&self.created_at).is_some_and(|created| now - created <= lifetime)
+        });
+        let session_ok = limits.max_age.is_none_or(|max_age| {
+            self.session_start()
+                .is_some_and(|started| now - started <= max_age)
+        });
+        issued_ok && session_ok
+    }
+
+    /// Classify a presented token and, when it is valid, mark it as used.
+    pub async fn check_and_consume(
+        self,
+        db: &crate::db::Db,
+        now: DateTime<Utc>,
+    ) -> Result<RefreshTokenCheck, AppError> {
+        if self.revoked_at.is_some() || parse_time(&self.expires_at).is_none_or(|exp| exp <= now) {
+            return Ok(RefreshTokenCheck::Invalid);
+        }
+
+        if let Some(used_at) = self.used_at.as_deref() {
+            let within_grace = parse_time(used_at)
+                .is_some_and(|used| now - used <= Duration::seconds(REUSE_GRACE_SECONDS));
+            if within_grace {
+                return Ok(RefreshTokenCheck::Grace(self));
+            }
+            log::warn!(
+                "Refresh token reuse detected for user {} device {}; revoking session",
+                self.user_id,
+                self.device_identifier
+            );
+            Self::revoke_family(db, &self.family_id).await?;
+            return Ok(RefreshTokenCheck::Reused);
+        }
+
+        // Only the first concurrent request wins the token; a loser sees the
+        // row as used and falls into the grace window above on retry.
+        let result = d1_query!(
+            db,
+            "UPDATE refresh_tokens SET used_at = ?1 WHERE id = ?2 AND used_at IS NULL",
+            db::format_time(now),
+            &self.id
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        let changes = result
+            .meta()
+            .map_err(|_| AppError::Database)?
+            .and_then(|m| m.changes)
+            .unwrap_or(0);
+
+        if changes == 0 {
+            return Ok(RefreshTokenCheck::Grace(self));
+        }
+        Ok(RefreshTokenCheck::Valid(self))
+    }
+
+    /// Revoke every token issued for one login session.
+    pub async fn revoke_family(db: &crate::db::Db, family_id: &str) -> Result<(), AppError> {
+        d1_query!(
+            db,
+            "UPDATE refresh_tokens SET revoked_at = ?1 WHERE family_id = ?2 AND revoked_at IS NULL",
+            db::now_string(),
+            family_id
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+
+    /// Revoke every refresh token of a user, logging out all sessions.
+    pub async fn revoke_all_by_user(db: &crate::db::Db, user_id: &str) -> Result<(), AppError> {
+        d1_query!(
+            db,
+            "UPDATE refresh_tokens SET revoked_at = ?1 WHERE user_id = ?2 AND revoked_at IS NULL",
+            db::now_string(),
+            user_id
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+
+    /// Delete tokens that expired before `cutoff`. Returns the number of deleted rows.
+    pub async fn delete_expired_before(db: &crate::db::Db, cutoff: &str) -> Result<u32, AppError> {
+        let result = d1_query!(
+            db,
+            "DELETE FROM refresh_tokens WHERE expires_at < ?1",
+            cutoff
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+
+        let changes = result
+            .meta()
+            .map_err(|_| AppError::Database)?
+            .and_then(|m| m.changes)
+            .unwrap_or(0) as u32;
+
+        Ok(changes)
+    }
+}
+
+fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|t| t.with_timezone(&Utc))
+}
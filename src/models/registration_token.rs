@@ -0,0 +1,154 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::crypto::{random_bytes, sha256_hex};
+use crate::d1_query;
+use crate::{db, error::AppError};
+
+/// A single-use token an admin handed out so that one account may register even when
+/// sign-ups are closed. The raw token is only returned when it is created; the
+/// database keeps its SHA-256 hash, and the row stays after use as a record of who
+/// registered with it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegistrationToken {
+    pub id: String,
+    /// The only address that may use the token, if it is bound to one.
+    pub email: Option<String>,
+    pub note: Option<String>,
+    #[serde(alias = "created_at")]
+    pub created_at: String,
+    #[serde(alias = "expires_at")]
+    pub expires_at: String,
+    #[serde(alias = "used_at")]
+    pub used_at: Option<String>,
+    #[serde(alias = "used_by_email")]
+    pub used_by_email: Option<String>,
+    #[serde(alias = "used_by_user_id")]
+    pub used_by_user_id: Option<String>,
+}
+
+impl RegistrationToken {
+    /// Persist a new token valid for `valid_hours`, returning the row and the raw token.
+    pub async fn issue(
+        db: &crate::db::Db,
+        email: Option<String>,
+        note: Option<String>,
+        valid_hours: i64,
+    ) -> Result<(Self, String), AppError> {
+        let raw = URL_SAFE_NO_PAD.encode(random_bytes(32)?);
+        let now = Utc::now();
+        let token = Self {
+            id: Uuid::new_v4().to_string(),
+            email,
+            note,
+            created_at: db::format_time(now),
+            expires_at: db::format_time(now + Duration::hours(valid_hours)),
+            used_at: None,
+            used_by_email: None,
+            used_by_user_id: None,
+        };
+
+        d1_query!(
+            db,
+            "INSERT INTO registration_tokens (id, token_hash, email, note, created_at, expires_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            &token.id,
+            sha256_hex(&raw),
+            &token.email,
+            &token.note,
+            &token.created_at,
+            &token.expires_at
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+
+        Ok((token, raw))
+    }
+
+    pub async fn list(db: &crate::db::Db) -> Result<Vec<Self>, AppError> {
+        db.prepare("SELECT * FROM registration_tokens ORDER BY created_at DESC")
+            .all()
+            .await
+            .map_err(|_| AppError::Database)?
+            .results()
+            .map_err(|_| AppError::Database)
+    }
+
+    /// Delete the token, whether it was used or not. Returns whether it existed.
+    pub async fn delete(db: &crate::db::Db, id: &str) -> Result<bool, AppError> {
+        let result = d1_query!(db, "DELETE FROM registration_tokens WHERE id = ?1", id)
+            .map_err(|_| AppError::Database)?
+            .run()
+            .await
+            .map_err(|_| AppError::Database)?;
+        Ok(result
+            .meta()
+            .ok()
+            .flatten()
+            .and_then(|meta| meta.changes)
+            .unwrap_or(0)
+            > 0)
+    }
+
+    /// Mark the token `raw` as used by `email` if it is unused, unexpired and not bound
+    /// to another address. Returns whether it was claimed; a claimed token is used up
+    /// even when the caller then fails, unless it calls [`Self::release`].
+    pub async fn claim(db: &crate::db::Db, raw: &str, email: &str) -> Result<bool, AppError> {
+        let now = db::now_string();
+        let result = d1_query!(
+            db,
+            "UPDATE registration_tokens SET used_at = ?1, used_by_email = ?2
+             WHERE token_hash = ?3 AND used_at IS NULL AND expires_at > ?1
+               AND (email IS NULL OR email = ?2)",
+            &now,
+            email,
+            sha256_hex(raw)
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(result
+            .meta()
+            .ok()
+            .flatten()
+            .and_then(|meta| meta.changes)
+            .unwrap_or(0)
+            > 0)
+    }
+
+    /// Give back a token [`Self::claim`]ed for an account that was not created.
+    pub async fn release(db: &crate::db::Db, raw: &str) -> Result<(), AppError> {
+        d1_query!(
+            db,
+            "UPDATE registration_tokens SET used_at = NULL, used_by_email = NULL
+             WHERE token_hash = ?1 AND used_by_user_id IS NULL",
+            sha256_hex(raw)
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+
+    /// Record the account a claimed token created.
+    pub async fn record_user(db: &crate::db::Db, raw: &str, user_id: &str) -> Result<(), AppError> {
+        d1_query!(
+            db,
+            "UPDATE registration_tokens SET used_by_user_id = ?1 WHERE token_hash = ?2",
+            user_id,
+            sha256_hex(raw)
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+}
@@ -352,19 +352,34 @@ impl SendDB {
         Ok(())
     }
 
+    /// Count one access. The check and the increment are a single statement, so
+    /// concurrent accesses cannot go past `max_access_count`; a send that became
+    /// inaccessible since it was loaded is reported like in [`Self::validate_access`].
     pub async fn increment_access_count(&mut self, db: &crate::db::Db) -> Result<(), AppError> {
-        self.access_count += 1;
-        self.updated_at = db::now_string();
-        d1_query!(
+        #[derive(Deserialize)]
+        struct Counted {
+            access_count: i32,
+        }
+
+        let now = db::now_string();
+        let counted: Option<Counted> = d1_query!(
             db,
-            "UPDATE sends SET access_count = ?1, updated_at = ?2 WHERE id = ?3",
-            self.access_count,
-            self.updated_at,
-            self.id
+            "UPDATE sends SET access_count = access_count + 1, updated_at = ?1
+             WHERE id = ?2 AND disabled = 0 AND deletion_date > ?1
+               AND (expiration_date IS NULL OR expiration_date > ?1)
+               AND (max_access_count IS NULL OR access_count < max_access_count)
+             RETURNING access_count",
+            &now,
+            &self.id
         )
         .map_err(|_| AppError::Database)?
-        .run()
-        .await?;
+        .first(None)
+        .await
+        .map_err(|_| AppError::Database)?;
+
+        let counted = counted.ok_or_else(inaccessible_error)?;
+        self.access_count = counted.access_count;
+        self.updated_at = now;
         Ok(())
     }
 
@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+
+use crate::d1_query;
+use crate::{db, error::AppError};
+
+/// Link between a user and their identity at the SSO identity provider. A user has
+/// at most one, made on their first SSO login.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SsoUser {
+    pub user_id: String,
+    /// `sub` claim of the identity provider.
+    pub identifier: String,
+    pub created_at: String,
+}
+
+impl SsoUser {
+    pub fn new(user_id: String, identifier: String) -> Self {
+        Self {
+            user_id,
+            identifier,
+            created_at: db::now_string(),
+        }
+    }
+
+    pub async fn find_by_identifier(
+        db: &crate::db::Db,
+        identifier: &str,
+    ) -> Result<Option<Self>, AppError> {
+        d1_query!(
+            db,
+            "SELECT * FROM sso_users WHERE identifier = ?1",
+            identifier
+        )
+        .map_err(|_| AppError::Database)?
+        .first(None)
+        .await
+        .map_err(|_| AppError::Database)
+    }
+
+    pub async fn find_by_user(db: &crate::db::Db, user_id: &str) -> Result<Option<Self>, AppError> {
+        d1_query!(db, "SELECT * FROM sso_users WHERE user_id = ?1", user_id)
+            .map_err(|_| AppError::Database)?
+            .first(None)
+            .await
+            .map_err(|_| AppError::Database)
+    }
+
+    pub async fn insert(&self, db: &crate::db::Db) -> Result<(), AppError> {
+        d1_query!(
+            db,
+            "INSERT INTO sso_users (user_id, identifier, created_at) VALUES (?1, ?2, ?3)",
+            &self.user_id,
+            &self.identifier,
+            &self.created_at
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+}
@@ -1,7 +1,8 @@
 use super::{folder::FolderResponse, user::User};
+use crate::d1_query;
 use crate::error::AppError;
 use chrono::SecondsFormat;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 #[derive(Debug, Serialize)]
@@ -30,12 +31,24 @@ pub struct Profile {
     pub providers: Vec<Value>,
     #[serde(default)]
     pub provider_organizations: Vec<Value>,
+    /// Attachment and Send storage in use, in GB.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage_gb: Option<f64>,
+    /// Storage quota in GB, when one applies.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_storage_gb: Option<f64>,
     #[serde(rename = "_status")]
     pub status: i32,
 }
 
 impl Profile {
-    pub fn from_user(user: User, two_factor_enabled: bool) -> Result<Self, AppError> {
+    /// `verification_required` reports the stored verification state; otherwise every
+    /// address counts as verified so clients do not restrict unverified accounts.
+    pub fn from_user(
+        user: User,
+        two_factor_enabled: bool,
+        verification_required: bool,
+    ) -> Result<Self, AppError> {
         let creation_date = chrono::DateTime::parse_from_rfc3339(&user.created_at)
             .map_err(|_| AppError::Internal)?
             .to_rfc3339_opts(SecondsFormat::Micros, true);
@@ -49,8 +62,8 @@ impl Profile {
             object: "profile".to_string(),
             premium_from_organization: false,
             culture: "en-US".to_string(),
-            force_password_reset: false,
-            email_verified: true,
+            force_password_reset: user.force_password_reset,
+            email_verified: user.email_verified || !verification_required,
             two_factor_enabled,
             premium: true,
             uses_key_connector: false,
@@ -60,6 +73,8 @@ impl Profile {
             organizations: Vec::new(),
             providers: Vec::new(),
             provider_organizations: Vec::new(),
+            storage_gb: None,
+            max_storage_gb: None,
             status: 0,
         })
     }
@@ -83,3 +98,105 @@ pub struct SyncResponse {
     pub sends: Vec<Value>,
     pub object: String,
 }
+
+/// Object kinds recorded in `sync_tombstones`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TombstoneType {
+    Cipher = 0,
+    Folder = 1,
+}
+
+/// Global delta sync counter (the single `sync_state` row).
+///
+/// Triggers stamp every cipher and folder write with the next `revision`. A client
+/// that last synced at revision `n` can ask for rows with `revision > n`, unless `n`
+/// is older than `min_delta_revision`, below which tombstones have been pruned.
+#[derive(Debug, Default, Deserialize)]
+pub struct SyncState {
+    pub revision: i64,
+    pub min_delta_revision: i64,
+}
+
+impl SyncState {
+    pub async fn load(db: &crate::db::Db) -> Result<Self, AppError> {
+        let state: Option<Self> = db
+            .prepare("SELECT revision, min_delta_revision FROM sync_state WHERE id = 1")
+            .first(None)
+            .await
+            .map_err(|_| AppError::Database)?;
+        Ok(state.unwrap_or_default())
+    }
+
+    /// A string that changes whenever anything in the user's sync response may have:
+    /// the global revision (ciphers, folders, attachments, access), the user row
+    /// (profile, keys, domains, personal changes), and the organizations, memberships,
+    /// collections, policies, two-factor providers and sends the response includes.
+    /// `None` when the user does not exist.
+    pub async fn user_fingerprint(
+        db: &crate::db::Db,
+        user_id: &str,
+    ) -> Result<Option<String>, AppError> {
+        let row: Option<Value> = d1_query!(
+            db,
+            "SELECT (SELECT revision FROM sync_state WHERE id = 1) AS revision,
+                    u.updated_at, u.security_stamp, u.access_revision, u.email_verified,
+                    (SELECT COUNT(*) || '/' || COALESCE(MAX(uo.updated_at), '') || '/' || COALESCE(MAX(o.updated_at), '')
+                       FROM users_organizations uo JOIN organizations o ON o.id = uo.organization_id
+                      WHERE uo.user_id = u.id) AS organizations,
+                    (SELECT COUNT(*) || '/' || COALESCE(MAX(c.updated_at), '')
+                       FROM collections c JOIN users_organizations uo ON uo.organization_id = c.organization_id
+                      WHERE uo.user_id = u.id) AS collections,
+                    (SELECT COUNT(*) || '/' || COALESCE(MAX(p.updated_at), '')
+                       FROM org_policies p JOIN users_organizations uo ON uo.organization_id = p.organization_id
+                      WHERE uo.user_id = u.id) AS policies,
+                    (SELECT COUNT(*) || '/' || COALESCE(SUM(enabled), 0)
+                       FROM twofactor WHERE user_uuid = u.id AND atype < 1000) AS twofactor,
+                    (SELECT COUNT(*) || '/' || COALESCE(MAX(updated_at), '') || '/' || COALESCE(SUM(access_count), 0)
+                       FROM sends WHERE user_id = u.id) AS sends
+             FROM users u WHERE u.id = ?1",
+            user_id
+        )
+        .map_err(|_| AppError::Database)?
+        .first(None)
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(row.map(|row| row.to_string()))
+    }
+
+    /// Whether changes since `since` can still be served as a delta.
+    pub fn can_serve_delta(&self, since: i64, access_revision: i64) -> bool {
+        since >= self.min_delta_revision && since <= self.revision && access_revision <= since
+    }
+
+    /// Delete tombstones recorded before `cutoff`, raising `min_delta_revision` past them.
+    /// Returns the number of deleted tombstones.
+    pub async fn prune_tombstones(db: &crate::db::Db, cutoff: &str) -> Result<u32, AppError> {
+        let raise = d1_query!(
+            db,
+            "UPDATE sync_state SET min_delta_revision = MAX(min_delta_revision,
+                 COALESCE((SELECT MAX(revision) FROM sync_tombstones WHERE deleted_at < ?1), 0))
+             WHERE id = 1",
+            cutoff
+        )
+        .map_err(|_| AppError::Database)?;
+        let delete = d1_query!(
+            db,
+            "DELETE FROM sync_tombstones WHERE deleted_at < ?1",
+            cutoff
+        )
+        .map_err(|_| AppError::Database)?;
+
+        let results = db
+            .batch(vec![raise, delete])
+            .await
+            .map_err(|_| AppError::Database)?;
+
+        let changes = results
+            .last()
+            .and_then(|r| r.meta().ok().flatten())
+            .and_then(|m| m.changes)
+            .unwrap_or(0) as u32;
+
+        Ok(changes)
+    }
+}
@@ -1,4 +1,9 @@
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::d1_query;
+use crate::models::organization::MembershipStatus;
+use crate::{db, error::AppError};
 
 /// Two-factor authentication types
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -13,6 +18,9 @@ pub enum TwoFactorType {
     OrganizationDuo = 6,
     Webauthn = 7,
     RecoveryCode = 8,
+
+    // Pending setup state, not a login provider (excluded by `atype < 1000` queries)
+    EmailVerificationChallenge = 1002,
 }
 
 impl TwoFactorType {
@@ -27,6 +35,20 @@ impl TwoFactorType {
             6 => Some(TwoFactorType::OrganizationDuo),
             7 => Some(TwoFactorType::Webauthn),
             8 => Some(TwoFactorType::RecoveryCode),
+            1002 => Some(TwoFactorType::EmailVerificationChallenge),
+            _ => None,
+        }
+    }
+
+    /// Name of a login provider users set up themselves, or `None` for the other types.
+    pub fn provider_name(self) -> Option<&'static str> {
+        match self {
+            TwoFactorType::Authenticator => Some("authenticator app"),
+            TwoFactorType::Email => Some("email"),
+            TwoFactorType::Duo => Some("Duo"),
+            TwoFactorType::YubiKey => Some("YubiKey OTP"),
+            TwoFactorType::U2f => Some("FIDO U2F"),
+            TwoFactorType::Webauthn => Some("security key (WebAuthn)"),
             _ => None,
         }
     }
@@ -65,6 +87,153 @@ impl TwoFactor {
     }
 }
 
+/// A two-factor provider an organization configured for its members, stored in
+/// `organization_twofactor`. Only `OrganizationDuo` exists.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrgTwoFactor {
+    pub uuid: String,
+    pub organization_id: String,
+    pub atype: i32,
+    #[serde(with = "bool_from_int")]
+    pub enabled: bool,
+    pub data: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl OrgTwoFactor {
+    pub fn new(organization_id: &str, atype: TwoFactorType, data: String) -> Self {
+        let now = db::now_string();
+        Self {
+            uuid: uuid::Uuid::new_v4().to_string(),
+            organization_id: organization_id.to_string(),
+            atype: atype as i32,
+            enabled: true,
+            data,
+            created_at: now.clone(),
+            updated_at: now,
+        }
+    }
+
+    /// The provider as a login option of a member (`user_uuid`).
+    pub fn to_member_twofactor(&self, user_uuid: &str) -> TwoFactor {
+        TwoFactor {
+            uuid: self.uuid.clone(),
+            user_uuid: user_uuid.to_string(),
+            atype: self.atype,
+            enabled: self.enabled,
+            data: self.data.clone(),
+            last_used: 0,
+        }
+    }
+
+    pub fn to_json_provider(&self) -> Value {
+        serde_json::json!({
+            "enabled": self.enabled,
+            "type": self.atype,
+            "object": "twoFactorProvider"
+        })
+    }
+
+    pub async fn list_by_org(
+        db: &crate::db::Db,
+        organization_id: &str,
+    ) -> Result<Vec<Self>, AppError> {
+        d1_query!(
+            db,
+            "SELECT * FROM organization_twofactor WHERE organization_id = ?1 ORDER BY atype",
+            organization_id
+        )
+        .map_err(|_| AppError::Database)?
+        .all()
+        .await
+        .map_err(|_| AppError::Database)?
+        .results()
+        .map_err(|_| AppError::Database)
+    }
+
+    pub async fn find_by_org_and_type(
+        db: &crate::db::Db,
+        organization_id: &str,
+        atype: TwoFactorType,
+    ) -> Result<Option<Self>, AppError> {
+        d1_query!(
+            db,
+            "SELECT * FROM organization_twofactor WHERE organization_id = ?1 AND atype = ?2",
+            organization_id,
+            atype as i32
+        )
+        .map_err(|_| AppError::Database)?
+        .first(None)
+        .await
+        .map_err(|_| AppError::Database)
+    }
+
+    /// The enabled provider of `atype` of an organization the user is a confirmed member of.
+    pub async fn find_enabled_for_user(
+        db: &crate::db::Db,
+        user_id: &str,
+        atype: TwoFactorType,
+    ) -> Result<Option<Self>, AppError> {
+        d1_query!(
+            db,
+            "SELECT t.* FROM organization_twofactor t
+             JOIN users_organizations uo ON uo.organization_id = t.organization_id
+             WHERE uo.user_id = ?1 AND uo.status = ?2 AND t.atype = ?3 AND t.enabled = 1
+             ORDER BY t.created_at LIMIT 1",
+            user_id,
+            MembershipStatus::Confirmed as i32,
+            atype as i32
+        )
+        .map_err(|_| AppError::Database)?
+        .first(None)
+        .await
+        .map_err(|_| AppError::Database)
+    }
+
+    /// Insert or update the provider of `(organization_id, atype)`.
+    pub async fn save(&mut self, db: &crate::db::Db) -> Result<(), AppError> {
+        self.updated_at = db::now_string();
+        d1_query!(
+            db,
+            "INSERT INTO organization_twofactor (uuid, organization_id, atype, enabled, data, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT(organization_id, atype) DO UPDATE SET
+               enabled = excluded.enabled, data = excluded.data, updated_at = excluded.updated_at",
+            &self.uuid,
+            &self.organization_id,
+            self.atype,
+            self.enabled as i32,
+            &self.data,
+            &self.created_at,
+            &self.updated_at
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+
+    pub async fn delete_by_org_and_type(
+        db: &crate::db::Db,
+        organization_id: &str,
+        atype: i32,
+    ) -> Result<(), AppError> {
+        d1_query!(
+            db,
+            "DELETE FROM organization_twofactor WHERE organization_id = ?1 AND atype = ?2",
+            organization_id,
+            atype
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(())
+    }
+}
+
 mod bool_from_int {
     use serde::{self, Deserialize, Deserializer, Serializer};
 
@@ -122,3 +291,178 @@ pub struct DisableAuthenticatorData {
     #[serde(rename = "type")]
     pub r#type: i32,
 }
+
+/// Credential returned by `navigator.credentials.create()`, as sent by the clients.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebauthnAttestation {
+    pub raw_id: String,
+    pub response: WebauthnAttestationResponse,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WebauthnAttestationResponse {
+    #[serde(rename = "AttestationObject", alias = "attestationObject")]
+    pub attestation_object: String,
+    #[serde(rename = "clientDataJson", alias = "clientDataJSON")]
+    pub client_data_json: String,
+}
+
+/// Credential returned by `navigator.credentials.get()`, sent JSON-encoded as the
+/// two-factor token during login.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebauthnAssertion {
+    pub raw_id: String,
+    pub response: WebauthnAssertionResponse,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WebauthnAssertionResponse {
+    #[serde(rename = "authenticatorData")]
+    pub authenticator_data: String,
+    #[serde(rename = "clientDataJson", alias = "clientDataJSON")]
+    pub client_data_json: String,
+    pub signature: String,
+}
+
+/// PUT/POST /api/two-factor/webauthn - Register a security key
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnableWebauthnData {
+    /// Key slot; the clients send it as a number or a string.
+    pub id: Value,
+    pub name: String,
+    pub device_response: WebauthnAttestation,
+    pub master_password_hash: Option<String>,
+    pub otp: Option<String>,
+}
+
+/// DELETE /api/two-factor/webauthn - Remove a security key
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteWebauthnData {
+    pub id: Value,
+    pub master_password_hash: Option<String>,
+    pub otp: Option<String>,
+}
+
+/// Parse a key slot id sent as either a JSON number or string.
+pub fn slot_id(value: &Value) -> Option<i32> {
+    match value {
+        Value::Number(n) => n.as_i64().and_then(|n| i32::try_from(n).ok()),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+/// State of the email provider, stored as JSON in the `twofactor` row data.
+///
+/// Only a SHA-256 hash of the current code is kept.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EmailTokenData {
+    pub email: String,
+    pub token_hash: Option<String>,
+    /// Unix timestamp (seconds) of the last code sent.
+    pub token_sent_at: i64,
+    /// Failed verification attempts for the current code.
+    pub attempts: u32,
+}
+
+impl EmailTokenData {
+    pub fn new(email: String) -> Self {
+        Self {
+            email,
+            ..Default::default()
+        }
+    }
+}
+
+/// POST /api/two-factor/send-email - Send a setup code to an address
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SendEmailData {
+    pub email: String,
+    pub master_password_hash: Option<String>,
+    pub otp: Option<String>,
+}
+
+/// PUT/POST /api/two-factor/email - Enable email 2FA with the setup code
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmailData {
+    pub email: String,
+    pub token: String,
+    pub master_password_hash: Option<String>,
+    pub otp: Option<String>,
+}
+
+/// POST /api/two-factor/send-email-login - Send a login code (unauthenticated)
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SendEmailLoginData {
+    pub email: String,
+    pub master_password_hash: String,
+}
+
+/// PUT/POST /api/two-factor/duo and /api/organizations/{org_id}/two-factor/duo
+///
+/// Older clients name the credentials `integrationKey` and `secretKey`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnableDuoData {
+    #[serde(alias = "integrationKey")]
+    pub client_id: String,
+    #[serde(alias = "secretKey")]
+    pub client_secret: String,
+    pub host: String,
+    pub master_password_hash: Option<String>,
+    pub otp: Option<String>,
+}
+
+/// POST /api/two-factor/recover
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecoverTwoFactorData {
+    pub email: String,
+    pub master_password_hash: String,
+    pub recovery_code: String,
+}
+
+/// PUT/POST /api/two-factor/yubikey
+///
+/// Each key is either an OTP from a new YubiKey or the public ID of a key
+/// already registered, as sent back by the settings screen.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnableYubikeyData {
+    pub key1: Option<String>,
+    pub key2: Option<String>,
+    pub key3: Option<String>,
+    pub key4: Option<String>,
+    pub key5: Option<String>,
+    #[serde(default)]
+    pub nfc: bool,
+    pub master_password_hash: Option<String>,
+    pub otp: Option<String>,
+}
+
+impl EnableYubikeyData {
+    /// The non-empty key fields, in order.
+    pub fn keys(&self) -> Vec<&str> {
+        [&self.key1, &self.key2, &self.key3, &self.key4, &self.key5]
+            .into_iter()
+            .filter_map(|key| key.as_deref().map(str::trim))
+            .filter(|key| !key.is_empty())
+            .collect()
+    }
+}
+
+/// Stored in the YubiKey two-factor row data.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct YubikeyMetadata {
+    /// Public IDs (the first 12 modhex characters of every OTP) of the registered keys.
+    pub keys: Vec<String>,
+    /// Whether a key supports NFC, so mobile clients offer to read it.
+    pub nfc: bool,
+}
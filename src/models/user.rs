@@ -1,3 +1,4 @@
+use chrono::{Duration, Utc};
 use constant_time_eq::constant_time_eq;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -36,10 +37,56 @@ pub struct User {
     #[serde(default = "default_json_array_string")]
     pub excluded_globals: String,
     pub totp_recover: Option<String>, // Recovery code for 2FA
+    /// Personal API key used as the `client_credentials` client secret.
+    #[serde(default)]
+    pub api_key: Option<String>,
+    /// Set when the user deleted their account; the data is wiped after a grace period.
+    #[serde(default)]
+    pub deletion_requested_at: Option<String>,
+    /// Disabled by an admin; the account cannot log in.
+    #[serde(default, with = "bool_from_int")]
+    pub disabled: bool,
+    /// Set by an admin; clients ask for a new master password after login.
+    #[serde(default, with = "bool_from_int")]
+    pub force_password_reset: bool,
+    /// Last login, kept to the hour (see [`User::record_login`]).
+    #[serde(default)]
+    pub last_login_at: Option<String>,
+    /// Last vault sync, kept to the hour (see [`User::record_sync`]).
+    #[serde(default)]
+    pub last_sync_at: Option<String>,
     pub created_at: String,
     pub updated_at: String,
 }
 
+/// Activity timestamps are only rewritten once they are this old, so busy clients do
+/// not cost a row write per request.
+const ACTIVITY_WRITE_INTERVAL_SECS: i64 = 3600;
+
+/// Set an activity column to now unless it was set within the write interval. Failures
+/// are only logged: the tracking must never get in the way of the request itself.
+async fn record_activity(db: &crate::db::Db, user_id: &str, column: &str) {
+    let now = crate::db::now_string();
+    let threshold = (Utc::now() - Duration::seconds(ACTIVITY_WRITE_INTERVAL_SECS))
+        .format("%Y-%m-%dT%H:%M:%S%.3fZ")
+        .to_string();
+    let result = match d1_query!(
+        db,
+        &format!(
+            "UPDATE users SET {column} = ?1 WHERE id = ?2 AND ({column} IS NULL OR {column} < ?3)"
+        ),
+        now,
+        user_id,
+        threshold
+    ) {
+        Ok(query) => query.run().await.map(|_| ()),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        log::warn!("Failed to record {column} of user {user_id}: {e}");
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PasswordVerification {
     MatchCurrentScheme,
@@ -61,6 +108,17 @@ impl PasswordVerification {
 }
 
 impl User {
+    pub async fn find_by_id(db: &crate::db::Db, id: &str) -> Result<Option<Self>, AppError> {
+        let row: Option<Value> = d1_query!(db, "SELECT * FROM users WHERE id = ?1", id)
+            .map_err(|_| AppError::Database)?
+            .first(None)
+            .await
+            .map_err(|_| AppError::Database)?;
+
+        row.map(|row| serde_json::from_value(row).map_err(|_| AppError::Internal))
+            .transpose()
+    }
+
     pub async fn find_by_email(db: &crate::db::Db, email: &str) -> Result<Option<Self>, AppError> {
         let row: Option<Value> = d1_query!(db, "SELECT * FROM users WHERE email = ?1", email)
             .map_err(|_| AppError::Database)?
@@ -72,6 +130,38 @@ impl User {
             .transpose()
     }
 
+    /// Note a login of the user in `last_login_at`.
+    pub async fn record_login(db: &crate::db::Db, user_id: &str) {
+        record_activity(db, user_id, "last_login_at").await;
+    }
+
+    /// Note a vault sync of the user in `last_sync_at`.
+    pub async fn record_sync(db: &crate::db::Db, user_id: &str) {
+        record_activity(db, user_id, "last_sync_at").await;
+    }
+
+    /// Replace the user's security stamp, which invalidates every access and refresh
+    /// token issued with the old one. Returns the new stamp.
+    pub async fn rotate_security_stamp(
+        db: &crate::db::Db,
+        user_id: &str,
+        now: &str,
+    ) -> Result<String, AppError> {
+        let security_stamp = uuid::Uuid::new_v4().to_string();
+        d1_query!(
+            db,
+            "UPDATE users SET security_stamp = ?1, updated_at = ?2 WHERE id = ?3",
+            &security_stamp,
+            now,
+            user_id
+        )
+        .map_err(|_| AppError::Database)?
+        .run()
+        .await
+        .map_err(|_| AppError::Database)?;
+        Ok(security_stamp)
+    }
+
     pub async fn verify_master_password(
         &self,
         provided_hash: &str,
@@ -104,7 +194,7 @@ impl User {
     }
 }
 
-mod bool_from_int {
+pub(crate) mod bool_from_int {
     use serde::{self, Deserialize, Deserializer, Serializer};
 
     pub fn deserialize<'de, D>(deserializer: D) -> Result<bool, D::Error>
@@ -132,7 +222,7 @@ mod bool_from_int {
 }
 
 // For /accounts/prelogin response
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PreloginResponse {
     pub kdf: i32,
@@ -155,6 +245,21 @@ pub struct RegisterRequest {
     pub kdf_iterations: i32,
     pub kdf_memory: Option<i32>, // Argon2 memory parameter (15-1024 MB)
     pub kdf_parallelism: Option<i32>, // Argon2 parallelism parameter (1-16)
+    /// Turnstile token, when registration is protected by a captcha.
+    pub captcha_response: Option<String>,
+    /// Registration token from the admin API. The web vault sends the `token` of its
+    /// finish-signup link as `emailVerificationToken`.
+    #[serde(alias = "emailVerificationToken")]
+    pub registration_token: Option<String>,
+}
+
+/// POST /accounts/resend-new-device-otp
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResendNewDeviceOtpRequest {
+    pub email: String,
+    #[serde(alias = "secret")]
+    pub master_password_hash: String,
 }
 
 // For POST /accounts/password-hint request
@@ -172,17 +277,23 @@ pub struct KeyData {
 }
 
 /// Request body for password-protected operations (delete account, purge vault, etc.)
-/// Supports both master password hash and OTP verification.
-/// Note: OTP verification is not implemented in this simplified version.
+/// Supports both master password hash and OTP verification (see `accounts::verify_user`).
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PasswordOrOtpData {
     #[serde(alias = "MasterPasswordHash")]
     pub master_password_hash: Option<String>,
-    #[allow(dead_code)] // OTP verification is not implemented in this simplified version
+    /// Code from `/accounts/request-otp`.
     pub otp: Option<String>,
 }
 
+/// POST /accounts/verify-otp
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifyOtpRequest {
+    pub otp: String,
+}
+
 // For POST /accounts/password request
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -193,6 +304,34 @@ pub struct ChangePasswordRequest {
     pub key: String,
 }
 
+// For POST /accounts/email-token request
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmailTokenRequest {
+    pub new_email: String,
+    pub master_password_hash: String,
+}
+
+// For POST /accounts/email request
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangeEmailRequest {
+    pub new_email: String,
+    pub master_password_hash: String,
+    pub new_master_password_hash: String,
+    pub token: String,
+    pub key: String,
+}
+
+// For PUT /accounts/update-temp-password request (after an admin forced a password reset)
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateTempPasswordRequest {
+    pub new_master_password_hash: String,
+    pub master_password_hint: Option<String>,
+    pub key: String,
+}
+
 // For POST /accounts/kdf request - Change KDF settings
 
 #[derive(Debug, Deserialize, PartialEq, Eq)]
@@ -236,6 +375,23 @@ pub struct ChangeKdfRequest {
     pub unlock_data: UnlockData,
 }
 
+// For POST /accounts/key request (legacy key rotation, master password unchanged)
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LegacyRotateKeyRequest {
+    pub master_password_hash: String,
+    pub key: String,
+    pub private_key: String,
+    pub ciphers: Vec<crate::models::cipher::CipherRequestData>,
+    pub folders: Vec<RotateFolderData>,
+    #[serde(default)]
+    pub sends: Vec<crate::models::send::SendRequestData>,
+    #[serde(default)]
+    pub reset_password_keys: Vec<crate::models::organization::ResetPasswordKeyData>,
+    #[serde(default)]
+    pub emergency_access_keys: Vec<crate::models::emergency_access::EmergencyAccessKeyData>,
+}
+
 // For POST /accounts/key-management/rotate-user-account-keys request
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -250,6 +406,13 @@ pub struct RotateKeyRequest {
 #[serde(rename_all = "camelCase")]
 pub struct RotateAccountUnlockData {
     pub master_password_unlock_data: MasterPasswordUnlockData,
+    #[serde(default)]
+    pub passkey_unlock_data: Vec<crate::models::passkey::PasskeyUnlockData>,
+    #[serde(default)]
+    pub organization_account_recovery_unlock_data:
+        Vec<crate::models::organization::ResetPasswordKeyData>,
+    #[serde(default)]
+    pub emergency_access_unlock_data: Vec<crate::models::emergency_access::EmergencyAccessKeyData>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -295,6 +458,10 @@ pub struct RotateFolderData {
 #[serde(rename_all = "camelCase")]
 pub struct ProfileData {
     pub name: String,
+    /// Replaces the hint when present; an empty hint removes it. Clients that do not
+    /// edit the hint send `null`, which keeps it.
+    #[serde(default)]
+    pub master_password_hint: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -302,3 +469,11 @@ pub struct ProfileData {
 pub struct AvatarData {
     pub avatar_color: Option<String>,
 }
+
+/// POST /api/accounts/verify-email-token
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifyEmailTokenRequest {
+    pub user_id: String,
+    pub token: String,
+}
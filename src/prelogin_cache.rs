@@ -0,0 +1,101 @@
+//! Cache of the KDF settings `/accounts/prelogin` returns, kept in Workers KV.
+//!
+//! Every login starts with a prelogin, so a burst of logins would otherwise be a
+//! burst of D1 reads. Only existing accounts are cached, keyed by a hash of the
+//! email, for `PRELOGIN_CACHE_TTL_SECONDS` (default 3600, `0` disables the cache).
+//! Changing the KDF or the email and deleting the account drop the entry; KV is
+//! eventually consistent, so other locations may serve the old settings for up to
+//! a minute after that.
+
+use worker::Env;
+
+use crate::crypto::sha256_hex;
+use crate::handlers::get_env_usize;
+use crate::models::user::PreloginResponse;
+use crate::webauthn::CACHE_KV;
+
+/// Smallest `expiration_ttl` accepted by KV.
+const MIN_KV_TTL: u64 = 60;
+
+const KEY_PREFIX: &str = "prelogin:";
+
+fn ttl_secs(env: &Env) -> u64 {
+    get_env_usize(env, "PRELOGIN_CACHE_TTL_SECONDS", 3600) as u64
+}
+
+fn kv_key(email: &str) -> String {
+    format!("{KEY_PREFIX}{}", sha256_hex(&email.to_lowercase()))
+}
+
+/// The cached settings of `email`, if any.
+pub async fn get(env: &Env, email: &str) -> Option<PreloginResponse> {
+    if ttl_secs(env) == 0 {
+        return None;
+    }
+    let kv = env.kv(CACHE_KV).ok()?;
+    match kv.get(&kv_key(email)).json::<PreloginResponse>().await {
+        Ok(cached) => cached,
+        Err(e) => {
+            log::warn!("Prelogin cache lookup failed: {e}");
+            None
+        }
+    }
+}
+
+/// Remember the settings of an existing account.
+pub async fn put(env: &Env, email: &str, response: &PreloginResponse) {
+    let ttl = ttl_secs(env);
+    if ttl == 0 {
+        return;
+    }
+    let Ok(kv) = env.kv(CACHE_KV) else {
+        return;
+    };
+    let stored = match kv.put(&kv_key(email), response) {
+        Ok(put) => put.expiration_ttl(ttl.max(MIN_KV_TTL)).execute().await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = stored {
+        log::warn!("Prelogin cache update failed: {e}");
+    }
+}
+
+/// Drop the entry of `email` after its KDF settings changed or it stopped existing.
+pub async fn invalidate(env: &Env, email: &str) {
+    let Ok(kv) = env.kv(CACHE_KV) else {
+        return;
+    };
+    if let Err(e) = kv.delete(&kv_key(email)).await {
+        log::warn!("Prelogin cache invalidation failed: {e}");
+    }
+}
+
+/// Drop every entry, e.g. after the users table was replaced by a restore.
+pub async fn clear(env: &Env) {
+    let Ok(kv) = env.kv(CACHE_KV) else {
+        return;
+    };
+    let mut cursor = None;
+    loop {
+        let mut list = kv.list().prefix(KEY_PREFIX.to_string());
+        if let Some(cursor) = cursor.take() {
+            list = list.cursor(cursor);
+        }
+        let page = match list.execute().await {
+            Ok(page) => page,
+            Err(e) => {
+                log::warn!("Prelogin cache listing failed: {e}");
+                return;
+            }
+        };
+        for key in page.keys {
+            if let Err(e) = kv.delete(&key.name).await {
+                log::warn!("Prelogin cache invalidation failed: {e}");
+            }
+        }
+        if page.list_complete || page.cursor.is_none() {
+            return;
+        }
+        cursor = page.cursor;
+    }
+}
@@ -0,0 +1,160 @@
+//! Fixed-window rate limiting backed by Workers KV.
+//!
+//! The `LOGIN_RATE_LIMITER` binding only supports 10 or 60 second periods. Endpoints
+//! that need a longer budget (e.g. a few requests per hour) count requests in the
+//! `CACHE_KV` namespace instead. KV is eventually consistent, so the limit is
+//! approximate; it is meant to slow down abuse, not to be exact.
+//!
+//! [`LoginBackoff`] uses the same namespace to delay repeated failed logins.
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use worker::Env;
+
+use crate::handlers::get_env_usize;
+use crate::webauthn::CACHE_KV;
+
+/// Smallest `expiration_ttl` accepted by KV.
+const MIN_KV_TTL: u64 = 60;
+
+/// Count one request for `key` and report whether it exceeds `limit` per `window_secs`.
+///
+/// Returns `None` when `CACHE_KV` is not bound, so callers can fall back to another
+/// limiter. KV errors are logged and treated as "not limited".
+pub async fn kv_limit_exceeded(env: &Env, key: &str, limit: u32, window_secs: u64) -> Option<bool> {
+    let kv = env.kv(CACHE_KV).ok()?;
+    let window_secs = window_secs.max(1);
+    let window = Utc::now().timestamp().max(0) as u64 / window_secs;
+    let kv_key = format!("ratelimit:{key}:{window}");
+
+    let count = match kv.get(&kv_key).text().await {
+        Ok(value) => value.and_then(|v| v.parse::<u32>().ok()).unwrap_or(0),
+        Err(e) => {
+            log::warn!("Rate limit lookup for {key} failed: {e}");
+            return Some(false);
+        }
+    };
+    if count >= limit {
+        return Some(true);
+    }
+
+    let stored = match kv.put(&kv_key, (count + 1).to_string()) {
+        Ok(put) => {
+            put.expiration_ttl(window_secs.max(MIN_KV_TTL))
+                .execute()
+                .await
+        }
+        Err(e) => Err(e),
+    };
+    if let Err(e) = stored {
+        log::warn!("Rate limit update for {key} failed: {e}");
+    }
+    Some(false)
+}
+
+/// Failed logins tracked for one key since the last success.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct FailureState {
+    count: u32,
+    /// Unix timestamp before which new attempts are refused.
+    locked_until: i64,
+}
+
+/// Exponential backoff after repeated failed logins, tracked per key in `CACHE_KV`.
+/// Send passwords are guarded the same way, under their own settings.
+///
+/// The first `free_attempts` failures are not delayed. Every failure after that locks
+/// the key for `BACKOFF_BASE_SECS`, doubled each time up to `max_secs`. Failures are
+/// forgotten after an hour without new ones or on a successful login.
+pub struct LoginBackoff {
+    pub free_attempts: u32,
+    pub max_secs: u64,
+}
+
+const BACKOFF_BASE_SECS: u64 = 30;
+const FAILURE_MEMORY_SECS: u64 = 3600;
+
+impl LoginBackoff {
+    /// Reads `LOGIN_FAILURES_BEFORE_BACKOFF` (default 5) and `LOGIN_BACKOFF_MAX_SECONDS`
+    /// (default 900).
+    pub fn from_env(env: &Env) -> Self {
+        Self {
+            free_attempts: get_env_usize(env, "LOGIN_FAILURES_BEFORE_BACKOFF", 5) as u32,
+            max_secs: get_env_usize(env, "LOGIN_BACKOFF_MAX_SECONDS", 900) as u64,
+        }
+    }
+
+    /// Reads `SEND_PASSWORD_FAILURES_BEFORE_LOCKOUT` (default 5) and
+    /// `SEND_PASSWORD_LOCKOUT_MAX_SECONDS` (default 900).
+    pub fn for_send_passwords(env: &Env) -> Self {
+        Self {
+            free_attempts: get_env_usize(env, "SEND_PASSWORD_FAILURES_BEFORE_LOCKOUT", 5) as u32,
+            max_secs: get_env_usize(env, "SEND_PASSWORD_LOCKOUT_MAX_SECONDS", 900) as u64,
+        }
+    }
+
+    fn kv_key(key: &str) -> String {
+        format!("loginfail:{key}")
+    }
+
+    async fn load(env: &Env, key: &str) -> Option<FailureState> {
+        let kv = env.kv(CACHE_KV).ok()?;
+        match kv.get(&Self::kv_key(key)).json::<FailureState>().await {
+            Ok(state) => state,
+            Err(e) => {
+                log::warn!("Login failure lookup for {key} failed: {e}");
+                None
+            }
+        }
+    }
+
+    /// Seconds until `key` may try again; 0 when it is not locked or KV is not bound.
+    pub async fn retry_after(&self, env: &Env, key: &str) -> u64 {
+        let Some(state) = Self::load(env, key).await else {
+            return 0;
+        };
+        (state.locked_until - Utc::now().timestamp()).max(0) as u64
+    }
+
+    /// Count a failed login for `key` and lock it when the free attempts are used up.
+    /// Returns the number of failures so far (0 when KV is not bound).
+    pub async fn record_failure(&self, env: &Env, key: &str) -> u32 {
+        let Ok(kv) = env.kv(CACHE_KV) else {
+            return 0;
+        };
+        let mut state = Self::load(env, key).await.unwrap_or_default();
+        state.count = state.count.saturating_add(1);
+
+        let mut lock_secs = 0;
+        if state.count > self.free_attempts {
+            let doublings = (state.count - self.free_attempts - 1).min(32);
+            lock_secs = BACKOFF_BASE_SECS
+                .saturating_mul(1u64 << doublings)
+                .min(self.max_secs);
+            state.locked_until = Utc::now().timestamp() + lock_secs as i64;
+        }
+
+        let stored = match kv.put(&Self::kv_key(key), &state) {
+            Ok(put) => {
+                put.expiration_ttl(FAILURE_MEMORY_SECS.max(lock_secs).max(MIN_KV_TTL))
+                    .execute()
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = stored {
+            log::warn!("Login failure update for {key} failed: {e}");
+        }
+        state.count
+    }
+
+    /// Forget the failures of `key` after a successful login.
+    pub async fn reset(&self, env: &Env, key: &str) {
+        let Ok(kv) = env.kv(CACHE_KV) else {
+            return;
+        };
+        if let Err(e) = kv.delete(&Self::kv_key(key)).await {
+            log::warn!("Login failure reset for {key} failed: {e}");
+        }
+    }
+}
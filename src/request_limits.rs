@@ -0,0 +1,168 @@
+//! Request body limits and early request validation.
+//!
+//! [`limit_request_bodies`] caps request bodies at `REQUEST_BODY_MAX_BYTES`
+//! (default 1 MiB), or at `LARGE_REQUEST_BODY_MAX_BYTES` (default 5 MiB) for the
+//! routes that legitimately carry a whole vault or a file: imports, key rotation,
+//! bulk sharing and multipart uploads. JSON bodies are checked to parse before any
+//! handler runs, and the plain-text rejections of axum's extractors (malformed
+//! JSON, missing fields, wrong content type) are rewritten into the [`ApiError`] model the
+//! Bitwarden clients display.
+
+use std::sync::Arc;
+
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Request, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::de::IgnoredAny;
+use worker::Env;
+
+use crate::error::ApiError;
+use crate::handlers::get_env_usize;
+
+const DEFAULT_MAX_BYTES: usize = 1024 * 1024;
+const DEFAULT_LARGE_MAX_BYTES: usize = 5 * 1024 * 1024;
+
+/// Largest rejection body read back for rewriting.
+const REJECTION_MAX_BYTES: usize = 16 * 1024;
+
+/// Routes allowed up to the large limit.
+fn is_large_route(method: &Method, path: &str) -> bool {
+    if *method != Method::POST && *method != Method::PUT {
+        return false;
+    }
+    let segs: Vec<&str> = path.trim_matches('/').split('/').collect();
+    matches!(
+        segs.as_slice(),
+        ["api", "ciphers", "import"]
+            | ["api", "ciphers", "import-organization"]
+            | ["api", "ciphers", "share"]
+            | ["api", "accounts", "key"]
+            | [
+                "api",
+                "accounts",
+                "key-management",
+                "rotate-user-account-keys"
+            ]
+            | ["api", "ciphers", _, "attachment"]
+            | ["api", "ciphers", _, "attachment", _]
+            | ["api", "sends", "file"]
+            | ["api", "sends", _, "file", _]
+    )
+}
+
+fn regular_limit(env: &Env) -> usize {
+    get_env_usize(env, "REQUEST_BODY_MAX_BYTES", DEFAULT_MAX_BYTES)
+}
+
+fn large_limit(env: &Env) -> usize {
+    get_env_usize(env, "LARGE_REQUEST_BODY_MAX_BYTES", DEFAULT_LARGE_MAX_BYTES)
+}
+
+/// The highest limit of any route, for axum's `DefaultBodyLimit` backstop.
+pub fn max_body_bytes(env: &Env) -> usize {
+    regular_limit(env).max(large_limit(env))
+}
+
+fn is_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .map(|mime| mime.trim().to_ascii_lowercase())
+        .is_some_and(|mime| mime == "application/json" || mime.ends_with("+json"))
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response {
+    ApiError::new(status, message).into_response()
+}
+
+fn too_large(limit: usize) -> Response {
+    error_response(
+        StatusCode::PAYLOAD_TOO_LARGE,
+        &format!("The request body exceeds the limit of {limit} bytes."),
+    )
+}
+
+/// Axum middleware enforcing the body limits and validating JSON bodies.
+pub async fn limit_request_bodies(
+    State(env): State<Arc<Env>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let method = req.method().clone();
+    if method == Method::GET || method == Method::HEAD || method == Method::OPTIONS {
+        return rewrite_rejection(next.run(req).await).await;
+    }
+
+    let limit = if is_large_route(&method, req.uri().path()) {
+        large_limit(&env)
+    } else {
+        regular_limit(&env)
+    };
+    let declared = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    if declared.is_some_and(|len| len > limit) {
+        return too_large(limit);
+    }
+
+    // Other bodies (forms, multipart uploads) are bounded by `DefaultBodyLimit` and
+    // the handlers' own limits as they are read.
+    if !is_json(req.headers()) {
+        return rewrite_rejection(next.run(req).await).await;
+    }
+
+    let (parts, body) = req.into_parts();
+    let Ok(bytes) = to_bytes(body, limit).await else {
+        return too_large(limit);
+    };
+    if !bytes.is_empty() && serde_json::from_slice::<IgnoredAny>(&bytes).is_err() {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            "The request body is not valid JSON.",
+        );
+    }
+
+    let req = Request::from_parts(parts, Body::from(bytes));
+    rewrite_rejection(next.run(req).await).await
+}
+
+/// Turn a plain-text client error (an extractor rejection) into an [`ApiError`].
+async fn rewrite_rejection(response: Response) -> Response {
+    let status = response.status();
+    let is_plain_text = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/plain"));
+    if !status.is_client_error() || !is_plain_text {
+        return response;
+    }
+
+    let message = match to_bytes(response.into_body(), REJECTION_MAX_BYTES).await {
+        Ok(bytes) => String::from_utf8_lossy(&bytes).trim().to_string(),
+        Err(_) => String::new(),
+    };
+    let message = if message.is_empty() {
+        status
+            .canonical_reason()
+            .unwrap_or("Bad request")
+            .to_string()
+    } else {
+        message
+    };
+    // Field errors of the JSON extractor are reported as 422; the clients treat
+    // every validation error as a 400.
+    let status = if status == StatusCode::UNPROCESSABLE_ENTITY {
+        StatusCode::BAD_REQUEST
+    } else {
+        status
+    };
+    error_response(status, &message)
+}
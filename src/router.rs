@@ -6,8 +6,9 @@ use std::sync::Arc;
 use worker::Env;
 
 use crate::handlers::{
-    accounts, attachments, auth_requests, ciphers, config, devices, domains, emergency_access,
-    folders, identity, import, meta, sends, sync, twofactor, webauth,
+    accounts, admin, attachments, auth_requests, ciphers, collections, config, devices, domains,
+    emergency_access, events, export, folders, groups, hibp, icons, identity, import, meta,
+    org_domains, organizations, policies, reports, sends, sso, sync, twofactor, webauth,
 };
 
 pub fn api_router(env: Env) -> Router {
@@ -26,6 +27,14 @@ pub fn api_router(env: Env) -> Router {
             post(accounts::register),
         )
         .route("/identity/connect/token", post(identity::token))
+        // SSO login
+        .route("/identity/sso/prevalidate", get(sso::prevalidate))
+        .route("/identity/connect/authorize", get(sso::authorize))
+        .route("/identity/connect/oidc-signin", get(sso::oidc_signin))
+        .route(
+            "/identity/accounts/webauthn/assertion-options",
+            get(webauth::get_login_assertion_options),
+        )
         .route(
             "/identity/accounts/register/send-verification-email",
             post(accounts::send_verification_email),
@@ -35,6 +44,10 @@ pub fn api_router(env: Env) -> Router {
         // For on-demand sync checks
         .route("/api/accounts/revision-date", get(accounts::revision_date))
         .route("/api/accounts/password-hint", post(accounts::password_hint))
+        .route(
+            "/api/accounts/resend-new-device-otp",
+            post(accounts::resend_new_device_otp),
+        )
         .route("/api/tasks", get(accounts::get_tasks))
         .route("/api/accounts/profile", get(accounts::get_profile))
         .route("/api/accounts/profile", post(accounts::post_profile))
@@ -47,9 +60,44 @@ pub fn api_router(env: Env) -> Router {
         .route("/api/accounts/kdf", post(accounts::post_kdf))
         // Change password
         .route("/api/accounts/password", post(accounts::post_password))
+        // Change email
+        .route(
+            "/api/accounts/email-token",
+            post(accounts::post_email_token),
+        )
+        .route("/api/accounts/email", post(accounts::post_email))
+        .route(
+            "/api/accounts/verify-email",
+            post(accounts::post_verify_email),
+        )
+        .route(
+            "/api/accounts/verify-email-token",
+            post(accounts::post_verify_email_token),
+        )
+        .route(
+            "/api/accounts/update-temp-password",
+            put(accounts::put_update_temp_password),
+        )
+        // User verification before sensitive operations
+        .route(
+            "/api/accounts/verify-password",
+            post(accounts::post_verify_password),
+        )
+        .route(
+            "/api/accounts/request-otp",
+            post(accounts::post_request_otp),
+        )
+        .route("/api/accounts/verify-otp", post(accounts::post_verify_otp))
         // Log out all sessions via security stamp rotation
         .route("/api/accounts/security-stamp", post(accounts::post_sstamp))
+        // Personal API key (client_credentials login)
+        .route("/api/accounts/api-key", post(accounts::post_api_key))
+        .route(
+            "/api/accounts/rotate-api-key",
+            post(accounts::post_rotate_api_key),
+        )
         // Rotate encryption keys
+        .route("/api/accounts/key", post(accounts::post_key))
         .route(
             "/api/accounts/key-management/rotate-user-account-keys",
             post(accounts::post_rotatekey),
@@ -76,6 +124,15 @@ pub fn api_router(env: Env) -> Router {
         .route("/api/ciphers", post(ciphers::create_cipher_simple))
         .route("/api/ciphers/create", post(ciphers::create_cipher))
         .route("/api/ciphers/import", post(import::import_data))
+        .route("/api/ciphers/export", get(export::export_vault))
+        .route(
+            "/api/ciphers/import-organization",
+            post(import::import_organization_data),
+        )
+        .route(
+            "/api/ciphers/organization-details",
+            get(ciphers::list_organization_ciphers),
+        )
         .route("/api/ciphers/{id}", get(ciphers::get_cipher))
         .route(
             "/api/ciphers/{id}/details",
@@ -119,6 +176,15 @@ pub fn api_router(env: Env) -> Router {
             "/api/ciphers/{id}/delete",
             post(ciphers::hard_delete_cipher),
         )
+        // Collections of an organization cipher
+        .route(
+            "/api/ciphers/{id}/collections",
+            put(ciphers::update_cipher_collections).post(ciphers::update_cipher_collections),
+        )
+        .route(
+            "/api/ciphers/{id}/collections_v2",
+            put(ciphers::update_cipher_collections_v2).post(ciphers::update_cipher_collections_v2),
+        )
         // Partial update for folder/favorite
         .route(
             "/api/ciphers/{id}/partial",
@@ -143,6 +209,9 @@ pub fn api_router(env: Env) -> Router {
         .route("/api/ciphers/{id}/restore", put(ciphers::restore_cipher))
         // Cipher bulk restore
         .route("/api/ciphers/restore", put(ciphers::restore_ciphers_bulk))
+        // Restore or empty the whole trash
+        .route("/api/ciphers/trash/restore", put(ciphers::restore_trash))
+        .route("/api/ciphers/trash", delete(ciphers::empty_trash))
         // Cipher archive (sets archived_at)
         .route("/api/ciphers/{id}/archive", put(ciphers::archive_cipher))
         .route(
@@ -155,6 +224,15 @@ pub fn api_router(env: Env) -> Router {
             "/api/ciphers/unarchive",
             put(ciphers::unarchive_ciphers_bulk),
         )
+        // Share personal ciphers with an organization
+        .route(
+            "/api/ciphers/{id}/share",
+            put(ciphers::share_cipher).post(ciphers::share_cipher),
+        )
+        .route(
+            "/api/ciphers/share",
+            put(ciphers::share_ciphers_bulk).post(ciphers::share_ciphers_bulk),
+        )
         // Move ciphers to folder
         .route("/api/ciphers/move", post(ciphers::move_cipher_selected))
         .route("/api/ciphers/move", put(ciphers::move_cipher_selected))
@@ -167,6 +245,248 @@ pub fn api_router(env: Env) -> Router {
         .route("/api/folders/{id}", put(folders::update_folder))
         .route("/api/folders/{id}", delete(folders::delete_folder))
         .route("/api/folders/{id}/delete", post(folders::delete_folder))
+        // Organizations
+        .route(
+            "/api/organizations",
+            post(organizations::create_organization),
+        )
+        .route(
+            "/api/organizations/{org_id}",
+            get(organizations::get_organization)
+                .put(organizations::update_organization)
+                .post(organizations::update_organization)
+                .delete(organizations::delete_organization),
+        )
+        .route(
+            "/api/organizations/{org_id}/delete",
+            post(organizations::delete_organization),
+        )
+        .route(
+            "/api/organizations/{org_id}/export",
+            get(export::export_organization),
+        )
+        .route(
+            "/api/organizations/{org_id}/leave",
+            post(organizations::leave_organization),
+        )
+        .route(
+            "/api/organizations/{org_id}/keys",
+            get(organizations::get_organization_keys).post(organizations::post_organization_keys),
+        )
+        .route(
+            "/api/organizations/{org_id}/public-key",
+            get(organizations::get_organization_public_key),
+        )
+        .route(
+            "/api/organizations/{org_id}/auto-enroll-status",
+            get(organizations::get_auto_enroll_status),
+        )
+        // Organization members
+        .route(
+            "/api/organizations/{org_id}/users",
+            get(organizations::list_members),
+        )
+        .route(
+            "/api/organizations/{org_id}/users/invite",
+            post(organizations::invite_members),
+        )
+        .route(
+            "/api/organizations/{org_id}/users/reinvite",
+            post(organizations::reinvite_members),
+        )
+        .route(
+            "/api/organizations/{org_id}/users/confirm",
+            post(organizations::confirm_members),
+        )
+        .route(
+            "/api/organizations/{org_id}/users/public-keys",
+            post(organizations::member_public_keys),
+        )
+        .route(
+            "/api/organizations/{org_id}/users/{member_id}",
+            get(organizations::get_member)
+                .put(organizations::edit_member)
+                .post(organizations::edit_member)
+                .delete(organizations::delete_member),
+        )
+        .route(
+            "/api/organizations/{org_id}/users/{member_id}/delete",
+            post(organizations::delete_member),
+        )
+        .route(
+            "/api/organizations/{org_id}/users/{member_id}/reinvite",
+            post(organizations::reinvite_member),
+        )
+        .route(
+            "/api/organizations/{org_id}/users/{member_id}/accept",
+            post(organizations::accept_invite),
+        )
+        .route(
+            "/api/organizations/{org_id}/users/{member_id}/confirm",
+            post(organizations::confirm_member),
+        )
+        .route(
+            "/api/organizations/{org_id}/users/{member_id}/reset-password-enrollment",
+            put(organizations::put_reset_password_enrollment),
+        )
+        .route(
+            "/api/organizations/{org_id}/users/{member_id}/reset-password-details",
+            get(organizations::get_reset_password_details),
+        )
+        .route(
+            "/api/organizations/{org_id}/users/{member_id}/reset-password",
+            put(organizations::put_reset_password),
+        )
+        .route(
+            "/api/organizations/{org_id}/users/{member_id}/groups",
+            get(organizations::get_member_groups)
+                .put(organizations::put_member_groups)
+                .post(organizations::put_member_groups),
+        )
+        // Groups
+        .route(
+            "/api/organizations/{org_id}/groups",
+            get(groups::list_groups)
+                .post(groups::create_group)
+                .delete(groups::bulk_delete_groups),
+        )
+        .route(
+            "/api/organizations/{org_id}/groups/details",
+            get(groups::list_group_details),
+        )
+        .route(
+            "/api/organizations/{org_id}/groups/{group_id}",
+            get(groups::get_group)
+                .put(groups::update_group)
+                .post(groups::update_group)
+                .delete(groups::delete_group),
+        )
+        .route(
+            "/api/organizations/{org_id}/groups/{group_id}/delete",
+            post(groups::delete_group),
+        )
+        .route(
+            "/api/organizations/{org_id}/groups/{group_id}/details",
+            get(groups::get_group_details),
+        )
+        .route(
+            "/api/organizations/{org_id}/groups/{group_id}/users",
+            get(groups::get_group_users)
+                .put(groups::put_group_users)
+                .post(groups::put_group_users),
+        )
+        .route(
+            "/api/organizations/{org_id}/groups/{group_id}/users/{member_id}",
+            delete(groups::delete_group_user),
+        )
+        .route(
+            "/api/organizations/{org_id}/groups/{group_id}/delete-user/{member_id}",
+            post(groups::delete_group_user),
+        )
+        .route(
+            "/api/organizations/{org_id}/events",
+            get(events::get_organization_events),
+        )
+        .route(
+            "/api/organizations/{org_id}/events/export",
+            get(events::export_organization_events),
+        )
+        // Reports
+        .route(
+            "/api/reports/member-access/{org_id}",
+            get(reports::member_access_report),
+        )
+        .route(
+            "/api/reports/member-cipher-details/{org_id}",
+            get(reports::member_cipher_details),
+        )
+        // Policies
+        .route(
+            "/api/organizations/{org_id}/policies",
+            get(policies::list_policies),
+        )
+        .route(
+            "/api/organizations/{org_id}/policies/{policy_type}",
+            get(policies::get_policy).put(policies::put_policy),
+        )
+        .route(
+            "/api/organizations/{org_id}/policies/{policy_type}/vnext",
+            put(policies::put_policy_vnext),
+        )
+        // Domain verification
+        .route(
+            "/api/organizations/{org_id}/domain",
+            get(org_domains::list_domains).post(org_domains::create_domain),
+        )
+        .route(
+            "/api/organizations/{org_id}/domain/{domain_id}",
+            get(org_domains::get_domain).delete(org_domains::delete_domain),
+        )
+        .route(
+            "/api/organizations/{org_id}/domain/{domain_id}/remove",
+            post(org_domains::delete_domain),
+        )
+        .route(
+            "/api/organizations/{org_id}/domain/{domain_id}/verify",
+            post(org_domains::verify_domain),
+        )
+        .route(
+            "/api/organizations/{org_id}/domain-sso-details",
+            get(org_domains::get_domain_sso_details),
+        )
+        .route(
+            "/api/organizations/domain/sso/details",
+            post(org_domains::post_sso_details),
+        )
+        .route(
+            "/api/organizations/domain/sso/verified",
+            post(org_domains::post_sso_verified),
+        )
+        // Collections
+        .route("/api/collections", get(collections::list_user_collections))
+        .route(
+            "/api/organizations/{org_id}/collections",
+            get(collections::list_collections).post(collections::create_collection),
+        )
+        .route(
+            "/api/organizations/{org_id}/collections/details",
+            get(collections::list_collection_details),
+        )
+        .route(
+            "/api/organizations/{org_id}/collections/bulk-access",
+            post(collections::bulk_collection_access),
+        )
+        .route(
+            "/api/organizations/{org_id}/collections/{collection_id}",
+            get(collections::get_collection)
+                .put(collections::update_collection)
+                .post(collections::update_collection)
+                .delete(collections::delete_collection),
+        )
+        .route(
+            "/api/organizations/{org_id}/collections/{collection_id}/details",
+            get(collections::get_collection_details),
+        )
+        .route(
+            "/api/organizations/{org_id}/collections/{collection_id}/delete",
+            post(collections::delete_collection),
+        )
+        .route(
+            "/api/organizations/{org_id}/collections/{collection_id}/users",
+            get(collections::get_collection_users).put(collections::put_collection_users),
+        )
+        .route(
+            "/api/organizations/{org_id}/collections/{collection_id}/user/{member_id}",
+            delete(collections::delete_collection_user),
+        )
+        .route(
+            "/api/organizations/{org_id}/collections/{collection_id}/delete-user/{member_id}",
+            post(collections::delete_collection_user),
+        )
+        .route(
+            "/api/users/{user_id}/public-key",
+            get(organizations::get_user_public_key),
+        )
         // Sends
         .route("/api/sends", get(sends::list_sends))
         .route("/api/sends", post(sends::create_text_send))
@@ -190,16 +510,19 @@ pub fn api_router(env: Env) -> Router {
             post(sends::access_file_send),
         )
         .route("/api/config", get(config::config))
+        .route("/app-id.json", get(config::app_id))
         // Meta endpoints (mirrors a subset of vaultwarden core/mod.rs)
+        .route("/alive", get(meta::liveness))
         .route("/api/alive", get(meta::alive))
+        .route("/api/health", get(meta::health))
         .route("/api/now", get(meta::now))
         .route("/api/version", get(meta::version))
-        .route("/api/hibp/breach", get(meta::hibp_breach))
+        .route("/api/hibp/breach", get(hibp::get_breach))
         // Settings (stubbed)
         .route("/api/settings/domains", get(domains::get_domains))
         .route("/api/settings/domains", post(domains::post_domains))
         .route("/api/settings/domains", put(domains::put_domains))
-        // Emergency access (stub - returns empty lists, feature not supported)
+        // Emergency access
         .route(
             "/api/emergency-access/trusted",
             get(emergency_access::get_trusted_contacts),
@@ -208,9 +531,80 @@ pub fn api_router(env: Env) -> Router {
             "/api/emergency-access/granted",
             get(emergency_access::get_granted_access),
         )
-        // Devices (stub - device tracking not implemented, JWT-based auth)
+        .route(
+            "/api/emergency-access/invite",
+            post(emergency_access::invite_emergency_contact),
+        )
+        .route(
+            "/api/emergency-access/{id}",
+            get(emergency_access::get_emergency_access)
+                .put(emergency_access::update_emergency_access)
+                .post(emergency_access::update_emergency_access)
+                .delete(emergency_access::delete_emergency_access),
+        )
+        .route(
+            "/api/emergency-access/{id}/delete",
+            post(emergency_access::delete_emergency_access),
+        )
+        .route(
+            "/api/emergency-access/{id}/reinvite",
+            post(emergency_access::reinvite_emergency_contact),
+        )
+        .route(
+            "/api/emergency-access/{id}/accept",
+            post(emergency_access::accept_emergency_invite),
+        )
+        .route(
+            "/api/emergency-access/{id}/confirm",
+            post(emergency_access::confirm_emergency_contact),
+        )
+        .route(
+            "/api/emergency-access/{id}/initiate",
+            post(emergency_access::initiate_recovery),
+        )
+        .route(
+            "/api/emergency-access/{id}/approve",
+            post(emergency_access::approve_recovery),
+        )
+        .route(
+            "/api/emergency-access/{id}/reject",
+            post(emergency_access::reject_recovery),
+        )
+        .route(
+            "/api/emergency-access/{id}/view",
+            post(emergency_access::view_grantor_vault),
+        )
+        .route(
+            "/api/emergency-access/{id}/takeover",
+            post(emergency_access::takeover_grantor_account),
+        )
+        .route(
+            "/api/emergency-access/{id}/password",
+            post(emergency_access::takeover_set_password),
+        )
+        .route(
+            "/api/emergency-access/{id}/policies",
+            get(emergency_access::get_grantor_policies),
+        )
+        // Website icons
+        .route("/icons/{domain}/icon.png", get(icons::get_icon))
+        // Devices
         .route("/api/devices", get(devices::get_devices))
         .route("/api/devices/knowndevice", get(devices::get_known_device))
+        .route(
+            "/api/devices/knowndevice/{email}/{identifier}",
+            get(devices::get_known_device_from_path),
+        )
+        .route(
+            "/api/devices/{device_id}",
+            put(devices::put_device)
+                .post(devices::put_device)
+                .delete(devices::delete_device),
+        )
+        .route(
+            "/api/devices/{device_id}/deactivate",
+            post(devices::delete_device),
+        )
         .route(
             "/api/devices/identifier/{device_id}",
             get(devices::get_device),
@@ -231,8 +625,25 @@ pub fn api_router(env: Env) -> Router {
             "/api/devices/identifier/{device_id}/clear-token",
             post(devices::post_clear_device_token),
         )
-        // WebAuthn (stub - prevents 404 errors, passkeys not supported)
-        .route("/api/webauthn", get(webauth::get_webauthn_credentials))
+        // Passkeys
+        .route(
+            "/api/webauthn",
+            get(webauth::get_webauthn_credentials)
+                .post(webauth::post_webauthn_credential)
+                .put(webauth::put_webauthn_credential),
+        )
+        .route(
+            "/api/webauthn/attestation-options",
+            post(webauth::post_attestation_options),
+        )
+        .route(
+            "/api/webauthn/assertion-options",
+            post(webauth::post_assertion_options),
+        )
+        .route(
+            "/api/webauthn/{id}/delete",
+            post(webauth::delete_webauthn_credential),
+        )
         // Two-factor authentication
         .route("/api/two-factor", get(twofactor::get_twofactor))
         .route(
@@ -259,6 +670,60 @@ pub fn api_router(env: Env) -> Router {
             "/api/two-factor/disable",
             put(twofactor::disable_twofactor_put),
         )
+        .route("/api/two-factor/get-email", post(twofactor::get_email))
+        .route("/api/two-factor/send-email", post(twofactor::send_email))
+        .route(
+            "/api/two-factor/send-email-login",
+            post(twofactor::send_email_login),
+        )
+        .route(
+            "/api/two-factor/email",
+            post(twofactor::activate_email_post).put(twofactor::activate_email),
+        )
+        .route(
+            "/api/two-factor/get-webauthn",
+            post(twofactor::get_webauthn),
+        )
+        .route(
+            "/api/two-factor/get-webauthn-challenge",
+            post(twofactor::get_webauthn_challenge),
+        )
+        .route(
+            "/api/two-factor/webauthn",
+            post(twofactor::activate_webauthn)
+                .put(twofactor::activate_webauthn_put)
+                .delete(twofactor::delete_webauthn),
+        )
         .route("/api/two-factor/get-recover", post(twofactor::get_recover))
+        .route("/api/two-factor/recover", post(twofactor::recover))
+        .route("/api/two-factor/get-yubikey", post(twofactor::get_yubikey))
+        .route(
+            "/api/two-factor/yubikey",
+            post(twofactor::activate_yubikey_post).put(twofactor::activate_yubikey),
+        )
+        .route("/api/two-factor/get-duo", post(twofactor::get_duo))
+        .route(
+            "/api/two-factor/duo",
+            post(twofactor::activate_duo_post).put(twofactor::activate_duo),
+        )
+        .route(
+            "/api/organizations/{org_id}/two-factor",
+            get(twofactor::get_organization_twofactor),
+        )
+        .route(
+            "/api/organizations/{org_id}/two-factor/get-duo",
+            post(twofactor::get_organization_duo),
+        )
+        .route(
+            "/api/organizations/{org_id}/two-factor/duo",
+            post(twofactor::activate_organization_duo_post)
+                .put(twofactor::activate_organization_duo),
+        )
+        .route(
+            "/api/organizations/{org_id}/two-factor/disable",
+            post(twofactor::disable_organization_twofactor_post)
+                .put(twofactor::disable_organization_twofactor),
+        )
+        .merge(admin::router(app_state.clone()))
         .with_state(app_state)
 }
@@ -0,0 +1,472 @@
+//! Single sign-on through one OpenID Connect identity provider configured for the
+//! whole server (`SSO_AUTHORITY`, `SSO_CLIENT_ID`, `SSO_CLIENT_SECRET`).
+//!
+//! The clients run the OAuth authorization-code flow against this server, which
+//! relays it to the identity provider:
+//! - [`authorize_url`] checks the client's request (PKCE is required) and sends the
+//!   browser to the provider, carrying the client's request in a signed `state`,
+//! - the provider sends the browser back to `/identity/connect/oidc-signin`, where
+//!   [`finish_login`] exchanges its code for an ID token and sends the browser on to
+//!   the client with a code of our own,
+//! - the client trades that code and its PKCE verifier for tokens in the
+//!   `authorization_code` grant, which calls [`redeem_code`].
+//!
+//! Nothing is stored while a login is in flight: the state and the code are JWTs
+//! signed with `JWT_SECRET`.
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::Duration;
+use jwt_compact::{alg::Hs256Key, AlgorithmExt, Claims as JwtClaims, Header, UntrustedToken};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use web_sys::UrlSearchParams;
+use worker::{Env, Fetch, Method, Request, RequestInit};
+
+use crate::auth::jwt_time_options;
+use crate::crypto::{ct_eq, random_bytes};
+use crate::error::AppError;
+
+/// How long a login can take at the identity provider.
+const STATE_TTL_MINUTES: i64 = 10;
+/// How long the client can redeem a code; it is sent again with the two-step login token.
+const CODE_TTL_MINUTES: i64 = 10;
+const STATE_ISSUER: &str = "warden-worker-sso-state";
+const CODE_ISSUER: &str = "warden-worker-sso-code";
+const DEFAULT_SCOPES: &str = "email profile";
+/// Where desktop and mobile clients receive the code.
+const NATIVE_REDIRECT_URI: &str = "bitwarden://sso-callback";
+
+/// The identity provider and the organization SSO users join.
+#[derive(Debug, Clone)]
+pub struct SsoConfig {
+    /// Issuer URL; the provider's endpoints are read from its discovery document.
+    pub authority: String,
+    pub client_id: String,
+    client_secret: String,
+    scopes: String,
+    /// Organization SSO users are added to (`SSO_ORGANIZATION_ID`), if any.
+    pub organization_id: Option<String>,
+}
+
+fn env_var(env: &Env, name: &str) -> Option<String> {
+    env.var(name)
+        .ok()
+        .map(|v| v.to_string().trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+impl SsoConfig {
+    pub fn from_env(env: &Env) -> Result<Self, AppError> {
+        let not_configured =
+            || AppError::BadRequest("SSO is not configured on this server".to_string());
+        let authority = env_var(env, "SSO_AUTHORITY").ok_or_else(not_configured)?;
+        let client_id = env_var(env, "SSO_CLIENT_ID").ok_or_else(not_configured)?;
+        let client_secret = env
+            .secret("SSO_CLIENT_SECRET")
+            .map(|v| v.to_string())
+            .map_err(|_| not_configured())?;
+        let scopes = env_var(env, "SSO_SCOPES").unwrap_or_else(|| DEFAULT_SCOPES.to_string());
+
+        Ok(Self {
+            authority: authority.trim_end_matches('/').to_string(),
+            client_id,
+            client_secret: client_secret.trim().to_string(),
+            scopes,
+            organization_id: env_var(env, "SSO_ORGANIZATION_ID"),
+        })
+    }
+
+    /// Requested scopes, always including `openid`.
+    fn scope(&self) -> String {
+        let scopes = self.scopes.split_whitespace().filter(|s| *s != "openid");
+        std::iter::once("openid")
+            .chain(scopes)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// The parts of the provider's discovery document the flow uses.
+#[derive(Deserialize)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    userinfo_endpoint: Option<String>,
+}
+
+async fn discover(config: &SsoConfig) -> Result<Discovery, AppError> {
+    let url = format!("{}/.well-known/openid-configuration", config.authority);
+    let mut response = Fetch::Url(url.parse().map_err(|_| AppError::Internal)?)
+        .send()
+        .await?;
+    if response.status_code() != 200 {
+        log::warn!("SSO discovery returned {}", response.status_code());
+        return Err(AppError::BadRequest(
+            "The SSO identity provider is not available".to_string(),
+        ));
+    }
+    response.json().await.map_err(|e| {
+        log::error!("SSO discovery returned an unexpected document: {e}");
+        AppError::Internal
+    })
+}
+
+/// The client's authorization request (GET /identity/connect/authorize).
+#[derive(Debug, Deserialize)]
+pub struct AuthorizeRequest {
+    pub redirect_uri: Option<String>,
+    pub response_type: Option<String>,
+    pub state: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+}
+
+/// What the signed `state` sent to the provider carries.
+#[derive(Serialize, Deserialize)]
+struct StateClaims {
+    iss: String,
+    redirect_uri: String,
+    state: String,
+    code_challenge: String,
+    nonce: String,
+}
+
+/// What the code handed to the client carries.
+#[derive(Serialize, Deserialize)]
+struct CodeClaims {
+    iss: String,
+    identifier: String,
+    email: Option<String>,
+    email_verified: Option<bool>,
+    redirect_uri: String,
+    code_challenge: String,
+}
+
+/// A user the identity provider signed in.
+#[derive(Debug)]
+pub struct SsoIdentity {
+    /// The provider's `sub` claim.
+    pub identifier: String,
+    pub email: Option<String>,
+    /// `None` when the provider does not say.
+    pub email_verified: Option<bool>,
+}
+
+/// Where SSO logins come back to from the identity provider.
+fn callback_uri(base_url: &str) -> String {
+    format!("{base_url}/identity/connect/oidc-signin")
+}
+
+/// Whether a client may receive codes at `uri`: the web vault's SSO connector, the
+/// native apps' callback, or the CLI's local listener.
+fn allowed_redirect_uri(base_url: &str, uri: &str) -> bool {
+    let local_port = |rest: &str| {
+        let port = rest.split(['/', '?']).next().unwrap_or_default();
+        !port.is_empty() && port.chars().all(|c| c.is_ascii_digit())
+    };
+    uri.starts_with(&format!("{base_url}/"))
+        || uri == NATIVE_REDIRECT_URI
+        || uri
+            .strip_prefix("http://localhost:")
+            .or_else(|| uri.strip_prefix("http://127.0.0.1:"))
+            .is_some_and(local_port)
+}
+
+fn jwt_key(env: &Env) -> Result<Hs256Key, AppError> {
+    Ok(Hs256Key::new(
+        env.secret("JWT_SECRET")?.to_string().as_bytes(),
+    ))
+}
+
+fn sign<T: Serialize>(env: &Env, claims: T, minutes: i64) -> Result<String, AppError> {
+    let claims = JwtClaims::new(claims)
+        .set_duration_and_issuance(&jwt_time_options(), Duration::minutes(minutes));
+    jwt_compact::alg::Hs256
+        .token(&Header::empty(), &claims, &jwt_key(env)?)
+        .map_err(|_| AppError::Crypto("Failed to sign SSO token".to_string()))
+}
+
+/// Claims of a JWT from [`sign`], or `None` when it is forged or expired.
+fn verify<T: for<'de> Deserialize<'de>>(env: &Env, token: &str) -> Result<Option<T>, AppError> {
+    let key = jwt_key(env)?;
+    let Ok(token) = UntrustedToken::new(token) else {
+        return Ok(None);
+    };
+    let Ok(token) = jwt_compact::alg::Hs256
+        .validator::<T>(&key)
+        .validate(&token)
+    else {
+        return Ok(None);
+    };
+    if token
+        .claims()
+        .validate_expiration(&jwt_time_options())
+        .is_err()
+    {
+        return Ok(None);
+    }
+    Ok(Some(token.into_parts().1.custom))
+}
+
+fn query_string(params: &[(&str, &str)]) -> Result<String, AppError> {
+    let search = UrlSearchParams::new().map_err(|_| AppError::Internal)?;
+    for (name, value) in params {
+        search.append(name, value);
+    }
+    Ok(search.to_string().into())
+}
+
+/// `uri` with `params` added to its query string.
+fn with_query(uri: &str, params: &[(&str, &str)]) -> Result<String, AppError> {
+    let separator = if uri.contains('?') { '&' } else { '?' };
+    Ok(format!("{uri}{separator}{}", query_string(params)?))
+}
+
+/// Check the client's authorization request and return the provider's login URL.
+pub async fn authorize_url(
+    env: &Env,
+    base_url: &str,
+    request: &AuthorizeRequest,
+) -> Result<String, AppError> {
+    let config = SsoConfig::from_env(env)?;
+    let invalid = |message: &str| AppError::BadRequest(message.to_string());
+
+    if request.response_type.as_deref() != Some("code") {
+        return Err(invalid("Unsupported response_type"));
+    }
+    let redirect_uri = request
+        .redirect_uri
+        .as_deref()
+        .filter(|uri| allowed_redirect_uri(base_url, uri))
+        .ok_or_else(|| invalid("Invalid redirect_uri"))?;
+    let code_challenge = request
+        .code_challenge
+        .as_deref()
+        .filter(|c| !c.is_empty())
+        .ok_or_else(|| invalid("Missing code_challenge"))?;
+    if request.code_challenge_method.as_deref() != Some("S256") {
+        return Err(invalid("Unsupported code_challenge_method"));
+    }
+
+    let nonce = hex::encode(random_bytes(16)?);
+    let state = sign(
+        env,
+        StateClaims {
+            iss: STATE_ISSUER.to_string(),
+            redirect_uri: redirect_uri.to_string(),
+            state: request.state.clone().unwrap_or_default(),
+            code_challenge: code_challenge.to_string(),
+            nonce: nonce.clone(),
+        },
+        STATE_TTL_MINUTES,
+    )?;
+
+    let discovery = discover(&config).await?;
+    with_query(
+        &discovery.authorization_endpoint,
+        &[
+            ("response_type", "code"),
+            ("client_id", &config.client_id),
+            ("redirect_uri", &callback_uri(base_url)),
+            ("scope", &config.scope()),
+            ("state", &state),
+            ("nonce", &nonce),
+        ],
+    )
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+    access_token: Option<String>,
+}
+
+/// Audience of an ID token: one client, or a list of them.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Audience {
+    fn contains(&self, client_id: &str) -> bool {
+        match self {
+            Audience::One(aud) => aud == client_id,
+            Audience::Many(auds) => auds.iter().any(|aud| aud == client_id),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct IdTokenClaims {
+    iss: String,
+    aud: Audience,
+    sub: String,
+    nonce: Option<String>,
+    email: Option<String>,
+    email_verified: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct UserInfo {
+    email: Option<String>,
+    email_verified: Option<bool>,
+}
+
+/// Exchange the provider's code for the signed-in user.
+///
+/// The ID token comes straight from the provider's token endpoint over TLS, which
+/// OpenID Connect accepts in place of checking its signature.
+async fn exchange_code(
+    config: &SsoConfig,
+    discovery: &Discovery,
+    base_url: &str,
+    code: &str,
+    nonce: &str,
+) -> Result<SsoIdentity, AppError> {
+    let invalid = || AppError::BadRequest("Invalid SSO login".to_string());
+    let body = query_string(&[
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", &callback_uri(base_url)),
+        ("client_id", &config.client_id),
+        ("client_secret", &config.client_secret),
+    ])?;
+    let mut init = RequestInit::new();
+    init.with_method(Method::Post).with_body(Some(body.into()));
+    let mut req = Request::new_with_init(&discovery.token_endpoint, &init)?;
+    req.headers_mut()?
+        .set("Content-Type", "application/x-www-form-urlencoded")?;
+    req.headers_mut()?.set("Accept", "application/json")?;
+
+    let mut response = Fetch::Request(req).send().await?;
+    let status = response.status_code();
+    let body: Value = response.json().await.unwrap_or(Value::Null);
+    if !(200..300).contains(&status) {
+        log::warn!("SSO token request failed ({status}): {body}");
+        return Err(AppError::BadRequest(
+            "The SSO identity provider rejected the login".to_string(),
+        ));
+    }
+    let tokens: TokenResponse = serde_json::from_value(body).map_err(|_| invalid())?;
+
+    let claims = UntrustedToken::new(&tokens.id_token)
+        .map_err(|_| invalid())?
+        .deserialize_claims_unchecked::<IdTokenClaims>()
+        .map_err(|_| invalid())?;
+    claims
+        .validate_expiration(&jwt_time_options())
+        .map_err(|_| invalid())?;
+    let claims = claims.custom;
+    let nonce_matches = claims.nonce.as_deref().is_some_and(|n| ct_eq(n, nonce));
+    if claims.iss.trim_end_matches('/') != discovery.issuer.trim_end_matches('/')
+        || !claims.aud.contains(&config.client_id)
+        || !nonce_matches
+    {
+        return Err(invalid());
+    }
+
+    let mut identity = SsoIdentity {
+        identifier: claims.sub,
+        email: claims.email,
+        email_verified: claims.email_verified,
+    };
+    if identity.email.is_none() {
+        if let (Some(endpoint), Some(access_token)) =
+            (&discovery.userinfo_endpoint, &tokens.access_token)
+        {
+            let info = userinfo(endpoint, access_token).await?;
+            identity.email = info.email;
+            identity.email_verified = info.email_verified;
+        }
+    }
+    Ok(identity)
+}
+
+async fn userinfo(endpoint: &str, access_token: &str) -> Result<UserInfo, AppError> {
+    let mut init = RequestInit::new();
+    init.with_method(Method::Get);
+    let mut req = Request::new_with_init(endpoint, &init)?;
+    req.headers_mut()?
+        .set("Authorization", &format!("Bearer {access_token}"))?;
+    req.headers_mut()?.set("Accept", "application/json")?;
+
+    let mut response = Fetch::Request(req).send().await?;
+    if response.status_code() != 200 {
+        log::warn!("SSO userinfo request returned {}", response.status_code());
+        return Err(AppError::BadRequest(
+            "The SSO identity provider did not return the user's email".to_string(),
+        ));
+    }
+    response.json().await.map_err(|e| {
+        log::error!("SSO userinfo returned an unexpected response: {e}");
+        AppError::Internal
+    })
+}
+
+/// Finish the login at the provider and return where to send the browser: the
+/// client's redirect URI with our code, or with the provider's error.
+pub async fn finish_login(
+    env: &Env,
+    base_url: &str,
+    code: Option<&str>,
+    state: &str,
+    error: Option<&str>,
+) -> Result<String, AppError> {
+    let state: StateClaims = verify(env, state)?
+        .filter(|s: &StateClaims| s.iss == STATE_ISSUER)
+        .ok_or_else(|| AppError::BadRequest("SSO login expired".to_string()))?;
+    if let Some(error) = error {
+        return with_query(
+            &state.redirect_uri,
+            &[("error", error), ("state", &state.state)],
+        );
+    }
+    let code = code.ok_or_else(|| AppError::BadRequest("Missing code".to_string()))?;
+
+    let config = SsoConfig::from_env(env)?;
+    let discovery = discover(&config).await?;
+    let identity = exchange_code(&config, &discovery, base_url, code, &state.nonce).await?;
+    let code = sign(
+        env,
+        CodeClaims {
+            iss: CODE_ISSUER.to_string(),
+            identifier: identity.identifier,
+            email: identity.email,
+            email_verified: identity.email_verified,
+            redirect_uri: state.redirect_uri.clone(),
+            code_challenge: state.code_challenge,
+        },
+        CODE_TTL_MINUTES,
+    )?;
+    with_query(
+        &state.redirect_uri,
+        &[("code", &code), ("state", &state.state)],
+    )
+}
+
+/// The user behind a code from [`finish_login`], once the client proved it started
+/// the login (PKCE) and asked for it at the same redirect URI.
+pub fn redeem_code(
+    env: &Env,
+    code: &str,
+    code_verifier: &str,
+    redirect_uri: &str,
+) -> Result<SsoIdentity, AppError> {
+    let invalid = || AppError::BadRequest("invalid_grant".to_string());
+    let claims: CodeClaims = verify(env, code)?
+        .filter(|c: &CodeClaims| c.iss == CODE_ISSUER)
+        .ok_or_else(invalid)?;
+    let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()));
+    if !ct_eq(&challenge, &claims.code_challenge) || claims.redirect_uri != redirect_uri {
+        return Err(invalid());
+    }
+    Ok(SsoIdentity {
+        identifier: claims.identifier,
+        email: claims.email,
+        email_verified: claims.email_verified,
+    })
+}
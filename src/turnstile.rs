@@ -0,0 +1,156 @@
+//! Optional Cloudflare Turnstile challenge on registration and password logins.
+//!
+//! Enabled when `TURNSTILE_SITE_KEY` and the `TURNSTILE_SECRET_KEY` secret are both
+//! set. Clients send the widget's token as `captchaResponse`; without a valid one
+//! they get the captcha-required error carrying the site key, which makes them show
+//! the challenge and retry. A login that then needs a second factor gets a
+//! short-lived bypass token, so the retry with the 2FA code is not challenged again.
+
+use chrono::{Duration, Utc};
+use jwt_compact::{alg::Hs256Key, AlgorithmExt, Claims as JwtClaims, Header, UntrustedToken};
+use serde::{Deserialize, Serialize};
+use web_sys::UrlSearchParams;
+use worker::{Env, Fetch, Method, Request, RequestInit};
+
+use crate::auth::jwt_time_options;
+use crate::error::AppError;
+
+const SITEVERIFY_URL: &str = "https://challenges.cloudflare.com/turnstile/v0/siteverify";
+const BYPASS_TOKEN_ISSUER: &str = "warden-worker-captcha-bypass";
+const BYPASS_TOKEN_TTL_MINUTES: i64 = 5;
+
+pub struct TurnstileConfig {
+    site_key: String,
+    secret_key: String,
+}
+
+impl TurnstileConfig {
+    /// The configuration, or `None` when Turnstile is not enabled.
+    pub fn from_env(env: &Env) -> Option<Self> {
+        let site_key = env
+            .var("TURNSTILE_SITE_KEY")
+            .ok()
+            .map(|v| v.to_string())
+            .filter(|v| !v.trim().is_empty())?;
+        let secret_key = env
+            .secret("TURNSTILE_SECRET_KEY")
+            .ok()
+            .map(|v| v.to_string())
+            .filter(|v| !v.trim().is_empty())?;
+        Some(Self {
+            site_key: site_key.trim().to_string(),
+            secret_key: secret_key.trim().to_string(),
+        })
+    }
+
+    fn required(&self) -> AppError {
+        AppError::CaptchaRequired(self.site_key.clone())
+    }
+}
+
+#[derive(Deserialize)]
+struct SiteverifyResponse {
+    success: bool,
+    #[serde(rename = "error-codes", default)]
+    error_codes: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BypassClaims {
+    /// Lowercased email of the account that passed the challenge.
+    sub: String,
+    iss: String,
+}
+
+/// Require a passed challenge (or a bypass token issued for `email`) when Turnstile is
+/// enabled. `email` is `None` where bypass tokens are not accepted.
+pub async fn verify(
+    env: &Env,
+    response: Option<&str>,
+    email: Option<&str>,
+    ip: &str,
+) -> Result<(), AppError> {
+    let Some(config) = TurnstileConfig::from_env(env) else {
+        return Ok(());
+    };
+    let Some(response) = response.map(str::trim).filter(|r| !r.is_empty()) else {
+        return Err(config.required());
+    };
+    if let Some(email) = email {
+        if check_bypass_token(env, email, response)? {
+            return Ok(());
+        }
+    }
+
+    let params = UrlSearchParams::new().map_err(|_| AppError::Internal)?;
+    params.append("secret", &config.secret_key);
+    params.append("response", response);
+    if ip != "unknown" {
+        params.append("remoteip", ip);
+    }
+    let body: String = params.to_string().into();
+
+    let mut init = RequestInit::new();
+    init.with_method(Method::Post).with_body(Some(body.into()));
+    let mut req = Request::new_with_init(SITEVERIFY_URL, &init)?;
+    req.headers_mut()?
+        .set("Content-Type", "application/x-www-form-urlencoded")?;
+
+    let mut response = Fetch::Request(req).send().await?;
+    let result: SiteverifyResponse = response.json().await.map_err(|e| {
+        log::error!("Turnstile siteverify returned an unexpected response: {e}");
+        AppError::Internal
+    })?;
+    if !result.success {
+        log::info!("Turnstile challenge rejected: {:?}", result.error_codes);
+        return Err(config.required());
+    }
+    Ok(())
+}
+
+/// A token letting `email` skip the challenge for a few minutes, or `None` when
+/// Turnstile is not enabled.
+pub fn bypass_token(env: &Env, email: &str) -> Result<Option<String>, AppError> {
+    if TurnstileConfig::from_env(env).is_none() {
+        return Ok(None);
+    }
+    let claims = JwtClaims::new(BypassClaims {
+        sub: email.to_lowercase(),
+        iss: BYPASS_TOKEN_ISSUER.to_string(),
+    })
+    .set_duration_and_issuance(
+        &jwt_time_options(),
+        Duration::minutes(BYPASS_TOKEN_TTL_MINUTES),
+    )
+    .set_not_before(Utc::now());
+
+    let secret = env.secret("JWT_SECRET")?.to_string();
+    let key = Hs256Key::new(secret.as_bytes());
+    jwt_compact::alg::Hs256
+        .token(&Header::empty(), &claims, &key)
+        .map(Some)
+        .map_err(|_| AppError::Crypto("Failed to create captcha bypass token".to_string()))
+}
+
+fn check_bypass_token(env: &Env, email: &str, raw_token: &str) -> Result<bool, AppError> {
+    let secret = env.secret("JWT_SECRET")?.to_string();
+    let key = Hs256Key::new(secret.as_bytes());
+    let Ok(token) = UntrustedToken::new(raw_token) else {
+        return Ok(false);
+    };
+    let Ok(token) = jwt_compact::alg::Hs256
+        .validator::<BypassClaims>(&key)
+        .validate(&token)
+    else {
+        return Ok(false);
+    };
+    let time_options = jwt_time_options();
+    if token.claims().validate_expiration(&time_options).is_err()
+        || token.claims().validate_maturity(&time_options).is_err()
+    {
+        return Ok(false);
+    }
+
+    let claims = token.into_parts().1.custom;
+    Ok(claims.iss == BYPASS_TOKEN_ISSUER && claims.sub == email.to_lowercase())
+}
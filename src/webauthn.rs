@@ -0,0 +1,594 @@
+//! WebAuthn (FIDO2) ceremonies for the WebAuthn two-factor provider and for
+//! passkey login.
+//!
+//! This implements the subset of the specification the Bitwarden clients rely
+//! on: registration with `"none"` attestation (the attestation statement is not
+//! verified) and assertions signed with ES256 or RS256 keys. Signatures are
+//! verified with the Web Crypto API.
+//!
+//! The challenge of an in-flight ceremony is kept in Workers KV (`CACHE_KV`)
+//! for a few minutes and consumed on first use. Two-factor ceremonies are keyed
+//! by user; passkey ceremonies by a random token handed to the client with the
+//! options, since a passkey login starts before the user is known.
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64URL, Engine};
+use ciborium::value::Value as CborValue;
+use constant_time_eq::constant_time_eq;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use worker::Env;
+
+use crate::crypto::{random_bytes, verify_signature, SignatureAlgorithm};
+use crate::error::AppError;
+use crate::models::twofactor::{WebauthnAssertion, WebauthnAttestation};
+
+/// KV namespace holding short-lived server state (WebAuthn challenges).
+pub const CACHE_KV: &str = "CACHE_KV";
+
+/// How long a ceremony challenge stays valid.
+const CHALLENGE_TTL_SECS: u64 = 300;
+/// Timeout hint for the client-side ceremony.
+const CEREMONY_TIMEOUT_MS: u64 = 60_000;
+
+const COSE_ALG_ES256: i64 = -7;
+const COSE_ALG_RS256: i64 = -257;
+
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL: u8 = 0x40;
+
+/// A registered security key, stored as a JSON array in the `twofactor` row data.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebauthnCredential {
+    /// Slot number chosen by the client (1-5).
+    pub id: i32,
+    pub name: String,
+    /// Credential id, base64url without padding.
+    pub credential_id: String,
+    /// Public key as a JWK JSON string.
+    pub public_key: String,
+    /// COSE algorithm identifier of the key.
+    pub algorithm: i64,
+    pub counter: u32,
+}
+
+impl WebauthnCredential {
+    pub fn list_from_data(data: &str) -> Result<Vec<Self>, AppError> {
+        serde_json::from_str(data).map_err(|_| AppError::Internal)
+    }
+
+    pub fn to_key_json(&self) -> Value {
+        json!({
+            "name": self.name,
+            "id": self.id,
+            "migrated": false,
+        })
+    }
+}
+
+/// Public key of a registered credential, as verified during registration.
+#[derive(Debug, Clone)]
+pub struct CredentialKey {
+    /// Credential id, base64url without padding.
+    pub credential_id: String,
+    /// Public key as a JWK JSON string.
+    pub public_key: String,
+    /// COSE algorithm identifier of the key.
+    pub algorithm: i64,
+    pub counter: u32,
+}
+
+/// The relying party, derived from the server's base URL.
+pub struct RelyingParty {
+    pub id: String,
+    pub origin: String,
+}
+
+impl RelyingParty {
+    pub fn from_base_url(base_url: &str) -> Self {
+        let (scheme, rest) = base_url.split_once("://").unwrap_or(("https", base_url));
+        let authority = rest.split('/').next().unwrap_or(rest);
+        let host = authority.split(':').next().unwrap_or(authority);
+        Self {
+            id: host.to_string(),
+            origin: format!("{scheme}://{authority}"),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    r#type: String,
+    challenge: String,
+    origin: String,
+}
+
+struct AuthenticatorData<'a> {
+    rp_id_hash: &'a [u8],
+    flags: u8,
+    counter: u32,
+    credential: Option<(Vec<u8>, CborValue)>,
+}
+
+/// Decode base64url, tolerating padding and the standard alphabet.
+pub fn decode_base64url(input: &str) -> Result<Vec<u8>, AppError> {
+    let normalized: String = input
+        .trim_end_matches('=')
+        .chars()
+        .map(|c| match c {
+            '+' => '-',
+            '/' => '_',
+            c => c,
+        })
+        .collect();
+    BASE64URL
+        .decode(normalized)
+        .map_err(|_| AppError::BadRequest("Invalid WebAuthn response encoding".to_string()))
+}
+
+fn invalid_response() -> AppError {
+    AppError::BadRequest("Invalid WebAuthn response".to_string())
+}
+
+async fn store_challenge(env: &Env, key: &str, challenge: &str) -> Result<(), AppError> {
+    let kv = env.kv(CACHE_KV).map_err(|_| {
+        AppError::BadRequest(format!(
+            "WebAuthn requires the {CACHE_KV} KV namespace to be bound"
+        ))
+    })?;
+    kv.put(key, challenge)
+        .map_err(|_| AppError::Internal)?
+        .expiration_ttl(CHALLENGE_TTL_SECS)
+        .execute()
+        .await
+        .map_err(|e| {
+            log::error!("KV put error for key '{key}': {e}");
+            AppError::Internal
+        })
+}
+
+/// Fetch and remove the pending challenge for `key`.
+async fn take_challenge(env: &Env, key: &str) -> Result<Vec<u8>, AppError> {
+    let kv = env.kv(CACHE_KV).map_err(|_| AppError::Internal)?;
+    let challenge = kv
+        .get(key)
+        .text()
+        .await
+        .map_err(|_| AppError::Internal)?
+        .ok_or_else(|| AppError::BadRequest("WebAuthn challenge expired".to_string()))?;
+    kv.delete(key).await.map_err(|_| AppError::Internal)?;
+    decode_base64url(&challenge)
+}
+
+fn register_key(user_id: &str) -> String {
+    format!("webauthn:register:{user_id}")
+}
+
+fn login_key(user_id: &str) -> String {
+    format!("webauthn:login:{user_id}")
+}
+
+/// KV key of a passkey ceremony. `user_id` is `None` for a login, where the user is
+/// only known once the assertion names its credential.
+fn passkey_key(kind: &str, user_id: Option<&str>, token: &str) -> String {
+    format!("webauthn:passkey-{kind}:{}:{token}", user_id.unwrap_or("-"))
+}
+
+fn credential_descriptors<'a>(ids: impl IntoIterator<Item = &'a str>) -> Vec<Value> {
+    ids.into_iter()
+        .map(|id| json!({ "type": "public-key", "id": id }))
+        .collect()
+}
+
+/// Verify clientDataJSON and return its SHA-256 hash.
+fn verify_client_data(
+    raw: &[u8],
+    expected_type: &str,
+    challenge: &[u8],
+    rp: &RelyingParty,
+) -> Result<Vec<u8>, AppError> {
+    let client_data: ClientData = serde_json::from_slice(raw).map_err(|_| invalid_response())?;
+    if client_data.r#type != expected_type {
+        return Err(invalid_response());
+    }
+    if !constant_time_eq(&decode_base64url(&client_data.challenge)?, challenge) {
+        return Err(AppError::BadRequest(
+            "WebAuthn challenge mismatch".to_string(),
+        ));
+    }
+    if client_data.origin != rp.origin {
+        return Err(AppError::BadRequest("WebAuthn origin mismatch".to_string()));
+    }
+    Ok(Sha256::digest(raw).to_vec())
+}
+
+fn parse_authenticator_data<'a>(
+    data: &'a [u8],
+    rp: &RelyingParty,
+    require_user_verification: bool,
+) -> Result<AuthenticatorData<'a>, AppError> {
+    if data.len() < 37 {
+        return Err(invalid_response());
+    }
+    let auth_data = AuthenticatorData {
+        rp_id_hash: &data[..32],
+        flags: data[32],
+        counter: u32::from_be_bytes([data[33], data[34], data[35], data[36]]),
+        credential: if data[32] & FLAG_ATTESTED_CREDENTIAL != 0 {
+            // aaguid (16) | credential id length (2) | credential id | COSE key
+            let rest = data.get(37 + 16..).ok_or_else(invalid_response)?;
+            let (len, rest) = rest.split_at_checked(2).ok_or_else(invalid_response)?;
+            let len = u16::from_be_bytes([len[0], len[1]]) as usize;
+            let (credential_id, cose_key) =
+                rest.split_at_checked(len).ok_or_else(invalid_response)?;
+            let cose_key: CborValue =
+                ciborium::from_reader(cose_key).map_err(|_| invalid_response())?;
+            Some((credential_id.to_vec(), cose_key))
+        } else {
+            None
+        },
+    };
+
+    if !constant_time_eq(auth_data.rp_id_hash, &Sha256::digest(rp.id.as_bytes())) {
+        return Err(AppError::BadRequest("WebAuthn RP ID mismatch".to_string()));
+    }
+    if auth_data.flags & FLAG_USER_PRESENT == 0 {
+        return Err(AppError::BadRequest(
+            "WebAuthn user presence required".to_string(),
+        ));
+    }
+    if require_user_verification && auth_data.flags & FLAG_USER_VERIFIED == 0 {
+        return Err(AppError::BadRequest(
+            "WebAuthn user verification required".to_string(),
+        ));
+    }
+    Ok(auth_data)
+}
+
+fn cbor_map_get(map: &[(CborValue, CborValue)], key: i64) -> Option<&CborValue> {
+    map.iter()
+        .find(|(k, _)| k.as_integer().map(i128::from) == Some(key as i128))
+        .map(|(_, v)| v)
+}
+
+fn cbor_bytes(map: &[(CborValue, CborValue)], key: i64) -> Result<String, AppError> {
+    cbor_map_get(map, key)
+        .and_then(CborValue::as_bytes)
+        .map(|b| BASE64URL.encode(b))
+        .ok_or_else(invalid_response)
+}
+
+/// Convert a COSE_Key into a JWK JSON string and its COSE algorithm.
+fn cose_key_to_jwk(cose_key: &CborValue) -> Result<(String, i64), AppError> {
+    let map = cose_key.as_map().ok_or_else(invalid_response)?;
+    let int = |key| {
+        cbor_map_get(map, key)
+            .and_then(CborValue::as_integer)
+            .and_then(|i| i64::try_from(i).ok())
+    };
+
+    let jwk = match (int(1), int(3)) {
+        // kty EC2, crv P-256
+        (Some(2), Some(COSE_ALG_ES256)) if int(-1) == Some(1) => json!({
+            "kty": "EC",
+            "crv": "P-256",
+            "x": cbor_bytes(map, -2)?,
+            "y": cbor_bytes(map, -3)?,
+        }),
+        // kty RSA
+        (Some(3), Some(COSE_ALG_RS256)) => json!({
+            "kty": "RSA",
+            "alg": "RS256",
+            "n": cbor_bytes(map, -1)?,
+            "e": cbor_bytes(map, -2)?,
+        }),
+        _ => {
+            return Err(AppError::BadRequest(
+                "Unsupported security key algorithm".to_string(),
+            ))
+        }
+    };
+
+    Ok((jwk.to_string(), int(3).unwrap_or_default()))
+}
+
+fn signature_algorithm(cose_alg: i64) -> Result<SignatureAlgorithm, AppError> {
+    match cose_alg {
+        COSE_ALG_ES256 => Ok(SignatureAlgorithm::Es256),
+        COSE_ALG_RS256 => Ok(SignatureAlgorithm::Rs256),
+        _ => Err(AppError::Internal),
+    }
+}
+
+/// Start a registration ceremony; returns the `PublicKeyCredentialCreationOptions`.
+pub async fn start_registration(
+    env: &Env,
+    rp: &RelyingParty,
+    user_id: &str,
+    email: &str,
+    name: Option<&str>,
+    existing: &[WebauthnCredential],
+) -> Result<Value, AppError> {
+    let challenge = BASE64URL.encode(random_bytes(32)?);
+    store_challenge(env, &register_key(user_id), &challenge).await?;
+
+    Ok(json!({
+        "rp": { "id": rp.id, "name": rp.id },
+        "user": {
+            "id": BASE64URL.encode(user_id.as_bytes()),
+            "name": email,
+            "displayName": name.unwrap_or(email),
+        },
+        "challenge": challenge,
+        "pubKeyCredParams": [
+            { "type": "public-key", "alg": COSE_ALG_ES256 },
+            { "type": "public-key", "alg": COSE_ALG_RS256 },
+        ],
+        "timeout": CEREMONY_TIMEOUT_MS,
+        "excludeCredentials": credential_descriptors(existing.iter().map(|c| c.credential_id.as_str())),
+        "authenticatorSelection": { "userVerification": "discouraged" },
+        "attestation": "none",
+        "extensions": {},
+    }))
+}
+
+/// Verify an attestation against `challenge` and extract the new credential.
+fn verify_attestation(
+    rp: &RelyingParty,
+    challenge: &[u8],
+    response: &WebauthnAttestation,
+    require_user_verification: bool,
+) -> Result<CredentialKey, AppError> {
+    let client_data = decode_base64url(&response.response.client_data_json)?;
+    verify_client_data(&client_data, "webauthn.create", challenge, rp)?;
+
+    let attestation: CborValue =
+        ciborium::from_reader(decode_base64url(&response.response.attestation_object)?.as_slice())
+            .map_err(|_| invalid_response())?;
+    let auth_data = attestation
+        .as_map()
+        .and_then(|map| {
+            map.iter()
+                .find(|(k, _)| k.as_text() == Some("authData"))
+                .and_then(|(_, v)| v.as_bytes())
+        })
+        .ok_or_else(invalid_response)?;
+
+    let auth_data = parse_authenticator_data(auth_data, rp, require_user_verification)?;
+    let (credential_id, cose_key) = auth_data.credential.ok_or_else(invalid_response)?;
+    if decode_base64url(&response.raw_id)? != credential_id {
+        return Err(invalid_response());
+    }
+    let (public_key, algorithm) = cose_key_to_jwk(&cose_key)?;
+
+    Ok(CredentialKey {
+        credential_id: BASE64URL.encode(credential_id),
+        public_key,
+        algorithm,
+        counter: auth_data.counter,
+    })
+}
+
+/// Finish a registration ceremony and return the new credential.
+pub async fn finish_registration(
+    env: &Env,
+    rp: &RelyingParty,
+    user_id: &str,
+    id: i32,
+    name: String,
+    response: &WebauthnAttestation,
+) -> Result<WebauthnCredential, AppError> {
+    let challenge = take_challenge(env, &register_key(user_id)).await?;
+    let key = verify_attestation(rp, &challenge, response, false)?;
+
+    Ok(WebauthnCredential {
+        id,
+        name,
+        credential_id: key.credential_id,
+        public_key: key.public_key,
+        algorithm: key.algorithm,
+        counter: key.counter,
+    })
+}
+
+/// Start an authentication ceremony; returns the `PublicKeyCredentialRequestOptions`
+/// sent to the client in the two-factor required response.
+pub async fn start_login(
+    env: &Env,
+    rp: &RelyingParty,
+    user_id: &str,
+    credentials: &[WebauthnCredential],
+) -> Result<Value, AppError> {
+    let challenge = BASE64URL.encode(random_bytes(32)?);
+    store_challenge(env, &login_key(user_id), &challenge).await?;
+
+    Ok(json!({
+        "challenge": challenge,
+        "timeout": CEREMONY_TIMEOUT_MS,
+        "rpId": rp.id,
+        "allowCredentials": credential_descriptors(credentials.iter().map(|c| c.credential_id.as_str())),
+        "userVerification": "discouraged",
+        "extensions": {},
+    }))
+}
+
+/// Verify an assertion against `challenge` and the stored `key`; returns the new
+/// signature counter.
+async fn verify_assertion(
+    rp: &RelyingParty,
+    challenge: &[u8],
+    key: &CredentialKey,
+    assertion: &WebauthnAssertion,
+    require_user_verification: bool,
+) -> Result<u32, AppError> {
+    let client_data = decode_base64url(&assertion.response.client_data_json)?;
+    let client_data_hash = verify_client_data(&client_data, "webauthn.get", challenge, rp)?;
+
+    let raw_auth_data = decode_base64url(&assertion.response.authenticator_data)?;
+    let counter = parse_authenticator_data(&raw_auth_data, rp, require_user_verification)?.counter;
+
+    let mut signed = raw_auth_data.clone();
+    signed.extend_from_slice(&client_data_hash);
+    let signature = decode_base64url(&assertion.response.signature)?;
+    if !verify_signature(
+        &key.public_key,
+        signature_algorithm(key.algorithm)?,
+        &signature,
+        &signed,
+    )
+    .await?
+    {
+        return Err(AppError::BadRequest(
+            "Invalid WebAuthn signature".to_string(),
+        ));
+    }
+
+    // Authenticators without a counter always report 0; otherwise it must increase.
+    if (counter != 0 || key.counter != 0) && counter <= key.counter {
+        return Err(AppError::BadRequest(
+            "Security key counter did not increase".to_string(),
+        ));
+    }
+    Ok(counter)
+}
+
+/// Verify an assertion (the JSON-encoded credential sent as the two-factor token)
+/// and advance the signature counter of the credential that produced it.
+pub async fn finish_login(
+    env: &Env,
+    rp: &RelyingParty,
+    user_id: &str,
+    credentials: &mut [WebauthnCredential],
+    token: &str,
+) -> Result<(), AppError> {
+    let assertion = parse_assertion(token)?;
+    let challenge = take_challenge(env, &login_key(user_id)).await?;
+
+    let credential_id = assertion_credential_id(&assertion)?;
+    let credential = credentials
+        .iter_mut()
+        .find(|c| c.credential_id == credential_id)
+        .ok_or_else(|| AppError::BadRequest("Unknown security key".to_string()))?;
+
+    let key = CredentialKey {
+        credential_id,
+        public_key: credential.public_key.clone(),
+        algorithm: credential.algorithm,
+        counter: credential.counter,
+    };
+    credential.counter = verify_assertion(rp, &challenge, &key, &assertion, false).await?;
+
+    Ok(())
+}
+
+/// Parse a JSON-encoded `navigator.credentials.get()` result.
+pub fn parse_assertion(json: &str) -> Result<WebauthnAssertion, AppError> {
+    serde_json::from_str(json).map_err(|_| invalid_response())
+}
+
+/// Credential id of an assertion, normalized to base64url without padding.
+pub fn assertion_credential_id(assertion: &WebauthnAssertion) -> Result<String, AppError> {
+    Ok(BASE64URL.encode(decode_base64url(&assertion.raw_id)?))
+}
+
+/// Start registering a passkey; returns the `PublicKeyCredentialCreationOptions` and
+/// the token that identifies the ceremony when it is finished.
+///
+/// Passkeys must be discoverable and verify the user, since they replace the master
+/// password rather than add a second factor.
+pub async fn start_passkey_registration(
+    env: &Env,
+    rp: &RelyingParty,
+    user_id: &str,
+    email: &str,
+    name: Option<&str>,
+    existing: &[&str],
+) -> Result<(Value, String), AppError> {
+    let token = BASE64URL.encode(random_bytes(32)?);
+    let challenge = BASE64URL.encode(random_bytes(32)?);
+    store_challenge(
+        env,
+        &passkey_key("register", Some(user_id), &token),
+        &challenge,
+    )
+    .await?;
+
+    let options = json!({
+        "rp": { "id": rp.id, "name": rp.id },
+        "user": {
+            "id": BASE64URL.encode(user_id.as_bytes()),
+            "name": email,
+            "displayName": name.unwrap_or(email),
+        },
+        "challenge": challenge,
+        "pubKeyCredParams": [
+            { "type": "public-key", "alg": COSE_ALG_ES256 },
+            { "type": "public-key", "alg": COSE_ALG_RS256 },
+        ],
+        "timeout": CEREMONY_TIMEOUT_MS,
+        "excludeCredentials": credential_descriptors(existing.iter().copied()),
+        "authenticatorSelection": {
+            "residentKey": "required",
+            "requireResidentKey": true,
+            "userVerification": "required",
+        },
+        "attestation": "none",
+        "extensions": { "credProps": true },
+    });
+    Ok((options, token))
+}
+
+/// Finish registering a passkey started with `token`.
+pub async fn finish_passkey_registration(
+    env: &Env,
+    rp: &RelyingParty,
+    user_id: &str,
+    token: &str,
+    response: &WebauthnAttestation,
+) -> Result<CredentialKey, AppError> {
+    let challenge = take_challenge(env, &passkey_key("register", Some(user_id), token)).await?;
+    verify_attestation(rp, &challenge, response, true)
+}
+
+/// Start a passkey assertion; returns the `PublicKeyCredentialRequestOptions` and the
+/// ceremony token.
+///
+/// With `user_id` the assertion proves possession of one of that user's passkeys
+/// (`allowed`); without it, it is a passkey login and any discoverable credential
+/// registered on this server is accepted.
+pub async fn start_passkey_assertion(
+    env: &Env,
+    rp: &RelyingParty,
+    user_id: Option<&str>,
+    allowed: &[&str],
+) -> Result<(Value, String), AppError> {
+    let token = BASE64URL.encode(random_bytes(32)?);
+    let challenge = BASE64URL.encode(random_bytes(32)?);
+    store_challenge(env, &passkey_key("assert", user_id, &token), &challenge).await?;
+
+    let options = json!({
+        "challenge": challenge,
+        "timeout": CEREMONY_TIMEOUT_MS,
+        "rpId": rp.id,
+        "allowCredentials": credential_descriptors(allowed.iter().copied()),
+        "userVerification": "required",
+        "extensions": {},
+    });
+    Ok((options, token))
+}
+
+/// Verify a passkey assertion for the ceremony started with `token`, made with the
+/// stored `key`; returns the new signature counter.
+pub async fn finish_passkey_assertion(
+    env: &Env,
+    rp: &RelyingParty,
+    user_id: Option<&str>,
+    token: &str,
+    key: &CredentialKey,
+    assertion: &WebauthnAssertion,
+) -> Result<u32, AppError> {
+    let challenge = take_challenge(env, &passkey_key("assert", user_id, token)).await?;
+    verify_assertion(rp, &challenge, key, assertion, true).await
+}
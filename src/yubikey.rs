@@ -0,0 +1,148 @@
+//! YubiKey OTP validation against YubiCloud (or a compatible validation server).
+//!
+//! Requests follow the Yubico validation protocol 2.0: the query is signed with
+//! HMAC-SHA1 using the API key from `YUBICO_SECRET_KEY`, and the signature of the
+//! response is checked the same way before its status is trusted.
+
+use std::collections::BTreeMap;
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use web_sys::UrlSearchParams;
+use worker::{Env, Fetch, Url};
+
+use crate::crypto::{ct_eq, hmac_sha1, random_bytes};
+use crate::error::AppError;
+
+const DEFAULT_SERVER: &str = "https://api.yubico.com/wsapi/2.0/verify";
+
+/// Length of a YubiKey OTP: a 12 character public ID and 32 characters of ciphertext.
+const OTP_LENGTH: usize = 44;
+const PUBLIC_ID_LENGTH: usize = 12;
+const MODHEX: &str = "cbdefghijklnrtuv";
+
+/// YubiCloud API client, from `YUBICO_CLIENT_ID`, `YUBICO_SECRET_KEY` and the
+/// optional `YUBICO_SERVER`.
+pub struct YubicoConfig {
+    client_id: String,
+    secret_key: Vec<u8>,
+    server: String,
+}
+
+impl YubicoConfig {
+    pub fn from_env(env: &Env) -> Result<Self, AppError> {
+        let not_configured =
+            || AppError::BadRequest("YubiKey OTP is not configured on this server".to_string());
+        let client_id = env
+            .var("YUBICO_CLIENT_ID")
+            .map(|v| v.to_string())
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .ok_or_else(not_configured)?;
+        let secret_key = env
+            .secret("YUBICO_SECRET_KEY")
+            .map(|v| v.to_string())
+            .map_err(|_| not_configured())?;
+        let secret_key = STANDARD.decode(secret_key.trim()).map_err(|_| {
+            log::error!("YUBICO_SECRET_KEY is not valid base64");
+            AppError::Internal
+        })?;
+        let server = env
+            .var("YUBICO_SERVER")
+            .map(|v| v.to_string())
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_SERVER.to_string());
+
+        Ok(Self {
+            client_id: client_id.trim().to_string(),
+            secret_key,
+            server,
+        })
+    }
+
+    /// Base64 HMAC-SHA1 of `key=value` pairs sorted by key and joined with `&`.
+    async fn signature(&self, params: &BTreeMap<String, String>) -> Result<String, AppError> {
+        let message = params
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect::<Vec<_>>()
+            .join("&");
+        Ok(STANDARD.encode(hmac_sha1(&self.secret_key, message.as_bytes()).await?))
+    }
+}
+
+/// The public ID of the key that generated `otp`, if it is shaped like a YubiKey OTP.
+pub fn public_id(otp: &str) -> Option<&str> {
+    (otp.len() == OTP_LENGTH && otp.chars().all(|c| MODHEX.contains(c)))
+        .then(|| &otp[..PUBLIC_ID_LENGTH])
+}
+
+/// Validate `otp` with the validation server, returning the public ID of its key.
+pub async fn verify_otp(config: &YubicoConfig, otp: &str) -> Result<String, AppError> {
+    let otp = otp.trim().to_ascii_lowercase();
+    let id = public_id(&otp)
+        .ok_or_else(|| AppError::BadRequest("Invalid YubiKey OTP".to_string()))?
+        .to_string();
+
+    let nonce = hex::encode(random_bytes(16)?);
+    let mut params = BTreeMap::from([
+        ("id".to_string(), config.client_id.clone()),
+        ("nonce".to_string(), nonce.clone()),
+        ("otp".to_string(), otp.clone()),
+    ]);
+    let signature = config.signature(&params).await?;
+    params.insert("h".to_string(), signature);
+
+    let query = UrlSearchParams::new().map_err(|_| AppError::Internal)?;
+    for (key, value) in &params {
+        query.append(key, value);
+    }
+    let query: String = query.to_string().into();
+    let url = Url::parse(&format!("{}?{query}", config.server)).map_err(|_| {
+        log::error!("YUBICO_SERVER is not a valid URL");
+        AppError::Internal
+    })?;
+
+    let mut response = Fetch::Url(url).send().await?;
+    if response.status_code() != 200 {
+        log::warn!(
+            "YubiKey validation server returned {}",
+            response.status_code()
+        );
+        return Err(AppError::BadRequest(
+            "YubiKey validation failed".to_string(),
+        ));
+    }
+    let body = response.text().await?;
+
+    let mut fields: BTreeMap<String, String> = body
+        .lines()
+        .filter_map(|line| line.trim().split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+    let Some(response_signature) = fields.remove("h") else {
+        return Err(AppError::BadRequest(
+            "YubiKey validation failed".to_string(),
+        ));
+    };
+    if !ct_eq(&config.signature(&fields).await?, &response_signature)
+        || fields.get("otp") != Some(&otp)
+        || fields.get("nonce") != Some(&nonce)
+    {
+        log::warn!("YubiKey validation response failed verification");
+        return Err(AppError::BadRequest(
+            "YubiKey validation failed".to_string(),
+        ));
+    }
+
+    match fields.get("status").map(String::as_str) {
+        Some("OK") => Ok(id),
+        Some("REPLAYED_OTP") => Err(AppError::BadRequest(
+            "This YubiKey OTP was already used".to_string(),
+        )),
+        status => {
+            log::info!("YubiKey OTP rejected: {}", status.unwrap_or("no status"));
+            Err(AppError::BadRequest("Invalid YubiKey OTP".to_string()))
+        }
+    }
+}